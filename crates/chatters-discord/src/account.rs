@@ -0,0 +1,89 @@
+use std::path::{Path, PathBuf};
+
+use chatters_lib::backends::{Error, Result, Setup, SetupQuestion};
+
+/// The Discord account to use, saved in the backend directory when linking.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Account {
+    /// Shell command to print the token, such as `pass show discord`, so that it isn't stored.
+    pub token_command: String,
+    /// Whether the token is for a bot rather than a user account.
+    #[serde(default)]
+    pub bot: bool,
+}
+
+impl Account {
+    pub fn load(path: &Path) -> Result<Self> {
+        let account_file = account_file(path);
+        if !account_file.exists() {
+            return Err(Error::Unlinked);
+        }
+        let content = std::fs::read_to_string(&account_file)
+            .map_err(|e| Error::Failure("Failed to read account".to_owned(), e.to_string()))?;
+        serde_json::from_str(&content)
+            .map_err(|e| Error::Failure("Failed to parse account".to_owned(), e.to_string()))
+    }
+
+    /// What to ask for in the setup wizard.
+    pub fn setup_questions() -> Vec<SetupQuestion> {
+        vec![
+            SetupQuestion {
+                key: "token_command",
+                prompt: "Token command",
+                default: "",
+                secret: false,
+            },
+            SetupQuestion {
+                key: "bot",
+                prompt: "Is this a bot token? (y/N)",
+                default: "n",
+                secret: false,
+            },
+        ]
+    }
+
+    /// The account from the answers in the setup wizard.
+    pub fn from_setup(setup: &Setup) -> Self {
+        Self {
+            token_command: setup.answer("token_command").to_owned(),
+            bot: setup.answer("bot").eq_ignore_ascii_case("y"),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self).unwrap();
+        std::fs::create_dir_all(path)
+            .and_then(|()| std::fs::write(account_file(path), content))
+            .map_err(|e| Error::Failure("Failed to save account".to_owned(), e.to_string()))
+    }
+
+    /// Run the token command to get the token.
+    pub fn token(&self) -> Result<String> {
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(&self.token_command)
+            .output()
+            .map_err(|e| Error::Failure("Failed to run token command".to_owned(), e.to_string()))?;
+        if !output.status.success() {
+            return Err(Error::Failure(
+                "Token command failed".to_owned(),
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ));
+        }
+        let token = String::from_utf8_lossy(&output.stdout);
+        Ok(token.lines().next().unwrap_or_default().to_owned())
+    }
+
+    /// The value of the authorization header for the token.
+    pub fn authorization(&self, token: &str) -> String {
+        if self.bot {
+            format!("Bot {token}")
+        } else {
+            token.to_owned()
+        }
+    }
+}
+
+fn account_file(path: &Path) -> PathBuf {
+    path.join("account.json")
+}
@@ -0,0 +1,285 @@
+use std::time::Duration;
+
+use chatters_lib::backends::{Error, Result};
+use log::{debug, warn};
+use reqwest::{header, Client, Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+const API: &str = "https://discord.com/api/v10";
+
+/// The start of 2015, which snowflakes count from, in milliseconds since the unix epoch.
+const DISCORD_EPOCH: u64 = 1_420_070_400_000;
+
+/// The time a snowflake id was created, in milliseconds since the unix epoch.
+pub fn snowflake_timestamp(id: &str) -> u64 {
+    id.parse::<u64>()
+        .map_or(0, |id| (id >> 22).saturating_add(DISCORD_EPOCH))
+}
+
+/// The smallest snowflake created at a time, for paginating by time.
+pub fn timestamp_snowflake(timestamp: u64) -> u64 {
+    timestamp.saturating_sub(DISCORD_EPOCH) << 22
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct User {
+    pub id: String,
+    pub username: String,
+    #[serde(default)]
+    pub global_name: Option<String>,
+}
+
+impl User {
+    pub fn display_name(&self) -> &str {
+        self.global_name.as_deref().unwrap_or(&self.username)
+    }
+}
+
+pub const CHANNEL_GUILD_TEXT: u8 = 0;
+pub const CHANNEL_DM: u8 = 1;
+pub const CHANNEL_GROUP_DM: u8 = 3;
+pub const CHANNEL_GUILD_ANNOUNCEMENT: u8 = 5;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Channel {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: u8,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub topic: Option<String>,
+    #[serde(default)]
+    pub recipients: Vec<User>,
+    #[serde(default)]
+    pub last_message_id: Option<String>,
+    #[serde(default)]
+    pub guild_id: Option<String>,
+    /// Who created a group DM.
+    #[serde(default)]
+    pub owner_id: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Guild {
+    pub id: String,
+    pub name: String,
+    /// Only sent when fetching the guild itself, not when listing them.
+    #[serde(default)]
+    pub owner_id: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Attachment {
+    pub id: String,
+    pub filename: String,
+    pub size: u64,
+    pub url: String,
+    #[serde(default)]
+    pub content_type: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct DiscordMessage {
+    pub id: String,
+    pub channel_id: String,
+    #[serde(default)]
+    pub guild_id: Option<String>,
+    pub author: User,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    #[serde(default)]
+    pub referenced_message: Option<Box<DiscordMessage>>,
+}
+
+/// The changed fields of an edited message.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MessageUpdate {
+    pub id: String,
+    pub channel_id: String,
+    #[serde(default)]
+    pub content: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Emoji {
+    /// Unicode emoji have a name but no id, custom emoji aren't supported.
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ReactionEvent {
+    pub user_id: String,
+    pub channel_id: String,
+    pub message_id: String,
+    pub emoji: Emoji,
+    /// Only sent for added reactions.
+    #[serde(default)]
+    pub message_author_id: Option<String>,
+}
+
+/// A client for the REST API.
+#[derive(Debug, Clone)]
+pub struct Api {
+    client: Client,
+}
+
+impl Api {
+    pub fn new(authorization: &str) -> Result<Self> {
+        let failure = |e: &dyn std::fmt::Display| {
+            Error::Failure("Failed to create Discord client".to_owned(), e.to_string())
+        };
+        let mut headers = header::HeaderMap::new();
+        let mut authorization =
+            header::HeaderValue::from_str(authorization).map_err(|e| failure(&e))?;
+        authorization.set_sensitive(true);
+        headers.insert(header::AUTHORIZATION, authorization);
+        let client = Client::builder()
+            .default_headers(headers)
+            .user_agent("DiscordBot (https://github.com/jeffa5/chatters, 0.1.0)")
+            .build()
+            .map_err(|e| failure(&e))?;
+        Ok(Self { client })
+    }
+
+    pub async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        self.json(Method::GET, path, None).await
+    }
+
+    pub async fn post<T: DeserializeOwned>(&self, path: &str, body: &Value) -> Result<T> {
+        self.json(Method::POST, path, Some(body)).await
+    }
+
+    pub async fn patch<T: DeserializeOwned>(&self, path: &str, body: &Value) -> Result<T> {
+        self.json(Method::PATCH, path, Some(body)).await
+    }
+
+    pub async fn put(&self, path: &str) -> Result<()> {
+        self.send(|| self.client.put(format!("{API}{path}")))
+            .await
+            .map(drop)
+    }
+
+    pub async fn delete(&self, path: &str) -> Result<()> {
+        self.send(|| self.client.delete(format!("{API}{path}")))
+            .await
+            .map(drop)
+    }
+
+    /// Post a message with files, which have to be sent as a multipart form.
+    pub async fn post_files<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        payload: &Value,
+        files: &[(String, Vec<u8>)],
+    ) -> Result<T> {
+        let form = || {
+            let mut form =
+                reqwest::multipart::Form::new().text("payload_json", payload.to_string());
+            for (i, (name, contents)) in files.iter().enumerate() {
+                let part =
+                    reqwest::multipart::Part::bytes(contents.clone()).file_name(name.clone());
+                form = form.part(format!("files[{i}]"), part);
+            }
+            form
+        };
+        let response = self
+            .send(|| self.client.post(format!("{API}{path}")).multipart(form()))
+            .await?;
+        parse(response).await
+    }
+
+    /// Download the contents of an attachment from the CDN.
+    /// Download a file from the CDN, calling `progress` with how many bytes have arrived so far.
+    pub async fn download(&self, url: &str, progress: impl Fn(u64)) -> Result<Vec<u8>> {
+        let failure = |e: reqwest::Error| {
+            Error::Failure("Failed to download attachment".to_owned(), e.to_string())
+        };
+        // the CDN doesn't want the api token
+        let mut response = Client::new()
+            .get(url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(failure)?;
+        let mut contents = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(failure)? {
+            contents.extend_from_slice(&chunk);
+            progress(contents.len() as u64);
+        }
+        Ok(contents)
+    }
+
+    async fn json<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<T> {
+        let response = self
+            .send(|| {
+                let request = self.client.request(method.clone(), format!("{API}{path}"));
+                match body {
+                    Some(body) => request.json(body),
+                    None => request,
+                }
+            })
+            .await?;
+        parse(response).await
+    }
+
+    /// Send the request, waiting out rate limits.
+    async fn send(&self, request: impl Fn() -> RequestBuilder) -> Result<reqwest::Response> {
+        let failure = |e: &dyn std::fmt::Display| {
+            Error::Failure("Discord request failed".to_owned(), e.to_string())
+        };
+        loop {
+            let response = request().send().await.map_err(|e| failure(&e))?;
+            let status = response.status();
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                let retry_after = response
+                    .json::<Value>()
+                    .await
+                    .ok()
+                    .and_then(|v| v["retry_after"].as_f64())
+                    .unwrap_or(1.0);
+                debug!(retry_after; "Rate limited by Discord");
+                tokio::time::sleep(Duration::from_secs_f64(retry_after)).await;
+                continue;
+            }
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                warn!(status:%, body:%; "Discord request failed");
+                return Err(failure(&format!("{status}: {body}")));
+            }
+            return Ok(response);
+        }
+    }
+}
+
+async fn parse<T: DeserializeOwned>(response: reqwest::Response) -> Result<T> {
+    response.json().await.map_err(|e| {
+        Error::Failure(
+            "Failed to parse response from Discord".to_owned(),
+            e.to_string(),
+        )
+    })
+}
+
+/// Percent encode a path segment, such as an emoji.
+pub fn encode_path(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-_.~:".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{b:02X}")
+            }
+        })
+        .collect()
+}
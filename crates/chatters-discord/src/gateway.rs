@@ -0,0 +1,211 @@
+use std::time::Duration;
+
+use futures::channel::mpsc;
+use futures::{SinkExt as _, StreamExt as _};
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+use crate::api::{DiscordMessage, MessageUpdate, ReactionEvent};
+
+const GATEWAY: &str = "wss://gateway.discord.gg";
+const GATEWAY_QUERY: &str = "/?v=10&encoding=json";
+
+/// Guilds, guild messages, guild message reactions, direct messages, direct message reactions and
+/// message content, which bots have to ask for.
+const BOT_INTENTS: u64 = (1 << 0) | (1 << 9) | (1 << 10) | (1 << 12) | (1 << 13) | (1 << 15);
+
+/// How long to wait before reconnecting after losing the connection.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+const OP_DISPATCH: u64 = 0;
+const OP_HEARTBEAT: u64 = 1;
+const OP_IDENTIFY: u64 = 2;
+const OP_RESUME: u64 = 6;
+const OP_RECONNECT: u64 = 7;
+const OP_INVALID_SESSION: u64 = 9;
+const OP_HELLO: u64 = 10;
+const OP_HEARTBEAT_ACK: u64 = 11;
+
+/// Events from the gateway that the backend cares about.
+#[derive(Debug)]
+pub enum Event {
+    /// Connected, either afresh or by resuming the previous session so that nothing was missed.
+    Connected,
+    Disconnected,
+    MessageCreate(DiscordMessage),
+    MessageUpdate(MessageUpdate),
+    ReactionAdd(ReactionEvent),
+    ReactionRemove(ReactionEvent),
+}
+
+/// A session that can be resumed after a disconnection, replaying the events missed.
+struct Session {
+    id: String,
+    resume_url: String,
+}
+
+/// Why a connection ended.
+enum Closed {
+    /// The session can be resumed on a new connection.
+    Resumable,
+    /// A new session is needed.
+    Invalid,
+}
+
+/// Stay connected to the gateway, sending the events received until the receiver is dropped.
+pub async fn run(token: String, bot: bool, events: mpsc::UnboundedSender<Event>) {
+    let mut session = None;
+    let mut sequence = None;
+    loop {
+        match connect(&token, bot, &mut session, &mut sequence, &events).await {
+            Ok(Closed::Resumable) => {}
+            Ok(Closed::Invalid) => {
+                session = None;
+                sequence = None;
+            }
+            Err(error) => warn!(error:%; "Discord gateway connection failed"),
+        }
+        if events.unbounded_send(Event::Disconnected).is_err() {
+            return;
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn connect(
+    token: &str,
+    bot: bool,
+    session: &mut Option<Session>,
+    sequence: &mut Option<u64>,
+    events: &mpsc::UnboundedSender<Event>,
+) -> Result<Closed, tokio_tungstenite::tungstenite::Error> {
+    let base = session.as_ref().map_or(GATEWAY, |s| &s.resume_url);
+    let (socket, _) = tokio_tungstenite::connect_async(format!("{base}{GATEWAY_QUERY}")).await?;
+    let (mut write, mut read) = socket.split();
+
+    let mut heartbeat = None;
+    let mut acknowledged = true;
+    loop {
+        let tick = async {
+            match &mut heartbeat {
+                Some(interval) => {
+                    tokio::time::Interval::tick(interval).await;
+                }
+                None => std::future::pending().await,
+            }
+        };
+        let frame = tokio::select! {
+            () = tick => {
+                if !acknowledged {
+                    debug!("Discord gateway stopped acknowledging heartbeats");
+                    return Ok(Closed::Resumable);
+                }
+                acknowledged = false;
+                write.send(payload(OP_HEARTBEAT, json!(sequence))).await?;
+                continue;
+            }
+            frame = read.next() => frame,
+        };
+        let text = match frame {
+            Some(Ok(WsMessage::Text(text))) => text,
+            Some(Ok(WsMessage::Close(close))) => {
+                debug!(close:?; "Discord gateway closed the connection");
+                return Ok(Closed::Resumable);
+            }
+            Some(Ok(_)) => continue,
+            Some(Err(error)) => return Err(error),
+            None => return Ok(Closed::Resumable),
+        };
+        let Ok(frame) = serde_json::from_str::<Value>(&text) else {
+            warn!(text:%; "Invalid frame from Discord gateway");
+            continue;
+        };
+        if let Some(s) = frame["s"].as_u64() {
+            *sequence = Some(s);
+        }
+        match frame["op"].as_u64() {
+            Some(OP_HELLO) => {
+                let interval = frame["d"]["heartbeat_interval"].as_u64().unwrap_or(41_250);
+                let mut interval = tokio::time::interval(Duration::from_millis(interval));
+                // wait a whole interval before the first heartbeat
+                interval.reset();
+                heartbeat = Some(interval);
+                let handshake = match (&session, &sequence) {
+                    (Some(session), Some(sequence)) => payload(
+                        OP_RESUME,
+                        json!({"token": token, "session_id": session.id, "seq": sequence}),
+                    ),
+                    _ => identify(token, bot),
+                };
+                write.send(handshake).await?;
+            }
+            Some(OP_HEARTBEAT) => {
+                write.send(payload(OP_HEARTBEAT, json!(sequence))).await?;
+            }
+            Some(OP_HEARTBEAT_ACK) => acknowledged = true,
+            Some(OP_RECONNECT) => return Ok(Closed::Resumable),
+            Some(OP_INVALID_SESSION) => {
+                return Ok(if frame["d"].as_bool().unwrap_or_default() {
+                    Closed::Resumable
+                } else {
+                    Closed::Invalid
+                });
+            }
+            Some(OP_DISPATCH) => {
+                let kind = frame["t"].as_str().unwrap_or_default();
+                let data = frame["d"].clone();
+                if kind == "READY" {
+                    info!("Connected to Discord gateway");
+                    *session = Some(Session {
+                        id: data["session_id"].as_str().unwrap_or_default().to_owned(),
+                        resume_url: data["resume_gateway_url"]
+                            .as_str()
+                            .unwrap_or(GATEWAY)
+                            .to_owned(),
+                    });
+                }
+                let Some(event) = dispatch(kind, data) else {
+                    continue;
+                };
+                if events.unbounded_send(event).is_err() {
+                    return Ok(Closed::Invalid);
+                }
+            }
+            op => debug!(op:?; "Ignoring frame from Discord gateway"),
+        }
+    }
+}
+
+fn dispatch(kind: &str, data: Value) -> Option<Event> {
+    let parsed = match kind {
+        // resuming replays the events missed before sending this
+        "READY" | "RESUMED" => return Some(Event::Connected),
+        "MESSAGE_CREATE" => serde_json::from_value(data).map(Event::MessageCreate),
+        "MESSAGE_UPDATE" => serde_json::from_value(data).map(Event::MessageUpdate),
+        "MESSAGE_REACTION_ADD" => serde_json::from_value(data).map(Event::ReactionAdd),
+        "MESSAGE_REACTION_REMOVE" => serde_json::from_value(data).map(Event::ReactionRemove),
+        _ => return None,
+    };
+    parsed
+        .inspect_err(|error| warn!(error:%, kind; "Invalid event from Discord gateway"))
+        .ok()
+}
+
+fn identify(token: &str, bot: bool) -> WsMessage {
+    let properties = json!({
+        "os": std::env::consts::OS,
+        "browser": "chatters",
+        "device": "chatters",
+    });
+    let data = if bot {
+        json!({"token": token, "intents": BOT_INTENTS, "properties": properties})
+    } else {
+        json!({"token": token, "properties": properties})
+    };
+    payload(OP_IDENTIFY, data)
+}
+
+fn payload(op: u64, data: Value) -> WsMessage {
+    WsMessage::text(json!({"op": op, "d": data}).to_string())
+}
@@ -0,0 +1,854 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::{Bound, RangeBounds as _};
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use chatters_lib::backends::sort_contacts;
+use chatters_lib::backends::timestamp;
+use chatters_lib::backends::Backend;
+use chatters_lib::backends::Connectivity;
+use chatters_lib::backends::Contact;
+use chatters_lib::backends::ContactDetails;
+use chatters_lib::backends::ContactId;
+use chatters_lib::backends::Error;
+use chatters_lib::backends::Invite;
+use chatters_lib::backends::Limits;
+use chatters_lib::backends::LoadProgress;
+use chatters_lib::backends::Message;
+use chatters_lib::backends::MessageAttachment;
+use chatters_lib::backends::MessageContent;
+use chatters_lib::backends::Progress;
+use chatters_lib::backends::Quote;
+use chatters_lib::backends::Receipt;
+use chatters_lib::backends::Result;
+use chatters_lib::backends::Setup;
+use chatters_lib::backends::SetupQuestion;
+use chatters_lib::backends::Verification;
+use chatters_lib::message::FrontendMessage;
+use futures::channel::{mpsc, oneshot};
+use futures::StreamExt as _;
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+
+use crate::account::Account;
+use crate::api::{
+    encode_path, snowflake_timestamp, timestamp_snowflake, Api, Attachment, Channel,
+    DiscordMessage, Guild, ReactionEvent, User,
+};
+use crate::gateway::Event;
+
+mod account;
+mod api;
+mod gateway;
+
+/// Messages per page of history, the most the API allows.
+const PAGE_SIZE: usize = 100;
+
+/// Discord, with guild channels and group DMs as groups and DMs as contacts.
+#[derive(Clone)]
+pub struct Discord {
+    api: Api,
+    account: Account,
+    self_user: User,
+    state: Arc<Mutex<State>>,
+    attachments_dir: PathBuf,
+    /// Where to tell the ui about new people found when loading messages, set once syncing.
+    updates: Arc<Mutex<Option<mpsc::UnboundedSender<FrontendMessage>>>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Everyone seen so far, by user id, so that the ui knows every sender.
+    people: BTreeMap<String, User>,
+    /// The conversation for each channel, keyed by channel id.
+    conversations: BTreeMap<String, Contact>,
+    /// The author of each message seen, by message id.
+    authors: HashMap<String, String>,
+    /// The id of each message seen, by channel id and timestamp.
+    ids: HashMap<(String, u64), String>,
+    /// Ids of the messages sent or edited from here, which come back through the gateway.
+    sent: HashSet<String>,
+    /// The attachments seen, indexed by attachment index.
+    attachments: Vec<Attachment>,
+}
+
+impl Backend for Discord {
+    const NAME: &'static str = "Discord";
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn load(path: &Path, _progress: LoadProgress) -> Result<Self> {
+        let account = Account::load(path)?;
+        let api = Api::new(&account.authorization(&account.token()?))?;
+        let self_user: User = api.get("/users/@me").await?;
+        info!(user = self_user.username; "Logged in to Discord");
+
+        let mut state = State::default();
+        state.add_person(&self_user);
+
+        // bots can't list their direct messages, they turn up as messages arrive
+        let private: Vec<Channel> = api
+            .get("/users/@me/channels")
+            .await
+            .unwrap_or_else(|error| {
+                warn!(error:%; "Failed to list direct messages");
+                Vec::new()
+            });
+        for channel in private {
+            state.add_private_channel(&channel, &self_user.id);
+        }
+
+        let guilds: Vec<Guild> = api.get("/users/@me/guilds").await?;
+        for guild in guilds {
+            let channels: Vec<Channel> =
+                match api.get(&format!("/guilds/{}/channels", guild.id)).await {
+                    Ok(channels) => channels,
+                    Err(error) => {
+                        warn!(error:%, guild = guild.name; "Failed to list guild channels");
+                        continue;
+                    }
+                };
+            for channel in channels {
+                if !matches!(
+                    channel.kind,
+                    api::CHANNEL_GUILD_TEXT | api::CHANNEL_GUILD_ANNOUNCEMENT
+                ) {
+                    continue;
+                }
+                let name = channel.name.clone().unwrap_or_default();
+                state.conversations.insert(
+                    channel.id.clone(),
+                    Contact {
+                        id: ContactId::Group(channel.id.clone().into_bytes()),
+                        name: format!("{} #{name}", guild.name),
+                        address: format!("#{name}"),
+                        last_message_timestamp: channel
+                            .last_message_id
+                            .as_deref()
+                            .map(snowflake_timestamp),
+                        description: channel.topic.clone().unwrap_or(guild.name.clone()),
+                        muted_until: None,
+                        verification: Verification::Unverified,
+                        members: None,
+                        encrypted: false,
+                    },
+                );
+            }
+        }
+        info!(count = state.conversations.len(); "Loaded conversations");
+
+        Ok(Self {
+            api,
+            account,
+            self_user,
+            state: Arc::new(Mutex::new(state)),
+            attachments_dir: path.join("attachments"),
+            updates: Arc::default(),
+        })
+    }
+
+    fn setup_questions() -> Vec<SetupQuestion> {
+        Account::setup_questions()
+    }
+
+    async fn link(
+        path: &Path,
+        setup: &Setup,
+        _provisioning_link_tx: oneshot::Sender<String>,
+    ) -> Result<Self> {
+        let account = Account::from_setup(setup);
+        account.save(path)?;
+        Self::load(path, LoadProgress::default()).await
+    }
+
+    async fn background_sync(
+        &mut self,
+        ba_tx: mpsc::UnboundedSender<FrontendMessage>,
+    ) -> Result<()> {
+        *self.updates.lock().unwrap() = Some(ba_tx.clone());
+        let (events_tx, mut events) = mpsc::unbounded();
+        let gateway = tokio::spawn(gateway::run(
+            self.account.token()?,
+            self.account.bot,
+            events_tx,
+        ));
+
+        while let Some(event) = events.next().await {
+            let (message, changed) = match event {
+                Event::Connected => {
+                    ba_tx
+                        .unbounded_send(FrontendMessage::Connectivity {
+                            connectivity: Connectivity::Online,
+                        })
+                        .unwrap();
+                    continue;
+                }
+                Event::Disconnected => {
+                    // the gateway tries again by itself
+                    ba_tx
+                        .unbounded_send(FrontendMessage::Connectivity {
+                            connectivity: Connectivity::Reconnecting,
+                        })
+                        .unwrap();
+                    continue;
+                }
+                Event::MessageCreate(message) => {
+                    let mut state = self.state.lock().unwrap();
+                    if state.sent.remove(&message.id) {
+                        // already given to the ui when sending
+                        continue;
+                    }
+                    let known = state.known();
+                    let message = state.add_message(message, &self.self_user.id);
+                    (message, state.known() != known)
+                }
+                Event::MessageUpdate(update) => {
+                    let mut state = self.state.lock().unwrap();
+                    if state.sent.remove(&update.id) {
+                        continue;
+                    }
+                    let message = update.content.and_then(|text| {
+                        let author = state.authors.get(&update.id)?;
+                        Some(Message {
+                            timestamp: snowflake_timestamp(&update.id),
+                            sender: author.clone().into_bytes(),
+                            contact_id: state.conversations.get(&update.channel_id)?.id.clone(),
+                            content: MessageContent::Edit {
+                                timestamp: timestamp(),
+                                text,
+                                mentions: Vec::new(),
+                                formatting: Vec::new(),
+                            },
+                            quote: None,
+                        })
+                    });
+                    (message, false)
+                }
+                Event::ReactionAdd(reaction) => {
+                    let state = self.state.lock().unwrap();
+                    (state.reaction(reaction, false), false)
+                }
+                Event::ReactionRemove(reaction) => {
+                    let state = self.state.lock().unwrap();
+                    (state.reaction(reaction, true), false)
+                }
+            };
+            let Some(message) = message else {
+                continue;
+            };
+            if changed {
+                self.send_contacts(&ba_tx);
+            }
+            ba_tx
+                .unbounded_send(FrontendMessage::NewMessage { message })
+                .unwrap();
+        }
+        // the gateway reconnects by itself, so the events only end if its task died, which a
+        // failure brings back with a new one
+        gateway.abort();
+        Err(Error::Failure(
+            "Gateway stopped".to_owned(),
+            "the connection to discord ended".to_owned(),
+        ))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn users(&self) -> Result<Vec<Contact>> {
+        Ok(self.state.lock().unwrap().users())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn groups(&self) -> Result<Vec<Contact>> {
+        Ok(self.state.lock().unwrap().groups())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn messages(
+        &mut self,
+        contact_id: ContactId,
+        start_ts: Bound<u64>,
+        end_ts: Bound<u64>,
+    ) -> Result<Vec<Message>> {
+        let Some(channel) = self.state.lock().unwrap().channel_of(&contact_id) else {
+            // no direct messages with them yet
+            return Ok(Vec::new());
+        };
+        let mut query = format!("?limit={PAGE_SIZE}");
+        match (start_ts, end_ts) {
+            (_, Bound::Included(end)) => {
+                query.push_str(&format!("&before={}", timestamp_snowflake(end + 1)))
+            }
+            (_, Bound::Excluded(end)) => {
+                query.push_str(&format!("&before={}", timestamp_snowflake(end)))
+            }
+            (Bound::Included(start) | Bound::Excluded(start), Bound::Unbounded) => {
+                query.push_str(&format!("&after={}", timestamp_snowflake(start)))
+            }
+            (Bound::Unbounded, Bound::Unbounded) => {}
+        }
+        let history: Vec<DiscordMessage> = self
+            .api
+            .get(&format!("/channels/{channel}/messages{query}"))
+            .await?;
+
+        let (mut messages, changed) = {
+            let mut state = self.state.lock().unwrap();
+            let known = state.known();
+            let messages = history
+                .into_iter()
+                .filter_map(|m| state.add_message(m, &self.self_user.id))
+                .filter(|m| (start_ts, end_ts).contains(&m.timestamp))
+                .collect::<Vec<_>>();
+            (messages, state.known() != known)
+        };
+        if changed {
+            if let Some(updates) = &*self.updates.lock().unwrap() {
+                // the senders need to be known before the messages are shown
+                self.send_contacts(updates);
+            }
+        }
+        messages.sort_by_key(|m| m.timestamp);
+        Ok(messages)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, content, quoting)))]
+    async fn send_message(
+        &mut self,
+        contact_id: ContactId,
+        content: MessageContent,
+        quoting: Option<&Quote>,
+    ) -> Result<Message> {
+        let channel = self.channel_for_sending(&contact_id).await?;
+        match content {
+            MessageContent::Text {
+                text, attachments, ..
+            } => {
+                let reference = quoting
+                    .and_then(|q| self.message_id(&channel, q.timestamp))
+                    .map(|id| json!({"message_id": id}));
+                let payload = json!({"content": text, "message_reference": reference});
+                let path = format!("/channels/{channel}/messages");
+                let sent: DiscordMessage = if attachments.is_empty() {
+                    self.api.post(&path, &payload).await?
+                } else {
+                    let files = attachments
+                        .iter()
+                        .filter_map(|a| a.path.as_ref())
+                        .map(|path| {
+                            let name = path.file_name().map_or_else(
+                                || "attachment".to_owned(),
+                                |n| n.to_string_lossy().into_owned(),
+                            );
+                            std::fs::read(path).map(|contents| (name, contents))
+                        })
+                        .collect::<std::io::Result<Vec<_>>>()
+                        .map_err(|e| {
+                            Error::Failure("Failed to read attachment".to_owned(), e.to_string())
+                        })?;
+                    self.api.post_files(&path, &payload, &files).await?
+                };
+                let mut state = self.state.lock().unwrap();
+                state.sent.insert(sent.id.clone());
+                state.add_message(sent, &self.self_user.id).ok_or_else(|| {
+                    Error::Failure(
+                        "Failed to record sent message".to_owned(),
+                        "unknown channel".to_owned(),
+                    )
+                })
+            }
+            MessageContent::Reaction {
+                message_author,
+                timestamp: target,
+                reaction,
+                remove,
+            } => {
+                let id = self.message_id_or_err(&channel, target)?;
+                let path = format!(
+                    "/channels/{channel}/messages/{id}/reactions/{}/@me",
+                    encode_path(&reaction)
+                );
+                if remove {
+                    self.api.delete(&path).await?;
+                } else {
+                    self.api.put(&path).await?;
+                }
+                Ok(Message {
+                    timestamp: timestamp(),
+                    sender: self.self_user.id.clone().into_bytes(),
+                    contact_id,
+                    content: MessageContent::Reaction {
+                        message_author,
+                        timestamp: target,
+                        reaction,
+                        remove,
+                    },
+                    quote: None,
+                })
+            }
+            MessageContent::Edit {
+                timestamp: target,
+                text,
+                ..
+            } => {
+                let id = self.message_id_or_err(&channel, target)?;
+                self.state.lock().unwrap().sent.insert(id.clone());
+                self.api
+                    .patch::<Value>(
+                        &format!("/channels/{channel}/messages/{id}"),
+                        &json!({ "content": text }),
+                    )
+                    .await?;
+                Ok(Message {
+                    timestamp: target,
+                    sender: self.self_user.id.clone().into_bytes(),
+                    contact_id,
+                    content: MessageContent::Edit {
+                        timestamp: timestamp(),
+                        text,
+                        mentions: Vec::new(),
+                        formatting: Vec::new(),
+                    },
+                    quote: None,
+                })
+            }
+            MessageContent::Unsupported { .. } | MessageContent::Call { .. } => {
+                Err(Error::Failure(
+                    "Failed to send message".to_owned(),
+                    "calls and unsupported messages can't be sent".to_owned(),
+                ))
+            }
+        }
+    }
+
+    async fn self_id(&self) -> Vec<u8> {
+        self.self_user.id.clone().into_bytes()
+    }
+
+    fn limits(&self) -> Limits {
+        Limits {
+            max_body_length: Some(2000),
+            max_attachment_size: Some(10_000_000),
+            max_attachment_count: Some(10),
+            // custom emoji are sent as `name:id`
+            any_reaction: true,
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn download_attachment(
+        &self,
+        attachment_index: usize,
+        progress: Progress,
+    ) -> Result<PathBuf> {
+        let Some(attachment) = self
+            .state
+            .lock()
+            .unwrap()
+            .attachments
+            .get(attachment_index)
+            .cloned()
+        else {
+            return Err(Error::UnknownAttachment(attachment_index));
+        };
+        // keep attachments with the same name apart
+        let file_path = self.attachments_dir.join(&attachment.id).join(
+            Path::new(&attachment.filename)
+                .file_name()
+                .unwrap_or("attachment".as_ref()),
+        );
+        if file_path.is_file() {
+            // already downloaded
+            return Ok(file_path);
+        }
+        let contents = self
+            .api
+            .download(&attachment.url, |downloaded| {
+                progress.report(downloaded, attachment.size)
+            })
+            .await?;
+        std::fs::create_dir_all(file_path.parent().unwrap())
+            .and_then(|()| std::fs::write(&file_path, contents))
+            .map_err(|e| Error::Failure("Failed to save attachment".to_owned(), e.to_string()))?;
+        Ok(file_path)
+    }
+
+    async fn pin_message(
+        &mut self,
+        contact_id: ContactId,
+        timestamp: u64,
+        pinned: bool,
+    ) -> Result<()> {
+        let channel = self.channel_for_sending(&contact_id).await?;
+        let id = self.message_id_or_err(&channel, timestamp)?;
+        let path = format!("/channels/{channel}/pins/{id}");
+        if pinned {
+            self.api.put(&path).await
+        } else {
+            self.api.delete(&path).await
+        }
+    }
+
+    async fn delete_message(&mut self, contact_id: ContactId, timestamp: u64) -> Result<()> {
+        let channel = self.channel_for_sending(&contact_id).await?;
+        let id = self.message_id_or_err(&channel, timestamp)?;
+        self.api
+            .delete(&format!("/channels/{channel}/messages/{id}"))
+            .await
+    }
+
+    async fn pinned_messages(&self, contact_id: ContactId) -> Result<Vec<u64>> {
+        let Some(channel) = self.state.lock().unwrap().channel_of(&contact_id) else {
+            return Ok(Vec::new());
+        };
+        let pinned: Vec<DiscordMessage> =
+            self.api.get(&format!("/channels/{channel}/pins")).await?;
+        Ok(pinned.iter().map(|m| snowflake_timestamp(&m.id)).collect())
+    }
+
+    async fn find_user(&mut self, _query: String) -> Result<Contact> {
+        Err(Error::Failure(
+            format!("{} doesn't support finding users", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn message_receipts(
+        &self,
+        _contact_id: ContactId,
+        _timestamp: u64,
+    ) -> Result<Vec<Receipt>> {
+        // Discord doesn't tell anyone when their messages are read
+        Ok(Vec::new())
+    }
+
+    async fn contact_details(&self, contact_id: ContactId) -> Result<ContactDetails> {
+        let mut details = ContactDetails::default();
+        if !matches!(contact_id, ContactId::Group(_)) {
+            return Ok(details);
+        }
+        let Some(channel) = self.state.lock().unwrap().channel_of(&contact_id) else {
+            return Ok(details);
+        };
+        let channel: Channel = self.api.get(&format!("/channels/{channel}")).await?;
+        let owner_id = match &channel.guild_id {
+            Some(guild_id) => {
+                let guild: Guild = self.api.get(&format!("/guilds/{guild_id}")).await?;
+                guild.owner_id
+            }
+            None => channel.owner_id,
+        };
+        let role = if owner_id.as_ref() == Some(&self.self_user.id) {
+            "owner"
+        } else {
+            "member"
+        };
+        details.role = Some(role.to_owned());
+        Ok(details)
+    }
+
+    async fn verify_device(&mut self) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support verifying devices", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn confirm_verification(&mut self, _confirmed: bool) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support verifying devices", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn invites(&self) -> Result<Vec<Invite>> {
+        Ok(Vec::new())
+    }
+
+    async fn answer_invite(&mut self, _contact_id: ContactId, _accept: bool) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support invites", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn set_group_title(&mut self, _contact_id: ContactId, _title: String) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support changing groups", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn set_group_description(
+        &mut self,
+        _contact_id: ContactId,
+        _description: String,
+    ) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support changing groups", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn request_contact_sync(&mut self) -> Result<()> {
+        // the gateway keeps the channels up to date, loading them again is enough
+        Ok(())
+    }
+
+    async fn conversation_summaries(&self) -> Result<Vec<Message>> {
+        // channels only give the id of their latest message, and fetching each one would run into
+        // the rate limits, so the previews fill in as messages arrive instead
+        Ok(Vec::new())
+    }
+}
+
+impl Discord {
+    fn send_contacts(&self, ba_tx: &mpsc::UnboundedSender<FrontendMessage>) {
+        let mut contacts = {
+            let state = self.state.lock().unwrap();
+            let mut contacts = state.users();
+            contacts.append(&mut state.groups());
+            contacts
+        };
+        sort_contacts(&mut contacts);
+        let _ = ba_tx.unbounded_send(FrontendMessage::LoadedContacts { contacts });
+    }
+
+    /// The channel to send to, opening a direct message channel with a person if there isn't one
+    /// yet.
+    async fn channel_for_sending(&self, contact_id: &ContactId) -> Result<String> {
+        if let Some(channel) = self.state.lock().unwrap().channel_of(contact_id) {
+            return Ok(channel);
+        }
+        let ContactId::User(user) = contact_id else {
+            return Err(Error::Failure(
+                "Failed to find channel".to_owned(),
+                contact_id.to_string(),
+            ));
+        };
+        let recipient = String::from_utf8_lossy(user);
+        debug!(recipient:%; "Opening direct message channel");
+        let channel: Channel = self
+            .api
+            .post("/users/@me/channels", &json!({ "recipient_id": recipient }))
+            .await?;
+        self.state
+            .lock()
+            .unwrap()
+            .add_private_channel(&channel, &self.self_user.id);
+        Ok(channel.id)
+    }
+
+    fn message_id(&self, channel: &str, timestamp: u64) -> Option<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .ids
+            .get(&(channel.to_owned(), timestamp))
+            .cloned()
+    }
+
+    fn message_id_or_err(&self, channel: &str, timestamp: u64) -> Result<String> {
+        self.message_id(channel, timestamp).ok_or_else(|| {
+            Error::Failure(
+                "Failed to find message".to_owned(),
+                format!("no message at {timestamp}"),
+            )
+        })
+    }
+}
+
+impl State {
+    /// How many people and conversations are known, to tell when the ui needs updating.
+    fn known(&self) -> (usize, usize) {
+        (self.people.len(), self.conversations.len())
+    }
+
+    fn add_person(&mut self, user: &User) {
+        self.people
+            .entry(user.id.clone())
+            .or_insert_with(|| user.clone());
+    }
+
+    fn add_private_channel(&mut self, channel: &Channel, self_id: &str) {
+        for recipient in &channel.recipients {
+            self.add_person(recipient);
+        }
+        let last_message_timestamp = channel.last_message_id.as_deref().map(snowflake_timestamp);
+        let contact = match channel.kind {
+            api::CHANNEL_DM => {
+                let Some(recipient) = channel.recipients.iter().find(|r| r.id != self_id) else {
+                    return;
+                };
+                Contact {
+                    id: ContactId::User(recipient.id.clone().into_bytes()),
+                    name: recipient.display_name().to_owned(),
+                    address: format!("@{}", recipient.username),
+                    last_message_timestamp,
+                    description: String::new(),
+                    muted_until: None,
+                    verification: Verification::Unverified,
+                    members: None,
+                    encrypted: false,
+                }
+            }
+            api::CHANNEL_GROUP_DM => {
+                let mut names = channel
+                    .recipients
+                    .iter()
+                    .map(User::display_name)
+                    .collect::<Vec<_>>();
+                names.sort_unstable();
+                Contact {
+                    id: ContactId::Group(channel.id.clone().into_bytes()),
+                    name: channel.name.clone().unwrap_or_else(|| names.join(", ")),
+                    address: channel.id.clone(),
+                    last_message_timestamp,
+                    description: names.join(", "),
+                    muted_until: None,
+                    verification: Verification::Unverified,
+                    members: None,
+                    encrypted: false,
+                }
+            }
+            _ => return,
+        };
+        self.conversations.insert(channel.id.clone(), contact);
+    }
+
+    /// The channel for a conversation, if there is one.
+    fn channel_of(&self, contact_id: &ContactId) -> Option<String> {
+        self.conversations
+            .iter()
+            .find(|(_, c)| &c.id == contact_id)
+            .map(|(channel, _)| channel.clone())
+    }
+
+    /// Everyone seen, using their direct message conversation where there is one.
+    fn users(&self) -> Vec<Contact> {
+        self.people
+            .values()
+            .map(|user| {
+                let id = ContactId::User(user.id.clone().into_bytes());
+                self.conversations
+                    .values()
+                    .find(|c| c.id == id)
+                    .cloned()
+                    .unwrap_or_else(|| Contact {
+                        id,
+                        name: user.display_name().to_owned(),
+                        address: format!("@{}", user.username),
+                        last_message_timestamp: None,
+                        description: String::new(),
+                        muted_until: None,
+                        verification: Verification::Unverified,
+                        members: None,
+                        encrypted: false,
+                    })
+            })
+            .collect()
+    }
+
+    fn groups(&self) -> Vec<Contact> {
+        self.conversations
+            .values()
+            .filter(|c| matches!(c.id, ContactId::Group(_)))
+            .cloned()
+            .collect()
+    }
+
+    /// Remember a message with its author and attachments, converting it for the ui, or `None` if it
+    /// isn't in a conversation we show.
+    fn add_message(&mut self, message: DiscordMessage, self_id: &str) -> Option<Message> {
+        let timestamp = snowflake_timestamp(&message.id);
+        self.add_person(&message.author);
+        if !self.conversations.contains_key(&message.channel_id)
+            && message.guild_id.is_none()
+            && message.author.id != self_id
+        {
+            // the first message of a new direct message conversation
+            let channel = Channel {
+                id: message.channel_id.clone(),
+                kind: api::CHANNEL_DM,
+                name: None,
+                topic: None,
+                recipients: vec![message.author.clone()],
+                last_message_id: None,
+                guild_id: None,
+                owner_id: None,
+            };
+            self.add_private_channel(&channel, self_id);
+        }
+        let conversation = self.conversations.get_mut(&message.channel_id)?;
+        conversation.last_message_timestamp =
+            conversation.last_message_timestamp.max(Some(timestamp));
+        let contact_id = conversation.id.clone();
+
+        self.authors
+            .insert(message.id.clone(), message.author.id.clone());
+        self.ids
+            .insert((message.channel_id.clone(), timestamp), message.id.clone());
+
+        let attachments = message
+            .attachments
+            .into_iter()
+            .map(|attachment| {
+                let index = self.attachments.len();
+                let converted = MessageAttachment {
+                    name: attachment.filename.clone(),
+                    size: attachment.size,
+                    index,
+                    path: None,
+                    content_type: attachment.content_type.clone(),
+                };
+                self.attachments.push(attachment);
+                converted
+            })
+            .collect();
+        let quote = message.referenced_message.map(|quoted| {
+            self.add_person(&quoted.author);
+            Quote {
+                timestamp: snowflake_timestamp(&quoted.id),
+                sender: quoted.author.id.into_bytes(),
+                text: quoted.content,
+                mentions: Vec::new(),
+                formatting: Vec::new(),
+            }
+        });
+        Some(Message {
+            timestamp,
+            sender: message.author.id.into_bytes(),
+            contact_id,
+            content: MessageContent::Text {
+                text: message.content,
+                attachments,
+                mentions: Vec::new(),
+                formatting: Vec::new(),
+            },
+            quote,
+        })
+    }
+
+    fn reaction(&self, reaction: ReactionEvent, remove: bool) -> Option<Message> {
+        let author = self
+            .authors
+            .get(&reaction.message_id)
+            .cloned()
+            .or(reaction.message_author_id)?;
+        if !self.people.contains_key(&reaction.user_id) {
+            // the ui has to know who reacted
+            return None;
+        }
+        Some(Message {
+            timestamp: timestamp(),
+            sender: reaction.user_id.into_bytes(),
+            contact_id: self.conversations.get(&reaction.channel_id)?.id.clone(),
+            content: MessageContent::Reaction {
+                message_author: author.into_bytes(),
+                timestamp: snowflake_timestamp(&reaction.message_id),
+                reaction: reaction.emoji.name?,
+                remove,
+            },
+            quote: None,
+        })
+    }
+}
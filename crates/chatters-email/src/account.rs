@@ -0,0 +1,126 @@
+use std::path::{Path, PathBuf};
+
+use chatters_lib::backends::{Error, Result, Setup, SetupQuestion};
+
+fn default_imap_port() -> u16 {
+    993
+}
+
+fn default_sent_mailbox() -> String {
+    "Sent".to_owned()
+}
+
+fn default_history() -> u32 {
+    500
+}
+
+/// The mail account to use, saved in the backend directory when linking.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Account {
+    /// Address to send from.
+    pub address: String,
+    /// Name to send as.
+    #[serde(default)]
+    pub name: String,
+    pub imap_host: String,
+    #[serde(default = "default_imap_port")]
+    pub imap_port: u16,
+    /// SMTP server to send through, using implicit TLS.
+    pub smtp_host: String,
+    /// Username to log in to both servers with.
+    pub username: String,
+    /// Shell command to print the password, such as `pass show mail`, so that it isn't stored.
+    pub password_command: String,
+    /// Mailbox with sent messages, to include our side of threads.
+    #[serde(default = "default_sent_mailbox")]
+    pub sent_mailbox: String,
+    /// How many of the most recent messages to load from each mailbox.
+    #[serde(default = "default_history")]
+    pub history: u32,
+}
+
+impl Account {
+    pub fn load(path: &Path) -> Result<Self> {
+        let account_file = account_file(path);
+        if !account_file.exists() {
+            return Err(Error::Unlinked);
+        }
+        let content = std::fs::read_to_string(&account_file)
+            .map_err(|e| Error::Failure("Failed to read account".to_owned(), e.to_string()))?;
+        serde_json::from_str(&content)
+            .map_err(|e| Error::Failure("Failed to parse account".to_owned(), e.to_string()))
+    }
+
+    /// What to ask for in the setup wizard.
+    pub fn setup_questions() -> Vec<SetupQuestion> {
+        let question = |key, prompt, default| SetupQuestion {
+            key,
+            prompt,
+            default,
+            secret: false,
+        };
+        vec![
+            question("address", "Email address", ""),
+            question("name", "Name", ""),
+            question("imap_host", "IMAP server", ""),
+            question("smtp_host", "SMTP server", ""),
+            question("username", "Username (default the address)", ""),
+            question("password_command", "Password command", ""),
+            question("sent_mailbox", "Sent mailbox", "Sent"),
+        ]
+    }
+
+    /// The account from the answers in the setup wizard.
+    pub fn from_setup(setup: &Setup) -> Self {
+        let address = setup.answer("address").to_owned();
+        let username = match setup.answer("username") {
+            "" => address.clone(),
+            username => username.to_owned(),
+        };
+        let sent_mailbox = match setup.answer("sent_mailbox") {
+            "" => default_sent_mailbox(),
+            sent_mailbox => sent_mailbox.to_owned(),
+        };
+        Self {
+            address,
+            name: setup.answer("name").to_owned(),
+            imap_host: setup.answer("imap_host").to_owned(),
+            imap_port: default_imap_port(),
+            smtp_host: setup.answer("smtp_host").to_owned(),
+            username,
+            password_command: setup.answer("password_command").to_owned(),
+            sent_mailbox,
+            history: default_history(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self).unwrap();
+        std::fs::create_dir_all(path)
+            .and_then(|()| std::fs::write(account_file(path), content))
+            .map_err(|e| Error::Failure("Failed to save account".to_owned(), e.to_string()))
+    }
+
+    /// Run the password command to get the password.
+    pub fn password(&self) -> Result<String> {
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(&self.password_command)
+            .output()
+            .map_err(|e| {
+                Error::Failure("Failed to run password command".to_owned(), e.to_string())
+            })?;
+        if !output.status.success() {
+            return Err(Error::Failure(
+                "Password command failed".to_owned(),
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ));
+        }
+        let password = String::from_utf8_lossy(&output.stdout);
+        Ok(password.lines().next().unwrap_or_default().to_owned())
+    }
+}
+
+fn account_file(path: &Path) -> PathBuf {
+    path.join("account.json")
+}
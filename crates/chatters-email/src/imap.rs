@@ -0,0 +1,116 @@
+use std::net::TcpStream;
+use std::time::Duration;
+
+use chatters_lib::backends::{Error, Result};
+use log::{debug, warn};
+use native_tls::{TlsConnector, TlsStream};
+
+use crate::account::Account;
+
+type Session = imap::Session<TlsStream<TcpStream>>;
+
+/// How often to refresh the idle, servers drop idle connections after 30 minutes.
+const IDLE_KEEPALIVE: Duration = Duration::from_secs(5 * 60);
+
+/// A message as fetched from the server.
+pub struct RawEmail {
+    pub uid: u32,
+    pub raw: Vec<u8>,
+}
+
+fn connect(account: &Account) -> Result<Session> {
+    let password = account.password()?;
+    let tls = TlsConnector::builder()
+        .build()
+        .map_err(|e| Error::Failure("Failed to set up TLS".to_owned(), e.to_string()))?;
+    let client = imap::connect(
+        (account.imap_host.as_str(), account.imap_port),
+        &account.imap_host,
+        &tls,
+    )
+    .map_err(|e| Error::Failure("Failed to connect to IMAP server".to_owned(), e.to_string()))?;
+    client
+        .login(&account.username, &password)
+        .map_err(|(e, _)| {
+            Error::Failure("Failed to log in to IMAP server".to_owned(), e.to_string())
+        })
+}
+
+/// Fetch the most recent messages from the inbox and sent mailboxes, returning them along with
+/// the highest uid seen in the inbox.
+pub fn fetch_recent(account: &Account) -> Result<(Vec<RawEmail>, u32)> {
+    let mut session = connect(account)?;
+    let inbox = fetch_mailbox(&mut session, "INBOX", account.history)
+        .map_err(|e| Error::Failure("Failed to fetch inbox".to_owned(), e.to_string()))?;
+    let last_uid = inbox.iter().map(|e| e.uid).max().unwrap_or_default();
+    let sent = match fetch_mailbox(&mut session, &account.sent_mailbox, account.history) {
+        Ok(sent) => sent,
+        Err(error) => {
+            warn!(error:%, mailbox:% = account.sent_mailbox; "Failed to fetch sent mailbox");
+            Vec::new()
+        }
+    };
+    if let Err(error) = session.logout() {
+        debug!(error:%; "Failed to log out of IMAP server");
+    }
+    let mut emails = inbox;
+    emails.extend(sent);
+    Ok((emails, last_uid))
+}
+
+fn fetch_mailbox(
+    session: &mut Session,
+    mailbox: &str,
+    history: u32,
+) -> imap::Result<Vec<RawEmail>> {
+    let selected = session.select(mailbox)?;
+    if selected.exists == 0 {
+        return Ok(Vec::new());
+    }
+    let first = selected.exists.saturating_sub(history) + 1;
+    let fetches = session.fetch(format!("{first}:*"), "(UID BODY.PEEK[])")?;
+    Ok(fetches.iter().filter_map(raw_email).collect())
+}
+
+fn fetch_new(session: &mut Session, after_uid: u32) -> imap::Result<Vec<RawEmail>> {
+    session.select("INBOX")?;
+    let fetches = session.uid_fetch(format!("{}:*", after_uid + 1), "(UID BODY.PEEK[])")?;
+    // `n:*` always includes the last message, even when its uid is below n
+    Ok(fetches
+        .iter()
+        .filter_map(raw_email)
+        .filter(|e| e.uid > after_uid)
+        .collect())
+}
+
+fn raw_email(fetch: &imap::types::Fetch) -> Option<RawEmail> {
+    Some(RawEmail {
+        uid: fetch.uid?,
+        raw: fetch.body()?.to_vec(),
+    })
+}
+
+/// Wait for new messages in the inbox, calling `on_new` with them until it returns false.
+///
+/// This blocks so should be run on its own thread.
+pub fn watch(
+    account: &Account,
+    mut last_uid: u32,
+    mut on_new: impl FnMut(Vec<RawEmail>) -> bool,
+) -> Result<()> {
+    let failure =
+        |e: imap::Error| Error::Failure("Failed to watch inbox".to_owned(), e.to_string());
+    let mut session = connect(account)?;
+    loop {
+        let new = fetch_new(&mut session, last_uid).map_err(failure)?;
+        if let Some(uid) = new.iter().map(|e| e.uid).max() {
+            last_uid = uid;
+        }
+        if !new.is_empty() && !on_new(new) {
+            return Ok(());
+        }
+        let mut idle = session.idle().map_err(failure)?;
+        idle.set_keepalive(IDLE_KEEPALIVE);
+        idle.wait_keepalive().map_err(failure)?;
+    }
+}
@@ -0,0 +1,458 @@
+use std::ops::Bound;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use chatters_lib::backends::sort_contacts;
+use chatters_lib::backends::timestamp;
+use chatters_lib::backends::Backend;
+use chatters_lib::backends::Contact;
+use chatters_lib::backends::ContactDetails;
+use chatters_lib::backends::ContactId;
+use chatters_lib::backends::Error;
+use chatters_lib::backends::Invite;
+use chatters_lib::backends::Limits;
+use chatters_lib::backends::LoadProgress;
+use chatters_lib::backends::Message;
+use chatters_lib::backends::MessageAttachment;
+use chatters_lib::backends::MessageContent;
+use chatters_lib::backends::Progress;
+use chatters_lib::backends::Quote;
+use chatters_lib::backends::Receipt;
+use chatters_lib::backends::Result;
+use chatters_lib::backends::Setup;
+use chatters_lib::backends::SetupQuestion;
+use chatters_lib::message::FrontendMessage;
+use chatters_lib::pins::PinStore;
+use futures::channel::{mpsc, oneshot};
+use futures::StreamExt as _;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{SmtpTransport, Transport as _};
+use log::{debug, info};
+
+use crate::account::Account;
+use crate::threads::{strip_reply_prefix, Threads};
+
+mod account;
+mod imap;
+mod threads;
+
+/// Email over IMAP and SMTP, with each thread as a conversation.
+#[derive(Clone)]
+pub struct Email {
+    account: Account,
+    threads: Arc<Mutex<Threads>>,
+    /// Highest uid fetched from the inbox, to watch for newer messages from.
+    last_uid: u32,
+    attachments_dir: PathBuf,
+    pins: PinStore,
+}
+
+impl Backend for Email {
+    const NAME: &'static str = "Email";
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn load(path: &Path, _progress: LoadProgress) -> Result<Self> {
+        let account = Account::load(path)?;
+        let fetch_account = account.clone();
+        let (raws, last_uid) =
+            tokio::task::spawn_blocking(move || imap::fetch_recent(&fetch_account))
+                .await
+                .unwrap()?;
+        info!(count = raws.len(); "Fetched messages");
+
+        let mut threads = Threads::default();
+        let name = Some(account.name.as_str());
+        threads.add_person(&account.address.to_lowercase(), name);
+        threads.extend(raws.into_iter().map(|e| e.raw).collect());
+
+        Ok(Self {
+            account,
+            threads: Arc::new(Mutex::new(threads)),
+            last_uid,
+            attachments_dir: path.join("attachments"),
+            pins: PinStore::load(&path.join("pins.json")),
+        })
+    }
+
+    fn setup_questions() -> Vec<SetupQuestion> {
+        Account::setup_questions()
+    }
+
+    async fn link(
+        path: &Path,
+        setup: &Setup,
+        _provisioning_link_tx: oneshot::Sender<String>,
+    ) -> Result<Self> {
+        let account = Account::from_setup(setup);
+        account.save(path)?;
+        Self::load(path, LoadProgress::default()).await
+    }
+
+    async fn background_sync(
+        &mut self,
+        ba_tx: mpsc::UnboundedSender<FrontendMessage>,
+    ) -> Result<()> {
+        let (new_tx, mut new_rx) = mpsc::unbounded();
+        let account = self.account.clone();
+        let last_uid = self.last_uid;
+        let watcher = tokio::task::spawn_blocking(move || {
+            imap::watch(&account, last_uid, |new| new_tx.unbounded_send(new).is_ok())
+        });
+
+        while let Some(new) = new_rx.next().await {
+            debug!(count = new.len(); "Received new messages");
+            // so that syncing again after losing the connection carries on from here
+            if let Some(uid) = new.iter().map(|e| e.uid).max() {
+                self.last_uid = self.last_uid.max(uid);
+            }
+            let (messages, contacts) = {
+                let mut threads = self.threads.lock().unwrap();
+                let known_threads = threads.threads().len();
+                let known_people = threads.people().len();
+                let added = threads.extend(new.into_iter().map(|e| e.raw).collect());
+                let messages = added
+                    .into_iter()
+                    .filter_map(|i| threads.get(i).map(|m| m.to_message()))
+                    .collect::<Vec<_>>();
+                // the ui only knows about the contacts it was last sent
+                let contacts = (threads.threads().len() != known_threads
+                    || threads.people().len() != known_people)
+                    .then(|| all_contacts(&threads));
+                (messages, contacts)
+            };
+            if let Some(contacts) = contacts {
+                ba_tx
+                    .unbounded_send(FrontendMessage::LoadedContacts { contacts })
+                    .unwrap();
+            }
+            for message in messages {
+                ba_tx
+                    .unbounded_send(FrontendMessage::NewMessage { message })
+                    .unwrap();
+            }
+        }
+        watcher.await.unwrap()
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn users(&self) -> Result<Vec<Contact>> {
+        Ok(self.threads.lock().unwrap().people())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn groups(&self) -> Result<Vec<Contact>> {
+        Ok(self.threads.lock().unwrap().threads())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn messages(
+        &mut self,
+        contact_id: ContactId,
+        start_ts: Bound<u64>,
+        end_ts: Bound<u64>,
+    ) -> Result<Vec<Message>> {
+        let ContactId::Group(thread) = contact_id else {
+            // direct messages to people start new threads
+            return Ok(Vec::new());
+        };
+        let thread = String::from_utf8_lossy(&thread);
+        Ok(self
+            .threads
+            .lock()
+            .unwrap()
+            .messages(&thread, start_ts, end_ts))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, content, quoting)))]
+    async fn send_message(
+        &mut self,
+        contact_id: ContactId,
+        content: MessageContent,
+        quoting: Option<&Quote>,
+    ) -> Result<Message> {
+        let MessageContent::Text {
+            text, attachments, ..
+        } = content
+        else {
+            return Err(Error::Failure(
+                "Failed to send message".to_owned(),
+                "email only supports text messages".to_owned(),
+            ));
+        };
+        let body = match quoting {
+            Some(quote) => {
+                let quoted = quote
+                    .text
+                    .lines()
+                    .map(|l| format!("> {l}"))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                format!("{quoted}\n\n{text}")
+            }
+            None => text,
+        };
+
+        let email = self.build_email(&contact_id, body, &attachments)?;
+        let password = self.account.password()?;
+        let transport = SmtpTransport::relay(&self.account.smtp_host)
+            .map_err(|e| {
+                Error::Failure("Failed to connect to SMTP server".to_owned(), e.to_string())
+            })?
+            .credentials(Credentials::new(self.account.username.clone(), password))
+            .build();
+        let raw = email.formatted();
+        tokio::task::spawn_blocking(move || transport.send(&email))
+            .await
+            .unwrap()
+            .map_err(|e| Error::Failure("Failed to send email".to_owned(), e.to_string()))?;
+
+        let mut threads = self.threads.lock().unwrap();
+        let added = threads.extend(vec![raw]);
+        added
+            .first()
+            .and_then(|i| threads.get(*i))
+            .map(|m| m.to_message())
+            .ok_or_else(|| {
+                Error::Failure(
+                    "Failed to record sent email".to_owned(),
+                    "unparseable".to_owned(),
+                )
+            })
+    }
+
+    async fn self_id(&self) -> Vec<u8> {
+        self.account.address.to_lowercase().into_bytes()
+    }
+
+    fn limits(&self) -> Limits {
+        Limits {
+            max_body_length: None,
+            // what most providers accept
+            max_attachment_size: Some(25_000_000),
+            max_attachment_count: None,
+            any_reaction: false,
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn download_attachment(
+        &self,
+        attachment_index: usize,
+        _progress: Progress,
+    ) -> Result<PathBuf> {
+        let Some((name, contents)) = self.threads.lock().unwrap().attachment(attachment_index)
+        else {
+            return Err(Error::UnknownAttachment(attachment_index));
+        };
+        // keep attachments with the same name apart
+        let file_path = self
+            .attachments_dir
+            .join(attachment_index.to_string())
+            .join(
+                Path::new(&name)
+                    .file_name()
+                    .unwrap_or("attachment".as_ref()),
+            );
+        if file_path.is_file() {
+            // already downloaded
+            return Ok(file_path);
+        }
+        std::fs::create_dir_all(file_path.parent().unwrap())
+            .and_then(|()| std::fs::write(&file_path, contents))
+            .map_err(|e| Error::Failure("Failed to save attachment".to_owned(), e.to_string()))?;
+        Ok(file_path)
+    }
+
+    async fn pin_message(
+        &mut self,
+        contact_id: ContactId,
+        timestamp: u64,
+        pinned: bool,
+    ) -> Result<()> {
+        self.pins
+            .set_pinned(&contact_id, timestamp, pinned)
+            .map_err(|e| Error::Failure("Failed to save pinned messages".to_owned(), e.to_string()))
+    }
+
+    async fn delete_message(&mut self, _contact_id: ContactId, _timestamp: u64) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} can't delete sent messages", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn pinned_messages(&self, contact_id: ContactId) -> Result<Vec<u64>> {
+        Ok(self.pins.pinned(&contact_id))
+    }
+
+    async fn find_user(&mut self, query: String) -> Result<Contact> {
+        let address = query.trim();
+        if !address.contains('@') {
+            return Err(Error::Failure(
+                "Failed to find user".to_owned(),
+                format!("{address} isn't an email address"),
+            ));
+        }
+        let address = address.to_lowercase();
+        let mut threads = self.threads.lock().unwrap();
+        // anyone with an address can be emailed
+        threads.add_person(&address, None);
+        threads
+            .people()
+            .into_iter()
+            .find(|c| c.address == address)
+            .ok_or_else(|| Error::Failure("Failed to find user".to_owned(), address))
+    }
+
+    async fn message_receipts(
+        &self,
+        _contact_id: ContactId,
+        _timestamp: u64,
+    ) -> Result<Vec<Receipt>> {
+        Ok(Vec::new())
+    }
+
+    async fn contact_details(&self, _contact_id: ContactId) -> Result<ContactDetails> {
+        Ok(ContactDetails::default())
+    }
+
+    async fn verify_device(&mut self) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support verifying devices", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn confirm_verification(&mut self, _confirmed: bool) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support verifying devices", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn invites(&self) -> Result<Vec<Invite>> {
+        Ok(Vec::new())
+    }
+
+    async fn answer_invite(&mut self, _contact_id: ContactId, _accept: bool) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support invites", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn set_group_title(&mut self, _contact_id: ContactId, _title: String) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support changing groups", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn set_group_description(
+        &mut self,
+        _contact_id: ContactId,
+        _description: String,
+    ) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support changing groups", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn request_contact_sync(&mut self) -> Result<()> {
+        // the conversations come from the mail, loading them again is enough
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn conversation_summaries(&self) -> Result<Vec<Message>> {
+        Ok(self.threads.lock().unwrap().summaries())
+    }
+}
+
+impl Email {
+    /// Build a reply to the latest message of a thread, or a new thread to a person.
+    fn build_email(
+        &self,
+        contact_id: &ContactId,
+        body: String,
+        attachments: &[MessageAttachment],
+    ) -> Result<lettre::Message> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            Error::Failure("Failed to build email".to_owned(), e.to_string())
+        };
+        let address = self.account.address.parse().map_err(|e| invalid(&e))?;
+        let name = Some(self.account.name.clone()).filter(|n| !n.is_empty());
+        let domain = self.account.address.rsplit('@').next().unwrap_or_default();
+        let mut builder = lettre::Message::builder()
+            .from(Mailbox::new(name, address))
+            .message_id(Some(format!("<{}.chatters@{domain}>", timestamp())));
+
+        match contact_id {
+            ContactId::Group(thread) => {
+                let threads = self.threads.lock().unwrap();
+                let Some(latest) = threads.latest(&String::from_utf8_lossy(thread)) else {
+                    return Err(invalid(&"unknown thread"));
+                };
+                let self_address = self.account.address.to_lowercase();
+                let mut recipients = std::iter::once(&latest.from)
+                    .chain(&latest.recipients)
+                    .filter(|a| **a != self_address)
+                    .collect::<Vec<_>>();
+                recipients.sort_unstable();
+                recipients.dedup();
+                for recipient in recipients {
+                    builder = builder.to(recipient.parse().map_err(|e| invalid(&e))?);
+                }
+                let references = latest
+                    .references
+                    .iter()
+                    .chain(std::iter::once(&latest.message_id))
+                    .map(|r| format!("<{r}>"))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                builder = builder
+                    .subject(format!("Re: {}", strip_reply_prefix(&latest.subject)))
+                    .in_reply_to(format!("<{}>", latest.message_id))
+                    .references(references);
+            }
+            ContactId::User(address) => {
+                let address = String::from_utf8_lossy(address);
+                let subject = body.lines().next().unwrap_or_default().to_owned();
+                builder = builder
+                    .to(address.parse().map_err(|e| invalid(&e))?)
+                    .subject(subject);
+            }
+        }
+
+        if attachments.is_empty() {
+            return builder.body(body).map_err(|e| invalid(&e));
+        }
+        let mut multipart = MultiPart::mixed().singlepart(SinglePart::plain(body));
+        for attachment in attachments {
+            let Some(path) = &attachment.path else {
+                continue;
+            };
+            let contents = std::fs::read(path).map_err(|e| invalid(&e))?;
+            let name = path.file_name().map_or_else(
+                || attachment.name.clone(),
+                |n| n.to_string_lossy().into_owned(),
+            );
+            multipart = multipart.singlepart(Attachment::new(name).body(
+                contents,
+                ContentType::parse("application/octet-stream").unwrap(),
+            ));
+        }
+        builder.multipart(multipart).map_err(|e| invalid(&e))
+    }
+}
+
+fn all_contacts(threads: &Threads) -> Vec<Contact> {
+    let mut contacts = threads.people();
+    contacts.append(&mut threads.threads());
+    sort_contacts(&mut contacts);
+    contacts
+}
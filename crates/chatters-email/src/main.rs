@@ -0,0 +1,62 @@
+use chatters_email::Email;
+use chatters_lib::log::init_logger;
+use chatters_lib::util::{self, Options};
+use clap::Parser;
+use directories::ProjectDirs;
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[clap(name = "chatters-email")]
+pub struct Arguments {
+    #[clap(long, default_value = "chatters-email")]
+    device_name: String,
+
+    #[clap(long)]
+    config_file: Option<PathBuf>,
+
+    /// Record messages between the frontend and backend, and the keys pressed, to this file for
+    /// debugging.
+    #[clap(long, alias = "journal")]
+    record: Option<PathBuf>,
+
+    /// Listen for control requests from external tools on this unix socket.
+    #[clap(long)]
+    ipc_socket: Option<PathBuf>,
+
+    #[cfg(feature = "tracing")]
+    #[clap(flatten)]
+    trace: chatters_lib::telemetry::TraceArgs,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let project_dirs = ProjectDirs::from("net", "jeffas", "chatters-email").unwrap();
+    let data_local_dir = project_dirs.data_local_dir();
+
+    let log_path = data_local_dir.join("logs.log");
+    init_logger(log_path);
+
+    let args = Arguments::parse();
+
+    #[cfg(feature = "tracing")]
+    let _tracing_guard = chatters_lib::telemetry::init_tracing(&args.trace);
+
+    let config_file = match args.config_file {
+        Some(cf) => cf,
+        None => project_dirs.config_local_dir().join("config.toml"),
+    };
+
+    let options = Options {
+        device_name: args.device_name,
+        data_local_dir: data_local_dir.to_owned(),
+        config_file,
+        app_name: "chatters-email".to_owned(),
+        journal_file: args.record,
+        replay_file: None,
+        ipc_socket: args.ipc_socket,
+    };
+
+    util::run::<Email>(options).await;
+
+    Ok(())
+}
@@ -0,0 +1,295 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::{Bound, RangeBounds as _};
+use std::sync::Arc;
+
+use chatters_lib::backends::{
+    Contact, ContactId, Message, MessageAttachment, MessageContent, Verification,
+};
+use mail_parser::{Address, MessageParser, MimeHeaders as _};
+
+/// A parsed message.
+#[derive(Debug)]
+pub struct Mail {
+    pub message_id: String,
+    pub thread: String,
+    pub subject: String,
+    pub from: String,
+    /// Everyone the message was sent to.
+    pub recipients: Vec<String>,
+    pub timestamp: u64,
+    pub references: Vec<String>,
+    text: String,
+    attachments: Vec<MessageAttachment>,
+}
+
+/// Messages grouped into threads, each shown as a conversation.
+#[derive(Debug, Default)]
+pub struct Threads {
+    /// All of the messages, in the order they were received.
+    emails: Vec<Mail>,
+    /// The thread each message id belongs to.
+    thread_of: HashMap<String, String>,
+    /// Names for the addresses seen.
+    people: BTreeMap<String, String>,
+    /// The raw message and position of each attachment, by attachment index.
+    attachments: Vec<(Arc<[u8]>, usize)>,
+}
+
+struct Parsed {
+    raw: Arc<[u8]>,
+    timestamp: u64,
+}
+
+impl Threads {
+    /// Add the messages, oldest first so that replies join the thread of their parent, returning
+    /// the indices of the new ones.
+    pub fn extend(&mut self, raws: Vec<Vec<u8>>) -> Vec<usize> {
+        let parser = MessageParser::default();
+        let mut parsed = raws
+            .into_iter()
+            .filter_map(|raw| {
+                let timestamp = parser.parse_headers(&raw)?.date()?.to_timestamp();
+                let timestamp = u64::try_from(timestamp).ok()? * 1000;
+                Some(Parsed {
+                    raw: raw.into(),
+                    timestamp,
+                })
+            })
+            .collect::<Vec<_>>();
+        parsed.sort_by_key(|p| p.timestamp);
+        parsed
+            .into_iter()
+            .filter_map(|p| self.insert(&parser, p))
+            .collect()
+    }
+
+    fn insert(&mut self, parser: &MessageParser, parsed: Parsed) -> Option<usize> {
+        let message = parser.parse(&parsed.raw)?;
+        let message_id = message.message_id()?.to_owned();
+        if self.thread_of.contains_key(&message_id) {
+            // seen already, e.g. in both the inbox and sent mailboxes
+            return None;
+        }
+
+        let mut references = message
+            .references()
+            .as_text_list()
+            .map(|l| l.iter().map(|r| r.to_string()).collect::<Vec<_>>())
+            .unwrap_or_default();
+        if let Some(in_reply_to) = message.in_reply_to().as_text() {
+            if !references.iter().any(|r| r == in_reply_to) {
+                references.push(in_reply_to.to_owned());
+            }
+        }
+        let thread = references
+            .iter()
+            .rev()
+            .find_map(|r| self.thread_of.get(r).cloned())
+            .or_else(|| references.first().cloned())
+            .unwrap_or_else(|| message_id.clone());
+
+        let mut from = String::new();
+        if let Some(addr) = message.from().and_then(Address::first) {
+            from = addr.address().unwrap_or_default().to_lowercase();
+            self.add_person(&from, addr.name());
+        }
+        let mut recipients = Vec::new();
+        for addr in message
+            .to()
+            .into_iter()
+            .chain(message.cc())
+            .flat_map(Address::iter)
+        {
+            let Some(address) = addr.address() else {
+                continue;
+            };
+            let address = address.to_lowercase();
+            self.add_person(&address, addr.name());
+            recipients.push(address);
+        }
+
+        let attachments = message
+            .attachments()
+            .enumerate()
+            .map(|(i, part)| {
+                let index = self.attachments.len();
+                self.attachments.push((Arc::clone(&parsed.raw), i));
+                MessageAttachment {
+                    name: part
+                        .attachment_name()
+                        .map_or_else(|| format!("attachment-{i}"), str::to_owned),
+                    size: part.len() as u64,
+                    index,
+                    path: None,
+                    content_type: part.content_type().map(|c| match c.subtype() {
+                        Some(subtype) => format!("{}/{subtype}", c.ctype()),
+                        None => c.ctype().to_owned(),
+                    }),
+                }
+            })
+            .collect();
+
+        // messages are identified by their timestamp so keep them unique within a thread
+        let mut timestamp = parsed.timestamp;
+        while self
+            .emails
+            .iter()
+            .any(|e| e.thread == thread && e.timestamp == timestamp)
+        {
+            timestamp += 1;
+        }
+
+        self.thread_of.insert(message_id.clone(), thread.clone());
+        self.emails.push(Mail {
+            message_id,
+            thread,
+            subject: message.subject().unwrap_or_default().to_owned(),
+            from,
+            recipients,
+            timestamp,
+            references,
+            text: message
+                .body_text(0)
+                .map(|t| t.trim_end().to_owned())
+                .unwrap_or_default(),
+            attachments,
+        });
+        Some(self.emails.len() - 1)
+    }
+
+    pub fn add_person(&mut self, address: &str, name: Option<&str>) {
+        let name = name.filter(|n| !n.is_empty()).unwrap_or(address);
+        self.people
+            .entry(address.to_owned())
+            .or_insert_with(|| name.to_owned());
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Mail> {
+        self.emails.get(index)
+    }
+
+    /// Everyone that has sent or received a message.
+    pub fn people(&self) -> Vec<Contact> {
+        self.people
+            .iter()
+            .map(|(address, name)| Contact {
+                id: ContactId::User(address.as_bytes().to_vec()),
+                name: name.clone(),
+                address: address.clone(),
+                last_message_timestamp: self
+                    .emails
+                    .iter()
+                    .filter(|e| &e.from == address)
+                    .map(|e| e.timestamp)
+                    .max(),
+                description: String::new(),
+                muted_until: None,
+                verification: Verification::Unverified,
+                members: None,
+                encrypted: false,
+            })
+            .collect()
+    }
+
+    /// Each thread, named after the subject of its first message.
+    pub fn threads(&self) -> Vec<Contact> {
+        let mut threads: BTreeMap<&str, Vec<&Mail>> = BTreeMap::new();
+        for email in &self.emails {
+            threads.entry(&email.thread).or_default().push(email);
+        }
+        threads
+            .into_iter()
+            .map(|(thread, emails)| {
+                let mut participants = emails
+                    .iter()
+                    .flat_map(|e| std::iter::once(&e.from).chain(&e.recipients))
+                    .map(|a| self.people.get(a).unwrap_or(a).as_str())
+                    .collect::<Vec<_>>();
+                participants.sort_unstable();
+                participants.dedup();
+                Contact {
+                    id: ContactId::Group(thread.as_bytes().to_vec()),
+                    name: strip_reply_prefix(&emails[0].subject).to_owned(),
+                    address: thread.to_owned(),
+                    last_message_timestamp: emails.iter().map(|e| e.timestamp).max(),
+                    description: participants.join(", "),
+                    muted_until: None,
+                    verification: Verification::Unverified,
+                    members: Some(participants.len()),
+                    encrypted: false,
+                }
+            })
+            .collect()
+    }
+
+    /// The messages in a thread within the range of timestamps, oldest first.
+    pub fn messages(&self, thread: &str, start_ts: Bound<u64>, end_ts: Bound<u64>) -> Vec<Message> {
+        let mut messages = self
+            .emails
+            .iter()
+            .filter(|e| e.thread == thread && (start_ts, end_ts).contains(&e.timestamp))
+            .map(Mail::to_message)
+            .collect::<Vec<_>>();
+        messages.sort_by_key(|m| m.timestamp);
+        messages
+    }
+
+    /// The latest message in a thread.
+    pub fn latest(&self, thread: &str) -> Option<&Mail> {
+        self.emails
+            .iter()
+            .filter(|e| e.thread == thread)
+            .max_by_key(|e| e.timestamp)
+    }
+
+    /// The latest message in each thread.
+    pub fn summaries(&self) -> Vec<Message> {
+        let mut latest: BTreeMap<&str, &Mail> = BTreeMap::new();
+        for email in &self.emails {
+            let entry = latest.entry(&email.thread).or_insert(email);
+            if email.timestamp > entry.timestamp {
+                *entry = email;
+            }
+        }
+        latest.into_values().map(Mail::to_message).collect()
+    }
+
+    /// The file name and contents of an attachment.
+    pub fn attachment(&self, index: usize) -> Option<(String, Vec<u8>)> {
+        let (raw, position) = self.attachments.get(index)?;
+        let message = MessageParser::default().parse(raw)?;
+        let part = message.attachments().nth(*position)?;
+        let name = part
+            .attachment_name()
+            .map_or_else(|| format!("attachment-{index}"), str::to_owned);
+        Some((name, part.contents().to_vec()))
+    }
+}
+
+impl Mail {
+    pub fn to_message(&self) -> Message {
+        Message {
+            timestamp: self.timestamp,
+            sender: self.from.as_bytes().to_vec(),
+            contact_id: ContactId::Group(self.thread.as_bytes().to_vec()),
+            content: MessageContent::Text {
+                text: self.text.clone(),
+                attachments: self.attachments.clone(),
+                mentions: Vec::new(),
+                formatting: Vec::new(),
+            },
+            quote: None,
+        }
+    }
+}
+
+pub fn strip_reply_prefix(subject: &str) -> &str {
+    let mut subject = subject.trim();
+    while let Some(rest) = ["Re:", "RE:", "re:", "Fwd:", "FW:", "Fw:"]
+        .iter()
+        .find_map(|p| subject.strip_prefix(p))
+    {
+        subject = rest.trim_start();
+    }
+    subject
+}
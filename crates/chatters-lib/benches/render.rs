@@ -0,0 +1,56 @@
+//! How long drawing a frame takes with a very long conversation open.
+
+use chatters_lib::backends::{ContactId, Message, MessageContent};
+use chatters_lib::tui::{render, TuiState};
+use criterion::{criterion_group, criterion_main, Criterion};
+use ratatui::{backend::TestBackend, Terminal};
+
+const MESSAGES: u64 = 10_000;
+
+fn long_conversation() -> TuiState {
+    let mut tui_state = TuiState::default();
+    let contact_id = ContactId::User(b"alice".to_vec());
+    tui_state
+        .messages
+        .add_multiple((0..MESSAGES).map(|i| Message {
+            timestamp: i * 1000,
+            sender: if i % 3 == 0 {
+                b"me".to_vec()
+            } else {
+                b"alice".to_vec()
+            },
+            contact_id: contact_id.clone(),
+            content: MessageContent::Text {
+                text: format!(
+                    "Message {i}, long enough that it has to be wrapped over a few lines when \
+                 rendered in the messages pane, as most conversations have a few of those"
+                ),
+                attachments: Vec::new(),
+                mentions: Vec::new(),
+                formatting: Vec::new(),
+            },
+            quote: None,
+        }));
+    tui_state
+}
+
+fn bench_render(c: &mut Criterion) {
+    let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+    let mut tui_state = long_conversation();
+
+    c.bench_function("render cached", |b| {
+        b.iter(|| {
+            terminal.draw(|f| render(f, &mut tui_state)).unwrap();
+        })
+    });
+
+    c.bench_function("render uncached", |b| {
+        b.iter(|| {
+            tui_state.messages.render_cache.clear();
+            terminal.draw(|f| render(f, &mut tui_state)).unwrap();
+        })
+    });
+}
+
+criterion_group!(benches, bench_render);
+criterion_main!(benches);
@@ -0,0 +1,1871 @@
+use crate::backends::{
+    timestamp, Connectivity, Contact, ContactId, Message, MessageContent, Transfer, Verification,
+};
+use crate::commands::{
+    self, Command as _, CommandMode, EditCommand, ExecuteCommand, NextCommand, NextContact,
+    NextMessage, NormalMode, PrevCommand, PrevContact, PrevMessage,
+};
+use crate::config::{Config, ContactOrder};
+use crate::downloads::unique_path;
+use crate::ipc::{self, IpcCall};
+use crate::journal::{Journal, JournalEntry, JournalEvent};
+use crate::keybinds::{Binding, KeyEvents};
+use crate::message::{BackendMessage, FrontendMessage};
+use crate::tui::lock::LockAction;
+use crate::tui::{render, Mode, Popup, PopupType, TuiState};
+use crate::util::reload_config;
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use futures::channel::mpsc;
+use futures::future::{select, Either};
+use futures::{pin_mut, Stream, StreamExt as _};
+use log::{debug, info, warn};
+use ratatui::backend::Backend;
+use ratatui::layout::Position;
+use ratatui::Terminal;
+use std::path::PathBuf;
+
+/// The ui: its state, the terminal it is drawn to, and how events from the user, the backend and
+/// ipc clients change it.
+///
+/// The terminal and the source of user events are given to it, so tests can drive it with
+/// scripted keys and look at the frames drawn to a `TestBackend`.
+pub struct App<T: Backend> {
+    pub tui_state: TuiState,
+    pub terminal: Terminal<T>,
+    ba_tx: mpsc::UnboundedSender<BackendMessage>,
+    journal: Option<Journal>,
+}
+
+impl<T: Backend> App<T> {
+    pub fn new(
+        terminal: Terminal<T>,
+        ba_tx: mpsc::UnboundedSender<BackendMessage>,
+        mut tui_state: TuiState,
+        journal: Option<Journal>,
+    ) -> Self {
+        tui_state.dirty = true;
+        Self {
+            tui_state,
+            terminal,
+            ba_tx,
+            journal,
+        }
+    }
+
+    /// Show the snapshot from the last run, if there is one, and ask the backend for the contacts
+    /// and invites.
+    pub fn start(&mut self) {
+        if !self.tui_state.snapshot.is_empty() {
+            show_snapshot(&mut self.tui_state);
+        }
+        self.ba_tx
+            .unbounded_send(BackendMessage::LoadContacts)
+            .unwrap();
+        self.ba_tx
+            .unbounded_send(BackendMessage::LoadInvites)
+            .unwrap();
+        // contacts are synced as the backend loads, so the schedule starts from now
+        self.tui_state.contacts_synced_at = timestamp();
+    }
+
+    pub fn draw(&mut self) {
+        self.terminal
+            .draw(|f| render(f, &mut self.tui_state))
+            .unwrap();
+        self.tui_state.dirty = false;
+        let now = timestamp();
+        if self.tui_state.needs_redraw(now) {
+            self.tui_state.update_redraw_at(now);
+        }
+    }
+
+    /// Handle an event from the user, returning whether to quit.
+    pub fn user_event(&mut self, event: Event) -> bool {
+        if let (Some(journal), Event::Key(key)) = (&self.journal, &event) {
+            journal.record_key(key);
+        }
+        if let Event::Key(key) = &event {
+            if self.tui_state.lock.is_none() {
+                // a new binding or command line, which could be the one stopping the recording
+                let starts_sequence = self.tui_state.key_events.0.is_empty()
+                    && self.tui_state.count_prefix.is_none()
+                    && !matches!(self.tui_state.mode, Mode::Command { .. });
+                let key = crate::keybinds::KeyEvent {
+                    code: key.code,
+                    modifiers: key.modifiers,
+                };
+                self.tui_state.macros.record(key, starts_sequence);
+            }
+        }
+        // the pointer moving around doesn't change anything
+        if !matches!(
+            event,
+            Event::Mouse(MouseEvent {
+                kind: MouseEventKind::Moved,
+                ..
+            })
+        ) {
+            self.tui_state.dirty = true;
+        }
+        // commands and reloads change the config, so each event gets the latest
+        let config = self.tui_state.config.clone();
+        if process_user_event(
+            &mut self.tui_state,
+            &self.ba_tx,
+            &mut self.terminal,
+            &config,
+            event,
+        ) {
+            return true;
+        }
+        // macros are played once the keys playing them are done with, outside of the command line
+        let Some(keys) = self.tui_state.macros.take_pending() else {
+            return false;
+        };
+        let config = self.tui_state.config.clone();
+        let quit = simulate_key_events(
+            &mut self.tui_state,
+            &self.ba_tx,
+            &mut self.terminal,
+            &config,
+            keys,
+        );
+        if self.tui_state.macros.take_pending().is_some() {
+            self.tui_state.command_line.error = "Failed to play a macro from a macro".to_owned();
+        }
+        quit
+    }
+
+    pub fn backend_message(&mut self, message: FrontendMessage) {
+        if let Some(journal) = &self.journal {
+            journal.record_frontend(&message);
+        }
+        // ticks only redraw when something shown has changed
+        if !matches!(message, FrontendMessage::Tick) {
+            self.tui_state.dirty = true;
+        }
+        let ages_changed = matches!(
+            message,
+            FrontendMessage::LoadedContacts { .. }
+                | FrontendMessage::LoadedSummaries { .. }
+                | FrontendMessage::LoadedMessages { .. }
+                | FrontendMessage::NewMessage { .. }
+                | FrontendMessage::ConfigChanged
+        );
+        let config = self.tui_state.config.clone();
+        process_backend_message(&mut self.tui_state, &self.ba_tx, &config, message);
+        if ages_changed {
+            self.tui_state.update_redraw_at(timestamp());
+        }
+    }
+
+    pub fn ipc_call(&mut self, call: IpcCall) {
+        debug!(request:? = call.request; "Received ipc request");
+        self.tui_state.dirty = true;
+        if self.tui_state.lock.is_some() {
+            let _ = call.reply.send(Err("Locked".to_owned()));
+        } else {
+            ipc::handle_call(&mut self.tui_state, &self.ba_tx, call);
+        }
+    }
+
+    /// Feed a recording back in, keeping the delays between its keys and messages, returning
+    /// whether its keys quit.
+    ///
+    /// What the ui sent to the backend is skipped, as replaying the keys sends it again.
+    pub async fn replay(&mut self, entries: Vec<JournalEntry>) -> bool {
+        info!(entries = entries.len(); "Starting replay");
+        let mut last_timestamp = entries.first().map_or(0, |e| e.timestamp);
+        for entry in entries {
+            let delay = entry.timestamp.saturating_sub(last_timestamp);
+            last_timestamp = entry.timestamp;
+            tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+            match entry.event {
+                JournalEvent::Frontend(message) => {
+                    debug!(message:?; "Replaying message");
+                    self.backend_message(message);
+                }
+                JournalEvent::Key(key) => {
+                    debug!(key:?; "Replaying key");
+                    if self.user_event(Event::Key(key)) {
+                        return true;
+                    }
+                }
+                JournalEvent::Backend(_) => continue,
+            }
+            self.draw();
+        }
+        info!("Finished replay");
+        false
+    }
+
+    /// Handle events until the user quits or their events end, drawing after each one, then save
+    /// the snapshot for the next run.
+    pub async fn run(
+        mut self,
+        mut events: impl Stream<Item = Event> + Unpin,
+        mut backend_actor_rx: mpsc::UnboundedReceiver<FrontendMessage>,
+        mut ipc_rx: mpsc::UnboundedReceiver<IpcCall>,
+    ) {
+        loop {
+            if self.tui_state.needs_redraw(timestamp()) {
+                self.draw();
+            }
+
+            let event_future = events.next();
+
+            let backend_future = async { backend_actor_rx.next().await.unwrap() };
+            pin_mut!(backend_future);
+
+            let ipc_future = async {
+                match ipc_rx.next().await {
+                    Some(call) => call,
+                    // no ipc server is running
+                    None => std::future::pending().await,
+                }
+            };
+            pin_mut!(ipc_future);
+
+            match select(event_future, select(backend_future, ipc_future)).await {
+                Either::Left((Some(event), _)) => {
+                    if self.user_event(event) {
+                        break;
+                    }
+                }
+                Either::Left((None, _)) => break,
+                Either::Right((Either::Left((message, _)), _)) => self.backend_message(message),
+                Either::Right((Either::Right((call, _)), _)) => self.ipc_call(call),
+            }
+        }
+
+        self.tui_state.snapshot.save(
+            &self.tui_state.self_id,
+            &self.tui_state.limits,
+            self.tui_state.contacts.iter_contacts_and_groups(),
+        );
+    }
+}
+
+/// Show the contacts and messages from the last run until the backend has loaded them afresh.
+fn show_snapshot(tui_state: &mut TuiState) {
+    let contacts = tui_state.snapshot.contacts.clone();
+    tui_state.mutes.sync_from_backend(&contacts);
+    tui_state.contacts.extend(contacts);
+    commands::arrange_contacts(tui_state);
+    tui_state.contacts.state.select_next();
+    if let Some(contact) = tui_state.contacts.selected() {
+        let messages = tui_state.snapshot.messages(&contact.id);
+        tui_state.messages.extend(messages);
+        tui_state.messages.state.select_last();
+    }
+}
+
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+fn process_user_event(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    terminal: &mut Terminal<impl Backend>,
+    config: &Config,
+    event: Event,
+) -> bool {
+    // dbg!(&event);
+
+    let mode = tui_state.mode;
+
+    debug!(event:? = event; "Received event");
+    match event {
+        Event::Key(key) if tui_state.lock.is_some() => {
+            return process_lock_key(tui_state, key);
+        }
+        Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) if tui_state
+            .popup
+            .as_ref()
+            .is_some_and(|p| matches!(p.typ, PopupType::EmojiPicker(_))) =>
+        {
+            // typing searches the emoji picker rather than running keybinds
+            process_emoji_picker_key(tui_state, ba_tx, code, modifiers);
+        }
+        Event::Mouse(mouse) if tui_state.lock.is_none() && tui_state.popup.is_none() => {
+            process_mouse_event(tui_state, ba_tx, mouse);
+        }
+        Event::Key(KeyEvent { code, .. })
+            if tui_state
+                .popup
+                .as_ref()
+                .is_some_and(|p| matches!(p.typ, PopupType::FilePicker(_))) =>
+        {
+            process_file_picker_key(tui_state, code);
+        }
+        Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) => {
+            // handle builtin keybindings
+            if code == KeyCode::Char(':')
+                && modifiers.is_empty()
+                && tui_state.key_events.0.is_empty()
+                && !matches!(tui_state.mode, Mode::Compose)
+            {
+                if let Err(error) = CommandMode.execute(tui_state, ba_tx) {
+                    tui_state.command_line.error = error.to_string();
+                }
+                return false;
+            }
+            if code == KeyCode::Esc && modifiers.is_empty() && tui_state.key_events.0.is_empty() {
+                tui_state.count_prefix = None;
+                if let Err(error) = NormalMode.execute(tui_state, ba_tx) {
+                    tui_state.command_line.error = error.to_string();
+                }
+                return false;
+            }
+
+            if let KeyCode::Char(c @ '0'..='9') = code {
+                // a leading 0 could still be bound to something
+                let counting = c != '0' || tui_state.count_prefix.is_some();
+                if counting
+                    && modifiers.is_empty()
+                    && tui_state.key_events.0.is_empty()
+                    && matches!(tui_state.mode, Mode::Normal | Mode::Popup)
+                {
+                    let digit = c.to_digit(10).unwrap() as usize;
+                    let count = tui_state.count_prefix.unwrap_or(0);
+                    tui_state.count_prefix = Some(count.saturating_mul(10).saturating_add(digit));
+                    return false;
+                }
+            }
+
+            let key_event = crate::keybinds::KeyEvent { code, modifiers };
+            tui_state.key_events.0.push(key_event);
+            debug!(key_events:? = tui_state.key_events; "Looking for a key binding");
+            match mode {
+                Mode::Normal => {
+                    match config.keybinds.get(&tui_state.key_events, mode) {
+                        Ok(binding) => {
+                            if run_binding(tui_state, ba_tx, terminal, config, binding.clone()) {
+                                return true;
+                            }
+                        }
+                        Err(true) => {
+                            // could still be a binding with more input, leave it
+                        }
+                        Err(false) => {
+                            tui_state.command_line.error =
+                                format!("Failed to find keybind for {}", tui_state.key_events);
+                            // no binding starts with this
+                            tui_state.key_events.0.clear();
+                            tui_state.count_prefix = None;
+                        }
+                    }
+                }
+                Mode::Command { previous: _ } => {
+                    // currently don't support keybinds in command mode as simulation uses command
+                    // mode itself
+                    tui_state.key_events.0.clear();
+                    let key_event = crossterm::event::KeyEvent {
+                        code,
+                        modifiers,
+                        kind: crossterm::event::KeyEventKind::Press,
+                        state: crossterm::event::KeyEventState::empty(),
+                    };
+                    if code == KeyCode::Char('r') && modifiers == KeyModifiers::CONTROL {
+                        tui_state.command_line.search_history();
+                    } else if tui_state.command_line.search_input(key_event) {
+                        // typed into the history search
+                    } else if code == KeyCode::Tab {
+                        commands::complete_command(tui_state, true);
+                    } else if code == KeyCode::BackTab {
+                        commands::complete_command(tui_state, false);
+                    } else if code == KeyCode::Enter {
+                        match ExecuteCommand.execute(tui_state, ba_tx) {
+                            Ok(cs) => match cs {
+                                commands::CommandSuccess::Nothing => {}
+                                commands::CommandSuccess::Quit => return true,
+                                commands::CommandSuccess::Clear => {
+                                    terminal.clear().unwrap();
+                                }
+                                commands::CommandSuccess::ShowPopup { title, text } => {
+                                    commands::show_popup(tui_state, title, text);
+                                }
+                            },
+                            Err(error) => {
+                                tui_state.command_line.error = error.to_string();
+                            }
+                        }
+                    } else if code == KeyCode::Char('e') && modifiers == KeyModifiers::ALT {
+                        match EditCommand.execute(tui_state, ba_tx) {
+                            Ok(_) => terminal.clear().unwrap(),
+                            Err(error) => tui_state.command_line.error = error.to_string(),
+                        }
+                    } else if code == KeyCode::Up {
+                        if let Err(error) = PrevCommand.execute(tui_state, ba_tx) {
+                            tui_state.command_line.error = error.to_string();
+                        }
+                    } else if code == KeyCode::Down {
+                        if let Err(error) = NextCommand.execute(tui_state, ba_tx) {
+                            tui_state.command_line.error = error.to_string();
+                        }
+                    } else {
+                        tui_state.command_line.input(key_event);
+                        commands::find_incrementally(tui_state);
+                    }
+                }
+                Mode::Compose => {
+                    match config.keybinds.get(&tui_state.key_events, mode) {
+                        Ok(binding) => {
+                            if run_binding(tui_state, ba_tx, terminal, config, binding.clone()) {
+                                return true;
+                            }
+                        }
+                        Err(true) => {
+                            // skip
+                        }
+                        Err(false) => {
+                            for key_event in tui_state.key_events.0.drain(..) {
+                                tui_state.compose.input(crossterm::event::KeyEvent {
+                                    code: key_event.code,
+                                    modifiers: key_event.modifiers,
+                                    kind: crossterm::event::KeyEventKind::Press,
+                                    state: crossterm::event::KeyEventState::empty(),
+                                });
+                            }
+                            commands::suggest_mentions(tui_state);
+                        }
+                    }
+                }
+                Mode::Popup => match config.keybinds.get(&tui_state.key_events, mode) {
+                    Ok(binding) => {
+                        if run_binding(tui_state, ba_tx, terminal, config, binding.clone()) {
+                            return true;
+                        }
+                    }
+                    Err(true) => {
+                        // prefix
+                    }
+                    Err(false) => {
+                        tui_state.command_line.error =
+                            format!("Failed to find keybind for {}", tui_state.key_events);
+                        tui_state.key_events.0.clear();
+                        tui_state.count_prefix = None;
+                    }
+                },
+            }
+        }
+        e => {
+            warn!(event:? = e; "Unhandled event");
+        }
+    }
+    false
+}
+
+/// Do what a key binding is bound to, returning whether to quit.
+fn run_binding(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    terminal: &mut Terminal<impl Backend>,
+    config: &Config,
+    binding: Binding,
+) -> bool {
+    tui_state.key_events.0.clear();
+    // the count applies to everything the binding runs, including other bindings it presses
+    let outer_count = tui_state.count;
+    if let Some(count) = tui_state.count_prefix.take() {
+        tui_state.count = Some(count);
+    }
+    let quit = match binding {
+        Binding::Keys(key_events) => {
+            simulate_key_events(tui_state, ba_tx, terminal, config, key_events)
+        }
+        Binding::Commands(commands) => run_commands(tui_state, ba_tx, terminal, commands),
+    };
+    tui_state.count = outer_count;
+    quit
+}
+
+fn run_commands(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    terminal: &mut Terminal<impl Backend>,
+    commands: Vec<String>,
+) -> bool {
+    for command in commands {
+        debug!(command:?; "Running command from keybinding");
+        match commands::run_command_line(tui_state, ba_tx, &command) {
+            Ok(commands::CommandSuccess::Nothing) => {}
+            Ok(commands::CommandSuccess::Quit) => return true,
+            Ok(commands::CommandSuccess::Clear) => terminal.clear().unwrap(),
+            Ok(commands::CommandSuccess::ShowPopup { title, text }) => {
+                commands::show_popup(tui_state, title, text);
+            }
+            Err(error) => {
+                // later commands may rely on this one
+                tui_state.command_line.error = error.to_string();
+                break;
+            }
+        }
+    }
+    false
+}
+
+fn simulate_key_events(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    terminal: &mut Terminal<impl Backend>,
+    config: &Config,
+    key_events: KeyEvents,
+) -> bool {
+    // loop through the keys calling process_user_event for each
+    debug!(key_events:?; "Simulating key events from keybinding");
+    let mut quit = false;
+    for key_event in key_events.0 {
+        debug!(key_event:?; "Simulating key event");
+        quit |= process_user_event(
+            tui_state,
+            ba_tx,
+            terminal,
+            config,
+            Event::Key(KeyEvent {
+                code: key_event.code,
+                modifiers: key_event.modifiers,
+                kind: crossterm::event::KeyEventKind::Press,
+                state: crossterm::event::KeyEventState::empty(),
+            }),
+        );
+    }
+    quit
+}
+
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+fn process_backend_message(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    config: &Config,
+    msg: FrontendMessage,
+) {
+    // dbg!(&msg);
+    match msg {
+        FrontendMessage::LoadProgress { status } => {
+            tui_state.load_status = status;
+            if status.catching_up {
+                tui_state.syncing = true;
+            }
+        }
+        FrontendMessage::BackendLoaded { self_id, limits } => {
+            tui_state.self_id = self_id;
+            tui_state.limits = limits;
+        }
+        FrontendMessage::LoadedContacts { contacts } => {
+            tui_state.syncing = tui_state.load_status.catching_up;
+            let first = tui_state.contacts.is_empty() && !contacts.is_empty();
+            config
+                .hooks
+                .do_on_contact_sync(&tui_state.app_name, &contacts);
+            tui_state.mutes.sync_from_backend(&contacts);
+            tui_state.contacts.clear();
+            tui_state.contacts.extend(contacts);
+            commands::arrange_contacts(tui_state);
+            if first {
+                tui_state.contacts.state.select_next();
+            }
+            if let Some(contact_id) = tui_state.contacts.selected().map(|c| c.id.clone()) {
+                commands::load_messages(tui_state, ba_tx, contact_id);
+            }
+            commands::load_split_messages(tui_state, ba_tx);
+            if config.contact_previews {
+                ba_tx.unbounded_send(BackendMessage::LoadSummaries).unwrap();
+            }
+        }
+        FrontendMessage::LoadedSummaries { summaries } => {
+            tui_state.previews = summaries
+                .into_iter()
+                .map(|message| (message.contact_id.clone(), message))
+                .collect();
+        }
+        FrontendMessage::LoadedInvites { invites } => {
+            tui_state.invites = invites;
+        }
+        FrontendMessage::NewInvite { invite } => {
+            // backends can report the same invite again as it syncs
+            if !tui_state
+                .invites
+                .iter()
+                .any(|i| i.contact_id == invite.contact_id)
+            {
+                info!(invite:?; "Invited");
+                config.hooks.do_on_invite(&tui_state.app_name, &invite);
+                tui_state.invites.push(invite);
+            }
+        }
+        FrontendMessage::LoadedMessages {
+            request_id,
+            messages,
+        } => {
+            if let Some(split) = tui_state
+                .split
+                .as_mut()
+                .filter(|split| request_id != 0 && request_id == split.messages_request)
+            {
+                if messages
+                    .last()
+                    .is_some_and(|m| m.contact_id == split.contact_id)
+                {
+                    if split.messages.is_empty() {
+                        split.messages.state.select_last();
+                    }
+                    split.messages.clear();
+                    split.messages.extend(messages);
+                }
+                return;
+            }
+            // replayed journals don't carry ids
+            if request_id != 0 && request_id != tui_state.messages_request {
+                debug!(request_id, latest = tui_state.messages_request; "Dropping stale messages");
+                return;
+            }
+            if let Some(contact) = tui_state.contacts.selected_mut() {
+                if let Some(last_message) = messages.last() {
+                    if last_message.contact_id == contact.id {
+                        tui_state.snapshot.set_messages(&contact.id, &messages);
+                        contact.last_message_timestamp = Some(last_message.timestamp);
+                        if tui_state.messages.is_empty() && !messages.is_empty() {
+                            tui_state.messages.state.select_last();
+                        }
+                        tui_state.messages.clear();
+                        tui_state.messages.extend(messages);
+                    }
+                }
+            }
+            if let Some(timestamp) = tui_state.goto_after_load.take() {
+                if tui_state.messages.get_by_timestamp(timestamp).is_some() {
+                    tui_state.messages.select_message(timestamp);
+                } else {
+                    tui_state.command_line.error = "Quoted message could not be found".to_owned();
+                }
+            }
+        }
+        FrontendMessage::NewMessage { message } => {
+            // sending a message stops them typing, even if the backend didn't say so
+            if let Some(typing) = tui_state.typing.get_mut(&message.contact_id) {
+                typing.retain(|sender| *sender != message.sender);
+            }
+            tui_state.snapshot.add_message(&message);
+            update_preview(tui_state, &message);
+            if let MessageContent::Call {
+                link: Some(link), ..
+            } = &message.content
+            {
+                tui_state
+                    .call_links
+                    .insert(message.contact_id.clone(), link.clone());
+            }
+            auto_download_attachments(tui_state, ba_tx, config, &message);
+            let in_split = match tui_state.split.as_mut() {
+                Some(split) if split.contact_id == message.contact_id => {
+                    split.messages.add_single(message.clone());
+                    true
+                }
+                _ => false,
+            };
+            let mention = message.sender != tui_state.self_id && mentions_self(tui_state, &message);
+            // not every backend reports everyone in its groups as a contact
+            let sender = tui_state.contacts.contact_by_id(&message.sender).cloned();
+            if let Some(contact) = tui_state
+                .contacts
+                .contact_or_group_by_id_mut(&message.contact_id)
+            {
+                let muted = tui_state.mutes.is_muted(&message.contact_id, timestamp());
+                if message.sender != tui_state.self_id && !muted {
+                    if let Some(sender) = &sender {
+                        config.hooks.do_on_new_message(
+                            &tui_state.app_name,
+                            contact,
+                            sender,
+                            &message,
+                        );
+                    }
+                    if let MessageContent::Call { missed, link } = &message.content {
+                        // a call is worth hearing about whoever it's from
+                        let caller = sender
+                            .clone()
+                            .unwrap_or_else(|| unknown_sender(&message.sender));
+                        config.hooks.do_on_call(
+                            &tui_state.app_name,
+                            contact,
+                            &caller,
+                            *missed,
+                            link.as_deref(),
+                        );
+                    }
+                }
+
+                contact.last_message_timestamp = Some(message.timestamp);
+                tui_state.plugins.notify_message(&message);
+
+                if message.sender != tui_state.self_id
+                    && message.timestamp
+                        > tui_state
+                            .contacts
+                            .last_read(&message.contact_id)
+                            .unwrap_or_default()
+                {
+                    tui_state.missed.add(&message.contact_id, mention);
+                }
+
+                let bump = match tui_state.config.contact_order {
+                    ContactOrder::BumpAlways => true,
+                    ContactOrder::BumpUnmuted => !muted,
+                    ContactOrder::Fixed => false,
+                };
+                let selected = tui_state.contacts.state.selected();
+                if let Some(i) = tui_state.contacts.index_by_id(&message.contact_id) {
+                    if bump {
+                        tui_state.contacts.move_by_index(i, 0);
+                    }
+                    if selected == Some(i) {
+                        tui_state
+                            .contacts
+                            .state
+                            .select(Some(if bump { 0 } else { i }));
+                        tui_state.messages.add_single(message);
+                    } else {
+                        if message.sender != tui_state.self_id && !muted && !in_split {
+                            tui_state.contacts.add_unread(&message.contact_id);
+                            if mention {
+                                tui_state.contacts.add_mention(&message.contact_id);
+                            }
+                        }
+                        // keep the same conversation selected when those above it move down
+                        if let Some(selected) = selected.filter(|s| bump && *s < i) {
+                            tui_state.contacts.state.select(Some(selected + 1));
+                        }
+                    }
+                    commands::arrange_contacts(tui_state);
+                }
+            }
+        }
+        FrontendMessage::RemovedMessage {
+            contact_id,
+            timestamp,
+        } => {
+            if tui_state
+                .contacts
+                .selected()
+                .is_some_and(|contact| contact.id == contact_id)
+            {
+                tui_state.messages.remove(timestamp);
+            }
+            if let Some(split) = tui_state
+                .split
+                .as_mut()
+                .filter(|split| split.contact_id == contact_id)
+            {
+                split.messages.remove(timestamp);
+            }
+        }
+        FrontendMessage::DownloadedAttachment {
+            contact_id,
+            timestamp,
+            index,
+            sender,
+            file_path: file_name,
+        } => {
+            tui_state.transfers.remove(&Transfer::Download {
+                contact_id: contact_id.clone(),
+                timestamp,
+                index,
+            });
+            let file_name = save_download(
+                tui_state,
+                config,
+                &contact_id,
+                &sender,
+                timestamp,
+                file_name,
+            );
+            config.hooks.do_on_attachment_downloaded(
+                &tui_state.app_name,
+                &contact_id,
+                timestamp,
+                index,
+                &file_name,
+            );
+            for forward in tui_state
+                .forwards
+                .downloaded(&contact_id, timestamp, index, &file_name)
+            {
+                commands::send_forward(ba_tx, &forward);
+            }
+            if let Some(attachment) = tui_state
+                .split
+                .as_mut()
+                .filter(|split| split.contact_id == contact_id)
+                .and_then(|split| split.messages.get_mut_by_timestamp(timestamp))
+                .and_then(|msg| msg.attachments.iter_mut().find(|a| a.index == index))
+            {
+                attachment.path = Some(file_name.clone());
+            }
+            if let Some(contact) = tui_state
+                .contacts
+                .state
+                .selected()
+                .and_then(|i| tui_state.contacts.contact_or_group_by_index_mut(i))
+            {
+                if contact_id == contact.id {
+                    if let Some(msg) = tui_state.messages.get_mut_by_timestamp(timestamp) {
+                        // mark attachment as downloaded
+                        let attachment = msg
+                            .attachments
+                            .iter_mut()
+                            .find(|a| a.index == index)
+                            .unwrap();
+                        attachment.path = Some(file_name);
+                    }
+                }
+            }
+        }
+        FrontendMessage::DownloadFailed {
+            contact_id,
+            timestamp,
+            index,
+            message,
+        } => {
+            tui_state.transfers.remove(&Transfer::Download {
+                contact_id: contact_id.clone(),
+                timestamp,
+                index,
+            });
+            let dropped = tui_state.forwards.failed(&contact_id, timestamp, index);
+            tui_state.command_line.error = if dropped > 0 {
+                format!("{message}, so the forward wasn't sent")
+            } else {
+                message
+            };
+        }
+        FrontendMessage::TransferProgress {
+            transfer,
+            transferred,
+            total,
+        } => {
+            tui_state.transfers.update(transfer, transferred, total);
+        }
+        FrontendMessage::LoadedPinnedMessages {
+            contact_id,
+            timestamps,
+        } => {
+            tui_state.pinned_messages.insert(contact_id, timestamps);
+        }
+        FrontendMessage::LoadedContactDetails {
+            contact_id,
+            details,
+        } => {
+            tui_state.contact_details.insert(contact_id, details);
+        }
+        FrontendMessage::FoundUser { query, result } => match result {
+            Ok(contact) => {
+                info!(query; "Found user");
+                let last_selected = tui_state.contacts.state.selected();
+                if tui_state.contacts.index_by_id(&contact.id).is_none() {
+                    tui_state.contacts.extend([contact.clone()]);
+                }
+                let index = tui_state.contacts.index_by_id(&contact.id);
+                tui_state.contacts.state.select(index);
+                commands::after_contact_changed(tui_state, ba_tx, last_selected);
+            }
+            Err(error) => {
+                warn!(query, error:%; "Failed to find user");
+                tui_state.command_line.error = error;
+            }
+        },
+        FrontendMessage::LoadedMessageReceipts {
+            contact_id,
+            timestamp,
+            receipts,
+        } => {
+            tui_state
+                .message_receipts
+                .insert((contact_id, timestamp), receipts);
+        }
+        FrontendMessage::Connectivity { connectivity } => {
+            tui_state.connectivity = connectivity;
+            if connectivity != Connectivity::Online {
+                info!(connectivity:%; "Backend disconnected");
+                tui_state.missed.disconnected(timestamp());
+            } else if tui_state.missed.is_disconnected() {
+                info!("Backend reconnected");
+                if tui_state.missed.connected() && matches!(tui_state.mode, Mode::Normal) {
+                    tui_state.popup = Some(Popup::new(PopupType::MissedMessages));
+                    tui_state.mode = Mode::Popup;
+                }
+            }
+        }
+        FrontendMessage::DeviceVerification { verification } => {
+            info!(verification:?; "Device verification progressed");
+            tui_state.device_verification = Some(verification);
+            let showing = tui_state
+                .popup
+                .as_ref()
+                .is_some_and(|p| matches!(p.typ, PopupType::Verification));
+            if !showing && matches!(tui_state.mode, Mode::Normal) {
+                tui_state.popup = Some(Popup::new(PopupType::Verification));
+                tui_state.mode = Mode::Popup;
+            }
+        }
+        FrontendMessage::Typing {
+            contact_id,
+            senders,
+        } => {
+            if senders.is_empty() {
+                tui_state.typing.remove(&contact_id);
+            } else {
+                tui_state.typing.insert(contact_id, senders);
+            }
+        }
+        FrontendMessage::PreSendFinished { id, outcome } => {
+            commands::pre_send_finished(tui_state, ba_tx, id, outcome);
+        }
+        FrontendMessage::Error { message } => {
+            tui_state.command_line.error = message;
+        }
+        FrontendMessage::ConfigChanged => {
+            if let Err(error) = reload_config(tui_state) {
+                tui_state.command_line.error = error;
+            }
+            commands::arrange_contacts(tui_state);
+        }
+        FrontendMessage::Redraw => {}
+        FrontendMessage::Tick => {
+            let now = timestamp();
+            if tui_state.mutes.remove_expired(now) {
+                tui_state.dirty = true;
+            }
+            if let Some(minutes) = config.contact_sync_minutes {
+                if now.saturating_sub(tui_state.contacts_synced_at) >= minutes * 60 * 1000 {
+                    commands::request_contact_sync(tui_state, ba_tx);
+                }
+            }
+        }
+    }
+}
+
+/// Keep the preview of a conversation up to date with the messages arriving in it.
+fn update_preview(tui_state: &mut TuiState, message: &Message) {
+    match &message.content {
+        MessageContent::Text { .. } => {
+            if tui_state
+                .previews
+                .get(&message.contact_id)
+                .is_none_or(|preview| preview.timestamp <= message.timestamp)
+            {
+                tui_state
+                    .previews
+                    .insert(message.contact_id.clone(), message.clone());
+            }
+        }
+        MessageContent::Edit {
+            timestamp, text, ..
+        } => {
+            if let Some(MessageContent::Text {
+                text: preview_text, ..
+            }) = tui_state
+                .previews
+                .get_mut(&message.contact_id)
+                .filter(|preview| preview.timestamp == *timestamp)
+                .map(|preview| &mut preview.content)
+            {
+                preview_text.clone_from(text);
+            }
+        }
+        MessageContent::Reaction { .. }
+        | MessageContent::Unsupported { .. }
+        | MessageContent::Call { .. } => {}
+    }
+}
+
+fn process_emoji_picker_key(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    code: KeyCode,
+    modifiers: KeyModifiers,
+) {
+    let Some(Popup {
+        typ: PopupType::EmojiPicker(picker),
+        ..
+    }) = &mut tui_state.popup
+    else {
+        return;
+    };
+    match code {
+        KeyCode::Esc => commands::close_emoji_picker(tui_state),
+        KeyCode::Enter => {
+            if let Err(error) = commands::pick_emoji(tui_state, ba_tx) {
+                tui_state.command_line.error = error.to_string();
+            }
+        }
+        KeyCode::Down | KeyCode::Tab => picker.select_next(&tui_state.emoji_usage),
+        KeyCode::Up | KeyCode::BackTab => picker.select_previous(),
+        KeyCode::Backspace => picker.pop(),
+        KeyCode::Char(c @ '1'..='9') if picker.quick => {
+            let index = c as usize - '1' as usize;
+            if picker.select(index, &tui_state.emoji_usage) {
+                if let Err(error) = commands::pick_emoji(tui_state, ba_tx) {
+                    tui_state.command_line.error = error.to_string();
+                }
+            }
+        }
+        KeyCode::Char(c) if !modifiers.contains(KeyModifiers::CONTROL) => picker.push(c),
+        _ => {}
+    }
+}
+
+/// Select what was clicked, opening links, and scroll the pane under the pointer.
+fn process_mouse_event(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    mouse: MouseEvent,
+) {
+    let position = Position::new(mouse.column, mouse.row);
+    let over_contacts = tui_state.areas.contacts.contains(position);
+    let over_messages = tui_state.areas.messages.contains(position);
+    let result = match mouse.kind {
+        MouseEventKind::ScrollDown if over_contacts => {
+            NextContact::default().execute(tui_state, ba_tx)
+        }
+        MouseEventKind::ScrollUp if over_contacts => {
+            PrevContact::default().execute(tui_state, ba_tx)
+        }
+        MouseEventKind::ScrollDown if over_messages => {
+            NextMessage::default().execute(tui_state, ba_tx)
+        }
+        MouseEventKind::ScrollUp if over_messages => {
+            PrevMessage::default().execute(tui_state, ba_tx)
+        }
+        MouseEventKind::Down(MouseButton::Left) if over_contacts => {
+            let row = usize::from(mouse.row - tui_state.areas.contacts.y);
+            if let Some(Some(index)) = tui_state.areas.contact_rows.get(row).copied() {
+                let last_selected = tui_state.contacts.state.selected();
+                tui_state.contacts.state.select(Some(index));
+                commands::after_contact_changed(tui_state, ba_tx, last_selected);
+            }
+            Ok(commands::CommandSuccess::Nothing)
+        }
+        MouseEventKind::Down(MouseButton::Left) if over_messages => {
+            let row = usize::from(mouse.row - tui_state.areas.messages.y);
+            let Some((index, text)) = tui_state.areas.message_rows.get(row).cloned() else {
+                return;
+            };
+            tui_state.messages.state.select(Some(index));
+            let column = usize::from(mouse.column - tui_state.areas.messages.x);
+            match commands::link_at(&text, column) {
+                Some(link) => {
+                    commands::open_link(&link).map(|()| commands::CommandSuccess::Nothing)
+                }
+                None => Ok(commands::CommandSuccess::Nothing),
+            }
+        }
+        _ => return,
+    };
+    if let Err(error) = result {
+        tui_state.command_line.error = error.to_string();
+    }
+}
+
+/// Unlock once the passphrase has been entered, returning whether to quit.
+fn process_lock_key(tui_state: &mut TuiState, key: KeyEvent) -> bool {
+    let (Some(lock), Some(vault)) = (&mut tui_state.lock, &tui_state.vault) else {
+        return false;
+    };
+    match lock.key(key) {
+        Some(LockAction::Submit(passphrase)) if vault.check(&passphrase) => tui_state.lock = None,
+        Some(LockAction::Submit(_)) => lock.error = Some("Wrong passphrase".to_owned()),
+        Some(LockAction::Quit) => return true,
+        None => {}
+    }
+    false
+}
+
+fn process_file_picker_key(tui_state: &mut TuiState, code: KeyCode) {
+    let Some(Popup {
+        typ: PopupType::FilePicker(picker),
+        ..
+    }) = &mut tui_state.popup
+    else {
+        return;
+    };
+    match code {
+        KeyCode::Esc | KeyCode::Char('q') => commands::close_file_picker(tui_state),
+        KeyCode::Enter | KeyCode::Char('l') | KeyCode::Right => {
+            commands::attach_picked_files(tui_state)
+        }
+        KeyCode::Down | KeyCode::Char('j') => picker.select_next(),
+        KeyCode::Up | KeyCode::Char('k') => picker.select_previous(),
+        KeyCode::Backspace | KeyCode::Char('h') | KeyCode::Left => picker.parent(),
+        KeyCode::Char(' ') => picker.toggle_mark(),
+        KeyCode::Char('.') => picker.toggle_hidden(),
+        _ => {}
+    }
+}
+
+/// A contact for a sender the backend hasn't reported, named by their id as the messages show
+/// them.
+fn unknown_sender(sender: &[u8]) -> Contact {
+    Contact {
+        id: ContactId::User(sender.to_vec()),
+        name: String::from_utf8(sender.to_vec()).unwrap_or_else(|_| hex::encode(sender)),
+        address: String::new(),
+        last_message_timestamp: None,
+        description: String::new(),
+        muted_until: None,
+        verification: Verification::Unverified,
+        members: None,
+        encrypted: false,
+    }
+}
+
+/// Download the attachments of a new message that the config or the conversation's override say
+/// to.
+fn auto_download_attachments(
+    tui_state: &TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    config: &Config,
+    message: &Message,
+) {
+    let MessageContent::Text { attachments, .. } = &message.content else {
+        return;
+    };
+    if message.sender == tui_state.self_id {
+        return;
+    }
+    let Some(contact) = tui_state
+        .contacts
+        .iter_contacts_and_groups()
+        .find(|c| c.id == message.contact_id)
+    else {
+        return;
+    };
+    let setting = tui_state.auto_downloads.get(&contact.id);
+    for attachment in attachments {
+        if attachment.path.is_none() && config.downloads.auto_download(contact, attachment, setting)
+        {
+            debug!(
+                name:? = attachment.name,
+                size = attachment.size;
+                "Automatically downloading attachment"
+            );
+            ba_tx
+                .unbounded_send(BackendMessage::DownloadAttachment {
+                    contact_id: contact.id.clone(),
+                    timestamp: message.timestamp,
+                    index: attachment.index,
+                    sender: message.sender.clone(),
+                })
+                .unwrap();
+        }
+    }
+}
+
+/// Copy a downloaded attachment to where the config says it belongs for the conversation,
+/// returning its new path, or the original if it should stay where the backend put it.
+fn save_download(
+    tui_state: &TuiState,
+    config: &Config,
+    contact_id: &ContactId,
+    sender: &[u8],
+    timestamp: u64,
+    file_path: PathBuf,
+) -> PathBuf {
+    // copying it out would leave it readable once the vault is sealed
+    if tui_state.vault.is_some() {
+        debug!(path:? = file_path; "Keeping attachment in the vault");
+        return file_path;
+    }
+    let Some(contact) = tui_state
+        .contacts
+        .iter_contacts_and_groups()
+        .find(|c| &c.id == contact_id)
+    else {
+        return file_path;
+    };
+    let sender = tui_state
+        .contacts
+        .contact_by_id(&sender.to_vec())
+        .map_or("unknown", |c| c.name.as_str());
+    let Some(destination) = config
+        .downloads
+        .destination(contact, sender, timestamp, &file_path)
+    else {
+        return file_path;
+    };
+    if destination == file_path {
+        return file_path;
+    }
+    // keep what was saved there before, such as another attachment with the same name
+    let destination = unique_path(destination);
+    let copied = destination
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| std::fs::copy(&file_path, &destination));
+    match copied {
+        Ok(_) => {
+            debug!(from:? = file_path, to:? = destination; "Saved attachment");
+            destination
+        }
+        Err(error) => {
+            warn!(error:%, path:? = destination; "Failed to save attachment, leaving it in place");
+            file_path
+        }
+    }
+}
+
+/// Whether the message mentions us, either as one of its mentions or by name, e.g. `@Alice`.
+fn mentions_self(tui_state: &TuiState, message: &Message) -> bool {
+    let MessageContent::Text { text, mentions, .. } = &message.content else {
+        return false;
+    };
+    tui_state.mentions_self(text, mentions.iter().map(|m| m.id.as_slice()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::{Contact, Invite, LoadStatus, Mention};
+    use crate::testing::{contact, test_app, text_message};
+
+    #[test]
+    fn test_drops_stale_loaded_messages() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let contact_id = ContactId::User(vec![1]);
+        let mut tui_state = TuiState::default();
+        tui_state
+            .contacts
+            .extend(vec![contact(contact_id.clone(), "Alice")]);
+        tui_state.contacts.state.select_next();
+        // switched conversations twice, so only the second load is still wanted
+        tui_state.messages_request = 2;
+
+        let loaded = |request_id, text: &str| FrontendMessage::LoadedMessages {
+            request_id,
+            messages: vec![Message {
+                timestamp: 1,
+                sender: vec![1],
+                contact_id: contact_id.clone(),
+                content: MessageContent::Text {
+                    text: text.to_owned(),
+                    attachments: Vec::new(),
+                    mentions: Vec::new(),
+                    formatting: Vec::new(),
+                },
+                quote: None,
+            }],
+        };
+        let config = Config::default();
+        process_backend_message(&mut tui_state, &ba_tx, &config, loaded(1, "stale"));
+        assert!(tui_state.messages.is_empty());
+        process_backend_message(&mut tui_state, &ba_tx, &config, loaded(2, "wanted"));
+        assert_eq!(tui_state.messages.len(), 1);
+    }
+
+    #[test]
+    fn test_split_messages() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let user = |id: u8| contact(ContactId::User(vec![id]), &id.to_string());
+        let message = |id: u8, timestamp| Message {
+            timestamp,
+            sender: vec![id],
+            contact_id: ContactId::User(vec![id]),
+            content: MessageContent::Text {
+                text: "hello".to_owned(),
+                attachments: Vec::new(),
+                mentions: Vec::new(),
+                formatting: Vec::new(),
+            },
+            quote: None,
+        };
+        let mut tui_state = TuiState {
+            messages_request: 1,
+            split: Some(crate::tui::split::Split {
+                contact_id: ContactId::User(vec![2]),
+                messages: Default::default(),
+                compose: Default::default(),
+                messages_request: 2,
+                on_left: true,
+            }),
+            ..Default::default()
+        };
+        tui_state.contacts.extend(vec![user(1), user(2)]);
+        tui_state.contacts.state.select(Some(0));
+        let config = Config::default();
+
+        // loads go to the pane that asked for them
+        let loaded = |request_id, id| FrontendMessage::LoadedMessages {
+            request_id,
+            messages: vec![message(id, 1)],
+        };
+        process_backend_message(&mut tui_state, &ba_tx, &config, loaded(2, 2));
+        process_backend_message(&mut tui_state, &ba_tx, &config, loaded(1, 1));
+        assert_eq!(tui_state.messages.len(), 1);
+        let split = tui_state.split.as_ref().unwrap();
+        assert_eq!(split.messages.len(), 1);
+
+        // new messages are shown in the other pane without counting as unread
+        let new = FrontendMessage::NewMessage {
+            message: message(2, 2),
+        };
+        process_backend_message(&mut tui_state, &ba_tx, &config, new);
+        assert_eq!(tui_state.messages.len(), 1);
+        assert_eq!(tui_state.split.as_ref().unwrap().messages.len(), 2);
+        assert_eq!(tui_state.contacts.total_unread_count(), 0);
+    }
+
+    #[test]
+    fn test_counts_mentions() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let group = ContactId::Group(vec![9]);
+        let mut tui_state = TuiState {
+            self_id: vec![1],
+            ..Default::default()
+        };
+        tui_state.contacts.extend(vec![
+            contact(ContactId::User(vec![2]), "Bob"),
+            Contact {
+                members: Some(3),
+                ..contact(group.clone(), "Climbing")
+            },
+        ]);
+        tui_state.contacts.state.select(Some(0));
+        let config = Config {
+            contact_order: ContactOrder::Fixed,
+            ..Default::default()
+        };
+        let message = |timestamp, mentions| FrontendMessage::NewMessage {
+            message: Message {
+                timestamp,
+                sender: vec![3],
+                contact_id: group.clone(),
+                content: MessageContent::Text {
+                    text: "hello".to_owned(),
+                    attachments: Vec::new(),
+                    mentions,
+                    formatting: Vec::new(),
+                },
+                quote: None,
+            },
+        };
+        let mention = |id: u8| Mention {
+            start: 0,
+            length: 0,
+            id: vec![id],
+        };
+
+        process_backend_message(&mut tui_state, &ba_tx, &config, message(1, Vec::new()));
+        process_backend_message(
+            &mut tui_state,
+            &ba_tx,
+            &config,
+            message(2, vec![mention(1)]),
+        );
+        process_backend_message(
+            &mut tui_state,
+            &ba_tx,
+            &config,
+            message(3, vec![mention(2)]),
+        );
+        assert_eq!(tui_state.contacts.unread_count(&group), 3);
+        assert_eq!(tui_state.contacts.mention_count(&group), 1);
+
+        tui_state.contacts.mark_read(&group);
+        assert_eq!(tui_state.contacts.mention_count(&group), 0);
+    }
+
+    fn press_keys(
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+        config: &Config,
+        keys: &str,
+    ) {
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(80, 24)).unwrap();
+        for c in keys.chars() {
+            let event = Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+            process_user_event(tui_state, ba_tx, &mut terminal, config, event);
+        }
+    }
+
+    fn keybinds_config() -> Config {
+        Config {
+            keybinds: toml::from_str(
+                r#"
+                [normal]
+                j = ":next-message<Enter>"
+                0 = ":prev-message<Enter>"
+                i = ":mode-compose<Enter>"
+                "#,
+            )
+            .unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_count_repeats_motion() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let config = keybinds_config();
+        let mut tui_state = TuiState::default();
+        tui_state.messages.state.select(Some(0));
+
+        press_keys(&mut tui_state, &ba_tx, &config, "3j");
+        assert_eq!(tui_state.messages.state.selected(), Some(3));
+        assert_eq!(tui_state.count_prefix, None);
+
+        // the count only applies to the next binding
+        press_keys(&mut tui_state, &ba_tx, &config, "j");
+        assert_eq!(tui_state.messages.state.selected(), Some(4));
+    }
+
+    #[test]
+    fn test_zero_is_a_key_until_counting() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let config = keybinds_config();
+        let mut tui_state = TuiState::default();
+        tui_state.messages.state.select(Some(20));
+
+        press_keys(&mut tui_state, &ba_tx, &config, "0");
+        assert_eq!(tui_state.messages.state.selected(), Some(19));
+
+        press_keys(&mut tui_state, &ba_tx, &config, "10j");
+        assert_eq!(tui_state.messages.state.selected(), Some(29));
+    }
+
+    #[test]
+    fn test_count_before_command_without_count() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let config = keybinds_config();
+        let mut tui_state = TuiState::default();
+        tui_state.messages.state.select(Some(0));
+
+        press_keys(&mut tui_state, &ba_tx, &config, "3i");
+        assert!(matches!(tui_state.mode, Mode::Compose));
+        assert_eq!(tui_state.count_prefix, None);
+        assert_eq!(tui_state.count, None);
+        assert_eq!(tui_state.messages.state.selected(), Some(0));
+    }
+
+    #[test]
+    fn test_contact_order() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let user = |id: u8, muted_until| Contact {
+            muted_until,
+            ..contact(ContactId::User(vec![id]), &id.to_string())
+        };
+        let new_message = |id: u8| FrontendMessage::NewMessage {
+            message: Message {
+                timestamp: 1,
+                sender: vec![id],
+                contact_id: ContactId::User(vec![id]),
+                content: MessageContent::Text {
+                    text: "hi".to_owned(),
+                    attachments: Vec::new(),
+                    mentions: Vec::new(),
+                    formatting: Vec::new(),
+                },
+                quote: None,
+            },
+        };
+        let order = |contact_order, id| {
+            let mut tui_state = TuiState::default();
+            tui_state.config.contact_order = contact_order;
+            let contacts = vec![user(1, None), user(2, None), user(3, Some(u64::MAX))];
+            tui_state.mutes.sync_from_backend(&contacts);
+            tui_state.contacts.extend(contacts);
+            tui_state.contacts.state.select(Some(1));
+            let config = Config::default();
+            process_backend_message(&mut tui_state, &ba_tx, &config, new_message(id));
+            let names = tui_state
+                .contacts
+                .iter_contacts_and_groups()
+                .map(|c| c.name.clone())
+                .collect::<Vec<_>>()
+                .join("");
+            let selected = tui_state.contacts.selected().unwrap().name.clone();
+            (names, selected)
+        };
+
+        // the selected conversation stays selected as others move around it
+        assert_eq!(
+            order(ContactOrder::BumpAlways, 3),
+            ("312".to_owned(), "2".to_owned())
+        );
+        assert_eq!(
+            order(ContactOrder::BumpAlways, 2),
+            ("213".to_owned(), "2".to_owned())
+        );
+        assert_eq!(
+            order(ContactOrder::BumpUnmuted, 3),
+            ("123".to_owned(), "2".to_owned())
+        );
+        assert_eq!(
+            order(ContactOrder::BumpUnmuted, 1),
+            ("123".to_owned(), "2".to_owned())
+        );
+        assert_eq!(
+            order(ContactOrder::Fixed, 3),
+            ("123".to_owned(), "2".to_owned())
+        );
+        assert_eq!(
+            order(ContactOrder::Fixed, 2),
+            ("123".to_owned(), "2".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_redraws_only_on_changes() {
+        let (mut app, _ba_rx) = test_app();
+        assert!(app.tui_state.dirty);
+        app.draw();
+        let now = timestamp();
+        assert!(!app.tui_state.needs_redraw(now));
+
+        app.backend_message(FrontendMessage::Tick);
+        assert!(!app.tui_state.needs_redraw(now));
+
+        app.backend_message(FrontendMessage::Redraw);
+        assert!(app.tui_state.needs_redraw(now));
+        app.draw();
+
+        app.user_event(Event::Resize(100, 30));
+        assert!(app.tui_state.needs_redraw(now));
+        app.draw();
+
+        // the age of a new contact moves on within the second
+        app.backend_message(FrontendMessage::LoadedContacts {
+            contacts: vec![Contact {
+                last_message_timestamp: Some(now),
+                ..contact(ContactId::User(vec![1]), "Alice")
+            }],
+        });
+        app.draw();
+        assert!(!app.tui_state.needs_redraw(now));
+        assert!(app.tui_state.needs_redraw(now + 1000));
+    }
+
+    #[test]
+    fn test_scheduled_contact_sync() {
+        let (mut app, mut ba_rx) = test_app();
+        app.tui_state.config.contact_sync_minutes = Some(60);
+        app.start();
+        let mut sent = || {
+            std::iter::from_fn(|| ba_rx.try_recv().ok())
+                .filter(|message| matches!(message, BackendMessage::SyncContacts))
+                .count()
+        };
+        app.backend_message(FrontendMessage::Tick);
+        assert_eq!(sent(), 0);
+
+        app.tui_state.contacts_synced_at -= 60 * 60 * 1000;
+        app.backend_message(FrontendMessage::Tick);
+        app.backend_message(FrontendMessage::Tick);
+        assert_eq!(sent(), 1);
+    }
+
+    #[test]
+    fn test_folders() {
+        let (mut app, _ba_rx) = test_app();
+        let user = |id: u8, name: &str| contact(ContactId::User(vec![id]), name);
+        app.tui_state.config.contact_sort = crate::config::ContactSort::Name;
+        app.tui_state
+            .config
+            .folders
+            .insert("Work".to_owned(), vec!["Alice".to_owned()]);
+        app.backend_message(FrontendMessage::LoadedContacts {
+            contacts: vec![user(1, "Alice"), user(2, "Carol"), user(3, "Bob")],
+        });
+        let names = |app: &App<_>| {
+            app.tui_state
+                .contacts
+                .iter_contacts_and_groups()
+                .map(|c| c.name.clone())
+                .collect::<Vec<_>>()
+        };
+        // those in no folder first
+        assert_eq!(names(&app), ["Bob", "Carol", "Alice"]);
+        assert_eq!(app.tui_state.contacts.state.selected(), Some(0));
+
+        app.draw();
+        assert_eq!(
+            app.tui_state.areas.contact_rows[..4],
+            [Some(0), Some(1), None, Some(2)]
+        );
+        let contacts = app.tui_state.areas.contacts;
+        let click = |row| {
+            Event::Mouse(MouseEvent {
+                kind: MouseEventKind::Down(MouseButton::Left),
+                column: contacts.x,
+                row: contacts.y + row,
+                modifiers: KeyModifiers::NONE,
+            })
+        };
+        // the folder heading
+        app.user_event(click(2));
+        assert_eq!(app.tui_state.contacts.state.selected(), Some(0));
+        app.user_event(click(3));
+        assert_eq!(app.tui_state.contacts.state.selected(), Some(2));
+    }
+
+    #[test]
+    fn test_contact_previews() {
+        let (mut app, mut ba_rx) = test_app();
+        app.tui_state.self_id = vec![1];
+        app.tui_state.config.contact_previews = true;
+        let group = ContactId::Group(vec![9]);
+        app.backend_message(FrontendMessage::LoadedContacts {
+            contacts: vec![
+                contact(ContactId::User(vec![2]), "Alice"),
+                contact(group.clone(), "Lunch"),
+            ],
+        });
+        assert!(std::iter::from_fn(|| ba_rx.try_recv().ok())
+            .any(|message| matches!(message, BackendMessage::LoadSummaries)));
+
+        app.backend_message(FrontendMessage::LoadedSummaries {
+            summaries: vec![
+                text_message(&ContactId::User(vec![2]), &[2], 1, "See you\nthere"),
+                text_message(&group, &[1], 2, "Noodles?"),
+            ],
+        });
+        app.backend_message(FrontendMessage::NewMessage {
+            message: text_message(&group, &[2], 3, "Pizza"),
+        });
+        app.backend_message(FrontendMessage::NewMessage {
+            message: Message {
+                content: MessageContent::Edit {
+                    timestamp: 3,
+                    text: "Pizza!".to_owned(),
+                    mentions: Vec::new(),
+                    formatting: Vec::new(),
+                },
+                ..text_message(&group, &[2], 4, "")
+            },
+        });
+
+        app.draw();
+        let screen = app.terminal.backend().to_string();
+        assert!(screen.contains("See you there"), "{screen}");
+        assert!(screen.contains("Alice: Pizza!"), "{screen}");
+        // both lines of a contact select it
+        assert_eq!(
+            app.tui_state.areas.contact_rows[..4],
+            [Some(0), Some(0), Some(1), Some(1)]
+        );
+    }
+
+    #[test]
+    fn test_calls() {
+        let (mut app, _ba_rx) = test_app();
+        let user = |id: u8, name: &str| contact(ContactId::User(vec![id]), name);
+        app.backend_message(FrontendMessage::LoadedContacts {
+            contacts: vec![user(1, "Alice"), user(2, "Bob")],
+        });
+        let call = |timestamp, id: u8, missed, link: Option<&str>| FrontendMessage::NewMessage {
+            message: Message {
+                timestamp,
+                sender: vec![id],
+                contact_id: ContactId::User(vec![id]),
+                content: MessageContent::Call {
+                    missed,
+                    link: link.map(str::to_owned),
+                },
+                quote: None,
+            },
+        };
+        app.backend_message(call(1, 1, false, Some("https://call.example/alice")));
+        app.backend_message(call(2, 1, true, None));
+        app.backend_message(call(3, 2, true, None));
+
+        // a later call without a link still leaves the earlier one to return
+        assert_eq!(
+            app.tui_state.call_links.get(&ContactId::User(vec![1])),
+            Some(&"https://call.example/alice".to_owned())
+        );
+        assert!(!app
+            .tui_state
+            .call_links
+            .contains_key(&ContactId::User(vec![2])));
+
+        app.draw();
+        let screen = app.terminal.backend().to_string();
+        assert!(screen.contains("Missed call"), "{screen}");
+
+        // nothing to open without a link or a command to call with
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let index = app
+            .tui_state
+            .contacts
+            .index_by_id(&ContactId::User(vec![2]))
+            .unwrap();
+        app.tui_state.contacts.state.select(Some(index));
+        assert!(commands::CallBack
+            .execute(&mut app.tui_state, &ba_tx)
+            .is_err());
+        app.tui_state.config.call_command = Some("true".to_owned());
+        assert!(commands::CallBack
+            .execute(&mut app.tui_state, &ba_tx)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_invites() {
+        let (mut app, _ba_rx) = test_app();
+        app.backend_message(FrontendMessage::LoadedContacts {
+            contacts: vec![contact(ContactId::User(vec![1]), "Alice")],
+        });
+        let invite = Invite {
+            contact_id: ContactId::Group(vec![2]),
+            name: "Book club".to_owned(),
+            inviter: "Alice".to_owned(),
+        };
+        // reported again as the backend syncs
+        for _ in 0..2 {
+            app.backend_message(FrontendMessage::NewInvite {
+                invite: invite.clone(),
+            });
+        }
+        assert_eq!(app.tui_state.invites, [invite]);
+
+        // wide enough to show the inviter
+        app.tui_state.panes.grow_contacts(2);
+        app.draw();
+        let screen = app.terminal.backend().to_string();
+        assert!(screen.contains("Book club from Alice"), "{screen}");
+        // the invite and headings can't be selected
+        assert_eq!(
+            app.tui_state.areas.contact_rows[..4],
+            [None, None, None, Some(0)]
+        );
+    }
+
+    #[test]
+    fn test_background_catch_up() {
+        let (mut app, _ba_rx) = test_app();
+        let mut status = LoadStatus {
+            messages: 200,
+            contacts_synced: false,
+            catching_up: true,
+        };
+        app.backend_message(FrontendMessage::LoadProgress { status });
+        // the batches shown while catching up don't end the sync
+        app.backend_message(FrontendMessage::LoadedContacts {
+            contacts: Vec::new(),
+        });
+        assert!(app.tui_state.syncing);
+
+        status.catching_up = false;
+        app.backend_message(FrontendMessage::LoadProgress { status });
+        app.backend_message(FrontendMessage::LoadedContacts {
+            contacts: Vec::new(),
+        });
+        assert!(!app.tui_state.syncing);
+    }
+
+    #[test]
+    fn test_mouse() {
+        let (mut app, _ba_rx) = test_app();
+        let user = |id: u8| contact(ContactId::User(vec![id]), &id.to_string());
+        app.tui_state
+            .contacts
+            .extend(vec![user(1), user(2), user(3)]);
+        app.tui_state.contacts.state.select(Some(0));
+        let mouse = |kind, column, row| {
+            Event::Mouse(MouseEvent {
+                kind,
+                column,
+                row,
+                modifiers: KeyModifiers::NONE,
+            })
+        };
+        let click = MouseEventKind::Down(MouseButton::Left);
+
+        app.draw();
+        let contacts = app.tui_state.areas.contacts;
+        app.user_event(mouse(click, contacts.x, contacts.y + 2));
+        assert_eq!(app.tui_state.contacts.state.selected(), Some(2));
+        app.user_event(mouse(MouseEventKind::ScrollUp, contacts.x, contacts.y));
+        assert_eq!(app.tui_state.contacts.state.selected(), Some(1));
+        // past the last contact
+        app.user_event(mouse(click, contacts.x, contacts.y + 5));
+        assert_eq!(app.tui_state.contacts.state.selected(), Some(1));
+
+        let text =
+            |timestamp, text: &str| text_message(&ContactId::User(vec![2]), &[1], timestamp, text);
+        app.tui_state
+            .messages
+            .extend(vec![text(1, "first"), text(2, "second"), text(3, "third")]);
+        app.tui_state.messages.state.select(Some(2));
+        app.draw();
+        let messages = app.tui_state.areas.messages;
+        let row = app
+            .tui_state
+            .areas
+            .message_rows
+            .iter()
+            .position(|(index, _)| *index == 0)
+            .unwrap() as u16;
+        app.user_event(mouse(click, messages.x, messages.y + row));
+        assert_eq!(app.tui_state.messages.state.selected(), Some(0));
+        app.user_event(mouse(MouseEventKind::ScrollDown, messages.x, messages.y));
+        assert_eq!(app.tui_state.messages.state.selected(), Some(1));
+    }
+
+    #[test]
+    fn test_render_long_conversation() {
+        let (mut app, _ba_rx) = test_app();
+        app.tui_state.messages.extend((1..=1000).map(|timestamp| {
+            text_message(
+                &ContactId::User(vec![2]),
+                &[1],
+                timestamp,
+                &format!("message {timestamp}"),
+            )
+        }));
+        let rows = |app: &App<_>| {
+            app.tui_state
+                .areas
+                .message_rows
+                .iter()
+                .map(|(index, _)| *index)
+                .collect::<Vec<_>>()
+        };
+
+        // only the messages around the selection are rendered, at their place in the whole list
+        app.tui_state.messages.state.select_last();
+        app.draw();
+        assert_eq!(app.tui_state.messages.state.selected(), Some(999));
+        assert_eq!(rows(&app).last(), Some(&999));
+        let screen = app.terminal.backend().to_string();
+        assert!(screen.contains("message 1000"), "{screen}");
+
+        app.tui_state.messages.state.select(Some(500));
+        app.draw();
+        assert!(rows(&app).contains(&500));
+        let offset = app.tui_state.messages.state.offset();
+        assert_eq!(rows(&app).first(), Some(&offset));
+
+        app.tui_state.messages.state.select(Some(0));
+        app.draw();
+        assert_eq!(rows(&app).first(), Some(&0));
+        assert_eq!(app.tui_state.messages.state.offset(), 0);
+    }
+
+    #[test]
+    fn test_focus() {
+        let (mut app, _ba_rx) = test_app();
+
+        app.draw();
+        assert!(app.tui_state.areas.contacts.width > 0);
+        assert!(app.tui_state.areas.messages.x > 0);
+
+        app.tui_state.focus = true;
+        app.draw();
+        assert_eq!(app.tui_state.areas.contacts.width, 0);
+        assert_eq!(app.tui_state.areas.messages.x, 0);
+    }
+}
@@ -1,77 +1,717 @@
-use std::cmp::Reverse;
+use std::future::Future;
 
 use crate::{
-    backends::Backend,
+    backends::{
+        sort_contacts, Backend, ContactId, DeviceVerification, Error, MessageContent, Progress,
+        Quote, Result, Transfer,
+    },
+    journal::Journal,
     message::{BackendMessage, FrontendMessage},
 };
-use futures::StreamExt;
-use log::info;
+use futures::{
+    channel::mpsc,
+    future::{select, Either, LocalBoxFuture},
+    stream::FuturesUnordered,
+    FutureExt as _, StreamExt,
+};
+use log::{info, warn};
 
 pub struct BackendActor<B> {
     pub backend: B,
     pub message_rx: futures::channel::mpsc::UnboundedReceiver<BackendMessage>,
     pub message_tx: futures::channel::mpsc::UnboundedSender<FrontendMessage>,
+    pub journal: Option<Journal>,
 }
 
-impl<B: Backend> BackendActor<B> {
+/// A message waiting for those sent before it.
+type QueuedSend = (ContactId, MessageContent, Option<Quote>);
+
+impl<B: Backend + Clone + 'static> BackendActor<B> {
     pub async fn run(&mut self) {
         info!("Started backend actor");
-        while let Some(message) = self.message_rx.next().await {
+        // requests run alongside each other on clones of the backend, so that slow ones such as
+        // loading a long conversation or downloading a large attachment don't hold up the rest
+        let mut requests = FuturesUnordered::new();
+        let (send_tx, send_rx) = mpsc::unbounded();
+        requests.push(self.send_in_order(send_rx));
+        loop {
+            let message = match select(self.message_rx.next(), requests.next()).await {
+                Either::Left((message, _)) => Some(message),
+                // a request finished
+                Either::Right(_) => None,
+            };
             match message {
-                BackendMessage::LoadContacts => {
-                    let mut contacts = self.backend.users().await.unwrap();
-                    let mut groups = self.backend.groups().await.unwrap();
-                    contacts.append(&mut groups);
-                    contacts.sort_by_key(|c| (Reverse(c.last_message_timestamp), c.name.clone()));
-                    self.message_tx
-                        .unbounded_send(FrontendMessage::LoadedContacts { contacts })
-                        .unwrap();
+                Some(Some(message)) => {
+                    if let Some(journal) = &self.journal {
+                        journal.record_backend(&message);
+                    }
+                    if let Some(request) = self.handle_message(message, &send_tx) {
+                        requests.push(request);
+                    }
                 }
-                BackendMessage::LoadMessages {
-                    contact_id: contact,
-                    start_ts,
-                    end_ts,
-                } => {
-                    let messages = self
-                        .backend
-                        .messages(contact, start_ts, end_ts)
-                        .await
-                        .unwrap();
-                    self.message_tx
-                        .unbounded_send(FrontendMessage::LoadedMessages { messages })
-                        .unwrap();
+                Some(None) => break,
+                None => {}
+            }
+        }
+        info!("Closing backend actor");
+    }
+
+    /// Start handling a message, returning the request to run alongside the others.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    fn handle_message(
+        &self,
+        message: BackendMessage,
+        send_tx: &mpsc::UnboundedSender<QueuedSend>,
+    ) -> Option<LocalBoxFuture<'static, ()>> {
+        match message {
+            BackendMessage::LoadContacts => self.request(|backend, message_tx| async move {
+                send_contacts(&backend, &message_tx).await;
+            }),
+            BackendMessage::LoadMessages {
+                request_id,
+                contact_id: contact,
+                start_ts,
+                end_ts,
+            } => self.request(move |mut backend, message_tx| async move {
+                let messages = match backend.messages(contact, start_ts, end_ts).await {
+                    Ok(messages) => messages,
+                    Err(error) => {
+                        return report_error(&message_tx, "Failed to load messages", error)
+                    }
+                };
+                let _ = message_tx.unbounded_send(FrontendMessage::LoadedMessages {
+                    request_id,
+                    messages,
+                });
+            }),
+            BackendMessage::SendMessage {
+                contact_id,
+                content,
+                quote,
+            } => {
+                send_tx
+                    .unbounded_send((contact_id, content, quote))
+                    .unwrap();
+                None
+            }
+            BackendMessage::DownloadAttachment {
+                contact_id,
+                timestamp,
+                index,
+                sender,
+            } => Some(self.download(contact_id, timestamp, index, sender)),
+            BackendMessage::PinMessage {
+                contact_id,
+                timestamp,
+                pinned,
+            } => self.request(move |mut backend, message_tx| async move {
+                if let Err(error) = backend
+                    .pin_message(contact_id.clone(), timestamp, pinned)
+                    .await
+                {
+                    let what = if pinned {
+                        "Failed to pin message"
+                    } else {
+                        "Failed to unpin message"
+                    };
+                    return report_error(&message_tx, what, error);
                 }
-                BackendMessage::SendMessage {
-                    contact_id,
-                    content,
-                    quote,
-                } => {
-                    let msg = self
-                        .backend
-                        .send_message(contact_id, content, quote.as_ref())
-                        .await
-                        .unwrap();
-                    self.message_tx
-                        .unbounded_send(FrontendMessage::NewMessage { message: msg })
-                        .unwrap();
+                send_pinned_messages(&backend, &message_tx, contact_id).await;
+            }),
+            BackendMessage::DeleteMessage {
+                contact_id,
+                timestamp,
+            } => self.request(move |mut backend, message_tx| async move {
+                match backend.delete_message(contact_id.clone(), timestamp).await {
+                    Ok(()) => {
+                        let _ = message_tx.unbounded_send(FrontendMessage::RemovedMessage {
+                            contact_id,
+                            timestamp,
+                        });
+                    }
+                    Err(error) => report_error(&message_tx, "Failed to delete message", error),
                 }
-                BackendMessage::DownloadAttachment {
+            }),
+            BackendMessage::LoadPinnedMessages { contact_id } => {
+                self.request(|backend, message_tx| async move {
+                    send_pinned_messages(&backend, &message_tx, contact_id).await;
+                })
+            }
+            BackendMessage::LoadContactDetails { contact_id } => {
+                self.request(|backend, message_tx| async move {
+                    let details = match backend.contact_details(contact_id.clone()).await {
+                        Ok(details) => details,
+                        Err(error) => {
+                            return report_error(&message_tx, "Failed to load details", error)
+                        }
+                    };
+                    let _ = message_tx.unbounded_send(FrontendMessage::LoadedContactDetails {
+                        contact_id,
+                        details,
+                    });
+                })
+            }
+            BackendMessage::LoadMessageReceipts {
+                contact_id,
+                timestamp,
+            } => self.request(move |backend, message_tx| async move {
+                let receipts = backend
+                    .message_receipts(contact_id.clone(), timestamp)
+                    .await
+                    .unwrap_or_else(|error| {
+                        warn!(error:%, timestamp; "Failed to load message receipts");
+                        Vec::new()
+                    });
+                let _ = message_tx.unbounded_send(FrontendMessage::LoadedMessageReceipts {
                     contact_id,
                     timestamp,
-                    index,
-                } => {
-                    let file_path = self.backend.download_attachment(index).await.unwrap();
-                    self.message_tx
-                        .unbounded_send(FrontendMessage::DownloadedAttachment {
-                            contact_id,
-                            timestamp,
-                            index,
-                            file_path,
-                        })
-                        .unwrap();
+                    receipts,
+                });
+            }),
+            BackendMessage::FindUser { query } => {
+                self.request(|mut backend, message_tx| async move {
+                    let result = backend.find_user(query.clone()).await.map_err(describe);
+                    let _ = message_tx.unbounded_send(FrontendMessage::FoundUser { query, result });
+                })
+            }
+            BackendMessage::VerifyDevice => self.request(|mut backend, message_tx| async move {
+                let result = backend.verify_device().await;
+                report_verification_error(&message_tx, result);
+            }),
+            BackendMessage::ConfirmVerification { confirmed } => {
+                self.request(move |mut backend, message_tx| async move {
+                    let result = backend.confirm_verification(confirmed).await;
+                    report_verification_error(&message_tx, result);
+                })
+            }
+            BackendMessage::SetGroupTitle { contact_id, title } => {
+                self.request(|mut backend, message_tx| async move {
+                    if let Err(error) = backend.set_group_title(contact_id, title).await {
+                        return report_error(&message_tx, "Failed to set group title", error);
+                    }
+                    // show the new title
+                    send_contacts(&backend, &message_tx).await;
+                })
+            }
+            BackendMessage::SetGroupDescription {
+                contact_id,
+                description,
+            } => self.request(|mut backend, message_tx| async move {
+                if let Err(error) = backend.set_group_description(contact_id, description).await {
+                    return report_error(&message_tx, "Failed to set group description", error);
                 }
+                send_contacts(&backend, &message_tx).await;
+            }),
+            BackendMessage::LoadInvites => self.request(|backend, message_tx| async move {
+                send_invites(&backend, &message_tx).await;
+            }),
+            BackendMessage::AnswerInvite { contact_id, accept } => {
+                self.request(move |mut backend, message_tx| async move {
+                    match backend.answer_invite(contact_id, accept).await {
+                        // the joined conversation is among the contacts now
+                        Ok(()) if accept => send_contacts(&backend, &message_tx).await,
+                        Ok(()) => {}
+                        Err(error) => {
+                            let what = if accept {
+                                "Failed to accept invite"
+                            } else {
+                                "Failed to decline invite"
+                            };
+                            report_error(&message_tx, what, error);
+                        }
+                    }
+                    // still waiting for an answer if it failed
+                    send_invites(&backend, &message_tx).await;
+                })
             }
+            BackendMessage::SyncContacts => self.request(|mut backend, message_tx| async move {
+                if let Err(error) = backend.request_contact_sync().await {
+                    return report_error(&message_tx, "Failed to sync contacts", error);
+                }
+                send_contacts(&backend, &message_tx).await;
+            }),
+            BackendMessage::LoadSummaries => self.request(|backend, message_tx| async move {
+                match backend.conversation_summaries().await {
+                    Ok(summaries) => {
+                        let _ = message_tx
+                            .unbounded_send(FrontendMessage::LoadedSummaries { summaries });
+                    }
+                    Err(error) => report_error(&message_tx, "Failed to load summaries", error),
+                }
+            }),
         }
-        info!("Closing backend actor");
+    }
+
+    /// A request made with its own clone of the backend.
+    fn request<F>(
+        &self,
+        f: impl FnOnce(B, mpsc::UnboundedSender<FrontendMessage>) -> F,
+    ) -> Option<LocalBoxFuture<'static, ()>>
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        Some(f(self.backend.clone(), self.message_tx.clone()).boxed_local())
+    }
+
+    /// Send messages one at a time, so that they arrive in the order they were written.
+    fn send_in_order(
+        &self,
+        send_rx: mpsc::UnboundedReceiver<QueuedSend>,
+    ) -> LocalBoxFuture<'static, ()> {
+        let backend = self.backend.clone();
+        let message_tx = self.message_tx.clone();
+        send_rx
+            .for_each(move |(contact_id, content, quote)| {
+                send(
+                    backend.clone(),
+                    message_tx.clone(),
+                    contact_id,
+                    content,
+                    quote,
+                )
+            })
+            .boxed_local()
+    }
+
+    fn download(
+        &self,
+        contact_id: ContactId,
+        timestamp: u64,
+        index: usize,
+        sender: Vec<u8>,
+    ) -> LocalBoxFuture<'static, ()> {
+        let backend = self.backend.clone();
+        let message_tx = self.message_tx.clone();
+        let transfer = Transfer::Download {
+            contact_id: contact_id.clone(),
+            timestamp,
+            index,
+        };
+        let progress = Progress::new(transfer, message_tx.clone());
+        async move {
+            // backends which can tell how big it is report again once they know
+            progress.report(0, 0);
+            let file_path = match backend.download_attachment(index, progress.clone()).await {
+                Ok(file_path) => file_path,
+                Err(error) => {
+                    progress.finish();
+                    warn!(error:%; "Failed to download attachment");
+                    // anything waiting on the download can give up on it
+                    let _ = message_tx.unbounded_send(FrontendMessage::DownloadFailed {
+                        contact_id,
+                        timestamp,
+                        index,
+                        message: format!("Failed to download attachment: {}", describe(error)),
+                    });
+                    return;
+                }
+            };
+            let _ = message_tx.unbounded_send(FrontendMessage::DownloadedAttachment {
+                contact_id,
+                timestamp,
+                index,
+                sender,
+                file_path,
+            });
+        }
+        .boxed_local()
+    }
+}
+
+/// Send a message, reporting each of its attachments as uploading until it's sent.
+async fn send<B: Backend>(
+    mut backend: B,
+    message_tx: mpsc::UnboundedSender<FrontendMessage>,
+    contact_id: ContactId,
+    content: MessageContent,
+    quote: Option<Quote>,
+) {
+    let uploads: Vec<_> = match &content {
+        MessageContent::Text { attachments, .. } => attachments
+            .iter()
+            // empty files are done as soon as they start
+            .filter(|a| a.size > 0)
+            .filter_map(|a| {
+                let transfer = Transfer::Upload {
+                    contact_id: contact_id.clone(),
+                    path: a.path.clone()?,
+                };
+                Some((Progress::new(transfer, message_tx.clone()), a.size))
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    for (progress, size) in &uploads {
+        progress.report(0, *size);
+    }
+    let result = backend
+        .send_message(contact_id, content, quote.as_ref())
+        .await;
+    for (progress, _) in &uploads {
+        progress.finish();
+    }
+    match result {
+        Ok(message) => {
+            let _ = message_tx.unbounded_send(FrontendMessage::NewMessage { message });
+        }
+        Err(error) => report_error(&message_tx, "Failed to send message", error),
+    }
+}
+
+/// Tell the user about a request that failed, as there is no one else waiting on it.
+fn report_error(message_tx: &mpsc::UnboundedSender<FrontendMessage>, what: &str, error: Error) {
+    warn!(error:%; "{what}");
+    // the ui may have closed already
+    let _ = message_tx.unbounded_send(FrontendMessage::Error {
+        message: format!("{what}: {}", describe(error)),
+    });
+}
+
+/// Describe an error for the user, including why a failure happened where it is known.
+fn describe(error: Error) -> String {
+    match error {
+        Error::Failure(what, why) if !why.is_empty() => format!("{what}: {why}"),
+        error => error.to_string(),
+    }
+}
+
+/// Show why a device verification failed, such as the backend not supporting it.
+fn report_verification_error(
+    message_tx: &mpsc::UnboundedSender<FrontendMessage>,
+    result: Result<()>,
+) {
+    if let Err(error) = result {
+        let _ = message_tx.unbounded_send(FrontendMessage::DeviceVerification {
+            verification: DeviceVerification::Cancelled {
+                reason: error.to_string(),
+            },
+        });
+    }
+}
+
+async fn send_contacts<B: Backend>(
+    backend: &B,
+    message_tx: &mpsc::UnboundedSender<FrontendMessage>,
+) {
+    let loaded = async { Ok::<_, Error>((backend.users().await?, backend.groups().await?)) };
+    let (mut contacts, mut groups) = match loaded.await {
+        Ok(loaded) => loaded,
+        Err(error) => return report_error(message_tx, "Failed to load contacts", error),
+    };
+    contacts.append(&mut groups);
+    sort_contacts(&mut contacts);
+    let _ = message_tx.unbounded_send(FrontendMessage::LoadedContacts { contacts });
+}
+
+async fn send_invites<B: Backend>(
+    backend: &B,
+    message_tx: &mpsc::UnboundedSender<FrontendMessage>,
+) {
+    match backend.invites().await {
+        Ok(invites) => {
+            let _ = message_tx.unbounded_send(FrontendMessage::LoadedInvites { invites });
+        }
+        Err(error) => report_error(message_tx, "Failed to load invites", error),
+    }
+}
+
+async fn send_pinned_messages<B: Backend>(
+    backend: &B,
+    message_tx: &mpsc::UnboundedSender<FrontendMessage>,
+    contact_id: ContactId,
+) {
+    let timestamps = match backend.pinned_messages(contact_id.clone()).await {
+        Ok(timestamps) => timestamps,
+        Err(error) => return report_error(message_tx, "Failed to load pinned messages", error),
+    };
+    let _ = message_tx.unbounded_send(FrontendMessage::LoadedPinnedMessages {
+        contact_id,
+        timestamps,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ops::Bound,
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    use futures::channel::oneshot;
+
+    use super::*;
+    use crate::backends::{
+        Contact, ContactDetails, Invite, Limits, LoadProgress, Message, MessageAttachment, Receipt,
+        Setup, SetupQuestion,
+    };
+
+    /// Sends messages with attachments slowly, as uploading them would, and has no groups.
+    #[derive(Clone)]
+    struct Slow;
+
+    fn unsupported<T>() -> Result<T> {
+        Err(Error::Failure("Unsupported".to_owned(), String::new()))
+    }
+
+    impl Backend for Slow {
+        const NAME: &'static str = "Slow";
+
+        async fn load(_path: &Path, _progress: LoadProgress) -> Result<Self> {
+            Ok(Self)
+        }
+
+        fn setup_questions() -> Vec<SetupQuestion> {
+            Vec::new()
+        }
+
+        async fn link(
+            _path: &Path,
+            _setup: &Setup,
+            _provisioning_link_tx: oneshot::Sender<String>,
+        ) -> Result<Self> {
+            Ok(Self)
+        }
+
+        async fn background_sync(
+            &mut self,
+            _ba_tx: mpsc::UnboundedSender<FrontendMessage>,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        async fn users(&self) -> Result<Vec<Contact>> {
+            Ok(Vec::new())
+        }
+
+        async fn groups(&self) -> Result<Vec<Contact>> {
+            Err(Error::Failure("Groups are down".to_owned(), String::new()))
+        }
+
+        async fn messages(
+            &mut self,
+            _contact_id: ContactId,
+            _start_ts: Bound<u64>,
+            _end_ts: Bound<u64>,
+        ) -> Result<Vec<Message>> {
+            Ok(Vec::new())
+        }
+
+        async fn send_message(
+            &mut self,
+            contact_id: ContactId,
+            body: MessageContent,
+            _quoting: Option<&Quote>,
+        ) -> Result<Message> {
+            if matches!(&body, MessageContent::Text { attachments, .. } if !attachments.is_empty())
+            {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            Ok(Message {
+                timestamp: 0,
+                sender: Vec::new(),
+                contact_id,
+                content: body,
+                quote: None,
+            })
+        }
+
+        async fn self_id(&self) -> Vec<u8> {
+            Vec::new()
+        }
+
+        fn limits(&self) -> Limits {
+            Limits::default()
+        }
+
+        async fn download_attachment(
+            &self,
+            _attachment_index: usize,
+            _progress: Progress,
+        ) -> Result<PathBuf> {
+            unsupported()
+        }
+
+        async fn pin_message(
+            &mut self,
+            _contact_id: ContactId,
+            _timestamp: u64,
+            _pinned: bool,
+        ) -> Result<()> {
+            unsupported()
+        }
+
+        async fn delete_message(&mut self, _contact_id: ContactId, _timestamp: u64) -> Result<()> {
+            unsupported()
+        }
+
+        async fn pinned_messages(&self, _contact_id: ContactId) -> Result<Vec<u64>> {
+            unsupported()
+        }
+
+        async fn find_user(&mut self, _query: String) -> Result<Contact> {
+            unsupported()
+        }
+
+        async fn message_receipts(
+            &self,
+            _contact_id: ContactId,
+            _timestamp: u64,
+        ) -> Result<Vec<Receipt>> {
+            unsupported()
+        }
+
+        async fn contact_details(&self, _contact_id: ContactId) -> Result<ContactDetails> {
+            unsupported()
+        }
+
+        async fn verify_device(&mut self) -> Result<()> {
+            unsupported()
+        }
+
+        async fn confirm_verification(&mut self, _confirmed: bool) -> Result<()> {
+            unsupported()
+        }
+
+        async fn set_group_title(&mut self, _contact_id: ContactId, _title: String) -> Result<()> {
+            Err(Error::NotAllowed("change the title".to_owned()))
+        }
+
+        async fn invites(&self) -> Result<Vec<Invite>> {
+            Ok(Vec::new())
+        }
+
+        async fn answer_invite(&mut self, _contact_id: ContactId, _accept: bool) -> Result<()> {
+            unsupported()
+        }
+
+        async fn set_group_description(
+            &mut self,
+            _contact_id: ContactId,
+            _description: String,
+        ) -> Result<()> {
+            unsupported()
+        }
+
+        async fn request_contact_sync(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn conversation_summaries(&self) -> Result<Vec<Message>> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn text(text: &str, attachments: Vec<MessageAttachment>) -> MessageContent {
+        MessageContent::Text {
+            text: text.to_owned(),
+            attachments,
+            mentions: Vec::new(),
+            formatting: Vec::new(),
+        }
+    }
+
+    /// Run the actor on the messages until `count` of the replies matching `keep` are back.
+    fn run(
+        messages: Vec<BackendMessage>,
+        count: usize,
+        keep: impl Fn(&FrontendMessage) -> bool,
+    ) -> Vec<FrontendMessage> {
+        let (ba_tx, message_rx) = mpsc::unbounded();
+        let (message_tx, f_rx) = mpsc::unbounded();
+        for message in messages {
+            ba_tx.unbounded_send(message).unwrap();
+        }
+        let mut actor = BackendActor {
+            backend: Slow,
+            message_rx,
+            message_tx,
+            journal: None,
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let replies = f_rx.filter(|m| futures::future::ready(keep(m))).take(count);
+            let replies = replies.collect::<Vec<_>>();
+            match select(actor.run().boxed_local(), replies.boxed_local()).await {
+                Either::Left(_) => panic!("backend actor stopped early"),
+                Either::Right((replies, _)) => replies,
+            }
+        })
+    }
+
+    #[test]
+    fn test_sends_in_order() {
+        let contact_id = ContactId::User(vec![1]);
+        let attachment = MessageAttachment {
+            name: "cat.jpg".to_owned(),
+            size: 4,
+            index: 0,
+            path: Some(PathBuf::from("cat.jpg")),
+            content_type: None,
+        };
+        let send = |content| BackendMessage::SendMessage {
+            contact_id: contact_id.clone(),
+            content,
+            quote: None,
+        };
+        let messages = vec![
+            send(text("first", vec![attachment])),
+            send(text("second", Vec::new())),
+        ];
+        let sent: Vec<_> = run(messages, 2, |m| {
+            matches!(m, FrontendMessage::NewMessage { .. })
+        })
+        .into_iter()
+        .map(|m| match m {
+            FrontendMessage::NewMessage {
+                message:
+                    Message {
+                        content: MessageContent::Text { text, .. },
+                        ..
+                    },
+            } => text,
+            message => panic!("unexpected {message:?}"),
+        })
+        .collect();
+        assert_eq!(sent, ["first", "second"]);
+    }
+
+    #[test]
+    fn test_reports_errors() {
+        let replies = run(vec![BackendMessage::LoadContacts], 1, |_| true);
+        assert!(
+            matches!(&replies[..], [FrontendMessage::Error { message }] if message.starts_with("Failed to load contacts"))
+        );
+    }
+
+    #[test]
+    fn test_reports_failed_downloads() {
+        let message = BackendMessage::DownloadAttachment {
+            contact_id: ContactId::User(vec![1]),
+            timestamp: 1,
+            index: 0,
+            sender: Vec::new(),
+        };
+        let replies = run(vec![message], 1, |m| {
+            !matches!(m, FrontendMessage::TransferProgress { .. })
+        });
+        assert!(
+            matches!(&replies[..], [FrontendMessage::DownloadFailed { timestamp: 1, index: 0, message, .. }] if message.starts_with("Failed to download attachment"))
+        );
+    }
+
+    #[test]
+    fn test_reports_not_allowed() {
+        let message = BackendMessage::SetGroupTitle {
+            contact_id: ContactId::Group(vec![1]),
+            title: "Lunch".to_owned(),
+        };
+        let replies = run(vec![message], 1, |_| true);
+        assert!(
+            matches!(&replies[..], [FrontendMessage::Error { message }] if message == "Failed to set group title: You aren't allowed to change the title")
+        );
     }
 }
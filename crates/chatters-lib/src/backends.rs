@@ -1,14 +1,16 @@
 use futures::channel::mpsc;
 use futures::channel::oneshot;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
 use std::future::Future;
 use std::ops::Bound;
 use std::path::Path;
 use std::path::PathBuf;
-use url::Url;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::message::FrontendMessage;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
 pub enum ContactId {
     User(Vec<u8>),
     Group(Vec<u8>),
@@ -27,7 +29,7 @@ impl std::fmt::Display for ContactId {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Message {
     pub timestamp: u64,
     pub sender: Vec<u8>,
@@ -36,11 +38,15 @@ pub struct Message {
     pub quote: Option<Quote>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub enum MessageContent {
     Text {
         text: String,
         attachments: Vec<MessageAttachment>,
+        #[serde(default)]
+        mentions: Vec<Mention>,
+        #[serde(default)]
+        formatting: Vec<Formatting>,
     },
     Reaction {
         message_author: Vec<u8>,
@@ -51,26 +57,78 @@ pub enum MessageContent {
     Edit {
         timestamp: u64,
         text: String,
+        /// Those mentioned in the edited text.
+        #[serde(default)]
+        mentions: Vec<Mention>,
+        /// Styles of the edited text.
+        #[serde(default)]
+        formatting: Vec<Formatting>,
+    },
+    /// Something the ui can't show, such as a change to a group, described so that it doesn't
+    /// leave a gap in the conversation.
+    Unsupported {
+        description: String,
+    },
+    /// A call, incoming or missed.
+    Call {
+        missed: bool,
+        /// Where to return the call, if the backend knows.
+        link: Option<String>,
     },
 }
 
 impl ToString for MessageContent {
     fn to_string(&self) -> String {
         match self {
-            MessageContent::Text { text, .. } => text,
-            MessageContent::Reaction { reaction, .. } => reaction,
-            MessageContent::Edit { text, .. } => text,
+            MessageContent::Text { text, .. } => text.to_owned(),
+            MessageContent::Reaction { reaction, .. } => reaction.to_owned(),
+            MessageContent::Edit { text, .. } => text.to_owned(),
+            MessageContent::Unsupported { description } => description.to_owned(),
+            MessageContent::Call { missed: true, .. } => "📞 Missed call".to_owned(),
+            MessageContent::Call { missed: false, .. } => "📞 Call".to_owned(),
         }
-        .to_owned()
     }
 }
 
-#[derive(Debug, Clone)]
+/// A user mentioned in the text of a message.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Mention {
+    /// Where the mention starts in the text, in chars.
+    pub start: usize,
+    /// How many chars of the text the mention covers.
+    pub length: usize,
+    pub id: Vec<u8>,
+}
+
+/// Formatting of part of the text of a message, such as bold or a spoiler.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Formatting {
+    /// Where the formatting starts in the text, in chars.
+    pub start: usize,
+    /// How many chars of the text are formatted.
+    pub length: usize,
+    pub kind: FormatKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum FormatKind {
+    Bold,
+    Italic,
+    Strikethrough,
+    Monospace,
+    /// Hidden until revealed.
+    Spoiler,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct MessageAttachment {
     pub name: String,
     pub size: u64,
     pub index: usize,
     pub path: Option<PathBuf>,
+    /// The mime type, such as `image/png`, if the backend knows it.
+    #[serde(default)]
+    pub content_type: Option<String>,
 }
 
 impl MessageAttachment {
@@ -81,17 +139,7 @@ impl MessageAttachment {
     }
 
     pub fn human_size(&self) -> String {
-        // starts in bytes
-        let size = self.size;
-        if size > 1_000_000_000 {
-            format!("{}GB", size / 1_000_000_000)
-        } else if size > 1_000_000 {
-            format!("{}MB", size / 1_000_000)
-        } else if size > 1_000 {
-            format!("{}KB", size / 1_000)
-        } else {
-            format!("{}B", size)
-        }
+        human_size(self.size)
     }
 
     pub fn message_line(&self) -> String {
@@ -103,20 +151,249 @@ impl MessageAttachment {
     }
 }
 
-#[derive(Debug)]
+pub fn human_size(size: u64) -> String {
+    // starts in bytes
+    if size > 1_000_000_000 {
+        format!("{}GB", size / 1_000_000_000)
+    } else if size > 1_000_000 {
+        format!("{}MB", size / 1_000_000)
+    } else if size > 1_000 {
+        format!("{}KB", size / 1_000)
+    } else {
+        format!("{}B", size)
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Quote {
     pub timestamp: u64,
     pub sender: Vec<u8>,
     pub text: String,
+    /// Those mentioned in the quoted text.
+    #[serde(default)]
+    pub mentions: Vec<Mention>,
+    /// Styles of the quoted text.
+    #[serde(default)]
+    pub formatting: Vec<Formatting>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Contact {
     pub id: ContactId,
     pub name: String,
     pub address: String,
     pub last_message_timestamp: Option<u64>,
     pub description: String,
+    /// When notifications for the conversation are muted until, as set upstream, in milliseconds
+    /// since the unix epoch. `u64::MAX` means muted indefinitely.
+    #[serde(default)]
+    pub muted_until: Option<u64>,
+    /// Whether the identity of the contact has been verified, always unverified for groups.
+    #[serde(default)]
+    pub verification: Verification,
+    /// How many members a group has, if known.
+    #[serde(default)]
+    pub members: Option<usize>,
+    /// Whether messages in the conversation are end-to-end encrypted.
+    #[serde(default)]
+    pub encrypted: bool,
+}
+
+/// Order contacts as the ui lists them, most recent conversations first and then by name.
+pub fn sort_contacts(contacts: &mut [Contact]) {
+    contacts.sort_by_key(|c| (Reverse(c.last_message_timestamp), c.name.clone()));
+}
+
+/// Trust in the identity of a contact, such as from comparing Signal safety numbers or Matrix
+/// cross-signing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Verification {
+    #[default]
+    Unverified,
+    Verified,
+    /// The identity changed after being verified, so needs verifying again.
+    Changed,
+}
+
+impl std::fmt::Display for Verification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unverified => write!(f, "unverified"),
+            Self::Verified => write!(f, "verified"),
+            Self::Changed => write!(f, "changed since verified"),
+        }
+    }
+}
+
+/// More about a conversation than is needed to list it, loaded when showing its info.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct ContactDetails {
+    /// The user's role in a group, such as admin.
+    pub role: Option<String>,
+    /// How long messages last before disappearing, in seconds, if they do.
+    pub disappearing_timer: Option<u64>,
+    /// Profile fields as named by the backend, such as an about text or status.
+    pub profile: Vec<(String, String)>,
+}
+
+/// An invitation to join a conversation, waiting to be accepted or declined.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Invite {
+    /// The conversation once joined.
+    pub contact_id: ContactId,
+    /// The name of the conversation, as far as can be seen before joining.
+    pub name: String,
+    /// Who sent the invitation, empty when not known.
+    pub inviter: String,
+}
+
+/// How far a message the user sent has got to one of its recipients.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Receipt {
+    pub recipient: Vec<u8>,
+    /// When the message reached one of their devices, in milliseconds since the unix epoch.
+    pub delivered: Option<u64>,
+    /// When they read the message, in milliseconds since the unix epoch.
+    pub read: Option<u64>,
+}
+
+/// Whether the backend can currently reach the service.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Connectivity {
+    #[default]
+    Online,
+    /// The connection was lost.
+    Offline,
+    /// Trying to connect again after losing the connection.
+    Reconnecting,
+}
+
+impl std::fmt::Display for Connectivity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Online => write!(f, "online"),
+            Self::Offline => write!(f, "offline"),
+            Self::Reconnecting => write!(f, "reconnecting"),
+        }
+    }
+}
+
+/// Progress verifying this device with another of the user's devices.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum DeviceVerification {
+    /// Waiting for the other device to accept the request.
+    Requested,
+    /// The emoji to compare with those shown on the other device, before confirming or rejecting
+    /// them.
+    Compare {
+        other_device: String,
+        emoji: Vec<String>,
+    },
+    Done {
+        other_device: String,
+    },
+    Cancelled {
+        reason: String,
+    },
+}
+
+/// An attachment being transferred to or from the backend.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub enum Transfer {
+    /// Downloading an attachment of a message.
+    Download {
+        contact_id: ContactId,
+        timestamp: u64,
+        index: usize,
+    },
+    /// Uploading a file attached to a message being sent.
+    Upload {
+        contact_id: ContactId,
+        path: PathBuf,
+    },
+}
+
+/// Reports how far along a transfer is, for the UI to show.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    transfer: Transfer,
+    tx: mpsc::UnboundedSender<FrontendMessage>,
+}
+
+impl Progress {
+    pub fn new(transfer: Transfer, tx: mpsc::UnboundedSender<FrontendMessage>) -> Self {
+        Self { transfer, tx }
+    }
+
+    /// Report that `transferred` of the `total` bytes have been transferred, with a `total` of 0
+    /// when it isn't known.
+    pub fn report(&self, transferred: u64, total: u64) {
+        // the ui may have closed already
+        let _ = self.tx.unbounded_send(FrontendMessage::TransferProgress {
+            transfer: self.transfer.clone(),
+            transferred,
+            total,
+        });
+    }
+
+    /// Report that the transfer is over, whether or not it succeeded.
+    pub fn finish(&self) {
+        self.report(u64::MAX, u64::MAX);
+    }
+}
+
+/// How far a backend has got catching up on what arrived while it wasn't running, either while it
+/// loads or in the background once it has.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LoadStatus {
+    /// How many messages have been received so far.
+    pub messages: u64,
+    /// Whether the contacts have been synced from the primary device.
+    pub contacts_synced: bool,
+    /// Whether it is still catching up in the background, so new contacts don't end the sync.
+    pub catching_up: bool,
+}
+
+/// Reports how far along loading is, for the UI to show instead of a frozen terminal.
+#[derive(Debug, Clone)]
+pub struct LoadProgress {
+    tx: mpsc::UnboundedSender<FrontendMessage>,
+}
+
+impl LoadProgress {
+    pub fn new(tx: mpsc::UnboundedSender<FrontendMessage>) -> Self {
+        Self { tx }
+    }
+
+    pub fn report(&self, status: LoadStatus) {
+        // nothing may be showing it
+        let _ = self
+            .tx
+            .unbounded_send(FrontendMessage::LoadProgress { status });
+    }
+}
+
+impl Default for LoadProgress {
+    /// Reports to nowhere, for loads nothing is waiting on.
+    fn default() -> Self {
+        Self::new(mpsc::unbounded().0)
+    }
+}
+
+/// Limits on outgoing messages imposed by a backend.
+///
+/// A `None` value means the backend imposes no limit.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct Limits {
+    /// Maximum length of a message body, in bytes.
+    pub max_body_length: Option<usize>,
+    /// Maximum size of a single attachment, in bytes.
+    pub max_attachment_size: Option<u64>,
+    /// Maximum number of attachments on a single message.
+    pub max_attachment_count: Option<usize>,
+    /// Whether reactions can be any text, such as Matrix's reaction keys, rather than only emoji.
+    #[serde(default)]
+    pub any_reaction: bool,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -127,17 +404,57 @@ pub enum Error {
     UnknownAttachment(usize),
     #[error("A failure occurred: {0}")]
     Failure(String, String),
+    /// The user lacks the permission, such as to change a group only its admins can.
+    #[error("You aren't allowed to {0}")]
+    NotAllowed(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// A question to ask in the setup wizard before linking a new device, besides what to name it.
+#[derive(Debug, Clone)]
+pub struct SetupQuestion {
+    /// Identifies the answer in the [`Setup`].
+    pub key: &'static str,
+    pub prompt: &'static str,
+    /// The answer to start with, which can be empty.
+    pub default: &'static str,
+    /// Hide the answer as it is typed, such as for passwords.
+    pub secret: bool,
+}
+
+/// How to set up a new device, as answered in the setup wizard.
+#[derive(Clone, Default)]
+pub struct Setup {
+    pub device_name: String,
+    /// Answers to the backend's [`SetupQuestion`]s, by their keys.
+    pub answers: BTreeMap<String, String>,
+}
+
+impl Setup {
+    /// The answer to the question with the key, empty if it wasn't asked.
+    pub fn answer(&self, key: &str) -> &str {
+        self.answers.get(key).map_or("", |answer| answer.trim())
+    }
+}
+
 pub trait Backend: Sized {
-    fn load(path: &Path) -> impl Future<Output = Result<Self>>;
+    /// Human readable name of the backend, used in messages to the user.
+    const NAME: &'static str;
+
+    /// Load a linked device, reporting progress where it has to catch up first, such as on the
+    /// messages queued while it wasn't running.
+    fn load(path: &Path, progress: LoadProgress) -> impl Future<Output = Result<Self>>;
 
+    /// Questions to ask in the setup wizard before linking, such as which server to use.
+    fn setup_questions() -> Vec<SetupQuestion>;
+
+    /// Link as a new device, sending the contents of a QR code for the user to scan with their
+    /// primary device, or the URL of a page to log in on in the browser.
     fn link(
         path: &Path,
-        device_name: &str,
-        provisioning_link_tx: oneshot::Sender<Url>,
+        setup: &Setup,
+        provisioning_link_tx: oneshot::Sender<String>,
     ) -> impl Future<Output = Result<Self>>;
 
     fn background_sync(
@@ -165,13 +482,138 @@ pub trait Backend: Sized {
 
     fn self_id(&self) -> impl Future<Output = Vec<u8>>;
 
-    fn download_attachment(&self, attachment_index: usize)
-        -> impl Future<Output = Result<PathBuf>>;
+    fn limits(&self) -> Limits;
+
+    /// Download an attachment, reporting how far along it is with the progress where the
+    /// backend can tell.
+    fn download_attachment(
+        &self,
+        attachment_index: usize,
+        progress: Progress,
+    ) -> impl Future<Output = Result<PathBuf>>;
+
+    /// Pin or unpin a message in a conversation.
+    fn pin_message(
+        &mut self,
+        contact_id: ContactId,
+        timestamp: u64,
+        pinned: bool,
+    ) -> impl Future<Output = Result<()>>;
+
+    /// Delete a message the user sent, for everyone in the conversation.
+    fn delete_message(
+        &mut self,
+        contact_id: ContactId,
+        timestamp: u64,
+    ) -> impl Future<Output = Result<()>>;
+
+    /// The timestamps of the pinned messages in a conversation.
+    fn pinned_messages(&self, contact_id: ContactId) -> impl Future<Output = Result<Vec<u64>>>;
+
+    /// Look up someone who isn't among the contacts yet, such as by their phone number or
+    /// username, to start a conversation with them.
+    fn find_user(&mut self, query: String) -> impl Future<Output = Result<Contact>>;
+
+    /// Who a message the user sent has been delivered to and read by, as far as the backend
+    /// knows.
+    fn message_receipts(
+        &self,
+        contact_id: ContactId,
+        timestamp: u64,
+    ) -> impl Future<Output = Result<Vec<Receipt>>>;
+
+    /// More about a conversation, such as the user's role in it and the profile of the contact.
+    fn contact_details(
+        &self,
+        contact_id: ContactId,
+    ) -> impl Future<Output = Result<ContactDetails>>;
+
+    /// Start verifying this device with another of the user's devices, reporting progress with
+    /// [`FrontendMessage::DeviceVerification`].
+    fn verify_device(&mut self) -> impl Future<Output = Result<()>>;
+
+    /// Confirm or reject that the emoji being compared for a device verification match.
+    fn confirm_verification(&mut self, confirmed: bool) -> impl Future<Output = Result<()>>;
+
+    /// The invitations to conversations waiting for an answer, with new ones arriving while
+    /// syncing reported with [`FrontendMessage::NewInvite`].
+    fn invites(&self) -> impl Future<Output = Result<Vec<Invite>>>;
+
+    /// Join the conversation of an invitation, or decline it.
+    fn answer_invite(
+        &mut self,
+        contact_id: ContactId,
+        accept: bool,
+    ) -> impl Future<Output = Result<()>>;
+
+    /// Change the title of a group, failing with [`Error::NotAllowed`] if the user can't.
+    fn set_group_title(
+        &mut self,
+        contact_id: ContactId,
+        title: String,
+    ) -> impl Future<Output = Result<()>>;
+
+    /// Change the description of a group, clearing it when empty.
+    fn set_group_description(
+        &mut self,
+        contact_id: ContactId,
+        description: String,
+    ) -> impl Future<Output = Result<()>>;
+
+    /// Ask for the contacts to be synced again, such as from the user's primary device, for
+    /// those that only sync them when asked.
+    ///
+    /// The contacts are loaded again once this returns, so those synced later in the background
+    /// should be sent with [`FrontendMessage::LoadedContacts`] when they arrive.
+    fn request_contact_sync(&mut self) -> impl Future<Output = Result<()>>;
+
+    /// The latest text message of each conversation that has one, to preview in the contacts list
+    /// without loading their histories.
+    fn conversation_summaries(&self) -> impl Future<Output = Result<Vec<Message>>>;
+}
+
+/// The time to report instead of the system's, or 0 to use the system's.
+static PINNED_CLOCK: AtomicU64 = AtomicU64::new(0);
+
+/// Report times from the start, in milliseconds since the epoch, rather than from the system
+/// clock so that tests see the same times on each run.
+///
+/// Each reading is a millisecond after the last so that timestamps stay unique. Only the first
+/// call has an effect, so tests running alongside each other share the one clock.
+pub fn pin_clock(start: u64) {
+    let _ = PINNED_CLOCK.compare_exchange(0, start, Ordering::Relaxed, Ordering::Relaxed);
 }
 
 pub fn timestamp() -> u64 {
+    if PINNED_CLOCK.load(Ordering::Relaxed) != 0 {
+        return PINNED_CLOCK.fetch_add(1, Ordering::Relaxed);
+    }
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .expect("Time went backwards")
         .as_millis() as u64
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::contact;
+
+    #[test]
+    fn test_sort_contacts() {
+        let user = |name: &str, last_message_timestamp| Contact {
+            last_message_timestamp,
+            ..contact(ContactId::User(name.as_bytes().to_vec()), name)
+        };
+        let mut contacts = vec![
+            user("Dan", None),
+            user("Carol", Some(10)),
+            user("Alice", None),
+            user("Bob", Some(20)),
+            user("Abe", Some(10)),
+        ];
+        sort_contacts(&mut contacts);
+        let names = contacts.iter().map(|c| c.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["Bob", "Abe", "Carol", "Alice", "Dan"]);
+    }
+}
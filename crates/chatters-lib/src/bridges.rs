@@ -0,0 +1,92 @@
+use log::warn;
+use regex::Regex;
+
+/// Patterns matching messages relayed by a bridge bot, such as `[irc] nick: hello`, to show
+/// who really sent them.
+///
+/// Each pattern is a regex with `sender` and `text` groups, and optionally a `network` group.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct BridgePatterns(pub Vec<String>);
+
+impl Default for BridgePatterns {
+    fn default() -> Self {
+        Self(vec![
+            // `[irc] nick: text`, as relayed from IRC or Telegram
+            r"^\[(?<network>[\w.-]+)\] (?<sender>[^\s:]+): (?<text>(?s).*)$".to_owned(),
+            // `<nick> text`
+            r"^<(?<sender>[^\s>]+)> (?<text>(?s).*)$".to_owned(),
+        ])
+    }
+}
+
+/// The compiled bridge patterns.
+#[derive(Debug, Default)]
+pub struct Bridges {
+    patterns: Vec<Regex>,
+}
+
+/// A message relayed by a bridge.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Bridged<'a> {
+    pub network: Option<&'a str>,
+    pub sender: &'a str,
+    pub text: &'a str,
+}
+
+impl Bridged<'_> {
+    /// The name to show for the sender, noting the network they are on.
+    pub fn sender_name(&self) -> String {
+        match self.network {
+            Some(network) => format!("{} [{network}]", self.sender),
+            None => self.sender.to_owned(),
+        }
+    }
+}
+
+impl Bridges {
+    pub fn new(patterns: &BridgePatterns) -> Self {
+        let patterns = patterns
+            .0
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(error) => {
+                    warn!(error:%, pattern; "Invalid bridge pattern");
+                    None
+                }
+            })
+            .collect();
+        Self { patterns }
+    }
+
+    /// Split out the real sender of a relayed message, if it matches one of the patterns.
+    pub fn parse<'a>(&self, text: &'a str) -> Option<Bridged<'a>> {
+        self.patterns.iter().find_map(|pattern| {
+            let captures = pattern.captures(text)?;
+            Some(Bridged {
+                network: captures.name("network").map(|m| m.as_str()),
+                sender: captures.name("sender")?.as_str(),
+                text: captures.name("text")?.as_str(),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bridged() {
+        let bridges = Bridges::new(&BridgePatterns::default());
+        let texts = [
+            "[irc] alice: hello there",
+            "[telegram] bob_99: multi\nline",
+            "<carol> hi",
+            "just a message",
+            "note: not bridged",
+        ];
+        insta::assert_debug_snapshot!(texts.map(|t| bridges.parse(t)));
+    }
+}
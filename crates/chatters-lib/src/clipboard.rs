@@ -0,0 +1,73 @@
+use std::{
+    io::Write as _,
+    process::{Command, Stdio},
+};
+
+use base64::Engine as _;
+use log::debug;
+
+/// Programs which copy their input to the clipboard, tried in order.
+const PROGRAMS: &[(&str, &[&str])] = &[
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("pbcopy", &[]),
+];
+
+/// Copy text to the system clipboard.
+///
+/// Over ssh, or without a clipboard program, this asks the terminal to do it with an OSC 52
+/// escape sequence, which reaches the clipboard of the machine the terminal is running on.
+pub fn copy(text: &str) -> std::io::Result<()> {
+    let remote = std::env::var_os("SSH_TTY").is_some();
+    if !remote {
+        for (program, args) in PROGRAMS {
+            match copy_with(program, args, text) {
+                Ok(()) => return Ok(()),
+                Err(error) => debug!(program, error:%; "Failed to copy with program"),
+            }
+        }
+    }
+    debug!("Copying with OSC 52");
+    let mut stdout = std::io::stdout();
+    stdout.write_all(osc52(text, std::env::var_os("TMUX").is_some()).as_bytes())?;
+    stdout.flush()
+}
+
+fn copy_with(program: &str, args: &[&str], text: &str) -> std::io::Result<()> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(text.as_bytes())?;
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(status.to_string()))
+    }
+}
+
+/// The escape sequence setting the clipboard to the text, passed through tmux when inside it.
+fn osc52(text: &str, tmux: bool) -> String {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    let sequence = format!("\x1b]52;c;{encoded}\x07");
+    if tmux {
+        // escapes within tmux's passthrough are doubled
+        format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+    } else {
+        sequence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc52() {
+        insta::assert_debug_snapshot!([osc52("hello", false), osc52("hello", true)]);
+    }
+}
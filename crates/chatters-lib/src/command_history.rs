@@ -1,12 +1,46 @@
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+/// How many commands are kept, dropping the oldest beyond it.
+const MAX_COMMANDS: usize = 1000;
+
 #[derive(Debug, Default)]
 pub struct CommandLineHistory {
+    path: PathBuf,
     commands: Vec<String>,
     selected: Option<usize>,
+    /// What to look for in the commands while searching back through them.
+    search: Option<String>,
 }
 
 impl CommandLineHistory {
+    /// Load the history from the state file at the path, starting empty if it doesn't exist yet.
+    pub fn load(path: &Path) -> Self {
+        let commands = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                warn!(error:%, path:?; "Failed to parse command history, starting afresh");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        Self {
+            path: path.to_owned(),
+            commands,
+            ..Default::default()
+        }
+    }
+
     pub fn push(&mut self, command_line: String) {
+        // running the same command again doesn't need another entry
+        if self.commands.last() == Some(&command_line) {
+            return;
+        }
         self.commands.push(command_line);
+        if self.commands.len() > MAX_COMMANDS {
+            self.commands.remove(0);
+        }
+        self.save();
     }
 
     pub fn selected_command(&self) -> Option<&String> {
@@ -33,9 +67,109 @@ impl CommandLineHistory {
 
     pub fn clear_selection(&mut self) {
         self.selected = None;
+        self.search = None;
+    }
+
+    /// Start searching back through the commands for those containing the query.
+    pub fn start_search(&mut self) {
+        self.selected = None;
+        self.search = Some(String::new());
+    }
+
+    pub fn stop_search(&mut self) {
+        self.search = None;
+    }
+
+    /// What is being searched for, if searching.
+    pub fn search_query(&self) -> Option<&str> {
+        self.search.as_deref()
+    }
+
+    /// Change what is being searched for, selecting the newest command containing it, or keeping
+    /// the one selected if none do.
+    pub fn set_search_query(&mut self, query: String) {
+        self.search = Some(query);
+        let previous = self.selected.take();
+        if !self.search_older() {
+            self.selected = previous;
+        }
+    }
+
+    /// Select the next command older than the one selected containing the query, returning
+    /// whether there was one.
+    pub fn search_older(&mut self) -> bool {
+        let Some(query) = &self.search else {
+            return false;
+        };
+        let end = self.selected.unwrap_or(self.commands.len());
+        match self.commands[..end].iter().rposition(|c| c.contains(query)) {
+            Some(index) => {
+                self.selected = Some(index);
+                true
+            }
+            None => false,
+        }
     }
 
     pub fn iter(&self) -> impl Iterator<Item = &String> {
         self.commands.iter()
     }
+
+    fn save(&self) {
+        if self.path.as_os_str().is_empty() {
+            // not backed by a file
+            return;
+        }
+        let result = serde_json::to_string(&self.commands)
+            .map_err(std::io::Error::from)
+            .and_then(|content| {
+                if let Some(parent) = self.path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&self.path, content)
+            });
+        if let Err(error) = result {
+            warn!(error:%, path:? = self.path; "Failed to save command history");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("command_history.json");
+        let mut history = CommandLineHistory::load(&path);
+        for command in [
+            "react 👍",
+            "find lunch",
+            "theme dark",
+            "find dinner",
+            "find dinner",
+        ] {
+            history.push(command.to_owned());
+        }
+
+        let mut history = CommandLineHistory::load(&path);
+        assert_eq!(history.iter().count(), 4);
+        history.start_search();
+        history.set_search_query("fi".to_owned());
+        assert_eq!(history.selected_command().unwrap(), "find dinner");
+        assert!(history.search_older());
+        assert_eq!(history.selected_command().unwrap(), "find lunch");
+        assert!(!history.search_older());
+        assert_eq!(history.selected_command().unwrap(), "find lunch");
+
+        // typing more searches again from the newest
+        history.set_search_query("the".to_owned());
+        assert_eq!(history.selected_command().unwrap(), "theme dark");
+        history.set_search_query("thex".to_owned());
+        assert_eq!(history.selected_command().unwrap(), "theme dark");
+
+        history.clear_selection();
+        assert_eq!(history.search_query(), None);
+    }
 }
@@ -4,7 +4,7 @@ use std::{
     ffi::OsString,
     fs::read_dir,
     io::{Read, Seek, Write as _},
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::Stdio,
     str::FromStr,
     sync::LazyLock,
@@ -12,17 +12,48 @@ use std::{
 
 use futures::channel::mpsc;
 use log::{debug, warn};
+use regex::{Regex, RegexBuilder};
+use unicode_width::UnicodeWidthChar as _;
 
 use crate::{
-    backends::MessageContent,
+    backends::{
+        human_size, timestamp, Contact, ContactId, DeviceVerification, Mention, MessageAttachment,
+        MessageContent, Verification,
+    },
+    clipboard,
+    config::{ContactOrder, ContactSort, MessageLayout, Theme},
+    downloads::AutoDownload,
+    forwards::{self, ForwardedMessage},
+    fuzzy,
+    hooks::PreSend,
+    keybinds::KeyBinds,
+    markdown,
     message::BackendMessage,
-    tui::{Mode, Popup, PopupType, Quote, TuiState},
+    outbox::{self, Outgoing},
+    spellcheck,
+    transcript::{self, TranscriptFormat, TranscriptMessage},
+    tui::{
+        emoji_picker::EmojiPicker, file_picker::FilePicker, lock::LockScreen, messages::Message,
+        split::Split, tabs::Tab, undo::Change, Mode, Popup, PopupType, Quote, TuiState,
+    },
+    util::suspend_terminal,
 };
 
 pub enum CommandSuccess {
     Nothing,
     Quit,
     Clear,
+    /// Show what the command found or did in a popup, scrolled through like the others.
+    ShowPopup {
+        title: String,
+        text: String,
+    },
+}
+
+/// Show the text a command returned in a popup.
+pub fn show_popup(tui_state: &mut TuiState, title: String, text: String) {
+    tui_state.popup = Some(Popup::new(PopupType::Output { title, text }));
+    tui_state.mode = Mode::Popup;
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -45,6 +76,15 @@ pub enum Error {
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// How to run a command and what it does, for `:help` and the commands popup.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CommandHelp {
+    /// The arguments after the name, such as `<register> [count]`.
+    pub usage: &'static str,
+    /// What the command does, in a line.
+    pub description: &'static str,
+}
+
 pub trait Command: std::fmt::Debug {
     fn execute(
         &self,
@@ -63,10 +103,16 @@ pub trait Command: std::fmt::Debug {
 
     fn names(&self) -> Vec<&'static str>;
 
+    fn help(&self) -> CommandHelp;
+
     fn complete(&self, _tui_state: &TuiState, _args: &str) -> Vec<Completion> {
         Vec::new()
     }
 
+    /// Apply the count typed before the key binding running the command, such as the 5 of `5j`.
+    /// Commands ignore it unless they say otherwise.
+    fn set_count(&mut self, _count: usize) {}
+
     fn dyn_clone(&self) -> Box<dyn Command>;
 }
 
@@ -85,26 +131,96 @@ pub fn commands() -> Vec<Box<dyn Command>> {
     v.push(Box::new(React::default()));
     v.push(Box::new(Unreact::default()));
     v.push(Box::new(ReloadContacts::default()));
+    v.push(Box::new(SyncContacts::default()));
     v.push(Box::new(ReloadMessages::default()));
     v.push(Box::new(ReloadConfig::default()));
+    v.push(Box::new(Lock::default()));
+    v.push(Box::new(ExportKeybinds::default()));
+    v.push(Box::new(ImportKeybinds::default()));
     v.push(Box::new(ComposeInEditor::default()));
+    v.push(Box::new(EditCommand::default()));
     v.push(Box::new(ClearCompose::default()));
+    v.push(Box::new(Preview::default()));
     v.push(Box::new(DownloadAttachments::default()));
     v.push(Box::new(OpenAttachments::default()));
     v.push(Box::new(OpenLink::default()));
+    v.push(Box::new(CallBack::default()));
+    v.push(Box::new(YankMessage::default()));
+    v.push(Box::new(YankLink::default()));
+    v.push(Box::new(YankAttachmentPath::default()));
     v.push(Box::new(MessageInfo::default()));
     v.push(Box::new(ContactInfo::default()));
+    v.push(Box::new(FindUser::default()));
     v.push(Box::new(Keybindings::default()));
     v.push(Box::new(Commands::default()));
+    v.push(Box::new(Help::default()));
     v.push(Box::new(CommandHistory::default()));
     v.push(Box::new(Reply::default()));
+    v.push(Box::new(ReplyPrivately::default()));
     v.push(Box::new(ScrollPopup::default()));
     v.push(Box::new(AttachFiles::default()));
+    v.push(Box::new(BrowseAttach::default()));
     v.push(Box::new(DetachFiles::default()));
     v.push(Box::new(GotoQuoted::default()));
     v.push(Box::new(PipeMessage::default()));
+    v.push(Box::new(PipeAttachment::default()));
     v.push(Box::new(Forward::default()));
+    v.push(Box::new(ConfirmForward::default()));
+    v.push(Box::new(ConfirmDelete::default()));
+    v.push(Box::new(Confirm::default()));
+    v.push(Box::new(VisualSelect::default()));
+    v.push(Box::new(ExportMessages::default()));
+    v.push(Box::new(ExportTranscript::default()));
+    v.push(Box::new(DeleteMessage::default()));
     v.push(Box::new(AlignMessage::default()));
+    v.push(Box::new(GotoBottom::default()));
+    v.push(Box::new(PinMessage::default()));
+    v.push(Box::new(UnpinMessage::default()));
+    v.push(Box::new(PinnedMessages::default()));
+    v.push(Box::new(MuteContact::default()));
+    v.push(Box::new(UnmuteContact::default()));
+    v.push(Box::new(Nickname::default()));
+    v.push(Box::new(MoveToFolder::default()));
+    v.push(Box::new(SetGroupTitle::default()));
+    v.push(Box::new(SetGroupDescription::default()));
+    v.push(Box::new(AcceptInvite::default()));
+    v.push(Box::new(DeclineInvite::default()));
+    v.push(Box::new(SetAutoDownload::default()));
+    v.push(Box::new(MissedMessages::default()));
+    v.push(Box::new(GotoMissed::default()));
+    v.push(Box::new(SetContactOrder::default()));
+    v.push(Box::new(SortContacts::default()));
+    v.push(Box::new(SetTheme::default()));
+    v.push(Box::new(SetMessageLayout::default()));
+    v.push(Box::new(CompleteWord::default()));
+    v.push(Box::new(OpenEmojiPicker::default()));
+    v.push(Box::new(QuickReact::default()));
+    v.push(Box::new(RevealSpoilers::default()));
+    v.push(Box::new(ShowTimestamp::default()));
+    v.push(Box::new(Find::default()));
+    v.push(Box::new(FindNext::default()));
+    v.push(Box::new(FindPrev::default()));
+    v.push(Box::new(GotoMention::default()));
+    v.push(Box::new(Record::default()));
+    v.push(Box::new(Play::default()));
+    v.push(Box::new(Undo::default()));
+    v.push(Box::new(Redo::default()));
+    v.push(Box::new(ToggleMarkdown::default()));
+    v.push(Box::new(GrowContacts::default()));
+    v.push(Box::new(ShrinkContacts::default()));
+    v.push(Box::new(ToggleContacts::default()));
+    v.push(Box::new(Focus::default()));
+    v.push(Box::new(VSplit::default()));
+    v.push(Box::new(SplitFocus::default()));
+    v.push(Box::new(CloseSplit::default()));
+    v.push(Box::new(TabOpen::default()));
+    v.push(Box::new(TabNext::default()));
+    v.push(Box::new(TabPrev::default()));
+    v.push(Box::new(TabClose::default()));
+    v.push(Box::new(CompleteWordPrevious::default()));
+    v.push(Box::new(VerifyDevice::default()));
+    v.push(Box::new(ConfirmVerification::default()));
+    v.push(Box::new(RejectVerification::default()));
     v
 }
 
@@ -128,13 +244,22 @@ impl Command for Quit {
         vec!["quit"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Quit chatters",
+        }
+    }
+
     fn dyn_clone(&self) -> Box<dyn Command> {
         Box::new(Self)
     }
 }
 
 #[derive(Debug)]
-pub struct NextContact;
+pub struct NextContact {
+    count: usize,
+}
 
 impl Command for NextContact {
     fn execute(
@@ -143,26 +268,46 @@ impl Command for NextContact {
         ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
         let last_selected = tui_state.contacts.state.selected();
-        tui_state.contacts.state.select_next();
+        for _ in 0..self.count {
+            tui_state.contacts.state.select_next();
+        }
         after_contact_changed(tui_state, ba_tx, last_selected);
         Ok(CommandSuccess::Nothing)
     }
 
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        self.count = parse_count(args)?;
+        Ok(())
+    }
+
+    fn set_count(&mut self, count: usize) {
+        self.count = count;
+    }
+
     fn default() -> Self {
-        Self
+        Self { count: 1 }
     }
 
     fn names(&self) -> Vec<&'static str> {
         vec!["next-contact"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[count]",
+            description: "Select the next conversation",
+        }
+    }
+
     fn dyn_clone(&self) -> Box<dyn Command> {
-        Box::new(Self)
+        Box::new(Self { count: self.count })
     }
 }
 
 #[derive(Debug)]
-pub struct PrevContact;
+pub struct PrevContact {
+    count: usize,
+}
 
 impl Command for PrevContact {
     fn execute(
@@ -171,26 +316,46 @@ impl Command for PrevContact {
         ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
         let last_selected = tui_state.contacts.state.selected();
-        tui_state.contacts.state.select_previous();
+        for _ in 0..self.count {
+            tui_state.contacts.state.select_previous();
+        }
         after_contact_changed(tui_state, ba_tx, last_selected);
         Ok(CommandSuccess::Nothing)
     }
 
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        self.count = parse_count(args)?;
+        Ok(())
+    }
+
+    fn set_count(&mut self, count: usize) {
+        self.count = count;
+    }
+
     fn default() -> Self {
-        Self
+        Self { count: 1 }
     }
 
     fn names(&self) -> Vec<&'static str> {
         vec!["prev-contact"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[count]",
+            description: "Select the previous conversation",
+        }
+    }
+
     fn dyn_clone(&self) -> Box<dyn Command> {
-        Box::new(Self)
+        Box::new(Self { count: self.count })
     }
 }
 
 #[derive(Debug)]
-pub struct NextMessage;
+pub struct NextMessage {
+    count: usize,
+}
 
 impl Command for NextMessage {
     fn execute(
@@ -198,25 +363,45 @@ impl Command for NextMessage {
         tui_state: &mut TuiState,
         _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        tui_state.messages.state.select_next();
+        for _ in 0..self.count {
+            tui_state.messages.state.select_next();
+        }
         Ok(CommandSuccess::Nothing)
     }
 
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        self.count = parse_count(args)?;
+        Ok(())
+    }
+
+    fn set_count(&mut self, count: usize) {
+        self.count = count;
+    }
+
     fn default() -> Self {
-        Self
+        Self { count: 1 }
     }
 
     fn names(&self) -> Vec<&'static str> {
         vec!["next-message"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[count]",
+            description: "Select the next, newer, message",
+        }
+    }
+
     fn dyn_clone(&self) -> Box<dyn Command> {
-        Box::new(Self)
+        Box::new(Self { count: self.count })
     }
 }
 
 #[derive(Debug)]
-pub struct PrevMessage;
+pub struct PrevMessage {
+    count: usize,
+}
 
 impl Command for PrevMessage {
     fn execute(
@@ -224,20 +409,38 @@ impl Command for PrevMessage {
         tui_state: &mut TuiState,
         _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        tui_state.messages.state.select_previous();
+        for _ in 0..self.count {
+            tui_state.messages.state.select_previous();
+        }
         Ok(CommandSuccess::Nothing)
     }
 
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        self.count = parse_count(args)?;
+        Ok(())
+    }
+
+    fn set_count(&mut self, count: usize) {
+        self.count = count;
+    }
+
     fn default() -> Self {
-        Self
+        Self { count: 1 }
     }
 
     fn names(&self) -> Vec<&'static str> {
         vec!["prev-message"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[count]",
+            description: "Select the previous, older, message",
+        }
+    }
+
     fn dyn_clone(&self) -> Box<dyn Command> {
-        Box::new(Self)
+        Box::new(Self { count: self.count })
     }
 }
 
@@ -282,6 +485,14 @@ impl Command for SelectMessage {
         vec!["select-message"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<index>",
+            description:
+                "Select the message at the index, counting back from the newest when negative",
+        }
+    }
+
     fn dyn_clone(&self) -> Box<dyn Command> {
         Box::new(Self { index: self.index })
     }
@@ -305,7 +516,7 @@ impl Command for SelectContact {
                 let Some(index) = tui_state
                     .contacts
                     .iter_contacts_and_groups()
-                    .position(|c| c.name.starts_with(name))
+                    .position(|c| matches_name(tui_state, c, name))
                 else {
                     return Err(Error::InvalidArgument {
                         arg: "item".to_owned(),
@@ -342,6 +553,13 @@ impl Command for SelectContact {
         vec!["select-contact"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<index|name>",
+            description: "Select the conversation at the index or with the name or nickname",
+        }
+    }
+
     fn dyn_clone(&self) -> Box<dyn Command> {
         Box::new(Self {
             item: self.item.clone(),
@@ -352,7 +570,7 @@ impl Command for SelectContact {
         let names = tui_state
             .contacts
             .iter_contacts_and_groups()
-            .map(|c| c.name.clone());
+            .map(|c| tui_state.nicknames.name(c).to_owned());
         let indices = (0..tui_state.contacts.len()).map(|i| i.to_string());
         let candidates = indices.chain(names);
         complete_from_iter(args, candidates)
@@ -368,6 +586,10 @@ impl Command for NormalMode {
         tui_state: &mut TuiState,
         _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
+        if matches!(tui_state.mode, Mode::Normal) && tui_state.popup.is_none() {
+            // escaping again leaves a visual selection
+            tui_state.messages.stop_visual();
+        }
         tui_state.mode = Mode::Normal;
         tui_state.popup = None;
         tui_state.key_events.0.clear();
@@ -383,6 +605,13 @@ impl Command for NormalMode {
         vec!["mode-normal"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Go back to normal mode, closing any popup or leaving a visual selection",
+        }
+    }
+
     fn dyn_clone(&self) -> Box<dyn Command> {
         Box::new(Self)
     }
@@ -417,6 +646,13 @@ impl Command for CommandMode {
         vec!["mode-command"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Start typing a command",
+        }
+    }
+
     fn dyn_clone(&self) -> Box<dyn Command> {
         Box::new(Self)
     }
@@ -443,6 +679,13 @@ impl Command for ComposeMode {
         vec!["mode-compose"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Start writing a message",
+        }
+    }
+
     fn dyn_clone(&self) -> Box<dyn Command> {
         Box::new(Self)
     }
@@ -460,6 +703,8 @@ impl Command for SendMessage {
         let message_body = tui_state.compose.lines().join("\n").trim().to_owned();
         let quoting = tui_state.compose.quote().clone();
         let attachments = tui_state.compose.attachments().to_vec();
+        // check before clearing so that the draft is kept for the user to fix
+        check_limits(tui_state, &message_body, &attachments)?;
         tui_state.compose.clear();
         NormalMode.execute(tui_state, ba_tx).unwrap();
 
@@ -468,20 +713,19 @@ impl Command for SendMessage {
         }
 
         if let Some(contact) = tui_state.contacts.selected() {
-            ba_tx
-                .unbounded_send(BackendMessage::SendMessage {
-                    contact_id: contact.id.clone(),
-                    content: MessageContent::Text {
-                        text: message_body,
-                        attachments,
-                    },
-                    quote: quoting.map(|m| crate::backends::Quote {
-                        timestamp: m.timestamp,
-                        sender: m.sender,
-                        text: m.text,
-                    }),
-                })
-                .unwrap();
+            let outgoing = Outgoing {
+                contact_id: contact.id.clone(),
+                text: message_body,
+                attachments,
+                quote: quoting.map(|m| crate::backends::Quote {
+                    timestamp: m.timestamp,
+                    sender: m.sender,
+                    text: m.text,
+                    mentions: m.mentions,
+                    formatting: m.formatting,
+                }),
+            };
+            send_message(tui_state, ba_tx, outgoing, None);
         }
         Ok(CommandSuccess::Nothing)
     }
@@ -494,6 +738,13 @@ impl Command for SendMessage {
         vec!["send-message"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Send the draft with its attachments and any quote",
+        }
+    }
+
     fn dyn_clone(&self) -> Box<dyn Command> {
         Box::new(Self)
     }
@@ -510,7 +761,7 @@ impl Command for React {
         tui_state: &mut TuiState,
         ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        let Some(e) = emojis::get_by_shortcode(&self.emoji) else {
+        let Some(reaction) = reaction_key(&self.emoji, tui_state.limits.any_reaction) else {
             return Err(Error::InvalidArgument {
                 arg: "emoji".to_owned(),
                 value: self.emoji.clone(),
@@ -531,12 +782,13 @@ impl Command for React {
                 content: MessageContent::Reaction {
                     message_author: selected_message.sender.clone(),
                     timestamp: selected_message.timestamp,
-                    reaction: e.as_str().to_owned(),
+                    reaction: reaction.clone(),
                     remove: false,
                 },
                 quote: None,
             })
             .unwrap();
+        tui_state.emoji_usage.record(&reaction);
         Ok(CommandSuccess::Nothing)
     }
 
@@ -559,11 +811,28 @@ impl Command for React {
         vec!["react"]
     }
 
-    fn complete(&self, _tui_state: &TuiState, args: &str) -> Vec<Completion> {
-        let candidates = emojis::iter()
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<emoji>",
+            description: "React to the selected message with an emoji, shortcode or reaction key",
+        }
+    }
+
+    fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        // reactions used before that aren't emoji, such as Matrix reaction keys
+        let other = tui_state
+            .emoji_usage
+            .ranked()
+            .into_iter()
+            .filter(|r| emojis::get(r).is_none())
+            .map(|r| r.to_owned())
+            .collect::<Vec<_>>();
+        let candidates = tui_state
+            .emoji_usage
+            .all_ranked()
             .flat_map(|e| e.shortcodes())
             .map(|s| s.to_owned());
-        complete_from_iter(args, candidates)
+        complete_from_iter(args, other.into_iter().chain(candidates))
     }
 
     fn dyn_clone(&self) -> Box<dyn Command> {
@@ -573,6 +842,68 @@ impl Command for React {
     }
 }
 
+/// The reaction to send for what was typed: the emoji for a shortcode, optionally wrapped in
+/// colons, or any emoji character itself, including those unknown to the emoji database. Other
+/// text is only used as is when the backend takes any reaction key.
+fn reaction_key(input: &str, any_reaction: bool) -> Option<String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return None;
+    }
+    let shortcode = input.trim_matches(':');
+    if let Some(e) = emojis::get_by_shortcode(shortcode).or_else(|| emojis::get(input)) {
+        return Some(e.as_str().to_owned());
+    }
+    // newer emoji and sequences may be missing from the database, but are never made of text
+    let looks_like_emoji = input
+        .chars()
+        .all(|c| !c.is_ascii() && !c.is_alphanumeric() && !c.is_whitespace());
+    (any_reaction || looks_like_emoji).then(|| input.to_owned())
+}
+
+#[derive(Debug)]
+pub struct QuickReact;
+
+impl Command for QuickReact {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        if tui_state.messages.selected().is_none() {
+            return Err(Error::NoMessageSelected);
+        }
+        // nothing to offer until something has been reacted with, so search them all
+        let picker = if tui_state.emoji_usage.is_empty() {
+            EmojiPicker::new(false)
+        } else {
+            EmojiPicker::quick()
+        };
+        tui_state.popup = Some(Popup::new(PopupType::EmojiPicker(picker)));
+        tui_state.mode = Mode::Popup;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["quick-react"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Pick a reaction for the selected message from the most recently used",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
 #[derive(Debug)]
 pub struct Unreact;
 
@@ -622,6 +953,13 @@ impl Command for Unreact {
         vec!["unreact"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Remove your reaction from the selected message",
+        }
+    }
+
     fn dyn_clone(&self) -> Box<dyn Command> {
         Box::new(Self)
     }
@@ -654,36 +992,7 @@ impl Command for ExecuteCommand {
 
         tui_state.command_line.history.push(cmdline.clone());
 
-        let args = shell_words::split(&cmdline)
-            .unwrap()
-            .into_iter()
-            .map(OsString::from)
-            .collect();
-        let mut pargs = pico_args::Arguments::from_vec(args);
-
-        debug!(pargs:? = pargs; "Parsed arguments for command");
-        let subcmd = loop {
-            let Some(subcmd) = pargs.subcommand().unwrap() else {
-                return Ok(CommandSuccess::Nothing);
-            };
-            if self.names().contains(&subcmd.as_str()) {
-                continue;
-            } else {
-                break subcmd;
-            }
-        };
-        let commands = commands();
-        let command = commands
-            .into_iter()
-            .find(|c| c.names().contains(&subcmd.as_str()));
-
-        if let Some(mut command) = command {
-            command.parse(pargs)?;
-            let ret = command.execute(tui_state, ba_tx)?;
-            Ok(ret)
-        } else {
-            Err(Error::UnknownCommand(subcmd.to_owned()))
-        }
+        run_command_line(tui_state, ba_tx, &cmdline)
     }
 
     fn default() -> Self {
@@ -694,6 +1003,13 @@ impl Command for ExecuteCommand {
         vec!["execute-command"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Run the command being typed",
+        }
+    }
+
     fn dyn_clone(&self) -> Box<dyn Command> {
         Box::new(Self)
     }
@@ -725,11 +1041,63 @@ impl Command for ReloadContacts {
         vec!["reload-contacts"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Load the conversations from the backend again",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct SyncContacts;
+
+impl Command for SyncContacts {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        request_contact_sync(tui_state, ba_tx);
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self
+    where
+        Self: Sized,
+    {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["sync-contacts"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Ask the backend to sync the contacts, such as from the primary device",
+        }
+    }
+
     fn dyn_clone(&self) -> Box<dyn Command> {
         Box::new(Self)
     }
 }
 
+/// Ask the backend to sync the contacts, restarting the wait for the next scheduled sync.
+pub fn request_contact_sync(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+) {
+    tui_state.contacts_synced_at = timestamp();
+    ba_tx.unbounded_send(BackendMessage::SyncContacts).unwrap();
+}
+
 #[derive(Debug)]
 pub struct ReloadMessages;
 
@@ -741,14 +1109,8 @@ impl Command for ReloadMessages {
     ) -> Result<CommandSuccess> {
         tui_state.messages.clear();
         tui_state.messages.state.select(None);
-        if let Some(contact) = tui_state.contacts.selected() {
-            ba_tx
-                .unbounded_send(BackendMessage::LoadMessages {
-                    contact_id: contact.id.clone(),
-                    start_ts: std::ops::Bound::Unbounded,
-                    end_ts: std::ops::Bound::Unbounded,
-                })
-                .unwrap();
+        if let Some(contact_id) = tui_state.contacts.selected().map(|c| c.id.clone()) {
+            load_messages(tui_state, ba_tx, contact_id);
         }
         Ok(CommandSuccess::Nothing)
     }
@@ -764,6 +1126,13 @@ impl Command for ReloadMessages {
         vec!["reload-messages"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Load the messages of the conversation from the backend again",
+        }
+    }
+
     fn dyn_clone(&self) -> Box<dyn Command> {
         Box::new(Self)
     }
@@ -785,22 +1154,16 @@ impl Command for ComposeInEditor {
         let contact_name = contact.name.replace(" ", "_");
 
         let compose_content = tui_state.compose.lines().join("\n");
-        let mut tmpfile = tempfile::Builder::new()
-            .prefix(&format!("chatters-{}-", contact_name))
-            .suffix(".txt")
-            .tempfile()
-            .unwrap();
-        tmpfile.write_all(compose_content.as_bytes()).unwrap();
-        let editor = std::env::var("EDITOR").unwrap_or("vim".to_owned());
-        let status = std::process::Command::new(editor)
-            .arg(tmpfile.path())
-            .status()
-            .unwrap();
-        if status.success() {
-            let mut compose_content = String::new();
-            tmpfile.seek(std::io::SeekFrom::Start(0)).unwrap();
-            tmpfile.read_to_string(&mut compose_content).unwrap();
-            let compose_lines = compose_content.lines().map(|l| l.to_owned()).collect();
+        let prefix = format!("chatters-{}-", contact_name);
+        if let Some(compose_content) = edit_in_external_editor(tui_state, &prefix, &compose_content)
+        {
+            let compose_lines = compose_content
+                .lines()
+                .map(|l| l.to_owned())
+                .collect::<Vec<_>>();
+            if compose_lines != tui_state.compose.lines() {
+                remember_draft(tui_state);
+            }
             tui_state.compose.set_text(compose_lines);
         } else {
             warn!("Not using compose content from external editor due to error status");
@@ -819,11 +1182,121 @@ impl Command for ComposeInEditor {
         vec!["compose-in-editor"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Write the draft in $VISUAL or $EDITOR",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct EditCommand;
+
+impl Command for EditCommand {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let command_line = match tui_state.mode {
+            Mode::Command { .. } => tui_state.command_line.text().to_owned(),
+            _ => String::new(),
+        };
+
+        if let Some(command_line) =
+            edit_in_external_editor(tui_state, "chatters-command-", &command_line)
+        {
+            CommandMode.execute(tui_state, ba_tx)?;
+            tui_state
+                .command_line
+                .set_text(join_command_lines(&command_line));
+        } else {
+            warn!("Not using command from external editor due to error status");
+        }
+        Ok(CommandSuccess::Clear)
+    }
+
+    fn default() -> Self
+    where
+        Self: Sized,
+    {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["edit-command", "edit-command-line"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Write a command in $VISUAL or $EDITOR, starting from the one being typed",
+        }
+    }
+
     fn dyn_clone(&self) -> Box<dyn Command> {
         Box::new(Self)
     }
 }
 
+/// Commands are a single line, so join up any that were split while editing.
+fn join_command_lines(text: &str) -> String {
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Open the content in the user's editor, returning the edited content if the editor exited
+/// successfully.
+fn edit_in_external_editor(tui_state: &TuiState, prefix: &str, content: &str) -> Option<String> {
+    let mut tmpfile = tempfile::Builder::new()
+        .prefix(prefix)
+        .suffix(".txt")
+        .tempfile()
+        .unwrap();
+    tmpfile.write_all(content.as_bytes()).unwrap();
+    let editor = editor();
+    let edited = suspend_terminal(!tui_state.config.disable_mouse, || {
+        run_editor(&editor, tmpfile.path())
+    });
+    if !edited {
+        return None;
+    }
+    let mut content = String::new();
+    tmpfile.seek(std::io::SeekFrom::Start(0)).unwrap();
+    tmpfile.read_to_string(&mut content).unwrap();
+    Some(content)
+}
+
+/// The editor from `$VISUAL`, then `$EDITOR`, falling back to vim.
+fn editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vim".to_owned())
+}
+
+/// Edit the file with the editor, which can have arguments such as `code --wait`, returning
+/// whether it exited successfully.
+fn run_editor(editor: &str, path: &Path) -> bool {
+    debug!(editor:?, path:?; "Running editor");
+    std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$1\""))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
 #[derive(Debug)]
 pub struct ClearCompose;
 
@@ -833,6 +1306,7 @@ impl Command for ClearCompose {
         tui_state: &mut TuiState,
         _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
+        remember_draft(tui_state);
         tui_state.compose.clear();
         Ok(CommandSuccess::Nothing)
     }
@@ -848,32 +1322,78 @@ impl Command for ClearCompose {
         vec!["clear-compose"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Throw away the draft, its attachments and quote",
+        }
+    }
+
     fn dyn_clone(&self) -> Box<dyn Command> {
         Box::new(Self)
     }
 }
 
+/// Show the draft as it will appear in the message list, before any `pre_send` hook runs.
 #[derive(Debug)]
-pub struct DownloadAttachments {
-    // TODO: change to vec of indices
-    item: Option<IndexOrString>,
-}
+pub struct Preview;
 
-impl Command for DownloadAttachments {
+impl Command for Preview {
     fn execute(
         &self,
         tui_state: &mut TuiState,
-        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        if let Some(message) = tui_state.messages.selected() {
-            let download_attachment =
-                |message: &crate::tui::messages::Message,
-                 attachment: &crate::backends::MessageAttachment| {
-                    ba_tx
-                        .unbounded_send(BackendMessage::DownloadAttachment {
-                            contact_id: message.contact_id.clone(),
-                            timestamp: message.timestamp,
+        tui_state.popup = Some(Popup::new(PopupType::Preview));
+        tui_state.mode = Mode::Popup;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self
+    where
+        Self: Sized,
+    {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["preview"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Show the draft as it will appear once sent",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct DownloadAttachments {
+    // TODO: change to vec of indices
+    item: Option<IndexOrString>,
+}
+
+impl Command for DownloadAttachments {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        if let Some(message) = tui_state.messages.selected() {
+            let download_attachment =
+                |message: &crate::tui::messages::Message,
+                 attachment: &crate::backends::MessageAttachment| {
+                    ba_tx
+                        .unbounded_send(BackendMessage::DownloadAttachment {
+                            contact_id: message.contact_id.clone(),
+                            timestamp: message.timestamp,
                             index: attachment.index,
+                            sender: message.sender.clone(),
                         })
                         .unwrap();
                 };
@@ -918,6 +1438,13 @@ impl Command for DownloadAttachments {
         vec!["download-attachments"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[index|name]",
+            description: "Download the attachments of the selected message, or just the one given",
+        }
+    }
+
     fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
         let Some(message) = tui_state.messages.selected() else {
             return Vec::new();
@@ -1004,6 +1531,14 @@ impl Command for OpenAttachments {
         vec!["open-attachments"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[index|name]",
+            description:
+                "Open the downloaded attachments of the selected message, or just the one given",
+        }
+    }
+
     fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
         let Some(message) = tui_state.messages.selected() else {
             return Vec::new();
@@ -1043,25 +1578,8 @@ impl Command for OpenLink {
         tui_state: &mut TuiState,
         _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        let Some(message) = tui_state.messages.selected() else {
-            return Err(Error::NoMessageSelected);
-        };
-
-        let mut links = LINK_REGEX.find_iter(&message.content).map(|m| m.as_str());
-
-        let link = match &self.item {
-            IndexOrString::Index(index) => {
-                let Some(link) = links.nth(*index) else {
-                    return Err(Error::Failure("Index past the number of links".to_owned()));
-                };
-                link
-            }
-            IndexOrString::Str(link) => link,
-        };
-
-        debug!(link:?; "Opening link");
-        open::that(link).unwrap();
-
+        let link = selected_link(tui_state, &self.item)?;
+        open_link(&link)?;
         Ok(CommandSuccess::Nothing)
     }
 
@@ -1087,15 +1605,15 @@ impl Command for OpenLink {
         vec!["open-link"]
     }
 
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<index|url>",
+            description: "Open a link in the selected message",
+        }
+    }
+
     fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
-        let Some(message) = tui_state.messages.selected() else {
-            return Vec::new();
-        };
-        let candidates = LINK_REGEX
-            .find_iter(&message.content)
-            .enumerate()
-            .flat_map(|(i, m)| [i.to_string(), m.as_str().to_owned()]);
-        complete_from_iter(args, candidates)
+        complete_link(tui_state, args)
     }
 
     fn dyn_clone(&self) -> Box<dyn Command> {
@@ -1106,21 +1624,41 @@ impl Command for OpenLink {
 }
 
 #[derive(Debug)]
-pub struct MessageInfo;
+pub struct CallBack;
 
-impl Command for MessageInfo {
+impl Command for CallBack {
     fn execute(
         &self,
         tui_state: &mut TuiState,
         _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        let Some(selected_message) = tui_state.messages.selected() else {
-            return Err(Error::NoMessageSelected);
+        let Some(contact) = tui_state.contacts.selected() else {
+            return Err(Error::NoContactSelected);
         };
-        tui_state.popup = Some(Popup::new(PopupType::MessageInfo {
-            timestamp: selected_message.timestamp,
-        }));
-        tui_state.mode = Mode::Popup;
+        let link = tui_state.call_links.get(&contact.id);
+        let Some(call_command) = &tui_state.config.call_command else {
+            let Some(link) = link else {
+                return Err(Error::Failure(
+                    "No link to call back with, set call_command in the config".to_owned(),
+                ));
+            };
+            open_link(link)?;
+            return Ok(CommandSuccess::Nothing);
+        };
+        debug!(call_command:?, link:?; "Calling back");
+        let mut child = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(call_command)
+            .env("CHATTERS_CONTACT_NAME", &contact.name)
+            .env("CHATTERS_CONTACT_ADDRESS", &contact.address)
+            .env("CHATTERS_CALL_LINK", link.map_or("", |link| link.as_str()))
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .map_err(|e| Error::Failure(format!("Failed to run {call_command}: {e}")))?;
+        // such as a softphone, left running in the background
+        std::thread::spawn(move || child.wait());
         Ok(CommandSuccess::Nothing)
     }
 
@@ -1132,7 +1670,14 @@ impl Command for MessageInfo {
     }
 
     fn names(&self) -> Vec<&'static str> {
-        vec!["message-info"]
+        vec!["call-back"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Call the selected conversation back, with call_command from the config or by opening the link to the latest call",
+        }
     }
 
     fn dyn_clone(&self) -> Box<dyn Command> {
@@ -1140,34 +1685,91 @@ impl Command for MessageInfo {
     }
 }
 
+pub fn open_link(link: &str) -> Result<()> {
+    debug!(link:?; "Opening link");
+    open::that(link).map_err(|e| Error::Failure(format!("Failed to open {link}: {e}")))
+}
+
+/// The link in the text covering the column, counted in terminal cells.
+pub fn link_at(text: &str, column: usize) -> Option<String> {
+    let mut width = 0;
+    let byte = text.char_indices().find_map(|(i, c)| {
+        width += c.width().unwrap_or(0);
+        (width > column).then_some(i)
+    })?;
+    LINK_REGEX
+        .find_iter(text)
+        .find(|m| m.range().contains(&byte))
+        .map(|m| m.as_str().to_owned())
+}
+
+/// A link in the selected message, by its index, or the link given.
+fn selected_link(tui_state: &TuiState, item: &IndexOrString) -> Result<String> {
+    let Some(message) = tui_state.messages.selected() else {
+        return Err(Error::NoMessageSelected);
+    };
+    match item {
+        IndexOrString::Index(index) => LINK_REGEX
+            .find_iter(&message.content)
+            .nth(*index)
+            .map(|m| m.as_str().to_owned())
+            .ok_or_else(|| Error::Failure("Index past the number of links".to_owned())),
+        IndexOrString::Str(link) => Ok(link.clone()),
+    }
+}
+
+fn complete_link(tui_state: &TuiState, args: &str) -> Vec<Completion> {
+    let Some(message) = tui_state.messages.selected() else {
+        return Vec::new();
+    };
+    let candidates = LINK_REGEX
+        .find_iter(&message.content)
+        .enumerate()
+        .flat_map(|(i, m)| [i.to_string(), m.as_str().to_owned()]);
+    complete_from_iter(args, candidates)
+}
+
+fn copy_to_clipboard(text: &str) -> Result<()> {
+    clipboard::copy(text).map_err(|e| Error::Failure(format!("Failed to copy: {e}")))
+}
+
+/// Copy the text of the selected messages to the clipboard, one per line.
 #[derive(Debug)]
-pub struct ContactInfo;
+pub struct YankMessage;
 
-impl Command for ContactInfo {
+impl Command for YankMessage {
     fn execute(
         &self,
         tui_state: &mut TuiState,
         _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        let Some(selected_contact) = tui_state.contacts.selected() else {
-            return Err(Error::NoContactSelected);
-        };
-        tui_state.popup = Some(Popup::new(PopupType::ContactInfo {
-            id: selected_contact.id.clone(),
-        }));
-        tui_state.mode = Mode::Popup;
+        let messages = tui_state.messages.selected_range();
+        if messages.is_empty() {
+            return Err(Error::NoMessageSelected);
+        }
+        let text = messages
+            .iter()
+            .map(|m| m.text())
+            .collect::<Vec<_>>()
+            .join("\n");
+        copy_to_clipboard(&text)?;
+        tui_state.messages.stop_visual();
         Ok(CommandSuccess::Nothing)
     }
 
-    fn default() -> Self
-    where
-        Self: Sized,
-    {
+    fn default() -> Self {
         Self
     }
 
     fn names(&self) -> Vec<&'static str> {
-        vec!["contact-info"]
+        vec!["yank-message"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Copy the text of the selected messages",
+        }
     }
 
     fn dyn_clone(&self) -> Box<dyn Command> {
@@ -1175,59 +1777,100 @@ impl Command for ContactInfo {
     }
 }
 
+/// Copy a link in the selected message to the clipboard.
 #[derive(Debug)]
-pub struct Keybindings;
+pub struct YankLink {
+    item: IndexOrString,
+}
 
-impl Command for Keybindings {
+impl Command for YankLink {
     fn execute(
         &self,
         tui_state: &mut TuiState,
         _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        tui_state.popup = Some(Popup::new(PopupType::Keybinds));
-        tui_state.mode = Mode::Popup;
+        let link = selected_link(tui_state, &self.item)?;
+        copy_to_clipboard(&link)?;
         Ok(CommandSuccess::Nothing)
     }
 
-    fn default() -> Self
-    where
-        Self: Sized,
-    {
-        Self
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let item = args
+            .opt_free_from_str()
+            .map_err(|_e| Error::MissingArgument("item".to_owned()))?;
+        self.item = item.unwrap_or(IndexOrString::Index(0));
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            item: IndexOrString::Index(0),
+        }
     }
 
     fn names(&self) -> Vec<&'static str> {
-        vec!["keybindings"]
+        vec!["yank-link"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[index|url]",
+            description: "Copy a link in the selected message, the first by default",
+        }
+    }
+
+    fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        complete_link(tui_state, args)
     }
 
     fn dyn_clone(&self) -> Box<dyn Command> {
-        Box::new(Self)
+        Box::new(Self {
+            item: self.item.clone(),
+        })
     }
 }
 
+/// Copy the paths of the downloaded attachments of the selected message to the clipboard, one per
+/// line.
 #[derive(Debug)]
-pub struct Commands;
+pub struct YankAttachmentPath;
 
-impl Command for Commands {
+impl Command for YankAttachmentPath {
     fn execute(
         &self,
         tui_state: &mut TuiState,
         _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        tui_state.popup = Some(Popup::new(PopupType::Commands));
-        tui_state.mode = Mode::Popup;
+        let Some(message) = tui_state.messages.selected() else {
+            return Err(Error::NoMessageSelected);
+        };
+        let paths = message
+            .attachments
+            .iter()
+            .filter_map(|a| a.path.as_ref())
+            .map(|p| p.to_string_lossy())
+            .collect::<Vec<_>>();
+        if paths.is_empty() {
+            return Err(Error::Failure("No downloaded attachments".to_owned()));
+        }
+        copy_to_clipboard(&paths.join("\n"))?;
         Ok(CommandSuccess::Nothing)
     }
 
-    fn default() -> Self
-    where
-        Self: Sized,
-    {
+    fn default() -> Self {
         Self
     }
 
     fn names(&self) -> Vec<&'static str> {
-        vec!["commands"]
+        vec!["yank-attachment-path"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Copy the paths of the downloaded attachments of the selected message",
+        }
     }
 
     fn dyn_clone(&self) -> Box<dyn Command> {
@@ -1236,22 +1879,30 @@ impl Command for Commands {
 }
 
 #[derive(Debug)]
-pub struct Reply;
+pub struct MessageInfo;
 
-impl Command for Reply {
+impl Command for MessageInfo {
     fn execute(
         &self,
         tui_state: &mut TuiState,
-        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
         let Some(selected_message) = tui_state.messages.selected() else {
             return Err(Error::NoMessageSelected);
         };
-        tui_state.compose.set_quote(Quote {
-            sender: selected_message.sender.clone(),
+        // only the user's own messages have receipts to show
+        if selected_message.sender == tui_state.self_id {
+            ba_tx
+                .unbounded_send(BackendMessage::LoadMessageReceipts {
+                    contact_id: selected_message.contact_id.clone(),
+                    timestamp: selected_message.timestamp,
+                })
+                .unwrap();
+        }
+        tui_state.popup = Some(Popup::new(PopupType::MessageInfo {
             timestamp: selected_message.timestamp,
-            text: selected_message.content.clone(),
-        });
+        }));
+        tui_state.mode = Mode::Popup;
         Ok(CommandSuccess::Nothing)
     }
 
@@ -1263,7 +1914,14 @@ impl Command for Reply {
     }
 
     fn names(&self) -> Vec<&'static str> {
-        vec!["reply"]
+        vec!["message-info"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Show the details of the selected message",
+        }
     }
 
     fn dyn_clone(&self) -> Box<dyn Command> {
@@ -1272,25 +1930,33 @@ impl Command for Reply {
 }
 
 #[derive(Debug)]
-pub struct CommandHistory;
+pub struct PinMessage;
 
-impl Command for CommandHistory {
+impl Command for PinMessage {
     fn execute(
         &self,
         tui_state: &mut TuiState,
-        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        tui_state.popup = Some(Popup::new(PopupType::CommandHistory));
-        tui_state.mode = Mode::Popup;
-        Ok(CommandSuccess::Nothing)
+        set_selected_message_pinned(tui_state, ba_tx, true)
     }
 
-    fn default() -> Self {
+    fn default() -> Self
+    where
+        Self: Sized,
+    {
         Self
     }
 
     fn names(&self) -> Vec<&'static str> {
-        vec!["command-history"]
+        vec!["pin-message"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Pin the selected message in its conversation",
+        }
     }
 
     fn dyn_clone(&self) -> Box<dyn Command> {
@@ -1299,29 +1965,33 @@ impl Command for CommandHistory {
 }
 
 #[derive(Debug)]
-pub struct PrevCommand;
+pub struct UnpinMessage;
 
-impl Command for PrevCommand {
+impl Command for UnpinMessage {
     fn execute(
         &self,
         tui_state: &mut TuiState,
-        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        tui_state.command_line.history.select_previous();
-        if let Some(selected_command) = tui_state.command_line.history.selected_command() {
-            tui_state.command_line.set_text(selected_command.clone());
-        } else {
-            tui_state.command_line.clear();
-        }
-        Ok(CommandSuccess::Nothing)
+        set_selected_message_pinned(tui_state, ba_tx, false)
     }
 
-    fn default() -> Self {
+    fn default() -> Self
+    where
+        Self: Sized,
+    {
         Self
     }
 
     fn names(&self) -> Vec<&'static str> {
-        vec!["prev-command"]
+        vec!["unpin-message"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Unpin the selected message",
+        }
     }
 
     fn dyn_clone(&self) -> Box<dyn Command> {
@@ -1329,245 +1999,225 @@ impl Command for PrevCommand {
     }
 }
 
+fn set_selected_message_pinned(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    pinned: bool,
+) -> Result<CommandSuccess> {
+    let Some(contact) = tui_state.contacts.selected() else {
+        return Err(Error::NoContactSelected);
+    };
+    let Some(message) = tui_state.messages.selected() else {
+        return Err(Error::NoMessageSelected);
+    };
+    ba_tx
+        .unbounded_send(BackendMessage::PinMessage {
+            contact_id: contact.id.clone(),
+            timestamp: message.timestamp,
+            pinned,
+        })
+        .unwrap();
+    Ok(CommandSuccess::Nothing)
+}
+
 #[derive(Debug)]
-pub struct NextCommand;
+pub struct PinnedMessages;
 
-impl Command for NextCommand {
+impl Command for PinnedMessages {
     fn execute(
         &self,
         tui_state: &mut TuiState,
-        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        tui_state.command_line.history.select_next();
-        if let Some(selected_command) = tui_state.command_line.history.selected_command() {
-            tui_state.command_line.set_text(selected_command.clone());
-        } else {
-            tui_state.command_line.clear();
-        }
-        Ok(CommandSuccess::Nothing)
-    }
-
-    fn default() -> Self {
-        Self
-    }
-
-    fn names(&self) -> Vec<&'static str> {
-        vec!["next-command"]
-    }
-
-    fn dyn_clone(&self) -> Box<dyn Command> {
-        Box::new(Self)
-    }
-}
-
-#[derive(Debug)]
-pub struct ScrollPopup {
-    pub amount: i16,
-}
-
-impl Command for ScrollPopup {
-    fn execute(
-        &self,
-        tui_state: &mut TuiState,
-        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
-    ) -> Result<CommandSuccess> {
-        debug!(amount:% = self.amount; "Scrolling popup");
-        let popup = tui_state.popup.as_mut().unwrap();
-        if self.amount > 0 {
-            popup.scroll += self.amount as u16;
-        } else if self.amount < 0 {
-            popup.scroll = popup.scroll.saturating_sub(self.amount.unsigned_abs());
-        }
+        let Some(contact) = tui_state.contacts.selected() else {
+            return Err(Error::NoContactSelected);
+        };
+        ba_tx
+            .unbounded_send(BackendMessage::LoadPinnedMessages {
+                contact_id: contact.id.clone(),
+            })
+            .unwrap();
+        tui_state.popup = Some(Popup::new(PopupType::PinnedMessages {
+            contact_id: contact.id.clone(),
+        }));
+        tui_state.mode = Mode::Popup;
         Ok(CommandSuccess::Nothing)
     }
 
-    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
-        let amount = args
-            .free_from_str()
-            .map_err(|_e| Error::MissingArgument("amount".to_owned()))?;
-        *self = Self { amount };
-        check_unused_args(args)?;
-        Ok(())
+    fn default() -> Self
+    where
+        Self: Sized,
+    {
+        Self
     }
 
-    fn default() -> Self {
-        Self { amount: 0 }
+    fn names(&self) -> Vec<&'static str> {
+        vec!["pinned-messages"]
     }
 
-    fn names(&self) -> Vec<&'static str> {
-        vec!["scroll-popup"]
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Show the pinned messages of the conversation",
+        }
     }
 
     fn dyn_clone(&self) -> Box<dyn Command> {
-        Box::new(Self {
-            amount: self.amount,
-        })
+        Box::new(Self)
     }
 }
 
+/// Look up someone who isn't among the contacts, such as by their phone number or username, and
+/// switch to a conversation with them.
 #[derive(Debug)]
-pub struct AttachFiles {
-    paths: Vec<String>,
+pub struct FindUser {
+    query: String,
 }
 
-impl Command for AttachFiles {
+impl Command for FindUser {
     fn execute(
         &self,
-        tui_state: &mut TuiState,
-        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+        _tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        if self.paths.is_empty() {
-            return Err(Error::MissingArgument("path".to_owned()));
-        }
-
-        for path in &self.paths {
-            let path = expand_tilde(path);
-
-            if !path.is_file() {
-                return Err(Error::InvalidArgument {
-                    arg: "path".to_owned(),
-                    value: path.to_string_lossy().into_owned(),
-                });
-            }
-            tui_state.compose.attach_file(path.clone());
+        if self.query.is_empty() {
+            return Err(Error::MissingArgument("phone|username".to_owned()));
         }
+        ba_tx
+            .unbounded_send(BackendMessage::FindUser {
+                query: self.query.clone(),
+            })
+            .unwrap();
         Ok(CommandSuccess::Nothing)
     }
 
-    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
-        loop {
-            let path = args
-                .opt_free_from_str()
-                .map_err(|_e| Error::MissingArgument("paths".to_owned()))?;
-            match path {
-                Some(path) => {
-                    self.paths.push(path);
-                }
-                None => break,
-            }
-        }
-        check_unused_args(args)?;
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        // phone numbers are often written with spaces
+        let query = args
+            .finish()
+            .into_iter()
+            .map(|s| s.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        self.query = query.join(" ");
         Ok(())
     }
 
     fn default() -> Self {
-        Self { paths: Vec::new() }
+        Self {
+            query: String::new(),
+        }
     }
 
     fn names(&self) -> Vec<&'static str> {
-        vec!["attach-files"]
+        vec!["find-user"]
     }
 
-    fn complete(&self, _tui_state: &TuiState, args: &str) -> Vec<Completion> {
-        let Some(path) = args.split(' ').last() else {
-            return Vec::new();
-        };
-
-        complete_path(path)
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<query...>",
+            description: "Start a conversation with someone by their phone number or username",
+        }
     }
 
     fn dyn_clone(&self) -> Box<dyn Command> {
         Box::new(Self {
-            paths: self.paths.clone(),
+            query: self.query.clone(),
         })
     }
 }
 
 #[derive(Debug)]
-pub struct DetachFiles {
-    // TODO: allow vec of items
-    item: Option<IndexOrString>,
+pub struct ContactInfo {
+    search: Option<String>,
 }
 
-impl Command for DetachFiles {
+impl Command for ContactInfo {
     fn execute(
         &self,
         tui_state: &mut TuiState,
-        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        match &self.item {
-            Some(item) => {
-                let index = match item {
-                    IndexOrString::Index(i) => Some(*i),
-                    IndexOrString::Str(name) => tui_state
-                        .compose
-                        .attachments()
-                        .iter()
-                        .position(|a| &a.name == name),
-                };
-                if let Some(index) = index {
-                    tui_state.compose.detach_file(index);
-                }
-            }
-            None => {
-                // detach all
-                let mut indices = (0..tui_state.compose.attachments().len()).collect::<Vec<_>>();
-                indices.sort();
-                indices.reverse();
-
-                for index in indices {
-                    tui_state.compose.detach_file(index);
-                }
-            }
-        }
-
+        let Some(selected_contact) = tui_state.contacts.selected() else {
+            return Err(Error::NoContactSelected);
+        };
+        ba_tx
+            .unbounded_send(BackendMessage::LoadContactDetails {
+                contact_id: selected_contact.id.clone(),
+            })
+            .unwrap();
+        tui_state.popup = Some(Popup::new(PopupType::ContactInfo {
+            id: selected_contact.id.clone(),
+            search: self.search.clone(),
+        }));
+        tui_state.mode = Mode::Popup;
         Ok(CommandSuccess::Nothing)
     }
 
     fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
-        let item = args
+        let search: Option<String> = args
             .opt_free_from_str()
-            .map_err(|_e| Error::MissingArgument("item".to_owned()))?;
-        self.item = item;
+            .map_err(|_e| Error::MissingArgument("search".to_owned()))?;
+        self.search = search;
         check_unused_args(args)?;
         Ok(())
     }
 
-    fn default() -> Self {
-        Self { item: None }
+    fn default() -> Self
+    where
+        Self: Sized,
+    {
+        Self { search: None }
     }
 
     fn names(&self) -> Vec<&'static str> {
-        vec!["detach-files"]
+        vec!["contact-info"]
     }
 
-    fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
-        let candidates = tui_state
-            .compose
-            .attachments()
-            .iter()
-            .enumerate()
-            .flat_map(|(i, a)| [i.to_string(), a.name.clone()]);
-        complete_from_iter(args, candidates)
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[search]",
+            description:
+                "Show the details of the conversation, only the fields matching the search if given",
+        }
     }
 
     fn dyn_clone(&self) -> Box<dyn Command> {
         Box::new(Self {
-            item: self.item.clone(),
+            search: self.search.clone(),
         })
     }
 }
 
 #[derive(Debug)]
-pub struct ReloadConfig;
+pub struct Keybindings;
 
-impl Command for ReloadConfig {
+impl Command for Keybindings {
     fn execute(
         &self,
         tui_state: &mut TuiState,
         _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        let config = crate::util::load_config(&tui_state.config_path);
-        tui_state.config = config;
+        tui_state.popup = Some(Popup::new(PopupType::Keybinds));
+        tui_state.mode = Mode::Popup;
         Ok(CommandSuccess::Nothing)
     }
 
-    fn default() -> Self {
+    fn default() -> Self
+    where
+        Self: Sized,
+    {
         Self
     }
 
     fn names(&self) -> Vec<&'static str> {
-        vec!["reload-config"]
+        vec!["keybindings"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Show the key bindings",
+        }
     }
 
     fn dyn_clone(&self) -> Box<dyn Command> {
@@ -1576,31 +2226,35 @@ impl Command for ReloadConfig {
 }
 
 #[derive(Debug)]
-pub struct GotoQuoted;
+pub struct Commands;
 
-impl Command for GotoQuoted {
+impl Command for Commands {
     fn execute(
         &self,
         tui_state: &mut TuiState,
         _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        let Some(message) = tui_state.messages.selected() else {
-            return Err(Error::NoMessageSelected);
-        };
-        let Some(quoted) = &message.quote else {
-            return Err(Error::Failure("No quote to follow".to_owned()));
-        };
-        tui_state.messages.select_message(quoted.timestamp);
-
+        tui_state.popup = Some(Popup::new(PopupType::Commands));
+        tui_state.mode = Mode::Popup;
         Ok(CommandSuccess::Nothing)
     }
 
-    fn default() -> Self {
+    fn default() -> Self
+    where
+        Self: Sized,
+    {
         Self
     }
 
     fn names(&self) -> Vec<&'static str> {
-        vec!["goto-quoted"]
+        vec!["commands"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Show the commands",
+        }
     }
 
     fn dyn_clone(&self) -> Box<dyn Command> {
@@ -1608,54 +2262,75 @@ impl Command for GotoQuoted {
     }
 }
 
+/// Show how to run a command, the other names it goes by and the keys bound to it.
 #[derive(Debug)]
-pub struct PipeMessage {
-    command: String,
+pub struct Help {
+    command: Option<String>,
 }
 
-impl Command for PipeMessage {
+impl Command for Help {
     fn execute(
         &self,
         tui_state: &mut TuiState,
-        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        let Some(message) = tui_state.messages.selected() else {
-            return Err(Error::NoMessageSelected);
+        let Some(name) = &self.command else {
+            return Commands.execute(tui_state, ba_tx);
         };
-
-        let mut child = std::process::Command::new("sh")
-            .arg("-c")
-            .arg(&self.command)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()
-            .unwrap();
-        let stdin = child.stdin.as_mut().unwrap();
-        writeln!(stdin, "{}", message.content).unwrap();
-        child.wait().unwrap();
-
-        Ok(CommandSuccess::Nothing)
+        let name = name.trim_start_matches(':');
+        let Some(command) = commands().into_iter().find(|c| c.names().contains(&name)) else {
+            let Some(command) = tui_state
+                .plugins
+                .commands()
+                .into_iter()
+                .find(|c| c.name == name)
+            else {
+                return Err(Error::InvalidArgument {
+                    arg: "command".to_owned(),
+                    value: name.to_owned(),
+                });
+            };
+            return Ok(CommandSuccess::ShowPopup {
+                title: format!(":{name}"),
+                text: format!(":{name}\n{}\nFrom a plugin", command.description),
+            });
+        };
+        Ok(CommandSuccess::ShowPopup {
+            title: format!(":{name}"),
+            text: help_text(command.as_ref(), &tui_state.config.keybinds),
+        })
     }
 
-    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
         let command = args
-            .finish()
-            .into_iter()
-            .map(|s| s.to_string_lossy().into_owned())
-            .collect::<Vec<_>>();
-        self.command = command.join(" ");
+            .opt_free_from_str()
+            .map_err(|_e| Error::MissingArgument("command".to_owned()))?;
+        check_unused_args(args)?;
+        *self = Self { command };
         Ok(())
     }
 
     fn default() -> Self {
-        Self {
-            command: String::new(),
-        }
+        Self { command: None }
     }
 
     fn names(&self) -> Vec<&'static str> {
-        vec!["pipe-message"]
+        vec!["help"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[command]",
+            description: "Show how to run a command and the keys bound to it, or list the commands",
+        }
+    }
+
+    fn complete(&self, _tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        let names = commands()
+            .into_iter()
+            .flat_map(|c| c.names())
+            .map(|n| n.to_owned());
+        complete_from_iter(args, names)
     }
 
     fn dyn_clone(&self) -> Box<dyn Command> {
@@ -1665,174 +2340,4687 @@ impl Command for PipeMessage {
     }
 }
 
-#[derive(Debug)]
-pub struct Forward {
-    contact_name: String,
-}
+/// The usage, description, other names and key bindings of a command, one to a line.
+fn help_text(command: &dyn Command, keybinds: &KeyBinds) -> String {
+    let names = command.names();
+    let help = command.help();
+    let mut lines = vec![
+        format!(":{} {}", names[0], help.usage)
+            .trim_end()
+            .to_owned(),
+        help.description.to_owned(),
+    ];
+    if names.len() > 1 {
+        let aliases = names[1..]
+            .iter()
+            .map(|n| format!(":{n}"))
+            .collect::<Vec<_>>();
+        lines.push(format!("Also {}", aliases.join(", ")));
+    }
+    let bound = keybinds.bound_to(&names);
+    if !bound.is_empty() {
+        lines.push(String::new());
+        for (mode, keys) in bound {
+            lines.push(format!("{keys} in {mode} mode"));
+        }
+    }
+    lines.join("\n")
+}
 
-impl Command for Forward {
+#[derive(Debug)]
+pub struct Reply;
+
+impl Command for Reply {
     fn execute(
         &self,
         tui_state: &mut TuiState,
-        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        let Some(contact) = tui_state
-            .contacts
-            .contact_or_group_by_name(&self.contact_name)
-        else {
-            return Err(Error::InvalidArgument {
-                arg: "contact".to_owned(),
-                value: self.contact_name.clone(),
-            });
+        let Some(selected_message) = tui_state.messages.selected() else {
+            return Err(Error::NoMessageSelected);
+        };
+        let quote = Quote {
+            sender: selected_message.sender.clone(),
+            timestamp: selected_message.timestamp,
+            text: selected_message.content.clone(),
+            mentions: selected_message.mentions.clone(),
+            formatting: selected_message.formatting.clone(),
         };
+        if tui_state.compose.quote().is_some() {
+            remember_draft(tui_state);
+        }
+        tui_state.compose.set_quote(quote);
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self
+    where
+        Self: Sized,
+    {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["reply"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Quote the selected message in the draft",
+        }
+    }
 
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct ReplyPrivately;
+
+impl Command for ReplyPrivately {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(contact) = tui_state.contacts.selected() else {
+            return Err(Error::NoContactSelected);
+        };
+        if !matches!(contact.id, ContactId::Group(_)) {
+            return Err(Error::Failure(
+                "Can only reply privately to group messages".to_owned(),
+            ));
+        }
         let Some(selected_message) = tui_state.messages.selected() else {
             return Err(Error::NoMessageSelected);
         };
+        if selected_message.sender == tui_state.self_id {
+            return Err(Error::Failure(
+                "Cannot reply privately to your own message".to_owned(),
+            ));
+        }
+        let quote = Quote {
+            sender: selected_message.sender.clone(),
+            timestamp: selected_message.timestamp,
+            text: selected_message.content.clone(),
+            mentions: selected_message.mentions.clone(),
+            formatting: selected_message.formatting.clone(),
+        };
 
-        ba_tx
-            .unbounded_send(BackendMessage::SendMessage {
-                contact_id: contact.id.clone(),
-                content: MessageContent::Text {
-                    text: selected_message.content.clone(),
-                    attachments: selected_message.attachments.clone(),
-                },
-                quote: None,
-            })
-            .unwrap();
+        let direct_id = ContactId::User(quote.sender.clone());
+        let index = match tui_state.contacts.index_by_id(&direct_id) {
+            Some(index) => index,
+            None => {
+                // no conversation with them yet, sending to them will start one
+                let contact = tui_state
+                    .contacts
+                    .contact_by_id(&quote.sender)
+                    .cloned()
+                    .unwrap_or_else(|| Contact {
+                        id: direct_id.clone(),
+                        name: hex::encode(&quote.sender),
+                        address: String::new(),
+                        last_message_timestamp: None,
+                        description: String::new(),
+                        muted_until: None,
+                        verification: Verification::Unverified,
+                        members: None,
+                        encrypted: false,
+                    });
+                tui_state.contacts.extend([contact]);
+                tui_state.contacts.len() - 1
+            }
+        };
+
+        let last_selected = tui_state.contacts.state.selected();
+        tui_state.contacts.state.select(Some(index));
+        after_contact_changed(tui_state, ba_tx, last_selected);
+
+        if tui_state.compose.quote().is_some() {
+            remember_draft(tui_state);
+        }
+        tui_state.compose.set_quote(quote);
         Ok(CommandSuccess::Nothing)
     }
 
-    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
-        let contact_name = args
-            .free_from_str()
-            .map_err(|_e| Error::MissingArgument("contact".to_owned()))?;
-        *self = Self { contact_name };
-        check_unused_args(args)?;
-        Ok(())
+    fn default() -> Self
+    where
+        Self: Sized,
+    {
+        Self
     }
 
-    fn default() -> Self {
-        Self {
-            contact_name: String::new(),
+    fn names(&self) -> Vec<&'static str> {
+        vec!["reply-privately"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Quote the selected group message in a conversation with its sender",
         }
     }
 
-    fn names(&self) -> Vec<&'static str> {
-        vec!["forward"]
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
     }
+}
 
-    fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
-        let contact_name = args;
+#[derive(Debug)]
+pub struct CommandHistory;
 
-        let candidates = tui_state
-            .contacts
-            .iter_contacts_and_groups()
-            .filter_map(|c| {
-                if c.name.starts_with(contact_name) {
-                    Some(shell_words::quote(&c.name).into_owned())
-                } else {
-                    None
-                }
-            });
-        complete_from_iter(args, candidates)
+impl Command for CommandHistory {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        tui_state.popup = Some(Popup::new(PopupType::CommandHistory));
+        tui_state.mode = Mode::Popup;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["command-history"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Show the commands run before",
+        }
     }
 
     fn dyn_clone(&self) -> Box<dyn Command> {
-        Box::new(Self {
-            contact_name: self.contact_name.clone(),
-        })
+        Box::new(Self)
     }
 }
 
 #[derive(Debug)]
-pub struct AlignMessage {
-    alignment: Alignment,
-}
+pub struct PrevCommand;
 
-#[derive(Debug, Clone, Copy)]
-enum Alignment {
-    Top,
-    Bottom,
+impl Command for PrevCommand {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        tui_state.command_line.history.select_previous();
+        if let Some(selected_command) = tui_state.command_line.history.selected_command() {
+            tui_state.command_line.set_text(selected_command.clone());
+        } else {
+            tui_state.command_line.clear();
+        }
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["prev-command"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Go back to the previous command in the history",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
 }
 
-impl FromStr for Alignment {
-    type Err = String;
+#[derive(Debug)]
+pub struct NextCommand;
 
-    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "top" => Ok(Self::Top),
-            "bottom" => Ok(Self::Bottom),
-            _ => Err(format!("Failed to match {s:?} to one of 'top' or 'bottom'")),
+impl Command for NextCommand {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        tui_state.command_line.history.select_next();
+        if let Some(selected_command) = tui_state.command_line.history.selected_command() {
+            tui_state.command_line.set_text(selected_command.clone());
+        } else {
+            tui_state.command_line.clear();
+        }
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["next-command"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Go forward to the next command in the history",
         }
     }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
 }
 
-impl Command for AlignMessage {
+#[derive(Debug)]
+pub struct ScrollPopup {
+    pub amount: i16,
+    count: i16,
+}
+
+impl Command for ScrollPopup {
     fn execute(
         &self,
         tui_state: &mut TuiState,
         _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
     ) -> Result<CommandSuccess> {
-        match self.alignment {
-            Alignment::Top => tui_state.messages.state.align_top(),
-            Alignment::Bottom => tui_state.messages.state.align_bottom(),
+        let amount = self.amount.saturating_mul(self.count);
+        debug!(amount:%; "Scrolling popup");
+        let popup = tui_state.popup.as_mut().unwrap();
+        if amount > 0 {
+            popup.scroll = popup.scroll.saturating_add(amount as u16);
+        } else if amount < 0 {
+            popup.scroll = popup.scroll.saturating_sub(amount.unsigned_abs());
         }
         Ok(CommandSuccess::Nothing)
     }
 
     fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
-        let alignment = args
+        let amount = args
             .free_from_str()
-            .map_err(|_e| Error::MissingArgument("alignment".to_owned()))?;
-        *self = Self { alignment };
+            .map_err(|_e| Error::MissingArgument("amount".to_owned()))?;
+        *self = Self { amount, count: 1 };
         check_unused_args(args)?;
         Ok(())
     }
 
+    fn set_count(&mut self, count: usize) {
+        self.count = count.try_into().unwrap_or(i16::MAX);
+    }
+
     fn default() -> Self {
         Self {
-            alignment: Alignment::Bottom,
+            amount: 0,
+            count: 1,
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["scroll-popup"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<amount>",
+            description: "Scroll the popup down by the amount, or up when negative",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            amount: self.amount,
+            count: self.count,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct AttachFiles {
+    paths: Vec<String>,
+}
+
+impl Command for AttachFiles {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        if self.paths.is_empty() {
+            return Err(Error::MissingArgument("path".to_owned()));
+        }
+
+        for path in &self.paths {
+            let path = expand_tilde(path);
+
+            if !path.is_file() {
+                return Err(Error::InvalidArgument {
+                    arg: "path".to_owned(),
+                    value: path.to_string_lossy().into_owned(),
+                });
+            }
+            tui_state.compose.attach_file(path.clone());
+        }
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        loop {
+            let path = args
+                .opt_free_from_str()
+                .map_err(|_e| Error::MissingArgument("paths".to_owned()))?;
+            match path {
+                Some(path) => {
+                    self.paths.push(path);
+                }
+                None => break,
+            }
+        }
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self { paths: Vec::new() }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["attach-files"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<path...>",
+            description: "Attach files to the draft",
+        }
+    }
+
+    fn complete(&self, _tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        let Some(path) = args.split(' ').last() else {
+            return Vec::new();
+        };
+
+        complete_path(path)
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            paths: self.paths.clone(),
+        })
+    }
+}
+
+/// Pick files to attach by browsing directories, starting from the given one or the current
+/// directory.
+#[derive(Debug)]
+pub struct BrowseAttach {
+    dir: Option<String>,
+}
+
+impl Command for BrowseAttach {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let dir = match &self.dir {
+            Some(dir) => expand_tilde(dir),
+            None => current_dir()
+                .map_err(|e| Error::Failure(format!("Failed to get current directory: {e}")))?,
+        };
+        if !dir.is_dir() {
+            return Err(Error::InvalidArgument {
+                arg: "dir".to_owned(),
+                value: dir.to_string_lossy().into_owned(),
+            });
+        }
+        let compose = matches!(tui_state.mode, Mode::Compose);
+        tui_state.popup = Some(Popup::new(PopupType::FilePicker(FilePicker::new(
+            dir, compose,
+        ))));
+        tui_state.mode = Mode::Popup;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        self.dir = args
+            .opt_free_from_str()
+            .map_err(|_e| Error::MissingArgument("dir".to_owned()))?;
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self { dir: None }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["browse-attach"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[dir]",
+            description: "Pick files to attach by browsing from the directory",
+        }
+    }
+
+    fn complete(&self, _tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        complete_path(args)
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            dir: self.dir.clone(),
+        })
+    }
+}
+
+/// Attach the files marked in the file picker, or the selected one without any marked, and close
+/// it.
+pub fn attach_picked_files(tui_state: &mut TuiState) {
+    let Some(Popup {
+        typ: PopupType::FilePicker(picker),
+        ..
+    }) = &mut tui_state.popup
+    else {
+        return;
+    };
+    let paths: Vec<_> = if picker.marked.is_empty() {
+        // enter directories rather than attaching them
+        picker.open().into_iter().collect()
+    } else {
+        std::mem::take(&mut picker.marked).into_iter().collect()
+    };
+    if paths.is_empty() {
+        return;
+    }
+    for path in paths {
+        tui_state.compose.attach_file(path);
+    }
+    close_file_picker(tui_state);
+}
+
+pub fn close_file_picker(tui_state: &mut TuiState) {
+    if let Some(Popup {
+        typ: PopupType::FilePicker(picker),
+        ..
+    }) = tui_state.popup.take()
+    {
+        tui_state.mode = if picker.compose {
+            Mode::Compose
+        } else {
+            Mode::Normal
+        };
+    }
+}
+
+#[derive(Debug)]
+pub struct DetachFiles {
+    // TODO: allow vec of items
+    item: Option<IndexOrString>,
+}
+
+impl Command for DetachFiles {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        match &self.item {
+            Some(item) => {
+                let index = match item {
+                    IndexOrString::Index(i) => Some(*i),
+                    IndexOrString::Str(name) => tui_state
+                        .compose
+                        .attachments()
+                        .iter()
+                        .position(|a| &a.name == name),
+                };
+                if let Some(index) = index.filter(|i| *i < tui_state.compose.attachments().len()) {
+                    remember_draft(tui_state);
+                    tui_state.compose.detach_file(index);
+                }
+            }
+            None => {
+                // detach all
+                if !tui_state.compose.attachments().is_empty() {
+                    remember_draft(tui_state);
+                }
+                let mut indices = (0..tui_state.compose.attachments().len()).collect::<Vec<_>>();
+                indices.sort();
+                indices.reverse();
+
+                for index in indices {
+                    tui_state.compose.detach_file(index);
+                }
+            }
+        }
+
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let item = args
+            .opt_free_from_str()
+            .map_err(|_e| Error::MissingArgument("item".to_owned()))?;
+        self.item = item;
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self { item: None }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["detach-files"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[index|name]",
+            description: "Remove an attachment from the draft, or all of them",
+        }
+    }
+
+    fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        let candidates = tui_state
+            .compose
+            .attachments()
+            .iter()
+            .enumerate()
+            .flat_map(|(i, a)| [i.to_string(), a.name.clone()]);
+        complete_from_iter(args, candidates)
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            item: self.item.clone(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ReloadConfig;
+
+impl Command for ReloadConfig {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        crate::util::reload_config(tui_state).map_err(Error::Failure)?;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["reload-config"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Load the config file again",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct Lock;
+
+impl Command for Lock {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        if tui_state.vault.is_none() {
+            return Err(Error::Failure(
+                "Locking needs a passphrase, set `encrypt = true` in the config".to_owned(),
+            ));
+        }
+        tui_state.lock = Some(LockScreen::new("Enter the passphrase to unlock"));
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["lock"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Hide everything until the passphrase is entered",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct ExportKeybinds {
+    path: String,
+}
+
+impl Command for ExportKeybinds {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let path = expand_tilde(&self.path);
+        let content = toml::to_string(&tui_state.config.keybinds)
+            .map_err(|e| Error::Failure(format!("Failed to serialize keybinds: {e}")))?;
+        std::fs::write(&path, content)
+            .map_err(|e| Error::Failure(format!("Failed to write keybinds: {e}")))?;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        self.path = args
+            .free_from_str()
+            .map_err(|_e| Error::MissingArgument("path".to_owned()))?;
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["export-keybinds"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<path>",
+            description: "Write the key bindings to a file to share",
+        }
+    }
+
+    fn complete(&self, _tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        complete_path(args)
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            path: self.path.clone(),
+        })
+    }
+}
+
+/// Import keybinds from a file in the same format as the `keybinds` section of the config, as
+/// written by `export-keybinds`.
+///
+/// Bindings that clash with existing ones are skipped unless `--force` is given. The imported
+/// bindings last until the config is reloaded.
+#[derive(Debug)]
+pub struct ImportKeybinds {
+    path: String,
+    force: bool,
+}
+
+impl Command for ImportKeybinds {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let path = expand_tilde(&self.path);
+        let content = std::fs::read_to_string(&path).map_err(|_e| Error::InvalidArgument {
+            arg: "path".to_owned(),
+            value: path.to_string_lossy().into_owned(),
+        })?;
+        let keybinds: KeyBinds = toml::from_str(&content)
+            .map_err(|e| Error::Failure(format!("Failed to parse keybinds: {e}")))?;
+        let conflicts = tui_state.config.keybinds.merge(keybinds, self.force);
+        if conflicts.is_empty() {
+            return Ok(CommandSuccess::Nothing);
+        }
+        for conflict in &conflicts {
+            warn!(conflict:%; "Conflicting keybind on import");
+        }
+        let action = if self.force { "Replaced" } else { "Skipped" };
+        let conflicts = conflicts
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        Err(Error::Failure(format!(
+            "{action} conflicting keybinds: {conflicts}"
+        )))
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        self.force = args.contains("--force");
+        self.path = args
+            .free_from_str()
+            .map_err(|_e| Error::MissingArgument("path".to_owned()))?;
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            force: false,
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["import-keybinds"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[--force] <path>",
+            description: "Add key bindings from a file, replacing clashing ones when forced",
+        }
+    }
+
+    fn complete(&self, _tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        let path = args.rsplit(' ').next().unwrap_or_default();
+        complete_path(path)
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            path: self.path.clone(),
+            force: self.force,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct GotoQuoted;
+
+impl Command for GotoQuoted {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(message) = tui_state.messages.selected() else {
+            return Err(Error::NoMessageSelected);
+        };
+        let Some(quoted) = &message.quote else {
+            return Err(Error::Failure("No quote to follow".to_owned()));
+        };
+        let timestamp = quoted.timestamp;
+        if tui_state.messages.get_by_timestamp(timestamp).is_some() {
+            tui_state.messages.select_message(timestamp);
+            return Ok(CommandSuccess::Nothing);
+        }
+
+        // older than the messages loaded so load back to it, selecting it once they arrive
+        let contact_id = message.contact_id.clone();
+        tui_state.goto_after_load = Some(timestamp);
+        request_messages(
+            tui_state,
+            ba_tx,
+            contact_id,
+            std::ops::Bound::Included(timestamp),
+        );
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["goto-quoted"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description:
+                "Select the message quoted by the selected one, loading back to it if needed",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+/// Where the output of the command messages are piped to goes, thrown away if not given.
+#[derive(Debug, Clone, Copy)]
+enum PipeOutput {
+    /// Shown in a popup.
+    Show,
+    /// Inserted into compose at the cursor.
+    Insert,
+}
+
+#[derive(Debug)]
+pub struct PipeMessage {
+    command: String,
+    output: Option<PipeOutput>,
+}
+
+impl Command for PipeMessage {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let messages = tui_state.messages.selected_range();
+        if messages.is_empty() {
+            return Err(Error::NoMessageSelected);
+        }
+
+        let captured = || {
+            if self.output.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            }
+        };
+        let mut child = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(captured())
+            .stderr(captured())
+            .spawn()
+            .map_err(|e| Error::Failure(format!("Failed to run {}: {e}", self.command)))?;
+        let mut input = Vec::new();
+        for message in messages {
+            writeln!(input, "{}", message.content).unwrap();
+        }
+        let mut stdin = child.stdin.take().unwrap();
+        // written alongside reading the output so that neither side waits on a full pipe
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+        let output = child
+            .wait_with_output()
+            .map_err(|e| Error::Failure(format!("Failed to run {}: {e}", self.command)))?;
+        let _ = writer.join();
+        tui_state.messages.stop_visual();
+
+        match self.output {
+            Some(output_to) => use_piped_output(tui_state, &self.command, output_to, output),
+            None => Ok(CommandSuccess::Nothing),
+        }
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let output = parse_pipe_output(&mut args)?;
+        let command = args
+            .finish()
+            .into_iter()
+            .map(|s| s.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        *self = Self {
+            command: command.join(" "),
+            output,
+        };
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            command: String::new(),
+            output: None,
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["pipe-message"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[--show|--insert] <command...>",
+            description: "Pipe the selected messages to a command, showing or inserting its output",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            command: self.command.clone(),
+            output: self.output,
+        })
+    }
+}
+
+/// Pipe a downloaded attachment of the selected message to a command, which has the terminal
+/// unless its output is wanted.
+#[derive(Debug)]
+pub struct PipeAttachment {
+    index: usize,
+    command: String,
+    output: Option<PipeOutput>,
+}
+
+impl Command for PipeAttachment {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(message) = tui_state.messages.selected() else {
+            return Err(Error::NoMessageSelected);
+        };
+        let Some(attachment) = message.attachments.get(self.index) else {
+            return Err(Error::InvalidArgument {
+                arg: "index".to_owned(),
+                value: self.index.to_string(),
+            });
+        };
+        let Some(path) = &attachment.path else {
+            return Err(Error::Failure(
+                "Attachment has not been downloaded".to_owned(),
+            ));
+        };
+        let file = std::fs::File::open(path)
+            .map_err(|e| Error::Failure(format!("Failed to open {}: {e}", path.display())))?;
+        debug!(path:?, command:? = self.command; "Piping attachment");
+
+        let mut command = std::process::Command::new("sh");
+        command.arg("-c").arg(&self.command).stdin(file);
+        let Some(output_to) = self.output else {
+            // such as a pager
+            suspend_terminal(!tui_state.config.disable_mouse, || command.status())
+                .map_err(|e| Error::Failure(format!("Failed to run {}: {e}", self.command)))?;
+            return Ok(CommandSuccess::Clear);
+        };
+        let output = command
+            .output()
+            .map_err(|e| Error::Failure(format!("Failed to run {}: {e}", self.command)))?;
+        use_piped_output(tui_state, &self.command, output_to, output)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let output = parse_pipe_output(&mut args)?;
+        let index = args
+            .free_from_str()
+            .map_err(|_e| Error::MissingArgument("index".to_owned()))?;
+        let command = args
+            .finish()
+            .into_iter()
+            .map(|s| s.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        if command.is_empty() {
+            return Err(Error::MissingArgument("command".to_owned()));
+        }
+        *self = Self {
+            index,
+            command: command.join(" "),
+            output,
+        };
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            index: 0,
+            command: String::new(),
+            output: None,
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["pipe-attachment"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[--show|--insert] <index> <command...>",
+            description: "Pipe a downloaded attachment of the selected message to a command",
+        }
+    }
+
+    fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        // only the index is completed, not the command after it
+        let mut words = args.split(' ').skip(1).filter(|w| !w.starts_with("--"));
+        words.next();
+        if words.next().is_some() {
+            return Vec::new();
+        }
+        let Some(message) = tui_state.messages.selected() else {
+            return Vec::new();
+        };
+        let candidates = message
+            .attachments
+            .iter()
+            .enumerate()
+            .filter(|(_i, a)| a.path.is_some())
+            .map(|(i, _a)| i.to_string());
+        complete_from_iter(args, candidates)
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            index: self.index,
+            command: self.command.clone(),
+            output: self.output,
+        })
+    }
+}
+
+/// Take `--show` or `--insert` for where the output of a piped command goes.
+fn parse_pipe_output(args: &mut pico_args::Arguments) -> Result<Option<PipeOutput>> {
+    match (args.contains("--show"), args.contains("--insert")) {
+        (true, true) => Err(Error::Failure(
+            "Only one of --show and --insert can be given".to_owned(),
+        )),
+        (true, false) => Ok(Some(PipeOutput::Show)),
+        (false, true) => Ok(Some(PipeOutput::Insert)),
+        (false, false) => Ok(None),
+    }
+}
+
+/// Show or insert what a piped command printed, or what it complained about if it failed.
+fn use_piped_output(
+    tui_state: &mut TuiState,
+    command: &str,
+    output_to: PipeOutput,
+    output: std::process::Output,
+) -> Result<CommandSuccess> {
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::Failure(format!(
+            "Failed to run {command}: {}",
+            stderr.trim()
+        )));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = stdout.trim_end();
+    match output_to {
+        PipeOutput::Show => Ok(CommandSuccess::ShowPopup {
+            title: command.to_owned(),
+            text: stdout.to_owned(),
+        }),
+        PipeOutput::Insert => {
+            remember_draft(tui_state);
+            tui_state.compose.insert_str(stdout);
+            Ok(CommandSuccess::Nothing)
+        }
+    }
+}
+
+/// Forward the selected messages to one or more contacts, after confirming who to.
+#[derive(Debug)]
+pub struct Forward {
+    contact_names: Vec<String>,
+}
+
+impl Command for Forward {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        if self.contact_names.is_empty() {
+            return Err(Error::MissingArgument("contact".to_owned()));
+        }
+        let mut recipients = Vec::new();
+        for name in &self.contact_names {
+            let Some(contact) = tui_state.contacts.contact_or_group_by_name(name) else {
+                return Err(Error::InvalidArgument {
+                    arg: "contact".to_owned(),
+                    value: name.clone(),
+                });
+            };
+            if !recipients.contains(&contact.id) {
+                recipients.push(contact.id.clone());
+            }
+        }
+
+        let messages = tui_state.messages.selected_range();
+        let Some(contact_id) = messages.first().map(|m| m.contact_id.clone()) else {
+            return Err(Error::NoMessageSelected);
+        };
+        // check them all first so that either all or none are forwarded
+        for message in &messages {
+            check_limits(tui_state, &message.content, &message.attachments)?;
+        }
+        let messages = messages
+            .into_iter()
+            .map(|m| ForwardedMessage {
+                timestamp: m.timestamp,
+                text: m.content.clone(),
+                attachments: m.attachments.clone(),
+            })
+            .collect();
+
+        tui_state.messages.stop_visual();
+        tui_state.popup = Some(Popup::new(PopupType::ConfirmForward(forwards::Forward {
+            contact_id,
+            recipients,
+            messages,
+        })));
+        tui_state.mode = Mode::Popup;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let mut contact_names = Vec::new();
+        while let Some(name) = args
+            .opt_free_from_str()
+            .map_err(|_e| Error::MissingArgument("contact".to_owned()))?
+        {
+            contact_names.push(name);
+        }
+        self.contact_names = contact_names;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            contact_names: Vec::new(),
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["forward"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<contact...>",
+            description: "Forward the selected messages to conversations, after confirming",
+        }
+    }
+
+    fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        let candidates = tui_state
+            .contacts
+            .iter_contacts_and_groups()
+            .map(|c| c.name.clone());
+        complete_from_iter(args, candidates)
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            contact_names: self.contact_names.clone(),
+        })
+    }
+}
+
+/// Send the forward being confirmed, once any attachments not yet downloaded have been.
+#[derive(Debug)]
+pub struct ConfirmForward;
+
+impl Command for ConfirmForward {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(PopupType::ConfirmForward(forward)) = tui_state.popup.as_ref().map(|p| &p.typ)
+        else {
+            return Err(Error::Failure("Nothing to forward".to_owned()));
+        };
+        let forward = forward.clone();
+        tui_state.popup = None;
+        tui_state.mode = Mode::Normal;
+
+        let missing = forward.missing_attachments();
+        if missing.is_empty() {
+            send_forward(ba_tx, &forward);
+            return Ok(CommandSuccess::Nothing);
+        }
+        // attachments are uploaded again from the files, so they need downloading first
+        for (timestamp, index) in missing {
+            // forwarded from the open conversation
+            let sender = tui_state
+                .messages
+                .get_by_timestamp(timestamp)
+                .map(|m| m.sender.clone())
+                .unwrap_or_default();
+            ba_tx
+                .unbounded_send(BackendMessage::DownloadAttachment {
+                    contact_id: forward.contact_id.clone(),
+                    timestamp,
+                    index,
+                    sender,
+                })
+                .unwrap();
+        }
+        tui_state.forwards.wait(forward);
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["confirm-forward"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Send the forward being confirmed",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+pub(crate) fn send_forward(
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    forward: &forwards::Forward,
+) {
+    for (contact_id, content) in forward.contents() {
+        ba_tx
+            .unbounded_send(BackendMessage::SendMessage {
+                contact_id,
+                content,
+                quote: None,
+            })
+            .unwrap();
+    }
+}
+
+/// Select a range of messages from the selected one, like Vim's visual line mode, for commands
+/// such as `forward` and `pipe-message` to act on them all.
+#[derive(Debug)]
+pub struct VisualSelect;
+
+impl Command for VisualSelect {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        if tui_state.messages.selected().is_none() {
+            return Err(Error::NoMessageSelected);
+        }
+        tui_state.messages.toggle_visual();
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["visual-select"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Select a range of messages for other commands to act on",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+/// Write the selected messages to a file, one per line with when and who sent them.
+#[derive(Debug)]
+pub struct ExportMessages {
+    path: String,
+}
+
+impl Command for ExportMessages {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let messages = tui_state.messages.selected_range();
+        if messages.is_empty() {
+            return Err(Error::NoMessageSelected);
+        }
+        let count = messages.len();
+        let mut content = String::new();
+        for message in messages {
+            let time = chrono::DateTime::from_timestamp_millis(message.timestamp as i64)
+                .unwrap_or_default()
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M");
+            let sender = crate::tui::sender_name(tui_state, &message.sender);
+            content.push_str(&format!("[{time}] {sender}: {}\n", message.text()));
+            for attachment in &message.attachments {
+                content.push_str(&format!("  + {}\n", attachment.name));
+            }
+        }
+        let path = expand_tilde(&self.path);
+        std::fs::write(&path, content)
+            .map_err(|e| Error::Failure(format!("Failed to write messages: {e}")))?;
+        tui_state.messages.stop_visual();
+        Ok(CommandSuccess::ShowPopup {
+            title: "Exported messages".to_owned(),
+            text: wrote_messages(count, &path),
+        })
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        self.path = args
+            .free_from_str()
+            .map_err(|_e| Error::MissingArgument("path".to_owned()))?;
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["export-messages"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<path>",
+            description: "Write the selected messages to a file",
+        }
+    }
+
+    fn complete(&self, _tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        complete_path(args)
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            path: self.path.clone(),
+        })
+    }
+}
+
+/// What an export says it did once done.
+fn wrote_messages(count: usize, path: &Path) -> String {
+    let messages = if count == 1 { "message" } else { "messages" };
+    format!("Wrote {count} {messages} to {}", path.display())
+}
+
+/// Write the loaded messages of the conversation to a file as an HTML or markdown transcript, with
+/// the format going by the file's extension unless given.
+#[derive(Debug)]
+pub struct ExportTranscript {
+    path: String,
+    format: Option<TranscriptFormat>,
+}
+
+impl Command for ExportTranscript {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(contact) = tui_state.contacts.selected() else {
+            return Err(Error::NoContactSelected);
+        };
+        let messages: Vec<_> = (0..tui_state.messages.len())
+            .filter_map(|i| tui_state.messages.get_by_index(i))
+            .map(|m| transcript_message(tui_state, m))
+            .collect();
+        let path = expand_tilde(&self.path);
+        let format = self
+            .format
+            .unwrap_or_else(|| TranscriptFormat::for_path(&path));
+        let content = transcript::render(&contact.name, &messages, format);
+        std::fs::write(&path, content)
+            .map_err(|e| Error::Failure(format!("Failed to write transcript: {e}")))?;
+        Ok(CommandSuccess::ShowPopup {
+            title: "Exported transcript".to_owned(),
+            text: wrote_messages(messages.len(), &path),
+        })
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let format: Option<String> = args
+            .opt_value_from_str("--format")
+            .map_err(|_e| Error::MissingArgument("format".to_owned()))?;
+        self.format = format
+            .map(|f| {
+                f.parse().map_err(|_e| Error::InvalidArgument {
+                    arg: "format".to_owned(),
+                    value: f,
+                })
+            })
+            .transpose()?;
+        self.path = args
+            .free_from_str()
+            .map_err(|_e| Error::MissingArgument("path".to_owned()))?;
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            format: None,
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["export-transcript"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[--format html|markdown] <path>",
+            description: "Write the loaded messages of the conversation to a transcript",
+        }
+    }
+
+    fn complete(&self, _tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        complete_path(args)
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            path: self.path.clone(),
+            format: self.format,
+        })
+    }
+}
+
+/// A message with the names of who sent, quoted and reacted to it, for a transcript.
+fn transcript_message(tui_state: &TuiState, message: &Message) -> TranscriptMessage {
+    let name = |id: &[u8]| crate::tui::sender_name(tui_state, id);
+    let mut reactions: Vec<(String, Vec<String>)> = Vec::new();
+    for reaction in &message.reactions {
+        match reactions
+            .iter_mut()
+            .find(|(emoji, _)| *emoji == reaction.emoji)
+        {
+            Some((_, names)) => names.push(name(&reaction.author)),
+            None => reactions.push((reaction.emoji.clone(), vec![name(&reaction.author)])),
+        }
+    }
+    TranscriptMessage {
+        time: chrono::DateTime::from_timestamp_millis(message.timestamp as i64)
+            .unwrap_or_default()
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string(),
+        sender: name(&message.sender),
+        text: message.text().to_owned(),
+        quote: message
+            .quote
+            .as_ref()
+            .map(|q| (name(&q.sender), q.text.clone())),
+        reactions,
+        attachments: message
+            .attachments
+            .iter()
+            .map(|a| (a.name.clone(), a.path.clone()))
+            .collect(),
+    }
+}
+
+/// Delete the selected messages for everyone, skipping any sent by others.
+#[derive(Debug)]
+pub struct DeleteMessage;
+
+impl Command for DeleteMessage {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let messages = tui_state.messages.selected_range();
+        if messages.is_empty() {
+            return Err(Error::NoMessageSelected);
+        }
+        let selected = messages.len();
+        let own: Vec<_> = messages
+            .into_iter()
+            .filter(|m| m.sender == tui_state.self_id)
+            .map(|m| (m.contact_id.clone(), m.timestamp))
+            .collect();
+        if own.is_empty() {
+            return Err(Error::Failure(
+                "Only your own messages can be deleted".to_owned(),
+            ));
+        }
+        tui_state.messages.stop_visual();
+        // deleting can't be undone, so check before deleting more than one
+        if own.len() > 1 {
+            tui_state.popup = Some(Popup::new(PopupType::ConfirmDelete {
+                skipped: selected - own.len(),
+                messages: own,
+            }));
+            tui_state.mode = Mode::Popup;
+            return Ok(CommandSuccess::Nothing);
+        }
+        delete_messages(ba_tx, own);
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["delete-message"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description:
+                "Delete the selected messages for everyone, after confirming if there are several",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+/// Delete the messages being confirmed.
+#[derive(Debug)]
+pub struct ConfirmDelete;
+
+impl Command for ConfirmDelete {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(PopupType::ConfirmDelete { messages, .. }) =
+            tui_state.popup.as_ref().map(|p| &p.typ)
+        else {
+            return Err(Error::Failure("Nothing to delete".to_owned()));
+        };
+        let messages = messages.clone();
+        tui_state.popup = None;
+        tui_state.mode = Mode::Normal;
+        delete_messages(ba_tx, messages);
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["confirm-delete"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Delete the messages being confirmed",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+/// Go ahead with whatever the popup is asking to confirm, such as a forward or a delete.
+#[derive(Debug)]
+pub struct Confirm;
+
+impl Command for Confirm {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        match tui_state.popup.as_ref().map(|p| &p.typ) {
+            Some(PopupType::ConfirmForward(_)) => ConfirmForward.execute(tui_state, ba_tx),
+            Some(PopupType::ConfirmDelete { .. }) => ConfirmDelete.execute(tui_state, ba_tx),
+            _ => Err(Error::Failure("Nothing to confirm".to_owned())),
+        }
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["confirm"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Go ahead with what the popup is asking to confirm",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+fn delete_messages(ba_tx: &mpsc::UnboundedSender<BackendMessage>, messages: Vec<(ContactId, u64)>) {
+    for (contact_id, timestamp) in messages {
+        ba_tx
+            .unbounded_send(BackendMessage::DeleteMessage {
+                contact_id,
+                timestamp,
+            })
+            .unwrap();
+    }
+}
+
+#[derive(Debug)]
+pub struct MuteContact {
+    duration: Option<MuteDuration>,
+}
+
+impl Command for MuteContact {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(contact) = tui_state.contacts.selected() else {
+            return Err(Error::NoContactSelected);
+        };
+        let until = match self.duration {
+            Some(MuteDuration(millis)) => timestamp().saturating_add(millis),
+            None => u64::MAX,
+        };
+        let contact_id = contact.id.clone();
+        let before = tui_state
+            .mutes
+            .get(&contact_id, timestamp())
+            .map(|m| m.until);
+        tui_state.undo.push(Change::Mute {
+            contact_id: contact_id.clone(),
+            until: before,
+        });
+        tui_state.mutes.mute(&contact_id, until);
+        tui_state.contacts.mark_read(&contact_id);
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let duration: Option<String> = args
+            .opt_free_from_str()
+            .map_err(|_e| Error::MissingArgument("duration".to_owned()))?;
+        self.duration = duration
+            .map(|d| {
+                d.parse().map_err(|()| Error::InvalidArgument {
+                    arg: "duration".to_owned(),
+                    value: d,
+                })
+            })
+            .transpose()?;
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self
+    where
+        Self: Sized,
+    {
+        Self { duration: None }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["mute-contact"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[duration]",
+            description: "Mute the conversation, for a while such as 8h or 2d if given",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            duration: self.duration,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct UnmuteContact;
+
+impl Command for UnmuteContact {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(contact) = tui_state.contacts.selected() else {
+            return Err(Error::NoContactSelected);
+        };
+        let contact_id = contact.id.clone();
+        let until = tui_state
+            .mutes
+            .get(&contact_id, timestamp())
+            .map(|m| m.until);
+        if !tui_state.mutes.unmute(&contact_id) {
+            return Err(Error::Failure("Contact is not muted".to_owned()));
+        }
+        tui_state.undo.push(Change::Mute { contact_id, until });
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self
+    where
+        Self: Sized,
+    {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["unmute-contact"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Unmute the conversation",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct Nickname {
+    nickname: String,
+}
+
+impl Command for Nickname {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(contact) = tui_state.contacts.selected() else {
+            return Err(Error::NoContactSelected);
+        };
+        let contact_id = contact.id.clone();
+        tui_state.nicknames.set(&contact_id, &self.nickname);
+        arrange_contacts(tui_state);
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        // without one, go back to the contact's own name
+        let nickname = args
+            .finish()
+            .into_iter()
+            .map(|s| s.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        self.nickname = nickname.join(" ");
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            nickname: String::new(),
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["nickname"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[name...]",
+            description: "Give the conversation a nickname, or go back to its own name",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            nickname: self.nickname.clone(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct MoveToFolder {
+    folder: String,
+}
+
+impl Command for MoveToFolder {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(contact) = tui_state.contacts.selected() else {
+            return Err(Error::NoContactSelected);
+        };
+        let contact_id = contact.id.clone();
+        tui_state.folders.set(&contact_id, &self.folder);
+        arrange_contacts(tui_state);
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        // without one, take it out of its folder
+        let folder = args
+            .finish()
+            .into_iter()
+            .map(|s| s.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        self.folder = folder.join(" ");
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            folder: String::new(),
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["move-to-folder"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[folder...]",
+            description: "Group the conversation under a folder, or take it out of its folder",
+        }
+    }
+
+    fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        complete_from_iter(args, tui_state.folders.names(&tui_state.config.folders))
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            folder: self.folder.clone(),
+        })
+    }
+}
+
+/// The selected conversation, if it is a group.
+fn selected_group(tui_state: &TuiState) -> Result<ContactId> {
+    let Some(contact) = tui_state.contacts.selected() else {
+        return Err(Error::NoContactSelected);
+    };
+    if !matches!(contact.id, ContactId::Group(_)) {
+        return Err(Error::Failure("Not a group".to_owned()));
+    }
+    Ok(contact.id.clone())
+}
+
+/// Rename the group for everyone in it, unlike a nickname.
+#[derive(Debug)]
+pub struct SetGroupTitle {
+    title: String,
+}
+
+impl Command for SetGroupTitle {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        if self.title.is_empty() {
+            return Err(Error::MissingArgument("title".to_owned()));
+        }
+        let contact_id = selected_group(tui_state)?;
+        ba_tx
+            .unbounded_send(BackendMessage::SetGroupTitle {
+                contact_id,
+                title: self.title.clone(),
+            })
+            .unwrap();
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        let title = args
+            .finish()
+            .into_iter()
+            .map(|s| s.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        self.title = title.join(" ");
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            title: String::new(),
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["set-group-title"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<title...>",
+            description: "Change the title of the group for everyone in it",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            title: self.title.clone(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct SetGroupDescription {
+    description: String,
+}
+
+impl Command for SetGroupDescription {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let contact_id = selected_group(tui_state)?;
+        ba_tx
+            .unbounded_send(BackendMessage::SetGroupDescription {
+                contact_id,
+                description: self.description.clone(),
+            })
+            .unwrap();
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        // without one, clear the description
+        let description = args
+            .finish()
+            .into_iter()
+            .map(|s| s.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        self.description = description.join(" ");
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            description: String::new(),
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["set-group-description"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[description...]",
+            description: "Change the description of the group for everyone in it, or clear it",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            description: self.description.clone(),
+        })
+    }
+}
+
+/// Accept or decline the invite with the name, or the first when none is given.
+fn answer_invite(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    name: &str,
+    accept: bool,
+) -> Result<CommandSuccess> {
+    let index = if name.is_empty() {
+        if tui_state.invites.is_empty() {
+            return Err(Error::Failure("No invites".to_owned()));
+        }
+        0
+    } else {
+        tui_state
+            .invites
+            .iter()
+            .position(|i| i.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| Error::InvalidArgument {
+                arg: "invite".to_owned(),
+                value: name.to_owned(),
+            })?
+    };
+    // the backend sends the invites left once it has answered
+    let invite = tui_state.invites.remove(index);
+    ba_tx
+        .unbounded_send(BackendMessage::AnswerInvite {
+            contact_id: invite.contact_id,
+            accept,
+        })
+        .unwrap();
+    Ok(CommandSuccess::Nothing)
+}
+
+fn invite_names(tui_state: &TuiState) -> Vec<String> {
+    tui_state.invites.iter().map(|i| i.name.clone()).collect()
+}
+
+/// Join a conversation the user was invited to.
+#[derive(Debug)]
+pub struct AcceptInvite {
+    name: String,
+}
+
+impl Command for AcceptInvite {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        answer_invite(tui_state, ba_tx, &self.name, true)
+    }
+
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        let name = args
+            .finish()
+            .into_iter()
+            .map(|s| s.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        self.name = name.join(" ");
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["accept-invite"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[name...]",
+            description: "Join the conversation of an invite, the first shown if not named",
+        }
+    }
+
+    fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        complete_from_iter(args, invite_names(tui_state))
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            name: self.name.clone(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct DeclineInvite {
+    name: String,
+}
+
+impl Command for DeclineInvite {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        answer_invite(tui_state, ba_tx, &self.name, false)
+    }
+
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        let name = args
+            .finish()
+            .into_iter()
+            .map(|s| s.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        self.name = name.join(" ");
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["decline-invite"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[name...]",
+            description: "Decline an invite, the first shown if not named",
+        }
+    }
+
+    fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        complete_from_iter(args, invite_names(tui_state))
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            name: self.name.clone(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct SetAutoDownload {
+    setting: AutoDownload,
+}
+
+impl Command for SetAutoDownload {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(contact) = tui_state.contacts.selected() else {
+            return Err(Error::NoContactSelected);
+        };
+        let contact_id = contact.id.clone();
+        tui_state.auto_downloads.set(&contact_id, self.setting);
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let setting: String = args
+            .free_from_str()
+            .map_err(|_e| Error::MissingArgument("setting".to_owned()))?;
+        let setting = setting
+            .parse()
+            .map_err(|_e: String| Error::InvalidArgument {
+                arg: "setting".to_owned(),
+                value: setting,
+            })?;
+        *self = Self { setting };
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            setting: AutoDownload::default(),
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["auto-download"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<always|never|rules>",
+            description: "Choose whether the conversation downloads attachments as they arrive",
+        }
+    }
+
+    fn complete(&self, _tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        complete_from_iter(args, AutoDownload::NAMES.map(str::to_owned))
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            setting: self.setting,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct MissedMessages;
+
+impl Command for MissedMessages {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        if tui_state.missed.summary().is_empty() {
+            return Err(Error::Failure("No missed messages".to_owned()));
+        }
+        tui_state.popup = Some(Popup::new(PopupType::MissedMessages));
+        tui_state.mode = Mode::Popup;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self
+    where
+        Self: Sized,
+    {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["missed-messages"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Show what arrived while away",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct GotoMissed {
+    /// Position in the missed messages summary, starting from 1.
+    pub number: usize,
+}
+
+impl Command for GotoMissed {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let invalid = || Error::InvalidArgument {
+            arg: "number".to_owned(),
+            value: self.number.to_string(),
+        };
+        let contact_id = self
+            .number
+            .checked_sub(1)
+            .and_then(|i| tui_state.missed.summary().get(i))
+            .map(|c| c.contact_id.clone())
+            .ok_or_else(invalid)?;
+        let index = tui_state
+            .contacts
+            .index_by_id(&contact_id)
+            .ok_or_else(invalid)?;
+
+        let last_selected = tui_state.contacts.state.selected();
+        tui_state.contacts.state.select(Some(index));
+        after_contact_changed(tui_state, ba_tx, last_selected);
+
+        tui_state.popup = None;
+        tui_state.mode = Mode::Normal;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let number = args
+            .free_from_str()
+            .map_err(|_e| Error::MissingArgument("number".to_owned()))?;
+        self.number = number;
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self { number: 1 }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["goto-missed"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<number>",
+            description: "Go to a conversation from the missed messages",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            number: self.number,
+        })
+    }
+
+    fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        let numbers = (1..=tui_state.missed.summary().len()).map(|i| i.to_string());
+        complete_from_iter(args, numbers)
+    }
+}
+
+#[derive(Debug)]
+pub struct GotoBottom;
+
+impl Command for GotoBottom {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        tui_state.messages.goto_bottom();
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self
+    where
+        Self: Sized,
+    {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["goto-bottom"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Select the newest message",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct AlignMessage {
+    alignment: Alignment,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Alignment {
+    Top,
+    Bottom,
+}
+
+impl FromStr for Alignment {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "top" => Ok(Self::Top),
+            "bottom" => Ok(Self::Bottom),
+            _ => Err(format!("Failed to match {s:?} to one of 'top' or 'bottom'")),
+        }
+    }
+}
+
+impl Command for AlignMessage {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        match self.alignment {
+            Alignment::Top => tui_state.messages.state.align_top(),
+            Alignment::Bottom => tui_state.messages.state.align_bottom(),
+        }
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let alignment = args
+            .free_from_str()
+            .map_err(|_e| Error::MissingArgument("alignment".to_owned()))?;
+        *self = Self { alignment };
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            alignment: Alignment::Bottom,
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["align-message"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<top|bottom>",
+            description: "Scroll the selected message to the top or bottom",
+        }
+    }
+
+    fn complete(&self, _tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        complete_from_iter(args, ["top".to_owned(), "bottom".to_owned()])
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            alignment: self.alignment,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct SetContactOrder {
+    order: ContactOrder,
+}
+
+impl Command for SetContactOrder {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        tui_state.config.contact_order = self.order;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let order: String = args
+            .free_from_str()
+            .map_err(|_e| Error::MissingArgument("order".to_owned()))?;
+        let order = order.parse().map_err(|_e: String| Error::InvalidArgument {
+            arg: "order".to_owned(),
+            value: order,
+        })?;
+        *self = Self { order };
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            order: ContactOrder::default(),
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["contact-order"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<order>",
+            description: "Choose how conversations move as messages arrive",
+        }
+    }
+
+    fn complete(&self, _tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        complete_from_iter(args, ContactOrder::NAMES.map(str::to_owned))
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self { order: self.order })
+    }
+}
+
+#[derive(Debug)]
+pub struct SortContacts {
+    /// Move on to the next sort when not given.
+    sort: Option<ContactSort>,
+}
+
+impl Command for SortContacts {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let sort = &mut tui_state.config.contact_sort;
+        *sort = self.sort.unwrap_or_else(|| sort.next());
+        if *sort == ContactSort::Recent {
+            // go back to the order of the latest messages from the order of another sort
+            tui_state
+                .contacts
+                .sort_by_key(|c| std::cmp::Reverse(c.last_message_timestamp));
+        }
+        arrange_contacts(tui_state);
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let sort: Option<String> = args
+            .opt_free_from_str()
+            .map_err(|_e| Error::MissingArgument("sort".to_owned()))?;
+        self.sort = sort
+            .map(|s| {
+                s.parse().map_err(|_e: String| Error::InvalidArgument {
+                    arg: "sort".to_owned(),
+                    value: s,
+                })
+            })
+            .transpose()?;
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self { sort: None }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["sort-contacts"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[sort]",
+            description: "Switch how conversations are sorted in each folder, or to the next sort",
+        }
+    }
+
+    fn complete(&self, _tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        complete_from_iter(args, ContactSort::NAMES.map(str::to_owned))
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self { sort: self.sort })
+    }
+}
+
+#[derive(Debug)]
+pub struct SetTheme {
+    theme: Theme,
+}
+
+impl Command for SetTheme {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        tui_state.config.theme = self.theme;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let theme: String = args
+            .free_from_str()
+            .map_err(|_e| Error::MissingArgument("theme".to_owned()))?;
+        let theme = theme.parse().map_err(|_e: String| Error::InvalidArgument {
+            arg: "theme".to_owned(),
+            value: theme,
+        })?;
+        *self = Self { theme };
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            theme: Theme::default(),
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["theme"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<auto|dark|light>",
+            description: "Switch the colours",
+        }
+    }
+
+    fn complete(&self, _tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        complete_from_iter(args, Theme::NAMES.map(str::to_owned))
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self { theme: self.theme })
+    }
+}
+
+#[derive(Debug)]
+pub struct SetMessageLayout {
+    /// Move on to the next layout when not given.
+    layout: Option<MessageLayout>,
+}
+
+impl Command for SetMessageLayout {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let layout = &mut tui_state.config.message_layout;
+        *layout = self.layout.unwrap_or_else(|| layout.next());
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let layout: Option<String> = args
+            .opt_free_from_str()
+            .map_err(|_e| Error::MissingArgument("layout".to_owned()))?;
+        self.layout = layout
+            .map(|l| {
+                l.parse().map_err(|_e: String| Error::InvalidArgument {
+                    arg: "layout".to_owned(),
+                    value: l,
+                })
+            })
+            .transpose()?;
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self { layout: None }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["message-layout"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[layout]",
+            description: "Switch how messages are laid out, or to the next layout",
+        }
+    }
+
+    fn complete(&self, _tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        complete_from_iter(args, MessageLayout::NAMES.map(str::to_owned))
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            layout: self.layout,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ToggleMarkdown;
+
+impl Command for ToggleMarkdown {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        tui_state.config.markdown = !tui_state.config.markdown;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["toggle-markdown"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Turn sending markdown as formatting on or off",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct GrowContacts {
+    count: usize,
+}
+
+impl Command for GrowContacts {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let steps = u16::try_from(self.count).unwrap_or(u16::MAX);
+        tui_state.panes.grow_contacts(steps);
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        self.count = parse_count(args)?;
+        Ok(())
+    }
+
+    fn set_count(&mut self, count: usize) {
+        self.count = count;
+    }
+
+    fn default() -> Self {
+        Self { count: 1 }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["grow-contacts"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[count]",
+            description: "Widen the contacts pane",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self { count: self.count })
+    }
+}
+
+#[derive(Debug)]
+pub struct ShrinkContacts {
+    count: usize,
+}
+
+impl Command for ShrinkContacts {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let steps = u16::try_from(self.count).unwrap_or(u16::MAX);
+        tui_state.panes.shrink_contacts(steps);
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        self.count = parse_count(args)?;
+        Ok(())
+    }
+
+    fn set_count(&mut self, count: usize) {
+        self.count = count;
+    }
+
+    fn default() -> Self {
+        Self { count: 1 }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["shrink-contacts"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[count]",
+            description: "Narrow the contacts pane",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self { count: self.count })
+    }
+}
+
+#[derive(Debug)]
+pub struct ToggleContacts;
+
+impl Command for ToggleContacts {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        tui_state.panes.toggle_contacts();
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["toggle-contacts"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Hide or show the contacts pane",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct Focus;
+
+impl Command for Focus {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        tui_state.focus = !tui_state.focus;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["focus"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Show only the conversation and compose, until run again",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct VSplit {
+    pub item: IndexOrString,
+}
+
+impl Command for VSplit {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(current) = tui_state.contacts.selected().map(|c| c.id.clone()) else {
+            return Err(Error::NoContactSelected);
+        };
+        let last_selected = tui_state.contacts.state.selected();
+        let index = contact_index(tui_state, &self.item)?;
+        if last_selected == Some(index) {
+            return Err(Error::Failure(
+                "The conversation is already shown".to_owned(),
+            ));
+        }
+
+        // the selected conversation moves aside, with its draft, for the new one to be focused
+        tui_state.split = Some(Split {
+            contact_id: current,
+            messages: std::mem::take(&mut tui_state.messages),
+            compose: std::mem::take(&mut tui_state.compose),
+            messages_request: tui_state.messages_request,
+            on_left: true,
+        });
+        tui_state.contacts.state.select(Some(index));
+        after_contact_changed(tui_state, ba_tx, last_selected);
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let item = args
+            .free_from_str()
+            .map_err(|_e| Error::MissingArgument("item".to_owned()))?;
+        self.item = item;
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            item: IndexOrString::Index(0),
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["vsplit"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<index|name>",
+            description: "Show another conversation beside this one",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            item: self.item.clone(),
+        })
+    }
+
+    fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        let names = tui_state
+            .contacts
+            .iter_contacts_and_groups()
+            .map(|c| c.name.clone());
+        complete_from_iter(args, names)
+    }
+}
+
+#[derive(Debug)]
+pub struct SplitFocus;
+
+impl Command for SplitFocus {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(mut split) = tui_state.split.take() else {
+            return Err(Error::Failure("No split open".to_owned()));
+        };
+        let (Some(current), Some(index)) = (
+            tui_state.contacts.selected().map(|c| c.id.clone()),
+            tui_state.contacts.index_by_id(&split.contact_id),
+        ) else {
+            tui_state.split = Some(split);
+            return Err(Error::NoContactSelected);
+        };
+        tui_state.contacts.state.select(Some(index));
+        tui_state.contacts.mark_read(&split.contact_id);
+        tui_state.goto_after_load = None;
+        std::mem::swap(&mut tui_state.messages, &mut split.messages);
+        std::mem::swap(&mut tui_state.compose, &mut split.compose);
+        std::mem::swap(&mut tui_state.messages_request, &mut split.messages_request);
+        split.contact_id = current;
+        split.on_left = !split.on_left;
+        tui_state.split = Some(split);
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["split-focus"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Move to the other conversation of the split",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct CloseSplit;
+
+impl Command for CloseSplit {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(split) = tui_state.split.take() else {
+            return Err(Error::Failure("No split open".to_owned()));
+        };
+        // the draft in the other pane goes with it
+        let draft = split.compose.draft();
+        if !draft.is_empty() {
+            let contact_id = split.contact_id;
+            tui_state.undo.push(Change::Draft { contact_id, draft });
+        }
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["close-split"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Close the other conversation of the split",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct TabOpen {
+    pub item: IndexOrString,
+}
+
+impl Command for TabOpen {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(current) = tui_state.contacts.selected().map(|c| c.id.clone()) else {
+            return Err(Error::NoContactSelected);
+        };
+        let index = contact_index(tui_state, &self.item)?;
+        let Some(contact_id) = tui_state
+            .contacts
+            .contact_or_group_by_index(index)
+            .map(|c| c.id.clone())
+        else {
+            return Err(Error::NoContactSelected);
+        };
+        if tui_state.tabs.is_empty() {
+            // the conversation already open becomes the first tab
+            tui_state.tabs.open(current);
+        }
+        let tab = match tui_state.tabs.position(&contact_id) {
+            Some(tab) => tab,
+            None => tui_state.tabs.open(contact_id),
+        };
+        switch_tab(tui_state, ba_tx, tab)?;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let item = args
+            .free_from_str()
+            .map_err(|_e| Error::MissingArgument("item".to_owned()))?;
+        self.item = item;
+        check_unused_args(args)?;
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            item: IndexOrString::Index(0),
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["tab-open"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<index|name>",
+            description: "Open a conversation in a new tab",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            item: self.item.clone(),
+        })
+    }
+
+    fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        let names = tui_state
+            .contacts
+            .iter_contacts_and_groups()
+            .map(|c| c.name.clone());
+        complete_from_iter(args, names)
+    }
+}
+
+#[derive(Debug)]
+pub struct TabNext {
+    count: usize,
+}
+
+impl Command for TabNext {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let len = tui_state.tabs.len();
+        if len == 0 {
+            return Err(Error::Failure("No tabs open".to_owned()));
+        }
+        let index = (tui_state.tabs.active() + self.count) % len;
+        switch_tab(tui_state, ba_tx, index)?;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        self.count = parse_count(args)?;
+        Ok(())
+    }
+
+    fn set_count(&mut self, count: usize) {
+        self.count = count;
+    }
+
+    fn default() -> Self {
+        Self { count: 1 }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["tab-next"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[count]",
+            description: "Move to the next tab",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self { count: self.count })
+    }
+}
+
+#[derive(Debug)]
+pub struct TabPrev {
+    count: usize,
+}
+
+impl Command for TabPrev {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let len = tui_state.tabs.len();
+        if len == 0 {
+            return Err(Error::Failure("No tabs open".to_owned()));
+        }
+        let index = (tui_state.tabs.active() + len - self.count % len) % len;
+        switch_tab(tui_state, ba_tx, index)?;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        self.count = parse_count(args)?;
+        Ok(())
+    }
+
+    fn set_count(&mut self, count: usize) {
+        self.count = count;
+    }
+
+    fn default() -> Self {
+        Self { count: 1 }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["tab-prev"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[count]",
+            description: "Move to the previous tab",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self { count: self.count })
+    }
+}
+
+#[derive(Debug)]
+pub struct TabClose;
+
+impl Command for TabClose {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        if tui_state.tabs.is_empty() {
+            return Err(Error::Failure("No tabs open".to_owned()));
+        }
+        // the last tab leaves its conversation selected, along with its draft
+        if tui_state.tabs.len() > 1 {
+            remember_draft(tui_state);
+        }
+        if let Some(next) = tui_state.tabs.close_active() {
+            show_tab(tui_state, ba_tx, next);
+        }
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["tab-close"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Close the tab",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct ShowTimestamp;
+
+impl Command for ShowTimestamp {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(timestamp) = tui_state.messages.selected().map(|m| m.timestamp) else {
+            return Err(Error::NoMessageSelected);
+        };
+        tui_state.revealed_timestamp = if tui_state.revealed_timestamp == Some(timestamp) {
+            None
+        } else {
+            Some(timestamp)
+        };
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["show-timestamp"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Show the full date and time of the selected message, until run again",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+/// A case insensitive search for the text, or none when empty.
+fn find_regex(query: &str) -> Option<Regex> {
+    if query.is_empty() {
+        return None;
+    }
+    RegexBuilder::new(&regex::escape(query))
+        .case_insensitive(true)
+        .build()
+        .ok()
+}
+
+/// Select the closest loaded message matching the search, returning whether there was one.
+fn select_found(tui_state: &mut TuiState, older: bool, include_selected: bool) -> bool {
+    let Some(regex) = &tui_state.find else {
+        return false;
+    };
+    let found = tui_state
+        .messages
+        .find(|m| regex.is_match(m.text()), older, include_selected);
+    if let Some(timestamp) = found {
+        tui_state.messages.select_message(timestamp);
+    }
+    found.is_some()
+}
+
+/// Search the loaded messages as the query for `:find` is typed, selecting the first match.
+pub fn find_incrementally(tui_state: &mut TuiState) {
+    let text = tui_state.command_line.text().to_owned();
+    let Some(query) = text.strip_prefix("find ") else {
+        return;
+    };
+    tui_state.find = find_regex(query);
+    select_found(tui_state, true, true);
+}
+
+#[derive(Debug)]
+pub struct Find {
+    query: String,
+}
+
+impl Command for Find {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        tui_state.find = find_regex(&self.query);
+        if tui_state.find.is_some() && !select_found(tui_state, true, true) {
+            return Err(Error::Failure(format!(
+                "No loaded messages match {:?}",
+                self.query
+            )));
+        }
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        // without a query, stop highlighting the last one
+        let query = args
+            .finish()
+            .into_iter()
+            .map(|s| s.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        self.query = query.join(" ");
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self {
+            query: String::new(),
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["find"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[query...]",
+            description: "Search the loaded messages, or stop highlighting matches",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            query: self.query.clone(),
+        })
+    }
+}
+
+/// Move to the next message matching the search in either direction, `count` times.
+fn find_again(tui_state: &mut TuiState, older: bool, count: usize) -> Result<CommandSuccess> {
+    if tui_state.find.is_none() {
+        return Err(Error::Failure(
+            "Nothing to find, search with :find first".to_owned(),
+        ));
+    }
+    for _ in 0..count {
+        if !select_found(tui_state, older, false) {
+            return Err(Error::Failure("No loaded messages match".to_owned()));
+        }
+    }
+    Ok(CommandSuccess::Nothing)
+}
+
+#[derive(Debug)]
+pub struct FindNext {
+    count: usize,
+}
+
+impl Command for FindNext {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        find_again(tui_state, true, self.count)
+    }
+
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        self.count = parse_count(args)?;
+        Ok(())
+    }
+
+    fn set_count(&mut self, count: usize) {
+        self.count = count;
+    }
+
+    fn default() -> Self {
+        Self { count: 1 }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["find-next"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[count]",
+            description: "Select the next older match of the search",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self { count: self.count })
+    }
+}
+
+#[derive(Debug)]
+pub struct FindPrev {
+    count: usize,
+}
+
+impl Command for FindPrev {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        find_again(tui_state, false, self.count)
+    }
+
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        self.count = parse_count(args)?;
+        Ok(())
+    }
+
+    fn set_count(&mut self, count: usize) {
+        self.count = count;
+    }
+
+    fn default() -> Self {
+        Self { count: 1 }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["find-prev"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[count]",
+            description: "Select the next newer match of the search",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self { count: self.count })
+    }
+}
+
+#[derive(Debug)]
+pub struct GotoMention {
+    count: usize,
+}
+
+impl Command for GotoMention {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        for _ in 0..self.count {
+            let found = tui_state.messages.find(
+                |m| {
+                    m.sender != tui_state.self_id
+                        && tui_state.mentions_self(
+                            m.text(),
+                            m.current_mentions().iter().map(|m| m.id.as_slice()),
+                        )
+                },
+                true,
+                false,
+            );
+            let Some(timestamp) = found else {
+                return Err(Error::Failure("No loaded messages mention you".to_owned()));
+            };
+            tui_state.messages.select_message(timestamp);
+        }
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, args: pico_args::Arguments) -> Result<()> {
+        self.count = parse_count(args)?;
+        Ok(())
+    }
+
+    fn set_count(&mut self, count: usize) {
+        self.count = count;
+    }
+
+    fn default() -> Self {
+        Self { count: 1 }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["goto-mention"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[count]",
+            description: "Select the next older message mentioning you",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self { count: self.count })
+    }
+}
+
+#[derive(Debug)]
+pub struct Record {
+    register: Option<String>,
+}
+
+impl Command for Record {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        // running it again stops, whatever register it is given
+        if tui_state.macros.stop().is_some() {
+            return Ok(CommandSuccess::Nothing);
+        }
+        let Some(register) = &self.register else {
+            return Err(Error::MissingArgument("register".to_owned()));
+        };
+        tui_state.macros.start(register);
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let register = args
+            .opt_free_from_str()
+            .map_err(|_e| Error::MissingArgument("register".to_owned()))?;
+        check_unused_args(args)?;
+        *self = Self { register };
+        Ok(())
+    }
+
+    fn default() -> Self {
+        Self { register: None }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["record"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "[register]",
+            description: "Start recording keys into the register, or stop recording",
+        }
+    }
+
+    fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        complete_from_iter(args, tui_state.macros.registers().map(|r| r.to_owned()))
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            register: self.register.clone(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Play {
+    register: String,
+    count: usize,
+}
+
+impl Command for Play {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        if !tui_state.macros.play(&self.register, self.count) {
+            return Err(Error::Failure(format!(
+                "Nothing recorded in @{}",
+                self.register
+            )));
+        }
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn parse(&mut self, mut args: pico_args::Arguments) -> Result<()> {
+        let register = args
+            .free_from_str()
+            .map_err(|_e| Error::MissingArgument("register".to_owned()))?;
+        *self = Self {
+            register,
+            count: parse_count(args)?,
+        };
+        Ok(())
+    }
+
+    fn set_count(&mut self, count: usize) {
+        self.count = count;
+    }
+
+    fn default() -> Self {
+        Self {
+            register: String::new(),
+            count: 1,
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["play"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "<register> [count]",
+            description: "Press the keys recorded in the register again",
+        }
+    }
+
+    fn complete(&self, tui_state: &TuiState, args: &str) -> Vec<Completion> {
+        complete_from_iter(args, tui_state.macros.registers().map(|r| r.to_owned()))
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self {
+            register: self.register.clone(),
+            count: self.count,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Undo;
+
+impl Command for Undo {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(change) = tui_state.undo.pop_undo() else {
+            return Err(Error::Failure("Nothing to undo".to_owned()));
+        };
+        match apply_change(tui_state, change.clone()) {
+            Ok(before) => tui_state.undo.push_redo(before),
+            Err(error) => {
+                tui_state.undo.push_undo(change);
+                return Err(error);
+            }
+        }
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self
+    where
+        Self: Sized,
+    {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["undo"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Undo the last change to a draft or mute",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct Redo;
+
+impl Command for Redo {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(change) = tui_state.undo.pop_redo() else {
+            return Err(Error::Failure("Nothing to redo".to_owned()));
+        };
+        match apply_change(tui_state, change.clone()) {
+            Ok(before) => tui_state.undo.push_undo(before),
+            Err(error) => {
+                tui_state.undo.push_redo(change);
+                return Err(error);
+            }
+        }
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self
+    where
+        Self: Sized,
+    {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["redo"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Redo the last change undone",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+/// Keep the draft of the selected conversation before changing it, to be undone, unless there is
+/// nothing to lose.
+fn remember_draft(tui_state: &mut TuiState) {
+    let draft = tui_state.compose.draft();
+    if draft.is_empty() {
+        return;
+    }
+    let Some(contact) = tui_state.contacts.selected() else {
+        return;
+    };
+    let contact_id = contact.id.clone();
+    tui_state.undo.push(Change::Draft { contact_id, draft });
+}
+
+/// Put things back as they were in the change, returning how they were before to go back again.
+fn apply_change(tui_state: &mut TuiState, change: Change) -> Result<Change> {
+    match change {
+        Change::Draft { contact_id, draft } => {
+            // compose is only for the selected conversation
+            if tui_state
+                .contacts
+                .selected()
+                .is_none_or(|c| c.id != contact_id)
+            {
+                return Err(Error::Failure(
+                    "Change was to the draft of another conversation".to_owned(),
+                ));
+            }
+            let before = tui_state.compose.draft();
+            tui_state.compose.restore(draft);
+            Ok(Change::Draft {
+                contact_id,
+                draft: before,
+            })
+        }
+        Change::Mute { contact_id, until } => {
+            let before = tui_state
+                .mutes
+                .get(&contact_id, timestamp())
+                .map(|m| m.until);
+            match until {
+                Some(until) => tui_state.mutes.mute(&contact_id, until),
+                None => {
+                    tui_state.mutes.unmute(&contact_id);
+                }
+            }
+            Ok(Change::Mute {
+                contact_id,
+                until: before,
+            })
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RevealSpoilers;
+
+impl Command for RevealSpoilers {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let Some(timestamp) = tui_state.messages.selected().map(|m| m.timestamp) else {
+            return Err(Error::NoMessageSelected);
+        };
+        if let Some(message) = tui_state.messages.get_mut_by_timestamp(timestamp) {
+            message.spoilers_revealed = !message.spoilers_revealed;
+        }
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["reveal-spoilers"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Show or hide the spoilers in the selected message",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct OpenEmojiPicker;
+
+impl Command for OpenEmojiPicker {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        let compose = matches!(tui_state.mode, Mode::Compose);
+        if !compose && tui_state.messages.selected().is_none() {
+            return Err(Error::NoMessageSelected);
+        }
+        tui_state.popup = Some(Popup::new(PopupType::EmojiPicker(EmojiPicker::new(
+            compose,
+        ))));
+        tui_state.mode = Mode::Popup;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["emoji-picker"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Pick an emoji to react with, or to insert while composing",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+/// Use the emoji selected in the picker, inserting it into the compose box or reacting with it.
+pub fn pick_emoji(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+) -> Result<()> {
+    let Some(Popup {
+        typ: PopupType::EmojiPicker(picker),
+        ..
+    }) = &tui_state.popup
+    else {
+        return Ok(());
+    };
+    let Some(emoji) = picker.selected_emoji(&tui_state.emoji_usage) else {
+        return Ok(());
+    };
+    let compose = picker.compose;
+    close_emoji_picker(tui_state);
+    if compose {
+        tui_state.compose.insert_str(&emoji);
+        tui_state.emoji_usage.record(&emoji);
+    } else {
+        let react = React { emoji };
+        react.execute(tui_state, ba_tx)?;
+    }
+    Ok(())
+}
+
+/// Go back to where the emoji picker was opened from.
+pub fn close_emoji_picker(tui_state: &mut TuiState) {
+    if let Some(Popup {
+        typ: PopupType::EmojiPicker(picker),
+        ..
+    }) = tui_state.popup.take()
+    {
+        tui_state.mode = if picker.compose {
+            Mode::Compose
+        } else {
+            Mode::Normal
+        };
+    }
+}
+
+#[derive(Debug)]
+pub struct CompleteWord;
+
+impl Command for CompleteWord {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        complete_word(tui_state, true);
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["complete-word"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Complete the word before the cursor while composing",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct CompleteWordPrevious;
+
+impl Command for CompleteWordPrevious {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        _ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        complete_word(tui_state, false);
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["complete-word-previous"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Go back to the previous completion of the word",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+/// Complete the word before the cursor in the compose box, offering corrections if it is
+/// misspelled, otherwise words from the conversation.
+fn complete_word(tui_state: &mut TuiState, next: bool) {
+    if tui_state.compose.is_completing() {
+        if next {
+            tui_state.compose.select_next_completion();
+        } else {
+            tui_state.compose.select_previous_completion();
+        }
+        return;
+    }
+
+    if tui_state.compose.mention_before_cursor().is_some() {
+        suggest_mentions(tui_state);
+        if !tui_state.compose.is_completing() {
+            return;
+        }
+        if next {
+            tui_state.compose.select_next_completion();
+        } else {
+            tui_state.compose.select_previous_completion();
+        }
+        return;
+    }
+
+    let word = tui_state.compose.word_before_cursor();
+    if word.is_empty() {
+        return;
+    }
+    let candidates = match tui_state.spell_checker.misspelled(&word) {
+        Some(suggestions) => suggestions,
+        None => vocabulary_completions(tui_state, &word),
+    };
+    if candidates.is_empty() {
+        return;
+    }
+    let single = candidates.len() == 1;
+    tui_state.compose.set_word_completions(candidates);
+    if next {
+        tui_state.compose.select_next_completion();
+    } else {
+        tui_state.compose.select_previous_completion();
+    }
+    if single {
+        tui_state.compose.clear_completions();
+    }
+}
+
+/// Words used in the conversation that start with the prefix, most used first.
+fn vocabulary_completions(tui_state: &TuiState, prefix: &str) -> Vec<String> {
+    let mut counts = std::collections::BTreeMap::<&str, usize>::new();
+    let texts = tui_state
+        .messages
+        .messages_by_ts
+        .values()
+        .map(|m| m.text())
+        .chain(tui_state.compose.lines().iter().map(String::as_str));
+    for text in texts {
+        for word in spellcheck::words(text) {
+            if word.len() > prefix.len() && word.starts_with(prefix) {
+                *counts.entry(word).or_default() += 1;
+            }
+        }
+    }
+    let mut words: Vec<_> = counts.into_iter().collect();
+    words.sort_by(|(_, a), (_, b)| b.cmp(a));
+    words.into_iter().map(|(w, _)| w.to_owned()).collect()
+}
+
+/// Show who could be mentioned while a mention is being typed in the compose box.
+pub fn suggest_mentions(tui_state: &mut TuiState) {
+    let Some(prefix) = tui_state.compose.mention_before_cursor() else {
+        return;
+    };
+    let prefix = prefix.to_lowercase();
+    let names: Vec<_> = mention_candidates(tui_state)
+        .into_iter()
+        .map(|(name, _)| name)
+        // match the start of any of their names, not just the first
+        .filter(|name| {
+            name.to_lowercase()
+                .split_whitespace()
+                .any(|part| part.starts_with(&prefix))
+        })
+        .collect();
+    if !names.is_empty() {
+        tui_state.compose.set_mention_completions(names);
+    }
+}
+
+/// The names and ids of users who can be mentioned in the selected conversation, those who
+/// have most recently sent messages in it first.
+fn mention_candidates(tui_state: &TuiState) -> Vec<(String, Vec<u8>)> {
+    let senders = tui_state
+        .messages
+        .messages_by_ts
+        .values()
+        .rev()
+        .map(|m| m.sender.clone());
+    let contacts = tui_state
+        .contacts
+        .iter_contacts_and_groups()
+        .filter_map(|c| match &c.id {
+            ContactId::User(id) => Some(id.clone()),
+            ContactId::Group(_) => None,
+        });
+    let mut candidates: Vec<(String, Vec<u8>)> = Vec::new();
+    for id in senders.chain(contacts) {
+        if id == tui_state.self_id || candidates.iter().any(|(_, i)| *i == id) {
+            continue;
+        }
+        let name = match tui_state.contacts.contact_by_id(&id) {
+            Some(contact) => contact.name.clone(),
+            // senders who aren't contacts, such as in Matrix rooms, go by their id
+            None => match String::from_utf8(id.clone()) {
+                Ok(id) => id.trim_start_matches('@').to_owned(),
+                Err(_) => continue,
+            },
+        };
+        candidates.push((name, id));
+    }
+    candidates
+}
+
+/// Find the `@name` mentions of the candidates in the text, preferring the longest names.
+fn find_mentions(text: &str, candidates: &[(String, Vec<u8>)]) -> Vec<Mention> {
+    let mut candidates: Vec<_> = candidates.iter().filter(|(n, _)| !n.is_empty()).collect();
+    candidates.sort_by_key(|(name, _)| std::cmp::Reverse(name.chars().count()));
+
+    let chars: Vec<char> = text.chars().collect();
+    let mut mentions = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '@' || (i > 0 && !chars[i - 1].is_whitespace()) {
+            i += 1;
+            continue;
+        }
+        let rest: String = chars[i + 1..].iter().collect();
+        let found = candidates.iter().find(|(name, _)| {
+            rest.starts_with(name.as_str())
+                && !rest[name.len()..]
+                    .chars()
+                    .next()
+                    .is_some_and(char::is_alphanumeric)
+        });
+        match found {
+            Some((name, id)) => {
+                let length = 1 + name.chars().count();
+                mentions.push(Mention {
+                    start: i,
+                    length,
+                    id: id.clone(),
+                });
+                i += length;
+            }
+            None => i += 1,
+        }
+    }
+    mentions
+}
+
+#[derive(Debug)]
+pub struct VerifyDevice;
+
+impl Command for VerifyDevice {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        ba_tx.unbounded_send(BackendMessage::VerifyDevice).unwrap();
+        tui_state.device_verification = Some(DeviceVerification::Requested);
+        tui_state.popup = Some(Popup::new(PopupType::Verification));
+        tui_state.mode = Mode::Popup;
+        Ok(CommandSuccess::Nothing)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["verify-device"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Start verifying this device",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+/// Answer whether the emoji being compared for a device verification match.
+fn answer_verification(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    confirmed: bool,
+) -> Result<CommandSuccess> {
+    if !matches!(
+        tui_state.device_verification,
+        Some(DeviceVerification::Compare { .. })
+    ) {
+        return Err(Error::Failure("No emoji to compare".to_owned()));
+    }
+    ba_tx
+        .unbounded_send(BackendMessage::ConfirmVerification { confirmed })
+        .unwrap();
+    Ok(CommandSuccess::Nothing)
+}
+
+#[derive(Debug)]
+pub struct ConfirmVerification;
+
+impl Command for ConfirmVerification {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        answer_verification(tui_state, ba_tx, true)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["confirm-verification"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Confirm the emoji match when verifying",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct RejectVerification;
+
+impl Command for RejectVerification {
+    fn execute(
+        &self,
+        tui_state: &mut TuiState,
+        ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    ) -> Result<CommandSuccess> {
+        answer_verification(tui_state, ba_tx, false)
+    }
+
+    fn default() -> Self {
+        Self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        vec!["reject-verification"]
+    }
+
+    fn help(&self) -> CommandHelp {
+        CommandHelp {
+            usage: "",
+            description: "Reject the emoji when they don't match",
+        }
+    }
+
+    fn dyn_clone(&self) -> Box<dyn Command> {
+        Box::new(Self)
+    }
+}
+
+/// Group the conversations by folder, those in none first, sorting each as configured while
+/// keeping the same one selected.
+pub(crate) fn arrange_contacts(tui_state: &mut TuiState) {
+    let sort = tui_state.config.contact_sort;
+    let keys = tui_state
+        .contacts
+        .iter_contacts_and_groups()
+        .map(|c| {
+            let folder = tui_state.folder(c).map(str::to_owned);
+            // recent keeps the order they were moved into as messages arrived
+            let key = match sort {
+                ContactSort::Recent => (false, String::new()),
+                ContactSort::Name => (false, tui_state.nicknames.name(c).to_lowercase()),
+                ContactSort::Unread => (tui_state.contacts.unread_count(&c.id) == 0, String::new()),
+            };
+            (c.id.clone(), (folder, key))
+        })
+        .collect::<std::collections::BTreeMap<_, _>>();
+    tui_state.contacts.sort_by_key(|c| keys.get(&c.id).cloned());
+}
+
+pub(crate) fn after_contact_changed(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    last_selected: Option<usize>,
+) {
+    let selected = tui_state.contacts.state.selected();
+    if selected == last_selected {
+        return;
+    }
+    if let Some(contact) = tui_state.contacts.selected().cloned() {
+        tui_state.contacts.mark_read(&contact.id);
+        tui_state.messages.clear();
+        tui_state.messages.state.select(None);
+        if tui_state.syncing {
+            // something to show until the backend has loaded
+            let messages = tui_state.snapshot.messages(&contact.id);
+            if !messages.is_empty() {
+                tui_state.messages.extend(messages);
+                tui_state.messages.state.select_last();
+            }
+        }
+        load_messages(tui_state, ba_tx, contact.id);
+    }
+}
+
+/// Whether the contact's name or nickname starts with the given name.
+fn matches_name(tui_state: &TuiState, contact: &Contact, name: &str) -> bool {
+    contact.name.starts_with(name) || tui_state.nicknames.name(contact).starts_with(name)
+}
+
+/// The index of a contact given by its index or the start of its name.
+fn contact_index(tui_state: &TuiState, item: &IndexOrString) -> Result<usize> {
+    let (index, value) = match item {
+        IndexOrString::Index(index) => (
+            Some(*index).filter(|i| *i < tui_state.contacts.len()),
+            index.to_string(),
+        ),
+        IndexOrString::Str(name) => (
+            tui_state
+                .contacts
+                .iter_contacts_and_groups()
+                .position(|c| matches_name(tui_state, c, name)),
+            name.clone(),
+        ),
+    };
+    index.ok_or_else(|| Error::InvalidArgument {
+        arg: "item".to_owned(),
+        value,
+    })
+}
+
+/// Make another tab active, keeping the messages and draft of the one being left.
+fn switch_tab(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    index: usize,
+) -> Result<()> {
+    if index == tui_state.tabs.active() {
+        return Ok(());
+    }
+    let Some(contact_id) = tui_state.contacts.selected().map(|c| c.id.clone()) else {
+        return Err(Error::NoContactSelected);
+    };
+    let leaving = Tab {
+        contact_id,
+        messages: std::mem::take(&mut tui_state.messages),
+        compose: std::mem::take(&mut tui_state.compose),
+    };
+    let entering = tui_state.tabs.switch(index, leaving);
+    show_tab(tui_state, ba_tx, entering);
+    Ok(())
+}
+
+/// Select the conversation of a tab becoming active, with its messages as they were when it was
+/// left until they are loaded again.
+fn show_tab(tui_state: &mut TuiState, ba_tx: &mpsc::UnboundedSender<BackendMessage>, tab: Tab) {
+    tui_state.messages = tab.messages;
+    tui_state.compose = tab.compose;
+    if let Some(index) = tui_state.contacts.index_by_id(&tab.contact_id) {
+        tui_state.contacts.state.select(Some(index));
+        tui_state.contacts.mark_read(&tab.contact_id);
+    }
+    load_messages(tui_state, ba_tx, tab.contact_id);
+}
+
+/// Load all the messages of a conversation, superseding any earlier loads still in flight.
+pub(crate) fn load_messages(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    contact_id: ContactId,
+) {
+    tui_state.goto_after_load = None;
+    request_messages(tui_state, ba_tx, contact_id, std::ops::Bound::Unbounded);
+}
+
+/// Load the messages of a conversation from the start given onwards, superseding any earlier loads
+/// still in flight.
+fn request_messages(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    contact_id: ContactId,
+    start_ts: std::ops::Bound<u64>,
+) {
+    tui_state.last_request_id += 1;
+    tui_state.messages_request = tui_state.last_request_id;
+    ba_tx
+        .unbounded_send(BackendMessage::LoadMessages {
+            request_id: tui_state.messages_request,
+            contact_id,
+            start_ts,
+            end_ts: std::ops::Bound::Unbounded,
+        })
+        .unwrap();
+}
+
+/// Load all the messages of the conversation in the other pane, if split, superseding any earlier
+/// loads for it still in flight.
+pub(crate) fn load_split_messages(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+) {
+    let Some(split) = tui_state.split.as_mut() else {
+        return;
+    };
+    tui_state.last_request_id += 1;
+    split.messages_request = tui_state.last_request_id;
+    ba_tx
+        .unbounded_send(BackendMessage::LoadMessages {
+            request_id: split.messages_request,
+            contact_id: split.contact_id.clone(),
+            start_ts: std::ops::Bound::Unbounded,
+            end_ts: std::ops::Bound::Unbounded,
+        })
+        .unwrap();
+}
+
+/// Send a message the way the compose box does, through the `pre_send` hook and then markdown,
+/// mentions and the backend's limits.
+///
+/// `reply` is told once the message has been handed to the backend or stopped, otherwise any
+/// error is shown on the command line.
+pub(crate) fn send_message(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    outgoing: Outgoing,
+    reply: Option<outbox::Reply>,
+) {
+    let Some(contact) = tui_state
+        .contacts
+        .iter_contacts_and_groups()
+        .find(|c| c.id == outgoing.contact_id)
+    else {
+        let error = Error::Failure(format!("Unknown contact {:?}", outgoing.contact_id));
+        report_send(tui_state, reply, Err(error));
+        return;
+    };
+    let waiting = tui_state.outbox.pre_send(
+        &tui_state.config.hooks,
+        &tui_state.app_name,
+        contact,
+        outgoing,
+        reply,
+    );
+    if let Some((outgoing, reply)) = waiting {
+        let result = deliver(tui_state, ba_tx, outgoing);
+        report_send(tui_state, reply, result);
+    }
+}
+
+/// Carry on sending a message from the outbox now that the `pre_send` hook has finished with it.
+pub(crate) fn pre_send_finished(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    id: u64,
+    outcome: PreSend,
+) {
+    let Some((outgoing, reply)) = tui_state.outbox.take(id) else {
+        return;
+    };
+    let result = match outcome {
+        PreSend::Send(text) => deliver(tui_state, ba_tx, Outgoing { text, ..outgoing }),
+        PreSend::Veto => Err(Error::Failure(
+            "Message was vetoed by the pre_send hook".to_owned(),
+        )),
+        PreSend::TimedOut => Err(Error::Failure(
+            "The pre_send hook timed out, so the message wasn't sent".to_owned(),
+        )),
+    };
+    report_send(tui_state, reply, result);
+}
+
+fn deliver(
+    tui_state: &TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    outgoing: Outgoing,
+) -> Result<()> {
+    let (text, formatting) = if tui_state.config.markdown {
+        markdown::parse_markdown(&outgoing.text)
+    } else {
+        (outgoing.text, Vec::new())
+    };
+    // the people who can be mentioned are only known for the open conversation
+    let open = tui_state.contacts.selected().map(|c| &c.id) == Some(&outgoing.contact_id);
+    let mentions = if open {
+        find_mentions(&text, &mention_candidates(tui_state))
+    } else {
+        Vec::new()
+    };
+    check_limits(tui_state, &text, &outgoing.attachments)?;
+    ba_tx
+        .unbounded_send(BackendMessage::SendMessage {
+            contact_id: outgoing.contact_id,
+            content: MessageContent::Text {
+                text,
+                attachments: outgoing.attachments,
+                mentions,
+                formatting,
+            },
+            quote: outgoing.quote,
+        })
+        .map_err(|e| Error::Failure(format!("Failed to send message: {e}")))
+}
+
+fn report_send(tui_state: &mut TuiState, reply: Option<outbox::Reply>, result: Result<()>) {
+    match reply {
+        Some(reply) => reply(result.map_err(|e| e.to_string())),
+        None => {
+            if let Err(error) = result {
+                tui_state.command_line.error = error.to_string();
+            }
+        }
+    }
+}
+
+/// Check an outgoing message against the limits of the backend so that we can give a useful error
+/// rather than failing in the backend.
+pub(crate) fn check_limits(
+    tui_state: &TuiState,
+    body: &str,
+    attachments: &[MessageAttachment],
+) -> Result<()> {
+    let limits = &tui_state.limits;
+    let backend = &tui_state.backend_name;
+    if let Some(max) = limits.max_body_length {
+        if body.len() > max {
+            return Err(Error::Failure(format!(
+                "Message is {} bytes, exceeding {backend}'s {max} byte limit",
+                body.len()
+            )));
+        }
+    }
+    if let Some(max) = limits.max_attachment_count {
+        if attachments.len() > max {
+            return Err(Error::Failure(format!(
+                "Message has {} attachments, exceeding {backend}'s limit of {max}",
+                attachments.len()
+            )));
         }
     }
-
-    fn names(&self) -> Vec<&'static str> {
-        vec!["align-message"]
+    if let Some(max) = limits.max_attachment_size {
+        if let Some(attachment) = attachments.iter().find(|a| a.size > max) {
+            return Err(Error::Failure(format!(
+                "Attachment {:?} exceeds {backend}'s {} limit",
+                attachment.name,
+                human_size(max)
+            )));
+        }
     }
+    Ok(())
+}
 
-    fn complete(&self, _tui_state: &TuiState, args: &str) -> Vec<Completion> {
-        complete_from_iter(args, ["top".to_owned(), "bottom".to_owned()])
-    }
+/// Split a command line into the command name and its arguments, skipping any leading
+/// `execute-command`s.
+fn split_command_line(cmdline: &str) -> Result<Option<(String, pico_args::Arguments)>> {
+    let args = shell_words::split(cmdline)
+        .map_err(|_e| Error::InvalidArgument {
+            arg: "command".to_owned(),
+            value: cmdline.to_owned(),
+        })?
+        .into_iter()
+        .map(OsString::from)
+        .collect();
+    let mut pargs = pico_args::Arguments::from_vec(args);
 
-    fn dyn_clone(&self) -> Box<dyn Command> {
-        Box::new(Self {
-            alignment: self.alignment,
-        })
+    debug!(pargs:? = pargs; "Parsed arguments for command");
+    loop {
+        let Some(subcmd) = pargs.subcommand().unwrap() else {
+            return Ok(None);
+        };
+        if subcmd != "execute-command" {
+            return Ok(Some((subcmd, pargs)));
+        }
     }
 }
 
-fn after_contact_changed(
+fn find_command(name: &str) -> Option<Box<dyn Command>> {
+    commands().into_iter().find(|c| c.names().contains(&name))
+}
+
+/// Run a command line, such as `select-message -1`, as if it was entered in command mode.
+pub fn run_command_line(
     tui_state: &mut TuiState,
     ba_tx: &mpsc::UnboundedSender<BackendMessage>,
-    last_selected: Option<usize>,
-) {
-    let selected = tui_state.contacts.state.selected();
-    if selected == last_selected {
-        return;
-    }
-    if let Some(contact) = tui_state.contacts.selected().cloned() {
-        tui_state.messages.clear();
-        tui_state.messages.state.select(None);
-        ba_tx
-            .unbounded_send(BackendMessage::LoadMessages {
-                contact_id: contact.id.clone(),
-                start_ts: std::ops::Bound::Unbounded,
-                end_ts: std::ops::Bound::Unbounded,
-            })
-            .unwrap();
+    cmdline: &str,
+) -> Result<CommandSuccess> {
+    let Some((subcmd, pargs)) = split_command_line(cmdline)? else {
+        return Ok(CommandSuccess::Nothing);
+    };
+    if let Some(mut command) = find_command(&subcmd) {
+        command.parse(pargs)?;
+        if let Some(count) = tui_state.count {
+            command.set_count(count);
+        }
+        command.execute(tui_state, ba_tx)
+    } else {
+        let args = pargs
+            .finish()
+            .into_iter()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect();
+        let contact_id = tui_state.contacts.selected().map(|c| &c.id);
+        if tui_state.plugins.run_command(&subcmd, args, contact_id) {
+            Ok(CommandSuccess::Nothing)
+        } else {
+            Err(Error::UnknownCommand(subcmd.to_owned()))
+        }
     }
 }
 
+/// Check that a command line names a builtin command and gives it valid arguments, without
+/// running it.
+pub fn check_command_line(cmdline: &str) -> Result<()> {
+    let Some((subcmd, pargs)) = split_command_line(cmdline)? else {
+        return Err(Error::MissingArgument("command".to_owned()));
+    };
+    let mut command = find_command(&subcmd).ok_or(Error::UnknownCommand(subcmd))?;
+    command.parse(pargs)
+}
+
+/// An optional number of times to do something, once by default.
+fn parse_count(mut args: pico_args::Arguments) -> Result<usize> {
+    let count = args
+        .opt_free_from_str::<String>()
+        .map_err(|_e| Error::MissingArgument("count".to_owned()))?;
+    let count = match count {
+        Some(count) => match count.parse() {
+            Ok(count) if count > 0 => count,
+            _ => {
+                return Err(Error::InvalidArgument {
+                    arg: "count".to_owned(),
+                    value: count,
+                })
+            }
+        },
+        None => 1,
+    };
+    check_unused_args(args)?;
+    Ok(count)
+}
+
 fn check_unused_args(args: pico_args::Arguments) -> Result<()> {
     let unused_args = args.finish();
     if !unused_args.is_empty() {
@@ -1847,7 +7035,7 @@ fn check_unused_args(args: pico_args::Arguments) -> Result<()> {
     Ok(())
 }
 
-fn expand_tilde(s: &str) -> PathBuf {
+pub(crate) fn expand_tilde(s: &str) -> PathBuf {
     if s.starts_with("~") {
         let home = std::env::var("HOME").expect("HOME environment variable was not set");
         if s == "~" {
@@ -1888,23 +7076,27 @@ pub fn complete_command(tui_state: &mut TuiState, next: bool) {
         let candidates = cmds
             .into_iter()
             .flat_map(|c| c.names())
-            .map(|n| n.to_owned());
+            .map(|n| n.to_owned())
+            .chain(tui_state.plugins.commands().into_iter().map(|c| c.name));
         complete_from_iter(&before_cursor, candidates)
     };
     if completions.len() == 1 {
-        tui_state
-            .command_line
-            .append_text(completions[0].append.clone());
+        tui_state.command_line.insert_completion(&completions[0]);
         tui_state.command_line.set_completions(Vec::new());
     } else {
         tui_state.command_line.set_completions(completions);
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Completion {
     pub display: String,
     pub append: String,
+    /// What was typed before the cursor that the completion replaces, empty when it carries on
+    /// from it.
+    pub replace: String,
+    /// The indices of the characters of `display` matching what was typed.
+    pub matched: Vec<usize>,
 }
 
 fn last_part_of_shell_string(s: &str) -> String {
@@ -1938,26 +7130,43 @@ fn last_part_of_shell_string(s: &str) -> String {
 fn complete_from_iter(cmd_line: &str, items: impl IntoIterator<Item = String>) -> Vec<Completion> {
     let last_part = last_part_of_shell_string(cmd_line);
 
-    let result = items
+    let mut result = items
         .into_iter()
         .map(|li| shell_words::quote(&li).into_owned())
         .filter_map(|li| {
-            if li.starts_with(&last_part) {
-                Some(Completion {
-                    append: li.strip_prefix(&last_part).unwrap().to_owned(),
-                    display: li,
-                })
-            } else {
-                None
+            if let Some(append) = li.strip_prefix(&last_part) {
+                return Some((
+                    true,
+                    0,
+                    Completion {
+                        append: append.to_owned(),
+                        replace: String::new(),
+                        matched: (0..last_part.chars().count()).collect(),
+                        display: li,
+                    },
+                ));
             }
+            let found = fuzzy::fuzzy_match(&last_part, &li)?;
+            Some((
+                false,
+                found.score,
+                Completion {
+                    append: li.clone(),
+                    replace: last_part.clone(),
+                    matched: found.indices,
+                    display: li,
+                },
+            ))
         })
-        .collect();
-    result
+        .collect::<Vec<_>>();
+    // those carrying on from what was typed first, in the order given, then the best matches
+    result.sort_by_key(|&(prefix, score, _)| std::cmp::Reverse((prefix, score)));
+    result.into_iter().map(|(_, _, c)| c).collect()
 }
 
 fn complete_path(current: &str) -> Vec<Completion> {
     let path = if current.is_empty() {
-        current_dir().unwrap()
+        current_dir().unwrap_or_default()
     } else {
         expand_tilde(current)
     };
@@ -1970,27 +7179,32 @@ fn complete_path(current: &str) -> Vec<Completion> {
     debug!(path:?, file_name:?, is_dir:? = path.is_dir(); "Getting completions for path");
 
     let candidates = if path.is_dir() {
-        read_dir(&path)
-            .unwrap()
-            .map(|e| {
-                let e = e.unwrap();
-                e.path().to_string_lossy().into_owned()
-            })
+        let Ok(entries) = read_dir(&path) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path().to_string_lossy().into_owned())
             .collect::<Vec<_>>()
     } else if let Some(path) = path.parent() {
         let Ok(entries) = read_dir(path) else {
             return Vec::new();
         };
         entries
-            .map(|e| {
-                let e = e.unwrap();
-                e.path().to_string_lossy().into_owned()
-            })
+            .filter_map(|e| e.ok())
+            .map(|e| e.path().to_string_lossy().into_owned())
             .collect::<Vec<_>>()
     } else {
         Vec::new()
     };
-    complete_from_iter(&path.to_string_lossy(), candidates)
+    let mut completions = complete_from_iter(&path.to_string_lossy(), candidates);
+    // what was typed may not be the path it expands to, such as with a `~`
+    for completion in &mut completions {
+        if !completion.replace.is_empty() {
+            completion.replace = last_part_of_shell_string(current);
+        }
+    }
+    completions
 }
 
 #[derive(Debug, Clone)]
@@ -2010,9 +7224,35 @@ impl FromStr for IndexOrString {
     }
 }
 
+/// How long to mute for, in milliseconds, given as a number with a unit of `s`, `m`, `h`, `d` or
+/// `w`, such as `30m` or `2d`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MuteDuration(u64);
+
+impl FromStr for MuteDuration {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let split = s.find(|c: char| !c.is_ascii_digit()).ok_or(())?;
+        let (num, unit) = s.split_at(split);
+        let num = num.parse::<u64>().map_err(|_| ())?;
+        let secs = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            "w" => 7 * 24 * 60 * 60,
+            _ => return Err(()),
+        };
+        num.checked_mul(secs * 1000).map(Self).ok_or(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::backends::Invite;
+    use crate::testing::{contact, text_message};
 
     #[test]
     fn test_list_completion() {
@@ -2023,6 +7263,27 @@ mod tests {
         insta::assert_debug_snapshot!(complete_from_iter("bar", list.clone()));
     }
 
+    #[test]
+    fn test_fuzzy_completion() {
+        let displays = |typed: &str, list: &[&str]| {
+            complete_from_iter(typed, list.iter().map(|s| s.to_string()))
+                .into_iter()
+                .map(|c| c.display)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            displays("mc", &["music", "mark-read", "mute-contact"]),
+            ["mute-contact", "music"]
+        );
+        // carrying on from what was typed comes first
+        assert_eq!(displays("mu", &["unmute", "mute"]), ["mute", "unmute"]);
+
+        let completions = complete_from_iter("select-contact ali", ["Alice Smith".to_owned()]);
+        assert_eq!(completions[0].replace, "ali");
+        assert_eq!(completions[0].append, "'Alice Smith'");
+        assert_eq!(completions[0].matched, [1, 2, 3]);
+    }
+
     #[test]
     fn test_last_part_of_shell_string() {
         insta::assert_debug_snapshot!(last_part_of_shell_string("abc"));
@@ -2041,4 +7302,784 @@ mod tests {
         insta::assert_debug_snapshot!(IndexOrString::from_str("/test/"));
         insta::assert_debug_snapshot!(IndexOrString::from_str("12/test/"));
     }
+
+    #[test]
+    fn test_mute_duration_from_str() {
+        insta::assert_debug_snapshot!(MuteDuration::from_str("30s"));
+        insta::assert_debug_snapshot!(MuteDuration::from_str("15m"));
+        insta::assert_debug_snapshot!(MuteDuration::from_str("8h"));
+        insta::assert_debug_snapshot!(MuteDuration::from_str("1w"));
+        insta::assert_debug_snapshot!(MuteDuration::from_str("10"));
+        insta::assert_debug_snapshot!(MuteDuration::from_str("h"));
+    }
+
+    #[test]
+    fn test_find_mentions() {
+        let candidates = [
+            ("Alice".to_owned(), vec![1]),
+            ("Alice Smith".to_owned(), vec![2]),
+            ("Bob".to_owned(), vec![3]),
+        ];
+        insta::assert_debug_snapshot!(find_mentions(
+            "@Alice Smith and @Bob, not alice@Bob or @Bobby 👋 @Alice",
+            &candidates
+        ));
+    }
+
+    #[test]
+    fn test_reaction_key() {
+        assert_eq!(reaction_key("thumbsup", false).as_deref(), Some("👍"));
+        assert_eq!(reaction_key(":thumbsup:", false).as_deref(), Some("👍"));
+        assert_eq!(reaction_key("👍", false).as_deref(), Some("👍"));
+        // a family, joined with zero width joiners
+        let family = "👩\u{200d}👩\u{200d}👧";
+        assert_eq!(reaction_key(family, false).as_deref(), Some(family));
+        assert_eq!(reaction_key("nice one", false), None);
+        assert_eq!(reaction_key("nice one", true).as_deref(), Some("nice one"));
+        assert_eq!(reaction_key(" ", true), None);
+    }
+
+    #[test]
+    fn test_check_limits() {
+        let tui_state = TuiState {
+            backend_name: "test".to_owned(),
+            limits: crate::backends::Limits {
+                max_body_length: Some(5),
+                max_attachment_size: Some(1000),
+                max_attachment_count: Some(1),
+                any_reaction: false,
+            },
+            ..Default::default()
+        };
+        let attachment = |name: &str, size| MessageAttachment {
+            name: name.to_owned(),
+            size,
+            index: 0,
+            path: None,
+            content_type: None,
+        };
+        insta::assert_debug_snapshot!([
+            check_limits(&tui_state, "hello", &[attachment("a.jpg", 1000)]),
+            check_limits(&tui_state, "hello!", &[]),
+            check_limits(
+                &tui_state,
+                "",
+                &[attachment("a.jpg", 1), attachment("b.jpg", 1)]
+            ),
+            check_limits(&tui_state, "", &[attachment("big.mp4", 1001)]),
+        ]);
+        // backends without limits take anything
+        let tui_state = TuiState::default();
+        assert!(check_limits(
+            &tui_state,
+            &"x".repeat(100_000),
+            &[attachment("big.mp4", u64::MAX)]
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_join_command_lines() {
+        assert_eq!(
+            join_command_lines("send-message\n  'hello there'  \n\n--quote 3\n"),
+            "send-message 'hello there' --quote 3"
+        );
+        assert_eq!(join_command_lines("quit"), "quit");
+        assert_eq!(join_command_lines("\n"), "");
+    }
+
+    #[test]
+    fn test_reply_privately() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let group = ContactId::Group(vec![9]);
+        let mut tui_state = TuiState {
+            self_id: vec![1],
+            ..Default::default()
+        };
+        tui_state
+            .contacts
+            .extend([contact(group.clone(), "Climbing")]);
+        tui_state.contacts.state.select(Some(0));
+        tui_state.messages.extend([
+            text_message(&group, &[1], 10, "hello"),
+            text_message(&group, &[2], 20, "hello"),
+        ]);
+
+        tui_state.messages.select_message(10);
+        assert!(ReplyPrivately.execute(&mut tui_state, &ba_tx).is_err());
+
+        // starts a conversation with them, quoting the group message
+        tui_state.messages.select_message(20);
+        ReplyPrivately.execute(&mut tui_state, &ba_tx).unwrap();
+        let selected = tui_state.contacts.selected().unwrap();
+        assert_eq!(selected.id, ContactId::User(vec![2]));
+        assert_eq!(tui_state.compose.quote().as_ref().unwrap().timestamp, 20);
+
+        // only from groups
+        assert!(ReplyPrivately.execute(&mut tui_state, &ba_tx).is_err());
+    }
+
+    #[test]
+    fn test_verify_device() {
+        let (ba_tx, mut ba_rx) = mpsc::unbounded();
+        let mut tui_state = TuiState::default();
+
+        VerifyDevice.execute(&mut tui_state, &ba_tx).unwrap();
+        assert!(matches!(ba_rx.try_recv(), Ok(BackendMessage::VerifyDevice)));
+        assert!(matches!(tui_state.mode, Mode::Popup));
+        assert!(matches!(
+            tui_state.device_verification,
+            Some(DeviceVerification::Requested)
+        ));
+
+        // nothing to answer until the emoji are shown
+        assert!(ConfirmVerification.execute(&mut tui_state, &ba_tx).is_err());
+        assert!(ba_rx.try_recv().is_err());
+
+        tui_state.device_verification = Some(DeviceVerification::Compare {
+            other_device: "phone".to_owned(),
+            emoji: vec!["🐶 Dog".to_owned()],
+        });
+        RejectVerification.execute(&mut tui_state, &ba_tx).unwrap();
+        assert!(matches!(
+            ba_rx.try_recv(),
+            Ok(BackendMessage::ConfirmVerification { confirmed: false })
+        ));
+        ConfirmVerification.execute(&mut tui_state, &ba_tx).unwrap();
+        assert!(matches!(
+            ba_rx.try_recv(),
+            Ok(BackendMessage::ConfirmVerification { confirmed: true })
+        ));
+    }
+
+    #[test]
+    fn test_split() {
+        let (ba_tx, mut ba_rx) = mpsc::unbounded();
+        let alice = ContactId::User(vec![1]);
+        let bob = ContactId::User(vec![2]);
+        let mut tui_state = TuiState::default();
+        tui_state
+            .contacts
+            .extend([contact(alice.clone(), "Alice"), contact(bob.clone(), "Bob")]);
+        tui_state.contacts.state.select(Some(0));
+        tui_state
+            .messages
+            .extend([text_message(&alice, &[1], 10, "hello")]);
+        tui_state.compose.set_text(vec!["for alice".to_owned()]);
+
+        assert!(SplitFocus.execute(&mut tui_state, &ba_tx).is_err());
+        let vsplit = |name: &str| VSplit {
+            item: IndexOrString::Str(name.to_owned()),
+        };
+        assert!(vsplit("Alice").execute(&mut tui_state, &ba_tx).is_err());
+
+        // the new conversation is focused, with alice's moved aside along with her draft
+        vsplit("Bob").execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(tui_state.contacts.selected().unwrap().id, bob);
+        assert!(tui_state.messages.is_empty());
+        assert!(tui_state.compose.lines().iter().all(|l| l.is_empty()));
+        let split = tui_state.split.as_ref().unwrap();
+        assert_eq!(split.contact_id, alice);
+        assert_eq!(split.messages.len(), 1);
+        assert_eq!(split.compose.lines(), ["for alice"]);
+        assert!(matches!(
+            ba_rx.try_recv(),
+            Ok(BackendMessage::LoadMessages { contact_id, .. }) if contact_id == bob
+        ));
+
+        SplitFocus.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(tui_state.contacts.selected().unwrap().id, alice);
+        assert_eq!(tui_state.messages.len(), 1);
+        assert_eq!(tui_state.compose.lines(), ["for alice"]);
+        let split = tui_state.split.as_ref().unwrap();
+        assert_eq!(split.contact_id, bob);
+        assert!(!split.on_left);
+
+        CloseSplit.execute(&mut tui_state, &ba_tx).unwrap();
+        assert!(tui_state.split.is_none());
+        assert!(CloseSplit.execute(&mut tui_state, &ba_tx).is_err());
+    }
+
+    #[test]
+    fn test_tabs() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let alice = ContactId::User(vec![1]);
+        let bob = ContactId::User(vec![2]);
+        let mut tui_state = TuiState::default();
+        tui_state
+            .contacts
+            .extend([contact(alice.clone(), "Alice"), contact(bob.clone(), "Bob")]);
+        tui_state.contacts.state.select(Some(0));
+        tui_state.messages.extend([
+            text_message(&alice, &[1], 10, "hello"),
+            text_message(&alice, &[1], 20, "hello"),
+        ]);
+        tui_state.messages.select_message(10);
+        tui_state.compose.set_text(vec!["for alice".to_owned()]);
+
+        assert!(TabNext { count: 1 }
+            .execute(&mut tui_state, &ba_tx)
+            .is_err());
+        let tab_open = TabOpen {
+            item: IndexOrString::Str("Bob".to_owned()),
+        };
+        tab_open.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(tui_state.tabs.len(), 2);
+        assert_eq!(tui_state.contacts.selected().unwrap().id, bob);
+        assert!(tui_state.messages.is_empty());
+
+        // the selected message and draft are as they were left
+        TabNext { count: 1 }
+            .execute(&mut tui_state, &ba_tx)
+            .unwrap();
+        assert_eq!(tui_state.contacts.selected().unwrap().id, alice);
+        assert_eq!(tui_state.messages.selected().unwrap().timestamp, 10);
+        assert_eq!(tui_state.compose.lines(), ["for alice"]);
+
+        // opening one already open switches to it
+        tab_open.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(tui_state.tabs.len(), 2);
+        assert_eq!(tui_state.tabs.active(), 1);
+
+        TabClose.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(tui_state.contacts.selected().unwrap().id, alice);
+        TabClose.execute(&mut tui_state, &ba_tx).unwrap();
+        assert!(tui_state.tabs.is_empty());
+        assert!(TabClose.execute(&mut tui_state, &ba_tx).is_err());
+    }
+
+    #[test]
+    fn test_find() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let alice = ContactId::User(vec![1]);
+        let mut tui_state = TuiState::default();
+        let message = |timestamp, text: &str| crate::backends::Message {
+            content: MessageContent::Text {
+                text: text.to_owned(),
+                attachments: Vec::new(),
+                mentions: Vec::new(),
+                formatting: Vec::new(),
+            },
+            ..text_message(&alice, &[1], timestamp, "hello")
+        };
+        tui_state.messages.extend([
+            message(10, "hello"),
+            message(20, "lunch?"),
+            message(30, "Hello again"),
+            message(40, "ok"),
+        ]);
+        tui_state.messages.state.select_last();
+        let selected = |tui_state: &TuiState| tui_state.messages.selected().unwrap().timestamp;
+
+        let find = |query: &str| Find {
+            query: query.to_owned(),
+        };
+        find("HELLO").execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(selected(&tui_state), 30);
+        FindNext { count: 1 }
+            .execute(&mut tui_state, &ba_tx)
+            .unwrap();
+        assert_eq!(selected(&tui_state), 10);
+        // wrapping around
+        FindNext { count: 1 }
+            .execute(&mut tui_state, &ba_tx)
+            .unwrap();
+        assert_eq!(selected(&tui_state), 30);
+        FindPrev { count: 1 }
+            .execute(&mut tui_state, &ba_tx)
+            .unwrap();
+        assert_eq!(selected(&tui_state), 10);
+
+        assert!(find("dinner").execute(&mut tui_state, &ba_tx).is_err());
+        find("").execute(&mut tui_state, &ba_tx).unwrap();
+        assert!(tui_state.find.is_none());
+        assert!(FindNext { count: 1 }
+            .execute(&mut tui_state, &ba_tx)
+            .is_err());
+    }
+
+    #[test]
+    fn test_goto_mention() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let alice = ContactId::User(vec![1]);
+        let mut tui_state = TuiState {
+            self_id: vec![9],
+            ..Default::default()
+        };
+        let message = |timestamp, mentioned: Option<u8>| crate::backends::Message {
+            content: MessageContent::Text {
+                text: "hi @someone".to_owned(),
+                attachments: Vec::new(),
+                mentions: mentioned
+                    .map(|id| Mention {
+                        start: 3,
+                        length: 8,
+                        id: vec![id],
+                    })
+                    .into_iter()
+                    .collect(),
+                formatting: Vec::new(),
+            },
+            ..text_message(&alice, &[1], timestamp, "hello")
+        };
+        tui_state.messages.extend([
+            message(10, Some(9)),
+            message(20, None),
+            message(30, Some(2)),
+            message(40, None),
+        ]);
+        // mentioned in an edit
+        tui_state.messages.add_single(crate::backends::Message {
+            content: MessageContent::Edit {
+                timestamp: 50,
+                text: "hi @me".to_owned(),
+                mentions: vec![Mention {
+                    start: 3,
+                    length: 3,
+                    id: vec![9],
+                }],
+                formatting: Vec::new(),
+            },
+            ..text_message(&alice, &[1], 20, "hello")
+        });
+        tui_state.messages.state.select_last();
+        let selected = |tui_state: &TuiState| tui_state.messages.selected().unwrap().timestamp;
+
+        let mut goto = GotoMention::default();
+        goto.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(selected(&tui_state), 20);
+        goto.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(selected(&tui_state), 10);
+        // wrapping around
+        goto.set_count(2);
+        goto.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(selected(&tui_state), 10);
+
+        tui_state.self_id = vec![8];
+        assert!(goto.execute(&mut tui_state, &ba_tx).is_err());
+    }
+
+    #[test]
+    fn test_record() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let mut tui_state = TuiState::default();
+
+        assert!(run_command_line(&mut tui_state, &ba_tx, "record").is_err());
+        run_command_line(&mut tui_state, &ba_tx, "record a").unwrap();
+        assert_eq!(tui_state.macros.recording(), Some("a"));
+        // the `:` starting the command line stopping it is left out
+        for key in "j:".parse::<crate::keybinds::KeyEvents>().unwrap().0 {
+            tui_state.macros.record(key, true);
+        }
+        // stopping whatever the register
+        run_command_line(&mut tui_state, &ba_tx, "record b").unwrap();
+        assert_eq!(tui_state.macros.recording(), None);
+
+        let mut play = Play::default();
+        play.parse(pico_args::Arguments::from_vec(vec!["a".into(), "3".into()]))
+            .unwrap();
+        play.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(tui_state.macros.take_pending().unwrap().to_string(), "jjj");
+        assert!(run_command_line(&mut tui_state, &ba_tx, "play b").is_err());
+    }
+
+    #[test]
+    fn test_undo() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let alice = ContactId::User(vec![1]);
+        let mut tui_state = TuiState::default();
+        tui_state.contacts.extend([contact(alice.clone(), "Alice")]);
+        tui_state.contacts.state.select(Some(0));
+
+        tui_state
+            .compose
+            .set_text(vec!["half a thought".to_owned()]);
+        ClearCompose.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(tui_state.compose.lines(), [""]);
+        Undo.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(tui_state.compose.lines(), ["half a thought"]);
+        Redo.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(tui_state.compose.lines(), [""]);
+        assert!(Redo.execute(&mut tui_state, &ba_tx).is_err());
+
+        MuteContact { duration: None }
+            .execute(&mut tui_state, &ba_tx)
+            .unwrap();
+        assert!(tui_state.mutes.is_muted(&alice, timestamp()));
+        Undo.execute(&mut tui_state, &ba_tx).unwrap();
+        assert!(!tui_state.mutes.is_muted(&alice, timestamp()));
+        Redo.execute(&mut tui_state, &ba_tx).unwrap();
+        assert!(tui_state.mutes.is_muted(&alice, timestamp()));
+
+        // the draft is only put back in its own conversation
+        Undo.execute(&mut tui_state, &ba_tx).unwrap();
+        tui_state.contacts.state.select(None);
+        assert!(Undo.execute(&mut tui_state, &ba_tx).is_err());
+        tui_state.contacts.state.select(Some(0));
+        Undo.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(tui_state.compose.lines(), ["half a thought"]);
+    }
+
+    #[test]
+    fn test_undo_overwritten_drafts() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let alice = ContactId::User(vec![1]);
+        let bob = ContactId::User(vec![2]);
+        let mut tui_state = TuiState::default();
+        tui_state
+            .contacts
+            .extend([contact(alice.clone(), "Alice"), contact(bob.clone(), "Bob")]);
+        tui_state.contacts.state.select(Some(0));
+        tui_state
+            .messages
+            .extend([text_message(&alice, &[1], 10, "hello")]);
+        tui_state.messages.select_message(10);
+
+        // inserting piped output
+        tui_state.compose.insert_str("said ");
+        let mut pipe = PipeMessage::default();
+        pipe.parse(pico_args::Arguments::from_vec(vec![
+            "--insert".into(),
+            "cat".into(),
+        ]))
+        .unwrap();
+        pipe.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(tui_state.compose.lines(), ["said hello"]);
+        Undo.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(tui_state.compose.lines(), ["said "]);
+
+        // replying to another message instead
+        Reply.execute(&mut tui_state, &ba_tx).unwrap();
+        tui_state
+            .messages
+            .extend([text_message(&alice, &[1], 20, "hello")]);
+        tui_state.messages.select_message(20);
+        Reply.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(tui_state.compose.quote().as_ref().unwrap().timestamp, 20);
+        Undo.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(tui_state.compose.quote().as_ref().unwrap().timestamp, 10);
+
+        // closing the split drops the draft of the other pane
+        VSplit {
+            item: IndexOrString::Str("Bob".to_owned()),
+        }
+        .execute(&mut tui_state, &ba_tx)
+        .unwrap();
+        tui_state.compose.set_text(vec!["for bob".to_owned()]);
+        SplitFocus.execute(&mut tui_state, &ba_tx).unwrap();
+        CloseSplit.execute(&mut tui_state, &ba_tx).unwrap();
+        tui_state.contacts.state.select(Some(1));
+        tui_state.compose.clear();
+        Undo.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(tui_state.compose.lines(), ["for bob"]);
+    }
+
+    #[test]
+    fn test_export_messages_shows_path() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let alice = ContactId::User(vec![1]);
+        let mut tui_state = TuiState::default();
+        tui_state
+            .messages
+            .extend([text_message(&alice, &[1], 10, "hello")]);
+        tui_state.messages.select_message(10);
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("messages.txt");
+
+        let export = ExportMessages {
+            path: path.to_string_lossy().into_owned(),
+        };
+        let Ok(CommandSuccess::ShowPopup { title, text }) = export.execute(&mut tui_state, &ba_tx)
+        else {
+            panic!("expected a popup");
+        };
+        assert_eq!(text, format!("Wrote 1 message to {}", path.display()));
+        assert!(std::fs::read_to_string(&path).unwrap().contains("hello"));
+
+        show_popup(&mut tui_state, title, text);
+        assert!(matches!(tui_state.mode, Mode::Popup));
+        assert!(matches!(
+            tui_state.popup.as_ref().map(|p| &p.typ),
+            Some(PopupType::Output { .. })
+        ));
+    }
+
+    #[test]
+    fn test_pipe_message_output() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let alice = ContactId::User(vec![1]);
+        let mut tui_state = TuiState::default();
+        tui_state
+            .messages
+            .extend([text_message(&alice, &[1], 10, "hello")]);
+        tui_state.messages.select_message(10);
+        let pipe = |args: &[&str]| {
+            let mut pipe = PipeMessage::default();
+            pipe.parse(pico_args::Arguments::from_vec(
+                args.iter().map(|a| a.into()).collect(),
+            ))
+            .unwrap();
+            pipe
+        };
+
+        let result = pipe(&["--show", "tr", "a-z", "A-Z"]).execute(&mut tui_state, &ba_tx);
+        let Ok(CommandSuccess::ShowPopup { text, .. }) = result else {
+            panic!("expected a popup");
+        };
+        assert_eq!(text, "HELLO");
+
+        pipe(&["--insert", "rev"])
+            .execute(&mut tui_state, &ba_tx)
+            .unwrap();
+        assert_eq!(tui_state.compose.lines(), ["olleh"]);
+
+        assert!(pipe(&["--insert", "false"])
+            .execute(&mut tui_state, &ba_tx)
+            .is_err());
+    }
+
+    #[test]
+    fn test_pipe_attachment() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let alice = ContactId::User(vec![1]);
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("scan.txt");
+        std::fs::write(&path, "scanned text\n").unwrap();
+        let attachment = |path: Option<PathBuf>| MessageAttachment {
+            name: "scan.txt".to_owned(),
+            index: 0,
+            size: 13,
+            path,
+            content_type: None,
+        };
+        let mut tui_state = TuiState::default();
+        tui_state.messages.extend([crate::backends::Message {
+            content: MessageContent::Text {
+                text: String::new(),
+                attachments: vec![attachment(Some(path)), attachment(None)],
+                mentions: Vec::new(),
+                formatting: Vec::new(),
+            },
+            ..text_message(&alice, &[1], 10, "hello")
+        }]);
+        tui_state.messages.select_message(10);
+        let pipe = |args: &[&str]| {
+            let mut pipe = PipeAttachment::default();
+            pipe.parse(pico_args::Arguments::from_vec(
+                args.iter().map(|a| a.into()).collect(),
+            ))
+            .map(|()| pipe)
+        };
+
+        let result = pipe(&["--show", "0", "cat"])
+            .unwrap()
+            .execute(&mut tui_state, &ba_tx);
+        let Ok(CommandSuccess::ShowPopup { text, .. }) = result else {
+            panic!("expected a popup");
+        };
+        assert_eq!(text, "scanned text");
+
+        // not downloaded
+        assert!(pipe(&["--show", "1", "cat"])
+            .unwrap()
+            .execute(&mut tui_state, &ba_tx)
+            .is_err());
+        assert!(pipe(&["0"]).is_err());
+
+        let completions = |args: &str| {
+            PipeAttachment::default()
+                .complete(&tui_state, args)
+                .into_iter()
+                .map(|c| c.display)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(completions("pipe-attachment "), ["0"]);
+        assert!(completions("pipe-attachment 0 ").is_empty());
+    }
+
+    #[test]
+    fn test_run_editor_with_arguments() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("draft.txt");
+        std::fs::write(&path, "teh draft").unwrap();
+        assert!(run_editor("sed -i s/teh/the/", &path));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "the draft");
+        assert!(!run_editor("false", &path));
+    }
+
+    #[test]
+    fn test_help() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let mut tui_state = TuiState::default();
+        tui_state.config.keybinds = toml::from_str(
+            r#"
+            [normal]
+            "<A-e>" = ":edit-command<Enter>"
+            "#,
+        )
+        .unwrap();
+
+        let help = |command: &str| Help {
+            command: Some(command.to_owned()),
+        };
+        let Ok(CommandSuccess::ShowPopup { title, text }) =
+            help(":edit-command-line").execute(&mut tui_state, &ba_tx)
+        else {
+            panic!("expected a popup");
+        };
+        assert_eq!(title, ":edit-command-line");
+        assert_eq!(
+            text,
+            ":edit-command\n\
+             Write a command in $VISUAL or $EDITOR, starting from the one being typed\n\
+             Also :edit-command-line\n\
+             \n\
+             <A-e> in normal mode"
+        );
+        assert!(help("not-a-command")
+            .execute(&mut tui_state, &ba_tx)
+            .is_err());
+
+        // every command says what it does
+        for command in commands() {
+            assert!(!command.help().description.is_empty(), "{command:?}");
+        }
+    }
+
+    #[test]
+    fn test_nickname() {
+        let (ba_tx, _ba_rx) = mpsc::unbounded();
+        let alice = ContactId::User(vec![1]);
+        let mut tui_state = TuiState::default();
+        tui_state.contacts.extend([
+            contact(ContactId::User(vec![2]), "Bob"),
+            contact(alice.clone(), "Alice"),
+        ]);
+        tui_state.contacts.state.select(Some(1));
+
+        let mut nickname = Nickname::default();
+        nickname
+            .parse(pico_args::Arguments::from_vec(vec![
+                "Big".into(),
+                "Al".into(),
+            ]))
+            .unwrap();
+        nickname.execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(tui_state.nicknames.get(&alice), Some("Big Al"));
+
+        // found by either name
+        assert_eq!(
+            contact_index(&tui_state, &IndexOrString::Str("Big".to_owned())).unwrap(),
+            1
+        );
+        assert_eq!(
+            contact_index(&tui_state, &IndexOrString::Str("Ali".to_owned())).unwrap(),
+            1
+        );
+
+        Nickname::default().execute(&mut tui_state, &ba_tx).unwrap();
+        assert_eq!(tui_state.nicknames.get(&alice), None);
+    }
+
+    #[test]
+    fn test_set_group_title() {
+        let (ba_tx, mut ba_rx) = mpsc::unbounded();
+        let group = ContactId::Group(vec![9]);
+        let mut tui_state = TuiState::default();
+        tui_state.contacts.extend([
+            contact(ContactId::User(vec![1]), "Alice"),
+            contact(group.clone(), "Lunch"),
+        ]);
+
+        let mut set_title = SetGroupTitle::default();
+        set_title
+            .parse(pico_args::Arguments::from_vec(vec![
+                "Long".into(),
+                "lunch".into(),
+            ]))
+            .unwrap();
+        tui_state.contacts.state.select(Some(0));
+        assert!(set_title.execute(&mut tui_state, &ba_tx).is_err());
+        assert!(ba_rx.try_recv().is_err());
+
+        tui_state.contacts.state.select(Some(1));
+        set_title.execute(&mut tui_state, &ba_tx).unwrap();
+        assert!(matches!(
+            ba_rx.try_recv(),
+            Ok(BackendMessage::SetGroupTitle { contact_id, title })
+                if contact_id == group && title == "Long lunch"
+        ));
+
+        // the description can be cleared, but not the title
+        assert!(matches!(
+            SetGroupTitle::default().execute(&mut tui_state, &ba_tx),
+            Err(Error::MissingArgument(_))
+        ));
+        SetGroupDescription::default()
+            .execute(&mut tui_state, &ba_tx)
+            .unwrap();
+        assert!(matches!(
+            ba_rx.try_recv(),
+            Ok(BackendMessage::SetGroupDescription { description, .. }) if description.is_empty()
+        ));
+    }
+
+    #[test]
+    fn test_answer_invite() {
+        let (ba_tx, mut ba_rx) = mpsc::unbounded();
+        let invite = |id: u8, name: &str| Invite {
+            contact_id: ContactId::Group(vec![id]),
+            name: name.to_owned(),
+            inviter: "Alice".to_owned(),
+        };
+        let mut tui_state = TuiState {
+            invites: vec![invite(1, "Climbing"), invite(2, "Book club")],
+            ..Default::default()
+        };
+
+        let mut decline = DeclineInvite::default();
+        decline
+            .parse(pico_args::Arguments::from_vec(vec![
+                "book".into(),
+                "CLUB".into(),
+            ]))
+            .unwrap();
+        decline.execute(&mut tui_state, &ba_tx).unwrap();
+        assert!(matches!(
+            ba_rx.try_recv(),
+            Ok(BackendMessage::AnswerInvite { contact_id, accept: false })
+                if contact_id == ContactId::Group(vec![2])
+        ));
+        assert!(matches!(
+            decline.execute(&mut tui_state, &ba_tx),
+            Err(Error::InvalidArgument { .. })
+        ));
+
+        // the first without a name
+        AcceptInvite::default()
+            .execute(&mut tui_state, &ba_tx)
+            .unwrap();
+        assert!(matches!(
+            ba_rx.try_recv(),
+            Ok(BackendMessage::AnswerInvite { contact_id, accept: true })
+                if contact_id == ContactId::Group(vec![1])
+        ));
+        assert!(tui_state.invites.is_empty());
+        assert!(AcceptInvite::default()
+            .execute(&mut tui_state, &ba_tx)
+            .is_err());
+    }
+
+    #[test]
+    fn test_link_at() {
+        let text = "日本 see https://example.com/a?b=c or chatters.dev";
+        assert_eq!(link_at(text, 0), None);
+        // the wide characters take two cells each
+        assert_eq!(
+            link_at(text, 9).as_deref(),
+            Some("https://example.com/a?b=c")
+        );
+        assert_eq!(link_at(text, 40).as_deref(), Some("chatters.dev"));
+        assert_eq!(link_at(text, 200), None);
+    }
 }
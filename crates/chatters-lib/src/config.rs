@@ -1,7 +1,685 @@
-use crate::{hooks::Hooks, keybinds::KeyBinds};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use ratatui::style::Color;
+
+use crate::{bridges::BridgePatterns, downloads::Downloads, hooks::Hooks, keybinds::KeyBinds};
+
+/// The config written on first run, with the default keybinds and examples of the other options.
+pub const DEFAULT_CONFIG: &str = include_str!("../../../config.toml");
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {path:?}: {error}")]
+    Read {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    #[error("Failed to create default config file {path:?}: {error}")]
+    Create {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    #[error("Malformed config file {path:?} at line {line}, column {column}: {message}")]
+    Syntax {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    #[error("Invalid `{key}` in config file {path:?}{}: {message}", at_line(*.line))]
+    Invalid {
+        path: PathBuf,
+        key: String,
+        line: Option<usize>,
+        message: String,
+    },
+}
 
 #[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Config {
+    #[serde(default)]
     pub hooks: Hooks,
+    #[serde(default)]
     pub keybinds: KeyBinds,
+    /// Plugins to launch on startup, mapping a name to the command to run.
+    #[serde(default)]
+    pub plugins: BTreeMap<String, String>,
+    #[serde(default)]
+    pub downloads: Downloads,
+    /// How conversations move in the contacts list when messages arrive.
+    #[serde(default)]
+    pub contact_order: ContactOrder,
+    /// How conversations are sorted within each folder in the contacts list.
+    #[serde(default)]
+    pub contact_sort: ContactSort,
+    /// Folders to group conversations under in the contacts list, mapping a folder's name to the
+    /// names of the conversations in it.
+    #[serde(default)]
+    pub folders: BTreeMap<String, Vec<String>>,
+    /// Which colours to use, following the terminal's background by default.
+    #[serde(default)]
+    pub theme: Theme,
+    /// Shell commands to pass message text through before showing it, in order.
+    #[serde(default)]
+    pub render_filters: Vec<String>,
+    /// A spell checker speaking the ispell pipe protocol, such as `hunspell -a`.
+    #[serde(default)]
+    pub spellcheck: Option<String>,
+    /// How messages are laid out in the list.
+    #[serde(default)]
+    pub message_layout: MessageLayout,
+    /// How the time of each message is shown in the list.
+    #[serde(default)]
+    pub timestamps: Timestamps,
+    /// Where the user's own messages are shown, to tell them apart from those received.
+    #[serde(default)]
+    pub own_messages: OwnMessages,
+    /// Colours for the names of senders, to tell them apart in group conversations.
+    #[serde(default)]
+    pub sender_colors: SenderColors,
+    /// Patterns for messages relayed by bridge bots, replacing the built-in ones.
+    #[serde(default)]
+    pub bridges: BridgePatterns,
+    /// Don't show the summary of the selected conversation above its messages.
+    #[serde(default)]
+    pub hide_header: bool,
+    /// The share of the width taken by the contacts pane, as a percentage, until resized at
+    /// runtime.
+    #[serde(default)]
+    pub contacts_width: Option<u16>,
+    /// Start with the contacts pane hidden, until toggled at runtime.
+    #[serde(default)]
+    pub hide_contacts: bool,
+    /// Show the latest message of each conversation under its name in the contacts list.
+    #[serde(default)]
+    pub contact_previews: bool,
+    /// Ask the backend to sync the contacts every this many minutes, as with `:sync-contacts`.
+    #[serde(default)]
+    pub contact_sync_minutes: Option<u64>,
+    /// Run by `:call-back` to call the selected conversation, given its name and address in
+    /// `CHATTERS_CONTACT_NAME` and `CHATTERS_CONTACT_ADDRESS` and any link to the latest call in
+    /// it in `CHATTERS_CALL_LINK`. Without it the link is opened.
+    #[serde(default)]
+    pub call_command: Option<String>,
+    /// Leave the mouse to the terminal, such as for selecting text, rather than clicking and
+    /// scrolling in the ui, only read on startup.
+    #[serde(default)]
+    pub disable_mouse: bool,
+    /// Send the Markdown written in the compose box as formatting.
+    #[serde(default)]
+    pub markdown: bool,
+    /// What to show in the status line, with placeholders such as `{mode}` and `{contact}`,
+    /// instead of the default layout.
+    #[serde(default)]
+    pub status_format: Option<String>,
+    /// Where to keep the backend's data and other state instead of the platform's default, only
+    /// read on startup.
+    #[serde(default)]
+    pub data_dir: Option<PathBuf>,
+    /// Keep the backend's data encrypted with a passphrase while not running, asking for it on
+    /// startup, along with the local state and downloaded attachments.
+    #[serde(default)]
+    pub encrypt: bool,
+    /// Let backends keep secrets, such as passphrases and access tokens, in plaintext files when
+    /// the system keyring can't be used.
+    #[serde(default)]
+    pub plaintext_secrets: bool,
+}
+
+impl Config {
+    /// Load the config from the file, writing the default config there first if it doesn't exist.
+    ///
+    /// Also returns warnings about anything in the file that was ignored or replaced by its default.
+    pub fn load(path: &Path) -> Result<(Self, Vec<String>), ConfigError> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                let create = |error| ConfigError::Create {
+                    path: path.to_owned(),
+                    error,
+                };
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent).map_err(create)?;
+                }
+                std::fs::write(path, DEFAULT_CONFIG).map_err(create)?;
+                let (config, mut warnings) = Self::parse(path, DEFAULT_CONFIG)?;
+                warnings.insert(0, format!("Created a default config file at {path:?}"));
+                return Ok((config, warnings));
+            }
+            Err(error) => {
+                return Err(ConfigError::Read {
+                    path: path.to_owned(),
+                    error,
+                })
+            }
+        };
+        Self::parse(path, &content)
+    }
+
+    /// Parse the content of the config file at the path.
+    ///
+    /// Sections which are missing, such as `keybinds.compose`, are taken from the default config,
+    /// as are invalid values that have a default there. Other invalid values and unknown keys are
+    /// ignored.
+    pub fn parse(path: &Path, content: &str) -> Result<(Self, Vec<String>), ConfigError> {
+        let mut table: toml::Table = content.parse().map_err(|error: toml::de::Error| {
+            let (line, column) = error
+                .span()
+                .map_or((1, 1), |span| line_column(content, span.start));
+            ConfigError::Syntax {
+                path: path.to_owned(),
+                line,
+                column,
+                message: error.message().trim().replace('\n', ", "),
+            }
+        })?;
+        let defaults: toml::Table = DEFAULT_CONFIG
+            .parse()
+            .expect("default config should be valid toml");
+        fill_missing(&mut table, &defaults);
+
+        let mut warnings = Vec::new();
+        let mut replaced = Vec::new();
+        loop {
+            let mut ignored = Vec::new();
+            let mut on_ignored = |key: serde_ignored::Path| ignored.push(key.to_string());
+            let deserializer = serde_ignored::Deserializer::new(
+                toml::Value::Table(table.clone()),
+                &mut on_ignored,
+            );
+            let error = match serde_path_to_error::deserialize::<_, Self>(deserializer) {
+                Ok(config) => {
+                    for key in ignored {
+                        let line = key_line(content, &key.split('.').collect::<Vec<_>>());
+                        warnings.push(format!(
+                            "Ignoring unknown config key `{key}`{}",
+                            at_line(line)
+                        ));
+                    }
+                    return Ok((config, warnings));
+                }
+                Err(error) => error,
+            };
+
+            let key: Vec<_> = error
+                .path()
+                .iter()
+                .filter_map(|segment| match segment {
+                    serde_path_to_error::Segment::Map { key } => Some(key.clone()),
+                    serde_path_to_error::Segment::Seq { index } => Some(index.to_string()),
+                    _ => None,
+                })
+                .collect();
+            let line = key_line(content, &key);
+            let message = error.inner().message().trim().to_owned();
+            if key.is_empty() || replaced.contains(&key) {
+                return Err(ConfigError::Invalid {
+                    path: path.to_owned(),
+                    key: key.join("."),
+                    line,
+                    message,
+                });
+            }
+            // fall back to the default if there is one, otherwise leave it unset
+            let default = lookup(&defaults, &key).cloned();
+            warnings.push(format!(
+                "Invalid `{}`{}: {message}, {}",
+                key.join("."),
+                at_line(line),
+                if default.is_some() {
+                    "using the default"
+                } else {
+                    "ignoring it"
+                }
+            ));
+            replace(&mut table, &key, default);
+            replaced.push(key);
+        }
+    }
+}
+
+/// Set the data directory in the config file, leaving the rest of the file as it is.
+pub fn save_data_dir(path: &Path, data_dir: &Path) -> std::io::Result<()> {
+    let content = std::fs::read_to_string(path)?;
+    let data_dir = toml::Value::String(data_dir.to_string_lossy().into_owned());
+    // top-level keys have to come before any tables
+    let mut new = format!("data_dir = {data_dir}\n");
+    for line in content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("data_dir"))
+    {
+        new.push_str(line);
+        new.push('\n');
+    }
+    std::fs::write(path, new)
+}
+
+fn at_line(line: Option<usize>) -> String {
+    line.map(|line| format!(" at line {line}"))
+        .unwrap_or_default()
+}
+
+/// The line and column, counting from 1, of a byte offset into the content.
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Add the sections of the defaults that are missing from the table, going into sections which only
+/// hold other sections, such as `keybinds`.
+fn fill_missing(table: &mut toml::Table, defaults: &toml::Table) {
+    for (key, default) in defaults {
+        match (table.get_mut(key), default) {
+            (None, _) => {
+                table.insert(key.clone(), default.clone());
+            }
+            (Some(toml::Value::Table(table)), toml::Value::Table(default))
+                if default.values().all(toml::Value::is_table) =>
+            {
+                fill_missing(table, default);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn lookup<'a>(table: &'a toml::Table, key: &[String]) -> Option<&'a toml::Value> {
+    let (last, parents) = key.split_last()?;
+    let mut table = table;
+    for parent in parents {
+        table = table.get(parent)?.as_table()?;
+    }
+    table.get(last)
+}
+
+/// Set the value of the key, removing it if there is no value.
+fn replace(table: &mut toml::Table, key: &[String], value: Option<toml::Value>) {
+    let Some((last, parents)) = key.split_last() else {
+        return;
+    };
+    let mut table = table;
+    for parent in parents {
+        match table.get_mut(parent).and_then(toml::Value::as_table_mut) {
+            Some(child) => table = child,
+            None => return,
+        }
+    }
+    match value {
+        Some(value) => table.insert(last.clone(), value),
+        None => table.remove(last),
+    };
+}
+
+/// The line that a key is set on, or the line of the closest table holding it, going by the table
+/// headers and keys at the start of lines.
+fn key_line(content: &str, key: &[impl AsRef<str>]) -> Option<usize> {
+    let mut table = Vec::new();
+    let mut closest: Option<(usize, usize)> = None;
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        let path = if let Some(header) = line.strip_prefix('[') {
+            let Some(header) = split_key(header.trim_start_matches('['), ']') else {
+                continue;
+            };
+            table = header;
+            table.clone()
+        } else if let Some(line_key) = split_key(line, '=').filter(|_| !line.starts_with('#')) {
+            table.iter().cloned().chain(line_key).collect()
+        } else {
+            continue;
+        };
+        let depth = path
+            .iter()
+            .zip(key)
+            .take_while(|(a, b)| a.as_str() == b.as_ref())
+            .count();
+        if depth > 0 && depth == path.len() && closest.is_none_or(|(d, _)| depth > d) {
+            closest = Some((depth, i + 1));
+        }
+    }
+    closest.map(|(_, line)| line)
+}
+
+/// Split a key up to the end character into its dotted parts, leaving those quoted as they are.
+fn split_key(s: &str, end: char) -> Option<Vec<String>> {
+    let mut parts = vec![String::new()];
+    let mut quote = None;
+    for c in s.chars() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('.', None) => parts.push(String::new()),
+            (c, None) if c == end => {
+                return Some(parts.iter().map(|p| p.trim().to_owned()).collect());
+            }
+            (c, _) => parts.last_mut()?.push(c),
+        }
+    }
+    None
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContactOrder {
+    /// Move a conversation to the top whenever a message arrives in it.
+    #[default]
+    BumpAlways,
+    /// Only move conversations that aren't muted.
+    BumpUnmuted,
+    /// Keep conversations where they are.
+    Fixed,
+}
+
+impl ContactOrder {
+    pub const NAMES: [&'static str; 3] = ["bump-always", "bump-unmuted", "fixed"];
+}
+
+impl FromStr for ContactOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bump-always" => Ok(Self::BumpAlways),
+            "bump-unmuted" => Ok(Self::BumpUnmuted),
+            "fixed" => Ok(Self::Fixed),
+            _ => Err(format!(
+                "Failed to match {s:?} to one of {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContactSort {
+    /// As the backend lists them, moved as messages arrive following the contact order.
+    #[default]
+    Recent,
+    /// Alphabetically by the name shown.
+    Name,
+    /// Those with unread messages first, otherwise as for recent.
+    Unread,
+}
+
+impl ContactSort {
+    pub const NAMES: [&'static str; 3] = ["recent", "name", "unread"];
+
+    /// The sort after this one, going back to the first after the last.
+    pub fn next(self) -> Self {
+        match self {
+            Self::Recent => Self::Name,
+            Self::Name => Self::Unread,
+            Self::Unread => Self::Recent,
+        }
+    }
+}
+
+impl FromStr for ContactSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "recent" => Ok(Self::Recent),
+            "name" => Ok(Self::Name),
+            "unread" => Ok(Self::Unread),
+            _ => Err(format!(
+                "Failed to match {s:?} to one of {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MessageLayout {
+    /// The sender and age in columns, with the messages lined up beside them.
+    #[default]
+    Aligned,
+    /// The sender and age just before the message, without padding.
+    Compact,
+    /// The sender and age above the message with a blank line after it, the user's own messages
+    /// indented to the right.
+    Cozy,
+}
+
+impl MessageLayout {
+    pub const NAMES: [&'static str; 3] = ["aligned", "compact", "cozy"];
+
+    /// The layout after this one, going back to the first after the last.
+    pub fn next(self) -> Self {
+        match self {
+            Self::Aligned => Self::Compact,
+            Self::Compact => Self::Cozy,
+            Self::Cozy => Self::Aligned,
+        }
+    }
+}
+
+impl FromStr for MessageLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "aligned" => Ok(Self::Aligned),
+            "compact" => Ok(Self::Compact),
+            "cozy" => Ok(Self::Cozy),
+            _ => Err(format!(
+                "Failed to match {s:?} to one of {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Timestamps {
+    /// How long ago it was sent, such as `5m`.
+    #[default]
+    Relative,
+    /// The local time it was sent, or the date if not today.
+    Absolute,
+    Both,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OwnMessages {
+    /// As the message layout places them, on the right when cozy and otherwise with the rest.
+    #[default]
+    Layout,
+    Left,
+    /// Indented to end at the right edge, like speech bubbles.
+    Right,
+    /// With the rest, but with a marker before the sender.
+    Marked,
+}
+
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SenderColors {
+    /// Show every sender in group conversations in the same colour, rather than each in one of
+    /// their own.
+    #[serde(default)]
+    pub uniform: bool,
+    /// The colours to give senders in group conversations on a dark background, instead of the
+    /// built-in ones.
+    #[serde(default)]
+    pub dark: Vec<ConfigColor>,
+    /// The colours to give senders in group conversations on a light background, instead of the
+    /// built-in ones.
+    #[serde(default)]
+    pub light: Vec<ConfigColor>,
+    /// Colours for particular senders by the name shown for them, in any conversation.
+    #[serde(default)]
+    pub names: BTreeMap<String, ConfigColor>,
+}
+
+/// A colour in the config, by name such as `green`, as `#rrggbb` or as an index into the
+/// terminal's palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ConfigColor(pub Color);
+
+impl TryFrom<String> for ConfigColor {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Color::from_str(&s)
+            .map(Self)
+            .map_err(|_| format!("Failed to parse {s:?} as a colour"))
+    }
+}
+
+impl From<ConfigColor> for String {
+    fn from(color: ConfigColor) -> Self {
+        color.0.to_string()
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    /// Pick dark or light colours based on the terminal's background.
+    #[default]
+    Auto,
+    Dark,
+    Light,
+}
+
+impl Theme {
+    pub const NAMES: [&'static str; 3] = ["auto", "dark", "light"];
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "dark" => Ok(Self::Dark),
+            "light" => Ok(Self::Light),
+            _ => Err(format!(
+                "Failed to match {s:?} to one of {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let path = Path::new("config.toml");
+        assert!(Config::parse(path, DEFAULT_CONFIG).unwrap().1.is_empty());
+
+        let content = r#"theme = "blue"
+markdown = true
+
+[hooks]
+on_new_mesage = "notify-send hi"
+
+[keybinds.normal]
+q = ":quit<Enter>"
+x = "not-a-command"
+"#;
+        let (config, warnings) = Config::parse(path, content).unwrap();
+        insta::assert_debug_snapshot!((
+            warnings,
+            config.theme,
+            config.markdown,
+            config.keybinds.normal.len(),
+            !config.keybinds.compose.is_empty(),
+        ));
+
+        let error = Config::parse(path, "markdown = true\n[keybinds\n").unwrap_err();
+        insta::assert_snapshot!("syntax_error", error);
+    }
+
+    #[test]
+    fn test_sender_colors() {
+        let content = r##"[sender_colors]
+uniform = true
+dark = ["red", "10"]
+[sender_colors.names]
+Alice = "green"
+Bob = "#ff8800"
+Carol = "not-a-colour"
+"##;
+        let (config, warnings) = Config::parse(Path::new("config.toml"), content).unwrap();
+        assert!(config.sender_colors.uniform);
+        assert_eq!(
+            config.sender_colors.dark,
+            [ConfigColor(Color::Red), ConfigColor(Color::Indexed(10))]
+        );
+        assert_eq!(
+            config.sender_colors.names.get("Alice"),
+            Some(&ConfigColor(Color::Green))
+        );
+        assert_eq!(
+            config.sender_colors.names.get("Bob"),
+            Some(&ConfigColor(Color::Rgb(0xff, 0x88, 0x00)))
+        );
+        assert!(!config.sender_colors.names.contains_key("Carol"));
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_contact_order_names() {
+        for name in ContactOrder::NAMES {
+            let order: ContactOrder = name.parse().unwrap();
+            let serialized = toml::to_string(&Config {
+                contact_order: order,
+                ..Default::default()
+            })
+            .unwrap();
+            assert!(serialized.contains(&format!("contact_order = \"{name}\"")));
+        }
+        assert_eq!("Fixed".parse(), Ok(ContactOrder::Fixed));
+        assert!("bump".parse::<ContactOrder>().is_err());
+    }
+
+    #[test]
+    fn test_contact_sort_names() {
+        let mut sort = ContactSort::default();
+        for name in ContactSort::NAMES {
+            assert_eq!(name.parse(), Ok(sort));
+            let serialized = toml::to_string(&Config {
+                contact_sort: sort,
+                ..Default::default()
+            })
+            .unwrap();
+            assert!(serialized.contains(&format!("contact_sort = \"{name}\"")));
+            sort = sort.next();
+        }
+        assert_eq!(sort, ContactSort::default());
+    }
+
+    #[test]
+    fn test_message_layout_names() {
+        let mut layout = MessageLayout::default();
+        for name in MessageLayout::NAMES {
+            assert_eq!(name.parse(), Ok(layout));
+            let serialized = toml::to_string(&Config {
+                message_layout: layout,
+                ..Default::default()
+            })
+            .unwrap();
+            assert!(serialized.contains(&format!("message_layout = \"{name}\"")));
+            layout = layout.next();
+        }
+        assert_eq!(layout, MessageLayout::default());
+    }
 }
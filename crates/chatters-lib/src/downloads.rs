@@ -0,0 +1,368 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use log::warn;
+
+use crate::backends::{Contact, ContactId, MessageAttachment};
+
+/// Where to save downloaded attachments, rather than leaving them in the backend's attachments
+/// directory.
+///
+/// File name templates can use `{date}`, `{time}`, `{sender}`, `{contact}` and `{name}`, the
+/// original file name.
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Downloads {
+    /// Directory to save attachments to when a conversation doesn't set one.
+    #[serde(default)]
+    pub dir: Option<String>,
+    /// File name template to use when a conversation doesn't set one.
+    #[serde(default)]
+    pub filename: Option<String>,
+    /// Settings for particular conversations, keyed by contact or group name.
+    #[serde(default)]
+    pub contacts: BTreeMap<String, DownloadRule>,
+    /// Attachments to download as soon as they arrive, those matching any of the rules.
+    #[serde(default)]
+    pub auto: Vec<AutoDownloadRule>,
+}
+
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DownloadRule {
+    #[serde(default)]
+    pub dir: Option<String>,
+    #[serde(default)]
+    pub filename: Option<String>,
+}
+
+/// Attachments to download automatically, matching all of the conditions that are set.
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AutoDownloadRule {
+    /// Largest size to download, in bytes.
+    #[serde(default)]
+    pub max_size: Option<u64>,
+    /// Mime type, such as `image/png`, or all of a type with `image/*`.
+    #[serde(default)]
+    pub mime_type: Option<String>,
+    /// Name of the contact or group the attachment was sent in.
+    #[serde(default)]
+    pub contact: Option<String>,
+}
+
+impl AutoDownloadRule {
+    fn matches(&self, contact: &Contact, attachment: &MessageAttachment) -> bool {
+        let size = self.max_size.is_none_or(|max| attachment.size <= max);
+        let mime_type = self.mime_type.as_ref().is_none_or(|pattern| {
+            attachment
+                .content_type
+                .as_ref()
+                .is_some_and(|content_type| mime_type_matches(pattern, content_type))
+        });
+        let contact = self
+            .contact
+            .as_ref()
+            .is_none_or(|name| *name == contact.name);
+        size && mime_type && contact
+    }
+}
+
+fn mime_type_matches(pattern: &str, content_type: &str) -> bool {
+    // parameters such as `; charset=utf-8` don't matter here
+    let content_type = content_type.split(';').next().unwrap_or_default().trim();
+    match pattern.strip_suffix("/*") {
+        Some(kind) => content_type
+            .split_once('/')
+            .is_some_and(|(k, _)| k.eq_ignore_ascii_case(kind)),
+        None => pattern == "*" || content_type.eq_ignore_ascii_case(pattern),
+    }
+}
+
+impl Downloads {
+    /// Whether to download an attachment as soon as it arrives in the conversation.
+    pub fn auto_download(
+        &self,
+        contact: &Contact,
+        attachment: &MessageAttachment,
+        setting: AutoDownload,
+    ) -> bool {
+        match setting {
+            AutoDownload::Always => true,
+            AutoDownload::Never => false,
+            AutoDownload::Rules => self.auto.iter().any(|r| r.matches(contact, attachment)),
+        }
+    }
+
+    /// Where to save a downloaded attachment, or `None` to leave it where the backend put it.
+    pub fn destination(
+        &self,
+        contact: &Contact,
+        sender: &str,
+        timestamp: u64,
+        file_path: &Path,
+    ) -> Option<PathBuf> {
+        let rule = self.contacts.get(&contact.name);
+        let dir = rule.and_then(|r| r.dir.as_ref()).or(self.dir.as_ref());
+        let filename = rule
+            .and_then(|r| r.filename.as_ref())
+            .or(self.filename.as_ref());
+        if dir.is_none() && filename.is_none() {
+            return None;
+        }
+
+        let name = file_path.file_name()?.to_string_lossy();
+        let dir = match dir {
+            Some(dir) => crate::commands::expand_tilde(dir),
+            None => file_path.parent()?.to_owned(),
+        };
+        let filename = match filename {
+            Some(template) => expand_template(template, &contact.name, sender, timestamp, &name),
+            None => name.into_owned(),
+        };
+        Some(dir.join(filename))
+    }
+}
+
+/// The path, or the first free one made by adding a number to the name like `scan (1).pdf`.
+pub fn unique_path(path: PathBuf) -> PathBuf {
+    if !path.exists() {
+        return path;
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{stem} ({n}){extension}")))
+        .find(|p| !p.exists())
+        .unwrap()
+}
+
+fn expand_template(
+    template: &str,
+    contact: &str,
+    sender: &str,
+    timestamp: u64,
+    name: &str,
+) -> String {
+    let time = chrono::DateTime::from_timestamp_millis(timestamp as i64)
+        .unwrap_or_default()
+        .with_timezone(&chrono::Local);
+    template
+        .replace("{date}", &time.format("%Y-%m-%d").to_string())
+        .replace("{time}", &time.format("%H%M%S").to_string())
+        // names could otherwise point outside the directory
+        .replace("{sender}", &sender.replace('/', "_"))
+        .replace("{contact}", &contact.replace('/', "_"))
+        .replace("{name}", name)
+}
+
+/// Whether attachments in a conversation are downloaded as they arrive.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AutoDownload {
+    Always,
+    Never,
+    /// Follow the rules in the config.
+    #[default]
+    Rules,
+}
+
+impl AutoDownload {
+    pub const NAMES: [&'static str; 3] = ["always", "never", "rules"];
+}
+
+impl std::fmt::Display for AutoDownload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Always => write!(f, "always"),
+            Self::Never => write!(f, "never"),
+            Self::Rules => write!(f, "rules"),
+        }
+    }
+}
+
+impl FromStr for AutoDownload {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            "rules" => Ok(Self::Rules),
+            _ => Err(format!(
+                "Failed to match {s:?} to one of {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StoredAutoDownload {
+    contact_id: ContactId,
+    setting: AutoDownload,
+}
+
+/// Conversations set to always or never download attachments as they arrive, whatever the
+/// rules say.
+#[derive(Debug, Default)]
+pub struct AutoDownloadOverrides {
+    path: PathBuf,
+    overrides: BTreeMap<ContactId, AutoDownload>,
+}
+
+impl AutoDownloadOverrides {
+    /// Load the overrides from the state file at the path, starting empty if it doesn't exist
+    /// yet.
+    pub fn load(path: &Path) -> Self {
+        let stored: Vec<StoredAutoDownload> = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                warn!(error:%, path:?; "Failed to parse auto-download overrides, starting afresh");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        Self {
+            path: path.to_owned(),
+            overrides: stored
+                .into_iter()
+                .map(|s| (s.contact_id, s.setting))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, id: &ContactId) -> AutoDownload {
+        self.overrides.get(id).copied().unwrap_or_default()
+    }
+
+    /// Set whether the conversation downloads attachments as they arrive, going back to the
+    /// rules with [`AutoDownload::Rules`].
+    pub fn set(&mut self, id: &ContactId, setting: AutoDownload) {
+        if setting == AutoDownload::Rules {
+            self.overrides.remove(id);
+        } else {
+            self.overrides.insert(id.clone(), setting);
+        }
+        self.save();
+    }
+
+    fn save(&self) {
+        if self.path.as_os_str().is_empty() {
+            // not backed by a file
+            return;
+        }
+        let stored = self
+            .overrides
+            .iter()
+            .map(|(id, setting)| StoredAutoDownload {
+                contact_id: id.clone(),
+                setting: *setting,
+            })
+            .collect::<Vec<_>>();
+        let result = serde_json::to_string(&stored)
+            .map_err(std::io::Error::from)
+            .and_then(|content| {
+                if let Some(parent) = self.path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&self.path, content)
+            });
+        if let Err(error) = result {
+            warn!(error:%, path:? = self.path; "Failed to save auto-download overrides");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::ContactId;
+    use crate::testing::contact;
+
+    #[test]
+    fn unique_download_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("scan.pdf");
+        assert_eq!(unique_path(path.clone()), path);
+        std::fs::write(&path, "first").unwrap();
+        let second = unique_path(path.clone());
+        assert_eq!(second, tmp.path().join("scan (1).pdf"));
+        std::fs::write(&second, "second").unwrap();
+        assert_eq!(unique_path(path), tmp.path().join("scan (2).pdf"));
+    }
+
+    #[test]
+    fn download_destination() {
+        let downloads: Downloads = toml::from_str(
+            r#"
+            filename = "{contact}-{name}"
+
+            [contacts.Invoices]
+            dir = "/docs/invoices"
+            filename = "{date}-{sender}-{name}"
+
+            [contacts.Photos]
+            dir = "/photos"
+            "#,
+        )
+        .unwrap();
+        let group = |name: &str| contact(ContactId::Group(Vec::new()), name);
+        let file_path = Path::new("/data/attachments/1/scan.pdf");
+        // midday so the date is the same in any timezone
+        let timestamp = 1_700_049_600_000;
+        insta::assert_debug_snapshot!(vec![
+            downloads.destination(&group("Invoices"), "A/B", timestamp, file_path),
+            downloads.destination(&group("Photos"), "Alice", timestamp, file_path),
+            downloads.destination(&group("Bob"), "Bob", timestamp, file_path),
+            Downloads::default().destination(&group("Bob"), "Bob", timestamp, file_path),
+        ]);
+    }
+
+    #[test]
+    fn auto_download() {
+        let downloads: Downloads = toml::from_str(
+            r#"
+            [[auto]]
+            mime_type = "image/*"
+            max_size = 1000
+
+            [[auto]]
+            contact = "Invoices"
+            mime_type = "application/pdf"
+            "#,
+        )
+        .unwrap();
+        let group = |name: &str| contact(ContactId::Group(Vec::new()), name);
+        let attachment = |size: u64, content_type: Option<&str>| MessageAttachment {
+            name: "file".to_owned(),
+            size,
+            index: 0,
+            path: None,
+            content_type: content_type.map(str::to_owned),
+        };
+        let auto_download = |name: &str, size: u64, content_type: Option<&str>| {
+            downloads.auto_download(
+                &group(name),
+                &attachment(size, content_type),
+                AutoDownload::Rules,
+            )
+        };
+        insta::assert_debug_snapshot!(vec![
+            auto_download("Bob", 500, Some("image/png")),
+            auto_download("Bob", 5000, Some("image/png")),
+            auto_download("Bob", 500, Some("IMAGE/JPEG; name=a.jpg")),
+            auto_download("Bob", 500, None),
+            auto_download("Bob", 500, Some("application/pdf")),
+            auto_download("Invoices", 50000, Some("application/pdf")),
+            downloads.auto_download(&group("Bob"), &attachment(5000, None), AutoDownload::Always),
+            downloads.auto_download(
+                &group("Bob"),
+                &attachment(500, Some("image/png")),
+                AutoDownload::Never
+            ),
+        ]);
+    }
+}
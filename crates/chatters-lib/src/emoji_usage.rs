@@ -0,0 +1,136 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+
+use crate::backends::timestamp;
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+struct Usage {
+    count: u64,
+    last_used: u64,
+}
+
+/// How often each emoji has been used to react, persisted so that common ones can be suggested
+/// first.
+#[derive(Debug, Default)]
+pub struct EmojiUsage {
+    path: PathBuf,
+    usage: BTreeMap<String, Usage>,
+}
+
+impl EmojiUsage {
+    /// Load the usage from the file at the path, starting empty if it doesn't exist yet.
+    pub fn load(path: &Path) -> Self {
+        let usage = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                warn!(error:%, path:?; "Failed to parse emoji usage, starting afresh");
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        Self {
+            path: path.to_owned(),
+            usage,
+        }
+    }
+
+    /// Record a use of the emoji and save the usage.
+    pub fn record(&mut self, emoji: &str) {
+        let usage = self.usage.entry(emoji.to_owned()).or_default();
+        usage.count += 1;
+        usage.last_used = timestamp();
+
+        if self.path.as_os_str().is_empty() {
+            // not backed by a file
+            return;
+        }
+        let result = serde_json::to_string(&self.usage)
+            .map_err(std::io::Error::from)
+            .and_then(|content| {
+                if let Some(parent) = self.path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&self.path, content)
+            });
+        if let Err(error) = result {
+            warn!(error:%, path:? = self.path; "Failed to save emoji usage");
+        }
+    }
+
+    /// The used emoji, most frequently used first and then most recently used.
+    pub fn ranked(&self) -> Vec<&str> {
+        let mut ranked = self.usage.iter().collect::<Vec<_>>();
+        ranked.sort_by(|(_, a), (_, b)| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| b.last_used.cmp(&a.last_used))
+        });
+        ranked.into_iter().map(|(e, _)| e.as_str()).collect()
+    }
+
+    /// The used reactions, most recently used first.
+    pub fn recent(&self) -> Vec<&str> {
+        let mut recent = self.usage.iter().collect::<Vec<_>>();
+        recent.sort_by_key(|(_, usage)| std::cmp::Reverse(usage.last_used));
+        recent.into_iter().map(|(e, _)| e.as_str()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.usage.is_empty()
+    }
+
+    /// All emoji, with the used ones first by rank and then the rest in their usual order.
+    pub fn all_ranked(&self) -> impl Iterator<Item = &'static emojis::Emoji> + '_ {
+        let ranked = self
+            .ranked()
+            .into_iter()
+            .filter_map(emojis::get)
+            .collect::<Vec<_>>();
+        let rest = emojis::iter().filter(move |e| !self.usage.contains_key(e.as_str()));
+        ranked.into_iter().chain(rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ranked() {
+        let usage = |count, last_used| Usage { count, last_used };
+        let emoji_usage = EmojiUsage {
+            path: PathBuf::new(),
+            usage: BTreeMap::from([
+                ("❤️".to_owned(), usage(1, 10)),
+                ("👍".to_owned(), usage(3, 5)),
+                ("😂".to_owned(), usage(1, 20)),
+            ]),
+        };
+        assert_eq!(emoji_usage.ranked(), vec!["👍", "😂", "❤️"]);
+        assert_eq!(emoji_usage.recent(), vec!["😂", "❤️", "👍"]);
+
+        let all = emoji_usage
+            .all_ranked()
+            .map(|e| e.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(&all[..3], ["👍", "😂", "❤️"]);
+        assert_eq!(all.len(), emojis::iter().count());
+    }
+
+    #[test]
+    fn test_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("emoji_usage.json");
+        let mut emoji_usage = EmojiUsage::load(&path);
+        emoji_usage.record("🎉");
+        emoji_usage.record("👍");
+        emoji_usage.record("👍");
+
+        // kept between runs
+        let emoji_usage = EmojiUsage::load(&path);
+        assert_eq!(emoji_usage.ranked(), vec!["👍", "🎉"]);
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+
+use crate::backends::{Contact, ContactId};
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StoredFolder {
+    contact_id: ContactId,
+    folder: String,
+}
+
+/// Folders conversations were moved into with `:move-to-folder`, taking precedence over those
+/// listed in the config.
+#[derive(Debug, Default)]
+pub struct Folders {
+    path: PathBuf,
+    /// An empty folder for conversations taken out of the one the config puts them in.
+    folders: BTreeMap<ContactId, String>,
+}
+
+impl Folders {
+    /// Load the folders from the state file at the path, starting empty if it doesn't exist yet.
+    pub fn load(path: &Path) -> Self {
+        let stored: Vec<StoredFolder> = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                warn!(error:%, path:?; "Failed to parse folders, starting afresh");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        Self {
+            path: path.to_owned(),
+            folders: stored
+                .into_iter()
+                .map(|s| (s.contact_id, s.folder))
+                .collect(),
+        }
+    }
+
+    /// The folder the contact is in, either the one it was moved to or the first in the config
+    /// listing it by its own name or the one shown for it.
+    pub fn folder<'a>(
+        &'a self,
+        contact: &Contact,
+        shown_name: &str,
+        configured: &'a BTreeMap<String, Vec<String>>,
+    ) -> Option<&'a str> {
+        if let Some(folder) = self.folders.get(&contact.id) {
+            return Some(folder.as_str()).filter(|f| !f.is_empty());
+        }
+        configured
+            .iter()
+            .find(|(_, names)| names.iter().any(|n| *n == contact.name || n == shown_name))
+            .map(|(folder, _)| folder.as_str())
+    }
+
+    /// Move the contact into the folder, or out of any when empty.
+    pub fn set(&mut self, id: &ContactId, folder: &str) {
+        self.folders.insert(id.clone(), folder.trim().to_owned());
+        self.save();
+    }
+
+    /// The names of the folders in use, either moved into or in the config.
+    pub fn names(&self, configured: &BTreeMap<String, Vec<String>>) -> BTreeSet<String> {
+        self.folders
+            .values()
+            .filter(|f| !f.is_empty())
+            .chain(configured.keys())
+            .cloned()
+            .collect()
+    }
+
+    fn save(&self) {
+        if self.path.as_os_str().is_empty() {
+            // not backed by a file
+            return;
+        }
+        let stored = self
+            .folders
+            .iter()
+            .map(|(id, folder)| StoredFolder {
+                contact_id: id.clone(),
+                folder: folder.clone(),
+            })
+            .collect::<Vec<_>>();
+        let result = serde_json::to_string(&stored)
+            .map_err(std::io::Error::from)
+            .and_then(|content| {
+                if let Some(parent) = self.path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&self.path, content)
+            });
+        if let Err(error) = result {
+            warn!(error:%, path:? = self.path; "Failed to save folders");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::contact;
+
+    #[test]
+    fn test_moved_over_configured() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("folders.json");
+        let contact = contact(ContactId::User(vec![1]), "Alice");
+        let configured = BTreeMap::from([("Friends".to_owned(), vec!["Ali".to_owned()])]);
+
+        let mut folders = Folders::load(&path);
+        assert_eq!(folders.folder(&contact, "Alice", &configured), None);
+        // by the nickname shown for them
+        assert_eq!(
+            folders.folder(&contact, "Ali", &configured),
+            Some("Friends")
+        );
+
+        folders.set(&contact.id, " Work ");
+        let mut folders = Folders::load(&path);
+        assert_eq!(folders.folder(&contact, "Ali", &configured), Some("Work"));
+        assert_eq!(
+            folders.names(&configured).into_iter().collect::<Vec<_>>(),
+            ["Friends", "Work"]
+        );
+
+        folders.set(&contact.id, "");
+        assert_eq!(folders.folder(&contact, "Ali", &configured), None);
+    }
+}
@@ -0,0 +1,174 @@
+use std::path::Path;
+
+use crate::backends::{ContactId, MessageAttachment, MessageContent};
+
+/// Messages to send on to other conversations, along with their attachments.
+#[derive(Debug, Clone)]
+pub struct Forward {
+    /// The conversation the messages are from.
+    pub contact_id: ContactId,
+    pub recipients: Vec<ContactId>,
+    pub messages: Vec<ForwardedMessage>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ForwardedMessage {
+    pub timestamp: u64,
+    pub text: String,
+    pub attachments: Vec<MessageAttachment>,
+}
+
+impl Forward {
+    /// The attachments which need downloading before they can be uploaded again, as the
+    /// timestamp of their message and their index.
+    pub fn missing_attachments(&self) -> Vec<(u64, usize)> {
+        self.messages
+            .iter()
+            .flat_map(|m| {
+                m.attachments
+                    .iter()
+                    .filter(|a| a.path.is_none())
+                    .map(|a| (m.timestamp, a.index))
+            })
+            .collect()
+    }
+
+    /// The messages to send to each recipient, in order.
+    pub fn contents(&self) -> Vec<(ContactId, MessageContent)> {
+        self.recipients
+            .iter()
+            .flat_map(|recipient| {
+                self.messages.iter().map(|m| {
+                    let content = MessageContent::Text {
+                        text: m.text.clone(),
+                        attachments: m.attachments.clone(),
+                        mentions: Vec::new(),
+                        formatting: Vec::new(),
+                    };
+                    (recipient.clone(), content)
+                })
+            })
+            .collect()
+    }
+}
+
+/// Forwards waiting for their attachments to download.
+#[derive(Debug, Default)]
+pub struct Forwards {
+    waiting: Vec<Forward>,
+}
+
+impl Forwards {
+    pub fn wait(&mut self, forward: Forward) {
+        self.waiting.push(forward);
+    }
+
+    /// Record where an attachment was downloaded to, returning the forwards with nothing left to
+    /// download.
+    pub fn downloaded(
+        &mut self,
+        contact_id: &ContactId,
+        timestamp: u64,
+        index: usize,
+        path: &Path,
+    ) -> Vec<Forward> {
+        for forward in self
+            .waiting
+            .iter_mut()
+            .filter(|f| &f.contact_id == contact_id)
+        {
+            let attachments = forward
+                .messages
+                .iter_mut()
+                .filter(|m| m.timestamp == timestamp)
+                .flat_map(|m| m.attachments.iter_mut())
+                .filter(|a| a.index == index);
+            for attachment in attachments {
+                attachment.path = Some(path.to_owned());
+            }
+        }
+        let (ready, waiting) = std::mem::take(&mut self.waiting)
+            .into_iter()
+            .partition(|f| f.missing_attachments().is_empty());
+        self.waiting = waiting;
+        ready
+    }
+
+    /// Drop the forwards waiting on an attachment which failed to download, returning how many
+    /// there were.
+    pub fn failed(&mut self, contact_id: &ContactId, timestamp: u64, index: usize) -> usize {
+        let before = self.waiting.len();
+        self.waiting.retain(|f| {
+            &f.contact_id != contact_id || !f.missing_attachments().contains(&(timestamp, index))
+        });
+        before - self.waiting.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forward_after_download() {
+        let contact_id = ContactId::User(vec![1]);
+        let attachment = |index| MessageAttachment {
+            name: format!("photo{index}.jpg"),
+            size: 10,
+            index,
+            path: None,
+            content_type: None,
+        };
+        let mut forwards = Forwards::default();
+        forwards.wait(Forward {
+            contact_id: contact_id.clone(),
+            recipients: vec![ContactId::User(vec![2]), ContactId::Group(vec![3])],
+            messages: vec![ForwardedMessage {
+                timestamp: 1,
+                text: "photos".to_owned(),
+                attachments: vec![attachment(0), attachment(1)],
+            }],
+        });
+        let first = forwards.downloaded(&contact_id, 1, 0, Path::new("/tmp/photo0.jpg"));
+        let second = forwards.downloaded(&contact_id, 1, 1, Path::new("/tmp/photo1.jpg"));
+        insta::assert_debug_snapshot!((
+            first.len(),
+            second.iter().map(Forward::contents).collect::<Vec<_>>()
+        ));
+    }
+
+    #[test]
+    fn test_forward_dropped_after_failed_download() {
+        let contact_id = ContactId::User(vec![1]);
+        let forward = |timestamp| Forward {
+            contact_id: contact_id.clone(),
+            recipients: vec![ContactId::User(vec![2])],
+            messages: vec![ForwardedMessage {
+                timestamp,
+                text: String::new(),
+                attachments: vec![MessageAttachment {
+                    name: "photo.jpg".to_owned(),
+                    size: 10,
+                    index: 0,
+                    path: None,
+                    content_type: None,
+                }],
+            }],
+        };
+        let mut forwards = Forwards::default();
+        forwards.wait(forward(1));
+        forwards.wait(forward(2));
+        // other conversations' attachments are left alone
+        assert_eq!(forwards.failed(&ContactId::User(vec![2]), 1, 0), 0);
+        assert_eq!(forwards.failed(&contact_id, 1, 0), 1);
+        assert!(forwards
+            .downloaded(&contact_id, 1, 0, Path::new("/tmp/photo.jpg"))
+            .is_empty());
+        assert_eq!(
+            forwards
+                .downloaded(&contact_id, 2, 0, Path::new("/tmp/photo.jpg"))
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use std::cmp::Reverse;
+
+/// Score for each character matched.
+const MATCH: i64 = 16;
+/// Extra for matching at the start of the candidate or of a word in it.
+const WORD_START: i64 = 8;
+/// Extra for matching right after the previous match.
+const CONSECUTIVE: i64 = 8;
+/// Taken off for each character skipped between matches.
+const GAP: i64 = 1;
+
+/// How a pattern matched a candidate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    /// Higher for better matches, such as those at word starts or without gaps.
+    pub score: i64,
+    /// The indices of the characters of the candidate matched, in order.
+    pub indices: Vec<usize>,
+}
+
+/// Match the characters of the pattern in order anywhere in the candidate, as fzf does, choosing
+/// the best scoring way they can match. Case is ignored unless the pattern has uppercase letters.
+pub fn fuzzy_match(pattern: &str, candidate: &str) -> Option<FuzzyMatch> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let candidate: Vec<char> = candidate.chars().collect();
+    if pattern.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            indices: Vec::new(),
+        });
+    }
+    let ignore_case = !pattern.iter().any(|c| c.is_uppercase());
+    let matches = |p: char, c: char| {
+        if ignore_case {
+            p.to_lowercase().eq(c.to_lowercase())
+        } else {
+            p == c
+        }
+    };
+
+    // the best score for the pattern up to i with its last character matched at j, along with
+    // where the character before it matched
+    let mut best = vec![vec![None::<(i64, usize)>; candidate.len()]; pattern.len()];
+    for (i, &p) in pattern.iter().enumerate() {
+        for (j, &c) in candidate.iter().enumerate() {
+            if !matches(p, c) {
+                continue;
+            }
+            let score = MATCH + word_start_bonus(&candidate, j);
+            if i == 0 {
+                best[i][j] = Some((score, 0));
+                continue;
+            }
+            best[i][j] = (0..j)
+                .filter_map(|k| {
+                    let (previous, _) = best[i - 1][k]?;
+                    let link = if k + 1 == j {
+                        CONSECUTIVE
+                    } else {
+                        -GAP * (j - k - 1) as i64
+                    };
+                    Some((previous + link + score, k))
+                })
+                .max_by_key(|&(score, k)| (score, Reverse(k)));
+        }
+    }
+
+    let (mut j, score) = best[pattern.len() - 1]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, b)| b.map(|(score, _)| (j, score)))
+        .max_by_key(|&(j, score)| (score, Reverse(j)))?;
+    let mut indices = vec![0; pattern.len()];
+    for i in (0..pattern.len()).rev() {
+        indices[i] = j;
+        j = best[i][j].map_or(0, |(_, k)| k);
+    }
+    Some(FuzzyMatch { score, indices })
+}
+
+fn word_start_bonus(candidate: &[char], j: usize) -> i64 {
+    let starts_word = match j.checked_sub(1).map(|k| candidate[k]) {
+        None => true,
+        Some(before) => {
+            !before.is_alphanumeric() || (before.is_lowercase() && candidate[j].is_uppercase())
+        }
+    };
+    if starts_word {
+        WORD_START
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(pattern: &str, candidate: &str) -> Option<Vec<usize>> {
+        fuzzy_match(pattern, candidate).map(|m| m.indices)
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert_eq!(indices("mut", "mute"), Some(vec![0, 1, 2]));
+        assert_eq!(indices("mc", "mute-contact"), Some(vec![0, 5]));
+        assert_eq!(indices("tup", "thumbsup"), Some(vec![0, 6, 7]));
+        assert_eq!(indices("xyz", "mute-contact"), None);
+        assert_eq!(indices("", "mute"), Some(vec![]));
+
+        // word starts beat closer matches
+        let score = |pattern, candidate| fuzzy_match(pattern, candidate).unwrap().score;
+        assert!(score("mc", "mute-contact") > score("mc", "music"));
+        assert!(score("mute", "mute-contact") > score("mute", "my-unmuted"));
+
+        // only uppercase in the pattern has to match case
+        assert_eq!(indices("c", "Contact"), Some(vec![0]));
+        assert_eq!(indices("C", "contact"), None);
+    }
+}
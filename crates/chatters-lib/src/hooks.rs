@@ -1,12 +1,95 @@
-use std::{collections::BTreeMap, process::Stdio};
+use std::{
+    collections::BTreeMap,
+    io::{Read as _, Write as _},
+    path::Path,
+    process::Stdio,
+    time::{Duration, Instant},
+};
 
-use log::warn;
+use log::{debug, warn};
 
-use crate::backends::{Contact, Message};
+use crate::backends::{Contact, ContactId, Invite, Message};
 
+/// Shell commands to run on events.
+///
+/// Each hook is run with `sh -c` and receives a json description of the event on stdin.
 #[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Hooks {
+    /// Run when a new message is received from someone else.
     pub on_new_message: Option<String>,
+    /// Run before sending a message.
+    ///
+    /// A non-zero exit status stops the message from being sent, otherwise any output on stdout
+    /// replaces the text of the message. A hook that takes longer than 5 seconds is killed and
+    /// the message isn't sent.
+    pub pre_send: Option<String>,
+    /// Run after an attachment has been downloaded.
+    pub on_attachment_downloaded: Option<String>,
+    /// Run after the contacts have been loaded from the backend.
+    pub on_contact_sync: Option<String>,
+    /// Run when an invitation to a conversation arrives.
+    pub on_invite: Option<String>,
+    /// Run when someone calls, or a call from them was missed.
+    pub on_call: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct NewMessageEvent<'a> {
+    app_name: &'a str,
+    contact: &'a Contact,
+    sender: &'a Contact,
+    message: &'a Message,
+}
+
+#[derive(serde::Serialize)]
+struct PreSendEvent<'a> {
+    app_name: &'a str,
+    contact: &'a Contact,
+    text: &'a str,
+}
+
+#[derive(serde::Serialize)]
+struct AttachmentDownloadedEvent<'a> {
+    app_name: &'a str,
+    contact_id: &'a ContactId,
+    timestamp: u64,
+    index: usize,
+    file_path: &'a Path,
+}
+
+#[derive(serde::Serialize)]
+struct ContactSyncEvent<'a> {
+    app_name: &'a str,
+    contacts: &'a [Contact],
+}
+
+#[derive(serde::Serialize)]
+struct InviteEvent<'a> {
+    app_name: &'a str,
+    invite: &'a Invite,
+}
+
+#[derive(serde::Serialize)]
+struct CallEvent<'a> {
+    app_name: &'a str,
+    contact: &'a Contact,
+    caller: &'a Contact,
+    missed: bool,
+    link: Option<&'a str>,
+}
+
+/// How long the pre-send hook has to finish before the message is dropped.
+const PRE_SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The outcome of running the pre-send hook.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub enum PreSend {
+    /// Send the message with this text.
+    Send(String),
+    /// Don't send the message.
+    Veto,
+    /// The hook took too long, so the message isn't sent.
+    TimedOut,
 }
 
 impl Hooks {
@@ -30,17 +113,235 @@ impl Hooks {
             message.content.to_string(),
         );
 
-        let child = std::process::Command::new("sh")
-            .arg("-c")
-            .arg(&script)
-            .envs(envs)
-            .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn();
-
-        if let Err(error) = child {
-            warn!(error:?; "Failed to execute on_new_message hook");
+        let event = NewMessageEvent {
+            app_name,
+            contact,
+            sender,
+            message,
+        };
+        spawn_hook("on_new_message", script, envs, &event);
+    }
+
+    /// Run the pre-send hook on another thread, giving its outcome to `done`.
+    pub fn do_pre_send(
+        &self,
+        app_name: &str,
+        contact: &Contact,
+        text: String,
+        done: impl FnOnce(PreSend) + Send + 'static,
+    ) {
+        let Some(script) = self.pre_send.clone() else {
+            done(PreSend::Send(text));
+            return;
+        };
+
+        let event = PreSendEvent {
+            app_name,
+            contact,
+            text: &text,
+        };
+        let input = serde_json::to_vec(&event).unwrap();
+        std::thread::spawn(move || done(run_pre_send(&script, input, text, PRE_SEND_TIMEOUT)));
+    }
+
+    pub fn do_on_attachment_downloaded(
+        &self,
+        app_name: &str,
+        contact_id: &ContactId,
+        timestamp: u64,
+        index: usize,
+        file_path: &Path,
+    ) {
+        let Some(script) = &self.on_attachment_downloaded else {
+            return;
+        };
+
+        let event = AttachmentDownloadedEvent {
+            app_name,
+            contact_id,
+            timestamp,
+            index,
+            file_path,
+        };
+        spawn_hook("on_attachment_downloaded", script, BTreeMap::new(), &event);
+    }
+
+    pub fn do_on_contact_sync(&self, app_name: &str, contacts: &[Contact]) {
+        let Some(script) = &self.on_contact_sync else {
+            return;
+        };
+
+        let event = ContactSyncEvent { app_name, contacts };
+        spawn_hook("on_contact_sync", script, BTreeMap::new(), &event);
+    }
+
+    pub fn do_on_invite(&self, app_name: &str, invite: &Invite) {
+        let Some(script) = &self.on_invite else {
+            return;
+        };
+
+        let mut envs: BTreeMap<String, String> = BTreeMap::new();
+        envs.insert("CHATTERS_APP_NAME".to_owned(), app_name.to_owned());
+        envs.insert("CHATTERS_INVITE_NAME".to_owned(), invite.name.clone());
+        envs.insert("CHATTERS_INVITER_NAME".to_owned(), invite.inviter.clone());
+
+        let event = InviteEvent { app_name, invite };
+        spawn_hook("on_invite", script, envs, &event);
+    }
+
+    pub fn do_on_call(
+        &self,
+        app_name: &str,
+        contact: &Contact,
+        caller: &Contact,
+        missed: bool,
+        link: Option<&str>,
+    ) {
+        let Some(script) = &self.on_call else {
+            return;
+        };
+
+        let mut envs: BTreeMap<String, String> = BTreeMap::new();
+        envs.insert("CHATTERS_APP_NAME".to_owned(), app_name.to_owned());
+        envs.insert("CHATTERS_CONTACT_NAME".to_owned(), contact.name.clone());
+        envs.insert("CHATTERS_CALLER_NAME".to_owned(), caller.name.clone());
+        envs.insert("CHATTERS_CALL_MISSED".to_owned(), missed.to_string());
+
+        let event = CallEvent {
+            app_name,
+            contact,
+            caller,
+            missed,
+            link,
+        };
+        spawn_hook("on_call", script, envs, &event);
+    }
+}
+
+/// Run a hook in the background, writing the event to its stdin.
+fn spawn_hook(
+    name: &str,
+    script: &str,
+    envs: BTreeMap<String, String>,
+    event: &impl serde::Serialize,
+) {
+    let input = serde_json::to_vec(event).unwrap();
+
+    let child = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .envs(envs)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(error) => {
+            warn!(error:?; "Failed to execute {name} hook");
+            return;
+        }
+    };
+
+    // write from another thread so that a hook that doesn't read its input can't block the ui
+    let name = name.to_owned();
+    std::thread::spawn(move || {
+        if let Some(mut stdin) = child.stdin.take() {
+            if let Err(error) = stdin.write_all(&input) {
+                debug!(error:%; "Failed to write event to {name} hook");
+            }
+        }
+        if let Err(error) = child.wait() {
+            warn!(error:%; "Failed to wait for {name} hook");
+        }
+    });
+}
+
+/// Run the pre-send hook to completion, killing it if it takes longer than `timeout`.
+fn run_pre_send(script: &str, input: Vec<u8>, text: String, timeout: Duration) -> PreSend {
+    let child = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(error) => {
+            warn!(error:%; "Failed to execute pre_send hook, sending original message");
+            return PreSend::Send(text);
+        }
+    };
+
+    let mut stdin = child.stdin.take().unwrap();
+    std::thread::spawn(move || {
+        if let Err(error) = stdin.write_all(&input) {
+            debug!(error:%; "Failed to write event to pre_send hook");
+        }
+    });
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = stdout.read_to_end(&mut output);
+        output
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+            Ok(None) => {
+                warn!(timeout:?; "pre_send hook timed out, not sending message");
+                let _ = child.kill();
+                let _ = child.wait();
+                return PreSend::TimedOut;
+            }
+            Err(error) => {
+                warn!(error:%; "Failed to wait for pre_send hook, sending original message");
+                return PreSend::Send(text);
+            }
         }
+    };
+
+    if !status.success() {
+        debug!(status:%; "pre_send hook vetoed message");
+        return PreSend::Veto;
+    }
+
+    let stdout = reader.join().unwrap_or_default();
+    let stdout = String::from_utf8_lossy(&stdout);
+    let rewritten = stdout.trim_end_matches('\n');
+    if rewritten.is_empty() {
+        PreSend::Send(text)
+    } else {
+        PreSend::Send(rewritten.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pre_send(script: &str, timeout: Duration) -> PreSend {
+        run_pre_send(script, b"{}".to_vec(), "hi".to_owned(), timeout)
+    }
+
+    #[test]
+    fn test_pre_send_rewrites_and_vetoes() {
+        let timeout = Duration::from_secs(5);
+        assert!(matches!(pre_send("true", timeout), PreSend::Send(t) if t == "hi"));
+        assert!(matches!(pre_send("echo bye", timeout), PreSend::Send(t) if t == "bye"));
+        assert!(matches!(pre_send("exit 1", timeout), PreSend::Veto));
+    }
+
+    #[test]
+    fn test_pre_send_times_out() {
+        let started = Instant::now();
+        let outcome = pre_send("exec sleep 10", Duration::from_millis(100));
+        assert!(matches!(outcome, PreSend::TimedOut));
+        assert!(started.elapsed() < Duration::from_secs(5));
     }
 }
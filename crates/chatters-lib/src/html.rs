@@ -0,0 +1,221 @@
+use crate::backends::{FormatKind, Formatting};
+
+/// The text of formatted HTML, such as Matrix's `formatted_body`, with the formatting of the
+/// tags it knows.
+///
+/// Other tags are dropped, keeping their text, except for Matrix reply fallbacks which repeat
+/// the message being replied to.
+pub fn parse_html(html: &str) -> (String, Vec<Formatting>) {
+    let mut parsed = Parsed::default();
+    // tags which may still be closed, with where they started in the text
+    let mut open: Vec<(String, Option<FormatKind>, usize)> = Vec::new();
+    let mut hidden = 0;
+    let mut preformatted = 0;
+
+    let mut rest = html;
+    while !rest.is_empty() {
+        let Some(tag) = rest.strip_prefix('<') else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            if hidden == 0 {
+                parsed.push_text(&decode_entities(&rest[..end]), preformatted > 0);
+            }
+            rest = &rest[end..];
+            continue;
+        };
+        let Some(end) = tag.find('>') else {
+            // not a tag after all
+            if hidden == 0 {
+                parsed.push_text(&decode_entities(rest), preformatted > 0);
+            }
+            break;
+        };
+        rest = &tag[end + 1..];
+        let tag = &tag[..end];
+        let closing = tag.starts_with('/');
+        let tag = tag.trim_start_matches('/').trim_end_matches('/');
+        let name = tag
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+
+        match name.as_str() {
+            "mx-reply" => {
+                if closing {
+                    hidden -= 1;
+                } else {
+                    hidden += 1;
+                }
+                continue;
+            }
+            "br" => {
+                if hidden == 0 {
+                    parsed.push('\n');
+                }
+                continue;
+            }
+            "p" | "div" | "blockquote" | "ul" | "ol" | "li" | "tr" | "pre" | "h1" | "h2" | "h3"
+            | "h4" | "h5" | "h6" => {
+                if hidden == 0 {
+                    parsed.end_line();
+                    if name == "li" && !closing {
+                        parsed.push_str("• ");
+                    }
+                }
+                if name == "pre" {
+                    preformatted = if closing {
+                        preformatted - 1
+                    } else {
+                        preformatted + 1
+                    };
+                }
+            }
+            _ => {}
+        }
+
+        if closing {
+            let Some(i) = open.iter().rposition(|(n, _, _)| *n == name) else {
+                continue;
+            };
+            let (_, kind, start) = open.remove(i);
+            if let Some(kind) = kind.filter(|_| parsed.len > start) {
+                parsed.formatting.push(Formatting {
+                    start,
+                    length: parsed.len - start,
+                    kind,
+                });
+            }
+        } else {
+            let kind = match name.as_str() {
+                "b" | "strong" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => Some(FormatKind::Bold),
+                "i" | "em" => Some(FormatKind::Italic),
+                "del" | "s" | "strike" => Some(FormatKind::Strikethrough),
+                "code" | "pre" => Some(FormatKind::Monospace),
+                "span" if tag.contains("data-mx-spoiler") => Some(FormatKind::Spoiler),
+                _ => None,
+            };
+            open.push((name, kind, parsed.len));
+        }
+    }
+
+    let mut text = parsed.text;
+    let trimmed = text.trim_end().len();
+    text.truncate(trimmed);
+    let len = text.chars().count();
+    let mut formatting = parsed.formatting;
+    for f in &mut formatting {
+        f.length = f.length.min(len.saturating_sub(f.start));
+    }
+    formatting.retain(|f| f.length > 0);
+    formatting.sort_by_key(|f| f.start);
+    // such as from `<pre><code>`
+    formatting.dedup();
+    (text, formatting)
+}
+
+#[derive(Default)]
+struct Parsed {
+    text: String,
+    /// The length of the text in chars.
+    len: usize,
+    formatting: Vec<Formatting>,
+}
+
+impl Parsed {
+    fn push(&mut self, c: char) {
+        self.text.push(c);
+        self.len += 1;
+    }
+
+    fn push_str(&mut self, s: &str) {
+        self.text.push_str(s);
+        self.len += s.chars().count();
+    }
+
+    /// Add text from the html, where line breaks are only whitespace unless preformatted.
+    fn push_text(&mut self, text: &str, preformatted: bool) {
+        if preformatted {
+            self.push_str(text);
+            return;
+        }
+        for c in text.chars() {
+            if c.is_whitespace() {
+                let at_space = self.text.is_empty() || self.text.ends_with([' ', '\n']);
+                if !at_space {
+                    self.push(' ');
+                }
+            } else {
+                self.push(c);
+            }
+        }
+    }
+
+    /// Start a new line, unless already at the start of one.
+    fn end_line(&mut self) {
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.push('\n');
+        }
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let entity = rest[1..]
+            .find(';')
+            .map(|end| &rest[1..end + 1])
+            .and_then(|name| Some((name, decode_entity(name)?)));
+        match entity {
+            Some((name, c)) => {
+                decoded.push(c);
+                rest = &rest[name.len() + 2..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => {
+            let number = name.strip_prefix('#')?;
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_html() {
+        insta::assert_debug_snapshot!(parse_html(
+            "<mx-reply><blockquote>earlier</blockquote></mx-reply>\
+             <b>bold</b> and <em>it&amp;lic</em><br>\
+             <del>gone</del> <code>x &lt; y</code> \
+             <span data-mx-spoiler=\"plot\">the end</span>"
+        ));
+        insta::assert_debug_snapshot!(parse_html(
+            "<p>one</p>\n<ul><li>a</li><li><strong>b</strong></li></ul><pre><code>fn main() {\n}\n</code></pre>"
+        ));
+    }
+}
@@ -0,0 +1,302 @@
+use std::{
+    os::unix::fs::{DirBuilderExt as _, PermissionsExt as _},
+    path::{Path, PathBuf},
+};
+
+use futures::channel::{mpsc, oneshot};
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+    net::{UnixListener, UnixStream},
+};
+
+use crate::{
+    backends::ContactId, commands::send_message, config::Theme, message::BackendMessage,
+    outbox::Outgoing, tui::TuiState,
+};
+
+/// A request from an external tool, sent as a line of json on the socket.
+///
+/// For example `{"method": "get-unread-count"}` or
+/// `{"method": "send-message", "params": {"contact": "Alice", "text": "hi"}}`.
+///
+/// Each request gets a single line response of either `{"result": ...}` or `{"error": "..."}`.
+#[derive(Debug, serde::Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "kebab-case")]
+pub enum IpcRequest {
+    ListContacts {},
+    SendMessage {
+        contact: ContactRef,
+        text: String,
+    },
+    MarkRead {
+        contact: ContactRef,
+    },
+    /// Get the unread count of a single contact, or the total if none is given.
+    GetUnreadCount {
+        #[serde(default)]
+        contact: Option<ContactRef>,
+    },
+    /// Switch between the dark and light colours, such as from a scheduled job.
+    SetTheme {
+        theme: Theme,
+    },
+}
+
+/// A contact given either by its id or its name.
+#[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+pub enum ContactRef {
+    Id(ContactId),
+    Name(String),
+}
+
+/// A request waiting for the ui to handle it.
+#[derive(Debug)]
+pub struct IpcCall {
+    pub request: IpcRequest,
+    pub reply: oneshot::Sender<Result<Value, String>>,
+}
+
+/// Listen for connections on a unix socket, forwarding their requests to the ui.
+///
+/// Anyone who can connect can send messages as the user, so the socket is only accessible to
+/// them.
+pub async fn serve(path: PathBuf, calls_tx: mpsc::UnboundedSender<IpcCall>) {
+    let listener = match bind(&path) {
+        Ok(listener) => listener,
+        Err(error) => {
+            warn!(error:%, path:?; "Failed to bind ipc socket");
+            return;
+        }
+    };
+    info!(path:?; "Listening for ipc connections");
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                debug!("Accepted ipc connection");
+                tokio::spawn(handle_connection(stream, calls_tx.clone()));
+            }
+            Err(error) => warn!(error:%; "Failed to accept ipc connection"),
+        }
+    }
+}
+
+/// Bind the socket in a directory only the user can enter and make it private before moving it
+/// into place, so there is no moment where others can connect.
+fn bind(path: &Path) -> std::io::Result<UnixListener> {
+    let mut private = path.as_os_str().to_owned();
+    private.push(".tmp");
+    let private = PathBuf::from(private);
+    // clear out what a previous run left behind
+    let _ = std::fs::remove_file(path);
+    let _ = std::fs::remove_dir_all(&private);
+    std::fs::DirBuilder::new().mode(0o700).create(&private)?;
+    let temporary = private.join("socket");
+    let listener = UnixListener::bind(&temporary)?;
+    std::fs::set_permissions(&temporary, std::fs::Permissions::from_mode(0o600))?;
+    std::fs::rename(&temporary, path)?;
+    std::fs::remove_dir(&private)?;
+    Ok(listener)
+}
+
+async fn handle_connection(stream: UnixStream, calls_tx: mpsc::UnboundedSender<IpcCall>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match parse_request(&line) {
+            Ok(request) => {
+                let (reply_tx, reply_rx) = oneshot::channel();
+                let call = IpcCall {
+                    request,
+                    reply: reply_tx,
+                };
+                if calls_tx.unbounded_send(call).is_err() {
+                    break;
+                }
+                match reply_rx.await {
+                    Ok(Ok(result)) => json!({ "result": result }),
+                    Ok(Err(error)) => json!({ "error": error }),
+                    Err(_) => break,
+                }
+            }
+            Err(error) => json!({ "error": error.to_string() }),
+        };
+        let mut response = serde_json::to_vec(&response).unwrap();
+        response.push(b'\n');
+        if let Err(error) = writer.write_all(&response).await {
+            debug!(error:%; "Failed to write ipc response");
+            break;
+        }
+    }
+    debug!("Closed ipc connection");
+}
+
+fn parse_request(line: &str) -> serde_json::Result<IpcRequest> {
+    let mut value: Value = serde_json::from_str(line)?;
+    // allow leaving out the params for requests that don't need any
+    if let Some(object) = value.as_object_mut() {
+        object.entry("params").or_insert_with(|| json!({}));
+    }
+    serde_json::from_value(value)
+}
+
+/// Handle a call against the current state of the ui, replying once it is done.
+pub(crate) fn handle_call(
+    tui_state: &mut TuiState,
+    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
+    call: IpcCall,
+) {
+    let IpcCall { request, reply } = call;
+    if let IpcRequest::SendMessage { contact, text } = request {
+        // sent like a message from the compose box, so the reply waits for the pre_send hook
+        let contact_id = match resolve_contact(tui_state, &contact) {
+            Ok(contact_id) => contact_id,
+            Err(error) => {
+                let _ = reply.send(Err(error));
+                return;
+            }
+        };
+        let outgoing = Outgoing {
+            contact_id,
+            text,
+            attachments: Vec::new(),
+            quote: None,
+        };
+        let reply = Box::new(move |result: Result<(), String>| {
+            let _ = reply.send(result.map(|()| Value::Null));
+        });
+        send_message(tui_state, ba_tx, outgoing, Some(reply));
+        return;
+    }
+    let result = handle_request(tui_state, request);
+    let _ = reply.send(result);
+}
+
+/// Handle a request that can be answered straight away.
+fn handle_request(tui_state: &mut TuiState, request: IpcRequest) -> Result<Value, String> {
+    match request {
+        IpcRequest::ListContacts {} => {
+            let contacts = tui_state
+                .contacts
+                .iter_contacts_and_groups()
+                .map(|c| {
+                    json!({
+                        "id": c.id,
+                        "name": c.name,
+                        "address": c.address,
+                        "last_message_timestamp": c.last_message_timestamp,
+                        "unread": tui_state.contacts.unread_count(&c.id),
+                    })
+                })
+                .collect();
+            Ok(Value::Array(contacts))
+        }
+        IpcRequest::SendMessage { .. } => unreachable!("handled by handle_call"),
+        IpcRequest::MarkRead { contact } => {
+            let contact_id = resolve_contact(tui_state, &contact)?;
+            tui_state.contacts.mark_read(&contact_id);
+            Ok(Value::Null)
+        }
+        IpcRequest::GetUnreadCount { contact } => {
+            let count = match contact {
+                Some(contact) => {
+                    let contact_id = resolve_contact(tui_state, &contact)?;
+                    tui_state.contacts.unread_count(&contact_id)
+                }
+                None => tui_state.contacts.total_unread_count(),
+            };
+            Ok(json!(count))
+        }
+        IpcRequest::SetTheme { theme } => {
+            tui_state.config.theme = theme;
+            Ok(Value::Null)
+        }
+    }
+}
+
+fn resolve_contact(tui_state: &TuiState, contact: &ContactRef) -> Result<ContactId, String> {
+    let found = match contact {
+        ContactRef::Id(id) => tui_state
+            .contacts
+            .iter_contacts_and_groups()
+            .find(|c| &c.id == id),
+        ContactRef::Name(name) => tui_state.contacts.contact_or_group_by_name(name),
+    };
+    found
+        .map(|c| c.id.clone())
+        .ok_or_else(|| format!("Unknown contact {contact:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, StreamExt as _};
+
+    use super::*;
+    use crate::{
+        backends::MessageContent, commands::pre_send_finished, message::FrontendMessage,
+        outbox::Outbox, testing::contact,
+    };
+
+    #[test]
+    fn test_socket_is_private() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("chatters.sock");
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .unwrap();
+        let listener = runtime.block_on(async { bind(&path) }).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // only the socket is left
+        let entries = std::fs::read_dir(tmp.path()).unwrap().count();
+        assert_eq!(entries, 1);
+
+        // binding again replaces it
+        drop(listener);
+        let _listener = runtime.block_on(async { bind(&path) }).unwrap();
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_send_message_runs_pre_send_hook() {
+        let (ba_tx, mut ba_rx) = mpsc::unbounded();
+        let (f_tx, mut f_rx) = mpsc::unbounded();
+        let mut tui_state = TuiState {
+            outbox: Outbox::new(f_tx),
+            ..Default::default()
+        };
+        tui_state.config.hooks.pre_send = Some("echo rewritten".to_owned());
+        tui_state
+            .contacts
+            .extend(vec![contact(ContactId::User(vec![1]), "Alice")]);
+
+        let (reply, mut reply_rx) = oneshot::channel();
+        let request = parse_request(
+            r#"{"method": "send-message", "params": {"contact": "Alice", "text": "hi"}}"#,
+        )
+        .unwrap();
+        handle_call(&mut tui_state, &ba_tx, IpcCall { request, reply });
+        assert!(matches!(reply_rx.try_recv(), Ok(None)));
+
+        let Some(FrontendMessage::PreSendFinished { id, outcome }) = block_on(f_rx.next()) else {
+            panic!("expected the pre_send hook to finish");
+        };
+        pre_send_finished(&mut tui_state, &ba_tx, id, outcome);
+        assert_eq!(block_on(reply_rx).unwrap(), Ok(Value::Null));
+        let Some(BackendMessage::SendMessage {
+            content: MessageContent::Text { text, .. },
+            ..
+        }) = block_on(ba_rx.next())
+        else {
+            panic!("expected the message to be sent");
+        };
+        assert_eq!(text, "rewritten");
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    fs::{create_dir_all, File},
+    io::{BufRead as _, BufReader, BufWriter, Write as _},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crossterm::event::KeyEvent;
+use log::warn;
+
+use crate::{
+    backends::timestamp,
+    message::{BackendMessage, FrontendMessage},
+};
+
+/// A single recorded event in the journal.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct JournalEntry {
+    /// When the event was recorded, in milliseconds since the unix epoch.
+    pub timestamp: u64,
+    pub event: JournalEvent,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub enum JournalEvent {
+    /// A message sent to the frontend.
+    Frontend(FrontendMessage),
+    /// A message sent to the backend actor.
+    Backend(BackendMessage),
+    /// A key pressed by the user.
+    Key(KeyEvent),
+}
+
+/// Records messages between the frontend and backend, and the keys pressed, to a file as json
+/// lines, for debugging.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    writer: Arc<Mutex<BufWriter<File>>>,
+}
+
+impl Journal {
+    pub fn new(path: &Path) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        let file = File::create(path)?;
+        Ok(Self {
+            writer: Arc::new(Mutex::new(BufWriter::new(file))),
+        })
+    }
+
+    pub fn record_frontend(&self, message: &FrontendMessage) {
+        if matches!(message, FrontendMessage::Tick | FrontendMessage::Redraw) {
+            // these only trigger redraws so just add noise
+            return;
+        }
+        self.record(&RecordRef::Frontend(message));
+    }
+
+    pub fn record_backend(&self, message: &BackendMessage) {
+        self.record(&RecordRef::Backend(message));
+    }
+
+    pub fn record_key(&self, key: &KeyEvent) {
+        self.record(&RecordRef::Key(key));
+    }
+
+    fn record(&self, event: &RecordRef<'_>) {
+        let entry = JournalEntryRef {
+            timestamp: timestamp(),
+            event,
+        };
+        let mut writer = self.writer.lock().unwrap();
+        let result = serde_json::to_writer(&mut *writer, &entry)
+            .map_err(std::io::Error::from)
+            .and_then(|()| writeln!(writer))
+            .and_then(|()| writer.flush());
+        if let Err(error) = result {
+            warn!(error:%; "Failed to write journal entry");
+        }
+    }
+}
+
+/// Borrowed version of [`JournalEntry`] to avoid cloning messages when recording them.
+#[derive(serde::Serialize)]
+struct JournalEntryRef<'a> {
+    timestamp: u64,
+    event: &'a RecordRef<'a>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename = "JournalEvent")]
+enum RecordRef<'a> {
+    Frontend(&'a FrontendMessage),
+    Backend(&'a BackendMessage),
+    Key(&'a KeyEvent),
+}
+
+/// Read all of the entries from a journal file.
+pub fn read_journal(path: &Path) -> std::io::Result<Vec<JournalEntry>> {
+    let file = File::open(path)?;
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(serde_json::from_str(&line)?);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    use super::*;
+
+    #[test]
+    fn test_journal_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal/session.jsonl");
+        let journal = Journal::new(&path).unwrap();
+        journal.record_backend(&BackendMessage::LoadContacts);
+        journal.record_frontend(&FrontendMessage::Tick);
+        journal.record_frontend(&FrontendMessage::LoadedContacts {
+            contacts: Vec::new(),
+        });
+        journal.record_key(&KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE));
+
+        let entries = read_journal(&path).unwrap();
+        assert_eq!(entries.len(), 3, "{entries:?}");
+        assert!(matches!(
+            entries[0].event,
+            JournalEvent::Backend(BackendMessage::LoadContacts)
+        ));
+        assert!(matches!(
+            entries[1].event,
+            JournalEvent::Frontend(FrontendMessage::LoadedContacts { .. })
+        ));
+        assert!(
+            matches!(entries[2].event, JournalEvent::Key(key) if key.code == KeyCode::Char('j'))
+        );
+        assert!(entries.windows(2).all(|e| e[0].timestamp <= e[1].timestamp));
+    }
+}
@@ -97,6 +97,12 @@ impl FromStr for KeyEvent {
                     "end" => KeyCode::End,
                     "pageup" => KeyCode::PageUp,
                     "pagedown" => KeyCode::PageDown,
+                    "tab" => KeyCode::Tab,
+                    "backtab" | "back tab" => {
+                        // terminals report shift with it
+                        slf.modifiers |= KeyModifiers::SHIFT;
+                        KeyCode::BackTab
+                    }
                     _ => return Err(()),
                 }
             };
@@ -227,20 +233,138 @@ impl<'de> serde::Deserialize<'de> for KeyEvents {
     }
 }
 
+/// What a key binding does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Binding {
+    /// Keys to simulate, such as `:quit<Enter>`, for bindings starting with `:` or `<`.
+    Keys(KeyEvents),
+    /// Command lines to run in order, such as `select-message -1; react 👍`.
+    Commands(Vec<String>),
+}
+
+impl Binding {
+    /// Whether the binding runs the command going by any of the names.
+    pub fn runs(&self, names: &[&str]) -> bool {
+        let command_lines = match self {
+            Self::Keys(keys) => {
+                // typed into the command line, such as `:find ` or `:quit<Enter>`
+                let mut chars = keys.0.iter().map_while(|event| match event.code {
+                    KeyCode::Char(c)
+                        if !event
+                            .modifiers
+                            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+                    {
+                        Some(c)
+                    }
+                    _ => None,
+                });
+                if chars.next() != Some(':') {
+                    return false;
+                }
+                vec![chars.collect()]
+            }
+            Self::Commands(commands) => commands.clone(),
+        };
+        command_lines.iter().any(|command_line| {
+            command_line
+                .split_whitespace()
+                .next()
+                .is_some_and(|name| names.contains(&name))
+        })
+    }
+}
+
+impl FromStr for Binding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with(':') || s.starts_with('<') {
+            return KeyEvents::from_str(s)
+                .map(Self::Keys)
+                .map_err(|()| format!("Failed to parse key events {s:?}"));
+        }
+        let commands = split_commands(s);
+        if commands.is_empty() {
+            return Err("Missing command to run".to_owned());
+        }
+        for command in &commands {
+            crate::commands::check_command_line(command)
+                .map_err(|error| format!("Invalid command {command:?}: {error}"))?;
+        }
+        Ok(Self::Commands(commands))
+    }
+}
+
+impl Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Keys(keys) => write!(f, "{keys}"),
+            Self::Commands(commands) => write!(f, "{}", commands.join("; ")),
+        }
+    }
+}
+
+impl serde::Serialize for Binding {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Binding {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Split command lines on the `;`s between them, leaving those quoted as they are.
+fn split_commands(s: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut command = String::new();
+    let mut quote = None;
+    let mut escaped = false;
+    for c in s.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\'' | '"' if quote == Some(c) => quote = None,
+            '\'' | '"' if quote.is_none() => quote = Some(c),
+            ';' if quote.is_none() => {
+                commands.push(std::mem::take(&mut command));
+                continue;
+            }
+            _ => {}
+        }
+        command.push(c);
+    }
+    commands.push(command);
+    commands
+        .into_iter()
+        .map(|c| c.trim().to_owned())
+        .filter(|c| !c.is_empty())
+        .collect()
+}
+
 #[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct KeyBinds {
     #[serde(default)]
-    pub normal: HashMap<KeyEvents, String>,
+    pub normal: HashMap<KeyEvents, Binding>,
     #[serde(default)]
-    pub command: HashMap<KeyEvents, String>,
+    pub command: HashMap<KeyEvents, Binding>,
     #[serde(default)]
-    pub compose: HashMap<KeyEvents, String>,
+    pub compose: HashMap<KeyEvents, Binding>,
     #[serde(default)]
-    pub popup: HashMap<KeyEvents, String>,
+    pub popup: HashMap<KeyEvents, Binding>,
 }
 
 impl KeyBinds {
-    pub fn get(&self, events: &KeyEvents, mode: Mode) -> Result<&String, bool> {
+    pub fn get(&self, events: &KeyEvents, mode: Mode) -> Result<&Binding, bool> {
         let bindings = match mode {
             Mode::Normal => &self.normal,
             Mode::Command { .. } => &self.command,
@@ -259,7 +383,72 @@ impl KeyBinds {
         Err(prefix)
     }
 
-    pub fn iter(&self, mode: Mode) -> impl Iterator<Item = (&KeyEvents, &String)> {
+    /// Add the bindings from another set, returning those that clash with existing bindings.
+    ///
+    /// Clashing bindings replace the existing ones when forced, otherwise they are skipped.
+    pub fn merge(&mut self, other: KeyBinds, force: bool) -> Vec<Conflict> {
+        let mut conflicts = Vec::new();
+        let modes = [
+            ("normal", &mut self.normal, other.normal),
+            ("command", &mut self.command, other.command),
+            ("compose", &mut self.compose, other.compose),
+            ("popup", &mut self.popup, other.popup),
+        ];
+        for (mode, existing, imported) in modes {
+            let mut imported = imported.into_iter().collect::<Vec<_>>();
+            imported.sort_by_key(|(keys, _)| keys.to_string());
+            for (keys, command) in imported {
+                // a prefix of another binding would never let the longer one be reached
+                let mut clashes = existing
+                    .iter()
+                    .filter(|(k, c)| {
+                        (k.0.starts_with(&keys.0) || keys.0.starts_with(&k.0)) && **c != command
+                    })
+                    .map(|(k, c)| (k.clone(), c.clone()))
+                    .collect::<Vec<_>>();
+                clashes.sort_by_key(|(k, _)| k.to_string());
+                for (existing_keys, existing_command) in &clashes {
+                    conflicts.push(Conflict {
+                        mode,
+                        keys: keys.clone(),
+                        existing_keys: existing_keys.clone(),
+                        existing: existing_command.clone(),
+                        imported: command.clone(),
+                    });
+                }
+                if clashes.is_empty() || force {
+                    for (existing_keys, _) in clashes {
+                        existing.remove(&existing_keys);
+                    }
+                    existing.insert(keys, command);
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// The keys bound to run the command going by any of the names, along with their mode.
+    pub fn bound_to(&self, names: &[&str]) -> Vec<(&'static str, &KeyEvents)> {
+        let modes = [
+            ("normal", &self.normal),
+            ("command", &self.command),
+            ("compose", &self.compose),
+            ("popup", &self.popup),
+        ];
+        let mut bound = Vec::new();
+        for (mode, bindings) in modes {
+            let mut keys = bindings
+                .iter()
+                .filter(|(_, binding)| binding.runs(names))
+                .map(|(keys, _)| (mode, keys))
+                .collect::<Vec<_>>();
+            keys.sort_by_key(|(_, keys)| keys.to_string());
+            bound.extend(keys);
+        }
+        bound
+    }
+
+    pub fn iter(&self, mode: Mode) -> impl Iterator<Item = (&KeyEvents, &Binding)> {
         match mode {
             Mode::Normal => &self.normal,
             Mode::Command { .. } => &self.command,
@@ -270,6 +459,28 @@ impl KeyBinds {
     }
 }
 
+/// A binding that couldn't be imported as the keys are already bound.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub mode: &'static str,
+    pub keys: KeyEvents,
+    /// The existing binding that clashes, which may be a prefix of the imported keys or the
+    /// other way round.
+    pub existing_keys: KeyEvents,
+    pub existing: Binding,
+    pub imported: Binding,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} = {} (already {} = {})",
+            self.mode, self.keys, self.imported, self.existing_keys, self.existing
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -298,4 +509,74 @@ mod tests {
             KeyEvent::from_str("<c-up>").unwrap().to_string(),
         ]);
     }
+
+    #[test]
+    fn parse_binding() {
+        insta::assert_debug_snapshot!([
+            ":quit<Enter>",
+            "select-message -1",
+            "select-message -1; react \"👍\"",
+            "pipe-message 'tr a; b'",
+            "not-a-command",
+            "select-message",
+        ]
+        .map(Binding::from_str));
+    }
+
+    #[test]
+    fn bound_to() {
+        let keybinds: KeyBinds = toml::from_str(
+            r#"
+            [normal]
+            n = ":find-next<Enter>"
+            "/" = ":find "
+            gn = "select-message 0; find-next"
+            q = ":quit<Enter>"
+            [popup]
+            n = "find-next 2"
+            "#,
+        )
+        .unwrap();
+        let bound = keybinds
+            .bound_to(&["find-next"])
+            .into_iter()
+            .map(|(mode, keys)| format!("{mode} {keys}"))
+            .collect::<Vec<_>>();
+        assert_eq!(bound, ["normal gn", "normal n", "popup n"]);
+        assert_eq!(keybinds.bound_to(&["find"]).len(), 1);
+    }
+
+    #[test]
+    fn merge_keybinds() {
+        let mut keybinds: KeyBinds = toml::from_str(
+            r#"
+            [normal]
+            j = "next-message"
+            gg = "select-message 0"
+            q = "quit"
+            "#,
+        )
+        .unwrap();
+        let imported: KeyBinds = toml::from_str(
+            r#"
+            [normal]
+            j = "next-message"
+            g = "goto-bottom"
+            q = ":quit<Enter>"
+            x = "react 👍"
+            "#,
+        )
+        .unwrap();
+        let conflicts = keybinds.clone().merge(imported.clone(), false);
+        insta::assert_debug_snapshot!(conflicts.iter().map(|c| c.to_string()).collect::<Vec<_>>());
+
+        keybinds.merge(imported, true);
+        let mut normal = keybinds
+            .normal
+            .iter()
+            .map(|(k, c)| format!("{k} = {c}"))
+            .collect::<Vec<_>>();
+        normal.sort();
+        insta::assert_debug_snapshot!(normal);
+    }
 }
@@ -1,11 +1,44 @@
+pub mod app;
 pub mod backend_actor;
 pub mod backends;
+pub mod backoff;
+pub mod bridges;
+pub mod clipboard;
 pub mod command_history;
 pub mod commands;
 pub mod config;
+pub mod downloads;
+pub mod emoji_usage;
+pub mod folders;
+pub mod forwards;
+pub mod fuzzy;
 pub mod hooks;
+pub mod html;
+pub mod ipc;
+pub mod journal;
 pub mod keybinds;
 pub mod log;
+pub mod macros;
+pub mod markdown;
 pub mod message;
+pub mod missed;
+pub mod mutes;
+pub mod nicknames;
+pub mod outbox;
+pub mod panes;
+pub mod pins;
+pub mod plugins;
+pub mod receipts;
+pub mod render_filters;
+pub mod secrets;
+pub mod snapshot;
+pub mod spellcheck;
+#[cfg(feature = "tracing")]
+pub mod telemetry;
+#[cfg(test)]
+mod testing;
+pub mod transcript;
+pub mod transfers;
 pub mod tui;
 pub mod util;
+pub mod vault;
@@ -0,0 +1,165 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+
+use crate::keybinds::{KeyEvent, KeyEvents};
+
+#[derive(Debug)]
+struct Recording {
+    register: String,
+    keys: Vec<KeyEvent>,
+    /// Where the latest binding or command line began, to leave out the keys stopping the
+    /// recording.
+    sequence_start: usize,
+}
+
+/// Keys recorded into named registers with `:record`, pressed again with `:play`.
+#[derive(Debug, Default)]
+pub struct Macros {
+    path: PathBuf,
+    registers: BTreeMap<String, KeyEvents>,
+    recording: Option<Recording>,
+    /// Keys to press once the event playing them has been handled.
+    pending: Option<KeyEvents>,
+}
+
+impl Macros {
+    /// Load the macros from the file at the path, starting empty if it doesn't exist yet.
+    pub fn load(path: &Path) -> Self {
+        let registers = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                warn!(error:%, path:?; "Failed to parse macros, starting afresh");
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        Self {
+            path: path.to_owned(),
+            registers,
+            ..Default::default()
+        }
+    }
+
+    pub fn get(&self, register: &str) -> Option<&KeyEvents> {
+        self.registers.get(register)
+    }
+
+    pub fn registers(&self) -> impl Iterator<Item = &str> {
+        self.registers.keys().map(String::as_str)
+    }
+
+    /// The register being recorded into, if recording.
+    pub fn recording(&self) -> Option<&str> {
+        self.recording.as_ref().map(|r| r.register.as_str())
+    }
+
+    /// Start recording the keys pressed into the register, replacing what it had once stopped.
+    pub fn start(&mut self, register: &str) {
+        self.recording = Some(Recording {
+            register: register.to_owned(),
+            keys: Vec::new(),
+            sequence_start: 0,
+        });
+    }
+
+    /// Keep a key the user pressed if recording, `starts_sequence` when it begins a new binding or
+    /// command line.
+    pub fn record(&mut self, key: KeyEvent, starts_sequence: bool) {
+        if let Some(recording) = &mut self.recording {
+            if starts_sequence {
+                recording.sequence_start = recording.keys.len();
+            }
+            recording.keys.push(key);
+        }
+    }
+
+    /// Stop recording, saving the keys pressed up to the ones stopping it, returning the register
+    /// and how many keys it got.
+    pub fn stop(&mut self) -> Option<(String, usize)> {
+        let mut recording = self.recording.take()?;
+        recording.keys.truncate(recording.sequence_start);
+        let len = recording.keys.len();
+        self.registers
+            .insert(recording.register.clone(), KeyEvents(recording.keys));
+        self.save();
+        Some((recording.register, len))
+    }
+
+    /// Queue the keys of the register to be pressed count times, returning whether it has any.
+    pub fn play(&mut self, register: &str, count: usize) -> bool {
+        let Some(keys) = self.registers.get(register).filter(|k| !k.0.is_empty()) else {
+            return false;
+        };
+        let keys = keys.0.iter().cloned().cycle().take(keys.0.len() * count);
+        self.pending = Some(KeyEvents(keys.collect()));
+        true
+    }
+
+    /// The keys queued to be played, if any.
+    pub fn take_pending(&mut self) -> Option<KeyEvents> {
+        self.pending.take()
+    }
+
+    fn save(&self) {
+        if self.path.as_os_str().is_empty() {
+            // not backed by a file
+            return;
+        }
+        let result = serde_json::to_string(&self.registers)
+            .map_err(std::io::Error::from)
+            .and_then(|content| {
+                if let Some(parent) = self.path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&self.path, content)
+            });
+        if let Err(error) = result {
+            warn!(error:%, path:? = self.path; "Failed to save macros");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn keys(s: &str) -> Vec<KeyEvent> {
+        KeyEvents::from_str(s).unwrap().0
+    }
+
+    #[test]
+    fn test_record_and_play() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("macros.json");
+
+        let mut macros = Macros::load(&path);
+        macros.start("a");
+        assert_eq!(macros.recording(), Some("a"));
+        for key in keys("jj:") {
+            macros.record(key, true);
+        }
+        // the rest of `:record<Enter>` is typed in command mode
+        for key in keys("record<Enter>") {
+            macros.record(key, false);
+        }
+        // the `:` started the keys stopping it
+        assert_eq!(macros.stop(), Some(("a".to_owned(), 2)));
+        assert_eq!(macros.recording(), None);
+
+        let loaded = Macros::load(&path);
+        assert_eq!(
+            loaded.get("a").map(|k| k.to_string()),
+            Some("jj".to_owned())
+        );
+
+        assert!(macros.play("a", 2));
+        assert_eq!(macros.take_pending().unwrap().0, keys("jjjj"));
+        assert_eq!(macros.take_pending(), None);
+        assert!(!macros.play("b", 1));
+    }
+}
@@ -0,0 +1,130 @@
+use crate::backends::{FormatKind, Formatting};
+
+/// Delimiters, longest first so `**` isn't taken as two `*`.
+const DELIMITERS: [(&str, FormatKind); 7] = [
+    ("**", FormatKind::Bold),
+    ("__", FormatKind::Bold),
+    ("~~", FormatKind::Strikethrough),
+    ("||", FormatKind::Spoiler),
+    ("*", FormatKind::Italic),
+    ("_", FormatKind::Italic),
+    ("`", FormatKind::Monospace),
+];
+
+/// The text and formatting of a message written with inline Markdown: `**bold**`, `*italic*`,
+/// `~~strikethrough~~`, `` `code` `` and `||spoilers||`.
+///
+/// Delimiters without a partner are kept as they are, as are those escaped with a backslash.
+pub fn parse_markdown(markdown: &str) -> (String, Vec<Formatting>) {
+    let chars: Vec<char> = markdown.chars().collect();
+    let mut text = String::new();
+    let mut len = 0;
+    let mut formatting = Vec::new();
+    // open delimiters, with where they started in the text
+    let mut open: Vec<(&str, FormatKind, usize)> = Vec::new();
+
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '\\' && chars.get(i + 1).is_some_and(|c| c.is_ascii_punctuation()) {
+            text.push(chars[i + 1]);
+            len += 1;
+            i += 2;
+            continue;
+        }
+        let Some(&(delimiter, kind)) = DELIMITERS.iter().find(|(d, _)| starts_with(&chars[i..], d))
+        else {
+            text.push(chars[i]);
+            len += 1;
+            i += 1;
+            continue;
+        };
+        let width = delimiter.len();
+
+        if kind == FormatKind::Monospace {
+            // code is taken literally up to the closing backtick
+            if let Some(end) = chars[i + 1..].iter().position(|c| *c == '`') {
+                let code = &chars[i + 1..i + 1 + end];
+                if !code.is_empty() {
+                    formatting.push(Formatting {
+                        start: len,
+                        length: code.len(),
+                        kind,
+                    });
+                    text.extend(code);
+                    len += code.len();
+                }
+                i += end + 2;
+                continue;
+            }
+        } else if let Some(position) = open.iter().rposition(|(d, _, _)| *d == delimiter) {
+            if closes(&chars, i, width) {
+                let (_, kind, start) = open.remove(position);
+                // anything opened within this is left unclosed
+                open.truncate(position);
+                if len > start {
+                    formatting.push(Formatting {
+                        start,
+                        length: len - start,
+                        kind,
+                    });
+                }
+                i += width;
+                continue;
+            }
+        } else if opens(&chars, i, width) && has_closing(&chars, i + width, delimiter) {
+            open.push((delimiter, kind, len));
+            i += width;
+            continue;
+        }
+        text.push_str(delimiter);
+        len += delimiter.chars().count();
+        i += width;
+    }
+    formatting.sort_by_key(|f| f.start);
+    (text, formatting)
+}
+
+fn starts_with(chars: &[char], delimiter: &str) -> bool {
+    chars
+        .iter()
+        .take(delimiter.len())
+        .copied()
+        .eq(delimiter.chars())
+}
+
+/// Opening delimiters come before text, and for `_` not in the middle of words like
+/// `snake_case`.
+fn opens(chars: &[char], i: usize, width: usize) -> bool {
+    let next = chars.get(i + width);
+    let previous = i.checked_sub(1).map(|p| chars[p]);
+    next.is_some_and(|c| !c.is_whitespace())
+        && (chars[i] != '_' || !previous.is_some_and(char::is_alphanumeric))
+}
+
+/// Closing delimiters come after text, and for `_` not in the middle of words.
+fn closes(chars: &[char], i: usize, width: usize) -> bool {
+    let previous = i.checked_sub(1).map(|p| chars[p]);
+    let next = chars.get(i + width);
+    previous.is_some_and(|c| !c.is_whitespace())
+        && (chars[i] != '_' || !next.is_some_and(|c| c.is_alphanumeric()))
+}
+
+fn has_closing(chars: &[char], from: usize, delimiter: &str) -> bool {
+    let width = delimiter.len();
+    (from..chars.len()).any(|i| starts_with(&chars[i..], delimiter) && closes(chars, i, width))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_markdown() {
+        insta::assert_debug_snapshot!(parse_markdown(
+            "**bold** and *it* or _it_, ~~no~~ `a*b*c` ||secret||"
+        ));
+        insta::assert_debug_snapshot!(parse_markdown(
+            "snake_case_name, 2 * 3 * 4, \\*literal\\*, **unclosed"
+        ));
+    }
+}
@@ -1,11 +1,18 @@
 use std::{ops::Bound, path::PathBuf};
 
-use crate::backends::{Contact, ContactId, Message, MessageContent, Quote};
+use crate::backends::{
+    Connectivity, Contact, ContactDetails, ContactId, DeviceVerification, Invite, Limits,
+    LoadStatus, Message, MessageContent, Quote, Receipt, Transfer,
+};
+use crate::hooks::PreSend;
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub enum BackendMessage {
     LoadContacts,
     LoadMessages {
+        /// Echoed back with the loaded messages so that those no longer wanted can be told apart.
+        #[serde(default)]
+        request_id: u64,
         contact_id: ContactId,
         start_ts: Bound<u64>,
         end_ts: Bound<u64>,
@@ -19,25 +26,158 @@ pub enum BackendMessage {
         contact_id: ContactId,
         timestamp: u64,
         index: usize,
+        /// Who sent the message, echoed back so the download can be saved under their name.
+        #[serde(default)]
+        sender: Vec<u8>,
     },
+    PinMessage {
+        contact_id: ContactId,
+        timestamp: u64,
+        pinned: bool,
+    },
+    DeleteMessage {
+        contact_id: ContactId,
+        timestamp: u64,
+    },
+    LoadPinnedMessages {
+        contact_id: ContactId,
+    },
+    LoadContactDetails {
+        contact_id: ContactId,
+    },
+    LoadMessageReceipts {
+        contact_id: ContactId,
+        timestamp: u64,
+    },
+    FindUser {
+        query: String,
+    },
+    VerifyDevice,
+    ConfirmVerification {
+        confirmed: bool,
+    },
+    SetGroupTitle {
+        contact_id: ContactId,
+        title: String,
+    },
+    SetGroupDescription {
+        contact_id: ContactId,
+        description: String,
+    },
+    LoadInvites,
+    AnswerInvite {
+        contact_id: ContactId,
+        accept: bool,
+    },
+    SyncContacts,
+    LoadSummaries,
 }
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub enum FrontendMessage {
+    /// How far the backend has got loading.
+    LoadProgress {
+        status: LoadStatus,
+    },
+    /// The backend has finished loading and is ready for requests.
+    BackendLoaded {
+        self_id: Vec<u8>,
+        limits: Limits,
+    },
     LoadedContacts {
         contacts: Vec<Contact>,
     },
+    /// The invitations waiting for an answer, replacing those loaded before.
+    LoadedInvites {
+        invites: Vec<Invite>,
+    },
+    /// An invitation arrived while syncing.
+    NewInvite {
+        invite: Invite,
+    },
+    /// The latest message of each conversation, replacing the previews loaded before.
+    LoadedSummaries {
+        summaries: Vec<Message>,
+    },
     LoadedMessages {
+        #[serde(default)]
+        request_id: u64,
         messages: Vec<Message>,
     },
     NewMessage {
         message: Message,
     },
+    /// A message was deleted, such as by its sender or a moderator.
+    RemovedMessage {
+        contact_id: ContactId,
+        timestamp: u64,
+    },
     DownloadedAttachment {
         contact_id: ContactId,
         timestamp: u64,
         index: usize,
+        #[serde(default)]
+        sender: Vec<u8>,
         file_path: PathBuf,
     },
+    /// Downloading an attachment failed, with why for the user.
+    DownloadFailed {
+        contact_id: ContactId,
+        timestamp: u64,
+        index: usize,
+        message: String,
+    },
+    LoadedPinnedMessages {
+        contact_id: ContactId,
+        timestamps: Vec<u64>,
+    },
+    LoadedContactDetails {
+        contact_id: ContactId,
+        details: ContactDetails,
+    },
+    LoadedMessageReceipts {
+        contact_id: ContactId,
+        timestamp: u64,
+        receipts: Vec<Receipt>,
+    },
+    /// The contact found for a query, or why none was.
+    FoundUser {
+        query: String,
+        result: Result<Contact, String>,
+    },
+    /// The connection to the service was lost, is being retried or was restored. Backends should
+    /// only report being online once any messages queued while offline have been delivered.
+    Connectivity {
+        connectivity: Connectivity,
+    },
+    DeviceVerification {
+        verification: DeviceVerification,
+    },
+    /// Who is typing in a conversation now, replacing whoever was before.
+    Typing {
+        contact_id: ContactId,
+        senders: Vec<Vec<u8>>,
+    },
+    /// How far along a download or upload of an attachment is, with a `total` of 0 when it isn't
+    /// known.
+    TransferProgress {
+        transfer: Transfer,
+        transferred: u64,
+        total: u64,
+    },
+    /// The `pre_send` hook has finished with a message waiting in the outbox.
+    PreSendFinished {
+        id: u64,
+        outcome: PreSend,
+    },
+    /// Something went wrong in the background that the user should know about.
+    Error {
+        message: String,
+    },
+    /// The config file was changed on disk, so should be loaded again.
+    ConfigChanged,
+    /// Something shown was updated off the ui thread, such as a render filter finishing.
+    Redraw,
+    /// A second has passed.
     Tick,
 }
@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+
+use crate::backends::ContactId;
+
+/// Messages that arrived in a conversation while disconnected.
+#[derive(Debug, Clone)]
+pub struct MissedConversation {
+    pub contact_id: ContactId,
+    pub messages: usize,
+    pub mentions: usize,
+}
+
+/// Tracks the messages that arrive while the backend is disconnected so that they can be
+/// summarised once it reconnects.
+#[derive(Debug, Default)]
+pub struct Missed {
+    /// When the backend disconnected, if it currently is.
+    disconnected_since: Option<u64>,
+    pending: BTreeMap<ContactId, MissedConversation>,
+    /// The conversations in the last summary, most messages first, for jumping to.
+    summary: Vec<MissedConversation>,
+}
+
+impl Missed {
+    /// Start tracking missed messages, keeping the earlier start if already disconnected.
+    pub fn disconnected(&mut self, now: u64) {
+        if self.disconnected_since.is_none() {
+            self.disconnected_since = Some(now);
+            self.pending.clear();
+        }
+    }
+
+    /// Stop tracking, returning whether anything was missed.
+    pub fn connected(&mut self) -> bool {
+        if self.disconnected_since.take().is_none() {
+            return false;
+        }
+        let mut summary = std::mem::take(&mut self.pending)
+            .into_values()
+            .collect::<Vec<_>>();
+        summary.sort_by_key(|c| std::cmp::Reverse((c.mentions, c.messages)));
+        if summary.is_empty() {
+            return false;
+        }
+        self.summary = summary;
+        true
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected_since.is_some()
+    }
+
+    /// Record a message from someone else that is newer than when its conversation was last read.
+    pub fn add(&mut self, contact_id: &ContactId, mention: bool) {
+        if !self.is_disconnected() {
+            return;
+        }
+        let conversation =
+            self.pending
+                .entry(contact_id.clone())
+                .or_insert_with(|| MissedConversation {
+                    contact_id: contact_id.clone(),
+                    messages: 0,
+                    mentions: 0,
+                });
+        conversation.messages += 1;
+        if mention {
+            conversation.mentions += 1;
+        }
+    }
+
+    /// The conversations from the last summary.
+    pub fn summary(&self) -> &[MissedConversation] {
+        &self.summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missed() {
+        let alice = ContactId::User(vec![1]);
+        let group = ContactId::Group(vec![2]);
+        let mut missed = Missed::default();
+
+        // only counted while disconnected
+        missed.add(&alice, false);
+        assert!(!missed.connected());
+
+        missed.disconnected(10);
+        missed.add(&alice, false);
+        missed.add(&alice, false);
+        missed.add(&group, true);
+        // still the same disconnection
+        missed.disconnected(20);
+        missed.add(&alice, false);
+        assert!(missed.connected());
+        assert!(!missed.is_disconnected());
+
+        // mentions first, then the most messages
+        let summary = missed
+            .summary()
+            .iter()
+            .map(|c| (c.contact_id.clone(), c.messages, c.mentions))
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![(group.clone(), 1, 1), (alice.clone(), 3, 0)]);
+
+        // nothing missed keeps the last summary
+        missed.disconnected(30);
+        assert!(!missed.connected());
+        assert_eq!(missed.summary().len(), 2);
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+
+use crate::backends::{Contact, ContactId};
+
+/// Where a mute came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MuteSource {
+    /// Muted upstream, e.g. from another device.
+    Backend,
+    /// Muted in chatters, kept in the state file.
+    Local,
+}
+
+#[derive(Debug, Clone)]
+pub struct Mute {
+    /// When the mute expires, in milliseconds since the unix epoch. `u64::MAX` means never.
+    pub until: u64,
+    pub source: MuteSource,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StoredMute {
+    contact_id: ContactId,
+    until: u64,
+}
+
+/// Conversations that shouldn't trigger notifications or unread highlighting.
+#[derive(Debug, Default)]
+pub struct Mutes {
+    path: PathBuf,
+    mutes: BTreeMap<ContactId, Mute>,
+}
+
+impl Mutes {
+    /// Load the local mutes from the state file at the path, starting empty if it doesn't exist
+    /// yet.
+    pub fn load(path: &Path) -> Self {
+        let stored: Vec<StoredMute> = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                warn!(error:%, path:?; "Failed to parse mutes, starting afresh");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        let mutes = stored
+            .into_iter()
+            .map(|m| {
+                let mute = Mute {
+                    until: m.until,
+                    source: MuteSource::Local,
+                };
+                (m.contact_id, mute)
+            })
+            .collect();
+        Self {
+            path: path.to_owned(),
+            mutes,
+        }
+    }
+
+    /// Replace the mutes from the backend with those of the freshly synced contacts.
+    ///
+    /// Local mutes take precedence over those from the backend.
+    pub fn sync_from_backend(&mut self, contacts: &[Contact]) {
+        self.mutes.retain(|_, m| m.source != MuteSource::Backend);
+        for contact in contacts {
+            if let Some(until) = contact.muted_until {
+                self.mutes.entry(contact.id.clone()).or_insert(Mute {
+                    until,
+                    source: MuteSource::Backend,
+                });
+            }
+        }
+    }
+
+    /// Mute a conversation until the given time.
+    pub fn mute(&mut self, id: &ContactId, until: u64) {
+        self.mutes.insert(
+            id.clone(),
+            Mute {
+                until,
+                source: MuteSource::Local,
+            },
+        );
+        self.save();
+    }
+
+    /// Unmute a conversation, returning whether it was muted.
+    pub fn unmute(&mut self, id: &ContactId) -> bool {
+        let removed = self.mutes.remove(id);
+        if removed
+            .as_ref()
+            .is_some_and(|m| m.source == MuteSource::Local)
+        {
+            self.save();
+        }
+        removed.is_some()
+    }
+
+    /// Drop any mutes that have expired, returning whether there were any.
+    pub fn remove_expired(&mut self, now: u64) -> bool {
+        let len = self.mutes.len();
+        self.mutes.retain(|_, m| m.until > now);
+        let removed = self.mutes.len() != len;
+        if removed {
+            self.save();
+        }
+        removed
+    }
+
+    pub fn get(&self, id: &ContactId, now: u64) -> Option<&Mute> {
+        self.mutes.get(id).filter(|m| m.until > now)
+    }
+
+    pub fn is_muted(&self, id: &ContactId, now: u64) -> bool {
+        self.get(id, now).is_some()
+    }
+
+    fn save(&self) {
+        if self.path.as_os_str().is_empty() {
+            // not backed by a file
+            return;
+        }
+        let stored = self
+            .mutes
+            .iter()
+            .filter(|(_, m)| m.source == MuteSource::Local)
+            .map(|(id, m)| StoredMute {
+                contact_id: id.clone(),
+                until: m.until,
+            })
+            .collect::<Vec<_>>();
+        let result = serde_json::to_string(&stored)
+            .map_err(std::io::Error::from)
+            .and_then(|content| {
+                if let Some(parent) = self.path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&self.path, content)
+            });
+        if let Err(error) = result {
+            warn!(error:%, path:? = self.path; "Failed to save mutes");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::contact;
+
+    fn muted(id: u8, muted_until: Option<u64>) -> Contact {
+        Contact {
+            muted_until,
+            ..contact(ContactId::User(vec![id]), "")
+        }
+    }
+
+    #[test]
+    fn test_sync_from_backend() {
+        let mut mutes = Mutes::default();
+        let id = |id| ContactId::User(vec![id]);
+        mutes.mute(&id(1), 100);
+
+        mutes.sync_from_backend(&[muted(1, Some(u64::MAX)), muted(2, Some(u64::MAX))]);
+        // muted here first, so that wins over the backend
+        assert_eq!(mutes.get(&id(1), 0).unwrap().source, MuteSource::Local);
+        assert_eq!(mutes.get(&id(1), 0).unwrap().until, 100);
+        assert_eq!(mutes.get(&id(2), 0).unwrap().source, MuteSource::Backend);
+
+        // unmuted upstream
+        mutes.sync_from_backend(&[muted(1, None), muted(2, None)]);
+        assert!(mutes.is_muted(&id(1), 0));
+        assert!(!mutes.is_muted(&id(2), 0));
+    }
+}
@@ -0,0 +1,107 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+
+use crate::backends::{Contact, ContactId};
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StoredNickname {
+    contact_id: ContactId,
+    nickname: String,
+}
+
+/// Names given to contacts locally, shown instead of the names from the backend.
+#[derive(Debug, Default)]
+pub struct Nicknames {
+    path: PathBuf,
+    nicknames: BTreeMap<ContactId, String>,
+}
+
+impl Nicknames {
+    /// Load the nicknames from the state file at the path, starting empty if it doesn't exist
+    /// yet.
+    pub fn load(path: &Path) -> Self {
+        let stored: Vec<StoredNickname> = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                warn!(error:%, path:?; "Failed to parse nicknames, starting afresh");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        Self {
+            path: path.to_owned(),
+            nicknames: stored
+                .into_iter()
+                .map(|s| (s.contact_id, s.nickname))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, id: &ContactId) -> Option<&str> {
+        self.nicknames.get(id).map(String::as_str)
+    }
+
+    /// The name to show for the contact, its nickname if it has one.
+    pub fn name<'a>(&'a self, contact: &'a Contact) -> &'a str {
+        self.get(&contact.id).unwrap_or(&contact.name)
+    }
+
+    /// Give the contact a nickname, going back to its own name when empty.
+    pub fn set(&mut self, id: &ContactId, nickname: &str) {
+        let nickname = nickname.trim();
+        if nickname.is_empty() {
+            self.nicknames.remove(id);
+        } else {
+            self.nicknames.insert(id.clone(), nickname.to_owned());
+        }
+        self.save();
+    }
+
+    fn save(&self) {
+        if self.path.as_os_str().is_empty() {
+            // not backed by a file
+            return;
+        }
+        let stored = self
+            .nicknames
+            .iter()
+            .map(|(id, nickname)| StoredNickname {
+                contact_id: id.clone(),
+                nickname: nickname.clone(),
+            })
+            .collect::<Vec<_>>();
+        let result = serde_json::to_string(&stored)
+            .map_err(std::io::Error::from)
+            .and_then(|content| {
+                if let Some(parent) = self.path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&self.path, content)
+            });
+        if let Err(error) = result {
+            warn!(error:%, path:? = self.path; "Failed to save nicknames");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_saved() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("nicknames.json");
+        let id = ContactId::User(vec![1]);
+
+        let mut nicknames = Nicknames::load(&path);
+        nicknames.set(&id, " Mum ");
+        assert_eq!(Nicknames::load(&path).get(&id), Some("Mum"));
+
+        nicknames.set(&id, "");
+        assert_eq!(Nicknames::load(&path).get(&id), None);
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use futures::channel::mpsc;
+
+use crate::{
+    backends::{Contact, ContactId, MessageAttachment, Quote},
+    hooks::Hooks,
+    message::FrontendMessage,
+};
+
+/// Told whether a message was handed to the backend, or why not.
+pub type Reply = Box<dyn FnOnce(Result<(), String>) + Send>;
+
+/// A message on its way to the backend.
+#[derive(Debug, Clone)]
+pub struct Outgoing {
+    pub contact_id: ContactId,
+    pub text: String,
+    pub attachments: Vec<MessageAttachment>,
+    pub quote: Option<Quote>,
+}
+
+/// Messages waiting for the `pre_send` hook to finish with them.
+///
+/// The hook runs on another thread so a slow one can't freeze the ui, handing its outcome back
+/// as a [`FrontendMessage::PreSendFinished`].
+#[derive(Debug, Default)]
+pub struct Outbox {
+    next_id: u64,
+    waiting: HashMap<u64, Waiting>,
+    /// Used to hand the outcome of the hook back to the ui.
+    done_tx: Option<mpsc::UnboundedSender<FrontendMessage>>,
+}
+
+struct Waiting {
+    outgoing: Outgoing,
+    reply: Option<Reply>,
+}
+
+impl std::fmt::Debug for Waiting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Waiting")
+            .field("outgoing", &self.outgoing)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Outbox {
+    pub fn new(done_tx: mpsc::UnboundedSender<FrontendMessage>) -> Self {
+        Self {
+            next_id: 0,
+            waiting: HashMap::new(),
+            done_tx: Some(done_tx),
+        }
+    }
+
+    /// Start the `pre_send` hook on the message, holding on to it until the hook finishes.
+    ///
+    /// The message is given back if there is no hook to run, or no ui to hand its outcome to.
+    pub fn pre_send(
+        &mut self,
+        hooks: &Hooks,
+        app_name: &str,
+        contact: &Contact,
+        outgoing: Outgoing,
+        reply: Option<Reply>,
+    ) -> Option<(Outgoing, Option<Reply>)> {
+        let Some(done_tx) = self.done_tx.clone().filter(|_| hooks.pre_send.is_some()) else {
+            return Some((outgoing, reply));
+        };
+
+        let id = self.next_id;
+        self.next_id += 1;
+        hooks.do_pre_send(app_name, contact, outgoing.text.clone(), move |outcome| {
+            let _ = done_tx.unbounded_send(FrontendMessage::PreSendFinished { id, outcome });
+        });
+        self.waiting.insert(id, Waiting { outgoing, reply });
+        None
+    }
+
+    /// Take back a message once the hook has finished with it.
+    pub fn take(&mut self, id: u64) -> Option<(Outgoing, Option<Reply>)> {
+        self.waiting.remove(&id).map(|w| (w.outgoing, w.reply))
+    }
+}
@@ -0,0 +1,175 @@
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+use crate::config::Config;
+
+/// The smallest and largest share of the width the contacts pane can take, as a percentage.
+const MIN_CONTACTS_WIDTH: u16 = 10;
+const MAX_CONTACTS_WIDTH: u16 = 90;
+
+/// How much the contacts pane grows or shrinks by at a time, as a percentage of the width.
+const STEP: u16 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PaneLayout {
+    /// The share of the width taken by the contacts pane, as a percentage.
+    pub contacts_width: u16,
+    /// Whether the contacts pane is hidden, leaving the width to the messages.
+    pub contacts_hidden: bool,
+}
+
+impl Default for PaneLayout {
+    fn default() -> Self {
+        Self {
+            contacts_width: 25,
+            contacts_hidden: false,
+        }
+    }
+}
+
+impl PaneLayout {
+    /// The layout set in the config.
+    pub fn from_config(config: &Config) -> Self {
+        let default = Self::default();
+        Self {
+            contacts_width: config
+                .contacts_width
+                .unwrap_or(default.contacts_width)
+                .clamp(MIN_CONTACTS_WIDTH, MAX_CONTACTS_WIDTH),
+            contacts_hidden: config.hide_contacts,
+        }
+    }
+}
+
+/// The layout of the panes, kept in a local state file once changed so that it is the same after
+/// restarting.
+#[derive(Debug, Default)]
+pub struct Panes {
+    path: PathBuf,
+    layout: PaneLayout,
+}
+
+impl Panes {
+    /// Load the layout from the state file at the path, using the one from the config if it hasn't
+    /// been changed yet.
+    pub fn load(path: &Path, config: &Config) -> Self {
+        let layout = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                warn!(error:%, path:?; "Failed to parse pane layout, using the config");
+                PaneLayout::from_config(config)
+            }),
+            Err(_) => PaneLayout::from_config(config),
+        };
+        Self {
+            path: path.to_owned(),
+            layout,
+        }
+    }
+
+    pub fn layout(&self) -> PaneLayout {
+        self.layout
+    }
+
+    /// Make the contacts pane wider by a number of steps, showing it if hidden.
+    pub fn grow_contacts(&mut self, steps: u16) {
+        let width = self
+            .layout
+            .contacts_width
+            .saturating_add(steps.saturating_mul(STEP));
+        self.set(PaneLayout {
+            contacts_width: width.min(MAX_CONTACTS_WIDTH),
+            contacts_hidden: false,
+        });
+    }
+
+    /// Make the contacts pane narrower by a number of steps, showing it if hidden.
+    pub fn shrink_contacts(&mut self, steps: u16) {
+        let width = self
+            .layout
+            .contacts_width
+            .saturating_sub(steps.saturating_mul(STEP));
+        self.set(PaneLayout {
+            contacts_width: width.max(MIN_CONTACTS_WIDTH),
+            contacts_hidden: false,
+        });
+    }
+
+    pub fn toggle_contacts(&mut self) {
+        self.set(PaneLayout {
+            contacts_hidden: !self.layout.contacts_hidden,
+            ..self.layout
+        });
+    }
+
+    fn set(&mut self, layout: PaneLayout) {
+        self.layout = layout;
+        if self.path.as_os_str().is_empty() {
+            // not backed by a file
+            return;
+        }
+        let result = serde_json::to_string(&self.layout)
+            .map_err(std::io::Error::from)
+            .and_then(|content| {
+                if let Some(parent) = self.path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&self.path, content)
+            });
+        if let Err(error) = result {
+            warn!(error:%, path:? = self.path; "Failed to save pane layout");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resize() {
+        let mut panes = Panes::default();
+        panes.grow_contacts(2);
+        assert_eq!(panes.layout().contacts_width, 35);
+        panes.grow_contacts(100);
+        assert_eq!(panes.layout().contacts_width, MAX_CONTACTS_WIDTH);
+        panes.shrink_contacts(100);
+        assert_eq!(panes.layout().contacts_width, MIN_CONTACTS_WIDTH);
+
+        panes.toggle_contacts();
+        assert!(panes.layout().contacts_hidden);
+        // resizing shows it again
+        panes.grow_contacts(1);
+        assert_eq!(
+            panes.layout(),
+            PaneLayout {
+                contacts_width: 15,
+                contacts_hidden: false
+            }
+        );
+    }
+
+    #[test]
+    fn test_kept_between_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("panes.json");
+        let config = Config {
+            contacts_width: Some(40),
+            ..Default::default()
+        };
+        let mut panes = Panes::load(&path, &config);
+        assert_eq!(panes.layout().contacts_width, 40);
+        panes.shrink_contacts(1);
+        panes.toggle_contacts();
+
+        // the changes win over the config
+        let panes = Panes::load(&path, &config);
+        assert_eq!(
+            panes.layout(),
+            PaneLayout {
+                contacts_width: 35,
+                contacts_hidden: true
+            }
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use log::warn;
+
+use crate::backends::ContactId;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Pin {
+    contact_id: ContactId,
+    timestamp: u64,
+}
+
+/// Pinned messages kept in a local file, for backends without their own support for pinning.
+#[derive(Debug, Clone)]
+pub struct PinStore {
+    path: PathBuf,
+    pins: Arc<Mutex<Vec<Pin>>>,
+}
+
+impl PinStore {
+    /// Load the pins from the file at the path, starting empty if it doesn't exist yet.
+    pub fn load(path: &Path) -> Self {
+        let pins = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                warn!(error:%, path:?; "Failed to parse pinned messages, starting afresh");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        Self {
+            path: path.to_owned(),
+            pins: Arc::new(Mutex::new(pins)),
+        }
+    }
+
+    /// Pin or unpin a message, saving the change to the file.
+    pub fn set_pinned(
+        &self,
+        contact_id: &ContactId,
+        timestamp: u64,
+        pinned: bool,
+    ) -> std::io::Result<()> {
+        let mut pins = self.pins.lock().unwrap();
+        let pin = Pin {
+            contact_id: contact_id.clone(),
+            timestamp,
+        };
+        if pinned {
+            if !pins.contains(&pin) {
+                pins.push(pin);
+            }
+        } else {
+            pins.retain(|p| p != &pin);
+        }
+        let content = serde_json::to_string(&*pins)?;
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, content)
+    }
+
+    /// The timestamps of the pinned messages for a contact, in the order they were pinned.
+    pub fn pinned(&self, contact_id: &ContactId) -> Vec<u64> {
+        self.pins
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|p| &p.contact_id == contact_id)
+            .map(|p| p.timestamp)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pins() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pins.json");
+        let alice = ContactId::User(vec![1]);
+        let group = ContactId::Group(vec![2]);
+
+        let pins = PinStore::load(&path);
+        pins.set_pinned(&alice, 20, true).unwrap();
+        pins.set_pinned(&alice, 10, true).unwrap();
+        pins.set_pinned(&alice, 20, true).unwrap();
+        pins.set_pinned(&group, 30, true).unwrap();
+        assert_eq!(pins.pinned(&alice), vec![20, 10]);
+
+        pins.set_pinned(&alice, 20, false).unwrap();
+        assert_eq!(pins.pinned(&alice), vec![10]);
+
+        // kept between runs
+        let pins = PinStore::load(&path);
+        assert_eq!(pins.pinned(&alice), vec![10]);
+        assert_eq!(pins.pinned(&group), vec![30]);
+    }
+}
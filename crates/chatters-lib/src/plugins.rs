@@ -0,0 +1,320 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufRead as _, BufReader, Write as _},
+    process::{Child, ChildStdin, ChildStdout, Stdio},
+    sync::{Arc, Mutex},
+};
+
+use futures::channel::{mpsc, oneshot};
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+
+use crate::{
+    backends::{ContactId, Message},
+    ipc::{ContactRef, IpcCall, IpcRequest},
+};
+
+/// The id of the `initialize` request sent to each plugin on startup.
+const INITIALIZE_ID: u64 = 0;
+
+/// JSON-RPC error code for an unknown method.
+const METHOD_NOT_FOUND: i64 = -32601;
+/// JSON-RPC error code for invalid parameters.
+const INVALID_PARAMS: i64 = -32602;
+/// JSON-RPC error code for a request that couldn't be handled.
+const SERVER_ERROR: i64 = -32000;
+
+/// External processes extending chatters, speaking line-delimited JSON-RPC 2.0 over stdin and
+/// stdout.
+///
+/// Each plugin is sent:
+/// - an `initialize` request on startup, which it can respond to with the commands it provides,
+///   as `{"commands": [{"name": "...", "description": "..."}]}`
+/// - a `message` notification for each new message
+/// - a `command` notification when one of its commands is run
+///
+/// Plugins can call the `send_message` method with a `contact_id` and `text` to send messages,
+/// which are handled by the ui like those sent over ipc.
+#[derive(Debug, Default)]
+pub struct Plugins {
+    plugins: Vec<Plugin>,
+}
+
+/// A command provided by a plugin.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PluginCommand {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Debug)]
+struct Plugin {
+    name: String,
+    child: Child,
+    /// Lines for the writer thread to send to the plugin, so that a plugin that stops reading
+    /// can't block the ui.
+    lines_tx: std::sync::mpsc::Sender<Value>,
+    commands: Arc<Mutex<Vec<PluginCommand>>>,
+}
+
+#[derive(serde::Deserialize)]
+struct Incoming {
+    id: Option<Value>,
+    method: Option<String>,
+    #[serde(default)]
+    params: Value,
+    result: Option<Value>,
+    error: Option<Value>,
+}
+
+#[derive(serde::Deserialize)]
+struct InitializeResult {
+    #[serde(default)]
+    commands: Vec<PluginCommand>,
+}
+
+#[derive(serde::Deserialize)]
+struct SendMessageParams {
+    contact_id: ContactId,
+    text: String,
+}
+
+impl Plugins {
+    /// Launch the configured plugins, given as a map from plugin name to the command to run.
+    pub fn start(
+        config: &BTreeMap<String, String>,
+        app_name: &str,
+        backend_name: &str,
+        calls_tx: &mpsc::UnboundedSender<IpcCall>,
+    ) -> Self {
+        let mut plugins = Vec::new();
+        for (name, script) in config {
+            match Plugin::spawn(name, script, calls_tx.clone()) {
+                Ok(plugin) => {
+                    info!(plugin:% = name; "Started plugin");
+                    plugin.request(
+                        INITIALIZE_ID,
+                        "initialize",
+                        json!({"app_name": app_name, "backend": backend_name}),
+                    );
+                    plugins.push(plugin);
+                }
+                Err(error) => warn!(error:%, plugin:% = name; "Failed to start plugin"),
+            }
+        }
+        Self { plugins }
+    }
+
+    /// Let the plugins observe a new message.
+    pub fn notify_message(&self, message: &Message) {
+        for plugin in &self.plugins {
+            plugin.notify("message", json!({ "message": message }));
+        }
+    }
+
+    /// All of the commands registered by plugins.
+    pub fn commands(&self) -> Vec<PluginCommand> {
+        self.plugins
+            .iter()
+            .flat_map(|p| p.commands.lock().unwrap().clone())
+            .collect()
+    }
+
+    /// Run a plugin command, returning false if no plugin provides it.
+    pub fn run_command(
+        &self,
+        name: &str,
+        args: Vec<String>,
+        contact_id: Option<&ContactId>,
+    ) -> bool {
+        let Some(plugin) = self
+            .plugins
+            .iter()
+            .find(|p| p.commands.lock().unwrap().iter().any(|c| c.name == name))
+        else {
+            return false;
+        };
+        plugin.notify(
+            "command",
+            json!({"name": name, "args": args, "contact_id": contact_id}),
+        );
+        true
+    }
+}
+
+impl Plugin {
+    fn spawn(
+        name: &str,
+        script: &str,
+        calls_tx: mpsc::UnboundedSender<IpcCall>,
+    ) -> std::io::Result<Self> {
+        let mut child = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(script)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let commands = Arc::new(Mutex::new(Vec::new()));
+
+        let (lines_tx, lines_rx) = std::sync::mpsc::channel();
+        let writer_name = name.to_owned();
+        std::thread::spawn(move || write_lines(&writer_name, stdin, lines_rx));
+
+        let reader = Reader {
+            name: name.to_owned(),
+            lines_tx: lines_tx.clone(),
+            commands: Arc::clone(&commands),
+            calls_tx,
+        };
+        std::thread::spawn(move || reader.run(stdout));
+
+        Ok(Self {
+            name: name.to_owned(),
+            child,
+            lines_tx,
+            commands,
+        })
+    }
+
+    fn request(&self, id: u64, method: &str, params: Value) {
+        self.send(json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}));
+    }
+
+    fn notify(&self, method: &str, params: Value) {
+        self.send(json!({"jsonrpc": "2.0", "method": method, "params": params}));
+    }
+
+    fn send(&self, value: Value) {
+        if self.lines_tx.send(value).is_err() {
+            debug!(plugin:% = self.name; "Plugin writer has stopped");
+        }
+    }
+}
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        if let Err(error) = self.child.kill() {
+            debug!(error:%, plugin:% = self.name; "Failed to kill plugin");
+        }
+        let _ = self.child.wait();
+    }
+}
+
+/// Handles the output of a plugin on a background thread.
+struct Reader {
+    name: String,
+    lines_tx: std::sync::mpsc::Sender<Value>,
+    commands: Arc<Mutex<Vec<PluginCommand>>>,
+    calls_tx: mpsc::UnboundedSender<IpcCall>,
+}
+
+impl Reader {
+    fn run(self, stdout: ChildStdout) {
+        for line in BufReader::new(stdout).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(error) => {
+                    warn!(error:%, plugin:% = self.name; "Failed to read from plugin");
+                    break;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Incoming>(&line) {
+                Ok(incoming) => self.handle(incoming),
+                Err(error) => warn!(error:%, plugin:% = self.name; "Invalid message from plugin"),
+            }
+        }
+        info!(plugin:% = self.name; "Plugin closed its output");
+    }
+
+    fn handle(&self, incoming: Incoming) {
+        if let Some(method) = incoming.method {
+            let result = match method.as_str() {
+                "send_message" => self.send_message(incoming.params),
+                _ => Err((METHOD_NOT_FOUND, format!("Unknown method {method:?}"))),
+            };
+            // wait for the ui off the reader thread, so the plugin's other output is still read
+            let name = self.name.clone();
+            let lines_tx = self.lines_tx.clone();
+            std::thread::spawn(move || {
+                let result =
+                    result.and_then(|reply_rx| match futures::executor::block_on(reply_rx) {
+                        Ok(result) => result.map_err(|e| (SERVER_ERROR, e)),
+                        Err(_) => Err((SERVER_ERROR, "Shutting down".to_owned())),
+                    });
+                // notifications don't get a response
+                let Some(id) = incoming.id else {
+                    if let Err((_, message)) = result {
+                        warn!(plugin:% = name, message:%; "Plugin notification failed");
+                    }
+                    return;
+                };
+                let response = match result {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                    Err((code, message)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {"code": code, "message": message},
+                    }),
+                };
+                let _ = lines_tx.send(response);
+            });
+        } else if let Some(error) = incoming.error {
+            warn!(plugin:% = self.name, error:%; "Plugin returned an error");
+        } else if incoming.id == Some(json!(INITIALIZE_ID)) {
+            let result = incoming.result.unwrap_or_default();
+            match serde_json::from_value::<InitializeResult>(result) {
+                Ok(init) => {
+                    debug!(plugin:% = self.name, commands:? = init.commands; "Plugin registered commands");
+                    *self.commands.lock().unwrap() = init.commands;
+                }
+                Err(error) => {
+                    warn!(error:%, plugin:% = self.name; "Invalid initialize result from plugin")
+                }
+            }
+        }
+    }
+
+    /// Hand the message to the ui to send, as it would one from an ipc client, so that it goes
+    /// through the `pre_send` hook and the backend's limits.
+    fn send_message(
+        &self,
+        params: Value,
+    ) -> Result<oneshot::Receiver<Result<Value, String>>, (i64, String)> {
+        let params: SendMessageParams =
+            serde_json::from_value(params).map_err(|e| (INVALID_PARAMS, e.to_string()))?;
+        let (reply, reply_rx) = oneshot::channel();
+        let call = IpcCall {
+            request: IpcRequest::SendMessage {
+                contact: ContactRef::Id(params.contact_id),
+                text: params.text,
+            },
+            reply,
+        };
+        self.calls_tx
+            .unbounded_send(call)
+            .map_err(|e| (SERVER_ERROR, e.to_string()))?;
+        Ok(reply_rx)
+    }
+}
+
+/// Write lines to a plugin until it is dropped or stops reading.
+fn write_lines(name: &str, mut stdin: ChildStdin, lines_rx: std::sync::mpsc::Receiver<Value>) {
+    for value in lines_rx {
+        let result = serde_json::to_writer(&mut stdin, &value)
+            .map_err(std::io::Error::from)
+            .and_then(|()| writeln!(stdin))
+            .and_then(|()| stdin.flush());
+        if let Err(error) = result {
+            warn!(error:%, plugin:% = name; "Failed to write to plugin");
+            break;
+        }
+    }
+}
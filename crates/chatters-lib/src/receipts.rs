@@ -0,0 +1,136 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use log::warn;
+
+use crate::backends::Receipt;
+
+/// How many of the user's latest messages to keep the receipts of.
+const MAX_MESSAGES: usize = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReceiptKind {
+    Delivered,
+    Read,
+}
+
+/// Receipts for the user's messages kept in a local file, for backends which are only told about
+/// receipts as they arrive.
+///
+/// Messages are identified by their timestamp alone, as receipts don't always say which
+/// conversation they are for.
+#[derive(Debug, Clone)]
+pub struct ReceiptStore {
+    path: PathBuf,
+    receipts: Arc<Mutex<BTreeMap<u64, Vec<Receipt>>>>,
+}
+
+impl ReceiptStore {
+    /// Load the receipts from the file at the path, starting empty if it doesn't exist yet.
+    pub fn load(path: &Path) -> Self {
+        let receipts = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                warn!(error:%, path:?; "Failed to parse message receipts, starting afresh");
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        Self {
+            path: path.to_owned(),
+            receipts: Arc::new(Mutex::new(receipts)),
+        }
+    }
+
+    /// Record that messages reached a recipient at a time, saving the change to the file.
+    pub fn record(
+        &self,
+        timestamps: &[u64],
+        recipient: &[u8],
+        kind: ReceiptKind,
+        at: u64,
+    ) -> std::io::Result<()> {
+        let mut receipts = self.receipts.lock().unwrap();
+        for timestamp in timestamps {
+            let message = receipts.entry(*timestamp).or_default();
+            let index = match message.iter().position(|r| r.recipient == recipient) {
+                Some(index) => index,
+                None => {
+                    message.push(Receipt {
+                        recipient: recipient.to_vec(),
+                        delivered: None,
+                        read: None,
+                    });
+                    message.len() - 1
+                }
+            };
+            let receipt = &mut message[index];
+            // reading a message means it was delivered, even if that receipt never came
+            receipt.delivered = receipt.delivered.or(Some(at));
+            if kind == ReceiptKind::Read {
+                receipt.read = receipt.read.or(Some(at));
+            }
+        }
+        while receipts.len() > MAX_MESSAGES {
+            receipts.pop_first();
+        }
+        let content = serde_json::to_string(&*receipts)?;
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, content)
+    }
+
+    /// The receipts for the message with the timestamp, in the order they first arrived.
+    pub fn receipts(&self, timestamp: u64) -> Vec<Receipt> {
+        self.receipts
+            .lock()
+            .unwrap()
+            .get(&timestamp)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("receipts.json");
+        let store = ReceiptStore::load(&path);
+        store
+            .record(&[10, 20], &[1], ReceiptKind::Delivered, 100)
+            .unwrap();
+        store.record(&[10], &[1], ReceiptKind::Read, 200).unwrap();
+        // read without hearing that it was delivered
+        store.record(&[10], &[2], ReceiptKind::Read, 300).unwrap();
+        // only the first of each receipt counts
+        store
+            .record(&[10], &[1], ReceiptKind::Delivered, 400)
+            .unwrap();
+
+        // kept between runs
+        let store = ReceiptStore::load(&path);
+        insta::assert_debug_snapshot!(store.receipts(10));
+        assert_eq!(store.receipts(20).len(), 1);
+        assert!(store.receipts(30).is_empty());
+    }
+
+    #[test]
+    fn test_keeps_latest_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ReceiptStore::load(&dir.path().join("receipts.json"));
+        let timestamps: Vec<u64> = (0..MAX_MESSAGES as u64 + 10).collect();
+        store
+            .record(&timestamps, &[1], ReceiptKind::Delivered, 1)
+            .unwrap();
+        assert!(store.receipts(9).is_empty());
+        assert_eq!(store.receipts(10).len(), 1);
+        assert_eq!(store.receipts(MAX_MESSAGES as u64 + 9).len(), 1);
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    collections::HashMap,
+    io::{Read as _, Write as _},
+    process::Stdio,
+    sync::{mpsc::SyncSender, Arc, LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures::channel::mpsc;
+use log::{debug, warn};
+use regex::Regex;
+
+use crate::message::FrontendMessage;
+
+/// Terminal escape sequences, such as colours, which can't be shown in the message list.
+static ESCAPES: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\x1b(\[[0-9;?]*[ -/]*[@-~]|\][^\x07\x1b]*(\x07|\x1b\\))").unwrap()
+});
+
+/// How many texts can wait to be filtered, more are left until they are rendered again.
+const QUEUE_LENGTH: usize = 64;
+
+/// How long each filter has to finish before it is killed and the text shown unfiltered.
+const FILTER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Programs that transform message text before it is shown, such as `mdcat` or a script to
+/// turn ticket numbers into links.
+///
+/// Each filter is run with `sh -c`, gets the text on stdin and replaces it with its stdout,
+/// passing it on to the next filter. Filters run one text at a time on a background thread and
+/// their output is cached by the text, showing the original until they finish or if any of them
+/// fail.
+#[derive(Debug, Default)]
+pub struct RenderFilters {
+    cache: Arc<Mutex<HashMap<String, Filtered>>>,
+    /// Texts waiting to be filtered by the worker.
+    queue_tx: Option<SyncSender<String>>,
+}
+
+#[derive(Debug)]
+enum Filtered {
+    Running,
+    Done(String),
+    Failed,
+}
+
+impl RenderFilters {
+    /// Start the worker running the filters, which redraws the ui through `redraw_tx` as each
+    /// text is filtered.
+    pub fn new(scripts: Vec<String>, redraw_tx: mpsc::UnboundedSender<FrontendMessage>) -> Self {
+        if scripts.is_empty() {
+            return Self::default();
+        }
+        let cache: Arc<Mutex<HashMap<String, Filtered>>> = Arc::default();
+        let (queue_tx, queue_rx) = std::sync::mpsc::sync_channel::<String>(QUEUE_LENGTH);
+        let worker_cache = Arc::clone(&cache);
+        // stops once the filters are replaced, such as when the config is reloaded
+        std::thread::spawn(move || {
+            for text in queue_rx {
+                let filtered = match run_filters(&scripts, &text) {
+                    Some(filtered) => Filtered::Done(filtered),
+                    None => Filtered::Failed,
+                };
+                worker_cache.lock().unwrap().insert(text, filtered);
+                let _ = redraw_tx.unbounded_send(FrontendMessage::Redraw);
+            }
+        });
+        Self {
+            cache,
+            queue_tx: Some(queue_tx),
+        }
+    }
+
+    /// The filtered version of the text, or none if it is still being filtered or there are
+    /// no filters.
+    pub fn get(&self, text: &str) -> Option<String> {
+        let queue_tx = self.queue_tx.as_ref()?;
+        if text.is_empty() {
+            return None;
+        }
+        let mut cache = self.cache.lock().unwrap();
+        match cache.get(text) {
+            Some(Filtered::Done(filtered)) => return Some(filtered.clone()),
+            Some(Filtered::Running | Filtered::Failed) => return None,
+            None => {}
+        }
+        // with the queue full it is tried again the next time it is rendered
+        if queue_tx.try_send(text.to_owned()).is_ok() {
+            cache.insert(text.to_owned(), Filtered::Running);
+        }
+        None
+    }
+}
+
+fn run_filters(scripts: &[String], text: &str) -> Option<String> {
+    let mut text = text.to_owned();
+    for script in scripts {
+        text = run_filter(script, &text, FILTER_TIMEOUT)?;
+    }
+    Some(ESCAPES.replace_all(&text, "").into_owned())
+}
+
+fn run_filter(script: &str, text: &str, timeout: Duration) -> Option<String> {
+    let mut child = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .inspect_err(|error| warn!(error:%, script; "Failed to spawn render filter"))
+        .ok()?;
+
+    // written and read on other threads so that a filter with a lot of output can't block on
+    // its stdout while we block on its stdin
+    let mut stdin = child.stdin.take().unwrap();
+    let input = text.to_owned();
+    let script_name = script.to_owned();
+    std::thread::spawn(move || {
+        if let Err(error) = stdin.write_all(input.as_bytes()) {
+            debug!(error:%, script = script_name; "Failed to write to render filter");
+        }
+    });
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = stdout.read_to_end(&mut output);
+        output
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+            Ok(None) => {
+                warn!(timeout:?, script; "Render filter timed out, showing the unfiltered text");
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+            Err(error) => {
+                warn!(error:%, script; "Failed to wait for render filter");
+                return None;
+            }
+        }
+    };
+    if !status.success() {
+        debug!(status:%, script; "Render filter failed");
+        return None;
+    }
+    String::from_utf8(reader.join().ok()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_filters() {
+        let scripts = [
+            "tr a-z A-Z".to_owned(),
+            "sed 's/#\\([0-9]*\\)/issue \\1/'".to_owned(),
+        ];
+        insta::assert_debug_snapshot!(run_filters(&scripts, "fixed in #42"));
+        insta::assert_debug_snapshot!(run_filters(
+            &["printf '\\033[1mbold\\033[0m'".to_owned()],
+            ""
+        ));
+        insta::assert_debug_snapshot!(run_filters(&["false".to_owned()], "text"));
+    }
+
+    #[test]
+    fn test_run_filter_large_output_and_timeout() {
+        // more output than a pipe holds, before reading any of the input
+        let text = "x".repeat(1_000_000);
+        let output = run_filter("yes | head -c 1000000; cat", &text, FILTER_TIMEOUT).unwrap();
+        assert_eq!(output.len(), 2_000_000);
+
+        let start = Instant::now();
+        assert_eq!(run_filter("sleep 10", "", Duration::from_millis(100)), None);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether backends may keep secrets in plaintext files when the system keyring can't be used, set
+/// from the config.
+static PLAINTEXT_FALLBACK: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, thiserror::Error)]
+pub enum SecretError {
+    #[error("Failed to use the system keyring: {0}")]
+    Keyring(#[from] keyring::Error),
+    #[error("Failed to use the system keyring: {0}")]
+    Join(#[from] tokio::task::JoinError),
+}
+
+pub fn set_plaintext_fallback(allowed: bool) {
+    PLAINTEXT_FALLBACK.store(allowed, Ordering::Relaxed);
+}
+
+pub fn plaintext_fallback() -> bool {
+    PLAINTEXT_FALLBACK.load(Ordering::Relaxed)
+}
+
+/// Store a secret in the system keyring, replacing any stored under the same name.
+///
+/// The keyring can block while asking to be unlocked, so is used off the async runtime.
+pub async fn store(service: &str, name: &str, secret: String) -> Result<(), SecretError> {
+    let entry = keyring::Entry::new(service, name)?;
+    tokio::task::spawn_blocking(move || entry.set_password(&secret)).await??;
+    Ok(())
+}
+
+/// Remove the secret stored in the system keyring under the name, if there is one.
+pub async fn delete(service: &str, name: &str) -> Result<(), SecretError> {
+    let entry = keyring::Entry::new(service, name)?;
+    match tokio::task::spawn_blocking(move || entry.delete_credential()).await? {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// The secret stored in the system keyring under the name, if there is one.
+pub async fn load(service: &str, name: &str) -> Result<Option<String>, SecretError> {
+    let entry = keyring::Entry::new(service, name)?;
+    match tokio::task::spawn_blocking(move || entry.get_password()).await? {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+
+use crate::backends::{Contact, ContactId, Limits, Message};
+
+/// How many of the latest messages to keep for each conversation.
+const MESSAGES_PER_CONVERSATION: usize = 20;
+/// How many of the most recently active conversations to keep messages for.
+const RECENT_CONVERSATIONS: usize = 20;
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct StoredSnapshot {
+    self_id: Vec<u8>,
+    limits: Limits,
+    contacts: Vec<Contact>,
+    messages: Vec<Message>,
+}
+
+/// A compact copy of the contacts and latest messages, saved on shutdown so that the next start
+/// can show them straight away while the backend is still loading.
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    path: PathBuf,
+    pub self_id: Vec<u8>,
+    pub limits: Limits,
+    pub contacts: Vec<Contact>,
+    messages: BTreeMap<ContactId, Vec<Message>>,
+}
+
+impl Snapshot {
+    /// Load the snapshot from the file at the path, starting empty if it doesn't exist yet.
+    pub fn load(path: &Path) -> Self {
+        let stored: StoredSnapshot = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                warn!(error:%, path:?; "Failed to parse snapshot, starting afresh");
+                StoredSnapshot::default()
+            }),
+            Err(_) => StoredSnapshot::default(),
+        };
+        let mut messages: BTreeMap<ContactId, Vec<Message>> = BTreeMap::new();
+        for message in stored.messages {
+            messages
+                .entry(message.contact_id.clone())
+                .or_default()
+                .push(message);
+        }
+        Self {
+            path: path.to_owned(),
+            self_id: stored.self_id,
+            limits: stored.limits,
+            contacts: stored.contacts,
+            messages,
+        }
+    }
+
+    /// Whether there is nothing to show from a previous run.
+    pub fn is_empty(&self) -> bool {
+        self.contacts.is_empty()
+    }
+
+    /// The latest messages of the conversation, oldest first.
+    pub fn messages(&self, id: &ContactId) -> Vec<Message> {
+        self.messages.get(id).cloned().unwrap_or_default()
+    }
+
+    /// Replace the messages of a conversation with the latest of those freshly loaded.
+    pub fn set_messages(&mut self, id: &ContactId, messages: &[Message]) {
+        let start = messages.len().saturating_sub(MESSAGES_PER_CONVERSATION);
+        self.messages.insert(id.clone(), messages[start..].to_vec());
+    }
+
+    /// Add a new message to its conversation, dropping the oldest beyond the limit.
+    pub fn add_message(&mut self, message: &Message) {
+        let messages = self.messages.entry(message.contact_id.clone()).or_default();
+        messages.push(message.clone());
+        let excess = messages.len().saturating_sub(MESSAGES_PER_CONVERSATION);
+        messages.drain(..excess);
+    }
+
+    /// Save the contacts along with the messages of the most recently active conversations.
+    pub fn save<'a>(
+        &mut self,
+        self_id: &[u8],
+        limits: &Limits,
+        contacts: impl Iterator<Item = &'a Contact>,
+    ) {
+        if self.path.as_os_str().is_empty() {
+            // not backed by a file
+            return;
+        }
+        self.self_id = self_id.to_owned();
+        self.limits = limits.clone();
+        self.contacts = contacts.cloned().collect();
+
+        let mut recent: Vec<_> = self.contacts.iter().collect();
+        recent.sort_by_key(|c| Reverse(c.last_message_timestamp));
+        let messages = recent
+            .into_iter()
+            .take(RECENT_CONVERSATIONS)
+            .flat_map(|c| self.messages(&c.id))
+            .collect();
+
+        let stored = StoredSnapshot {
+            self_id: self.self_id.clone(),
+            limits: self.limits.clone(),
+            contacts: self.contacts.clone(),
+            messages,
+        };
+        let result = serde_json::to_string(&stored)
+            .map_err(std::io::Error::from)
+            .and_then(|content| {
+                if let Some(parent) = self.path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&self.path, content)
+            });
+        if let Err(error) = result {
+            warn!(error:%, path:? = self.path; "Failed to save snapshot");
+        }
+    }
+}
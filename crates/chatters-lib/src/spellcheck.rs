@@ -0,0 +1,175 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead as _, BufReader, Write as _},
+    process::{Child, ChildStdin, ChildStdout, Stdio},
+    sync::{Arc, Mutex},
+};
+
+use futures::channel::mpsc;
+use log::{debug, info, warn};
+
+use crate::message::FrontendMessage;
+
+/// Checks the spelling of words being composed using a program speaking the ispell pipe
+/// protocol, such as `hunspell -a -d en_GB` or `aspell -a`.
+///
+/// Words are checked in the background and cached, so they count as correctly spelled until
+/// the checker has answered.
+#[derive(Debug, Default)]
+pub struct SpellChecker {
+    cache: Arc<Mutex<HashMap<String, Spelling>>>,
+    words_tx: Option<std::sync::mpsc::Sender<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Spelling {
+    Checking,
+    Correct,
+    Misspelled { suggestions: Vec<String> },
+}
+
+impl SpellChecker {
+    pub fn start(script: &str, redraw_tx: mpsc::UnboundedSender<FrontendMessage>) -> Self {
+        let checker = match Checker::spawn(script) {
+            Ok(checker) => checker,
+            Err(error) => {
+                warn!(error:%, script; "Failed to start spell checker");
+                return Self::default();
+            }
+        };
+        info!(script; "Started spell checker");
+
+        let cache = Arc::<Mutex<HashMap<String, Spelling>>>::default();
+        let (words_tx, words_rx) = std::sync::mpsc::channel::<String>();
+        let thread_cache = Arc::clone(&cache);
+        std::thread::spawn(move || {
+            let mut checker = checker;
+            for word in words_rx {
+                let Some(spelling) = checker.check(&word) else {
+                    warn!("Spell checker stopped responding");
+                    break;
+                };
+                thread_cache.lock().unwrap().insert(word, spelling);
+                let _ = redraw_tx.unbounded_send(FrontendMessage::Redraw);
+            }
+        });
+        Self {
+            cache,
+            words_tx: Some(words_tx),
+        }
+    }
+
+    /// The suggested corrections if the word is misspelled, or none if it is spelled correctly
+    /// or hasn't been checked yet.
+    pub fn misspelled(&self, word: &str) -> Option<Vec<String>> {
+        let words_tx = self.words_tx.as_ref()?;
+        let mut cache = self.cache.lock().unwrap();
+        match cache.get(word) {
+            Some(Spelling::Misspelled { suggestions }) => Some(suggestions.clone()),
+            Some(Spelling::Correct | Spelling::Checking) => None,
+            None => {
+                cache.insert(word.to_owned(), Spelling::Checking);
+                let _ = words_tx.send(word.to_owned());
+                None
+            }
+        }
+    }
+}
+
+/// The words in some text, as the spell checker sees them.
+pub fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !is_word_char(c))
+        .map(|w| w.trim_matches('\''))
+        .filter(|w| w.chars().count() > 1 && w.chars().all(|c| c.is_alphabetic() || c == '\''))
+}
+
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '\''
+}
+
+struct Checker {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Checker {
+    fn spawn(script: &str) -> std::io::Result<Self> {
+        let mut child = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(script)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        // the first line identifies the checker
+        let mut banner = String::new();
+        stdout.read_line(&mut banner)?;
+        debug!(banner = banner.trim(); "Spell checker identified itself");
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    fn check(&mut self, word: &str) -> Option<Spelling> {
+        // `^` stops the word being taken as a command
+        writeln!(self.stdin, "^{word}").ok()?;
+        self.stdin.flush().ok()?;
+        let mut spelling = Spelling::Correct;
+        // the result is ended by an empty line
+        loop {
+            let mut line = String::new();
+            if self.stdout.read_line(&mut line).ok()? == 0 {
+                return None;
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                return Some(spelling);
+            }
+            spelling = parse_result(line);
+        }
+    }
+}
+
+impl Drop for Checker {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Parse a line of the ispell pipe protocol's results.
+fn parse_result(line: &str) -> Spelling {
+    match line.chars().next() {
+        // `& word count offset: suggestion, suggestion`, `?` is the same but for guesses
+        Some('&' | '?') => {
+            let suggestions = line
+                .split_once(": ")
+                .map(|(_, s)| s.split(", ").map(str::to_owned).collect())
+                .unwrap_or_default();
+            Spelling::Misspelled { suggestions }
+        }
+        // `# word offset`
+        Some('#') => Spelling::Misspelled {
+            suggestions: Vec::new(),
+        },
+        // `*`, `+ root` or `-` for compounds
+        _ => Spelling::Correct,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_result() {
+        let lines = ["*", "+ walk", "& teh 3 0: the, tech, eh", "# qwzx 0"];
+        insta::assert_debug_snapshot!(lines.map(parse_result));
+        insta::assert_debug_snapshot!(words("don't 'quote' a, b2 c-d").collect::<Vec<_>>());
+    }
+}
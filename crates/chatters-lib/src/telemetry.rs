@@ -0,0 +1,111 @@
+use std::{fs::File, path::PathBuf, sync::Mutex};
+
+use tracing_subscriber::{fmt::format::FmtSpan, layer::SubscriberExt as _, Layer as _, Registry};
+
+/// Command line arguments for exporting tracing spans.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct TraceArgs {
+    /// Write tracing spans, with their durations, to this file as json lines.
+    #[clap(long)]
+    pub trace_file: Option<PathBuf>,
+
+    /// Export tracing spans to this OTLP (gRPC) endpoint, e.g. http://localhost:4317.
+    #[cfg(feature = "otlp")]
+    #[clap(long)]
+    pub otlp_endpoint: Option<String>,
+}
+
+/// Flushes any remaining spans when dropped.
+#[derive(Default)]
+pub struct TracingGuard {
+    #[cfg(feature = "otlp")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otlp")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(error) = provider.shutdown() {
+                log::warn!(error:%; "Failed to shutdown tracer provider");
+            }
+        }
+    }
+}
+
+/// Install a global tracing subscriber exporting spans to the configured targets.
+///
+/// Spans are only recorded when one of the targets is given.
+pub fn init_tracing(args: &TraceArgs) -> TracingGuard {
+    #[allow(unused_mut)]
+    let mut guard = TracingGuard::default();
+    let mut layers = Vec::new();
+
+    if let Some(path) = &args.trace_file {
+        match File::create(path) {
+            Ok(file) => {
+                let layer = tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_span_events(FmtSpan::CLOSE)
+                    .with_writer(Mutex::new(file))
+                    .boxed();
+                layers.push(layer);
+            }
+            Err(error) => log::warn!(error:%, path:?; "Failed to create trace file"),
+        }
+    }
+
+    #[cfg(feature = "otlp")]
+    if let Some(endpoint) = &args.otlp_endpoint {
+        use opentelemetry::trace::TracerProvider as _;
+        use opentelemetry_otlp::WithExportConfig as _;
+
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build();
+        match exporter {
+            Ok(exporter) => {
+                let provider = opentelemetry_sdk::trace::TracerProvider::builder()
+                    .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+                    .build();
+                let tracer = provider.tracer("chatters");
+                layers.push(tracing_opentelemetry::layer().with_tracer(tracer).boxed());
+                guard.provider = Some(provider);
+            }
+            Err(error) => log::warn!(error:%, endpoint:?; "Failed to create OTLP exporter"),
+        }
+    }
+
+    if layers.is_empty() {
+        return guard;
+    }
+
+    let subscriber = Registry::default().with(layers);
+    if let Err(error) = tracing::subscriber::set_global_default(subscriber) {
+        log::warn!(error:%; "Failed to set global tracing subscriber");
+    }
+    guard
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.jsonl");
+        let _guard = init_tracing(&TraceArgs {
+            trace_file: Some(path.clone()),
+            #[cfg(feature = "otlp")]
+            otlp_endpoint: None,
+        });
+        tracing::info_span!("load_contacts").in_scope(|| {});
+
+        // closed spans are written with how long they took
+        let trace = std::fs::read_to_string(&path).unwrap();
+        assert!(trace.contains("load_contacts"), "{trace}");
+        assert!(trace.contains("time.busy"), "{trace}");
+    }
+}
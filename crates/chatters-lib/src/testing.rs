@@ -0,0 +1,48 @@
+//! Helpers shared by the tests of the modules.
+
+use futures::channel::mpsc;
+use ratatui::backend::TestBackend;
+use ratatui::Terminal;
+
+use crate::app::App;
+use crate::backends::{Contact, ContactId, Message, MessageContent, Verification};
+use crate::message::BackendMessage;
+use crate::tui::TuiState;
+
+/// A contact with just an id and a name, for tests to change the rest of.
+pub fn contact(id: ContactId, name: &str) -> Contact {
+    Contact {
+        id,
+        name: name.to_owned(),
+        address: String::new(),
+        last_message_timestamp: None,
+        description: String::new(),
+        muted_until: None,
+        verification: Verification::Unverified,
+        members: None,
+        encrypted: false,
+    }
+}
+
+/// A plain text message without attachments, mentions, formatting or a quote.
+pub fn text_message(contact_id: &ContactId, sender: &[u8], timestamp: u64, text: &str) -> Message {
+    Message {
+        timestamp,
+        sender: sender.to_vec(),
+        contact_id: contact_id.clone(),
+        content: MessageContent::Text {
+            text: text.to_owned(),
+            attachments: Vec::new(),
+            mentions: Vec::new(),
+            formatting: Vec::new(),
+        },
+        quote: None,
+    }
+}
+
+/// An app drawing to an 80x24 terminal in memory, with what it sends to the backend.
+pub fn test_app() -> (App<TestBackend>, mpsc::UnboundedReceiver<BackendMessage>) {
+    let (ba_tx, ba_rx) = mpsc::unbounded();
+    let terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+    (App::new(terminal, ba_tx, TuiState::default(), None), ba_rx)
+}
@@ -0,0 +1,229 @@
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// How to format a transcript of a conversation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    Html,
+    Markdown,
+}
+
+impl TranscriptFormat {
+    /// The format for a file, going by its extension and defaulting to markdown.
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("html" | "htm") => Self::Html,
+            _ => Self::Markdown,
+        }
+    }
+}
+
+impl FromStr for TranscriptFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "html" => Ok(Self::Html),
+            "md" | "markdown" => Ok(Self::Markdown),
+            _ => Err(format!(
+                "unknown transcript format {s:?}, expected html or md"
+            )),
+        }
+    }
+}
+
+/// A message as it appears in a transcript, with names in place of ids.
+#[derive(Debug, Clone)]
+pub struct TranscriptMessage {
+    pub time: String,
+    pub sender: String,
+    pub text: String,
+    /// Who sent the quoted message and its text.
+    pub quote: Option<(String, String)>,
+    /// Each emoji reacted with, and the names of who reacted with it.
+    pub reactions: Vec<(String, Vec<String>)>,
+    /// The names of the attachments, and where they were downloaded to if they have been.
+    pub attachments: Vec<(String, Option<PathBuf>)>,
+}
+
+/// Render the messages of a conversation as a standalone document.
+pub fn render(title: &str, messages: &[TranscriptMessage], format: TranscriptFormat) -> String {
+    match format {
+        TranscriptFormat::Html => render_html(title, messages),
+        TranscriptFormat::Markdown => render_markdown(title, messages),
+    }
+}
+
+fn render_markdown(title: &str, messages: &[TranscriptMessage]) -> String {
+    let mut out = format!("# {title}\n");
+    for message in messages {
+        let _ = write!(out, "\n**{}** · {}\n\n", message.sender, message.time);
+        if let Some((sender, text)) = &message.quote {
+            let first = escape_markdown(text.lines().next().unwrap_or_default());
+            let _ = writeln!(out, "> **{sender}**: {first}\n");
+        }
+        if !message.text.is_empty() {
+            // trailing spaces keep the line breaks within a paragraph
+            let _ = writeln!(
+                out,
+                "{}",
+                escape_markdown(&message.text)
+                    .lines()
+                    .collect::<Vec<_>>()
+                    .join("  \n")
+            );
+        }
+        if !message.attachments.is_empty() {
+            out.push('\n');
+        }
+        for (name, path) in &message.attachments {
+            match path.as_deref().and_then(file_url) {
+                Some(url) => {
+                    let _ = writeln!(out, "- [{name}](<{url}>)");
+                }
+                None => {
+                    let _ = writeln!(out, "- {name} (not downloaded)");
+                }
+            }
+        }
+        if !message.reactions.is_empty() {
+            out.push('\n');
+            let reactions = message
+                .reactions
+                .iter()
+                .map(|(emoji, names)| format!("{emoji} {}", names.join(", ")))
+                .collect::<Vec<_>>();
+            let _ = writeln!(out, "{}", reactions.join(" · "));
+        }
+    }
+    out
+}
+
+/// Styles for the HTML transcript, kept inline so that it is a single file to share.
+const STYLE: &str = "\
+body { font-family: sans-serif; max-width: 50em; margin: auto; }
+.message { margin: 1em 0; }
+.time { color: gray; }
+blockquote { border-left: 3px solid lightgray; margin: 0.5em 0; padding-left: 0.5em; }
+.text { white-space: pre-wrap; }
+";
+
+fn render_html(title: &str, messages: &[TranscriptMessage]) -> String {
+    let title = escape(title);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>\n{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n"
+    );
+    for message in messages {
+        out.push_str("<div class=\"message\">\n");
+        let _ = writeln!(
+            out,
+            "<div><strong>{}</strong> <span class=\"time\">{}</span></div>",
+            escape(&message.sender),
+            escape(&message.time)
+        );
+        if let Some((sender, text)) = &message.quote {
+            let first = text.lines().next().unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "<blockquote><strong>{}</strong>: {}</blockquote>",
+                escape(sender),
+                escape(first)
+            );
+        }
+        if !message.text.is_empty() {
+            let _ = writeln!(out, "<div class=\"text\">{}</div>", escape(&message.text));
+        }
+        if !message.attachments.is_empty() {
+            out.push_str("<ul>\n");
+            for (name, path) in &message.attachments {
+                match path.as_deref().and_then(file_url) {
+                    Some(url) => {
+                        let _ = writeln!(
+                            out,
+                            "<li><a href=\"{}\">{}</a></li>",
+                            escape(&url),
+                            escape(name)
+                        );
+                    }
+                    None => {
+                        let _ = writeln!(out, "<li>{} (not downloaded)</li>", escape(name));
+                    }
+                }
+            }
+            out.push_str("</ul>\n");
+        }
+        if !message.reactions.is_empty() {
+            let reactions = message
+                .reactions
+                .iter()
+                .map(|(emoji, names)| format!("{} {}", escape(emoji), escape(&names.join(", "))))
+                .collect::<Vec<_>>();
+            let _ = writeln!(out, "<div>{}</div>", reactions.join(" · "));
+        }
+        out.push_str("</div>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// A link to a downloaded attachment, which must have an absolute path to have one.
+fn file_url(path: &Path) -> Option<String> {
+    url::Url::from_file_path(path).ok().map(String::from)
+}
+
+/// Keep angle brackets in messages from being taken as HTML, leaving any markdown in them.
+fn escape_markdown(s: &str) -> String {
+    s.replace('<', "\\<")
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_transcript() {
+        let messages = vec![
+            TranscriptMessage {
+                time: "2025-01-02 10:00".to_owned(),
+                sender: "Alice".to_owned(),
+                text: "photos from <the> trip\nenjoy".to_owned(),
+                quote: None,
+                reactions: vec![("👍".to_owned(), vec!["Bob".to_owned(), "Carol".to_owned()])],
+                attachments: vec![
+                    (
+                        "beach.jpg".to_owned(),
+                        Some(PathBuf::from("/tmp/beach photo.jpg")),
+                    ),
+                    ("hills.jpg".to_owned(), None),
+                ],
+            },
+            TranscriptMessage {
+                time: "2025-01-02 10:05".to_owned(),
+                sender: "Bob".to_owned(),
+                text: "lovely".to_owned(),
+                quote: Some((
+                    "Alice".to_owned(),
+                    "photos from <the> trip\nenjoy".to_owned(),
+                )),
+                reactions: Vec::new(),
+                attachments: Vec::new(),
+            },
+        ];
+        insta::assert_snapshot!(
+            "markdown",
+            render("Trip", &messages, TranscriptFormat::Markdown)
+        );
+        insta::assert_snapshot!("html", render("Trip", &messages, TranscriptFormat::Html));
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+
+use crate::backends::{ContactId, Transfer};
+
+/// How far along a transfer is, in bytes, with a `total` of 0 when it isn't known.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransferProgress {
+    pub transferred: u64,
+    pub total: u64,
+}
+
+impl TransferProgress {
+    pub fn percent(&self) -> Option<u64> {
+        (self.total > 0).then(|| (self.transferred.min(self.total) * 100) / self.total)
+    }
+
+    /// A bar of the given width filling up as the transfer goes, with the percentage after it.
+    pub fn bar(&self, width: usize) -> String {
+        match self.percent() {
+            Some(percent) => {
+                let filled = width * percent as usize / 100;
+                format!(
+                    "[{}{}] {percent}%",
+                    "#".repeat(filled),
+                    "-".repeat(width - filled)
+                )
+            }
+            None => format!("[{}] …", "-".repeat(width)),
+        }
+    }
+}
+
+/// Attachments being downloaded or uploaded, as last reported by the backend.
+#[derive(Debug, Default)]
+pub struct Transfers {
+    transfers: BTreeMap<Transfer, TransferProgress>,
+}
+
+impl Transfers {
+    /// Record the progress of a transfer, forgetting it once it has finished.
+    pub fn update(&mut self, transfer: Transfer, transferred: u64, total: u64) {
+        if total > 0 && transferred >= total {
+            self.transfers.remove(&transfer);
+        } else {
+            self.transfers
+                .insert(transfer, TransferProgress { transferred, total });
+        }
+    }
+
+    pub fn remove(&mut self, transfer: &Transfer) {
+        self.transfers.remove(transfer);
+    }
+
+    pub fn download(
+        &self,
+        contact_id: &ContactId,
+        timestamp: u64,
+        index: usize,
+    ) -> Option<TransferProgress> {
+        self.transfers
+            .get(&Transfer::Download {
+                contact_id: contact_id.clone(),
+                timestamp,
+                index,
+            })
+            .copied()
+    }
+
+    /// A short summary of the transfers, such as `↓2 40% ↑1 10%`, or nothing without any.
+    pub fn summary(&self) -> String {
+        let mut downloads = Vec::new();
+        let mut uploads = Vec::new();
+        for (transfer, progress) in &self.transfers {
+            match transfer {
+                Transfer::Download { .. } => downloads.push(*progress),
+                Transfer::Upload { .. } => uploads.push(*progress),
+            }
+        }
+        [("↓", downloads), ("↑", uploads)]
+            .into_iter()
+            .filter(|(_, progresses)| !progresses.is_empty())
+            .map(|(arrow, progresses)| {
+                let count = progresses.len();
+                let total = progresses
+                    .iter()
+                    .fold(TransferProgress::default(), |sum, p| TransferProgress {
+                        transferred: sum.transferred + p.transferred,
+                        total: sum.total + p.total,
+                    });
+                // those without a known size make the overall progress unknown too
+                let known = progresses.iter().all(|p| p.total > 0);
+                match total.percent().filter(|_| known) {
+                    Some(percent) => format!("{arrow}{count} {percent}%"),
+                    None => format!("{arrow}{count}"),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer_summary() {
+        let mut transfers = Transfers::default();
+        let contact_id = ContactId::User(vec![1]);
+        let download = |timestamp| Transfer::Download {
+            contact_id: contact_id.clone(),
+            timestamp,
+            index: 0,
+        };
+        transfers.update(download(1), 25, 100);
+        transfers.update(download(2), 75, 300);
+        transfers.update(download(3), 300, 300);
+        transfers.update(
+            Transfer::Upload {
+                contact_id: contact_id.clone(),
+                path: "/tmp/photo.jpg".into(),
+            },
+            0,
+            0,
+        );
+        insta::assert_debug_snapshot!((
+            transfers.summary(),
+            transfers.download(&contact_id, 1, 0).map(|p| p.bar(10)),
+            transfers.download(&contact_id, 3, 0),
+        ));
+    }
+}
@@ -1,18 +1,24 @@
 use command_line::CommandLine;
 use compose::Compose;
 use contacts::Contacts;
+use emoji_picker::EmojiPicker;
+use file_picker::FilePicker;
 use list::HorizontalList;
+use list::ListState;
 use list::VerticalList;
+use lock::LockScreen;
 use log::warn;
+use messages::MentionNames;
 use messages::Message;
 use messages::Messages;
+use messages::RenderCache;
 use ratatui::layout::Alignment;
 use ratatui::layout::Constraint;
 use ratatui::layout::Direction;
 use ratatui::layout::Flex;
 use ratatui::layout::Layout;
-use ratatui::layout::Margin;
 use ratatui::layout::Rect;
+use ratatui::style::Color;
 use ratatui::style::Style;
 use ratatui::style::Styled;
 use ratatui::style::Stylize;
@@ -28,31 +34,80 @@ use ratatui::widgets::Scrollbar;
 use ratatui::widgets::ScrollbarOrientation;
 use ratatui::widgets::ScrollbarState;
 use ratatui::widgets::Table;
+use ratatui::widgets::TableState;
 use ratatui::Frame;
+use regex::Regex;
+use split::Split;
+use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::path::PathBuf;
+use tabs::Tabs;
 use textwrap::Options;
+use theme::Background;
+use theme::Palette;
+use undo::UndoStack;
+use unicode_segmentation::UnicodeSegmentation as _;
+use unicode_width::UnicodeWidthStr as _;
 
+use crate::backends;
+use crate::backends::timestamp;
+use crate::backends::Connectivity;
 use crate::backends::Contact;
+use crate::backends::ContactDetails;
 use crate::backends::ContactId;
+use crate::backends::DeviceVerification;
+use crate::backends::Invite;
+use crate::backends::Limits;
+use crate::backends::LoadStatus;
+use crate::backends::Mention;
+use crate::backends::Receipt;
+use crate::backends::Verification;
+use crate::bridges::Bridges;
+use crate::commands::Completion;
 use crate::config::Config;
+use crate::config::MessageLayout;
+use crate::config::OwnMessages;
+use crate::config::Theme;
+use crate::config::Timestamps;
+use crate::downloads::AutoDownloadOverrides;
+use crate::emoji_usage::EmojiUsage;
+use crate::folders::Folders;
+use crate::forwards::Forward;
+use crate::forwards::Forwards;
+use crate::keybinds::Binding;
 use crate::keybinds::KeyBinds;
 use crate::keybinds::KeyEvents;
+use crate::macros::Macros;
+use crate::missed::Missed;
+use crate::mutes::Mutes;
+use crate::nicknames::Nicknames;
+use crate::outbox::Outbox;
+use crate::panes::Panes;
+use crate::plugins::Plugins;
+use crate::render_filters::RenderFilters;
+use crate::snapshot::Snapshot;
+use crate::spellcheck;
+use crate::spellcheck::SpellChecker;
+use crate::transfers::Transfers;
+use crate::vault::Vault;
 
 mod command_line;
 mod compose;
 mod contacts;
+pub mod emoji_picker;
+pub mod file_picker;
 mod list;
+pub mod loading;
+pub mod lock;
 pub mod messages;
+pub mod setup;
+pub mod split;
+pub mod status;
+pub mod tabs;
+pub mod theme;
+pub mod undo;
 pub use messages::Quote;
 
-fn timestamp() -> u64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_millis() as u64
-}
-
 #[derive(Debug, Clone, Copy)]
 pub enum BasicMode {
     Normal,
@@ -97,17 +152,46 @@ impl Popup {
 
 #[derive(Debug)]
 pub enum PopupType {
-    MessageInfo { timestamp: u64 },
-    ContactInfo { id: ContactId },
+    MessageInfo {
+        timestamp: u64,
+    },
+    ContactInfo {
+        id: ContactId,
+        /// Only show the fields matching this.
+        search: Option<String>,
+    },
     Keybinds,
     Commands,
     CommandHistory,
+    PinnedMessages {
+        contact_id: ContactId,
+    },
+    MissedMessages,
+    Preview,
+    Verification,
+    EmojiPicker(EmojiPicker),
+    FilePicker(FilePicker),
+    /// Checking who to forward messages to before sending them.
+    ConfirmForward(Forward),
+    /// Checking before deleting several messages, as their conversation and timestamp, along
+    /// with how many of those selected were someone else's and so are left alone.
+    ConfirmDelete {
+        messages: Vec<(ContactId, u64)>,
+        skipped: usize,
+    },
+    /// Text returned by a command to show.
+    Output {
+        title: String,
+        text: String,
+    },
 }
 
 #[derive(Debug, Default)]
 pub struct TuiState {
     pub app_name: String,
     pub self_id: Vec<u8>,
+    pub backend_name: String,
+    pub limits: Limits,
     pub contacts: Contacts,
     pub messages: Messages,
     pub compose: Compose,
@@ -115,57 +199,398 @@ pub struct TuiState {
     pub mode: Mode,
     pub popup: Option<Popup>,
     pub key_events: KeyEvents,
+    /// The count being typed before a key binding in normal mode or a popup, such as the 5 of `5j`.
+    pub count_prefix: Option<usize>,
+    /// The count for the commands of the key binding being run.
+    pub count: Option<usize>,
     pub config: Config,
     pub config_path: PathBuf,
+    pub plugins: Plugins,
+    pub render_filters: RenderFilters,
+    pub bridges: Bridges,
+    pub spell_checker: SpellChecker,
+    /// Timestamps of the pinned messages for each contact, as last loaded from the backend.
+    pub pinned_messages: BTreeMap<ContactId, Vec<u64>>,
+    /// Details of the contacts whose info has been shown, as last loaded from the backend.
+    pub contact_details: BTreeMap<ContactId, ContactDetails>,
+    /// Receipts of the user's messages whose info has been shown, as last loaded from the backend.
+    pub message_receipts: BTreeMap<(ContactId, u64), Vec<Receipt>>,
+    pub emoji_usage: EmojiUsage,
+    pub mutes: Mutes,
+    /// Names given to contacts locally, shown instead of their own.
+    pub nicknames: Nicknames,
+    /// Folders conversations were moved into, alongside those in the config.
+    pub folders: Folders,
+    /// Invitations to conversations waiting for `:accept-invite` or `:decline-invite`, shown
+    /// above the contacts.
+    pub invites: Vec<Invite>,
+    /// How wide the contacts pane is and whether it is shown, kept across restarts.
+    pub panes: Panes,
+    /// Conversations which always or never download attachments as they arrive.
+    pub auto_downloads: AutoDownloadOverrides,
+    /// Keys recorded with `:record`, kept next to the config.
+    pub macros: Macros,
+    /// Drafts and mutes as they were before being changed, for `:undo` and `:redo`.
+    pub undo: UndoStack,
+    pub missed: Missed,
+    /// The latest progress of verifying this device, if one has been started.
+    pub device_verification: Option<DeviceVerification>,
+    /// The terminal's background, if it reported one.
+    pub background: Option<Background>,
+    /// The latest message of each conversation, previewed in the contacts list.
+    pub previews: BTreeMap<ContactId, backends::Message>,
+    /// Where to return the latest call in each conversation, for `:call-back`.
+    pub call_links: BTreeMap<ContactId, String>,
+    /// Who is typing in each conversation, as last reported by the backend.
+    pub typing: BTreeMap<ContactId, Vec<Vec<u8>>>,
+    /// The contacts and latest messages from the last run, shown until the backend has loaded.
+    pub snapshot: Snapshot,
+    /// Showing the snapshot while waiting for the backend to load the contacts.
+    pub syncing: bool,
+    /// How far the backend has got catching up while syncing.
+    pub load_status: LoadStatus,
+    /// When the backend was last asked to sync the contacts, in milliseconds since the epoch.
+    pub contacts_synced_at: u64,
+    pub transfers: Transfers,
+    /// Forwarded messages waiting for their attachments to download.
+    pub forwards: Forwards,
+    /// Messages waiting on the `pre_send` hook.
+    pub outbox: Outbox,
+    /// The id of the latest request to load messages, older responses are for conversations no
+    /// longer selected.
+    pub messages_request: u64,
+    /// The id given to the last request to load messages, for either pane.
+    pub last_request_id: u64,
+    /// The conversation shown beside the selected one, if split.
+    pub split: Option<Split>,
+    /// Conversations open in tabs, the active one being the selected contact.
+    pub tabs: Tabs,
+    /// Whether the backend can reach the service, as last reported.
+    pub connectivity: Connectivity,
+    /// The quoted message to select once the messages being loaded for it arrive.
+    pub goto_after_load: Option<u64>,
+    /// Keeps the backend's data encrypted while not running, if turned on in the config.
+    pub vault: Option<Vault>,
+    /// Shown in place of everything else while locked.
+    pub lock: Option<LockScreen>,
+    /// The search through the loaded messages from `:find`, highlighted where it matches.
+    pub find: Option<Regex>,
+    /// The message whose full date and time is shown instead of its age, while it is selected.
+    pub revealed_timestamp: Option<u64>,
+    /// Showing only the selected conversation and compose across the whole width, with the status
+    /// line only while typing a command.
+    pub focus: bool,
+    /// Something shown has changed since the last draw.
+    pub dirty: bool,
+    /// When something shown next changes with time, such as an age, in milliseconds since the
+    /// epoch.
+    pub redraw_at: u64,
+    pub areas: Areas,
+}
+
+/// Where the panes were last drawn, to find what the mouse is over.
+#[derive(Debug, Default)]
+pub struct Areas {
+    /// The rows of contacts, without the border and scrollbar.
+    pub contacts: Rect,
+    /// The index of the contact on each row of the contacts area, from the top, none for folder
+    /// headings.
+    pub contact_rows: Vec<Option<usize>>,
+    /// The messages, without the scrollbar.
+    pub messages: Rect,
+    /// The index of the message on each row of the messages area, from the top, and the text
+    /// shown on that row.
+    pub message_rows: Vec<(usize, String)>,
+}
+
+impl TuiState {
+    /// Whether a message mentions the user, either among those it mentions or by name in its
+    /// text, e.g. `@Alice`.
+    pub fn mentions_self<'a>(
+        &self,
+        text: &str,
+        mentioned: impl IntoIterator<Item = &'a [u8]>,
+    ) -> bool {
+        if mentioned.into_iter().any(|id| id == self.self_id) {
+            return true;
+        }
+        let Some(self_contact) = self.contacts.contact_by_id(&self.self_id) else {
+            return false;
+        };
+        let mention = format!("@{}", self_contact.name).to_lowercase();
+        text.to_lowercase().contains(&mention)
+    }
+
+    /// The folder a conversation is grouped under in the contacts list, if any.
+    pub fn folder(&self, contact: &Contact) -> Option<&str> {
+        self.folders
+            .folder(contact, self.nicknames.name(contact), &self.config.folders)
+    }
+
+    /// The colours for the configured theme.
+    pub fn palette(&self) -> Palette {
+        Palette::for_background(self.theme_background())
+    }
+
+    /// The background the colours are chosen for.
+    fn theme_background(&self) -> Background {
+        match self.config.theme {
+            Theme::Auto => self.background.unwrap_or(Background::Dark),
+            Theme::Dark => Background::Dark,
+            Theme::Light => Background::Light,
+        }
+    }
+
+    /// The colour configured for the sender with the name, if any.
+    pub fn configured_sender_color(&self, name: &str) -> Option<Color> {
+        self.config.sender_colors.names.get(name).map(|c| c.0)
+    }
+
+    /// The colour to show a sender's name in: as configured for them, that of the user's own
+    /// messages for theirs, otherwise one of their own in group conversations, picked by their id
+    /// so that it stays the same even if they change their name.
+    pub fn sender_color(&self, sender: &[u8], name: &str, group: bool) -> Color {
+        if let Some(color) = self.configured_sender_color(name) {
+            color
+        } else if sender == self.self_id {
+            self.palette().own
+        } else if group && !self.config.sender_colors.uniform {
+            self.hashed_sender_color(&hex::encode(sender))
+        } else {
+            self.palette().sender
+        }
+    }
+
+    /// A colour for the sender with the key, from the configured colours for the theme if there
+    /// are any.
+    pub fn hashed_sender_color(&self, key: &str) -> Color {
+        let configured = match self.theme_background() {
+            Background::Dark => &self.config.sender_colors.dark,
+            Background::Light => &self.config.sender_colors.light,
+        };
+        let colors = configured.iter().map(|c| c.0).collect::<Vec<_>>();
+        theme::pick_color(&colors, key).unwrap_or_else(|| self.palette().sender_color(key))
+    }
+
+    /// Whether the terminal needs drawing again, either for a change or for the ages shown to
+    /// move on.
+    pub fn needs_redraw(&self, now: u64) -> bool {
+        self.dirty || now >= self.redraw_at
+    }
+
+    /// Work out when something shown next changes with time, to only go through the messages and
+    /// contacts again when they change or that time comes.
+    pub fn update_redraw_at(&mut self, now: u64) {
+        const MINUTE: u64 = 60 * 1000;
+        let contacts = self
+            .contacts
+            .iter_contacts_and_groups()
+            .filter_map(|c| c.last_message_timestamp);
+        let messages = std::iter::once(&self.messages)
+            .chain(self.split.as_ref().map(|s| &s.messages))
+            .flat_map(|ms| ms.messages_by_ts.values())
+            .map(|m| m.edits.last().map_or(m.timestamp, |e| e.timestamp));
+        let mut redraw_at = contacts
+            .chain(messages)
+            .map(|ts| next_age_change(ts, now))
+            .min()
+            .unwrap_or(u64::MAX);
+        if self
+            .config
+            .status_format
+            .as_ref()
+            .is_some_and(|f| f.contains("{time}"))
+        {
+            redraw_at = redraw_at.min((now / MINUTE + 1) * MINUTE);
+        }
+        if self.config.timestamps != Timestamps::Relative {
+            // times from today become dates
+            redraw_at = redraw_at.min(next_midnight(now));
+        }
+        self.redraw_at = redraw_at;
+    }
 }
 
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
 pub fn render(frame: &mut Frame<'_>, tui_state: &mut TuiState) {
+    if let Some(lock) = &tui_state.lock {
+        lock.render(frame);
+        return;
+    }
     let now = timestamp();
     let area = frame.area();
+    let status_height = if tui_state.focus && !matches!(tui_state.mode, Mode::Command { .. }) {
+        0
+    } else {
+        1
+    };
     let vertical_splits = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Min(1),
-            Constraint::Length(1),
+            Constraint::Length(status_height),
             Constraint::Length(1),
         ])
         .split(area);
 
+    let mut main_rect = vertical_splits[0];
+    if !tui_state.tabs.is_empty() && !tui_state.focus {
+        let [tabs_rect, rest] =
+            Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(main_rect);
+        render_tabs(frame, tabs_rect, tui_state);
+        main_rect = rest;
+    }
+
+    let layout = tui_state.panes.layout();
+    let contacts_hidden = layout.contacts_hidden || tui_state.focus;
+    let contacts_width = if contacts_hidden {
+        0
+    } else {
+        layout.contacts_width
+    };
     let contacts_messages =
-        Layout::horizontal([Constraint::Percentage(25), Constraint::Percentage(75)])
-            .split(vertical_splits[0]);
+        Layout::horizontal([Constraint::Percentage(contacts_width), Constraint::Fill(1)])
+            .split(main_rect);
 
-    render_contacts(frame, contacts_messages[0], tui_state, now);
+    if contacts_hidden {
+        tui_state.areas.contacts = Rect::default();
+    } else {
+        render_contacts(frame, contacts_messages[0], tui_state, now);
+    }
+
+    let header_height = if tui_state.config.hide_header || tui_state.focus {
+        0
+    } else {
+        1
+    };
+    let mut conversation_rect = contacts_messages[1];
+    match tui_state.split.as_ref().map(|split| split.on_left) {
+        Some(on_left) if !tui_state.focus => {
+            let [left, right] = Layout::horizontal([Constraint::Fill(1), Constraint::Fill(1)])
+                .areas(conversation_rect);
+            let (other, focused) = if on_left {
+                (left, right)
+            } else {
+                (right, left)
+            };
+            // drawn first so that the mouse works on the focused pane
+            render_split(frame, other, tui_state, header_height, now);
+            conversation_rect = focused;
+        }
+        _ => {}
+    }
 
     let compose_height = tui_state.compose.height();
-    let message_rect = Layout::vertical([Constraint::Fill(1), Constraint::Length(compose_height)])
-        .split(contacts_messages[1]);
+    let message_rect = Layout::vertical([
+        Constraint::Length(header_height),
+        Constraint::Fill(1),
+        Constraint::Length(compose_height),
+    ])
+    .split(conversation_rect);
 
-    render_messages(frame, message_rect[0], tui_state, now);
-    render_compose(frame, message_rect[1], tui_state, now);
+    if let Some(contact) = tui_state.contacts.selected() {
+        render_header(frame, message_rect[0], tui_state, contact, now);
+    }
+    let group = tui_state
+        .contacts
+        .selected()
+        .is_some_and(|c| matches!(c.id, ContactId::Group(_)));
+    render_messages(frame, message_rect[1], tui_state, group, now);
+    render_compose(frame, message_rect[2], tui_state, now);
 
-    render_status(frame, vertical_splits[1], tui_state, now);
+    if status_height > 0 {
+        render_status(frame, vertical_splits[1], tui_state, now);
+    }
     render_command(frame, vertical_splits[2], tui_state, now);
 
     render_popup(frame, area, tui_state);
 }
 
 fn render_contacts(frame: &mut Frame<'_>, rect: Rect, tui_state: &mut TuiState, now: u64) {
-    let contact_items: Vec<_> = tui_state
-        .contacts
-        .iter_contacts_and_groups()
-        .map(|c| {
-            let age = if let Some(ts) = c.last_message_timestamp {
-                biggest_duration_string(now.saturating_sub(ts))
-            } else {
+    let palette = tui_state.palette();
+    // less the border, scrollbar and age column
+    let name_width = (rect.width as usize).saturating_sub(1 + 1 + 1 + 3);
+    let contact_height = if tui_state.config.contact_previews {
+        2
+    } else {
+        1
+    };
+    let contact_row = |c: &Contact| {
+        let age = if let Some(ts) = c.last_message_timestamp {
+            biggest_duration_string(now.saturating_sub(ts))
+        } else {
+            String::new()
+        };
+        let unread = tui_state.contacts.unread_count(&c.id);
+        let verification = match c.verification {
+            Verification::Unverified => Span::from(""),
+            Verification::Verified => Span::from(" ✔").fg(palette.verified),
+            Verification::Changed => Span::from(" ⚠").fg(palette.warning),
+        };
+        let name = |suffix: String| {
+            let width = name_width.saturating_sub(verification.width() + suffix.width());
+            Line::from(vec![
+                Span::from(truncate_middle(tui_state.nicknames.name(c), width)),
+                verification,
+                Span::from(suffix),
+            ])
+        };
+        let mentions = tui_state.contacts.mention_count(&c.id);
+        let name = if tui_state.mutes.is_muted(&c.id, now) {
+            name(" 🔕".to_owned()).dim()
+        } else if mentions > 0 {
+            name(format!(" ({unread}, @{mentions})"))
+                .bold()
+                .fg(palette.mention)
+        } else if unread > 0 {
+            name(format!(" ({unread})")).bold()
+        } else {
+            name(String::new())
+        };
+        let mut name = Text::from(name);
+        if tui_state.config.contact_previews {
+            name.push_line(Line::from(contact_preview(tui_state, c, name_width)).dim());
+        }
+        Row::new(vec![name, Text::from(age).alignment(Alignment::Right)]).height(contact_height)
+    };
+    let mut contact_items = Vec::new();
+    // the index of the contact on each row, none for headings and invites
+    let mut row_contacts = Vec::new();
+    if !tui_state.invites.is_empty() {
+        contact_items.push(Row::new(vec![Text::from("Invites").bold().underlined()]));
+        row_contacts.push(None);
+        for invite in &tui_state.invites {
+            let from = if invite.inviter.is_empty() {
                 String::new()
+            } else {
+                format!(" from {}", invite.inviter)
             };
-            Row::new(vec![
-                Text::from(c.name.to_string()),
-                Text::from(age).alignment(Alignment::Right),
-            ])
-        })
-        .collect();
+            let name = truncate_end(&format!("{}{from}", invite.name), name_width);
+            contact_items.push(Row::new(vec![Text::from(name).italic()]));
+            row_contacts.push(None);
+        }
+        // so that conversations outside of folders don't look like more invites
+        let first = tui_state.contacts.iter_contacts_and_groups().next();
+        if first.is_some_and(|c| tui_state.folder(c).is_none()) {
+            contact_items.push(Row::new(vec![Text::from("Conversations")
+                .bold()
+                .underlined()]));
+            row_contacts.push(None);
+        }
+    }
+    let mut folder = None;
+    for (i, c) in tui_state.contacts.iter_contacts_and_groups().enumerate() {
+        let contact_folder = tui_state.folder(c);
+        if let Some(name) = contact_folder.filter(|_| contact_folder != folder) {
+            contact_items.push(Row::new(vec![Text::from(name.to_owned())
+                .bold()
+                .underlined()]));
+            row_contacts.push(None);
+        }
+        folder = contact_folder;
+        contact_items.push(contact_row(c));
+        row_contacts.push(Some(i));
+    }
     let contact_items_len = contact_items.len();
     let block = Block::new().borders(Borders::RIGHT);
     let area = block.inner(rect);
@@ -178,64 +603,490 @@ fn render_contacts(frame: &mut Frame<'_>, rect: Rect, tui_state: &mut TuiState,
         frame,
         area,
         contact_items_len,
-        tui_state.contacts.state.offset(),
+        tui_state.contacts.row_offset,
     );
 
-    frame.render_stateful_widget(contacts, remaining_area, &mut tui_state.contacts.state);
+    let selected = tui_state.contacts.state.selected();
+    let mut state = TableState::new()
+        .with_offset(tui_state.contacts.row_offset)
+        .with_selected(selected.and_then(|s| row_contacts.iter().position(|r| *r == Some(s))));
+    frame.render_stateful_widget(contacts, remaining_area, &mut state);
+    tui_state.contacts.row_offset = state.offset();
+    tui_state.areas.contacts = remaining_area;
+    // contacts take up more than one line with their previews
+    tui_state.areas.contact_rows = row_contacts
+        .split_off(state.offset().min(row_contacts.len()))
+        .into_iter()
+        .flat_map(|row| {
+            let height = if row.is_some() { contact_height } else { 1 };
+            std::iter::repeat_n(row, usize::from(height))
+        })
+        .collect();
 }
 
-fn render_messages(frame: &mut Frame<'_>, rect: Rect, tui_state: &mut TuiState, now: u64) {
-    let message_width = rect.width as usize - 1;
-    let message_items = tui_state.messages.messages_by_ts.values().map(|m| {
-        let sender_width = 20;
-        let sender = tui_state
+/// The latest message of a conversation on a single line, after who sent it if not obvious.
+fn contact_preview(tui_state: &TuiState, contact: &Contact, width: usize) -> String {
+    let Some(backends::Message {
+        sender,
+        content:
+            backends::MessageContent::Text {
+                text,
+                attachments,
+                mentions,
+                ..
+            },
+        ..
+    }) = tui_state.previews.get(&contact.id)
+    else {
+        return String::new();
+    };
+    let text = messages::name_mentions(text, mentions, &mention_names(tui_state, mentions));
+    let mut text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() && !attachments.is_empty() {
+        let plural = if attachments.len() == 1 { "" } else { "s" };
+        text = format!("{} attachment{plural}", attachments.len());
+    }
+    let preview = if *sender == tui_state.self_id {
+        format!("You: {text}")
+    } else if matches!(contact.id, ContactId::Group(_)) {
+        format!("{}: {text}", sender_name(tui_state, sender))
+    } else {
+        text
+    };
+    truncate_end(&preview, width)
+}
+
+/// The open tabs, named after their conversations, with the active one highlighted and those
+/// with unread messages in bold.
+fn render_tabs(frame: &mut Frame<'_>, rect: Rect, tui_state: &TuiState) {
+    let active = tui_state.tabs.active();
+    let selected = tui_state.contacts.selected().map(|c| &c.id);
+    let mut spans = Vec::new();
+    for (i, contact_id) in tui_state.tabs.contact_ids().enumerate() {
+        // the active tab follows the selected conversation
+        let contact_id = if i == active {
+            selected.unwrap_or(contact_id)
+        } else {
+            contact_id
+        };
+        let name = tui_state
             .contacts
-            .contact_by_id(&m.sender)
-            .map(|c| c.name.clone())
-            .unwrap();
-        let sender = truncate_or_pad(sender, sender_width);
-        let age = biggest_duration_string(
-            now.saturating_sub(m.edits.last().map_or(m.timestamp, |e| e.timestamp)),
-        );
-        let sender_time = format!("{sender} {age:>3} ");
+            .iter_contacts_and_groups()
+            .find(|c| c.id == *contact_id)
+            .map_or_else(
+                || contact_id.to_string(),
+                |c| tui_state.nicknames.name(c).to_owned(),
+            );
+        let tab = Span::from(format!(" {} {} ", i + 1, truncate_end(&name, 20)));
+        spans.push(if i == active {
+            tab.reversed()
+        } else if tui_state.contacts.unread_count(contact_id) > 0 {
+            tab.bold()
+        } else {
+            tab
+        });
+    }
+    frame.render_widget(Line::from(spans), rect);
+}
 
-        let content_width = message_width
-            .saturating_sub(sender_time.len())
-            .saturating_sub(1);
-        let content_indent = " ".repeat(sender_time.len());
+/// A summary of the selected conversation: its name, members, encryption, whether it is muted
+/// and who is typing, followed by as much of the description as fits.
+fn render_header(
+    frame: &mut Frame<'_>,
+    rect: Rect,
+    tui_state: &TuiState,
+    contact: &Contact,
+    now: u64,
+) {
+    if rect.height == 0 {
+        return;
+    }
+    let palette = tui_state.palette();
+    let width = rect.width as usize;
+    let name = truncate_end(tui_state.nicknames.name(contact), width);
+    let mut used = name.width();
+    let mut spans = vec![Span::from(name).bold()];
 
-        let content_lines = m.render(content_width);
-        if content_lines.is_empty() {
-            warn!(message:? = m; "Message with no information...");
+    let mut details = Vec::new();
+    if let Some(members) = contact.members {
+        details.push(Span::from(format!("{members} members")));
+    }
+    details.push(if contact.encrypted {
+        Span::from("encrypted").fg(palette.verified)
+    } else {
+        Span::from("unencrypted").fg(palette.warning)
+    });
+    if tui_state.mutes.is_muted(&contact.id, now) {
+        details.push(Span::from("muted"));
+    }
+    let typing: Vec<_> = tui_state
+        .typing
+        .get(&contact.id)
+        .into_iter()
+        .flatten()
+        .map(|sender| sender_name(tui_state, sender))
+        .collect();
+    match typing.as_slice() {
+        [] => {}
+        [one] => details.push(Span::from(format!("{one} is typing…")).italic()),
+        [one, two] => details.push(Span::from(format!("{one} and {two} are typing…")).italic()),
+        _ => details.push(Span::from(format!("{} people are typing…", typing.len())).italic()),
+    }
+    for detail in details {
+        // stop at the first detail that doesn't fit, rather than cutting it short
+        if used + 3 + detail.width() > width {
+            break;
         }
+        used += 3 + detail.width();
+        spans.push(Span::from(" · ").dim());
+        spans.push(detail);
+    }
+
+    // the description is whatever fits after the rest, on its first line
+    let description = contact.description.lines().next().unwrap_or_default();
+    let remaining = width.saturating_sub(used + 3);
+    if !description.is_empty() && remaining > 1 {
+        spans.push(Span::from(" - ").dim());
+        spans.push(Span::from(truncate_end(description, remaining)).dim());
+    }
+    frame.render_widget(Line::from(spans), rect);
+}
+
+/// The conversation in the other pane of a split, without its compose and with its header dimmed
+/// to show that the focus is elsewhere.
+fn render_split(
+    frame: &mut Frame<'_>,
+    rect: Rect,
+    tui_state: &mut TuiState,
+    header_height: u16,
+    now: u64,
+) {
+    let Some(mut split) = tui_state.split.take() else {
+        return;
+    };
+    let block = Block::new().borders(if split.on_left {
+        Borders::RIGHT
+    } else {
+        Borders::LEFT
+    });
+    let area = block.inner(rect);
+    frame.render_widget(block, rect);
+    let [header, messages] =
+        Layout::vertical([Constraint::Length(header_height), Constraint::Fill(1)]).areas(area);
 
-        let mut lines = Vec::new();
-        for (i, line) in content_lines.into_iter().enumerate() {
-            if i == 0 {
-                lines.push(Line::from(vec![Span::from(sender_time.clone()), line]));
+    if let Some(contact) = tui_state
+        .contacts
+        .iter_contacts_and_groups()
+        .find(|c| c.id == split.contact_id)
+    {
+        render_header(frame, header, tui_state, contact, now);
+        frame.buffer_mut().set_style(header, Style::new().dim());
+    }
+    // rendered as if selected, for the selection and scroll position of its own list
+    std::mem::swap(&mut tui_state.messages, &mut split.messages);
+    let group = matches!(split.contact_id, ContactId::Group(_));
+    render_messages(frame, messages, tui_state, group, now);
+    std::mem::swap(&mut tui_state.messages, &mut split.messages);
+    tui_state.split = Some(split);
+}
+
+/// The messages of a conversation, `group` when it has more than one other member.
+fn render_messages(
+    frame: &mut Frame<'_>,
+    rect: Rect,
+    tui_state: &mut TuiState,
+    group: bool,
+    now: u64,
+) {
+    let message_width = rect.width as usize - 1;
+    let visual_range = tui_state.messages.visual_range();
+    let selection = Style::new().bg(tui_state.palette().selection);
+    // only the messages that can be on screen are rendered: every message takes at least a row,
+    // so those more than a screen away from the selection can't be
+    let len = tui_state.messages.messages_by_ts.len();
+    let height = usize::from(rect.height);
+    let selected = tui_state
+        .messages
+        .state
+        .selected()
+        .filter(|_| len > 0)
+        .map(|s| s.min(len - 1));
+    let offset = tui_state.messages.state.offset();
+    let (start, end) = match selected {
+        Some(selected) => (
+            offset.min(selected).max(selected.saturating_sub(height)),
+            selected.saturating_add(height).saturating_add(1).min(len),
+        ),
+        None => (0, height.min(len)),
+    };
+    // taken out while rendering so that the rest of the state can be read alongside it
+    let mut cache = std::mem::take(&mut tui_state.messages.render_cache);
+    let message_items = tui_state
+        .messages
+        .messages_by_ts
+        .values()
+        .enumerate()
+        .skip(start)
+        .take(end - start)
+        .map(|(i, m)| {
+            let item = render_message_item(tui_state, &mut cache, m, message_width, group, now);
+            if visual_range.as_ref().is_some_and(|r| r.contains(&i)) {
+                item.patch_style(selection)
             } else {
-                lines.push(Line::from(vec![Span::from(content_indent.clone()), line]));
+                item
             }
-        }
-        Text::from(lines)
-    });
+        });
     let mut messages = VerticalList::new(message_items.collect());
+    tui_state.messages.render_cache = cache;
     messages.set_selected_item_style(Style::new().reversed());
 
-    let remaining_area = render_scrollbar(
-        frame,
-        rect,
-        messages.len(),
-        tui_state.messages.state.offset(),
+    let remaining_area = render_scrollbar(frame, rect, len, offset);
+
+    let mut window_state = ListState::default();
+    window_state.select(selected.map(|s| s - start));
+    window_state.set_offset(offset.saturating_sub(start));
+    frame.render_stateful_widget(&messages, remaining_area, &mut window_state);
+    tui_state
+        .messages
+        .state
+        .set_offset(window_state.offset() + start);
+    if let Some(selected) = window_state.selected() {
+        tui_state.messages.state.select(Some(selected + start));
+    }
+    tui_state.areas.messages = remaining_area;
+    tui_state.areas.message_rows = messages
+        .rows(&window_state, remaining_area.height)
+        .into_iter()
+        .map(|(i, text)| (i + start, text))
+        .collect();
+
+    if tui_state.messages.is_at_bottom() {
+        tui_state.messages.clear_new_below();
+    }
+    let new_below = tui_state.messages.new_below();
+    if new_below > 0 {
+        let plural = if new_below == 1 { "" } else { "s" };
+        let indicator = Line::from(format!(" {new_below} new message{plural} ↓ "))
+            .reversed()
+            .alignment(Alignment::Right);
+        let indicator_area = Rect {
+            y: remaining_area.bottom().saturating_sub(1),
+            height: 1.min(remaining_area.height),
+            ..remaining_area
+        };
+        frame.render_widget(indicator, indicator_area);
+    }
+}
+
+/// A message as shown in the message list, with the sender and age before the content.
+fn render_message_item(
+    tui_state: &TuiState,
+    cache: &mut RenderCache,
+    m: &Message,
+    message_width: usize,
+    group: bool,
+    now: u64,
+) -> Text<'static> {
+    let sender_width = 20;
+    let palette = tui_state.palette();
+    // relayed messages are shown as from whoever the bridge says sent them
+    let (sender, sender_color, text) = match tui_state.bridges.parse(m.text()) {
+        Some(bridged) => (
+            bridged.sender_name(),
+            tui_state
+                .configured_sender_color(bridged.sender)
+                .unwrap_or_else(|| tui_state.hashed_sender_color(bridged.sender)),
+            bridged.text,
+        ),
+        None => {
+            let name = sender_name(tui_state, &m.sender);
+            let color = tui_state.sender_color(&m.sender, &name, group);
+            (name, color, m.text())
+        }
+    };
+    // those mentioning the user stand out from the rest
+    let mentions_self = m.sender != tui_state.self_id
+        && tui_state.mentions_self(text, m.current_mentions().iter().map(|m| m.id.as_slice()));
+    let sender_color = if mentions_self {
+        palette.mention
+    } else {
+        sender_color
+    };
+    let ts = m.edits.last().map_or(m.timestamp, |e| e.timestamp);
+    let revealed = tui_state.revealed_timestamp == Some(m.timestamp)
+        && tui_state
+            .messages
+            .selected()
+            .is_some_and(|s| s.timestamp == m.timestamp);
+    // padded to line up the messages in the aligned layout
+    let stamp = if revealed {
+        full_time(ts)
+    } else {
+        let age = biggest_duration_string(now.saturating_sub(ts));
+        match tui_state.config.timestamps {
+            Timestamps::Relative => format!("{age:>3}"),
+            Timestamps::Absolute => format!("{:>6}", clock_time(ts, now)),
+            Timestamps::Both => format!("{:>6} {age:>3}", clock_time(ts, now)),
+        }
+    };
+    let layout = tui_state.config.message_layout;
+    let own = m.sender == tui_state.self_id;
+    let right = own
+        && match tui_state.config.own_messages {
+            OwnMessages::Layout => layout == MessageLayout::Cozy,
+            OwnMessages::Right => true,
+            OwnMessages::Left | OwnMessages::Marked => false,
+        };
+    let sender = if own && tui_state.config.own_messages == OwnMessages::Marked {
+        format!("» {sender}")
+    } else {
+        sender
+    };
+    // without the padding when not lined up
+    let compact_stamp = stamp.split_whitespace().collect::<Vec<_>>().join(" ");
+    let (sender, time) = match layout {
+        MessageLayout::Aligned => (truncate_or_pad(sender, sender_width), format!(" {stamp} ")),
+        MessageLayout::Compact => (
+            truncate_middle(&sender, sender_width),
+            format!(" {compact_stamp} "),
+        ),
+        MessageLayout::Cozy => (sender, format!(" {compact_stamp}")),
+    };
+    let sender_time = format!("{sender}{time}");
+
+    let (content_width, content_indent) = match layout {
+        MessageLayout::Aligned => (
+            message_width
+                .saturating_sub(sender_time.width())
+                .saturating_sub(1),
+            " ".repeat(sender_time.width()),
+        ),
+        // lines after the first start at the edge
+        MessageLayout::Compact => (
+            message_width
+                .saturating_sub(sender_time.width())
+                .saturating_sub(1),
+            String::new(),
+        ),
+        MessageLayout::Cozy => (message_width.saturating_sub(3), "  ".to_owned()),
+    };
+    // those on the right take up less of the width, like a speech bubble
+    let content_width = if right {
+        content_width.min(message_width * 3 / 4)
+    } else {
+        content_width
+    };
+
+    let filtered = tui_state.render_filters.get(text);
+    let quote_sender = quote_sender_name(tui_state, m);
+    let content_lines = cache.render(
+        m,
+        content_width,
+        &palette,
+        filtered.as_deref().unwrap_or(text),
+        filtered.is_some(),
+        &tui_state.transfers,
+        &quote_sender,
+        &message_mention_names(tui_state, m),
     );
+    if content_lines.is_empty() {
+        warn!(message:? = m; "Message with no information...");
+    }
 
-    frame.render_stateful_widget(&messages, remaining_area, &mut tui_state.messages.state);
+    let sender_spans = if mentions_self {
+        vec![
+            Span::from(sender).fg(sender_color).bold(),
+            Span::from(time).fg(sender_color),
+        ]
+    } else {
+        vec![Span::from(sender).fg(sender_color), Span::from(time)]
+    };
+    // on a line of its own when cozy, otherwise before the first line of the content
+    let mut sender_spans = Some(sender_spans);
+    let mut lines = Vec::new();
+    if layout == MessageLayout::Cozy {
+        lines.push(Line::from(sender_spans.take().unwrap_or_default()));
+    }
+    let found = Style::new().fg(Color::Black).bg(palette.found);
+    for line in content_lines {
+        let line = match &tui_state.find {
+            Some(regex) => highlight_matches(line, regex, found),
+            None => line,
+        };
+        let mut spans = sender_spans
+            .take()
+            .unwrap_or_else(|| vec![Span::from(content_indent.clone())]);
+        spans.extend(line.spans);
+        lines.push(Line::from(spans));
+    }
+    if right {
+        // padded rather than aligned so that the text of each row is as it is on screen
+        for line in &mut lines {
+            let padding = message_width.saturating_sub(line.width());
+            line.spans.insert(0, Span::from(" ".repeat(padding)));
+        }
+    }
+    if layout == MessageLayout::Cozy {
+        lines.push(Line::from(""));
+    }
+    Text::from(lines)
+}
+
+/// Split the spans of the line where the search matches, styling the matching parts. The search
+/// runs over the text of the whole line so that matches can cross from one span into the next.
+fn highlight_matches(line: Line<'static>, regex: &Regex, style: Style) -> Line<'static> {
+    let text = line
+        .spans
+        .iter()
+        .map(|span| span.content.as_ref())
+        .collect::<String>();
+    let matches = regex
+        .find_iter(&text)
+        .map(|m| m.range())
+        .filter(|range| !range.is_empty())
+        .collect::<Vec<_>>();
+    if matches.is_empty() {
+        return line;
+    }
+    let mut spans = Vec::new();
+    let mut matches = matches.into_iter().peekable();
+    let mut offset = 0;
+    for span in line.spans {
+        let end = offset + span.content.len();
+        let mut last = offset;
+        while let Some(m) = matches.peek().filter(|m| m.start < end) {
+            let start = m.start.max(last);
+            if start > last {
+                spans.push(Span::styled(text[last..start].to_owned(), span.style));
+            }
+            let m_end = m.end.min(end);
+            spans.push(Span::styled(
+                text[start..m_end].to_owned(),
+                span.style.patch(style),
+            ));
+            last = m_end;
+            if m.end > end {
+                // carries on into the next span
+                break;
+            }
+            matches.next();
+        }
+        if last == offset {
+            spans.push(span);
+        } else if last < end {
+            spans.push(Span::styled(text[last..end].to_owned(), span.style));
+        }
+        offset = end;
+    }
+    Line { spans, ..line }
 }
 
 fn render_compose(frame: &mut Frame<'_>, rect: Rect, tui_state: &mut TuiState, _now: u64) {
-    tui_state
-        .compose
-        .set_block(Block::new().borders(Borders::TOP));
+    let mut block = Block::new().borders(Borders::TOP);
+    if tui_state.config.markdown {
+        block = block.title_top(Line::from(" markdown ").right_aligned());
+    }
+    tui_state.compose.set_block(block);
     if matches!(tui_state.mode, Mode::Compose) {
         // show cursor
         tui_state.compose.set_cursor_style(Style::new().reversed());
@@ -247,6 +1098,18 @@ fn render_compose(frame: &mut Frame<'_>, rect: Rect, tui_state: &mut TuiState, _
         tui_state.compose.set_cursor_style(Style::new());
         tui_state.compose.set_cursor_line_style(Style::new());
     }
+    // leave the word being typed until it is finished
+    let typing = tui_state.compose.word_before_cursor();
+    let misspelled: Vec<String> = tui_state
+        .compose
+        .lines()
+        .iter()
+        .flat_map(|line| spellcheck::words(line))
+        .filter(|word| *word != typing && tui_state.spell_checker.misspelled(word).is_some())
+        .map(str::to_owned)
+        .collect();
+    let style = Style::new().underlined().fg(tui_state.palette().error);
+    tui_state.compose.set_misspelled(&misspelled, style);
     frame.render_widget(&tui_state.compose, rect);
 }
 
@@ -258,46 +1121,208 @@ fn render_status(frame: &mut Frame<'_>, rect: Rect, tui_state: &mut TuiState, _n
         rect,
     );
 
-    let completions = tui_state
-        .command_line
-        .completions()
-        .iter()
-        .map(|c| Span::from(c.display.clone()).style(revstyle));
+    if let Some(format) = tui_state.config.status_format.clone() {
+        render_status_format(frame, rect, tui_state, &format);
+        return;
+    }
 
+    let syncing = match syncing(tui_state) {
+        syncing if syncing.is_empty() => syncing,
+        syncing => format!(" {syncing} "),
+    };
+    let transfers = match tui_state.transfers.summary() {
+        summary if summary.is_empty() => summary,
+        summary => format!(" {summary} "),
+    };
+    let recording = match recording(tui_state) {
+        recording if recording.is_empty() => recording,
+        recording => format!(" {recording} "),
+    };
+    // only shown when something is wrong
+    let connectivity = match tui_state.connectivity {
+        Connectivity::Online => String::new(),
+        Connectivity::Offline => " offline ".to_owned(),
+        Connectivity::Reconnecting => " reconnecting… ".to_owned(),
+    };
     let splits = Layout::horizontal([
         Constraint::Length(8),
         Constraint::Fill(1),
+        Constraint::Length(recording.width() as u16),
+        Constraint::Length(transfers.width() as u16),
+        Constraint::Length(syncing.width() as u16),
+        Constraint::Length(connectivity.width() as u16),
         Constraint::Length(4),
     ])
     .split(rect);
 
+    frame.render_widget(Span::from(mode_name(tui_state)).style(revstyle), splits[0]);
+
+    render_completions(frame, splits[1], tui_state);
+
+    frame.render_widget(Span::from(recording).style(revstyle), splits[2]);
+
+    frame.render_widget(Span::from(transfers).style(revstyle), splits[3]);
+
     frame.render_widget(
-        Span::from(tui_state.mode.to_string()).style(revstyle),
-        splits[0],
+        Span::from(syncing).style(revstyle.fg(tui_state.palette().warning)),
+        splits[4],
     );
 
-    let mut completions_list = HorizontalList::new(completions.collect());
-    completions_list.set_selected_item_style(Style::new().bold());
-    frame.render_stateful_widget(
-        &completions_list,
-        splits[1],
-        &mut tui_state.command_line.completions.list_state,
+    frame.render_widget(
+        Span::from(connectivity).style(revstyle.fg(tui_state.palette().error)),
+        splits[5],
     );
 
     frame.render_widget(
-        Span::from(tui_state.key_events.to_string()).style(revstyle),
-        splits[2],
+        Span::from(pending_keys(tui_state)).style(revstyle),
+        splits[6],
     );
 }
 
+/// The mode to show, with a visual selection of messages being its own in normal mode.
+fn mode_name(tui_state: &TuiState) -> String {
+    if matches!(tui_state.mode, Mode::Normal) && tui_state.messages.is_visual() {
+        "Visual".to_owned()
+    } else {
+        tui_state.mode.to_string()
+    }
+}
+
+/// The count and keys typed so far towards a key binding.
+fn pending_keys(tui_state: &TuiState) -> String {
+    let count = tui_state
+        .count_prefix
+        .map(|c| c.to_string())
+        .unwrap_or_default();
+    format!("{count}{}", tui_state.key_events)
+}
+
+/// Whether the backend is still syncing, with how many messages it has caught up on.
+fn syncing(tui_state: &TuiState) -> String {
+    match tui_state.load_status.messages {
+        _ if !tui_state.syncing => String::new(),
+        0 => "syncing…".to_owned(),
+        messages => format!("syncing {messages} messages…"),
+    }
+}
+
+/// The register keys are being recorded into with `:record`, if any.
+fn recording(tui_state: &TuiState) -> String {
+    tui_state
+        .macros
+        .recording()
+        .map(|r| format!("recording @{r}"))
+        .unwrap_or_default()
+}
+
+/// The words being completed while composing, otherwise commands.
+fn render_completions(frame: &mut Frame<'_>, rect: Rect, tui_state: &mut TuiState) {
+    let completions = if matches!(tui_state.mode, Mode::Compose) {
+        &mut tui_state.compose.completions
+    } else {
+        &mut tui_state.command_line.completions
+    };
+    let completion_items = completions
+        .candidates()
+        .iter()
+        .map(|c| completion_line(c).style(Style::new().reversed()))
+        .collect();
+    let mut completions_list = HorizontalList::new(completion_items);
+    completions_list.set_selected_item_style(Style::new().bold());
+    frame.render_stateful_widget(&completions_list, rect, &mut completions.list_state);
+}
+
+/// A completion with the characters matching what was typed underlined.
+fn completion_line(completion: &Completion) -> Line<'static> {
+    let mut spans: Vec<Span<'static>> = Vec::new();
+    let mut run = String::new();
+    let mut run_matched = false;
+    for (i, c) in completion.display.chars().enumerate() {
+        let matched = completion.matched.contains(&i);
+        if matched != run_matched && !run.is_empty() {
+            spans.push(matched_span(std::mem::take(&mut run), run_matched));
+        }
+        run_matched = matched;
+        run.push(c);
+    }
+    if !run.is_empty() {
+        spans.push(matched_span(run, run_matched));
+    }
+    Line::from(spans)
+}
+
+fn matched_span(text: String, matched: bool) -> Span<'static> {
+    if matched {
+        Span::from(text).style(Style::new().underlined())
+    } else {
+        Span::from(text)
+    }
+}
+
+/// Render the status line from the configured format, with the completions taking up the space
+/// left at `{completions}`.
+fn render_status_format(frame: &mut Frame<'_>, rect: Rect, tui_state: &mut TuiState, format: &str) {
+    let revstyle = Style::new().reversed();
+    let (left, right) = format.split_once("{completions}").unwrap_or((format, ""));
+    let left = status::expand(left, |name| status_value(tui_state, name));
+    let right = status::expand(right, |name| status_value(tui_state, name));
+    let splits = Layout::horizontal([
+        Constraint::Length(left.width() as u16),
+        Constraint::Fill(1),
+        Constraint::Length(right.width() as u16),
+    ])
+    .split(rect);
+
+    frame.render_widget(Span::from(left).style(revstyle), splits[0]);
+    render_completions(frame, splits[1], tui_state);
+    frame.render_widget(Span::from(right).style(revstyle), splits[2]);
+}
+
+/// The value of a placeholder in the status line format.
+fn status_value(tui_state: &TuiState, name: &str) -> Option<String> {
+    let value = match name {
+        "mode" => mode_name(tui_state),
+        "contact" => tui_state
+            .contacts
+            .selected()
+            .map(|c| tui_state.nicknames.name(c).to_owned())
+            .unwrap_or_default(),
+        "unread" => tui_state
+            .contacts
+            .selected()
+            .map(|c| tui_state.contacts.unread_count(&c.id).to_string())
+            .unwrap_or_default(),
+        "unread_total" => tui_state.contacts.total_unread_count().to_string(),
+        "pending_keys" => pending_keys(tui_state),
+        "recording" => recording(tui_state),
+        "transfers" => tui_state.transfers.summary(),
+        "syncing" => syncing(tui_state),
+        "connection" => tui_state.connectivity.to_string(),
+        "account" => tui_state
+            .contacts
+            .contact_by_id(&tui_state.self_id)
+            .map_or_else(|| hex::encode(&tui_state.self_id), |c| c.name.clone()),
+        "backend" => tui_state.backend_name.clone(),
+        "time" => chrono::Local::now().format("%H:%M").to_string(),
+        _ => return None,
+    };
+    Some(value)
+}
+
 fn render_command(frame: &mut Frame<'_>, rect: Rect, tui_state: &mut TuiState, _now: u64) {
     if tui_state.command_line.error.is_empty() {
         if matches!(tui_state.mode, Mode::Command { .. }) {
-            frame.render_widget(Line::from(":"), rect);
-            let inner_rect = rect.inner(Margin {
-                horizontal: 1,
-                vertical: 0,
-            });
+            let prompt = match tui_state.command_line.history.search_query() {
+                Some(query) => format!("(history `{query}`):"),
+                None => ":".to_owned(),
+            };
+            let prompt_width = prompt.width() as u16;
+            frame.render_widget(Line::from(prompt), rect);
+            let inner_rect = Rect {
+                x: rect.x + prompt_width,
+                width: rect.width.saturating_sub(prompt_width),
+                ..rect
+            };
             tui_state
                 .command_line
                 .textarea()
@@ -308,12 +1333,69 @@ fn render_command(frame: &mut Frame<'_>, rect: Rect, tui_state: &mut TuiState, _
         }
     } else {
         frame.render_widget(
-            Paragraph::new(tui_state.command_line.error.clone()).set_style(Style::new().red()),
+            Paragraph::new(tui_state.command_line.error.clone())
+                .set_style(Style::new().fg(tui_state.palette().error)),
             rect,
         );
     };
 }
 
+/// The local time of the timestamp, just the time of day for today and the date otherwise.
+fn clock_time(ts: u64, now: u64) -> String {
+    let local = |ts: u64| {
+        chrono::DateTime::from_timestamp_millis(ts as i64).map(|t| t.with_timezone(&chrono::Local))
+    };
+    let (Some(time), Some(now)) = (local(ts), local(now)) else {
+        return String::new();
+    };
+    let format = if time.date_naive() == now.date_naive() {
+        "%H:%M"
+    } else {
+        "%d %b"
+    };
+    time.format(format).to_string()
+}
+
+/// The full local date and time of the timestamp.
+fn full_time(ts: u64) -> String {
+    chrono::DateTime::from_timestamp_millis(ts as i64)
+        .map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_default()
+}
+
+/// When the age shown for the timestamp next changes after `now`, as its unit ticks over or
+/// gives way to a bigger one.
+fn next_age_change(ts: u64, now: u64) -> u64 {
+    const SECOND: u64 = 1000;
+    const HOUR: u64 = 60 * 60 * SECOND;
+    const DAY: u64 = 24 * HOUR;
+    const UNITS: [u64; 7] = [SECOND, 60 * SECOND, HOUR, DAY, 7 * DAY, 30 * DAY, 365 * DAY];
+    let age = now.saturating_sub(ts);
+    let Some(i) = UNITS.iter().rposition(|unit| age >= *unit) else {
+        return ts + SECOND;
+    };
+    let next = ts + (age / UNITS[i] + 1) * UNITS[i];
+    UNITS
+        .get(i + 1)
+        .map_or(next, |bigger| next.min(ts + bigger))
+}
+
+/// The start of the next local day after the timestamp.
+fn next_midnight(now: u64) -> u64 {
+    chrono::DateTime::from_timestamp_millis(now as i64)
+        .and_then(|t| t.with_timezone(&chrono::Local).date_naive().succ_opt())
+        .and_then(|d| {
+            d.and_time(chrono::NaiveTime::MIN)
+                .and_local_timezone(chrono::Local)
+                .earliest()
+        })
+        .map_or(u64::MAX, |t| t.timestamp_millis() as u64)
+}
+
 fn biggest_duration_string(duration_ms: u64) -> String {
     let year = duration_ms / (1000 * 60 * 60 * 24 * 365);
     let month = duration_ms / (1000 * 60 * 60 * 24 * 30);
@@ -349,13 +1431,67 @@ fn wrap_text(s: &str, width: usize) -> Text {
     Text::from(content)
 }
 
-fn truncate_or_pad(mut s: String, width: usize) -> String {
-    if s.len() >= width {
-        s[..width].to_owned()
-    } else {
-        s.push_str(&" ".repeat(width - s.len()));
-        s
+fn truncate_or_pad(s: String, width: usize) -> String {
+    let mut s = truncate_middle(&s, width);
+    s.push_str(&" ".repeat(width.saturating_sub(s.width())));
+    s
+}
+
+/// Fit the text in the width by replacing its middle with an ellipsis, keeping both ends which
+/// tend to tell long names apart.
+fn truncate_middle(s: &str, width: usize) -> String {
+    if s.width() <= width {
+        return s.to_owned();
     }
+    if width == 0 {
+        return String::new();
+    }
+    let available = width - 1;
+    // by grapheme so that emoji sequences and combining marks aren't split
+    let mut start = String::new();
+    let mut start_width = 0;
+    for g in s.graphemes(true) {
+        let w = g.width();
+        if start_width + w > available.div_ceil(2) {
+            break;
+        }
+        start_width += w;
+        start.push_str(g);
+    }
+    let mut end = Vec::new();
+    let mut end_width = 0;
+    for g in s.graphemes(true).rev() {
+        let w = g.width();
+        if start_width + end_width + w > available {
+            break;
+        }
+        end_width += w;
+        end.push(g);
+    }
+    start.push('…');
+    start.extend(end.into_iter().rev());
+    start
+}
+
+/// Fit the text in the width, ending with an ellipsis if it had to be cut short.
+fn truncate_end(s: &str, width: usize) -> String {
+    if s.width() <= width {
+        return s.to_owned();
+    }
+    let mut truncated = String::new();
+    let mut truncated_width = 0;
+    for g in s.graphemes(true) {
+        let w = g.width();
+        if truncated_width + w + 1 > width {
+            break;
+        }
+        truncated_width += w;
+        truncated.push_str(g);
+    }
+    if width > 0 {
+        truncated.push('…');
+    }
+    truncated
 }
 
 fn render_popup(frame: &mut Frame<'_>, area: Rect, tui_state: &mut TuiState) {
@@ -365,6 +1501,7 @@ fn render_popup(frame: &mut Frame<'_>, area: Rect, tui_state: &mut TuiState) {
     let area = popup_area(area, 60, 50);
     frame.render_widget(Clear, area); // this clears out the background
     let width = area.width.saturating_sub(2) as usize;
+    let mut selected_line = None;
     let (title, text) = match &popup.typ {
         PopupType::MessageInfo { timestamp } => {
             let Some(message) = tui_state.messages.get_by_timestamp(*timestamp) else {
@@ -373,7 +1510,7 @@ fn render_popup(frame: &mut Frame<'_>, area: Rect, tui_state: &mut TuiState) {
             };
             render_message_info(width, tui_state, message)
         }
-        PopupType::ContactInfo { id } => {
+        PopupType::ContactInfo { id, search } => {
             let Some(contact) = tui_state
                 .contacts
                 .iter_contacts_and_groups()
@@ -382,16 +1519,44 @@ fn render_popup(frame: &mut Frame<'_>, area: Rect, tui_state: &mut TuiState) {
                 warn!(id:?; "No contact with id when rendering popup for contact info");
                 return;
             };
-            render_contact_info(contact)
+            render_contact_info(tui_state, contact, search.as_deref())
         }
         PopupType::Keybinds => render_keybinds(&tui_state.config.keybinds),
-        PopupType::Commands => render_commands(),
+        PopupType::Commands => render_commands(&tui_state.plugins),
         PopupType::CommandHistory => render_command_line_history(tui_state),
+        PopupType::PinnedMessages { contact_id } => render_pinned_messages(tui_state, contact_id),
+        PopupType::MissedMessages => render_missed_messages(tui_state),
+        PopupType::Preview => render_preview(width, tui_state),
+        PopupType::Verification => render_verification(tui_state),
+        PopupType::ConfirmForward(forward) => render_confirm_forward(tui_state, forward),
+        PopupType::ConfirmDelete { messages, skipped } => {
+            render_confirm_delete(messages.len(), *skipped)
+        }
+        PopupType::EmojiPicker(picker) => {
+            let (text, line) = picker.render(&tui_state.emoji_usage);
+            selected_line = Some(line as u16);
+            ("Emoji", text)
+        }
+        PopupType::FilePicker(picker) => {
+            let (text, line) = picker.render();
+            selected_line = Some(line as u16);
+            ("Attach files", text)
+        }
+        PopupType::Output { title, text } => (title.as_str(), Text::from(text.clone())),
     };
+    let title = title.to_owned();
 
     let line_count = text.lines.len() as u16;
     let max_scroll = line_count.saturating_sub(area.height.saturating_sub(2));
     let popup = tui_state.popup.as_mut().unwrap();
+    if let Some(selected_line) = selected_line {
+        // keep the selection in view
+        let height = area.height.saturating_sub(2);
+        popup.scroll = popup.scroll.clamp(
+            selected_line.saturating_sub(height.saturating_sub(1)),
+            selected_line,
+        );
+    }
     popup.scroll = popup.scroll.min(max_scroll);
     let block = Block::bordered().title(title);
     let inner_area = block.inner(area);
@@ -404,6 +1569,43 @@ fn render_popup(frame: &mut Frame<'_>, area: Rect, tui_state: &mut TuiState) {
     frame.render_widget(para, remaining_area);
 }
 
+/// The name of a sender, or their id if the backend hasn't reported them as a contact, such as
+/// members of Matrix rooms.
+pub(crate) fn sender_name(tui_state: &TuiState, sender: &[u8]) -> String {
+    match tui_state.contacts.contact_by_id(&sender.to_vec()) {
+        Some(contact) => tui_state.nicknames.name(contact).to_owned(),
+        None => String::from_utf8(sender.to_vec()).unwrap_or_else(|_| hex::encode(sender)),
+    }
+}
+
+/// The name of who sent the message quoted, empty without a quote.
+fn quote_sender_name(tui_state: &TuiState, message: &Message) -> String {
+    message
+        .quote
+        .as_ref()
+        .map_or_else(String::new, |q| sender_name(tui_state, &q.sender))
+}
+
+/// The names to show for those mentioned who are known.
+fn mention_names<'a>(
+    tui_state: &TuiState,
+    mentions: impl IntoIterator<Item = &'a Mention>,
+) -> MentionNames {
+    mentions
+        .into_iter()
+        .filter_map(|m| {
+            let contact = tui_state.contacts.contact_by_id(&m.id)?;
+            Some((m.id.clone(), tui_state.nicknames.name(contact).to_owned()))
+        })
+        .collect()
+}
+
+/// The names of those mentioned in the message and the message it quotes.
+fn message_mention_names(tui_state: &TuiState, message: &Message) -> MentionNames {
+    let quoted = message.quote.iter().flat_map(|q| &q.mentions);
+    mention_names(tui_state, message.current_mentions().iter().chain(quoted))
+}
+
 fn render_message_info(
     width: usize,
     tui_state: &TuiState,
@@ -416,48 +1618,399 @@ fn render_message_info(
         ts_nanos.try_into().unwrap(),
     )
     .unwrap();
-    let sender_name = tui_state
-        .contacts
-        .contact_by_id(&message.sender)
-        .unwrap()
-        .name
-        .to_string();
+    let sender_name = sender_name(tui_state, &message.sender);
     let mut text = vec![
         Line::from(format!("Sender name: {}", sender_name)),
         Line::from(format!("Sender id:   {}", hex::encode(&message.sender))),
+    ];
+    if let Some(bridged) = tui_state.bridges.parse(message.text()) {
+        text.push(Line::from(format!(
+            "Bridged for: {}",
+            bridged.sender_name()
+        )));
+    }
+    text.extend([
         Line::from(format!("Time:        {}", time.to_rfc3339())),
         Line::from(""),
-    ];
-    let message_lines = message.render(width).into_iter().map(|s| Line::from(s));
+    ]);
+    let message_lines = message.render(
+        width,
+        &tui_state.palette(),
+        message.text(),
+        false,
+        &tui_state.transfers,
+        &quote_sender_name(tui_state, message),
+        &message_mention_names(tui_state, message),
+    );
     text.extend(message_lines);
+    if message.sender == tui_state.self_id {
+        text.extend(render_receipts(tui_state, message));
+    }
     ("Message info", Text::from(text))
 }
 
-fn render_contact_info(contact: &Contact) -> (&'static str, Text) {
-    let time = contact
-        .last_message_timestamp
+/// Who the user's message has been delivered to and read by, and when.
+fn render_receipts(tui_state: &TuiState, message: &Message) -> Vec<Line<'static>> {
+    let mut lines = vec![Line::from(""), Line::from("Receipts:").bold()];
+    let Some(receipts) = tui_state
+        .message_receipts
+        .get(&(message.contact_id.clone(), message.timestamp))
+    else {
+        lines.push(Line::from("Loading…").italic());
+        return lines;
+    };
+    if receipts.is_empty() {
+        lines.push(Line::from("None yet").italic());
+    }
+    let time = |ts: Option<u64>| {
+        ts.and_then(|ts| chrono::DateTime::from_timestamp_millis(ts.try_into().ok()?))
+            .map(|t| {
+                t.with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            })
+    };
+    for receipt in receipts {
+        let name = truncate_or_pad(sender_name(tui_state, &receipt.recipient), 20);
+        let status = match (time(receipt.delivered), time(receipt.read)) {
+            (_, Some(read)) => format!("read {read}"),
+            (Some(delivered), None) => format!("delivered {delivered}"),
+            (None, None) => "sent".to_owned(),
+        };
+        lines.push(Line::from(format!("{name} {status}")));
+    }
+    lines
+}
+
+fn render_pinned_messages(
+    tui_state: &TuiState,
+    contact_id: &ContactId,
+) -> (&'static str, Text<'static>) {
+    let Some(timestamps) = tui_state.pinned_messages.get(contact_id) else {
+        return ("Pinned messages", Text::from("Loading pinned messages..."));
+    };
+    if timestamps.is_empty() {
+        return ("Pinned messages", Text::from("No pinned messages"));
+    }
+    let lines = timestamps
+        .iter()
         .map(|ts| {
-            let ts_seconds = ts / 1_000;
-            let ts_nanos = (ts % 1_000) * 1_000_000;
-            let time = chrono::DateTime::from_timestamp(
-                ts_seconds.try_into().unwrap(),
-                ts_nanos.try_into().unwrap(),
-            )
-            .unwrap();
-            time.to_rfc3339()
+            let time = chrono::DateTime::from_timestamp_millis(*ts as i64)
+                .map(|t| {
+                    t.with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M")
+                        .to_string()
+                })
+                .unwrap_or_default();
+            match tui_state.messages.get_by_timestamp(*ts) {
+                Some(message) => {
+                    let sender = sender_name(tui_state, &message.sender);
+                    let content = message.content.lines().next().unwrap_or_default();
+                    Line::from(format!("{time} {sender}: {content}"))
+                }
+                None => Line::from(format!("{time} (message not loaded)")).italic(),
+            }
         })
-        .unwrap_or_else(|| "unknown".to_owned());
-    let text = vec![
-        Line::from(format!("Name:              {}", contact.name)),
-        Line::from(format!("Id:                {}", contact.id)),
-        Line::from(format!("Last message time: {}", time)),
-        Line::from(format!("Description:       {}", contact.description)),
+        .collect::<Vec<_>>();
+    ("Pinned messages", Text::from(lines))
+}
+
+fn render_preview(width: usize, tui_state: &TuiState) -> (&'static str, Text<'static>) {
+    let body = tui_state.compose.lines().join("\n").trim().to_owned();
+    let attachments = tui_state.compose.attachments().to_vec();
+    if body.is_empty() {
+        return ("Preview", Text::from("Nothing to send"));
+    }
+    let (body, formatting) = if tui_state.config.markdown {
+        crate::markdown::parse_markdown(&body)
+    } else {
+        (body, Vec::new())
+    };
+    let message = Message {
+        timestamp: timestamp(),
+        sender: tui_state.self_id.clone(),
+        contact_id: tui_state
+            .contacts
+            .selected()
+            .map_or_else(|| ContactId::User(Vec::new()), |c| c.id.clone()),
+        content: body.clone(),
+        reactions: Vec::new(),
+        attachments: attachments.clone(),
+        quote: tui_state.compose.quote().clone(),
+        edits: Vec::new(),
+        formatting,
+        mentions: Vec::new(),
+        spoilers_revealed: false,
+    };
+    // not yet sent so not kept with the others
+    let mut cache = RenderCache::default();
+    // from the user, so never coloured as one of the senders in a group
+    let mut lines = render_message_item(
+        tui_state,
+        &mut cache,
+        &message,
+        width,
+        false,
+        message.timestamp,
+    )
+    .lines;
+    lines.push(Line::from(""));
+    // the same checks as sending, so problems show up before the draft is sent
+    match crate::commands::check_limits(tui_state, &body, &attachments) {
+        Ok(()) => lines.push(Line::from("Within the limits of the backend").italic()),
+        Err(error) => lines.push(Line::from(error.to_string()).red()),
+    }
+    ("Preview", Text::from(lines))
+}
+
+fn render_missed_messages(tui_state: &TuiState) -> (&'static str, Text<'static>) {
+    let summary = tui_state.missed.summary();
+    let plural = |n: usize, word: &str| {
+        if n == 1 {
+            format!("{n} {word}")
+        } else {
+            format!("{n} {word}s")
+        }
+    };
+    let messages = summary.iter().map(|c| c.messages).sum();
+    let mentions = summary.iter().map(|c| c.mentions).sum();
+    let mut lines = vec![
+        Line::from(format!(
+            "{} in {}, {}",
+            plural(messages, "message"),
+            plural(summary.len(), "conversation"),
+            plural(mentions, "mention"),
+        ))
+        .bold(),
+        Line::from(""),
+    ];
+    for (i, conversation) in summary.iter().enumerate() {
+        let name = tui_state
+            .contacts
+            .iter_contacts_and_groups()
+            .find(|c| c.id == conversation.contact_id)
+            .map_or_else(
+                || conversation.contact_id.to_string(),
+                |c| tui_state.nicknames.name(c).to_owned(),
+            );
+        let mut line = format!(
+            "{}. {name}: {}",
+            i + 1,
+            plural(conversation.messages, "message")
+        );
+        if conversation.mentions > 0 {
+            line.push_str(&format!(", {}", plural(conversation.mentions, "mention")));
+        }
+        lines.push(Line::from(line));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from("Jump to a conversation with :goto-missed <number>").italic());
+    ("Missed messages", Text::from(lines))
+}
+
+fn render_confirm_forward(
+    tui_state: &TuiState,
+    forward: &Forward,
+) -> (&'static str, Text<'static>) {
+    let count = forward.messages.len();
+    let attachments: usize = forward.messages.iter().map(|m| m.attachments.len()).sum();
+    let plural = if count == 1 { "" } else { "s" };
+    let mut heading = format!("Forward {count} message{plural}");
+    if attachments > 0 {
+        let plural = if attachments == 1 { "" } else { "s" };
+        heading.push_str(&format!(" with {attachments} attachment{plural}"));
+    }
+    let mut lines = vec![Line::from(format!("{heading} to:")).bold(), Line::from("")];
+    for recipient in &forward.recipients {
+        let name = tui_state
+            .contacts
+            .iter_contacts_and_groups()
+            .find(|c| &c.id == recipient)
+            .map_or_else(
+                || "Unknown contact".to_owned(),
+                |c| tui_state.nicknames.name(c).to_owned(),
+            );
+        lines.push(Line::from(format!("  {name}")));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from("Send with :confirm-forward or cancel with <Esc>").italic());
+    ("Forward", Text::from(lines))
+}
+
+fn render_confirm_delete(count: usize, skipped: usize) -> (&'static str, Text<'static>) {
+    let mut lines = vec![
+        Line::from(format!("Delete {count} messages for everyone?")).bold(),
+        Line::from(""),
+    ];
+    if skipped > 0 {
+        let plural = if skipped == 1 { "" } else { "s" };
+        lines.push(Line::from(format!(
+            "{skipped} selected message{plural} from others will be kept"
+        )));
+        lines.push(Line::from(""));
+    }
+    lines.push(Line::from(":confirm-delete to delete or <Esc> to cancel").italic());
+    ("Delete", Text::from(lines))
+}
+
+fn render_verification(tui_state: &TuiState) -> (&'static str, Text<'static>) {
+    let lines = match &tui_state.device_verification {
+        None => vec![Line::from("No device verification in progress")],
+        Some(DeviceVerification::Requested) => vec![Line::from(
+            "Waiting for another of your devices to accept the verification request",
+        )],
+        Some(DeviceVerification::Compare {
+            other_device,
+            emoji,
+        }) => {
+            let mut lines = vec![
+                Line::from(format!(
+                    "Do these emoji match those shown on {other_device}?"
+                ))
+                .bold(),
+                Line::from(""),
+            ];
+            lines.extend(emoji.iter().map(|e| Line::from(format!("  {e}"))));
+            lines.push(Line::from(""));
+            lines.push(
+                Line::from(
+                    "Confirm with :confirm-verification or reject with :reject-verification",
+                )
+                .italic(),
+            );
+            lines
+        }
+        Some(DeviceVerification::Done { other_device }) => {
+            vec![Line::from(format!("Verified with {other_device}"))]
+        }
+        Some(DeviceVerification::Cancelled { reason }) => {
+            vec![Line::from(format!("Verification cancelled: {reason}"))]
+        }
+    };
+    ("Device verification", Text::from(lines))
+}
+
+fn render_contact_info(
+    tui_state: &TuiState,
+    contact: &Contact,
+    search: Option<&str>,
+) -> (&'static str, Text<'static>) {
+    let time = |ts: u64| {
+        chrono::DateTime::from_timestamp_millis(ts as i64)
+            .map(|t| t.with_timezone(&chrono::Local).to_rfc3339())
+            .unwrap_or_else(|| "unknown".to_owned())
+    };
+    let mut fields = vec![
+        ("Name", contact.name.clone()),
+        ("Id", contact.id.to_string()),
     ];
+    if let Some(nickname) = tui_state.nicknames.get(&contact.id) {
+        fields.insert(1, ("Nickname", nickname.to_owned()));
+    }
+    if !contact.address.is_empty() {
+        fields.push(("Address", contact.address.clone()));
+    }
+    fields.extend([
+        (
+            "Last message time",
+            contact
+                .last_message_timestamp
+                .map_or_else(|| "unknown".to_owned(), time),
+        ),
+        ("Description", contact.description.clone()),
+        ("Verification", contact.verification.to_string()),
+        (
+            "Encrypted",
+            if contact.encrypted { "yes" } else { "no" }.to_owned(),
+        ),
+    ]);
+    if let Some(members) = contact.members {
+        fields.push(("Members", members.to_string()));
+    }
+    let details = tui_state.contact_details.get(&contact.id);
+    if let Some(role) = details.and_then(|d| d.role.clone()) {
+        fields.push(("Your role", role));
+    }
+    if let Some(details) = details {
+        let timer = details
+            .disappearing_timer
+            .map_or_else(|| "off".to_owned(), human_duration);
+        fields.push(("Disappearing messages", timer));
+    }
+    let muted = match tui_state.mutes.get(&contact.id, timestamp()) {
+        None => "no".to_owned(),
+        Some(mute) if mute.until == u64::MAX => "indefinitely".to_owned(),
+        Some(mute) => format!("until {}", time(mute.until)),
+    };
+    fields.push(("Muted", muted));
+    fields.push((
+        "Auto-download",
+        tui_state.auto_downloads.get(&contact.id).to_string(),
+    ));
+    if tui_state
+        .contacts
+        .selected()
+        .is_some_and(|c| c.id == contact.id)
+    {
+        // only the messages of the selected conversation are loaded
+        let attachments: usize = tui_state
+            .messages
+            .messages_by_ts
+            .values()
+            .map(|m| m.attachments.len())
+            .sum();
+        fields.push(("Shared attachments", attachments.to_string()));
+    }
+    for (name, value) in details.into_iter().flat_map(|d| &d.profile) {
+        fields.push((name.as_str(), value.clone()));
+    }
+
+    if let Some(search) = search {
+        let search = search.to_lowercase();
+        fields.retain(|(name, value)| {
+            name.to_lowercase().contains(&search) || value.to_lowercase().contains(&search)
+        });
+    }
+    let name_width = fields
+        .iter()
+        .map(|(name, _)| name.width())
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let mut text: Vec<_> = fields
+        .into_iter()
+        .map(|(name, value)| Line::from(format!("{:name_width$} {value}", format!("{name}:"))))
+        .collect();
+    if let (Some(search), true) = (search, text.is_empty()) {
+        text.push(Line::from(format!("Nothing matching {search:?}")).italic());
+    }
+    if details.is_none() {
+        text.push(Line::from("Loading details...").italic());
+    }
     ("Contact info", Text::from(text))
 }
 
+/// A duration in seconds in its largest whole unit, such as `1w` or `90m`.
+fn human_duration(secs: u64) -> String {
+    let units = [
+        ("w", 7 * 24 * 60 * 60),
+        ("d", 24 * 60 * 60),
+        ("h", 60 * 60),
+        ("m", 60),
+    ];
+    units
+        .into_iter()
+        .find(|(_, unit)| secs > 0 && secs.is_multiple_of(*unit))
+        .map_or_else(
+            || format!("{secs}s"),
+            |(name, unit)| format!("{}{name}", secs / unit),
+        )
+}
+
 fn render_keybinds(keybindings: &KeyBinds) -> (&'static str, Text) {
-    fn display_keybinds<'a>(bindings: impl Iterator<Item = (&'a KeyEvents, &'a String)>) -> String {
+    fn display_keybinds<'a>(
+        bindings: impl Iterator<Item = (&'a KeyEvents, &'a Binding)>,
+    ) -> String {
         let mut bs = bindings
             .map(|(k, c)| format!("{} = {}", k, c))
             .collect::<Vec<_>>();
@@ -479,17 +2032,26 @@ fn render_keybinds(keybindings: &KeyBinds) -> (&'static str, Text) {
     ("Keybindings", Text::from(text))
 }
 
-fn render_commands() -> (&'static str, Text<'static>) {
+fn render_commands(plugins: &Plugins) -> (&'static str, Text<'static>) {
     let mut commands = crate::commands::commands()
         .into_iter()
         .map(|c| {
-            c.names()
+            let names = c
+                .names()
                 .into_iter()
                 .map(|s| format!(":{s}"))
                 .collect::<Vec<_>>()
-                .join(", ")
+                .join(", ");
+            format!("{names} - {}", c.help().description)
         })
         .collect::<Vec<_>>();
+    commands.extend(plugins.commands().into_iter().map(|c| {
+        if c.description.is_empty() {
+            format!(":{}", c.name)
+        } else {
+            format!(":{} - {}", c.name, c.description)
+        }
+    }));
     commands.sort();
     let text = commands.join("\n");
 
@@ -527,3 +2089,440 @@ fn render_scrollbar(frame: &mut Frame<'_>, area: Rect, length: usize, position:
         ..area
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::style::Modifier;
+    use ratatui::Terminal;
+
+    use crate::testing::contact;
+
+    #[test]
+    fn test_truncate_middle() {
+        let names = [
+            "short",
+            "A very long group name about everything",
+            "日本語のとても長いグループ名",
+            "emoji 🎉🎉🎉 party planning",
+        ];
+        insta::assert_debug_snapshot!(names.map(|n| truncate_middle(n, 16)));
+        insta::assert_debug_snapshot!(names.map(|n| truncate_or_pad(n.to_owned(), 8)));
+
+        // sequences of emoji joined with zero width joiners are kept whole
+        let family = "👩\u{200d}👩\u{200d}👧";
+        for width in 1..8 {
+            for truncated in [
+                truncate_middle(&format!("{family} trip"), width),
+                truncate_end(&format!("{family} trip"), width),
+            ] {
+                assert!(
+                    !truncated.contains('\u{200d}') || truncated.contains(family),
+                    "{truncated:?}"
+                );
+                assert!(truncated.width() <= width, "{truncated:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_preview() {
+        let text = |tui_state: &TuiState| {
+            let (_, text) = render_preview(60, tui_state);
+            text.lines
+                .iter()
+                .map(|l| {
+                    l.spans
+                        .iter()
+                        .map(|s| s.content.as_ref())
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let mut tui_state = TuiState::default();
+        tui_state.config.markdown = true;
+        assert_eq!(text(&tui_state), "Nothing to send");
+
+        // shown as it will be sent, without the markdown
+        tui_state
+            .compose
+            .set_text(vec!["see **this**".to_owned(), String::new()]);
+        let preview = text(&tui_state);
+        assert!(preview.contains("see this"), "{preview}");
+        assert!(!preview.contains("**"), "{preview}");
+        assert!(
+            preview.ends_with("Within the limits of the backend"),
+            "{preview}"
+        );
+
+        tui_state.backend_name = "test".to_owned();
+        tui_state.limits.max_body_length = Some(4);
+        let preview = text(&tui_state);
+        assert!(
+            preview.ends_with("Message is 8 bytes, exceeding test's 4 byte limit"),
+            "{preview}"
+        );
+    }
+
+    #[test]
+    fn test_render_contacts_verification() {
+        let mut tui_state = TuiState::default();
+        tui_state.contacts.extend(vec![
+            contact(ContactId::User(vec![1]), "Alice"),
+            Contact {
+                verification: Verification::Verified,
+                ..contact(ContactId::User(vec![2]), "Bob")
+            },
+            Contact {
+                verification: Verification::Changed,
+                ..contact(ContactId::User(vec![3]), "Carol")
+            },
+        ]);
+        let mut terminal = Terminal::new(TestBackend::new(20, 4)).unwrap();
+        terminal
+            .draw(|frame| render_contacts(frame, frame.area(), &mut tui_state, 0))
+            .unwrap();
+        insta::assert_snapshot!(terminal.backend());
+    }
+
+    #[test]
+    fn test_render_verification() {
+        let text = |tui_state: &TuiState| {
+            let (_, text) = render_verification(tui_state);
+            text.lines
+                .iter()
+                .map(|l| {
+                    l.spans
+                        .iter()
+                        .map(|s| s.content.as_ref())
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut tui_state = TuiState {
+            device_verification: Some(DeviceVerification::Compare {
+                other_device: "phone".to_owned(),
+                emoji: vec!["🐶 Dog".to_owned(), "🔑 Key".to_owned()],
+            }),
+            ..Default::default()
+        };
+        insta::assert_debug_snapshot!(text(&tui_state));
+        tui_state.device_verification = Some(DeviceVerification::Cancelled {
+            reason: "The emoji didn't match".to_owned(),
+        });
+        insta::assert_debug_snapshot!(text(&tui_state));
+    }
+
+    #[test]
+    fn test_render_header() {
+        let group = ContactId::Group(vec![9]);
+        let mut tui_state = TuiState::default();
+        tui_state.contacts.extend(vec![
+            Contact {
+                members: Some(4),
+                encrypted: true,
+                description: "Weekend trips\nand gear swaps".to_owned(),
+                ..contact(group.clone(), "Climbing")
+            },
+            contact(ContactId::User(vec![1]), "Alice"),
+            contact(ContactId::User(vec![2]), "Bob"),
+        ]);
+        tui_state.contacts.state.select(Some(0));
+        tui_state.typing.insert(group, vec![vec![1], vec![2]]);
+
+        let render = |width| {
+            let mut terminal = Terminal::new(TestBackend::new(width, 1)).unwrap();
+            let contact = tui_state.contacts.selected().unwrap();
+            terminal
+                .draw(|frame| render_header(frame, frame.area(), &tui_state, contact, 0))
+                .unwrap();
+            terminal.backend().to_string()
+        };
+        // details that don't fit are left out whole, along with the description
+        insta::assert_snapshot!([render(90), render(50), render(20)].join(""));
+    }
+
+    #[test]
+    fn test_render_contact_info() {
+        let text = |tui_state: &TuiState, search| {
+            let contact = tui_state.contacts.selected().unwrap();
+            let (_, text) = render_contact_info(tui_state, contact, search);
+            text.lines
+                .iter()
+                .map(|l| {
+                    l.spans
+                        .iter()
+                        .map(|s| s.content.as_ref())
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut tui_state = TuiState::default();
+        tui_state.contacts.extend(vec![Contact {
+            members: Some(3),
+            ..contact(ContactId::User(vec![9]), "Climbing")
+        }]);
+        tui_state.contacts.state.select(Some(0));
+        insta::assert_debug_snapshot!(text(&tui_state, None));
+
+        let id = ContactId::User(vec![9]);
+        tui_state.contact_details.insert(
+            id,
+            ContactDetails {
+                role: Some("admin".to_owned()),
+                disappearing_timer: Some(7 * 24 * 60 * 60),
+                profile: vec![("About".to_owned(), "Mostly bouldering".to_owned())],
+            },
+        );
+        insta::assert_debug_snapshot!(text(&tui_state, None));
+        insta::assert_debug_snapshot!(text(&tui_state, Some("BOULDER")));
+        insta::assert_debug_snapshot!(text(&tui_state, Some("nothing")));
+    }
+
+    #[test]
+    fn test_human_duration() {
+        assert_eq!(human_duration(0), "0s");
+        assert_eq!(human_duration(30), "30s");
+        assert_eq!(human_duration(90 * 60), "90m");
+        assert_eq!(human_duration(24 * 60 * 60), "1d");
+        assert_eq!(human_duration(14 * 24 * 60 * 60), "2w");
+    }
+
+    #[test]
+    fn test_render_receipts() {
+        let text = |tui_state: &TuiState, message| {
+            render_receipts(tui_state, message)
+                .iter()
+                .map(|l| {
+                    l.spans
+                        .iter()
+                        .map(|s| s.content.as_ref())
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+        };
+        let group = ContactId::Group(vec![9]);
+        let message = Message {
+            timestamp: 10,
+            sender: vec![0],
+            contact_id: group.clone(),
+            content: "hi".to_owned(),
+            reactions: Vec::new(),
+            attachments: Vec::new(),
+            quote: None,
+            edits: Vec::new(),
+            formatting: Vec::new(),
+            mentions: Vec::new(),
+            spoilers_revealed: false,
+        };
+        let mut tui_state = TuiState::default();
+        tui_state.contacts.extend(vec![
+            contact(ContactId::User(vec![1]), "Alice"),
+            contact(ContactId::User(vec![2]), "Bob"),
+        ]);
+        assert_eq!(text(&tui_state, &message)[2], "Loading…");
+
+        tui_state
+            .message_receipts
+            .insert((group.clone(), 10), Vec::new());
+        assert_eq!(text(&tui_state, &message)[2], "None yet");
+
+        let receipt = |recipient: &[u8], delivered, read| Receipt {
+            recipient: recipient.to_vec(),
+            delivered,
+            read,
+        };
+        tui_state.message_receipts.insert(
+            (group, 10),
+            vec![
+                receipt(&[1], Some(100), Some(200)),
+                receipt(&[2], Some(100), None),
+                // not known as a contact
+                receipt(b"carol", None, None),
+            ],
+        );
+        let lines = text(&tui_state, &message);
+        assert!(
+            lines[2].starts_with("Alice                read "),
+            "{lines:?}"
+        );
+        assert!(
+            lines[3].starts_with("Bob                  delivered "),
+            "{lines:?}"
+        );
+        assert_eq!(lines[4], "carol                sent");
+    }
+
+    #[test]
+    fn test_message_layouts() {
+        let message = |sender: &[u8]| Message {
+            timestamp: 0,
+            sender: sender.to_vec(),
+            contact_id: ContactId::User(b"alice".to_vec()),
+            content: "hi".to_owned(),
+            reactions: Vec::new(),
+            attachments: Vec::new(),
+            quote: None,
+            edits: Vec::new(),
+            formatting: Vec::new(),
+            mentions: Vec::new(),
+            spoilers_revealed: false,
+        };
+        let mut tui_state = TuiState {
+            self_id: b"me".to_vec(),
+            ..Default::default()
+        };
+        let render = |tui_state: &TuiState, sender: &[u8]| {
+            let mut cache = RenderCache::default();
+            render_message_item(tui_state, &mut cache, &message(sender), 30, false, 60_000)
+                .lines
+                .iter()
+                .map(|l| l.spans.iter().map(|s| s.content.as_ref()).collect())
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(
+            render(&tui_state, b"alice"),
+            [format!("alice{} 1m   hi", " ".repeat(16))]
+        );
+
+        tui_state.config.message_layout = MessageLayout::Compact;
+        assert_eq!(render(&tui_state, b"alice"), ["alice 1m   hi"]);
+        tui_state.config.timestamps = Timestamps::Both;
+        assert_eq!(
+            render(&tui_state, b"alice"),
+            [format!("alice {} 1m   hi", clock_time(0, 60_000))]
+        );
+        tui_state.config.timestamps = Timestamps::Relative;
+
+        tui_state.config.message_layout = MessageLayout::Cozy;
+        assert_eq!(render(&tui_state, b"alice"), ["alice 1m", "    hi", ""]);
+        // the user's own messages sit on the right
+        assert_eq!(
+            render(&tui_state, b"me"),
+            [
+                format!("{}me 1m", " ".repeat(25)),
+                format!("{}    hi", " ".repeat(24)),
+                String::new(),
+            ]
+        );
+
+        tui_state.config.own_messages = OwnMessages::Left;
+        assert_eq!(render(&tui_state, b"me"), ["me 1m", "    hi", ""]);
+        tui_state.config.own_messages = OwnMessages::Marked;
+        assert_eq!(render(&tui_state, b"me"), ["» me 1m", "    hi", ""]);
+        tui_state.config.message_layout = MessageLayout::Compact;
+        tui_state.config.own_messages = OwnMessages::Right;
+        assert_eq!(
+            render(&tui_state, b"me"),
+            [format!("{}me 1m   hi", " ".repeat(20))]
+        );
+    }
+
+    #[test]
+    fn test_highlight_matches() {
+        let regex = regex::RegexBuilder::new("lo")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        let line = Line::from(vec![Span::from("  hello "), Span::from("LOUD").bold()]);
+        let line = highlight_matches(line, &regex, Style::new().reversed());
+        let parts = line
+            .spans
+            .iter()
+            .map(|s| (s.content.as_ref(), s.style.add_modifier))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parts,
+            [
+                ("  hel", Modifier::empty()),
+                ("lo", Modifier::REVERSED),
+                (" ", Modifier::empty()),
+                ("LO", Modifier::BOLD | Modifier::REVERSED),
+                ("UD", Modifier::BOLD),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_highlight_matches_across_spans() {
+        let regex = regex::Regex::new("lo wo|ld!").unwrap();
+        let line = Line::from(vec![
+            Span::from("hello"),
+            Span::from(" ").italic(),
+            Span::from("world").bold(),
+            Span::from("!"),
+        ]);
+        let line = highlight_matches(line, &regex, Style::new().reversed());
+        let parts = line
+            .spans
+            .iter()
+            .map(|s| (s.content.as_ref(), s.style.add_modifier))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parts,
+            [
+                ("hel", Modifier::empty()),
+                ("lo", Modifier::REVERSED),
+                (" ", Modifier::ITALIC | Modifier::REVERSED),
+                ("wo", Modifier::BOLD | Modifier::REVERSED),
+                ("r", Modifier::BOLD),
+                ("ld", Modifier::BOLD | Modifier::REVERSED),
+                ("!", Modifier::REVERSED),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_needs_redraw() {
+        let minute = 60 * 1000;
+        let hour = 60 * minute;
+        let now = 1000 * hour;
+        let mut tui_state = TuiState::default();
+        tui_state.update_redraw_at(now);
+        // nothing shown changes with time
+        assert!(!tui_state.needs_redraw(now + 1000 * hour));
+
+        // ages in seconds change a second on from when it was sent
+        tui_state.contacts.extend(vec![Contact {
+            last_message_timestamp: Some(now - 1500),
+            ..contact(ContactId::User(vec![1]), "Alice")
+        }]);
+        tui_state.update_redraw_at(now);
+        assert!(!tui_state.needs_redraw(now + 499));
+        assert!(tui_state.needs_redraw(now + 500));
+
+        // and then as the minutes, hours and days pass
+        tui_state.contacts = Contacts::new(vec![Contact {
+            last_message_timestamp: Some(now - 90 * minute),
+            ..contact(ContactId::User(vec![1]), "Alice")
+        }]);
+        tui_state.update_redraw_at(now);
+        assert!(!tui_state.needs_redraw(now + 30 * minute - 1));
+        assert!(tui_state.needs_redraw(now + 30 * minute));
+
+        tui_state.update_redraw_at(now + 30 * minute);
+        assert!(!tui_state.needs_redraw(now + 90 * minute - 1));
+        assert!(tui_state.needs_redraw(now + 90 * minute));
+
+        tui_state.dirty = true;
+        assert!(tui_state.needs_redraw(now));
+    }
+
+    #[test]
+    fn test_next_age_change() {
+        let minute = 60 * 1000;
+        let day = 24 * 60 * minute;
+        assert_eq!(next_age_change(0, 0), 1000);
+        // sent in the future, shown as now until it has been a second
+        assert_eq!(next_age_change(5000, 0), 6000);
+        assert_eq!(next_age_change(0, 59_999), minute);
+        assert_eq!(next_age_change(0, 90 * minute), 2 * 60 * minute);
+        // 4w becomes 1M at 30 days rather than 5w at 35 days
+        assert_eq!(next_age_change(0, 29 * day), 30 * day);
+        // 12M becomes 1y at 365 days
+        assert_eq!(next_age_change(0, 361 * day), 365 * day);
+    }
+}
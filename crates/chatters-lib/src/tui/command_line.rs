@@ -1,4 +1,4 @@
-use crossterm::event::KeyEvent;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use tui_textarea::TextArea;
 
 use crate::{command_history::CommandLineHistory, commands::Completion};
@@ -26,11 +26,7 @@ impl CommandLine {
             .map(|i| self.completions.candidates[i].clone())
         {
             let mut textarea = self.command.clone();
-            let char_count = completion.append.chars().count();
-            for _ in 0..char_count {
-                textarea.move_cursor(tui_textarea::CursorMove::Back);
-            }
-            textarea.delete_str(char_count);
+            remove_completion(&mut textarea, &completion);
             textarea.lines().first().unwrap().to_owned()
         } else {
             self.text().to_owned()
@@ -62,6 +58,61 @@ impl CommandLine {
         self.command.input(key_event);
     }
 
+    /// Search back through the history for an older command containing what is typed after,
+    /// starting the search if not searching yet.
+    pub fn search_history(&mut self) {
+        self.completions.clear();
+        if self.history.search_query().is_none() {
+            self.history.start_search();
+        } else {
+            self.history.search_older();
+        }
+        self.show_found_command();
+    }
+
+    /// Type a key into the history search, returning whether it was used. Other keys end the
+    /// search, leaving the command found to be edited or run.
+    pub fn search_input(&mut self, key_event: KeyEvent) -> bool {
+        let Some(query) = self.history.search_query() else {
+            return false;
+        };
+        let mut query = query.to_owned();
+        match key_event.code {
+            KeyCode::Char(c)
+                if !key_event
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                query.push(c)
+            }
+            KeyCode::Backspace => {
+                query.pop();
+            }
+            _ => {
+                self.history.stop_search();
+                return false;
+            }
+        }
+        self.history.set_search_query(query);
+        self.show_found_command();
+        true
+    }
+
+    fn show_found_command(&mut self) {
+        let text = self.history.selected_command().cloned().unwrap_or_default();
+        self.set_text(text);
+    }
+
+    /// Put the completion in place of what it completes before the cursor.
+    pub fn insert_completion(&mut self, completion: &Completion) {
+        let char_count = completion.replace.chars().count();
+        for _ in 0..char_count {
+            self.command.move_cursor(tui_textarea::CursorMove::Back);
+        }
+        self.command.delete_str(char_count);
+        self.command.insert_str(&completion.append);
+    }
+
     pub fn completions(&self) -> &[Completion] {
         self.completions.candidates()
     }
@@ -87,11 +138,7 @@ impl CommandLine {
             .map(|i| self.completions.candidates[i].clone());
         self.completions.select_next();
         if let Some(last) = last {
-            let char_count = last.append.chars().count();
-            for _ in 0..char_count {
-                self.command.move_cursor(tui_textarea::CursorMove::Back);
-            }
-            self.command.delete_str(char_count);
+            remove_completion(&mut self.command, &last);
         }
         if let Some(comp) = self
             .completions
@@ -99,7 +146,7 @@ impl CommandLine {
             .selected()
             .map(|i| self.completions.candidates[i].clone())
         {
-            self.command.insert_str(comp.append);
+            self.insert_completion(&comp);
         }
     }
 
@@ -111,11 +158,7 @@ impl CommandLine {
             .map(|i| self.completions.candidates[i].clone());
         self.completions.select_previous();
         if let Some(last) = last {
-            let char_count = last.append.chars().count();
-            for _ in 0..char_count {
-                self.command.move_cursor(tui_textarea::CursorMove::Back);
-            }
-            self.command.delete_str(char_count);
+            remove_completion(&mut self.command, &last);
         }
         if let Some(comp) = self
             .completions
@@ -123,7 +166,7 @@ impl CommandLine {
             .selected()
             .map(|i| self.completions.candidates[i].clone())
         {
-            self.command.insert_str(comp.append);
+            self.insert_completion(&comp);
         }
     }
 
@@ -132,6 +175,16 @@ impl CommandLine {
     }
 }
 
+/// Take the completion before the cursor back out, restoring what it replaced.
+fn remove_completion(textarea: &mut TextArea<'static>, completion: &Completion) {
+    let char_count = completion.append.chars().count();
+    for _ in 0..char_count {
+        textarea.move_cursor(tui_textarea::CursorMove::Back);
+    }
+    textarea.delete_str(char_count);
+    textarea.insert_str(&completion.replace);
+}
+
 #[derive(Debug, Default)]
 pub struct Completions {
     pub candidates: Vec<Completion>,
@@ -158,7 +211,7 @@ impl Completions {
         self.generated_for = None;
     }
 
-    fn select_next(&mut self) {
+    pub(super) fn select_next(&mut self) {
         if self.candidates.is_empty() {
             return;
         }
@@ -174,7 +227,7 @@ impl Completions {
         }
     }
 
-    fn select_previous(&mut self) {
+    pub(super) fn select_previous(&mut self) {
         if self.candidates.is_empty() {
             return;
         }
@@ -1,16 +1,33 @@
 use std::{os::unix::fs::MetadataExt as _, path::PathBuf};
 
 use crossterm::event::KeyEvent;
+use log::warn;
 use ratatui::{
     layout::{Constraint, Layout},
     style::Style,
     widgets::{Block, Paragraph},
 };
-use tui_textarea::TextArea;
+use tui_textarea::{CursorMove, TextArea};
 
-use crate::backends::MessageAttachment;
+use crate::{backends::MessageAttachment, commands::Completion, spellcheck::is_word_char};
 
-use super::messages::Quote;
+use super::{command_line::Completions, messages::Quote};
+
+/// What was written in compose, kept to put back with [`Compose::restore`].
+#[derive(Debug, Clone, Default)]
+pub struct Draft {
+    lines: Vec<String>,
+    quote: Option<Quote>,
+    attachments: Vec<MessageAttachment>,
+}
+
+impl Draft {
+    pub fn is_empty(&self) -> bool {
+        self.lines.iter().all(|l| l.is_empty())
+            && self.quote.is_none()
+            && self.attachments.is_empty()
+    }
+}
 
 #[derive(Debug, Default)]
 pub struct Compose {
@@ -18,6 +35,9 @@ pub struct Compose {
     block: Block<'static>,
     quote: Option<Quote>,
     attachments: Vec<MessageAttachment>,
+    pub completions: Completions,
+    /// The word before the cursor when completing started, shown again after the last candidate.
+    completing: Option<String>,
 }
 
 impl Compose {
@@ -25,6 +45,12 @@ impl Compose {
         self.textarea = TextArea::new(lines);
     }
 
+    /// Insert text at the cursor.
+    pub fn insert_str(&mut self, text: &str) {
+        self.clear_completions();
+        self.textarea.insert_str(text);
+    }
+
     pub fn set_quote(&mut self, quote: Quote) {
         self.quote = Some(quote);
     }
@@ -44,6 +70,7 @@ impl Compose {
             index: 0,
             size: path.metadata().map(|m| m.size()).unwrap_or(0),
             path: Some(path),
+            content_type: None,
         })
     }
 
@@ -71,13 +98,152 @@ impl Compose {
     }
 
     pub fn input(&mut self, key_event: KeyEvent) {
+        self.clear_completions();
         self.textarea.input(key_event);
     }
 
+    pub fn draft(&self) -> Draft {
+        Draft {
+            lines: self.lines().to_vec(),
+            quote: self.quote.clone(),
+            attachments: self.attachments.clone(),
+        }
+    }
+
+    /// Put back what was written, replacing what is there now.
+    pub fn restore(&mut self, draft: Draft) {
+        self.clear();
+        self.textarea = TextArea::new(draft.lines);
+        self.textarea.move_cursor(CursorMove::Bottom);
+        self.textarea.move_cursor(CursorMove::End);
+        self.quote = draft.quote;
+        self.attachments = draft.attachments;
+    }
+
     pub fn clear(&mut self) {
         self.textarea = TextArea::default();
         self.quote = None;
         self.attachments.clear();
+        self.clear_completions();
+    }
+
+    /// Underline the misspelled words.
+    pub fn set_misspelled(&mut self, words: &[String], style: Style) {
+        let pattern = if words.is_empty() {
+            String::new()
+        } else {
+            let words: Vec<_> = words.iter().map(|w| regex::escape(w)).collect();
+            format!(r"\b({})\b", words.join("|"))
+        };
+        if let Err(error) = self.textarea.set_search_pattern(pattern) {
+            warn!(error:%; "Failed to highlight misspelled words");
+        }
+        self.textarea.set_search_style(style);
+    }
+
+    /// The partial word just before the cursor.
+    pub fn word_before_cursor(&self) -> String {
+        let (row, col) = self.textarea.cursor();
+        let Some(line) = self.textarea.lines().get(row) else {
+            return String::new();
+        };
+        let before: Vec<char> = line.chars().take(col).collect();
+        let start = before
+            .iter()
+            .rposition(|c| !is_word_char(*c))
+            .map_or(0, |i| i + 1);
+        before[start..].iter().collect()
+    }
+
+    /// The name typed so far after an `@` just before the cursor, if a mention is being typed.
+    pub fn mention_before_cursor(&self) -> Option<String> {
+        let (row, col) = self.textarea.cursor();
+        let line = self.textarea.lines().get(row)?;
+        let before: Vec<char> = line.chars().take(col).collect();
+        let at = before.iter().rposition(|c| *c == '@')?;
+        let name = &before[at + 1..];
+        if name.iter().any(|c| c.is_whitespace()) {
+            return None;
+        }
+        // an `@` within a word is more likely an email address
+        if at > 0 && !before[at - 1].is_whitespace() {
+            return None;
+        }
+        Some(name.iter().collect())
+    }
+
+    pub fn is_completing(&self) -> bool {
+        self.completing.is_some()
+    }
+
+    /// Offer replacements for the word before the cursor.
+    pub fn set_word_completions(&mut self, words: Vec<String>) {
+        let word = self.word_before_cursor();
+        let completions = words
+            .into_iter()
+            .map(|w| Completion {
+                display: w.clone(),
+                append: w,
+                ..Default::default()
+            })
+            .collect();
+        self.completions.set_completions(completions, word.clone());
+        self.completing = Some(word);
+    }
+
+    /// Offer names to mention in place of the mention before the cursor.
+    pub fn set_mention_completions(&mut self, names: Vec<String>) {
+        let Some(name) = self.mention_before_cursor() else {
+            return;
+        };
+        let mention = format!("@{name}");
+        let completions = names
+            .into_iter()
+            .map(|n| Completion {
+                display: format!("@{n}"),
+                append: format!("@{n}"),
+                ..Default::default()
+            })
+            .collect();
+        self.completions
+            .set_completions(completions, mention.clone());
+        self.completing = Some(mention);
+    }
+
+    pub fn select_next_completion(&mut self) {
+        let current = self.current_completion();
+        self.completions.select_next();
+        self.replace_word(&current);
+    }
+
+    pub fn select_previous_completion(&mut self) {
+        let current = self.current_completion();
+        self.completions.select_previous();
+        self.replace_word(&current);
+    }
+
+    /// Keep the selected candidate in the text and stop completing.
+    pub fn clear_completions(&mut self) {
+        self.completions.clear();
+        self.completing = None;
+    }
+
+    /// The word currently in the text, either the selected candidate or the original.
+    fn current_completion(&self) -> String {
+        match self.completions.list_state.selected() {
+            Some(i) => self.completions.candidates[i].append.clone(),
+            None => self.completing.clone().unwrap_or_default(),
+        }
+    }
+
+    fn replace_word(&mut self, current: &str) {
+        let char_count = current.chars().count();
+        for _ in 0..char_count {
+            self.textarea.move_cursor(CursorMove::Back);
+        }
+        self.textarea.delete_str(char_count);
+        let new = self.current_completion();
+        self.textarea.insert_str(new);
     }
 
     pub fn height(&self) -> u16 {
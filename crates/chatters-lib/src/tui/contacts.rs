@@ -2,13 +2,20 @@ use std::collections::BTreeMap;
 
 use ratatui::widgets::TableState;
 
-use crate::backends::{Contact, ContactId};
+use crate::backends::{timestamp, Contact, ContactId};
 
 #[derive(Debug, Default)]
 pub struct Contacts {
     contacts_and_groups: Vec<Contact>,
     contacts_by_id: BTreeMap<Vec<u8>, Contact>,
+    unread: BTreeMap<ContactId, usize>,
+    /// How many of the unread messages mention the user.
+    mentions: BTreeMap<ContactId, usize>,
+    /// When each contact was last read, in milliseconds since the unix epoch.
+    last_read: BTreeMap<ContactId, u64>,
     pub state: TableState,
+    /// The first row shown, which isn't the first contact shown when there are folder headings.
+    pub row_offset: usize,
 }
 
 impl Contacts {
@@ -26,7 +33,11 @@ impl Contacts {
         Self {
             contacts_and_groups,
             contacts_by_id,
+            unread: BTreeMap::new(),
+            mentions: BTreeMap::new(),
+            last_read: BTreeMap::new(),
             state: TableState::default(),
+            row_offset: 0,
         }
     }
 
@@ -66,6 +77,41 @@ impl Contacts {
         self.contacts_and_groups.len()
     }
 
+    /// The number of unread messages for a contact.
+    pub fn unread_count(&self, id: &ContactId) -> usize {
+        self.unread.get(id).copied().unwrap_or_default()
+    }
+
+    /// The number of unread messages across all contacts.
+    pub fn total_unread_count(&self) -> usize {
+        self.unread.values().sum()
+    }
+
+    pub fn add_unread(&mut self, id: &ContactId) {
+        *self.unread.entry(id.clone()).or_default() += 1;
+    }
+
+    /// The number of unread messages for a contact which mention the user.
+    pub fn mention_count(&self, id: &ContactId) -> usize {
+        self.mentions.get(id).copied().unwrap_or_default()
+    }
+
+    /// Count an unread message as mentioning the user, alongside [`Self::add_unread`].
+    pub fn add_mention(&mut self, id: &ContactId) {
+        *self.mentions.entry(id.clone()).or_default() += 1;
+    }
+
+    pub fn mark_read(&mut self, id: &ContactId) {
+        self.unread.remove(id);
+        self.mentions.remove(id);
+        self.last_read.insert(id.clone(), timestamp());
+    }
+
+    /// When the contact was last read, if it has been since starting.
+    pub fn last_read(&self, id: &ContactId) -> Option<u64> {
+        self.last_read.get(id).copied()
+    }
+
     pub fn clear(&mut self) {
         self.contacts_and_groups.clear();
         self.contacts_by_id.clear();
@@ -76,6 +122,16 @@ impl Contacts {
         self.contacts_and_groups.insert(to, c);
     }
 
+    /// Sort the contacts by the key, keeping the same one selected.
+    pub fn sort_by_key<K: Ord>(&mut self, key: impl FnMut(&Contact) -> K) {
+        let selected = self.selected().map(|c| c.id.clone());
+        self.contacts_and_groups.sort_by_key(key);
+        if let Some(id) = selected {
+            let index = self.index_by_id(&id);
+            self.state.select(index);
+        }
+    }
+
     pub fn selected(&self) -> Option<&Contact> {
         self.state
             .selected()
@@ -0,0 +1,271 @@
+use emojis::{Emoji, Group};
+use ratatui::{
+    style::{Style, Stylize as _},
+    text::{Line, Text},
+};
+
+use crate::emoji_usage::EmojiUsage;
+
+/// How many of the most recently used reactions the quick menu offers, picked with a digit.
+pub const QUICK_COUNT: usize = 9;
+
+/// Searchable emoji, grouped as usual with the most used first.
+#[derive(Debug)]
+pub struct EmojiPicker {
+    pub query: String,
+    /// Index of the selected emoji among the matching ones.
+    pub selected: usize,
+    /// Insert the emoji into the compose box rather than reacting to the selected message.
+    pub compose: bool,
+    /// Only offer the most recently used reactions, numbered to pick with a digit, until
+    /// something is typed to search all of the emoji.
+    pub quick: bool,
+}
+
+enum Entry<'a> {
+    Heading(&'static str),
+    Emoji(&'static Emoji),
+    /// A reaction used before that isn't a known emoji, such as a Matrix reaction key.
+    Other(&'a str),
+}
+
+impl Entry<'_> {
+    fn used(reaction: &str) -> Entry<'_> {
+        emojis::get(reaction).map_or(Entry::Other(reaction), Entry::Emoji)
+    }
+}
+
+impl EmojiPicker {
+    pub fn new(compose: bool) -> Self {
+        Self {
+            query: String::new(),
+            selected: 0,
+            compose,
+            quick: false,
+        }
+    }
+
+    /// A picker of the most recently used reactions, for reacting to the selected message.
+    pub fn quick() -> Self {
+        Self {
+            quick: true,
+            ..Self::new(false)
+        }
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+        self.selected = 0;
+        self.quick = false;
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+        self.selected = 0;
+    }
+
+    pub fn select_next(&mut self, usage: &EmojiUsage) {
+        let count = self.matches(usage).count();
+        self.selected = (self.selected + 1).min(count.saturating_sub(1));
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Select the match at the index, returning whether there is one.
+    pub fn select(&mut self, index: usize, usage: &EmojiUsage) -> bool {
+        if index < self.matches(usage).count() {
+            self.selected = index;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn selected_emoji(&self, usage: &EmojiUsage) -> Option<String> {
+        self.matches(usage).nth(self.selected)
+    }
+
+    fn matches<'a>(&self, usage: &'a EmojiUsage) -> impl Iterator<Item = String> + 'a {
+        self.entries(usage)
+            .into_iter()
+            .filter_map(|entry| match entry {
+                Entry::Heading(_) => None,
+                Entry::Emoji(e) => Some(e.as_str().to_owned()),
+                Entry::Other(reaction) => Some(reaction.to_owned()),
+            })
+    }
+
+    fn entries<'a>(&self, usage: &'a EmojiUsage) -> Vec<Entry<'a>> {
+        let query = self.query.to_lowercase();
+        let mut entries = Vec::new();
+        if self.quick {
+            entries.push(Entry::Heading("Recently used"));
+            entries.extend(
+                usage
+                    .recent()
+                    .into_iter()
+                    .take(QUICK_COUNT)
+                    .map(Entry::used),
+            );
+            return entries;
+        }
+        if query.is_empty() {
+            let used = usage.ranked();
+            if !used.is_empty() {
+                entries.push(Entry::Heading("Frequently used"));
+                entries.extend(used.into_iter().map(Entry::used));
+            }
+        }
+        for group in Group::iter() {
+            let mut emoji = group
+                .emojis()
+                .filter(|e| {
+                    query.is_empty()
+                        || e.name().contains(&query)
+                        || e.shortcodes().any(|s| s.contains(&query))
+                })
+                .peekable();
+            if emoji.peek().is_some() {
+                entries.push(Entry::Heading(group_name(group)));
+                entries.extend(emoji.map(Entry::Emoji));
+            }
+        }
+        entries
+    }
+
+    /// The lines to show, and which of them is selected.
+    pub fn render(&self, usage: &EmojiUsage) -> (Text<'static>, usize) {
+        let mut lines = vec![
+            Line::from(format!("Search: {}▏", self.query)),
+            Line::from(""),
+        ];
+        let mut selected_line = 0;
+        let mut index = 0;
+        for entry in self.entries(usage) {
+            let line = match entry {
+                Entry::Heading(heading) => {
+                    lines.push(Line::from(heading).bold());
+                    continue;
+                }
+                Entry::Emoji(e) => {
+                    let shortcode = e.shortcode().map(|s| format!(":{s}: ")).unwrap_or_default();
+                    format!("{} {shortcode}{}", e.as_str(), e.name())
+                }
+                Entry::Other(reaction) => reaction.to_owned(),
+            };
+            let line = if self.quick {
+                Line::from(format!("  {} {line}", index + 1))
+            } else {
+                Line::from(format!("  {line}"))
+            };
+            if index == self.selected {
+                selected_line = lines.len();
+                lines.push(line.style(Style::new().reversed()));
+            } else {
+                lines.push(line);
+            }
+            index += 1;
+        }
+        if index == 0 {
+            lines.push(Line::from("No matching emoji"));
+        }
+        (Text::from(lines), selected_line)
+    }
+}
+
+fn group_name(group: Group) -> &'static str {
+    match group {
+        Group::SmileysAndEmotion => "Smileys & emotion",
+        Group::PeopleAndBody => "People & body",
+        Group::AnimalsAndNature => "Animals & nature",
+        Group::FoodAndDrink => "Food & drink",
+        Group::TravelAndPlaces => "Travel & places",
+        Group::Activities => "Activities",
+        Group::Objects => "Objects",
+        Group::Symbols => "Symbols",
+        Group::Flags => "Flags",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(picker: &EmojiPicker, usage: &EmojiUsage) -> Vec<String> {
+        let (text, _) = picker.render(usage);
+        text.lines
+            .iter()
+            .map(|l| {
+                l.spans
+                    .iter()
+                    .map(|s| s.content.as_ref())
+                    .collect::<String>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_search() {
+        let usage = EmojiUsage::default();
+        let mut picker = EmojiPicker::new(true);
+        for c in "thumbs".chars() {
+            picker.push(c);
+        }
+        insta::assert_debug_snapshot!(lines(&picker, &usage));
+
+        picker.select_next(&usage);
+        assert_eq!(picker.selected_emoji(&usage).unwrap().as_str(), "👎");
+        // stays on the last match
+        picker.select_next(&usage);
+        assert_eq!(picker.selected_emoji(&usage).unwrap().as_str(), "👎");
+        picker.select_previous();
+        assert_eq!(picker.selected_emoji(&usage).unwrap().as_str(), "👍");
+
+        // searching again starts from the first match
+        picker.push('x');
+        assert!(picker.selected_emoji(&usage).is_none());
+        assert_eq!(lines(&picker, &usage).last().unwrap(), "No matching emoji");
+        picker.pop();
+        assert_eq!(picker.selected, 0);
+    }
+
+    #[test]
+    fn test_frequently_used_first() {
+        let mut usage = EmojiUsage::default();
+        usage.record("🎉");
+        let picker = EmojiPicker::new(false);
+        assert_eq!(picker.selected_emoji(&usage).unwrap().as_str(), "🎉");
+        let lines = lines(&picker, &usage);
+        assert_eq!(lines[2], "Frequently used");
+        assert_eq!(lines[4], "Smileys & emotion");
+    }
+
+    #[test]
+    fn test_quick() {
+        let mut usage = EmojiUsage::default();
+        usage.record("👍");
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        usage.record("party_parrot");
+        let mut picker = EmojiPicker::quick();
+        assert_eq!(
+            lines(&picker, &usage),
+            [
+                "Search: ▏",
+                "",
+                "Recently used",
+                "  1 party_parrot",
+                "  2 👍 :+1: thumbs up"
+            ]
+        );
+        assert!(picker.select(1, &usage));
+        assert_eq!(picker.selected_emoji(&usage).unwrap(), "👍");
+        assert!(!picker.select(2, &usage));
+
+        // searching goes back to all of the emoji
+        picker.push('t');
+        assert!(!picker.quick);
+        assert_eq!(lines(&picker, &usage)[2], "Smileys & emotion");
+    }
+}
@@ -0,0 +1,236 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use ratatui::{
+    style::{Style, Stylize as _},
+    text::{Line, Text},
+};
+
+use crate::backends::human_size;
+
+/// Browse directories to pick files to attach, marking several to attach them together.
+#[derive(Debug)]
+pub struct FilePicker {
+    pub dir: PathBuf,
+    entries: Vec<Entry>,
+    /// Index of the selected entry.
+    pub selected: usize,
+    pub show_hidden: bool,
+    pub marked: BTreeSet<PathBuf>,
+    /// Why the directory couldn't be read, if it couldn't.
+    error: Option<String>,
+    /// Opened while composing, to go back to afterwards.
+    pub compose: bool,
+}
+
+#[derive(Debug)]
+struct Entry {
+    name: String,
+    path: PathBuf,
+    is_dir: bool,
+    size: u64,
+}
+
+impl FilePicker {
+    pub fn new(dir: PathBuf, compose: bool) -> Self {
+        let mut picker = Self {
+            dir,
+            entries: Vec::new(),
+            selected: 0,
+            show_hidden: false,
+            marked: BTreeSet::new(),
+            error: None,
+            compose,
+        };
+        picker.load();
+        picker
+    }
+
+    /// Read the entries of the directory, directories first.
+    fn load(&mut self) {
+        self.entries.clear();
+        self.selected = 0;
+        self.error = None;
+        if let Some(parent) = self.dir.parent() {
+            self.entries.push(Entry {
+                name: "..".to_owned(),
+                path: parent.to_owned(),
+                is_dir: true,
+                size: 0,
+            });
+        }
+        let read = match std::fs::read_dir(&self.dir) {
+            Ok(read) => read,
+            Err(error) => {
+                self.error = Some(error.to_string());
+                return;
+            }
+        };
+        let mut entries: Vec<_> = read
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let name = e.file_name().to_string_lossy().into_owned();
+                // follow symlinks to show what they point to
+                let metadata = std::fs::metadata(e.path()).ok()?;
+                Some(Entry {
+                    name,
+                    path: e.path(),
+                    is_dir: metadata.is_dir(),
+                    size: metadata.len(),
+                })
+            })
+            .filter(|e| self.show_hidden || !e.name.starts_with('.'))
+            .collect();
+        entries.sort_by(|a, b| (!a.is_dir, &a.name).cmp(&(!b.is_dir, &b.name)));
+        self.entries.extend(entries);
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1).min(self.entries.len().saturating_sub(1));
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Go into the selected directory, returning the selected file if it isn't one.
+    pub fn open(&mut self) -> Option<PathBuf> {
+        let entry = self.entries.get(self.selected)?;
+        if entry.is_dir {
+            let dir = entry.path.clone();
+            self.change_dir(dir);
+            None
+        } else {
+            Some(entry.path.clone())
+        }
+    }
+
+    /// Go up to the parent directory, keeping the one left selected.
+    pub fn parent(&mut self) {
+        let Some(parent) = self.dir.parent().map(Path::to_owned) else {
+            return;
+        };
+        let left = std::mem::replace(&mut self.dir, parent);
+        self.load();
+        if let Some(index) = self.entries.iter().position(|e| e.path == left) {
+            self.selected = index;
+        }
+    }
+
+    fn change_dir(&mut self, dir: PathBuf) {
+        if self.dir.parent() == Some(dir.as_path()) {
+            self.parent();
+        } else {
+            self.dir = dir;
+            self.load();
+        }
+    }
+
+    pub fn toggle_hidden(&mut self) {
+        self.show_hidden = !self.show_hidden;
+        let selected = self.entries.get(self.selected).map(|e| e.path.clone());
+        self.load();
+        if let Some(index) = selected.and_then(|s| self.entries.iter().position(|e| e.path == s)) {
+            self.selected = index;
+        }
+    }
+
+    /// Mark or unmark the selected file, moving on to the next entry.
+    pub fn toggle_mark(&mut self) {
+        let Some(entry) = self.entries.get(self.selected) else {
+            return;
+        };
+        if entry.is_dir {
+            return;
+        }
+        if !self.marked.remove(&entry.path) {
+            self.marked.insert(entry.path.clone());
+        }
+        self.select_next();
+    }
+
+    /// The lines to show, and which of them is selected.
+    pub fn render(&self) -> (Text<'static>, usize) {
+        let mut lines = vec![
+            Line::from(self.dir.to_string_lossy().into_owned()).bold(),
+            Line::from(""),
+        ];
+        let mut selected_line = lines.len();
+        if let Some(error) = &self.error {
+            lines.push(Line::from(format!("Failed to read directory: {error}")));
+        }
+        for (i, entry) in self.entries.iter().enumerate() {
+            let mark = if self.marked.contains(&entry.path) {
+                '*'
+            } else {
+                ' '
+            };
+            let line = if entry.is_dir {
+                Line::from(format!("{mark} {}/", entry.name))
+            } else {
+                Line::from(format!("{mark} {} {}", entry.name, human_size(entry.size)))
+            };
+            if i == self.selected {
+                selected_line = lines.len();
+                lines.push(line.style(Style::new().reversed()));
+            } else {
+                lines.push(line);
+            }
+        }
+        let marked = self.marked.len();
+        lines.push(Line::from(""));
+        lines.push(
+            Line::from(format!(
+                "{marked} marked. <Space> marks, <Enter> attaches, . shows hidden files"
+            ))
+            .italic(),
+        );
+        (Text::from(lines), selected_line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_picker() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("photos")).unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "hello").unwrap();
+        std::fs::write(dir.path().join(".hidden"), "").unwrap();
+        std::fs::write(dir.path().join("photos/cat.jpg"), "meow").unwrap();
+
+        let mut picker = FilePicker::new(dir.path().to_owned(), false);
+        let names = |picker: &FilePicker| {
+            picker
+                .entries
+                .iter()
+                .map(|e| e.name.clone())
+                .collect::<Vec<_>>()
+        };
+        let listed = names(&picker);
+        picker.toggle_hidden();
+        let with_hidden = names(&picker);
+
+        // into photos and mark the cat
+        picker.selected = 1;
+        picker.open();
+        picker.select_next();
+        picker.toggle_mark();
+        let marked: Vec<_> = picker
+            .marked
+            .iter()
+            .map(|p| p.strip_prefix(dir.path()).unwrap().to_owned())
+            .collect();
+        picker.parent();
+        insta::assert_debug_snapshot!((
+            listed,
+            with_hidden,
+            marked,
+            names(&picker)[picker.selected].clone()
+        ));
+    }
+}
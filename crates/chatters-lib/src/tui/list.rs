@@ -1,7 +1,7 @@
 use ratatui::{
     layout::Rect,
     style::Style,
-    text::Span,
+    text::Line,
     text::Text,
     widgets::{StatefulWidget, Widget},
 };
@@ -41,6 +41,10 @@ impl ListState {
         self.offset
     }
 
+    pub fn set_offset(&mut self, offset: usize) {
+        self.offset = offset;
+    }
+
     pub fn align_top(&mut self) {
         if let Some(selected) = self.selected {
             self.offset = selected;
@@ -74,6 +78,23 @@ impl VerticalList {
     pub fn len(&self) -> usize {
         self.items.len()
     }
+
+    /// The index and text of the item on each row of an area of the height, as last rendered
+    /// with the state.
+    pub fn rows(&self, state: &ListState, height: u16) -> Vec<(usize, String)> {
+        self.items
+            .iter()
+            .enumerate()
+            .skip(state.offset)
+            .flat_map(|(i, item)| {
+                item.lines.iter().map(move |line| {
+                    let text = line.spans.iter().map(|s| s.content.as_ref()).collect();
+                    (i, text)
+                })
+            })
+            .take(height.into())
+            .collect()
+    }
 }
 
 impl StatefulWidget for &VerticalList {
@@ -143,13 +164,13 @@ impl StatefulWidget for &VerticalList {
 
 #[derive(Debug, Default)]
 pub struct HorizontalList {
-    items: Vec<Span<'static>>,
+    items: Vec<Line<'static>>,
 
     selected_item_style: Style,
 }
 
 impl HorizontalList {
-    pub fn new(items: Vec<Span<'static>>) -> Self {
+    pub fn new(items: Vec<Line<'static>>) -> Self {
         Self {
             items,
             selected_item_style: Style::new(),
@@ -186,7 +207,7 @@ impl StatefulWidget for &HorizontalList {
                     .items
                     .iter()
                     .skip(state.offset)
-                    .map(|t| t.content.len())
+                    .map(|t| t.width())
                     .take(selected.saturating_sub(state.offset).saturating_add(1))
                     .collect::<Vec<_>>();
                 let width = widths.iter().sum::<usize>() + widths.len().saturating_sub(1);
@@ -208,7 +229,9 @@ impl StatefulWidget for &HorizontalList {
                 // no space left to draw
                 break;
             }
-            let item_width = item.content.len() as u16 + 1;
+            let item_width = u16::try_from(item.width())
+                .unwrap_or(u16::MAX)
+                .saturating_add(1);
 
             let remaining_width = area.width.saturating_sub(used_width);
 
@@ -217,10 +240,11 @@ impl StatefulWidget for &HorizontalList {
                 text = text.style(self.selected_item_style);
             }
 
+            // leave the gap after it unstyled
             text.render(
                 Rect {
                     x: area.x + used_width,
-                    width: item_width.min(remaining_width),
+                    width: item_width.saturating_sub(1).min(remaining_width),
                     ..area
                 },
                 buf,
@@ -266,9 +290,29 @@ mod tests {
         assert_snapshot!(terminal.backend());
     }
 
+    #[test]
+    fn test_vertical_list_rows() {
+        let list = VerticalList::new(vec!["one".into(), "two\nlines".into(), "three".into()]);
+        let mut state = ListState::default();
+        state.select_last();
+        let mut terminal = Terminal::new(TestBackend::new(20, 3)).unwrap();
+        terminal
+            .draw(|frame| frame.render_stateful_widget(&list, frame.area(), &mut state))
+            .unwrap();
+        // scrolled down to show the last item
+        assert_eq!(
+            list.rows(&state, 3),
+            [
+                (1, "two".to_owned()),
+                (1, "lines".to_owned()),
+                (2, "three".to_owned())
+            ]
+        );
+    }
+
     #[test]
     fn test_horizontal_list() {
-        let list = HorizontalList::new((0..20).map(|i| Span::from(i.to_string())).collect());
+        let list = HorizontalList::new((0..20).map(|i| Line::from(i.to_string())).collect());
         let mut state = ListState::default();
         let mut terminal = Terminal::new(TestBackend::new(20, 2)).unwrap();
         terminal
@@ -294,4 +338,20 @@ mod tests {
             .unwrap();
         assert_snapshot!(terminal.backend());
     }
+
+    #[test]
+    fn test_horizontal_list_wide() {
+        // two columns each, but more bytes
+        let list = HorizontalList::new(vec!["日本".into(), "🎉".into(), "ab".into()]);
+        let mut state = ListState::default();
+        state.select_last();
+        let mut terminal = Terminal::new(TestBackend::new(10, 1)).unwrap();
+        terminal
+            .draw(|frame| frame.render_stateful_widget(&list, frame.area(), &mut state))
+            .unwrap();
+        assert_eq!(state.offset(), 0);
+        let buffer = terminal.backend().buffer();
+        let symbols = [0, 2, 5, 8, 9].map(|x| buffer[(x, 0)].symbol().to_owned());
+        assert_eq!(symbols, ["日", "本", "🎉", "a", "b"]);
+    }
 }
@@ -0,0 +1,95 @@
+use ratatui::{
+    layout::Alignment,
+    style::Stylize as _,
+    text::{Line, Text},
+    widgets::{Block, Paragraph},
+    Frame,
+};
+
+use crate::backends::LoadStatus;
+
+/// Shown while the backend loads on a start without a snapshot to show instead, such as while it
+/// catches up on the messages that arrived since it last ran.
+#[derive(Debug)]
+pub struct LoadingScreen {
+    app_name: String,
+    backend_name: String,
+    pub status: LoadStatus,
+}
+
+impl LoadingScreen {
+    pub fn new(app_name: &str, backend_name: &str) -> Self {
+        Self {
+            app_name: app_name.to_owned(),
+            backend_name: backend_name.to_owned(),
+            status: LoadStatus::default(),
+        }
+    }
+
+    pub fn render(&self, frame: &mut Frame<'_>) {
+        let block = Block::bordered().title(format!(" Starting {} ", self.app_name));
+        let area = block.inner(frame.area());
+        let text = self.text();
+        // in the middle of the screen
+        let top = area.height.saturating_sub(text.height() as u16) / 2;
+        let mut lines = vec![Line::from(""); top as usize];
+        lines.extend(text.lines);
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(block)
+                .alignment(Alignment::Center),
+            frame.area(),
+        );
+    }
+
+    fn text(&self) -> Text<'static> {
+        let mut lines = vec![Line::from(format!("Loading {}…", self.backend_name))];
+        if self.status.messages > 0 {
+            lines.push(Line::from(format!(
+                "Received {} messages sent while away",
+                self.status.messages
+            )));
+        }
+        if self.status.contacts_synced {
+            lines.push(Line::from("Synced contacts"));
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::from("<Esc> quits").italic());
+        Text::from(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loading_screen() {
+        let text = |screen: &LoadingScreen| {
+            screen
+                .text()
+                .lines
+                .iter()
+                .map(|l| l.to_string())
+                .collect::<Vec<_>>()
+        };
+        let mut screen = LoadingScreen::new("chatters", "Signal");
+        assert_eq!(text(&screen), ["Loading Signal…", "", "<Esc> quits"]);
+
+        screen.status = LoadStatus {
+            messages: 120,
+            contacts_synced: true,
+            catching_up: false,
+        };
+        assert_eq!(
+            text(&screen),
+            [
+                "Loading Signal…",
+                "Received 120 messages sent while away",
+                "Synced contacts",
+                "",
+                "<Esc> quits"
+            ]
+        );
+    }
+}
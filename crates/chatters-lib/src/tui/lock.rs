@@ -0,0 +1,70 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    layout::{Alignment, Constraint, Flex, Layout},
+    style::Stylize as _,
+    text::{Line, Text},
+    widgets::{Block, Clear, Paragraph},
+    Frame,
+};
+
+/// Hides everything behind a prompt for the passphrase.
+#[derive(Debug)]
+pub struct LockScreen {
+    pub prompt: String,
+    input: String,
+    /// Why the last passphrase wasn't accepted.
+    pub error: Option<String>,
+}
+
+/// What to do after a key press on the lock screen.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LockAction {
+    Submit(String),
+    Quit,
+}
+
+impl LockScreen {
+    pub fn new(prompt: impl Into<String>) -> Self {
+        Self {
+            prompt: prompt.into(),
+            input: String::new(),
+            error: None,
+        }
+    }
+
+    pub fn key(&mut self, key: KeyEvent) -> Option<LockAction> {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') if control => return Some(LockAction::Quit),
+            KeyCode::Char('u') if control => self.input.clear(),
+            KeyCode::Enter => return Some(LockAction::Submit(std::mem::take(&mut self.input))),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(c) if !control => self.input.push(c),
+            _ => {}
+        }
+        None
+    }
+
+    pub fn render(&self, frame: &mut Frame<'_>) {
+        let area = frame.area();
+        frame.render_widget(Clear, area);
+        let mut lines = vec![
+            Line::from(self.prompt.clone()),
+            Line::from("*".repeat(self.input.chars().count())),
+        ];
+        if let Some(error) = &self.error {
+            lines.push(Line::from(error.clone()).red());
+        }
+        let [area] = Layout::vertical([Constraint::Length(lines.len() as u16 + 2)])
+            .flex(Flex::Center)
+            .areas(area);
+        frame.render_widget(
+            Paragraph::new(Text::from(lines))
+                .block(Block::bordered().title(" Locked "))
+                .alignment(Alignment::Center),
+            area,
+        );
+    }
+}
@@ -1,20 +1,63 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::RangeInclusive,
+};
 
 use crate::tui::list::ListState;
 use ratatui::{
-    style::{Style, Stylize as _},
-    text::Span,
+    style::{Modifier, Style, Stylize as _},
+    text::{Line, Span},
 };
+use unicode_width::{UnicodeWidthChar as _, UnicodeWidthStr as _};
+
+use crate::backends::{ContactId, FormatKind, Formatting, Mention, MessageAttachment};
+use crate::transfers::Transfers;
+
+use super::{theme::Palette, wrap_text};
 
-use crate::backends::{ContactId, MessageAttachment};
+/// The char some backends, such as Signal, leave in the text where each mention goes, for the
+/// name of who is mentioned to be shown in its place.
+pub const MENTION_PLACEHOLDER: char = '\u{fffc}';
 
-use super::wrap_text;
+/// The names to show for those mentioned, by their ids, left out for those not known.
+pub type MentionNames = BTreeMap<Vec<u8>, String>;
+
+/// The text with `@` and the name of who is mentioned in place of each mention's placeholder,
+/// leaving those without a name as they are.
+pub fn name_mentions(text: &str, mentions: &[Mention], names: &MentionNames) -> String {
+    let mut named = String::new();
+    for (i, c) in text.chars().enumerate() {
+        let name = mentions
+            .iter()
+            .find(|m| m.start == i && c == MENTION_PLACEHOLDER)
+            .and_then(|m| names.get(&m.id));
+        match name {
+            Some(name) => {
+                named.push('@');
+                named.push_str(name);
+            }
+            None => named.push(c),
+        }
+    }
+    named
+}
 
 #[derive(Debug, Clone)]
 pub struct Quote {
     pub timestamp: u64,
     pub sender: Vec<u8>,
     pub text: String,
+    pub mentions: Vec<Mention>,
+    pub formatting: Vec<Formatting>,
+}
+
+impl Quote {
+    /// The start of the quoted text on a single line, trimmed to fit the width.
+    pub fn preview(&self, width: usize, names: &MentionNames) -> String {
+        let text = name_mentions(&self.text, &self.mentions, names);
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        super::truncate_end(&text, width)
+    }
 }
 
 #[derive(Debug)]
@@ -33,40 +76,128 @@ pub struct Message {
     pub attachments: Vec<MessageAttachment>,
     pub quote: Option<Quote>,
     pub edits: Vec<MessageEdit>,
+    /// Styles of the original text.
+    pub formatting: Vec<Formatting>,
+    /// Those mentioned in the original text.
+    pub mentions: Vec<Mention>,
+    pub spoilers_revealed: bool,
 }
 
 #[derive(Debug)]
 pub struct MessageEdit {
     pub timestamp: u64,
     pub text: String,
+    pub mentions: Vec<Mention>,
+    pub formatting: Vec<Formatting>,
 }
 
 impl Message {
-    pub fn render(&self, width: usize) -> Vec<Span<'static>> {
+    /// The text of the latest edit, or the original text if it hasn't been edited.
+    pub fn text(&self) -> &str {
+        self.edits.last().map_or(&self.content, |e| &e.text)
+    }
+
+    /// Those mentioned in the text of the latest edit, or the original text if it hasn't been
+    /// edited.
+    pub fn current_mentions(&self) -> &[Mention] {
+        self.edits.last().map_or(&self.mentions, |e| &e.mentions)
+    }
+
+    /// Styles of the text of the latest edit, or the original text if it hasn't been edited.
+    pub fn current_formatting(&self) -> &[Formatting] {
+        self.edits
+            .last()
+            .map_or(&self.formatting, |e| &e.formatting)
+    }
+
+    /// Render the message with the text to show for it, such as from the render filters or
+    /// without a bridge's prefix, the name of who sent the message it quotes and the names of
+    /// those mentioned.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &self,
+        width: usize,
+        palette: &Palette,
+        text: &str,
+        filtered: bool,
+        transfers: &Transfers,
+        quote_sender: &str,
+        names: &MentionNames,
+    ) -> Vec<Line<'static>> {
         let mut lines = Vec::new();
         if let Some(quote) = &self.quote {
-            if let Some(line) = quote.text.lines().next() {
-                lines.push(Span::from(format!("> {line}")).style(Style::new().italic()));
-            }
+            let style = Style::new().italic().fg(palette.quote);
+            let prefix = format!("> {quote_sender}: ");
+            let preview = quote.preview(width.saturating_sub(prefix.width()), names);
+            lines.push(Line::from(vec![
+                Span::from(prefix).style(style.bold()),
+                Span::from(preview).style(style),
+            ]));
         }
         if !self.attachments.is_empty() {
             for attachment in &self.attachments {
-                lines.push(Span::from(attachment.message_line()));
+                let line =
+                    match transfers.download(&self.contact_id, self.timestamp, attachment.index) {
+                        Some(progress) => format!(
+                            "+ {} {} {}",
+                            attachment.name,
+                            attachment.human_size(),
+                            progress.bar(10)
+                        ),
+                        None => attachment.message_line(),
+                    };
+                lines.push(Line::from(line));
             }
         }
-        if let Some(edit) = self.edits.last() {
-            let content = wrap_text(edit.text.trim(), width);
+        if !text.is_empty() {
+            let edited = if self.edits.is_empty() { ' ' } else { 'e' };
+            let marker = format!("{edited}{}", if filtered { 'f' } else { ' ' });
+            let trimmed = text.trim();
+            let styles = self.char_styles(trimmed, !filtered);
+            let mentioned = self.mentioned_chars(trimmed, !filtered);
+            // the names of those mentioned go in place of their placeholders before wrapping
+            let mut shown = String::new();
+            let mut shown_styles = Vec::new();
+            for (c, (style, mention)) in trimmed.chars().zip(styles.into_iter().zip(mentioned)) {
+                let name = mention
+                    .filter(|_| c == MENTION_PLACEHOLDER)
+                    .and_then(|id| names.get(id));
+                match name {
+                    Some(name) => {
+                        for n in format!("@{name}").chars() {
+                            shown.push(n);
+                            shown_styles.push((style, true));
+                        }
+                    }
+                    None => {
+                        shown.push(c);
+                        shown_styles.push((style, mention.is_some()));
+                    }
+                }
+            }
+            let content = wrap_text(&shown, width);
+            let mut chars = shown.chars().zip(shown_styles).peekable();
             for (i, line) in content.lines.iter().enumerate() {
-                if i == 0 {
-                    lines.push(Span::from(format!("e {line}")));
+                let prefix = if i == 0 {
+                    marker.clone()
                 } else {
-                    lines.push(Span::from(format!("  {line}")));
+                    "  ".to_owned()
+                };
+                let mut spans = vec![Span::from(prefix)];
+                for c in line.to_string().chars() {
+                    // wrapping drops the whitespace it breaks at
+                    while chars
+                        .next_if(|(o, _)| *o != c && o.is_whitespace())
+                        .is_some()
+                    {}
+                    let (style, mentioned) = chars.next().map_or((None, false), |(_, s)| s);
+                    let mut span = styled_char(c, style, self.spoilers_revealed);
+                    if mentioned && style != Some(FormatKind::Spoiler) {
+                        span = span.fg(palette.mention).bold();
+                    }
+                    spans.push(span);
                 }
-            }
-        } else if !self.content.is_empty() {
-            let content = wrap_text(self.content.trim(), width);
-            for line in content.lines {
-                lines.push(Span::from(format!("  {line}")));
+                lines.push(merge_spans(spans));
             }
         }
         if !self.reactions.is_empty() {
@@ -86,17 +217,203 @@ impl Message {
                     }
                 })
                 .collect::<Vec<_>>();
-            lines.push(Span::from(format!("r {}", react_line.join(" "))));
+            lines.push(Line::from(format!("r {}", react_line.join(" "))));
+        }
+        lines
+    }
+
+    /// The formatting of each char of the text to show, which only applies while it is the
+    /// latest text, or the end of it for those relayed by bridges.
+    fn char_styles(&self, text: &str, formatted: bool) -> Vec<Option<FormatKind>> {
+        let len = text.chars().count();
+        let mut styles = vec![None; len];
+        let current = self.text().trim_end();
+        if !formatted || !current.ends_with(text) {
+            return styles;
+        }
+        let offset = current.chars().count() - len;
+        for f in self.current_formatting() {
+            let start = f.start.saturating_sub(offset);
+            let end = (f.start + f.length).saturating_sub(offset).min(len);
+            for style in styles.iter_mut().take(end).skip(start) {
+                // spoilers hide any other formatting
+                if *style != Some(FormatKind::Spoiler) {
+                    *style = Some(f.kind);
+                }
+            }
+        }
+        styles
+    }
+
+    /// Who each char of the text to show mentions, if part of a mention, which follows the
+    /// latest edit unlike the formatting.
+    fn mentioned_chars(&self, text: &str, formatted: bool) -> Vec<Option<&[u8]>> {
+        let len = text.chars().count();
+        let mut mentioned = vec![None; len];
+        let current = self.text().trim_end();
+        if !formatted || !current.ends_with(text) {
+            return mentioned;
+        }
+        let offset = current.chars().count() - len;
+        for m in self.current_mentions() {
+            let start = m.start.saturating_sub(offset);
+            let end = (m.start + m.length).saturating_sub(offset).min(len);
+            for c in mentioned.iter_mut().take(end).skip(start) {
+                *c = Some(m.id.as_slice());
+            }
         }
+        mentioned
+    }
+}
+
+fn styled_char(c: char, kind: Option<FormatKind>, reveal_spoilers: bool) -> Span<'static> {
+    let style = match kind {
+        None => Style::new(),
+        Some(FormatKind::Bold) => Style::new().bold(),
+        Some(FormatKind::Italic) => Style::new().italic(),
+        Some(FormatKind::Strikethrough) => Style::new().crossed_out(),
+        Some(FormatKind::Monospace) => Style::new().add_modifier(Modifier::DIM),
+        Some(FormatKind::Spoiler) if reveal_spoilers => Style::new().underlined(),
+        Some(FormatKind::Spoiler) => {
+            let hidden = if c.is_whitespace() {
+                c.to_string()
+            } else {
+                "▒".repeat(c.width().unwrap_or(1))
+            };
+            return Span::from(hidden);
+        }
+    };
+    Span::styled(c.to_string(), style)
+}
+
+/// Join neighbouring spans with the same style.
+fn merge_spans(spans: Vec<Span<'static>>) -> Line<'static> {
+    let mut merged: Vec<Span<'static>> = Vec::new();
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if last.style == span.style => {
+                last.content.to_mut().push_str(&span.content);
+            }
+            _ => merged.push(span),
+        }
+    }
+    Line::from(merged)
+}
+
+/// Messages rendered before, kept between frames as wrapping and styling every message of a long
+/// conversation each time is slow.
+///
+/// Entries are for a message's timestamp, width and edit count, and are checked against the rest
+/// of what they were rendered from. [`Messages`] drops them when changing messages in place, such
+/// as for reactions and downloads.
+#[derive(Debug, Default)]
+pub struct RenderCache {
+    entries: HashMap<u64, RenderedMessage>,
+}
+
+#[derive(Debug)]
+struct RenderedMessage {
+    width: usize,
+    edits: usize,
+    text: String,
+    filtered: bool,
+    quote_sender: String,
+    names: MentionNames,
+    palette: Palette,
+    lines: Vec<Line<'static>>,
+}
+
+impl RenderCache {
+    /// Render the message as [`Message::render`] does, reusing the lines from last time if
+    /// nothing they came from has changed.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        message: &Message,
+        width: usize,
+        palette: &Palette,
+        text: &str,
+        filtered: bool,
+        transfers: &Transfers,
+        quote_sender: &str,
+        names: &MentionNames,
+    ) -> Vec<Line<'static>> {
+        // progress bars move on as downloads go, so aren't kept
+        let downloading = message.attachments.iter().any(|a| {
+            transfers
+                .download(&message.contact_id, message.timestamp, a.index)
+                .is_some()
+        });
+        if downloading {
+            self.entries.remove(&message.timestamp);
+            return message.render(
+                width,
+                palette,
+                text,
+                filtered,
+                transfers,
+                quote_sender,
+                names,
+            );
+        }
+        if let Some(entry) = self.entries.get(&message.timestamp) {
+            if entry.width == width
+                && entry.edits == message.edits.len()
+                && entry.filtered == filtered
+                && entry.palette == *palette
+                && entry.text == text
+                && entry.quote_sender == quote_sender
+                && entry.names == *names
+            {
+                return entry.lines.clone();
+            }
+        }
+        let lines = message.render(
+            width,
+            palette,
+            text,
+            filtered,
+            transfers,
+            quote_sender,
+            names,
+        );
+        self.entries.insert(
+            message.timestamp,
+            RenderedMessage {
+                width,
+                edits: message.edits.len(),
+                text: text.to_owned(),
+                filtered,
+                quote_sender: quote_sender.to_owned(),
+                names: names.clone(),
+                palette: *palette,
+                lines: lines.clone(),
+            },
+        );
         lines
     }
+
+    pub fn invalidate(&mut self, timestamp: u64) {
+        self.entries.remove(&timestamp);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
 }
+
 #[derive(Debug, Default)]
 pub struct Messages {
     pub messages_by_ts: BTreeMap<u64, Message>,
     pub messages_by_index: Vec<u64>,
     pub timestamp_to_index: BTreeMap<u64, usize>,
     pub state: ListState,
+    /// Messages that arrived below the selection while scrolled up.
+    new_below: usize,
+    /// The timestamp of the message a visual selection started from, covering the messages
+    /// between it and the selected one.
+    visual_start: Option<u64>,
+    pub render_cache: RenderCache,
 }
 
 impl Messages {
@@ -105,9 +422,44 @@ impl Messages {
     }
 
     pub fn add_multiple(&mut self, messages: impl IntoIterator<Item = crate::backends::Message>) {
+        // keep the view on the same messages, unless following the latest ones
+        let selected_ts = self.selected().map(|m| m.timestamp);
+        let offset_ts = self.messages_by_index.get(self.state.offset()).copied();
+        let at_bottom = self.is_at_bottom();
+
         for message in messages {
-            match message.content {
-                crate::backends::MessageContent::Text { text, attachments } => {
+            let content = match message.content {
+                // shown in italics so that it isn't mistaken for what someone wrote
+                content @ (crate::backends::MessageContent::Unsupported { .. }
+                | crate::backends::MessageContent::Call { .. }) => {
+                    let description = content.to_string();
+                    crate::backends::MessageContent::Text {
+                        formatting: vec![Formatting {
+                            start: 0,
+                            length: description.chars().count(),
+                            kind: FormatKind::Italic,
+                        }],
+                        text: description,
+                        attachments: Vec::new(),
+                        mentions: Vec::new(),
+                    }
+                }
+                content => content,
+            };
+            match content {
+                crate::backends::MessageContent::Text {
+                    text,
+                    attachments,
+                    formatting,
+                    mentions,
+                } => {
+                    if !at_bottom
+                        && selected_ts.is_some_and(|ts| message.timestamp > ts)
+                        && !self.messages_by_ts.contains_key(&message.timestamp)
+                    {
+                        self.new_below += 1;
+                    }
+                    self.render_cache.invalidate(message.timestamp);
                     // assume a new message
                     self.messages_by_ts.insert(
                         message.timestamp,
@@ -122,8 +474,13 @@ impl Messages {
                                 timestamp: q.timestamp,
                                 sender: q.sender,
                                 text: q.text,
+                                mentions: q.mentions,
+                                formatting: q.formatting,
                             }),
                             edits: Vec::new(),
+                            formatting,
+                            mentions,
+                            spoilers_revealed: false,
                         },
                     );
                 }
@@ -133,6 +490,7 @@ impl Messages {
                     reaction,
                     remove,
                 } => {
+                    self.render_cache.invalidate(timestamp);
                     if let Some(m) = self.messages_by_ts.get_mut(&timestamp) {
                         assert_eq!(m.sender, message_author);
                         let existing_reaction =
@@ -156,13 +514,21 @@ impl Messages {
                 crate::backends::MessageContent::Edit {
                     timestamp: edit_timestamp,
                     text,
+                    mentions,
+                    formatting,
                 } => {
+                    self.render_cache.invalidate(message.timestamp);
                     let existing = self.messages_by_ts.get_mut(&message.timestamp).unwrap();
                     existing.edits.push(MessageEdit {
                         timestamp: edit_timestamp,
                         text,
+                        mentions,
+                        formatting,
                     });
                 }
+                // turned into text above
+                crate::backends::MessageContent::Unsupported { .. }
+                | crate::backends::MessageContent::Call { .. } => unreachable!(),
             }
         }
         self.messages_by_index = self.messages_by_ts.keys().copied().collect();
@@ -172,6 +538,61 @@ impl Messages {
             .enumerate()
             .map(|(i, ts)| (*ts, i))
             .collect();
+
+        if let Some(offset_ts) = offset_ts {
+            if let Some(offset) = self.timestamp_to_index.get(&offset_ts) {
+                self.state.set_offset(*offset);
+            }
+        }
+        if at_bottom && selected_ts.is_some() {
+            self.state
+                .select(self.messages_by_index.len().checked_sub(1));
+        } else if let Some(selected_ts) = selected_ts {
+            self.select_message(selected_ts);
+        }
+    }
+
+    /// Remove a message that was deleted, selecting the one before it if it was selected.
+    pub fn remove(&mut self, timestamp: u64) {
+        let Some(&index) = self.timestamp_to_index.get(&timestamp) else {
+            return;
+        };
+        if self.state.selected() == Some(index) {
+            self.state.select(Some(index.saturating_sub(1)));
+        }
+        if self.visual_start == Some(timestamp) {
+            self.visual_start = None;
+        }
+        self.messages_by_ts.remove(&timestamp);
+        self.render_cache.invalidate(timestamp);
+        // re-index, keeping the view on the same messages
+        self.add_multiple(std::iter::empty());
+        if self.messages_by_ts.is_empty() {
+            self.state.select(None);
+        }
+    }
+
+    /// Whether the last message is selected.
+    pub fn is_at_bottom(&self) -> bool {
+        self.state
+            .selected()
+            .is_some_and(|i| i >= self.messages_by_index.len().saturating_sub(1))
+    }
+
+    /// Select the latest message.
+    pub fn goto_bottom(&mut self) {
+        self.state
+            .select(self.messages_by_index.len().checked_sub(1));
+        self.new_below = 0;
+    }
+
+    /// The number of messages that arrived below the selection since scrolling to the bottom.
+    pub fn new_below(&self) -> usize {
+        self.new_below
+    }
+
+    pub fn clear_new_below(&mut self) {
+        self.new_below = 0;
     }
 
     pub fn get_by_index(&self, index: usize) -> Option<&Message> {
@@ -184,13 +605,18 @@ impl Messages {
         self.messages_by_ts.get(&timestamp)
     }
 
+    /// The message to change in place, which is rendered afresh afterwards.
     pub fn get_mut_by_timestamp(&mut self, timestamp: u64) -> Option<&mut Message> {
+        self.render_cache.invalidate(timestamp);
         self.messages_by_ts.get_mut(&timestamp)
     }
 
     pub fn clear(&mut self) {
         self.messages_by_ts.clear();
         self.messages_by_index.clear();
+        self.render_cache.clear();
+        self.new_below = 0;
+        self.visual_start = None;
     }
 
     pub fn is_empty(&self) -> bool {
@@ -205,6 +631,65 @@ impl Messages {
         self.state.selected().and_then(|i| self.get_by_index(i))
     }
 
+    /// Start a visual selection from the selected message, or stop the one in progress.
+    pub fn toggle_visual(&mut self) {
+        self.visual_start = match self.visual_start {
+            Some(_) => None,
+            None => self.selected().map(|m| m.timestamp),
+        };
+    }
+
+    pub fn stop_visual(&mut self) {
+        self.visual_start = None;
+    }
+
+    pub fn is_visual(&self) -> bool {
+        self.visual_start.is_some()
+    }
+
+    /// The indices covered by the visual selection, if there is one.
+    pub fn visual_range(&self) -> Option<RangeInclusive<usize>> {
+        let start = *self.timestamp_to_index.get(&self.visual_start?)?;
+        let end = self.state.selected()?;
+        Some(start.min(end)..=start.max(end))
+    }
+
+    /// The messages in the visual selection, oldest first, otherwise just the selected one.
+    pub fn selected_range(&self) -> Vec<&Message> {
+        match self.visual_range() {
+            Some(range) => range.filter_map(|i| self.get_by_index(i)).collect(),
+            None => self.selected().into_iter().collect(),
+        }
+    }
+
+    /// The timestamp of the closest matching message, going from the selected one towards older
+    /// messages, or newer ones when not `older`, and wrapping around at the ends. The selected
+    /// message itself is checked last, or first with `include_selected`.
+    pub fn find(
+        &self,
+        matches: impl Fn(&Message) -> bool,
+        older: bool,
+        include_selected: bool,
+    ) -> Option<u64> {
+        let len = self.messages_by_index.len();
+        if len == 0 {
+            return None;
+        }
+        let selected = self.state.selected().map_or(len - 1, |i| i.min(len - 1));
+        let steps = if include_selected { 0..len } else { 1..len + 1 };
+        steps
+            .map(|step| {
+                if older {
+                    (selected + len - step % len) % len
+                } else {
+                    (selected + step) % len
+                }
+            })
+            .filter_map(|i| self.get_by_index(i))
+            .find(|m| matches(m))
+            .map(|m| m.timestamp)
+    }
+
     pub fn select_message(&mut self, timestamp: u64) {
         let Some(index) = self.timestamp_to_index.get(&timestamp) else {
             return;
@@ -226,3 +711,363 @@ impl Extend<crate::backends::Message> for Messages {
         self.add_multiple(iter);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::MessageContent;
+
+    fn message(timestamp: u64) -> crate::backends::Message {
+        crate::backends::Message {
+            timestamp,
+            sender: vec![0],
+            contact_id: ContactId::User(vec![0]),
+            content: MessageContent::Text {
+                text: format!("message {timestamp}"),
+                attachments: Vec::new(),
+                mentions: Vec::new(),
+                formatting: Vec::new(),
+            },
+            quote: None,
+        }
+    }
+
+    #[test]
+    fn test_new_messages_keep_selection() {
+        let mut messages = (1..=5).map(message).collect::<Messages>();
+
+        // scrolled up, new messages don't move the selection
+        messages.select_message(2);
+        messages.add_single(message(6));
+        messages.add_single(message(7));
+        insta::assert_debug_snapshot!((
+            messages.selected().map(|m| m.timestamp),
+            messages.new_below()
+        ));
+
+        // at the bottom, new messages are followed
+        messages.goto_bottom();
+        messages.add_single(message(8));
+        insta::assert_debug_snapshot!((
+            messages.selected().map(|m| m.timestamp),
+            messages.new_below()
+        ));
+    }
+
+    #[test]
+    fn test_visual_selection() {
+        let mut messages = (1..=5).map(message).collect::<Messages>();
+        let timestamps = |messages: &Messages| {
+            messages
+                .selected_range()
+                .iter()
+                .map(|m| m.timestamp)
+                .collect::<Vec<_>>()
+        };
+
+        messages.select_message(4);
+        messages.toggle_visual();
+        messages.select_message(2);
+        let upwards = timestamps(&messages);
+        // earlier messages arriving don't change what is selected
+        messages.add_single(message(0));
+        let after_load = timestamps(&messages);
+        messages.toggle_visual();
+        insta::assert_debug_snapshot!((upwards, after_load, timestamps(&messages)));
+    }
+
+    #[test]
+    fn test_render_formatting() {
+        let mut messages = Messages::default();
+        messages.add_single(crate::backends::Message {
+            content: MessageContent::Text {
+                text: "some bold text and a spoiler".to_owned(),
+                attachments: Vec::new(),
+                mentions: Vec::new(),
+                formatting: vec![
+                    Formatting {
+                        start: 5,
+                        length: 9,
+                        kind: FormatKind::Bold,
+                    },
+                    Formatting {
+                        start: 21,
+                        length: 7,
+                        kind: FormatKind::Spoiler,
+                    },
+                ],
+            },
+            ..message(1)
+        });
+        let message = messages.get_by_timestamp(1).unwrap();
+        let palette = Palette::for_background(super::super::theme::Background::Dark);
+        let render = |m: &Message| {
+            m.render(
+                12,
+                &palette,
+                m.text(),
+                false,
+                &Transfers::default(),
+                "",
+                &MentionNames::new(),
+            )
+            .iter()
+            .map(|line| {
+                line.spans
+                    .iter()
+                    .map(|s| format!("[{}]{:?}", s.content, s.style.add_modifier))
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+        };
+        insta::assert_debug_snapshot!(render(message));
+    }
+
+    #[test]
+    fn test_render_quote() {
+        let mut messages = Messages::default();
+        messages.add_single(crate::backends::Message {
+            quote: Some(crate::backends::Quote {
+                timestamp: 1,
+                sender: vec![1],
+                text: "a long quoted message\nover two lines".to_owned(),
+                mentions: Vec::new(),
+                formatting: Vec::new(),
+            }),
+            ..message(2)
+        });
+        let message = messages.get_by_timestamp(2).unwrap();
+        let palette = Palette::for_background(super::super::theme::Background::Dark);
+        let lines = message.render(
+            30,
+            &palette,
+            message.text(),
+            false,
+            &Transfers::default(),
+            "Alice",
+            &MentionNames::new(),
+        );
+        insta::assert_debug_snapshot!(lines
+            .iter()
+            .map(|line| line
+                .spans
+                .iter()
+                .map(|s| s.content.as_ref())
+                .collect::<String>())
+            .collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_render_cache_invalidated_by_reactions() {
+        let mut messages = (1..=2).map(message).collect::<Messages>();
+        let palette = Palette::for_background(super::super::theme::Background::Dark);
+        let transfers = Transfers::default();
+        let render = |messages: &mut Messages| {
+            let message = messages.get_by_timestamp(2).unwrap();
+            let text = message.text().to_owned();
+            let mut cache = std::mem::take(&mut messages.render_cache);
+            let lines = cache.render(
+                messages.get_by_timestamp(2).unwrap(),
+                30,
+                &palette,
+                &text,
+                false,
+                &transfers,
+                "",
+                &MentionNames::new(),
+            );
+            messages.render_cache = cache;
+            lines.len()
+        };
+
+        let before = render(&mut messages);
+        assert!(messages.render_cache.entries.contains_key(&2));
+        messages.add_single(crate::backends::Message {
+            timestamp: 3,
+            sender: vec![0],
+            contact_id: ContactId::User(vec![0]),
+            content: MessageContent::Reaction {
+                message_author: vec![0],
+                timestamp: 2,
+                reaction: "👍".to_owned(),
+                remove: false,
+            },
+            quote: None,
+        });
+        assert!(!messages.render_cache.entries.contains_key(&2));
+        // the reaction is shown below the text
+        assert_eq!(render(&mut messages), before + 1);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let mut messages = Messages::default();
+        messages.add_single(crate::backends::Message {
+            content: MessageContent::Unsupported {
+                description: "📞 Missed call".to_owned(),
+            },
+            ..message(1)
+        });
+        let message = messages.get_by_timestamp(1).unwrap();
+        assert_eq!(message.text(), "📞 Missed call");
+        let palette = Palette::for_background(super::super::theme::Background::Dark);
+        let lines = message.render(
+            30,
+            &palette,
+            message.text(),
+            false,
+            &Transfers::default(),
+            "",
+            &MentionNames::new(),
+        );
+        // told apart from what someone wrote
+        assert!(lines[0].spans[1..]
+            .iter()
+            .all(|s| s.style.add_modifier.contains(Modifier::ITALIC)));
+    }
+
+    #[test]
+    fn test_render_mentions() {
+        let mut messages = Messages::default();
+        let mention = |start, length| Mention {
+            start,
+            length,
+            id: vec![1],
+        };
+        messages.add_single(crate::backends::Message {
+            content: MessageContent::Text {
+                text: "hi @Alice".to_owned(),
+                attachments: Vec::new(),
+                mentions: vec![mention(3, 6)],
+                formatting: Vec::new(),
+            },
+            ..message(1)
+        });
+        let palette = Palette::for_background(super::super::theme::Background::Dark);
+        let styled = |messages: &Messages, style: Style| {
+            let message = messages.get_by_timestamp(1).unwrap();
+            let lines = message.render(
+                30,
+                &palette,
+                message.text(),
+                false,
+                &Transfers::default(),
+                "",
+                &MentionNames::new(),
+            );
+            lines[0]
+                .spans
+                .iter()
+                .filter(|s| s.style == style)
+                .map(|s| s.content.to_string())
+                .collect::<Vec<_>>()
+        };
+        let mention_style = Style::new().fg(palette.mention).bold();
+        assert_eq!(styled(&messages, mention_style), ["@Alice"]);
+
+        // the mentions and formatting of an edit replace those of the original text
+        messages.add_single(crate::backends::Message {
+            content: MessageContent::Edit {
+                timestamp: 2,
+                text: "hello there @Bob".to_owned(),
+                mentions: vec![mention(12, 4)],
+                formatting: vec![Formatting {
+                    start: 0,
+                    length: 5,
+                    kind: FormatKind::Bold,
+                }],
+            },
+            ..message(1)
+        });
+        assert_eq!(styled(&messages, mention_style), ["@Bob"]);
+        assert_eq!(styled(&messages, Style::new().bold()), ["hello"]);
+    }
+
+    #[test]
+    fn test_render_mention_placeholders() {
+        let mut messages = Messages::default();
+        let mention = |start, id| Mention {
+            start,
+            length: 1,
+            id: vec![id],
+        };
+        let quote_text = format!("for {MENTION_PLACEHOLDER}");
+        messages.add_single(crate::backends::Message {
+            content: MessageContent::Text {
+                text: format!("hi {MENTION_PLACEHOLDER}, {MENTION_PLACEHOLDER} and you"),
+                attachments: Vec::new(),
+                mentions: vec![mention(3, 1), mention(6, 2)],
+                formatting: vec![Formatting {
+                    start: 12,
+                    length: 3,
+                    kind: FormatKind::Bold,
+                }],
+            },
+            quote: Some(crate::backends::Quote {
+                timestamp: 0,
+                sender: vec![1],
+                text: quote_text,
+                mentions: vec![mention(4, 1)],
+                formatting: Vec::new(),
+            }),
+            ..message(1)
+        });
+        let message = messages.get_by_timestamp(1).unwrap();
+        let palette = Palette::for_background(super::super::theme::Background::Dark);
+        let names = MentionNames::from([(vec![1], "Alice".to_owned())]);
+        let lines = message.render(
+            40,
+            &palette,
+            message.text(),
+            false,
+            &Transfers::default(),
+            "Alice",
+            &names,
+        );
+        let text = |line: &Line<'_>| {
+            line.spans
+                .iter()
+                .map(|s| s.content.as_ref())
+                .collect::<String>()
+        };
+        assert_eq!(text(&lines[0]), "> Alice: for @Alice");
+        // those not known keep their placeholder
+        assert_eq!(
+            text(&lines[1]),
+            format!("  hi @Alice, {MENTION_PLACEHOLDER} and you")
+        );
+        let styled = |line: &Line<'_>, style: Style| {
+            line.spans
+                .iter()
+                .filter(|s| s.style == style)
+                .map(|s| s.content.to_string())
+                .collect::<Vec<_>>()
+        };
+        let mention_style = Style::new().fg(palette.mention).bold();
+        assert_eq!(
+            styled(&lines[1], mention_style),
+            ["@Alice".to_owned(), MENTION_PLACEHOLDER.to_string()]
+        );
+        // the formatting stays on the same text
+        assert_eq!(styled(&lines[1], Style::new().bold()), ["you"]);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut messages = (1..=3).map(message).collect::<Messages>();
+        messages.select_message(3);
+        messages.remove(3);
+        assert_eq!(messages.len(), 2);
+        assert!(messages.get_by_timestamp(3).is_none());
+        assert_eq!(messages.selected().map(|m| m.timestamp), Some(2));
+
+        // unknown messages are left alone
+        messages.remove(10);
+        assert_eq!(messages.len(), 2);
+
+        messages.remove(1);
+        messages.remove(2);
+        assert!(messages.is_empty());
+        assert!(messages.selected().is_none());
+    }
+}
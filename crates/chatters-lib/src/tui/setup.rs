@@ -0,0 +1,270 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use qrcode::{render::unicode::Dense1x2, EcLevel, QrCode};
+use ratatui::{
+    layout::Alignment,
+    style::{Color, Style, Stylize as _},
+    text::{Line, Text},
+    widgets::{Block, Paragraph},
+    Frame,
+};
+
+use crate::backends::{Setup, SetupQuestion};
+
+const DEVICE_NAME: &str = "device_name";
+const DATA_DIR: &str = "data_dir";
+
+/// Questions asked on the first run to set up the backend, and the progress of linking it as a new
+/// device afterwards.
+#[derive(Debug)]
+pub struct SetupWizard {
+    app_name: String,
+    fields: Vec<Field>,
+    /// Index of the field being answered.
+    pub selected: usize,
+    /// Set while linking, with the contents of the QR code or the login page once they arrive.
+    pub linking: Option<Option<String>>,
+    /// Why linking failed the last time.
+    pub error: Option<String>,
+}
+
+#[derive(Debug)]
+struct Field {
+    key: &'static str,
+    prompt: &'static str,
+    answer: String,
+    secret: bool,
+}
+
+/// What to do after a key press in the wizard.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SetupAction {
+    Link,
+    /// Stop linking to change the answers.
+    Cancel,
+    Quit,
+}
+
+impl SetupWizard {
+    pub fn new(
+        app_name: &str,
+        device_name: &str,
+        data_dir: &Path,
+        questions: Vec<SetupQuestion>,
+    ) -> Self {
+        let mut fields = vec![
+            Field {
+                key: DEVICE_NAME,
+                prompt: "Device name",
+                answer: device_name.to_owned(),
+                secret: false,
+            },
+            Field {
+                key: DATA_DIR,
+                prompt: "Data directory",
+                answer: data_dir.to_string_lossy().into_owned(),
+                secret: false,
+            },
+        ];
+        fields.extend(questions.into_iter().map(|q| Field {
+            key: q.key,
+            prompt: q.prompt,
+            answer: q.default.to_owned(),
+            secret: q.secret,
+        }));
+        Self {
+            app_name: app_name.to_owned(),
+            fields,
+            selected: 0,
+            linking: None,
+            error: None,
+        }
+    }
+
+    pub fn key(&mut self, key: KeyEvent) -> Option<SetupAction> {
+        let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers == KeyModifiers::CONTROL;
+        if self.linking.is_some() {
+            return (ctrl_c || key.code == KeyCode::Esc).then_some(SetupAction::Cancel);
+        }
+        if ctrl_c {
+            return Some(SetupAction::Quit);
+        }
+        let last = self.fields.len() - 1;
+        let answer = &mut self.fields[self.selected].answer;
+        match key.code {
+            KeyCode::Esc => return Some(SetupAction::Quit),
+            KeyCode::Enter if self.selected == last => return Some(SetupAction::Link),
+            KeyCode::Enter | KeyCode::Tab | KeyCode::Down => {
+                self.selected = (self.selected + 1).min(last);
+            }
+            KeyCode::BackTab | KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Backspace => {
+                answer.pop();
+            }
+            KeyCode::Char('u') if key.modifiers == KeyModifiers::CONTROL => answer.clear(),
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => answer.push(c),
+            _ => {}
+        }
+        None
+    }
+
+    /// The setup from the answers, and the directory to keep the data in.
+    pub fn setup(&self) -> (Setup, PathBuf) {
+        let answer = |key| {
+            self.fields
+                .iter()
+                .find(|f| f.key == key)
+                .map(|f| f.answer.trim().to_owned())
+                .unwrap_or_default()
+        };
+        let answers: BTreeMap<_, _> = self
+            .fields
+            .iter()
+            .filter(|f| f.key != DEVICE_NAME && f.key != DATA_DIR)
+            .map(|f| (f.key.to_owned(), f.answer.clone()))
+            .collect();
+        let setup = Setup {
+            device_name: answer(DEVICE_NAME),
+            answers,
+        };
+        (setup, PathBuf::from(answer(DATA_DIR)))
+    }
+
+    pub fn render(&self, frame: &mut Frame<'_>) {
+        let text = match &self.linking {
+            None => self.render_questions(),
+            Some(link) => self.render_linking(link.as_deref()),
+        };
+        let block = Block::bordered().title(format!(" Set up {} ", self.app_name));
+        frame.render_widget(
+            Paragraph::new(text)
+                .block(block)
+                .alignment(Alignment::Center),
+            frame.area(),
+        );
+    }
+
+    fn render_questions(&self) -> Text<'static> {
+        let mut lines = vec![
+            Line::from("Answer these to link as a new device."),
+            Line::from(""),
+        ];
+        for (i, field) in self.fields.iter().enumerate() {
+            let answer = if field.secret {
+                "*".repeat(field.answer.chars().count())
+            } else {
+                field.answer.clone()
+            };
+            let line = Line::from(format!("{}: {answer}", field.prompt));
+            if i == self.selected {
+                lines.push(line.reversed());
+            } else {
+                lines.push(line);
+            }
+        }
+        if let Some(error) = &self.error {
+            lines.push(Line::from(""));
+            lines.push(Line::from(error.clone()).red());
+        }
+        lines.push(Line::from(""));
+        lines.push(
+            Line::from("<Tab> next, <S-Tab> previous, <Enter> on the last one links, <Esc> quits")
+                .italic(),
+        );
+        Text::from(lines)
+    }
+
+    fn render_linking(&self, link: Option<&str>) -> Text<'static> {
+        let (setup, _) = self.setup();
+        let mut lines = Vec::new();
+        match link {
+            None => lines.push(Line::from("Linking…")),
+            // web logins, such as single sign-on, happen in the browser
+            Some(link) if link.starts_with("https://") || link.starts_with("http://") => {
+                lines.push(Line::from("Log in in the browser at"));
+                lines.push(Line::from(link.to_owned()));
+            }
+            Some(link) => {
+                lines.push(Line::from(format!(
+                    "Scan this from the linked devices settings on your phone to link {}",
+                    setup.device_name
+                )));
+                lines.push(Line::from(""));
+                lines.extend(qr_code(link));
+            }
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::from("<Esc> goes back").italic());
+        Text::from(lines)
+    }
+}
+
+/// Draw a QR code with half blocks, fitting two rows of it on each line.
+///
+/// The colours are fixed as scanners struggle with light codes on dark backgrounds.
+pub fn qr_code(data: &str) -> Vec<Line<'static>> {
+    let code = match QrCode::with_error_correction_level(data, EcLevel::L) {
+        Ok(code) => code,
+        Err(error) => return vec![Line::from(format!("Failed to make QR code: {error}"))],
+    };
+    let style = Style::new().fg(Color::Black).bg(Color::White);
+    code.render::<Dense1x2>()
+        .build()
+        .lines()
+        .map(|line| Line::styled(line.to_owned(), style))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_setup_wizard() {
+        let questions = vec![
+            SetupQuestion {
+                key: "homeserver",
+                prompt: "Homeserver URL",
+                default: "https://matrix.org",
+                secret: false,
+            },
+            SetupQuestion {
+                key: "password",
+                prompt: "Password",
+                default: "",
+                secret: true,
+            },
+        ];
+        let mut wizard = SetupWizard::new("chatters", "laptop", Path::new("/data"), questions);
+        let press = |wizard: &mut SetupWizard, keys: &str| {
+            keys.chars()
+                .map(|c| {
+                    let code = match c {
+                        '\n' => KeyCode::Enter,
+                        '\x08' => KeyCode::Backspace,
+                        c => KeyCode::Char(c),
+                    };
+                    wizard.key(KeyEvent::new(code, KeyModifiers::NONE))
+                })
+                .last()
+                .flatten()
+        };
+        // rename the device, keep the data directory and homeserver, then set the password
+        let pressed = press(
+            &mut wizard,
+            "\x08\x08\x08\x08\x08\x08desktop\n\n\nhunter2\n",
+        );
+        let (setup, data_dir) = wizard.setup();
+        insta::assert_debug_snapshot!((
+            pressed,
+            setup.device_name,
+            setup.answers,
+            data_dir,
+            wizard.render_questions().lines[5].to_string()
+        ));
+    }
+}
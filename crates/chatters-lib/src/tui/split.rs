@@ -0,0 +1,21 @@
+use crate::backends::ContactId;
+
+use super::compose::Compose;
+use super::messages::Messages;
+
+/// A second conversation shown beside the selected one.
+///
+/// The focused pane is always the selected contact, with the messages and compose of the
+/// [`TuiState`](super::TuiState), so that commands and sends act on it. This holds the other pane,
+/// swapped with those when the focus moves.
+#[derive(Debug)]
+pub struct Split {
+    pub contact_id: ContactId,
+    pub messages: Messages,
+    /// The draft being written in the other pane, kept until it is focused again.
+    pub compose: Compose,
+    /// The id of the latest request to load its messages.
+    pub messages_request: u64,
+    /// Whether it is drawn to the left of the focused pane.
+    pub on_left: bool,
+}
@@ -0,0 +1,55 @@
+/// Expand the `{placeholder}`s of a status line template with their values, leaving unknown ones
+/// as they are. `{{` and `}}` give literal braces.
+pub fn expand(template: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    let mut expanded = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.next_if_eq(&'{').is_some() => expanded.push('{'),
+            '}' if chars.next_if_eq(&'}').is_some() => expanded.push('}'),
+            '{' => {
+                let mut name = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    name.push(c);
+                }
+                match value(&name).filter(|_| closed) {
+                    Some(value) => expanded.push_str(&value),
+                    None => {
+                        expanded.push('{');
+                        expanded.push_str(&name);
+                        if closed {
+                            expanded.push('}');
+                        }
+                    }
+                }
+            }
+            c => expanded.push(c),
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let value = |name: &str| match name {
+            "mode" => Some("Normal".to_owned()),
+            "unread_total" => Some("3".to_owned()),
+            "syncing" => Some(String::new()),
+            _ => None,
+        };
+        insta::assert_debug_snapshot!([
+            "{mode} {unread_total} unread{syncing}",
+            "{{mode}} {unknown} {mode",
+        ]
+        .map(|template| expand(template, value)));
+    }
+}
@@ -0,0 +1,124 @@
+use crate::backends::ContactId;
+
+use super::compose::Compose;
+use super::messages::Messages;
+
+/// A conversation open in a tab, with the messages, scroll position and draft it had when last
+/// active.
+#[derive(Debug)]
+pub struct Tab {
+    pub contact_id: ContactId,
+    pub messages: Messages,
+    pub compose: Compose,
+}
+
+impl Tab {
+    fn new(contact_id: ContactId) -> Self {
+        Self {
+            contact_id,
+            messages: Messages::default(),
+            compose: Compose::default(),
+        }
+    }
+}
+
+/// Conversations open in tabs.
+///
+/// The active tab is the selected contact, using the messages and compose of the
+/// [`TuiState`](super::TuiState), so only the others keep their state here.
+#[derive(Debug, Default)]
+pub struct Tabs {
+    tabs: Vec<Tab>,
+    active: usize,
+}
+
+impl Tabs {
+    pub fn is_empty(&self) -> bool {
+        self.tabs.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tabs.len()
+    }
+
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// The conversations of the tabs in order, that of the active one being as it was when last
+    /// left.
+    pub fn contact_ids(&self) -> impl Iterator<Item = &ContactId> {
+        self.tabs.iter().map(|t| &t.contact_id)
+    }
+
+    pub fn position(&self, contact_id: &ContactId) -> Option<usize> {
+        self.tabs.iter().position(|t| t.contact_id == *contact_id)
+    }
+
+    /// Open a tab for a conversation after the others, returning its index.
+    pub fn open(&mut self, contact_id: ContactId) -> usize {
+        self.tabs.push(Tab::new(contact_id));
+        self.tabs.len() - 1
+    }
+
+    /// Make another tab active, keeping the one being left and returning the state of the new
+    /// one.
+    pub fn switch(&mut self, index: usize, leaving: Tab) -> Tab {
+        let entering = Tab::new(self.tabs[index].contact_id.clone());
+        let entering = std::mem::replace(&mut self.tabs[index], entering);
+        self.tabs[self.active] = leaving;
+        self.active = index;
+        entering
+    }
+
+    /// Close the active tab, returning the state of the one next to it which takes its place, if
+    /// any are left.
+    pub fn close_active(&mut self) -> Option<Tab> {
+        self.tabs.remove(self.active);
+        if self.tabs.is_empty() {
+            self.active = 0;
+            return None;
+        }
+        self.active = self.active.min(self.tabs.len() - 1);
+        let contact_id = self.tabs[self.active].contact_id.clone();
+        Some(std::mem::replace(
+            &mut self.tabs[self.active],
+            Tab::new(contact_id),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tab(id: u8, draft: &str) -> Tab {
+        let mut tab = Tab::new(ContactId::User(vec![id]));
+        tab.compose.set_text(vec![draft.to_owned()]);
+        tab
+    }
+
+    #[test]
+    fn test_switch_and_close() {
+        let mut tabs = Tabs::default();
+        tabs.open(ContactId::User(vec![1]));
+        let second = tabs.open(ContactId::User(vec![2]));
+        assert_eq!(second, 1);
+
+        let entered = tabs.switch(second, tab(1, "first draft"));
+        assert_eq!(entered.contact_id, ContactId::User(vec![2]));
+        assert_eq!(tabs.active(), 1);
+
+        // the draft left behind comes back
+        let entered = tabs.switch(0, tab(2, "second draft"));
+        assert_eq!(entered.compose.lines(), ["first draft"]);
+
+        let next = tabs.close_active().unwrap();
+        assert_eq!(next.compose.lines(), ["second draft"]);
+        assert_eq!(tabs.active(), 0);
+        assert_eq!(tabs.position(&ContactId::User(vec![2])), Some(0));
+
+        assert!(tabs.close_active().is_none());
+        assert!(tabs.is_empty());
+    }
+}
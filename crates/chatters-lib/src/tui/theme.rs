@@ -0,0 +1,212 @@
+use std::{
+    fs::File,
+    io::{Read as _, Write as _},
+    os::fd::AsRawFd,
+    time::{Duration, Instant},
+};
+
+use log::debug;
+use ratatui::style::Color;
+
+/// Whether the terminal draws on a dark or a light background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    Dark,
+    Light,
+}
+
+/// The colours used when rendering, chosen to be readable on the background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub sender: Color,
+    /// The name on the user's own messages.
+    pub own: Color,
+    pub quote: Color,
+    pub error: Color,
+    pub verified: Color,
+    pub warning: Color,
+    /// The background of messages in a visual selection.
+    pub selection: Color,
+    /// Messages and conversations mentioning the user.
+    pub mention: Color,
+    /// The background of text matching a search.
+    pub found: Color,
+    /// Colours to tell apart senders who aren't contacts, such as those relayed by a bridge.
+    pub senders: [Color; 6],
+}
+
+impl Palette {
+    pub fn for_background(background: Background) -> Self {
+        match background {
+            Background::Dark => Self {
+                sender: Color::Cyan,
+                own: Color::LightBlue,
+                quote: Color::Gray,
+                error: Color::LightRed,
+                verified: Color::LightGreen,
+                warning: Color::Yellow,
+                selection: Color::DarkGray,
+                mention: Color::LightMagenta,
+                found: Color::Yellow,
+                senders: [
+                    Color::LightBlue,
+                    Color::LightGreen,
+                    Color::LightMagenta,
+                    Color::LightYellow,
+                    Color::LightCyan,
+                    Color::LightRed,
+                ],
+            },
+            Background::Light => Self {
+                sender: Color::Blue,
+                own: Color::Indexed(25),
+                quote: Color::DarkGray,
+                error: Color::Red,
+                verified: Color::Green,
+                warning: Color::Indexed(130),
+                selection: Color::Gray,
+                mention: Color::Magenta,
+                found: Color::LightYellow,
+                senders: [
+                    Color::Blue,
+                    Color::Green,
+                    Color::Magenta,
+                    Color::Indexed(130),
+                    Color::Cyan,
+                    Color::Red,
+                ],
+            },
+        }
+    }
+
+    /// A colour for the sender, the same each time for the same name.
+    pub fn sender_color(&self, name: &str) -> Color {
+        pick_color(&self.senders, name).unwrap_or(self.sender)
+    }
+}
+
+/// One of the colours for the key, the same each time for the same key, or none without any
+/// colours to pick from.
+pub fn pick_color(colors: &[Color], key: &str) -> Option<Color> {
+    // FNV-1a, to stay the same between runs
+    let hash = key.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    let len = u64::try_from(colors.len()).ok().filter(|len| *len > 0)?;
+    Some(colors[(hash % len) as usize])
+}
+
+/// Ask the terminal for its background colour.
+///
+/// The OSC 11 query is followed by a device attributes request, which terminals always answer,
+/// so the read finishes even when the background colour isn't reported. Must be called in raw
+/// mode, before anything else reads the terminal's input.
+pub fn detect_background(timeout: Duration) -> Option<Background> {
+    let mut tty = File::open("/dev/tty").ok()?;
+    let mut stdout = std::io::stdout();
+    stdout.write_all(b"\x1b]11;?\x1b\\\x1b[c").ok()?;
+    stdout.flush().ok()?;
+
+    let deadline = Instant::now() + timeout;
+    let mut response = Vec::new();
+    let mut byte = [0];
+    // read a byte at a time to leave any keys pressed afterwards for the ui, waiting for each so
+    // that nothing is left reading the terminal once the deadline passes
+    loop {
+        let remaining = deadline.checked_duration_since(Instant::now())?;
+        if !wait_readable(&tty, remaining) {
+            debug!("Timed out detecting background");
+            return None;
+        }
+        tty.read_exact(&mut byte).ok()?;
+        response.push(byte[0]);
+        // the device attributes response is `ESC [ ? ... c`
+        if byte[0] == b'c' && find(&response, b"\x1b[?").is_some() {
+            break;
+        }
+    }
+    let background = parse_background(&response);
+    debug!(response:? = String::from_utf8_lossy(&response), background:?; "Detected background");
+    background
+}
+
+/// Wait for the file to have something to read, returning whether it does before the timeout.
+fn wait_readable(file: &impl AsRawFd, timeout: Duration) -> bool {
+    let mut poll_fd = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout = libc::c_int::try_from(timeout.as_millis()).unwrap_or(libc::c_int::MAX);
+    // SAFETY: the descriptor stays open while the file is borrowed and there is one `pollfd`
+    let ready = unsafe { libc::poll(&mut poll_fd, 1, timeout) };
+    ready > 0 && poll_fd.revents & libc::POLLIN != 0
+}
+
+/// Parse an OSC 11 response like `ESC ] 11 ; rgb:ffff/ffff/ffff ESC \`.
+fn parse_background(response: &[u8]) -> Option<Background> {
+    let start = find(response, b"]11;rgb:")? + b"]11;rgb:".len();
+    let rgb = &response[start..];
+    let end = rgb.iter().position(|b| *b == b'\x1b' || *b == b'\x07')?;
+    let rgb = std::str::from_utf8(&rgb[..end]).ok()?;
+
+    let mut components = rgb.split('/').map(|c| {
+        // components have 1 to 4 hex digits, scale them all to 0-1
+        let value = u16::from_str_radix(c, 16).ok()?;
+        let max = 16_u32.checked_pow(c.len() as u32)? - 1;
+        Some(f64::from(value) / f64::from(max))
+    });
+    let r = components.next()??;
+    let g = components.next()??;
+    let b = components.next()??;
+
+    let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    if luminance > 0.5 {
+        Some(Background::Light)
+    } else {
+        Some(Background::Dark)
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_background() {
+        let responses: [&[u8]; 5] = [
+            b"\x1b]11;rgb:0000/0000/0000\x1b\\\x1b[?62;22c",
+            b"\x1b]11;rgb:ffff/ffff/dddd\x07\x1b[?1;2c",
+            b"\x1b]11;rgb:fd/f6/e3\x1b\\\x1b[?6c",
+            b"\x1b]11;rgb:2828/2c2c/3434\x1b\\",
+            b"\x1b[?62;22c",
+        ];
+        insta::assert_debug_snapshot!(responses.map(parse_background));
+    }
+
+    #[test]
+    fn test_pick_color() {
+        let colors = [Color::Red, Color::Green, Color::Blue];
+        let picked = pick_color(&colors, "0102");
+        assert!(picked.is_some());
+        assert_eq!(pick_color(&colors, "0102"), picked);
+        // spread across the colours
+        let picked = (0..30)
+            .filter_map(|i| pick_color(&colors, &i.to_string()))
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(picked.len(), colors.len());
+        assert_eq!(pick_color(&[], "0102"), None);
+    }
+
+    #[test]
+    fn test_wait_readable() {
+        let (mut tx, rx) = std::os::unix::net::UnixStream::pair().unwrap();
+        assert!(!wait_readable(&rx, Duration::from_millis(10)));
+        tx.write_all(b"\x1b").unwrap();
+        assert!(wait_readable(&rx, Duration::from_millis(10)));
+    }
+}
@@ -0,0 +1,93 @@
+use crate::backends::ContactId;
+
+use super::compose::Draft;
+
+/// How many changes are kept to undo, dropping the oldest beyond it.
+const MAX_CHANGES: usize = 100;
+
+/// How something local was before it was changed, to go back to with `:undo`.
+#[derive(Debug, Clone)]
+pub enum Change {
+    /// The draft of a conversation before it was cleared, detached from or overwritten.
+    Draft { contact_id: ContactId, draft: Draft },
+    /// When a conversation was muted until, if it was, before muting or unmuting it.
+    Mute {
+        contact_id: ContactId,
+        until: Option<u64>,
+    },
+}
+
+/// Changes to undo, and those undone to redo.
+#[derive(Debug, Default)]
+pub struct UndoStack {
+    undo: Vec<Change>,
+    redo: Vec<Change>,
+}
+
+impl UndoStack {
+    /// Remember how things were before a change, which can't redo what was undone before it.
+    pub fn push(&mut self, change: Change) {
+        self.redo.clear();
+        self.push_undo(change);
+    }
+
+    pub fn pop_undo(&mut self) -> Option<Change> {
+        self.undo.pop()
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Change> {
+        self.redo.pop()
+    }
+
+    /// Put back a change to undo, such as after redoing it.
+    pub fn push_undo(&mut self, change: Change) {
+        self.undo.push(change);
+        if self.undo.len() > MAX_CHANGES {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Keep how things were before undoing a change, to redo it.
+    pub fn push_redo(&mut self, change: Change) {
+        self.redo.push(change);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mute(until: u64) -> Change {
+        Change::Mute {
+            contact_id: ContactId::User(vec![1]),
+            until: Some(until),
+        }
+    }
+
+    fn until(change: Option<Change>) -> Option<u64> {
+        match change {
+            Some(Change::Mute { until, .. }) => until,
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_new_change_drops_redo() {
+        let mut stack = UndoStack::default();
+        stack.push(mute(1));
+        let undone = stack.pop_undo().unwrap();
+        stack.push_redo(undone);
+        stack.push(mute(2));
+        assert_eq!(until(stack.pop_redo()), None);
+        assert_eq!(until(stack.pop_undo()), Some(2));
+
+        for i in 0..=MAX_CHANGES as u64 {
+            stack.push(mute(i));
+        }
+        let mut oldest = None;
+        while let Some(change) = stack.pop_undo() {
+            oldest = until(Some(change));
+        }
+        assert_eq!(oldest, Some(1));
+    }
+}
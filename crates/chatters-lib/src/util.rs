@@ -1,29 +1,45 @@
-use crate::commands::{
-    self, Command as _, CommandMode, ExecuteCommand, NextCommand, NormalMode, PrevCommand,
-};
-use crate::config::Config;
-use crate::keybinds::KeyEvents;
-use crate::message::BackendMessage;
-use crate::tui::{render, Mode, TuiState};
+use crate::app::App;
+use crate::backoff::Backoff;
+use crate::bridges::Bridges;
+use crate::command_history::CommandLineHistory;
+use crate::config::{save_data_dir, Config};
+use crate::downloads::AutoDownloadOverrides;
+use crate::emoji_usage::EmojiUsage;
+use crate::folders::Folders;
+use crate::ipc;
+use crate::journal::{read_journal, Journal};
+use crate::macros::Macros;
+use crate::mutes::Mutes;
+use crate::nicknames::Nicknames;
+use crate::outbox::Outbox;
+use crate::panes::Panes;
+use crate::plugins::Plugins;
+use crate::render_filters::RenderFilters;
+use crate::secrets;
+use crate::snapshot::Snapshot;
+use crate::spellcheck::SpellChecker;
+use crate::tui::loading::LoadingScreen;
+use crate::tui::lock::{LockAction, LockScreen};
+use crate::tui::setup::{SetupAction, SetupWizard};
+use crate::tui::theme::detect_background;
+use crate::tui::TuiState;
+use crate::vault::{SealOnDrop, Vault};
 use crate::{
     backend_actor::BackendActor,
-    backends::{Backend, Error},
+    backends::{Backend, Error, LoadProgress, Setup},
     message::FrontendMessage,
 };
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
 use crossterm::event::{Event, EventStream};
-use crossterm::event::{KeyCode, KeyEvent};
+use crossterm::event::{KeyCode, KeyEventKind, KeyModifiers};
 use futures::channel::mpsc;
 use futures::future::Either;
-use futures::StreamExt as _;
 use futures::{future::select, pin_mut};
+use futures::{FutureExt as _, StreamExt as _};
 use log::{debug, info, warn};
-use qrcode_generator::QrCodeEcc;
-use ratatui::prelude::CrosstermBackend;
-use ratatui::{DefaultTerminal, Terminal};
-use std::io::Stdout;
+use ratatui::DefaultTerminal;
 use std::path::Path;
 use std::path::PathBuf;
-use std::str::FromStr as _;
 
 #[derive(Debug, Clone)]
 pub struct Options {
@@ -31,78 +47,188 @@ pub struct Options {
     pub data_local_dir: PathBuf,
     pub config_file: PathBuf,
     pub app_name: String,
+    /// Record messages between the frontend and backend, and the keys pressed, to this file.
+    pub journal_file: Option<PathBuf>,
+    /// Feed the keys and frontend messages recorded in this journal file into the UI instead of
+    /// syncing with the backend.
+    pub replay_file: Option<PathBuf>,
+    /// Listen for control requests from external tools on this unix socket.
+    pub ipc_socket: Option<PathBuf>,
 }
 
-pub async fn run<B: Backend + Clone>(options: Options) {
-    let backend_path = options.data_local_dir.join("backend");
+pub async fn run<B: Backend + Clone + 'static>(options: Options) {
+    let (config, config_warnings) = match Config::load(&options.config_file) {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            eprintln!("{error}");
+            return;
+        }
+    };
+    debug!(config:?, warnings:? = config_warnings; "Loaded config file");
+    secrets::set_plaintext_fallback(config.plaintext_secrets);
+
+    let replay = match &options.replay_file {
+        Some(path) => match read_journal(path) {
+            Ok(entries) => Some(entries),
+            Err(error) => {
+                eprintln!("Failed to read journal {path:?}: {error}");
+                return;
+            }
+        },
+        None => None,
+    };
 
-    let config = load_config(&options.config_file);
-    debug!(config:?; "Loaded config file");
+    let mut data_dir = config
+        .data_dir
+        .clone()
+        .unwrap_or_else(|| options.data_local_dir.clone());
+
+    // sealed again when dropped, so that the data isn't left decrypted however this returns
+    let mut vault = None;
+    if config.encrypt {
+        let Some(unlocked) = unlock_vault(&options.app_name, &data_dir.join("backend")).await
+        else {
+            return;
+        };
+        vault = Some(SealOnDrop::new(unlocked));
+    }
+    // the snapshot would leave conversations readable, so isn't kept when encrypting
+    let load_snapshot = |data_dir: &Path| {
+        let path = data_dir.join("snapshot.json");
+        if config.encrypt {
+            let _ = std::fs::remove_file(&path);
+            Snapshot::default()
+        } else {
+            Snapshot::load(&path)
+        }
+    };
 
-    let backend = match B::load(&backend_path).await {
-        Ok(b) => b,
-        Err(Error::Unlinked) => {
-            let (provisioning_link_tx, provisioning_link_rx) = futures::channel::oneshot::channel();
-            let backend = futures::future::join(
-                B::link(&backend_path, &options.device_name, provisioning_link_tx),
-                async move {
-                    match provisioning_link_rx.await {
-                        Ok(url) => {
-                            let qr_path = "/tmp/signal_link_device_qrcode.svg";
-                            println!(
-                                "Please scan in the QR code from the image file at {qr_path:?}"
-                            );
-                            let qr_svg = qrcode_generator::to_svg_to_string(
-                                url.to_string(),
-                                QrCodeEcc::Low,
-                                1024,
-                                None::<String>,
-                            )
-                            .unwrap();
-                            std::fs::write(qr_path, qr_svg.as_bytes()).unwrap();
-                            open::that(qr_path).unwrap();
-                        }
-                        Err(error) => warn!(error:% = error; "Linking device was cancelled"),
+    // with a snapshot from the last run the ui can start while the backend loads, otherwise load
+    // it first, setting up a new device if it hasn't been linked
+    // a replay starts from nothing so that it goes the same way each time
+    let mut snapshot = if replay.is_some() {
+        Snapshot::default()
+    } else {
+        load_snapshot(&data_dir)
+    };
+    let warm_start = !snapshot.is_empty();
+    info!(warm_start; "Loaded snapshot");
+    let replaying = replay.is_some();
+    let loaded = if warm_start || replaying {
+        None
+    } else {
+        match load_backend::<B>(&options.app_name, &data_dir.join("backend")).await {
+            None => return,
+            Some(Ok(backend)) => Some(backend),
+            Some(Err(Error::Unlinked)) => {
+                let Some((backend, setup_dir)) = setup_backend::<B>(&options, &data_dir).await
+                else {
+                    return;
+                };
+                if setup_dir != data_dir {
+                    if let Err(error) = save_data_dir(&options.config_file, &setup_dir) {
+                        warn!(error:%; "Failed to save data directory in config");
                     }
-                },
-            )
-            .await;
-            backend.0.unwrap()
-        }
-        Err(_) => {
-            unimplemented!()
+                    snapshot = load_snapshot(&setup_dir);
+                    if let Some(vault) = &mut vault {
+                        vault.relocate(&setup_dir.join("backend"));
+                    }
+                    data_dir = setup_dir;
+                }
+                Some(backend)
+            }
+            Some(Err(error)) => {
+                eprintln!("Failed to load {}: {error}", B::NAME);
+                return;
+            }
         }
     };
+    let backend_path = data_dir.join("backend");
+    let snapshot_path = data_dir.join("snapshot.json");
+    let state_dir = if vault.is_some() {
+        let state_dir = backend_path.join("chatters");
+        move_into_vault(&data_dir, &state_dir);
+        state_dir
+    } else {
+        data_dir.clone()
+    };
 
-    let self_id = backend.self_id().await;
-
-    info!("Loaded backend");
-
-    let mut backend2 = backend.clone();
+    let mut tui_state = TuiState {
+        app_name: options.app_name.clone(),
+        self_id: snapshot.self_id.clone(),
+        backend_name: B::NAME.to_owned(),
+        limits: snapshot.limits.clone(),
+        config: config.clone(),
+        config_path: options.config_file.clone(),
+        emoji_usage: EmojiUsage::load(&state_dir.join(EMOJI_USAGE_FILE)),
+        mutes: Mutes::load(&state_dir.join(MUTES_FILE)),
+        nicknames: Nicknames::load(&state_dir.join(NICKNAMES_FILE)),
+        folders: Folders::load(&state_dir.join(FOLDERS_FILE)),
+        panes: Panes::load(&state_dir.join(PANES_FILE), &config),
+        auto_downloads: AutoDownloadOverrides::load(&state_dir.join(AUTO_DOWNLOAD_FILE)),
+        macros: Macros::load(&macros_path(&options.config_file)),
+        snapshot,
+        syncing: true,
+        vault: vault.as_deref().cloned(),
+        ..Default::default()
+    };
+    tui_state.command_line.history =
+        CommandLineHistory::load(&state_dir.join(COMMAND_HISTORY_FILE));
+    tui_state.command_line.error = config_warnings.join("; ");
+
+    let journal = options
+        .journal_file
+        .as_ref()
+        .and_then(|path| match Journal::new(path) {
+            Ok(journal) => {
+                info!(path:?; "Recording journal");
+                Some(journal)
+            }
+            Err(error) => {
+                warn!(error:%, path:?; "Failed to create journal file");
+                None
+            }
+        });
 
     let (b_tx, b_rx) = mpsc::unbounded();
     let (f_tx, f_rx) = mpsc::unbounded();
 
-    let mut ba = BackendActor {
-        backend,
-        message_rx: b_rx,
-        message_tx: f_tx.clone(),
-    };
+    let _config_watcher = watch_config(&options.config_file, f_tx.clone());
+    tui_state.bridges = Bridges::new(&config.bridges);
+    tui_state.render_filters = RenderFilters::new(config.render_filters.clone(), f_tx.clone());
+    if let Some(spellcheck) = &config.spellcheck {
+        tui_state.spell_checker = SpellChecker::start(spellcheck, f_tx.clone());
+    }
+    tui_state.outbox = Outbox::new(f_tx.clone());
+    let (ipc_tx, ipc_rx) = mpsc::unbounded();
+    tui_state.plugins = Plugins::start(
+        &config.plugins,
+        &tui_state.app_name,
+        &tui_state.backend_name,
+        &ipc_tx,
+    );
+
+    if let Some(ipc_socket) = options.ipc_socket.clone() {
+        tokio::spawn(ipc::serve(ipc_socket, ipc_tx));
+    }
 
+    let journal2 = journal.clone();
     let ui = async move {
-        let terminal = ratatui::init();
-        run_ui(
-            terminal,
-            b_tx,
-            f_rx,
-            self_id,
-            options.app_name,
-            &config,
-            options.config_file,
-        )
-        .await;
+        let terminal = init_terminal(!tui_state.config.disable_mouse);
+        // ask before the event stream starts reading the terminal's responses
+        tui_state.background = detect_background(std::time::Duration::from_millis(200));
+        let events = EventStream::new().map(|event| event.unwrap());
+        let mut app = App::new(terminal, b_tx, tui_state, journal2);
+        app.start();
+        // keys pressed during a replay are handled once it finishes, so it can be inspected
+        let quit = match replay {
+            Some(entries) => app.replay(entries).await,
+            None => false,
+        };
+        if !quit {
+            app.run(events, f_rx, ipc_rx).await;
+        }
         debug!("Finished run_ui task");
-        ratatui::restore();
     };
     pin_mut!(ui);
 
@@ -122,356 +248,478 @@ pub async fn run<B: Backend + Clone>(options: Options) {
     };
     pin_mut!(frontend);
 
-    let actor = async move {
-        ba.run().await;
-        debug!("Finished backend actor task");
-    };
-    pin_mut!(actor);
-
-    let sync = async move {
-        info!("Starting background sync");
-        backend2.background_sync(f_tx).await.unwrap();
-        debug!("Finished background sync task");
-    };
-    pin_mut!(sync);
-
+    let ipc_socket = options.ipc_socket.clone();
     let backend = async move {
-        select(actor, sync).await;
+        if replaying {
+            // the journal has the backend's replies, so what the ui sends it goes unanswered
+            let _requests = b_rx;
+            return std::future::pending().await;
+        }
+        let backend = match loaded {
+            Some(backend) => backend,
+            // the ui is up, so shows how far it has got
+            None => B::load(&backend_path, LoadProgress::new(f_tx.clone())).await?,
+        };
+        info!("Loaded backend");
+        f_tx.unbounded_send(FrontendMessage::BackendLoaded {
+            self_id: backend.self_id().await,
+            limits: backend.limits(),
+        })
+        .unwrap();
+
+        let mut backend2 = backend.clone();
+        let mut ba = BackendActor {
+            backend,
+            message_rx: b_rx,
+            message_tx: f_tx.clone(),
+            journal,
+        };
+
+        let actor = async move {
+            ba.run().await;
+            debug!("Finished backend actor task");
+        };
+        pin_mut!(actor);
+
+        let sync = async move {
+            let mut backoff = Backoff::default();
+            loop {
+                info!("Starting background sync");
+                let started = std::time::Instant::now();
+                let error = match backend2.background_sync(f_tx.clone()).await {
+                    Ok(()) => break,
+                    Err(Error::Unlinked) => return Err(Error::Unlinked),
+                    Err(Error::Failure(what, why)) if !why.is_empty() => format!("{what}: {why}"),
+                    Err(error) => error.to_string(),
+                };
+                warn!(error:%; "Background sync failed");
+                // a sync that ran for a while was connected, so isn't failing over and over
+                if started.elapsed() > std::time::Duration::from_secs(60) {
+                    backoff.reset();
+                }
+                let delay = backoff.next_delay();
+                let _ = f_tx.unbounded_send(FrontendMessage::Error {
+                    message: format!("{error}, syncing again in {}s", delay.as_secs()),
+                });
+                tokio::time::sleep(delay).await;
+            }
+            debug!("Finished background sync task");
+            Ok(())
+        };
+        pin_mut!(sync);
+
+        let result = match select(actor, sync).await {
+            Either::Left(((), _)) => Ok(()),
+            Either::Right((result, _)) => result,
+        };
         debug!("Finished backend task");
+        result
     };
     pin_mut!(backend);
 
-    select(frontend, backend).await;
+    // the ui is already up when loading after a warm start, so failures are reported once it has
+    // been put away, whichever task finished first
+    let result = match select(frontend, backend).await {
+        Either::Left(_) => Ok(()),
+        Either::Right((result, _)) => result,
+    };
+    restore_terminal();
+    match result {
+        Err(Error::Unlinked) => {
+            warn!("Backend is unlinked");
+            // the snapshot belongs to the old device, start afresh once linked again
+            let _ = std::fs::remove_file(&snapshot_path);
+            eprintln!("This device is no longer linked, run again to link it");
+        }
+        Err(error) => eprintln!("Failed to load {}: {error}", B::NAME),
+        Ok(()) => {}
+    }
+
+    if let Some(ipc_socket) = ipc_socket {
+        let _ = std::fs::remove_file(ipc_socket);
+    }
 }
 
-async fn run_ui(
-    mut terminal: DefaultTerminal,
-    backend_actor_tx: mpsc::UnboundedSender<BackendMessage>,
-    mut backend_actor_rx: mpsc::UnboundedReceiver<FrontendMessage>,
-    self_id: Vec<u8>,
-    app_name: String,
-    config: &Config,
-    config_path: PathBuf,
-) {
-    // select on two channels, one for keyboard events, another for messages from the backend
-    // (responses)
-    //
-    // handle either action then render the ui again
-
-    let mut tui_state = TuiState::default();
-    tui_state.app_name = app_name;
-    tui_state.self_id = self_id;
-    tui_state.config = config.clone();
-    tui_state.config_path = config_path;
-
-    let mut event_stream = EventStream::new();
-
-    backend_actor_tx
-        .unbounded_send(BackendMessage::LoadContacts)
-        .unwrap();
+/// Take over the terminal for the ui, capturing the mouse if wanted.
+fn init_terminal(capture_mouse: bool) -> DefaultTerminal {
+    let terminal = ratatui::init();
+    if capture_mouse {
+        if let Err(error) = crossterm::execute!(std::io::stdout(), EnableMouseCapture) {
+            warn!(error:%; "Failed to capture the mouse");
+        }
+        // the hook from ratatui restores the rest of the terminal
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let _ = crossterm::execute!(std::io::stdout(), DisableMouseCapture);
+            hook(info);
+        }));
+    }
+    terminal
+}
 
-    loop {
-        // dbg!(&tui_state);
-        terminal.draw(|f| render(f, &mut tui_state)).unwrap();
-
-        let event_future = async { event_stream.next().await.unwrap().unwrap() };
-        pin_mut!(event_future);
-
-        let backend_future = async { backend_actor_rx.next().await.unwrap() };
-        pin_mut!(backend_future);
-
-        match select(event_future, backend_future).await {
-            Either::Left((event, _)) => {
-                if process_user_event(
-                    &mut tui_state,
-                    &backend_actor_tx,
-                    &mut terminal,
-                    config,
-                    event,
-                ) {
-                    break;
-                }
-            }
-            Either::Right((message, _)) => {
-                process_backend_message(&mut tui_state, &backend_actor_tx, &config, message);
-            }
+/// Give the terminal back after [`init_terminal`], releasing the mouse.
+fn restore_terminal() {
+    let _ = crossterm::execute!(std::io::stdout(), DisableMouseCapture);
+    ratatui::restore();
+}
+
+/// Hand the terminal over to another program, such as an editor, while running it, taking it back
+/// afterwards for the ui to be drawn again from scratch.
+pub(crate) fn suspend_terminal<T>(capture_mouse: bool, run: impl FnOnce() -> T) -> T {
+    let _ = crossterm::execute!(std::io::stdout(), DisableMouseCapture);
+    ratatui::restore();
+    let result = run();
+    let _ = crossterm::terminal::enable_raw_mode();
+    let _ = crossterm::execute!(std::io::stdout(), crossterm::terminal::EnterAlternateScreen);
+    if capture_mouse {
+        let _ = crossterm::execute!(std::io::stdout(), EnableMouseCapture);
+    }
+    result
+}
+
+/// Macros live beside the config rather than in the data directory, as they are set up like
+/// keybinds.
+fn macros_path(config_file: &Path) -> PathBuf {
+    config_file
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("macros.json")
+}
+
+const EMOJI_USAGE_FILE: &str = "emoji_usage.json";
+const MUTES_FILE: &str = "mutes.json";
+const NICKNAMES_FILE: &str = "nicknames.json";
+const FOLDERS_FILE: &str = "folders.json";
+const PANES_FILE: &str = "panes.json";
+const AUTO_DOWNLOAD_FILE: &str = "auto_download.json";
+const COMMAND_HISTORY_FILE: &str = "command_history.json";
+
+/// Move state left from before encrypting into the vault so that it is sealed along with the
+/// backend's data.
+fn move_into_vault(data_dir: &Path, state_dir: &Path) {
+    for name in [
+        EMOJI_USAGE_FILE,
+        MUTES_FILE,
+        NICKNAMES_FILE,
+        FOLDERS_FILE,
+        PANES_FILE,
+        AUTO_DOWNLOAD_FILE,
+        COMMAND_HISTORY_FILE,
+    ] {
+        let from = data_dir.join(name);
+        if !from.exists() || state_dir.join(name).exists() {
+            continue;
+        }
+        let moved = std::fs::create_dir_all(state_dir)
+            .and_then(|()| std::fs::rename(&from, state_dir.join(name)));
+        match moved {
+            Ok(()) => info!(name; "Moved state into the vault"),
+            Err(error) => warn!(error:%, name; "Failed to move state into the vault"),
         }
     }
 }
 
-fn process_user_event(
-    tui_state: &mut TuiState,
-    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
-    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
-    config: &Config,
-    event: Event,
-) -> bool {
-    // dbg!(&event);
-
-    let mode = tui_state.mode;
-
-    debug!(event:? = event; "Received event");
-    match event {
-        Event::Key(KeyEvent {
-            code, modifiers, ..
-        }) => {
-            // handle builtin keybindings
-            if code == KeyCode::Char(':')
-                && modifiers.is_empty()
-                && tui_state.key_events.0.is_empty()
-                && !matches!(tui_state.mode, Mode::Compose)
-            {
-                if let Err(error) = CommandMode.execute(tui_state, ba_tx) {
-                    tui_state.command_line.error = error.to_string();
-                }
-                return false;
-            }
-            if code == KeyCode::Esc && modifiers.is_empty() && tui_state.key_events.0.is_empty() {
-                if let Err(error) = NormalMode.execute(tui_state, ba_tx) {
-                    tui_state.command_line.error = error.to_string();
+/// Ask for the passphrase the backend's data is encrypted with, or for a new one to encrypt it
+/// with if it hasn't been yet.
+async fn unlock_vault(app_name: &str, dir: &Path) -> Option<Vault> {
+    let exists = Vault::exists(dir);
+    let prompt = if exists {
+        format!("Enter the passphrase for {app_name}")
+    } else {
+        format!("Choose a passphrase to encrypt the data of {app_name} with")
+    };
+    let mut terminal = ratatui::init();
+    let mut lock = LockScreen::new(prompt.clone());
+    // the first entry of a new passphrase, while it is repeated
+    let mut first = None;
+    let mut events = EventStream::new();
+    let vault = loop {
+        terminal.draw(|f| lock.render(f)).unwrap();
+        let Some(Ok(event)) = events.next().await else {
+            break None;
+        };
+        let Event::Key(key) = event else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let passphrase = match lock.key(key) {
+            Some(LockAction::Submit(passphrase)) => passphrase,
+            Some(LockAction::Quit) => break None,
+            None => continue,
+        };
+        if passphrase.is_empty() {
+            lock.error = Some("The passphrase can't be empty".to_owned());
+            continue;
+        }
+
+        if exists {
+            lock.prompt = "Unlocking…".to_owned();
+            terminal.draw(|f| lock.render(f)).unwrap();
+            match Vault::open(dir, passphrase.into()) {
+                Ok(vault) => break Some(vault),
+                Err(error) => {
+                    lock.prompt = prompt.clone();
+                    lock.error = Some(error.to_string());
                 }
-                return false;
             }
-
-            let key_event = crate::keybinds::KeyEvent { code, modifiers };
-            tui_state.key_events.0.push(key_event);
-            debug!(key_events:? = tui_state.key_events; "Looking for a key binding");
-            match mode {
-                Mode::Normal => {
-                    match config.keybinds.get(&tui_state.key_events, mode) {
-                        Ok(command) => {
-                            if execute_command(tui_state, ba_tx, terminal, config, command.clone())
-                            {
-                                return true;
-                            }
-                        }
-                        Err(true) => {
-                            // could still be a binding with more input, leave it
-                        }
-                        Err(false) => {
-                            tui_state.command_line.error =
-                                format!("Failed to find keybind for {}", tui_state.key_events);
-                            // no binding starts with this
-                            tui_state.key_events.0.clear();
-                        }
-                    }
+        } else {
+            match first.take() {
+                None => {
+                    first = Some(passphrase);
+                    lock.prompt = "Enter the passphrase again".to_owned();
+                    lock.error = None;
                 }
-                Mode::Command { previous: _ } => {
-                    // currently don't support keybinds in command mode as simulation uses command
-                    // mode itself
-                    tui_state.key_events.0.clear();
-                    if code == KeyCode::Tab {
-                        commands::complete_command(tui_state, true);
-                    } else if code == KeyCode::BackTab {
-                        commands::complete_command(tui_state, false);
-                    } else if code == KeyCode::Enter {
-                        match ExecuteCommand.execute(tui_state, ba_tx) {
-                            Ok(cs) => match cs {
-                                commands::CommandSuccess::Nothing => {}
-                                commands::CommandSuccess::Quit => return true,
-                                commands::CommandSuccess::Clear => {
-                                    terminal.clear().unwrap();
-                                }
-                            },
-                            Err(error) => {
-                                tui_state.command_line.error = error.to_string();
-                            }
-                        }
-                    } else if code == KeyCode::Up {
-                        if let Err(error) = PrevCommand.execute(tui_state, ba_tx) {
-                            tui_state.command_line.error = error.to_string();
-                        }
-                    } else if code == KeyCode::Down {
-                        if let Err(error) = NextCommand.execute(tui_state, ba_tx) {
-                            tui_state.command_line.error = error.to_string();
-                        }
-                    } else {
-                        tui_state.command_line.input(crossterm::event::KeyEvent {
-                            code,
-                            modifiers,
-                            kind: crossterm::event::KeyEventKind::Press,
-                            state: crossterm::event::KeyEventState::empty(),
-                        });
-                    }
+                Some(first) if first == passphrase => {
+                    break Some(Vault::create(dir, passphrase.into()));
                 }
-                Mode::Compose => {
-                    match config.keybinds.get(&tui_state.key_events, mode) {
-                        Ok(command) => {
-                            if execute_command(tui_state, ba_tx, terminal, config, command.clone())
-                            {
-                                return true;
-                            }
-                        }
-                        Err(true) => {
-                            // skip
-                        }
-                        Err(false) => {
-                            for key_event in tui_state.key_events.0.drain(..) {
-                                tui_state.compose.input(crossterm::event::KeyEvent {
-                                    code: key_event.code,
-                                    modifiers: key_event.modifiers,
-                                    kind: crossterm::event::KeyEventKind::Press,
-                                    state: crossterm::event::KeyEventState::empty(),
-                                });
-                            }
-                        }
-                    }
+                Some(_) => {
+                    lock.prompt = prompt.clone();
+                    lock.error = Some("The passphrases didn't match".to_owned());
                 }
-                Mode::Popup => match config.keybinds.get(&tui_state.key_events, mode) {
-                    Ok(command) => {
-                        if execute_command(tui_state, ba_tx, terminal, config, command.clone()) {
-                            return true;
-                        }
-                    }
-                    Err(true) => {
-                        // prefix
-                    }
-                    Err(false) => {
-                        tui_state.command_line.error =
-                            format!("Failed to find keybind for {}", tui_state.key_events);
-                        tui_state.key_events.0.clear();
-                    }
-                },
             }
         }
-        e => {
-            warn!(event:? = e; "Unhandled event");
-        }
-    }
-    false
-}
-
-fn execute_command(
-    tui_state: &mut TuiState,
-    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
-    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
-    config: &Config,
-    cmd: String,
-) -> bool {
-    tui_state.key_events.0.clear();
-    // break cmd into keyevents, then loop through them calling process_user_event for each
-    debug!(cmd:?; "Executing command from keybinding");
-    let key_events = KeyEvents::from_str(&cmd).unwrap();
-    debug!(key_events:?; "Broke keybinding command up into key_events");
-    let mut quit = false;
-    for key_event in key_events.0 {
-        debug!(key_event:?; "Simulating key event");
-        quit |= process_user_event(
-            tui_state,
-            ba_tx,
-            terminal,
-            config,
-            Event::Key(KeyEvent {
-                code: key_event.code,
-                modifiers: key_event.modifiers,
-                kind: crossterm::event::KeyEventKind::Press,
-                state: crossterm::event::KeyEventState::empty(),
-            }),
-        );
-    }
-    quit
+    };
+    ratatui::restore();
+    vault
 }
 
-fn process_backend_message(
-    tui_state: &mut TuiState,
-    ba_tx: &mpsc::UnboundedSender<BackendMessage>,
-    config: &Config,
-    msg: FrontendMessage,
-) {
-    // dbg!(&msg);
-    match msg {
-        FrontendMessage::LoadedContacts { contacts } => {
-            if tui_state.contacts.is_empty() && !contacts.is_empty() {
-                tui_state.contacts.state.select_next();
+/// Load the backend, showing how far it has got on the loading screen rather than leaving the
+/// terminal frozen while it catches up, returning nothing if the user quit.
+async fn load_backend<B: Backend>(app_name: &str, backend_path: &Path) -> Option<Result<B, Error>> {
+    let mut terminal = ratatui::init();
+    let mut screen = LoadingScreen::new(app_name, B::NAME);
+    let (progress_tx, mut progress_rx) = mpsc::unbounded();
+    let load = B::load(backend_path, LoadProgress::new(progress_tx));
+    pin_mut!(load);
+    let mut events = EventStream::new();
+    let result = loop {
+        terminal.draw(|f| screen.render(f)).unwrap();
+        // stays pending if the backend doesn't report progress
+        let progress = progress_rx.select_next_some();
+        match select(&mut load, select(progress, events.next())).await {
+            Either::Left((result, _)) => break Some(result),
+            Either::Right((Either::Left((FrontendMessage::LoadProgress { status }, _)), _)) => {
+                screen.status = status;
             }
-            tui_state.contacts.clear();
-            tui_state.contacts.extend(contacts);
-            if let Some(contact) = tui_state.contacts.selected() {
-                ba_tx
-                    .unbounded_send(BackendMessage::LoadMessages {
-                        contact_id: contact.id.clone(),
-                        start_ts: std::ops::Bound::Unbounded,
-                        end_ts: std::ops::Bound::Unbounded,
-                    })
-                    .unwrap();
-            }
-        }
-        FrontendMessage::LoadedMessages { messages } => {
-            if let Some(contact) = tui_state.contacts.selected_mut() {
-                if let Some(last_message) = messages.last() {
-                    if last_message.contact_id == contact.id {
-                        contact.last_message_timestamp = Some(last_message.timestamp);
-                        if tui_state.messages.is_empty() && !messages.is_empty() {
-                            tui_state.messages.state.select_last();
-                        }
-                        tui_state.messages.clear();
-                        tui_state.messages.extend(messages);
+            Either::Right((Either::Left(_), _)) => {}
+            Either::Right((Either::Right((event, _)), _)) => match event {
+                Some(Ok(Event::Key(key))) if key.kind == KeyEventKind::Press => {
+                    let ctrl_c = key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL);
+                    if key.code == KeyCode::Esc || ctrl_c {
+                        break None;
                     }
                 }
-            }
+                Some(Ok(_)) => {}
+                _ => break None,
+            },
         }
-        FrontendMessage::NewMessage { message } => {
-            let sender = tui_state
-                .contacts
-                .contact_by_id(&message.sender)
-                .unwrap()
-                .clone();
-            if let Some(contact) = tui_state
-                .contacts
-                .contact_or_group_by_id_mut(&message.contact_id)
-            {
-                if message.sender != tui_state.self_id {
-                    config
-                        .hooks
-                        .do_on_new_message(&tui_state.app_name, contact, &sender, &message);
-                }
+    };
+    ratatui::restore();
+    result
+}
 
-                contact.last_message_timestamp = Some(message.timestamp);
+/// Set up and link a new device in the setup wizard, returning the backend and the directory its
+/// data is kept in, or nothing if the user quit.
+async fn setup_backend<B: Backend>(options: &Options, data_dir: &Path) -> Option<(B, PathBuf)> {
+    let mut terminal = ratatui::init();
+    let mut wizard = SetupWizard::new(
+        &options.app_name,
+        &options.device_name,
+        data_dir,
+        B::setup_questions(),
+    );
+    let mut events = EventStream::new();
+    let result = loop {
+        terminal.draw(|f| wizard.render(f)).unwrap();
+        let Some(Ok(event)) = events.next().await else {
+            break None;
+        };
+        let Event::Key(key) = event else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match wizard.key(key) {
+            Some(SetupAction::Link) => {}
+            Some(SetupAction::Quit) => break None,
+            Some(SetupAction::Cancel) | None => continue,
+        }
 
-                let selected = tui_state.contacts.state.selected();
-                if let Some(i) = tui_state.contacts.index_by_id(&message.contact_id) {
-                    tui_state.contacts.move_by_index(i, 0);
-                    if selected == Some(i) {
-                        tui_state.contacts.state.select(Some(0));
-                        tui_state.messages.add_single(message);
-                    } else if let Some(selected) = selected {
-                        tui_state.contacts.state.select(Some(selected + 1));
-                    }
-                }
+        let (setup, data_dir) = wizard.setup();
+        let backend_path = data_dir.join("backend");
+        // the directory chosen may already have a linked device in it
+        if let Ok(backend) = B::load(&backend_path, LoadProgress::default()).await {
+            break Some((backend, data_dir));
+        }
+        wizard.error = None;
+        wizard.linking = Some(None);
+        let linked = link_backend::<B>(
+            &mut terminal,
+            &mut wizard,
+            &mut events,
+            &backend_path,
+            &setup,
+        )
+        .await;
+        wizard.linking = None;
+        match linked {
+            Some(Ok(backend)) => break Some((backend, data_dir)),
+            Some(Err(error)) => {
+                warn!(error:%; "Failed to link device");
+                wizard.error = Some(match error {
+                    Error::Failure(what, why) if !why.is_empty() => format!("{what}: {why}"),
+                    error => error.to_string(),
+                });
             }
+            None => info!("Linking device was cancelled"),
         }
-        FrontendMessage::DownloadedAttachment {
-            contact_id,
-            timestamp,
-            index,
-            file_path: file_name,
-        } => {
-            if let Some(contact) = tui_state
-                .contacts
-                .state
-                .selected()
-                .and_then(|i| tui_state.contacts.contact_or_group_by_index_mut(i))
-            {
-                if contact_id == contact.id {
-                    if let Some(msg) = tui_state.messages.get_mut_by_timestamp(timestamp) {
-                        // mark attachment as downloaded
-                        let attachment = msg
-                            .attachments
-                            .iter_mut()
-                            .find(|a| a.index == index)
-                            .unwrap();
-                        attachment.path = Some(file_name);
+    };
+    ratatui::restore();
+    result
+}
+
+/// Link as a new device, showing the QR code or login page sent by the backend in the wizard.
+///
+/// Returns nothing if the user went back to change their answers.
+async fn link_backend<B: Backend>(
+    terminal: &mut DefaultTerminal,
+    wizard: &mut SetupWizard,
+    events: &mut EventStream,
+    backend_path: &Path,
+    setup: &Setup,
+) -> Option<Result<B, Error>> {
+    let (provisioning_link_tx, provisioning_link_rx) = futures::channel::oneshot::channel();
+    // fused so that it stays pending once the link has been received
+    let mut provisioning_link_rx = provisioning_link_rx.fuse();
+    let link = B::link(backend_path, setup, provisioning_link_tx);
+    pin_mut!(link);
+    loop {
+        terminal.draw(|f| wizard.render(f)).unwrap();
+        match select(&mut link, select(&mut provisioning_link_rx, events.next())).await {
+            Either::Left((result, _)) => return Some(result),
+            Either::Right((Either::Left((Ok(link), _)), _)) => {
+                // web logins, such as single sign-on, happen in the browser
+                if link.starts_with("https://") || link.starts_with("http://") {
+                    if let Err(error) = open::that(&link) {
+                        warn!(error:%; "Failed to open login page");
                     }
                 }
+                wizard.linking = Some(Some(link));
             }
+            Either::Right((Either::Left((Err(_), _)), _)) => {}
+            Either::Right((Either::Right((event, _)), _)) => match event {
+                Some(Ok(Event::Key(key))) if key.kind == KeyEventKind::Press => {
+                    if wizard.key(key).is_some() {
+                        return None;
+                    }
+                }
+                Some(Ok(_)) => {}
+                _ => return None,
+            },
         }
-        FrontendMessage::Tick => {
-            // do nothing, just trigger a UI redraw
+    }
+}
+
+/// Load the config file again, keeping the current config if it can't be loaded.
+///
+/// Warnings about the new config are shown in the status line.
+pub fn reload_config(tui_state: &mut TuiState) -> Result<(), String> {
+    let (config, warnings) = Config::load(&tui_state.config_path).map_err(|e| e.to_string())?;
+    secrets::set_plaintext_fallback(config.plaintext_secrets);
+    tui_state.bridges = Bridges::new(&config.bridges);
+    tui_state.config = config;
+    tui_state.command_line.error = warnings.join("; ");
+    info!(warnings:?; "Reloaded config");
+    Ok(())
+}
+
+/// Watch the config file, telling the ui when it changes so that it can be reloaded.
+///
+/// The directory is watched rather than the file itself, as editors often save by replacing the
+/// file. Changes stop being watched once the watcher is dropped.
+fn watch_config(
+    path: &Path,
+    f_tx: mpsc::UnboundedSender<FrontendMessage>,
+) -> Option<notify::RecommendedWatcher> {
+    use notify::Watcher as _;
+
+    let path = path.canonicalize().ok()?;
+    let dir = path.parent()?.to_owned();
+    let watched = path.clone();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        let changed = matches!(
+            event.kind,
+            notify::EventKind::Create(_) | notify::EventKind::Modify(_)
+        );
+        if changed && event.paths.contains(&watched) {
+            let _ = f_tx.unbounded_send(FrontendMessage::ConfigChanged);
+        }
+    });
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(error) => {
+            warn!(error:%; "Failed to watch config file");
+            return None;
         }
+    };
+    if let Err(error) = watcher.watch(&dir, notify::RecursiveMode::NonRecursive) {
+        warn!(error:%, path:?; "Failed to watch config file");
+        return None;
     }
+    Some(watcher)
 }
 
-pub fn load_config(path: &Path) -> Config {
-    let content = std::fs::read_to_string(path).expect("Config file was missing");
-    toml::from_str(&content).expect("Malformed config file")
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reload_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "hide_header = true\n").unwrap();
+        let mut tui_state = TuiState {
+            config_path: path.clone(),
+            ..Default::default()
+        };
+
+        reload_config(&mut tui_state).unwrap();
+        assert!(tui_state.config.hide_header);
+
+        // a broken file keeps the config from before
+        std::fs::write(&path, "hide_header = \n").unwrap();
+        assert!(reload_config(&mut tui_state).is_err());
+        assert!(tui_state.config.hide_header);
+    }
+
+    #[test]
+    fn test_watch_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "").unwrap();
+        let (f_tx, mut f_rx) = mpsc::unbounded();
+        let _watcher = watch_config(&path, f_tx).unwrap();
+
+        // other files in the directory don't count
+        std::fs::write(dir.path().join("other.toml"), "").unwrap();
+        std::fs::write(&path, "hide_header = true\n").unwrap();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        let message = loop {
+            match f_rx.try_recv() {
+                Ok(message) => break message,
+                Err(_) if std::time::Instant::now() < deadline => {
+                    std::thread::sleep(std::time::Duration::from_millis(10))
+                }
+                Err(_) => panic!("No change to the config was seen"),
+            }
+        };
+        assert!(matches!(message, FrontendMessage::ConfigChanged));
+    }
 }
@@ -0,0 +1,252 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+};
+
+use age::secrecy::{ExposeSecret as _, SecretString};
+use log::{info, warn};
+use sha2::{Digest as _, Sha256};
+
+#[derive(Debug, thiserror::Error)]
+pub enum VaultError {
+    #[error("Wrong passphrase")]
+    WrongPassphrase,
+    #[error("Failed to decrypt {path:?}: {error}")]
+    Decrypt {
+        path: PathBuf,
+        error: age::DecryptError,
+    },
+    #[error("Failed to encrypt {path:?}: {error}")]
+    Encrypt {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    #[error("Failed to unpack {path:?}: {error}")]
+    Unpack {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+}
+
+/// A directory kept encrypted with a passphrase while chatters isn't running, such as the
+/// backend's stores and downloaded attachments.
+///
+/// The directory is packed into an age file next to it when sealed and unpacked again when
+/// opened.
+#[derive(Debug, Clone)]
+pub struct Vault {
+    dir: PathBuf,
+    passphrase: SecretString,
+    /// Mixed into the passphrases being checked, so that the digests compared are of no use
+    /// elsewhere.
+    salt: [u8; 32],
+}
+
+impl Vault {
+    /// Where the directory is kept while encrypted.
+    fn archive(dir: &Path) -> PathBuf {
+        dir.with_extension("age")
+    }
+
+    /// Whether the directory has been encrypted before, so needs the passphrase to open it.
+    pub fn exists(dir: &Path) -> bool {
+        Self::archive(dir).exists()
+    }
+
+    /// Start encrypting a directory with a new passphrase, which happens when it is sealed.
+    pub fn create(dir: &Path, passphrase: SecretString) -> Self {
+        Self {
+            dir: dir.to_owned(),
+            passphrase,
+            salt: rand::random(),
+        }
+    }
+
+    /// Decrypt the directory with the passphrase.
+    ///
+    /// If the directory was left unencrypted, such as after a crash, it is newer than the
+    /// encrypted copy so is kept as it is once the passphrase has been checked.
+    pub fn open(dir: &Path, passphrase: SecretString) -> Result<Self, VaultError> {
+        let path = Self::archive(dir);
+        let decrypt = |error| VaultError::Decrypt {
+            path: path.clone(),
+            error,
+        };
+        let file = File::open(&path).map_err(|e| decrypt(e.into()))?;
+        let decryptor = age::Decryptor::new_buffered(BufReader::new(file)).map_err(decrypt)?;
+        let identity = age::scrypt::Identity::new(passphrase.clone());
+        let reader = decryptor
+            .decrypt(std::iter::once(&identity as &dyn age::Identity))
+            .map_err(|error| match error {
+                age::DecryptError::DecryptionFailed | age::DecryptError::NoMatchingKeys => {
+                    VaultError::WrongPassphrase
+                }
+                error => decrypt(error),
+            })?;
+
+        if dir.exists() {
+            warn!(dir:?; "Keeping the directory left unencrypted from the last run");
+        } else {
+            // unpack alongside so that a failure part way doesn't leave a partial directory
+            let unpacking = dir.with_extension("unpacking");
+            let unpack = |error| VaultError::Unpack {
+                path: path.clone(),
+                error,
+            };
+            let _ = std::fs::remove_dir_all(&unpacking);
+            tar::Archive::new(reader)
+                .unpack(&unpacking)
+                .map_err(unpack)?;
+            std::fs::rename(&unpacking, dir).map_err(unpack)?;
+            info!(dir:?; "Decrypted directory");
+        }
+        Ok(Self {
+            dir: dir.to_owned(),
+            passphrase,
+            salt: rand::random(),
+        })
+    }
+
+    /// Encrypt the directory again, removing the unencrypted copy.
+    pub fn seal(&self) -> Result<(), VaultError> {
+        let path = Self::archive(&self.dir);
+        let encrypt = |error| VaultError::Encrypt {
+            path: path.clone(),
+            error,
+        };
+        if !self.dir.exists() {
+            return Ok(());
+        }
+        // write alongside so that the old copy stays until the new one is complete
+        let sealing = self.dir.with_extension("age.sealing");
+        let file = File::create(&sealing).map_err(encrypt)?;
+        let encryptor = age::Encryptor::with_user_passphrase(self.passphrase.clone());
+        let writer = encryptor
+            .wrap_output(BufWriter::new(file))
+            .map_err(encrypt)?;
+        let mut archive = tar::Builder::new(writer);
+        archive.append_dir_all(".", &self.dir).map_err(encrypt)?;
+        let writer = archive.into_inner().map_err(encrypt)?;
+        writer
+            .finish()
+            .and_then(|mut file| std::io::Write::flush(&mut file))
+            .map_err(encrypt)?;
+        std::fs::rename(&sealing, &path).map_err(encrypt)?;
+        std::fs::remove_dir_all(&self.dir).map_err(encrypt)?;
+        info!(dir:? = self.dir; "Encrypted directory");
+        Ok(())
+    }
+
+    /// Encrypt a different directory with the same passphrase.
+    pub fn relocate(&mut self, dir: &Path) {
+        self.dir = dir.to_owned();
+    }
+
+    /// Whether the passphrase is the one the directory is encrypted with.
+    ///
+    /// Digests of the same length are compared byte by byte, so how long it takes gives away
+    /// neither how much of the passphrase was right nor how long it is.
+    pub fn check(&self, passphrase: &str) -> bool {
+        let expected = self.digest(self.passphrase.expose_secret());
+        let given = self.digest(passphrase);
+        let difference = expected
+            .iter()
+            .zip(given)
+            .fold(0, |difference, (a, b)| difference | (a ^ b));
+        std::hint::black_box(difference) == 0
+    }
+
+    fn digest(&self, passphrase: &str) -> [u8; 32] {
+        Sha256::new()
+            .chain_update(self.salt)
+            .chain_update(passphrase)
+            .finalize()
+            .into()
+    }
+}
+
+/// Seals the vault when dropped, so that the directory is encrypted again however chatters stops,
+/// including returning early on an error or unwinding from a panic.
+#[derive(Debug)]
+pub struct SealOnDrop(Vault);
+
+impl SealOnDrop {
+    pub fn new(vault: Vault) -> Self {
+        Self(vault)
+    }
+}
+
+impl Deref for SealOnDrop {
+    type Target = Vault;
+
+    fn deref(&self) -> &Vault {
+        &self.0
+    }
+}
+
+impl DerefMut for SealOnDrop {
+    fn deref_mut(&mut self) -> &mut Vault {
+        &mut self.0
+    }
+}
+
+impl Drop for SealOnDrop {
+    fn drop(&mut self) {
+        if let Err(error) = self.0.seal() {
+            eprintln!("{error}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vault() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("backend");
+        std::fs::create_dir_all(dir.join("attachments")).unwrap();
+        std::fs::write(dir.join("session.json"), "secret").unwrap();
+        std::fs::write(dir.join("attachments/cat.jpg"), "meow").unwrap();
+
+        let vault = Vault::create(&dir, SecretString::from("hunter2"));
+        vault.seal().unwrap();
+        assert!(!dir.exists());
+        assert!(Vault::exists(&dir));
+
+        let wrong = Vault::open(&dir, SecretString::from("hunter3"));
+        assert!(matches!(wrong, Err(VaultError::WrongPassphrase)));
+        let vault = Vault::open(&dir, SecretString::from("hunter2")).unwrap();
+        assert!(vault.check("hunter2"));
+        assert!(!vault.check("hunter"));
+        assert!(!vault.check("hunter22"));
+        assert!(!vault.check(""));
+        assert_eq!(
+            std::fs::read_to_string(dir.join("attachments/cat.jpg")).unwrap(),
+            "meow"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("session.json")).unwrap(),
+            "secret"
+        );
+    }
+
+    #[test]
+    fn test_seal_on_drop() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("backend");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("mutes.json"), "[]").unwrap();
+
+        let result = std::panic::catch_unwind(|| {
+            let _vault = SealOnDrop::new(Vault::create(&dir, SecretString::from("hunter2")));
+            panic!("crashed");
+        });
+        assert!(result.is_err());
+        assert!(!dir.exists());
+        assert!(Vault::exists(&dir));
+    }
+}
@@ -0,0 +1,54 @@
+use chatters_lib::log::init_logger;
+use chatters_lib::util::{self, Options};
+use chatters_local::Local;
+use clap::Parser;
+use directories::ProjectDirs;
+use std::path::PathBuf;
+
+/// Replay a session recorded with `--record` into the UI, using the local backend.
+#[derive(Debug, Parser)]
+#[clap(name = "chatters-replay")]
+pub struct Arguments {
+    /// The journal file to replay.
+    journal: PathBuf,
+
+    #[clap(long)]
+    config_file: Option<PathBuf>,
+
+    #[cfg(feature = "tracing")]
+    #[clap(flatten)]
+    trace: chatters_lib::telemetry::TraceArgs,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let project_dirs = ProjectDirs::from("net", "jeffas", "chatters-replay").unwrap();
+    let data_local_dir = project_dirs.data_local_dir();
+
+    let log_path = data_local_dir.join("logs.log");
+    init_logger(log_path);
+
+    let args = Arguments::parse();
+
+    #[cfg(feature = "tracing")]
+    let _tracing_guard = chatters_lib::telemetry::init_tracing(&args.trace);
+
+    let config_file = match args.config_file {
+        Some(cf) => cf,
+        None => project_dirs.config_local_dir().join("config.toml"),
+    };
+
+    let opts = Options {
+        device_name: "chatters-replay".to_owned(),
+        data_local_dir: data_local_dir.to_owned(),
+        config_file,
+        app_name: "chatters-replay".to_owned(),
+        journal_file: None,
+        replay_file: Some(args.journal),
+        ipc_socket: None,
+    };
+
+    util::run::<Local>(opts).await;
+
+    Ok(())
+}
@@ -0,0 +1,211 @@
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use chatters_lib::backends::{
+    Contact, ContactId, Message, MessageAttachment, MessageContent, Verification,
+};
+
+/// The fixture used when there isn't one in the data directory.
+const DEFAULT_FIXTURE: &str = include_str!("../fixture.toml");
+
+/// The fixture given on the command line, used in place of the one in the data directory.
+static FIXTURE_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Load the contacts and messages from this file rather than the data directory.
+pub fn use_fixture(path: PathBuf) {
+    let _ = FIXTURE_PATH.set(path);
+}
+
+/// Contacts and messages for the local backend to pretend to have, see `fixture.toml` for the
+/// default one.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fixture {
+    /// The id of the contact that is the user.
+    #[serde(rename = "self")]
+    pub self_id: String,
+    /// How long contacts take to echo back what is sent to them, if they do.
+    #[serde(default)]
+    pub echo_delay_ms: Option<u64>,
+    #[serde(default)]
+    pub contacts: Vec<FixtureContact>,
+    /// Messages from before starting.
+    #[serde(default)]
+    pub messages: Vec<FixtureMessage>,
+    /// Messages arriving one after another while syncing.
+    #[serde(default)]
+    pub incoming: Vec<FixtureMessage>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixtureContact {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub address: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub group: bool,
+    /// The ids of the members of a group.
+    #[serde(default)]
+    pub members: Vec<String>,
+    #[serde(default)]
+    pub verified: bool,
+    #[serde(default)]
+    pub encrypted: bool,
+    /// The id of who invited the user, for a conversation waiting for them to join.
+    #[serde(default)]
+    pub invited_by: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixtureMessage {
+    /// The id of the contact or group the message is in.
+    pub contact: String,
+    pub sender: String,
+    /// How many seconds before starting it was sent.
+    #[serde(default)]
+    pub ago: u64,
+    /// How many seconds after the previous incoming message it arrives.
+    #[serde(default)]
+    pub after: u64,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub attachments: Vec<FixtureAttachment>,
+    /// An emoji to react with instead of sending text.
+    #[serde(default)]
+    pub reaction: Option<String>,
+    /// The `ago` of the message reacted to.
+    #[serde(default)]
+    pub reaction_to: Option<u64>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixtureAttachment {
+    pub name: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// A file to give when the attachment is downloaded, relative to the fixture, otherwise a
+    /// placeholder is written.
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+}
+
+impl Fixture {
+    /// Load the fixture given on the command line, or the one in the directory, falling back to
+    /// the default one.
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let given = FIXTURE_PATH.get();
+        let path = given.cloned().unwrap_or_else(|| dir.join("fixture.toml"));
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(error) if given.is_none() && error.kind() == std::io::ErrorKind::NotFound => {
+                DEFAULT_FIXTURE.to_owned()
+            }
+            Err(error) => return Err(format!("Failed to read fixture {path:?}: {error}")),
+        };
+        let mut fixture: Self =
+            toml::from_str(&content).map_err(|e| format!("Invalid fixture {path:?}: {e}"))?;
+        let base = path.parent().unwrap_or(dir);
+        for message in fixture.messages.iter_mut().chain(&mut fixture.incoming) {
+            for attachment in &mut message.attachments {
+                if let Some(file) = &mut attachment.file {
+                    *file = base.join(&*file);
+                }
+            }
+        }
+        Ok(fixture)
+    }
+
+    pub fn contact_id(&self, id: &str) -> ContactId {
+        let group = self.contacts.iter().any(|c| c.id == id && c.group);
+        if group {
+            ContactId::Group(id.as_bytes().to_vec())
+        } else {
+            ContactId::User(id.as_bytes().to_vec())
+        }
+    }
+
+    pub fn contact(&self, contact: &FixtureContact) -> Contact {
+        Contact {
+            id: self.contact_id(&contact.id),
+            name: contact.name.clone(),
+            address: contact.address.clone(),
+            last_message_timestamp: None,
+            description: contact.description.clone(),
+            muted_until: None,
+            verification: if contact.verified {
+                Verification::Verified
+            } else {
+                Verification::Unverified
+            },
+            members: contact.group.then_some(contact.members.len()),
+            encrypted: contact.encrypted,
+        }
+    }
+
+    /// Make a message from the fixture, adding its attachments to those that can be downloaded.
+    ///
+    /// Reactions find what they react to by its `ago` from when the fixture was loaded.
+    pub fn message(
+        &self,
+        message: &FixtureMessage,
+        timestamp: u64,
+        loaded_at: u64,
+        attachments: &mut Vec<FixtureAttachment>,
+    ) -> Message {
+        let content = match (&message.reaction, message.reaction_to) {
+            (Some(reaction), Some(reaction_to)) => {
+                let message_author = self
+                    .messages
+                    .iter()
+                    .find(|m| m.contact == message.contact && m.ago == reaction_to)
+                    .map_or(&message.sender, |m| &m.sender);
+                MessageContent::Reaction {
+                    message_author: message_author.as_bytes().to_vec(),
+                    timestamp: ago(loaded_at, reaction_to),
+                    reaction: reaction.clone(),
+                    remove: false,
+                }
+            }
+            _ => MessageContent::Text {
+                text: message.text.clone(),
+                attachments: message
+                    .attachments
+                    .iter()
+                    .map(|attachment| {
+                        attachments.push(attachment.clone());
+                        MessageAttachment {
+                            name: attachment.name.clone(),
+                            size: attachment.size,
+                            index: attachments.len() - 1,
+                            path: None,
+                            content_type: attachment.content_type.clone(),
+                        }
+                    })
+                    .collect(),
+                mentions: Vec::new(),
+                formatting: Vec::new(),
+            },
+        };
+        Message {
+            timestamp,
+            sender: message.sender.as_bytes().to_vec(),
+            contact_id: self.contact_id(&message.contact),
+            content,
+            quote: None,
+        }
+    }
+}
+
+/// The timestamp some seconds before another.
+pub fn ago(timestamp: u64, seconds: u64) -> u64 {
+    timestamp.saturating_sub(seconds * 1000)
+}
@@ -1,127 +1,430 @@
-use std::path::PathBuf;
+mod fixture;
+
+use std::collections::BTreeMap;
+use std::ops::RangeBounds as _;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use chatters_lib::backends::Contact;
+use chatters_lib::backends::ContactDetails;
+use chatters_lib::backends::Error;
+use chatters_lib::backends::Invite;
+use chatters_lib::backends::Limits;
+use chatters_lib::backends::LoadProgress;
 use chatters_lib::backends::Message;
 use chatters_lib::backends::MessageContent;
+use chatters_lib::backends::Progress;
+use chatters_lib::backends::Receipt;
 use chatters_lib::backends::Result;
+use chatters_lib::backends::Setup;
+use chatters_lib::backends::SetupQuestion;
 use chatters_lib::backends::{timestamp, Backend, ContactId, Quote};
 use chatters_lib::message::FrontendMessage;
+use chatters_lib::pins::PinStore;
+use fixture::{ago, Fixture, FixtureAttachment, FixtureContact};
+use futures::channel::mpsc;
+
+pub use fixture::use_fixture;
 
+/// A pretend backend with the contacts and messages from a fixture, for trying out and developing
+/// the ui without an account.
 #[derive(Clone)]
-pub struct Local {}
+pub struct Local {
+    pins: PinStore,
+    dir: PathBuf,
+    state: Arc<Mutex<State>>,
+    /// Where messages arriving while running go, set once syncing.
+    updates: Arc<Mutex<Option<mpsc::UnboundedSender<FrontendMessage>>>>,
+}
+
+struct State {
+    fixture: Fixture,
+    /// When the fixture was loaded, which the times in it are from.
+    loaded_at: u64,
+    /// Every message in every conversation, oldest first.
+    messages: Vec<Message>,
+    /// The attachments of the fixture's messages, by their index.
+    attachments: Vec<FixtureAttachment>,
+}
+
+impl Local {
+    fn own_id(&self) -> Vec<u8> {
+        self.state
+            .lock()
+            .unwrap()
+            .fixture
+            .self_id
+            .as_bytes()
+            .to_vec()
+    }
+
+    fn contacts(&self, groups: bool) -> Vec<Contact> {
+        let state = self.state.lock().unwrap();
+        state
+            .fixture
+            .contacts
+            .iter()
+            .filter(|c| c.group == groups && c.invited_by.is_none())
+            .map(|c| {
+                let mut contact = state.fixture.contact(c);
+                contact.last_message_timestamp = state
+                    .messages
+                    .iter()
+                    .rev()
+                    .find(|m| m.contact_id == contact.id)
+                    .map(|m| m.timestamp);
+                contact
+            })
+            .collect()
+    }
+
+    /// Change a group of the fixture, only until the next run which starts from the fixture again.
+    fn change_group(
+        &self,
+        contact_id: &ContactId,
+        change: impl FnOnce(&mut FixtureContact),
+    ) -> Result<()> {
+        let ContactId::Group(id) = contact_id else {
+            return Err(Error::Failure(
+                "Failed to change group".to_owned(),
+                "not a group".to_owned(),
+            ));
+        };
+        let mut state = self.state.lock().unwrap();
+        let group = state
+            .fixture
+            .contacts
+            .iter_mut()
+            .find(|c| c.group && c.id.as_bytes() == id.as_slice())
+            .ok_or_else(|| {
+                Error::Failure(
+                    "Failed to change group".to_owned(),
+                    "unknown group".to_owned(),
+                )
+            })?;
+        change(group);
+        Ok(())
+    }
+
+    /// Have a contact send back what was sent to them after the fixture's delay, as if they
+    /// replied.
+    fn echo(&self, sent: &Message) {
+        let self_id = self.own_id();
+        let Some(delay) = self.state.lock().unwrap().fixture.echo_delay_ms else {
+            return;
+        };
+        // groups and notes to self have no one to reply
+        let ContactId::User(contact) = &sent.contact_id else {
+            return;
+        };
+        if *contact == self_id || !matches!(sent.content, MessageContent::Text { .. }) {
+            return;
+        }
+        let Some(updates) = self.updates.lock().unwrap().clone() else {
+            return;
+        };
+        let mut echo = sent.clone();
+        echo.sender = contact.clone();
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            echo.timestamp = timestamp();
+            state.lock().unwrap().messages.push(echo.clone());
+            let _ = updates.unbounded_send(FrontendMessage::NewMessage { message: echo });
+        });
+    }
+}
 
 impl Backend for Local {
-    async fn load(_path: &std::path::Path) -> Result<Self> {
-        Ok(Self {})
+    const NAME: &'static str = "Local";
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn load(path: &Path, _progress: LoadProgress) -> Result<Self> {
+        let fixture = Fixture::load(path).map_err(|e| Error::Failure(e, String::new()))?;
+        let loaded_at = timestamp();
+        let mut attachments = Vec::new();
+        let mut messages: Vec<_> = fixture
+            .messages
+            .iter()
+            .map(|m| fixture.message(m, ago(loaded_at, m.ago), loaded_at, &mut attachments))
+            .collect();
+        messages.sort_by_key(|m| m.timestamp);
+        Ok(Self {
+            pins: PinStore::load(&path.join("pins.json")),
+            dir: path.to_owned(),
+            state: Arc::new(Mutex::new(State {
+                fixture,
+                loaded_at,
+                messages,
+                attachments,
+            })),
+            updates: Arc::default(),
+        })
+    }
+
+    fn setup_questions() -> Vec<SetupQuestion> {
+        Vec::new()
     }
 
     async fn link(
-        _path: &std::path::Path,
-        _device_name: &str,
-        _provisioning_link_tx: futures::channel::oneshot::Sender<url::Url>,
+        _path: &Path,
+        _setup: &Setup,
+        _provisioning_link_tx: futures::channel::oneshot::Sender<String>,
     ) -> Result<Self> {
         unimplemented!()
     }
 
     async fn background_sync(
         &mut self,
-        _ba_tx: futures::channel::mpsc::UnboundedSender<FrontendMessage>,
+        ba_tx: mpsc::UnboundedSender<FrontendMessage>,
     ) -> Result<()> {
+        *self.updates.lock().unwrap() = Some(ba_tx.clone());
+        let incoming = self.state.lock().unwrap().fixture.incoming.clone();
+        for message in incoming {
+            tokio::time::sleep(Duration::from_secs(message.after)).await;
+            let message = {
+                let mut state = self.state.lock().unwrap();
+                let State {
+                    fixture,
+                    loaded_at,
+                    messages,
+                    attachments,
+                } = &mut *state;
+                let message = fixture.message(&message, timestamp(), *loaded_at, attachments);
+                messages.push(message.clone());
+                message
+            };
+            if ba_tx
+                .unbounded_send(FrontendMessage::NewMessage { message })
+                .is_err()
+            {
+                return Ok(());
+            }
+        }
         std::future::pending::<()>().await;
         Ok(())
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
     async fn users(&self) -> Result<Vec<Contact>> {
-        Ok(vec![Contact {
-            id: ContactId::User(vec![0]),
-            name: "Self".to_owned(),
-            address: "no address".to_owned(),
-            last_message_timestamp: None,
-            description: "some description".to_owned(),
-        }])
+        Ok(self.contacts(false))
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
     async fn groups(&self) -> Result<Vec<Contact>> {
-        Ok(Vec::new())
+        Ok(self.contacts(true))
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
     async fn messages(
         &mut self,
-        _contact: ContactId,
-        _start_ts: std::ops::Bound<u64>,
-        _end_ts: std::ops::Bound<u64>,
+        contact: ContactId,
+        start_ts: std::ops::Bound<u64>,
+        end_ts: std::ops::Bound<u64>,
     ) -> Result<Vec<Message>> {
-        let now = timestamp();
-        let mut msgs = vec![
-            Message {
-                timestamp: now - 100,
-                sender: vec![0],
-                contact_id: ContactId::User(vec![0]),
-                content: MessageContent::Text {
-                    text: "Message 1".to_owned(),
-                    attachments: Vec::new(),
-                },
-                quote: None,
-            },
-            Message {
-                timestamp: now - 90,
-                sender: vec![0],
-                contact_id: ContactId::User(vec![0]),
-                content: MessageContent::Text {
-                    text: "Message 2".to_owned(),
-                    attachments: Vec::new(),
-                },
-                quote: None,
-            },
-            Message {
-                timestamp: now - 80,
-                sender: vec![0],
-                contact_id: ContactId::User(vec![0]),
-                content: MessageContent::Reaction {
-                    message_author: vec![0],
-                    timestamp: now - 100,
-                    reaction: "🚀".to_owned(),
-                    remove: false,
-                },
-                quote: None,
-            },
-        ];
-        for i in (0..50).rev() {
-            msgs.push(Message {
-                timestamp: now - i,
-                sender: vec![0],
-                contact_id: ContactId::User(vec![0]),
-                content: MessageContent::Text {
-                    text: format!("msg {i}"),
-                    attachments: Vec::new(),
-                },
-                quote: None,
-            });
-        }
-        Ok(msgs)
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .messages
+            .iter()
+            .filter(|m| m.contact_id == contact && (start_ts, end_ts).contains(&m.timestamp))
+            .cloned()
+            .collect())
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, body, quoted)))]
     async fn send_message(
         &mut self,
         contact: ContactId,
         body: MessageContent,
-        _quoted: Option<&Quote>,
+        quoted: Option<&Quote>,
     ) -> Result<Message> {
-        let msg = Message {
+        let message = Message {
             timestamp: timestamp(),
-            sender: vec![0],
+            sender: self.own_id(),
             contact_id: contact,
             content: body,
-            quote: None,
+            quote: quoted.cloned(),
         };
-        Ok(msg)
+        self.state.lock().unwrap().messages.push(message.clone());
+        self.echo(&message);
+        Ok(message)
     }
 
     async fn self_id(&self) -> Vec<u8> {
-        vec![0]
+        self.own_id()
+    }
+
+    fn limits(&self) -> Limits {
+        Limits::default()
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn download_attachment(
+        &self,
+        attachment_index: usize,
+        progress: Progress,
+    ) -> Result<PathBuf> {
+        let attachment = self
+            .state
+            .lock()
+            .unwrap()
+            .attachments
+            .get(attachment_index)
+            .cloned()
+            .ok_or_else(|| {
+                Error::Failure(
+                    format!("No attachment {attachment_index} in the fixture"),
+                    String::new(),
+                )
+            })?;
+        let path = match attachment.file {
+            Some(file) => file,
+            None => {
+                let path = self.dir.join("attachments").join(&attachment.name);
+                std::fs::create_dir_all(self.dir.join("attachments"))
+                    .and_then(|()| {
+                        std::fs::write(
+                            &path,
+                            format!("{} from the local backend's fixture\n", attachment.name),
+                        )
+                    })
+                    .map_err(|e| {
+                        Error::Failure("Failed to write attachment".to_owned(), e.to_string())
+                    })?;
+                path
+            }
+        };
+        progress.report(attachment.size, attachment.size);
+        Ok(path)
     }
 
-    async fn download_attachment(&self, _attachment_index: usize) -> Result<PathBuf> {
-        Ok(PathBuf::new())
+    async fn pin_message(
+        &mut self,
+        contact_id: ContactId,
+        timestamp: u64,
+        pinned: bool,
+    ) -> Result<()> {
+        self.pins
+            .set_pinned(&contact_id, timestamp, pinned)
+            .map_err(|e| Error::Failure("Failed to save pinned messages".to_owned(), e.to_string()))
+    }
+
+    async fn delete_message(&mut self, contact_id: ContactId, timestamp: u64) -> Result<()> {
+        // only until the next run, which starts from the fixture again
+        self.state
+            .lock()
+            .unwrap()
+            .messages
+            .retain(|m| m.contact_id != contact_id || m.timestamp != timestamp);
+        Ok(())
+    }
+
+    async fn pinned_messages(&self, contact_id: ContactId) -> Result<Vec<u64>> {
+        Ok(self.pins.pinned(&contact_id))
+    }
+
+    async fn find_user(&mut self, query: String) -> Result<Contact> {
+        let query = query.to_lowercase();
+        self.contacts(false)
+            .into_iter()
+            .find(|c| c.name.to_lowercase() == query || c.address == query)
+            .ok_or_else(|| {
+                Error::Failure(format!("No contact {query} in the fixture"), String::new())
+            })
+    }
+
+    async fn message_receipts(
+        &self,
+        _contact_id: ContactId,
+        _timestamp: u64,
+    ) -> Result<Vec<Receipt>> {
+        Ok(Vec::new())
+    }
+
+    async fn contact_details(&self, _contact_id: ContactId) -> Result<ContactDetails> {
+        Ok(ContactDetails::default())
+    }
+
+    async fn verify_device(&mut self) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support verifying devices", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn confirm_verification(&mut self, _confirmed: bool) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support verifying devices", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn invites(&self) -> Result<Vec<Invite>> {
+        let state = self.state.lock().unwrap();
+        let fixture = &state.fixture;
+        Ok(fixture
+            .contacts
+            .iter()
+            .filter_map(|c| {
+                let inviter = c.invited_by.as_ref()?;
+                Some(Invite {
+                    contact_id: fixture.contact_id(&c.id),
+                    name: c.name.clone(),
+                    inviter: fixture
+                        .contacts
+                        .iter()
+                        .find(|i| i.id == *inviter)
+                        .map_or_else(|| inviter.clone(), |i| i.name.clone()),
+                })
+            })
+            .collect())
+    }
+
+    async fn answer_invite(&mut self, contact_id: ContactId, accept: bool) -> Result<()> {
+        // only until the next run, which starts from the fixture again
+        let mut state = self.state.lock().unwrap();
+        let fixture = &mut state.fixture;
+        let index = fixture
+            .contacts
+            .iter()
+            .position(|c| c.invited_by.is_some() && fixture.contact_id(&c.id) == contact_id)
+            .ok_or_else(|| {
+                Error::Failure("Failed to answer invite".to_owned(), "no invite".to_owned())
+            })?;
+        if accept {
+            fixture.contacts[index].invited_by = None;
+        } else {
+            fixture.contacts.remove(index);
+        }
+        Ok(())
+    }
+
+    async fn set_group_title(&mut self, contact_id: ContactId, title: String) -> Result<()> {
+        self.change_group(&contact_id, |group| group.name = title)
+    }
+
+    async fn set_group_description(
+        &mut self,
+        contact_id: ContactId,
+        description: String,
+    ) -> Result<()> {
+        self.change_group(&contact_id, |group| group.description = description)
+    }
+
+    async fn request_contact_sync(&mut self) -> Result<()> {
+        // the fixture has nothing more to sync, loading it again is enough
+        Ok(())
+    }
+
+    async fn conversation_summaries(&self) -> Result<Vec<Message>> {
+        let state = self.state.lock().unwrap();
+        let mut latest = BTreeMap::new();
+        for message in &state.messages {
+            if matches!(message.content, MessageContent::Text { .. }) {
+                latest.insert(message.contact_id.clone(), message.clone());
+            }
+        }
+        Ok(latest.into_values().collect())
     }
 }
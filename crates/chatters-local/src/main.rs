@@ -13,6 +13,29 @@ pub struct Arguments {
 
     #[clap(long)]
     config_file: Option<PathBuf>,
+
+    /// Record messages between the frontend and backend, and the keys pressed, to this file for
+    /// debugging.
+    #[clap(long, alias = "journal")]
+    record: Option<PathBuf>,
+
+    /// Replay a session recorded with `--record` into the UI, without needing the account it was
+    /// recorded with.
+    #[clap(long)]
+    replay: Option<PathBuf>,
+
+    /// Pretend to have the contacts and messages in this fixture, see `fixture.toml` for the
+    /// format, rather than the one in the data directory or the default one.
+    #[clap(long)]
+    fixture: Option<PathBuf>,
+
+    /// Listen for control requests from external tools on this unix socket.
+    #[clap(long)]
+    ipc_socket: Option<PathBuf>,
+
+    #[cfg(feature = "tracing")]
+    #[clap(flatten)]
+    trace: chatters_lib::telemetry::TraceArgs,
 }
 
 #[tokio::main]
@@ -25,16 +48,26 @@ async fn main() -> anyhow::Result<()> {
 
     let args = Arguments::parse();
 
+    #[cfg(feature = "tracing")]
+    let _tracing_guard = chatters_lib::telemetry::init_tracing(&args.trace);
+
     let config_file = match args.config_file {
         Some(cf) => cf,
         None => project_dirs.config_local_dir().join("config.toml"),
     };
 
+    if let Some(fixture) = args.fixture {
+        chatters_local::use_fixture(fixture);
+    }
+
     let opts = Options {
         device_name: args.device_name,
         data_local_dir: data_local_dir.to_owned(),
         config_file,
         app_name: "chatters-local".to_owned(),
+        journal_file: args.record,
+        replay_file: args.replay,
+        ipc_socket: args.ipc_socket,
     };
 
     util::run::<Local>(opts).await;
@@ -0,0 +1,204 @@
+//! Drive the ui against the local backend with scripted keys, checking the frames it draws.
+//!
+//! The clocks are pinned so that the frames are the same on each run: times shown relative to
+//! now come from [`pin_clock`], and tokio's clock is paused so that waiting for the backend to go
+//! quiet only ends once it has nothing left to run.
+
+use std::future::Future;
+use std::path::Path;
+use std::pin::{pin, Pin};
+use std::str::FromStr as _;
+use std::time::Duration;
+
+use chatters_lib::app::App;
+use chatters_lib::backend_actor::BackendActor;
+use chatters_lib::backends::{pin_clock, Backend as _, LoadProgress};
+use chatters_lib::config::{Config, DEFAULT_CONFIG};
+use chatters_lib::journal::{read_journal, Journal};
+use chatters_lib::keybinds::KeyEvents;
+use chatters_lib::message::{BackendMessage, FrontendMessage};
+use chatters_lib::tui::TuiState;
+use chatters_local::Local;
+use crossterm::event::{Event, KeyEvent, KeyEventKind, KeyEventState};
+use futures::channel::mpsc;
+use futures::future::{select, Either};
+use futures::StreamExt as _;
+use ratatui::backend::TestBackend;
+use ratatui::Terminal;
+
+struct Harness {
+    app: App<TestBackend>,
+    f_rx: mpsc::UnboundedReceiver<FrontendMessage>,
+    /// The backend actor isn't `Send`, so runs while waiting for its replies rather than in a
+    /// task of its own.
+    actor: Pin<Box<dyn Future<Output = ()>>>,
+    _dir: tempfile::TempDir,
+}
+
+impl Harness {
+    /// Start the ui with the default config and a fresh local backend, as on a first run.
+    async fn start() -> Self {
+        Self::start_recording(None).await
+    }
+
+    async fn start_recording(journal: Option<Journal>) -> Self {
+        pin_clock(START);
+        let dir = tempfile::tempdir().unwrap();
+        let (b_tx, b_rx) = mpsc::unbounded();
+        let (f_tx, f_rx) = mpsc::unbounded();
+        let backend = Local::load(dir.path(), LoadProgress::new(f_tx.clone()))
+            .await
+            .unwrap();
+        f_tx.unbounded_send(FrontendMessage::BackendLoaded {
+            self_id: backend.self_id().await,
+            limits: backend.limits(),
+        })
+        .unwrap();
+        let mut actor = BackendActor {
+            backend,
+            message_rx: b_rx,
+            message_tx: f_tx,
+            journal: journal.clone(),
+        };
+        let actor = Box::pin(async move { actor.run().await });
+
+        let mut app = new_app(b_tx, journal);
+        app.start();
+        let mut harness = Self {
+            app,
+            f_rx,
+            actor,
+            _dir: dir,
+        };
+        harness.loaded().await;
+        harness
+    }
+
+    /// The next message from the backend, or `None` once it has gone quiet.
+    async fn receive(&mut self) -> Option<FrontendMessage> {
+        let next = pin!(tokio::time::timeout(
+            Duration::from_millis(100),
+            self.f_rx.next()
+        ));
+        match select(self.actor.as_mut(), next).await {
+            Either::Left(((), _)) => panic!("Backend actor stopped"),
+            Either::Right((Ok(message), _)) => message,
+            Either::Right((Err(_), _)) => None,
+        }
+    }
+
+    /// Handle a message from the backend, drawing after it as the ui does.
+    fn handle(&mut self, message: FrontendMessage) {
+        self.app.backend_message(message);
+        self.app.draw();
+    }
+
+    /// Handle what the backend sends until the first conversation's messages have loaded.
+    async fn loaded(&mut self) {
+        loop {
+            let message = self
+                .receive()
+                .await
+                .expect("Backend went quiet before loading messages");
+            let loaded = matches!(message, FrontendMessage::LoadedMessages { .. });
+            self.handle(message);
+            if loaded {
+                break;
+            }
+        }
+        self.settle().await;
+    }
+
+    /// Handle what the backend sends until it goes quiet.
+    async fn settle(&mut self) {
+        while let Some(message) = self.receive().await {
+            self.handle(message);
+        }
+    }
+
+    /// Press keys written as in the keybinds config, returning whether the ui quit.
+    async fn press(&mut self, keys: &str) -> bool {
+        let mut quit = false;
+        for key in KeyEvents::from_str(keys).unwrap().0 {
+            quit |= self.app.user_event(Event::Key(KeyEvent {
+                code: key.code,
+                modifiers: key.modifiers,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::empty(),
+            }));
+            self.app.draw();
+        }
+        self.settle().await;
+        quit
+    }
+
+    fn screen(&self) -> String {
+        self.app.terminal.backend().to_string()
+    }
+}
+
+/// When the tests run, as far as the ui and backend can tell.
+const START: u64 = 1_700_000_000_000;
+
+fn new_app(
+    b_tx: mpsc::UnboundedSender<BackendMessage>,
+    journal: Option<Journal>,
+) -> App<TestBackend> {
+    let (config, _) = Config::parse(Path::new("config.toml"), DEFAULT_CONFIG).unwrap();
+    let tui_state = TuiState {
+        app_name: "chatters-local".to_owned(),
+        backend_name: Local::NAME.to_owned(),
+        config,
+        ..Default::default()
+    };
+    let terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+    App::new(terminal, b_tx, tui_state, journal)
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_start() {
+    let harness = Harness::start().await;
+    insta::assert_snapshot!(harness.screen());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_compose_and_command() {
+    let mut harness = Harness::start().await;
+    harness.press("Jihello<Esc>").await;
+    insta::assert_snapshot!("compose", harness.screen());
+    harness.press(":next-con").await;
+    insta::assert_snapshot!("command", harness.screen());
+    assert!(harness.press("<Esc>q").await);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_record_and_replay() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("session.jsonl");
+    let mut harness = Harness::start_recording(Some(Journal::new(&path).unwrap())).await;
+    harness.press("JJihello<Esc>:next-con").await;
+    let recorded = harness.screen();
+
+    // nothing answers the replay's requests, the recorded replies stand in for the backend
+    let (b_tx, _b_rx) = mpsc::unbounded();
+    let mut app = new_app(b_tx, None);
+    app.start();
+    assert!(!app.replay(read_journal(&path).unwrap()).await);
+    assert_eq!(app.terminal.backend().to_string(), recorded);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_delete_several_asks_first() {
+    let mut harness = Harness::start().await;
+    harness.press("ifirst<Esc><Enter>").await;
+    harness.press("isecond<Esc><Enter>").await;
+    harness.press("GVk:delete-message<Enter>").await;
+    insta::assert_snapshot!("confirm_delete", harness.screen());
+    harness.press("<Enter>").await;
+    let screen = harness.screen();
+    assert!(
+        !screen.contains("first") && !screen.contains("second"),
+        "{screen}"
+    );
+    assert!(screen.contains("Buy chalk"), "{screen}");
+}
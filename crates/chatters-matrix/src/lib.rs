@@ -1,34 +1,65 @@
 use chatters_lib::backends::timestamp;
 use chatters_lib::backends::Backend;
+use chatters_lib::backends::Connectivity;
 use chatters_lib::backends::Contact;
+use chatters_lib::backends::ContactDetails;
 use chatters_lib::backends::ContactId;
+use chatters_lib::backends::DeviceVerification;
 use chatters_lib::backends::Error;
+use chatters_lib::backends::FormatKind;
+use chatters_lib::backends::Formatting;
+use chatters_lib::backends::Invite;
+use chatters_lib::backends::Limits;
+use chatters_lib::backends::LoadProgress;
+use chatters_lib::backends::Mention;
 use chatters_lib::backends::Message;
 use chatters_lib::backends::MessageContent;
+use chatters_lib::backends::Progress;
 use chatters_lib::backends::Quote;
+use chatters_lib::backends::Receipt;
 use chatters_lib::backends::Result;
+use chatters_lib::backends::Setup;
+use chatters_lib::backends::SetupQuestion;
+use chatters_lib::backends::Verification;
+use chatters_lib::backoff::Backoff;
 use chatters_lib::message::FrontendMessage;
+use chatters_lib::secrets;
 
-use futures::future::select;
-use futures::{pin_mut, StreamExt as _};
-use log::debug;
-use matrix_sdk::crypto::{format_emojis, Emoji, SasState};
+use futures::channel::{mpsc, oneshot};
+use futures::{pin_mut, FutureExt as _, StreamExt as _};
+use log::{debug, info, warn};
+use matrix_sdk::crypto::{IdentityState, SasState};
 use matrix_sdk::encryption::verification::{
-    SasVerification, Verification, VerificationRequest, VerificationRequestState,
+    SasVerification, VerificationRequest, VerificationRequestState,
 };
-use matrix_sdk::matrix_auth::MatrixSession;
+use matrix_sdk::matrix_auth::{MatrixAuth, MatrixSession};
+use matrix_sdk::notification_settings::RoomNotificationMode;
 use matrix_sdk::room::MessagesOptions;
+use matrix_sdk::room::RoomMemberRole;
+use matrix_sdk::ruma::api::client::error::ErrorKind;
+use matrix_sdk::ruma::api::client::session::get_login_types::v3::LoginType;
+use matrix_sdk::ruma::events::reaction::ReactionEventContent;
+use matrix_sdk::ruma::events::receipt::{ReceiptThread, ReceiptType};
+use matrix_sdk::ruma::events::relation::Annotation;
 use matrix_sdk::ruma::events::room::message::RoomMessageEventContent;
-use matrix_sdk::ruma::RoomId;
+use matrix_sdk::ruma::events::room::pinned_events::RoomPinnedEventsEventContent;
+use matrix_sdk::ruma::events::Mentions;
+use matrix_sdk::ruma::{EventId, OwnedEventId, OwnedUserId, RoomId, UInt};
+use matrix_sdk::Room;
 use matrix_sdk::{config::SyncSettings, Client};
 use matrix_sdk::{LoopCtrl, RoomMemberships};
 use rand::distr::Alphanumeric;
 use rand::Rng;
 use serde::Deserialize;
 use serde::Serialize;
-use std::io::{self, Write as _};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write as _;
+use std::ops::{Bound, RangeBounds as _};
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 /// The data needed to re-build a client.
 #[derive(Debug, Serialize, Deserialize)]
@@ -39,7 +70,8 @@ struct ClientSession {
     /// The path of the database.
     db_path: PathBuf,
 
-    /// The passphrase of the database.
+    /// The passphrase of the database, left out of the file when kept in the system keyring.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     passphrase: String,
 }
 
@@ -49,36 +81,138 @@ struct FullSession {
     /// The data to re-build the client.
     client_session: ClientSession,
 
-    /// The Matrix user session.
-    user_session: MatrixSession,
+    /// The Matrix user session, left out of the file when kept in the system keyring.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_session: Option<MatrixSession>,
 
     /// The latest sync token.
     ///
-    /// It is only needed to persist it when using `Client::sync_once()` and we
-    /// want to make our syncs faster by not receiving all the initial sync
-    /// again.
+    /// Syncing from it on startup only receives what happened since the last run, rather than
+    /// the whole initial sync again.
     #[serde(skip_serializing_if = "Option::is_none")]
     sync_token: Option<String>,
+
+    /// The name the secrets are kept under in the system keyring, from the user and device ids.
+    ///
+    /// Sessions saved before this was added kept them under the path of the session file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    keyring_name: Option<String>,
+}
+
+/// The parts of the session kept in the system keyring, under the [`keyring_name`].
+#[derive(Serialize, Deserialize)]
+struct SessionSecrets {
+    passphrase: String,
+    user_session: MatrixSession,
 }
 
+const KEYRING_SERVICE: &str = "chatters-matrix";
+
+/// How many events of a room's history to load when opening it.
+const HISTORY_EVENTS: u32 = 200;
+
+/// How many of the latest events of a room to look through for the message to preview, past
+/// reactions and state changes.
+const SUMMARY_EVENTS: u32 = 20;
+
 #[derive(Clone)]
 pub struct Matrix {
     client: Client,
+    state: Arc<Mutex<State>>,
+    verifier: Verifier,
+    session_file: PathBuf,
+    /// Where the last run finished syncing, if it got that far.
+    sync_token: Option<String>,
+}
+
+/// Runs device verifications, showing their progress in the ui.
+#[derive(Clone, Default)]
+struct Verifier {
+    /// Where to report progress, set once syncing.
+    updates: Arc<Mutex<Option<mpsc::UnboundedSender<FrontendMessage>>>>,
+    /// Waiting for the user to say whether the emoji match.
+    confirmation: Arc<Mutex<Option<oneshot::Sender<bool>>>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The messages and reactions seen, so that reactions and redactions can find what they refer
+    /// to.
+    events: HashMap<OwnedEventId, SeenEvent>,
+    /// Ids of the events sent from here, which come back when syncing.
+    sent: HashSet<OwnedEventId>,
+}
+
+#[derive(Debug, Clone)]
+enum SeenEvent {
+    Message {
+        contact_id: ContactId,
+        timestamp: u64,
+        sender: Vec<u8>,
+    },
+    Reaction {
+        contact_id: ContactId,
+        sender: Vec<u8>,
+        message_author: Vec<u8>,
+        /// The timestamp of the message reacted to.
+        timestamp: u64,
+        reaction: String,
+    },
 }
 
 impl Backend for Matrix {
-    async fn load(path: &Path) -> Result<Self> {
+    const NAME: &'static str = "Matrix";
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn load(path: &Path, _progress: LoadProgress) -> Result<Self> {
         let session_file = get_session_file(path);
         if !session_file.exists() {
             return Err(Error::Unlinked);
         }
         // The session was serialized as JSON in a file.
-        let serialized_session = std::fs::read_to_string(session_file).unwrap();
+        let serialized_session = std::fs::read_to_string(&session_file)
+            .map_err(|e| Error::Failure("Failed to read the session".to_owned(), e.to_string()))?;
         let FullSession {
-            client_session,
+            mut client_session,
             user_session,
-            sync_token: _,
-        } = serde_json::from_str(&serialized_session).unwrap();
+            sync_token,
+            keyring_name,
+        } = serde_json::from_str(&serialized_session)
+            .map_err(|e| Error::Failure("Failed to parse the session".to_owned(), e.to_string()))?;
+        let user_session = match user_session {
+            // kept in plaintext, so move it into the keyring if that is wanted and works now
+            Some(user_session) => {
+                if !secrets::plaintext_fallback() {
+                    if let Err(error) = save_session(
+                        &session_file,
+                        &client_session,
+                        &user_session,
+                        sync_token.clone(),
+                    )
+                    .await
+                    {
+                        warn!(error:%; "Failed to move the session secrets into the system keyring");
+                    }
+                }
+                user_session
+            }
+            None => {
+                let legacy_name = session_file.to_string_lossy().into_owned();
+                let name = keyring_name.as_deref().unwrap_or(&legacy_name);
+                let secrets = load_session_secrets(name).await?;
+                client_session.passphrase = secrets.passphrase;
+                if keyring_name.is_none() {
+                    migrate_keyring_name(
+                        &session_file,
+                        &client_session,
+                        &secrets.user_session,
+                        sync_token.clone(),
+                    )
+                    .await;
+                }
+                secrets.user_session
+            }
+        };
 
         // Build the client with the previous settings from the session.
         let client = Client::builder()
@@ -86,123 +220,248 @@ impl Backend for Matrix {
             .sqlite_store(client_session.db_path, Some(&client_session.passphrase))
             .build()
             .await
-            .unwrap();
+            .map_err(|e| Error::Failure("Failed to open the database".to_owned(), e.to_string()))?;
 
         let user_id = user_session.meta.user_id.clone();
-        println!("Restoring session for {}…", user_id);
+        info!(user_id:%; "Restoring session");
 
         // Restore the Matrix user session.
-        client.restore_session(user_session).await.unwrap();
+        client.restore_session(user_session).await.map_err(|e| {
+            Error::Failure("Failed to restore the session".to_owned(), e.to_string())
+        })?;
 
         if !client.logged_in() {
             debug!("Client not logged in after load, getting to link again");
             return Err(Error::Unlinked);
         }
 
-        let this_user = client
-            .encryption()
-            .get_user_identity(&user_id)
-            .await
-            .unwrap()
-            .unwrap();
-        debug!(user:? = this_user, verified:? = this_user.is_verified(); "Loading");
-        if !this_user.is_verified() {
-            verify(&client).await;
-        }
+        Ok(Self {
+            client,
+            state: Arc::default(),
+            verifier: Verifier::default(),
+            session_file,
+            sync_token,
+        })
+    }
 
-        Ok(Self { client })
+    fn setup_questions() -> Vec<SetupQuestion> {
+        vec![
+            SetupQuestion {
+                key: "homeserver",
+                prompt: "Homeserver URL",
+                default: "https://matrix.org",
+                secret: false,
+            },
+            SetupQuestion {
+                key: "username",
+                prompt: "Username (leave empty for single sign-on)",
+                default: "",
+                secret: false,
+            },
+            SetupQuestion {
+                key: "password",
+                prompt: "Password",
+                default: "",
+                secret: true,
+            },
+        ]
     }
 
     async fn link(
         path: &Path,
-        _device_name: &str,
-        _provisioning_link_tx: futures::channel::oneshot::Sender<url::Url>,
+        setup: &Setup,
+        provisioning_link_tx: futures::channel::oneshot::Sender<String>,
     ) -> Result<Self> {
-        let (client, client_session) = build_client(path).await.unwrap();
+        let (client, client_session) = build_client(path, setup.answer("homeserver"))
+            .await
+            .map_err(|e| {
+                Error::Failure("Failed to check the homeserver".to_owned(), e.to_string())
+            })?;
         let matrix_auth = client.matrix_auth();
+        let device_name = &setup.device_name;
+        let username = setup.answer("username");
 
-        loop {
-            print!("\nUsername: ");
-            io::stdout().flush().expect("Unable to write to stdout");
-            let mut username = String::new();
-            io::stdin()
-                .read_line(&mut username)
-                .expect("Unable to read user input");
-            username = username.trim().to_owned();
-
-            print!("Password: ");
-            io::stdout().flush().expect("Unable to write to stdout");
-            let mut password = String::new();
-            io::stdin()
-                .read_line(&mut password)
-                .expect("Unable to read user input");
-            password = password.trim().to_owned();
-
-            match matrix_auth
-                .login_username(&username, &password)
-                .initial_device_display_name("chatters-matrix")
-                .await
-            {
-                Ok(_) => {
-                    println!("Logged in as {username}");
-                    break;
-                }
-                Err(error) => {
-                    println!("Error logging in: {error}");
-                    println!("Please try again\n");
-                }
-            }
+        let supports_sso = matrix_auth
+            .get_login_types()
+            .await
+            .is_ok_and(|login_types| supports_sso(&login_types.flows));
+        if username.is_empty() && supports_sso {
+            login_sso(&matrix_auth, device_name, provisioning_link_tx).await?;
+        } else {
+            login_password(
+                &matrix_auth,
+                device_name,
+                username,
+                setup.answer("password"),
+            )
+            .await?;
         }
 
         // Persist the session to reuse it later.
-        // This is not very secure, for simplicity. If the system provides a way of
-        // storing secrets securely, it should be used instead.
         // Note that we could also build the user session from the login response.
         let user_session = matrix_auth
             .session()
             .expect("A logged-in client should have a session");
-        let serialized_session = serde_json::to_string(&FullSession {
-            client_session,
-            user_session,
-            sync_token: None,
-        })
-        .unwrap();
-
         let session_file = get_session_file(path);
-        std::fs::write(&session_file, serialized_session).unwrap();
+        save_session(&session_file, &client_session, &user_session, None).await?;
 
         debug!(
             "Matrix session persisted in {}",
             session_file.to_string_lossy()
         );
 
-        verify(&client).await;
-
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            state: Arc::default(),
+            verifier: Verifier::default(),
+            session_file,
+            sync_token: None,
+        })
     }
 
     async fn background_sync(
         &mut self,
-        _ba_tx: futures::channel::mpsc::UnboundedSender<FrontendMessage>,
+        ba_tx: mpsc::UnboundedSender<FrontendMessage>,
     ) -> Result<()> {
-        let sync_settings = SyncSettings::default();
-        self.client
-            .sync_with_result_callback(sync_settings, |sync_result| async move {
-                let response = sync_result?;
-                debug!(response:?; "Got sync response");
-
-                // We persist the token each time to be able to restore our session
-                // persist_sync_token(session_file, response.next_batch)
-                //     .await
-                //     .map_err(|err| Error::UnknownError(err.into()))?;
+        *self.verifier.updates.lock().unwrap() = Some(ba_tx.clone());
+        // now that the ui can show the emoji to compare
+        if !own_identity_verified(&self.client).await {
+            if let Err(error) = self.verify_device().await {
+                warn!(error:%; "Failed to start verifying device");
+            }
+        }
 
-                Ok(LoopCtrl::Continue)
-            })
-            .await
-            .unwrap();
-        Ok(())
+        let sync_token = match self.sync_token.take() {
+            // anything since the last run was missed while offline, so report it as new
+            Some(sync_token) => sync_token,
+            None => {
+                // earlier messages are loaded with the rest of the history rather than as new ones
+                let response = self
+                    .client
+                    .sync_once(SyncSettings::default())
+                    .await
+                    .map_err(|e| Error::Failure("Failed to sync".to_owned(), e.to_string()))?;
+                persist_sync_token(&self.session_file, &response.next_batch);
+                response.next_batch
+            }
+        };
+        // where to carry on from after reconnecting
+        let sync_token = Arc::new(Mutex::new(sync_token));
+        let online = Arc::new(AtomicBool::new(true));
+        let mut backoff = Backoff::default();
+        loop {
+            let sync_settings = SyncSettings::default().token(sync_token.lock().unwrap().clone());
+            let result = self
+                .client
+                .sync_with_result_callback(sync_settings, |sync_result| {
+                    let client = self.client.clone();
+                    let state = self.state.clone();
+                    let ba_tx = ba_tx.clone();
+                    let session_file = self.session_file.clone();
+                    let sync_token = sync_token.clone();
+                    let online = online.clone();
+                    async move {
+                        let response = sync_result?;
+                        debug!(response:?; "Got sync response");
+                        if !online.swap(true, Ordering::Relaxed) {
+                            ba_tx
+                                .unbounded_send(FrontendMessage::Connectivity {
+                                    connectivity: Connectivity::Online,
+                                })
+                                .unwrap();
+                        }
+
+                        for (room_id, update) in response.rooms.join {
+                            let Some(room) = client.get_room(&room_id) else {
+                                continue;
+                            };
+                            let contact_id = contact_id(&room).await;
+                            for event in update.timeline.events {
+                                let Ok(event) = event.raw().deserialize_as::<Value>() else {
+                                    continue;
+                                };
+                                let message = {
+                                    let mut state = state.lock().unwrap();
+                                    if state.echoed(&event) {
+                                        // already given to the ui when sending
+                                        continue;
+                                    }
+                                    state.timeline_event(&contact_id, &event)
+                                };
+                                if let Some(message) = message {
+                                    ba_tx.unbounded_send(message).unwrap();
+                                }
+                            }
+                            for event in update.ephemeral {
+                                let Ok(event) = event.deserialize_as::<Value>() else {
+                                    continue;
+                                };
+                                if event["type"] != "m.typing" {
+                                    continue;
+                                }
+                                // everyone typing in the room, which includes us while we are
+                                let own_id = client.user_id().map(|id| id.as_str().to_owned());
+                                let senders = event["content"]["user_ids"]
+                                    .as_array()
+                                    .into_iter()
+                                    .flatten()
+                                    .filter_map(Value::as_str)
+                                    .filter(|id| own_id.as_deref() != Some(*id))
+                                    .map(|id| id.as_bytes().to_vec())
+                                    .collect();
+                                ba_tx
+                                    .unbounded_send(FrontendMessage::Typing {
+                                        contact_id: contact_id.clone(),
+                                        senders,
+                                    })
+                                    .unwrap();
+                            }
+                        }
+
+                        for room_id in response.rooms.invite.keys() {
+                            let Some(room) = client.get_room(room_id) else {
+                                continue;
+                            };
+                            ba_tx
+                                .unbounded_send(FrontendMessage::NewInvite {
+                                    invite: invite(&room).await,
+                                })
+                                .unwrap();
+                        }
+
+                        // so the next start can carry on from here
+                        persist_sync_token(&session_file, &response.next_batch);
+                        *sync_token.lock().unwrap() = response.next_batch;
+
+                        Ok(LoopCtrl::Continue)
+                    }
+                })
+                .await;
+            match result {
+                Ok(()) => return Ok(()),
+                Err(error) => {
+                    warn!(error:%; "Failed to sync with Matrix");
+                    if online.swap(false, Ordering::Relaxed) {
+                        // it was working before this, so try again quickly
+                        backoff.reset();
+                    }
+                    ba_tx
+                        .unbounded_send(FrontendMessage::Connectivity {
+                            connectivity: Connectivity::Offline,
+                        })
+                        .unwrap();
+                    backoff.wait().await;
+                    ba_tx
+                        .unbounded_send(FrontendMessage::Connectivity {
+                            connectivity: Connectivity::Reconnecting,
+                        })
+                        .unwrap();
+                }
+            }
+        }
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
     async fn users(&self) -> Result<Vec<Contact>> {
         let rooms = self.client.rooms();
         for room in rooms {
@@ -226,12 +485,17 @@ impl Backend for Matrix {
                 address: String::new(),
                 last_message_timestamp: None,
                 description: String::new(),
+                muted_until: muted_until(&room).await,
+                verification: verification(&self.client, &room).await,
+                members: None,
+                encrypted: room.is_encrypted().await.unwrap_or(false),
             };
             users.push(user);
         }
         Ok(users)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
     async fn groups(&self) -> Result<Vec<Contact>> {
         let rooms = self.client.joined_rooms();
         let mut groups = Vec::new();
@@ -244,86 +508,153 @@ impl Backend for Matrix {
 
             let group = Contact {
                 id: ContactId::Group(room.room_id().as_bytes().to_vec()),
-                name: room.name().unwrap(),
+                // rooms without a name are named after their members
+                name: room
+                    .compute_display_name()
+                    .await
+                    .map_or(room.room_id().to_string(), |n| n.to_string()),
                 address: String::new(),
                 last_message_timestamp: None,
-                description: String::new(),
+                description: room.topic().unwrap_or_default(),
+                muted_until: muted_until(&room).await,
+                verification: Verification::Unverified,
+                members: Some(member_count),
+                encrypted: room.is_encrypted().await.unwrap_or(false),
             };
             groups.push(group);
         }
         Ok(groups)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
     async fn messages(
         &mut self,
         contact: ContactId,
-        _start_ts: std::ops::Bound<u64>,
-        _end_ts: std::ops::Bound<u64>,
+        start_ts: Bound<u64>,
+        end_ts: Bound<u64>,
     ) -> Result<Vec<Message>> {
-        let contact_bytes = match contact {
-            ContactId::User(vec) => vec,
-            ContactId::Group(vec) => vec,
-        };
-        let contact_str = String::from_utf8(contact_bytes).unwrap();
-        let room_id = RoomId::parse(contact_str).unwrap();
-
-        let messages = Vec::new();
-
-        if let Some(room) = self.client.get_room(&room_id) {
-            let messages = room.messages(MessagesOptions::forward()).await.unwrap();
-            debug!(start:? = messages.start, end:? = messages.end; "Got some messages");
-            for event in messages.chunk {
-                debug!(event:? = event; "Got timeline event");
-            }
-        }
-
+        let room = self.room(&contact)?;
+        let history = recent_events(&room, HISTORY_EVENTS).await?;
+        let mut state = self.state.lock().unwrap();
+        // oldest first so that reactions come after the messages they are for
+        let messages = history
+            .iter()
+            .rev()
+            .filter_map(|event| match state.timeline_event(&contact, event)? {
+                FrontendMessage::NewMessage { message } => Some(message),
+                _ => None,
+            })
+            .filter(|message| (start_ts, end_ts).contains(&message.timestamp))
+            .collect();
         Ok(messages)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, content, quoting)))]
     async fn send_message(
         &mut self,
         contact: ContactId,
         content: MessageContent,
         quoting: Option<&Quote>,
     ) -> Result<Message> {
-        let contact_bytes = match &contact {
-            ContactId::User(vec) => vec,
-            ContactId::Group(vec) => vec,
-        }
-        .clone();
-        let contact_str = String::from_utf8(contact_bytes).unwrap();
-        let room_id = RoomId::parse(contact_str).unwrap();
-
-        let room = self.client.get_room(&room_id).unwrap();
-        let matrix_content = match &content {
+        let room = self.room(&contact)?;
+        let sent_timestamp = timestamp();
+        let self_id = self.self_id().await;
+        let send_failure = |e: matrix_sdk::Error| {
+            Error::Failure("Failed to send message".to_owned(), e.to_string())
+        };
+        match &content {
             MessageContent::Text {
                 text,
                 attachments: _,
-            } => RoomMessageEventContent::text_plain(text),
+                mentions,
+                formatting,
+            } => {
+                let response = room
+                    .send(formatted_message(text, mentions, formatting))
+                    .await
+                    .map_err(send_failure)?;
+                let mut state = self.state.lock().unwrap();
+                state.events.insert(
+                    response.event_id.clone(),
+                    SeenEvent::Message {
+                        contact_id: contact.clone(),
+                        timestamp: sent_timestamp,
+                        sender: self_id.clone(),
+                    },
+                );
+                state.sent.insert(response.event_id);
+            }
+            MessageContent::Reaction {
+                message_author,
+                timestamp,
+                reaction,
+                remove: false,
+            } => {
+                let event_id = self.event_id(&room, &contact, *timestamp).await?;
+                let response = room
+                    .send(ReactionEventContent::new(Annotation::new(
+                        event_id,
+                        reaction.clone(),
+                    )))
+                    .await
+                    .map_err(send_failure)?;
+                let mut state = self.state.lock().unwrap();
+                state.events.insert(
+                    response.event_id.clone(),
+                    SeenEvent::Reaction {
+                        contact_id: contact.clone(),
+                        sender: self_id.clone(),
+                        message_author: message_author.clone(),
+                        timestamp: *timestamp,
+                        reaction: reaction.clone(),
+                    },
+                );
+                state.sent.insert(response.event_id);
+            }
             MessageContent::Reaction {
                 message_author: _,
-                timestamp: _,
-                reaction: _,
-                remove: _,
-            } => todo!(),
-            MessageContent::Edit {
-                timestamp: _,
-                text: _,
+                timestamp,
+                reaction,
+                remove: true,
             } => {
+                let event_id = self
+                    .state
+                    .lock()
+                    .unwrap()
+                    .own_reaction(&contact, &self_id, *timestamp, reaction)
+                    .ok_or_else(|| {
+                        Error::Failure(
+                            "Failed to find reaction to remove".to_owned(),
+                            format!("no reaction {reaction} to message at {timestamp}"),
+                        )
+                    })?;
+                room.redact(&event_id, None, None).await.map_err(|e| {
+                    Error::Failure("Failed to remove reaction".to_owned(), e.to_string())
+                })?;
+                // so that the redaction coming back doesn't remove it again
+                self.state.lock().unwrap().events.remove(&event_id);
+            }
+            MessageContent::Edit { .. } => {
                 todo!()
             }
-        };
-
-        room.send(matrix_content).await.unwrap();
+            MessageContent::Unsupported { .. } | MessageContent::Call { .. } => {
+                return Err(Error::Failure(
+                    "Failed to send message".to_owned(),
+                    "calls and unsupported messages can't be sent".to_owned(),
+                ))
+            }
+        }
 
         let quote = quoting.map(|quoted| Quote {
             timestamp: quoted.timestamp,
             sender: quoted.sender.clone(),
             text: quoted.text.clone(),
+            mentions: quoted.mentions.clone(),
+            formatting: quoted.formatting.clone(),
         });
         Ok(Message {
-            timestamp: timestamp(),
-            sender: self.self_id().await,
+            timestamp: sent_timestamp,
+            sender: self_id,
             contact_id: contact,
             content,
             quote,
@@ -334,17 +665,783 @@ impl Backend for Matrix {
         self.client.user_id().unwrap().as_bytes().to_vec()
     }
 
-    async fn download_attachment(&self, _attachment_index: usize) -> Result<PathBuf> {
+    fn limits(&self) -> Limits {
+        Limits {
+            // events are limited to 64KiB in total, including the surrounding json
+            max_body_length: Some(60_000),
+            max_attachment_size: None,
+            // sending attachments is not supported yet
+            max_attachment_count: Some(0),
+            // reactions are annotations with any key
+            any_reaction: true,
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn download_attachment(
+        &self,
+        _attachment_index: usize,
+        _progress: Progress,
+    ) -> Result<PathBuf> {
         todo!()
     }
+
+    async fn pin_message(
+        &mut self,
+        contact_id: ContactId,
+        timestamp: u64,
+        pinned: bool,
+    ) -> Result<()> {
+        let room = self.room(&contact_id)?;
+        let event_id = self.event_id(&room, &contact_id, timestamp).await?;
+
+        let mut pinned_ids = room.pinned_event_ids().unwrap_or_default();
+        if pinned {
+            if !pinned_ids.contains(&event_id) {
+                pinned_ids.push(event_id);
+            }
+        } else {
+            pinned_ids.retain(|id| id != &event_id);
+        }
+        room.send_state_event(RoomPinnedEventsEventContent::new(pinned_ids))
+            .await
+            .map_err(|e| {
+                Error::Failure("Failed to update pinned events".to_owned(), e.to_string())
+            })?;
+        Ok(())
+    }
+
+    async fn delete_message(&mut self, contact_id: ContactId, timestamp: u64) -> Result<()> {
+        let room = self.room(&contact_id)?;
+        let event_id = self.event_id(&room, &contact_id, timestamp).await?;
+        room.redact(&event_id, None, None)
+            .await
+            .map_err(|e| Error::Failure("Failed to redact event".to_owned(), e.to_string()))?;
+        Ok(())
+    }
+
+    async fn pinned_messages(&self, contact_id: ContactId) -> Result<Vec<u64>> {
+        let room = self.room(&contact_id)?;
+        let pinned_ids = room.pinned_event_ids().unwrap_or_default();
+        if pinned_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        // only pinned events in the recent history can be matched up to messages
+        let events = recent_event_timestamps(&room).await?;
+        Ok(pinned_ids
+            .iter()
+            .filter_map(|id| events.iter().find(|(e, _)| e == id).map(|(_, ts)| *ts))
+            .collect())
+    }
+
+    async fn find_user(&mut self, _query: String) -> Result<Contact> {
+        Err(Error::Failure(
+            format!("{} doesn't support finding users", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn message_receipts(
+        &self,
+        contact_id: ContactId,
+        timestamp: u64,
+    ) -> Result<Vec<Receipt>> {
+        let room = self.room(&contact_id)?;
+        let event_id = self.event_id(&room, &contact_id, timestamp).await?;
+        let own_id = self.client.user_id().map(|id| id.to_owned());
+        // matrix only has read receipts, which point at the latest message each member has read so
+        // earlier messages show fewer
+        let receipts = room
+            .load_event_receipts(ReceiptType::Read, ReceiptThread::Unthreaded, &event_id)
+            .await
+            .map_err(|e| Error::Failure("Failed to load receipts".to_owned(), e.to_string()))?;
+        Ok(receipts
+            .into_iter()
+            .filter(|(user_id, _)| Some(user_id) != own_id.as_ref())
+            .map(|(user_id, receipt)| Receipt {
+                recipient: user_id.as_bytes().to_vec(),
+                delivered: None,
+                read: Some(receipt.ts.map_or(timestamp, |ts| ts.0.into())),
+            })
+            .collect())
+    }
+
+    async fn contact_details(&self, contact_id: ContactId) -> Result<ContactDetails> {
+        let failure = |e: &dyn std::fmt::Display| {
+            Error::Failure("Failed to load room details".to_owned(), e.to_string())
+        };
+        let room = self.room(&contact_id)?;
+        let own_id = self
+            .client
+            .user_id()
+            .ok_or_else(|| failure(&"not logged in"))?;
+        let members = room
+            .members(RoomMemberships::JOIN)
+            .await
+            .map_err(|e| failure(&e))?;
+        let mut details = ContactDetails::default();
+        for member in members {
+            if member.user_id() == own_id {
+                let role = match member.suggested_role_for_power_level() {
+                    RoomMemberRole::Administrator => "admin",
+                    RoomMemberRole::Moderator => "moderator",
+                    RoomMemberRole::User => "member",
+                };
+                details.role = Some(role.to_owned());
+            } else if matches!(contact_id, ContactId::User(_)) {
+                // the other person in a direct message
+                details
+                    .profile
+                    .push(("User id".to_owned(), member.user_id().to_string()));
+                if let Some(name) = member.display_name() {
+                    details
+                        .profile
+                        .push(("Display name".to_owned(), name.to_owned()));
+                }
+            }
+        }
+        Ok(details)
+    }
+
+    async fn verify_device(&mut self) -> Result<()> {
+        let failure = |e: &dyn std::fmt::Display| {
+            Error::Failure("Failed to request verification".to_owned(), e.to_string())
+        };
+        let user_id = self
+            .client
+            .user_id()
+            .ok_or_else(|| failure(&"not logged in"))?
+            .to_owned();
+        let identity = self
+            .client
+            .encryption()
+            .request_user_identity(&user_id)
+            .await
+            .map_err(|e| failure(&e))?
+            .ok_or_else(|| failure(&"no cross-signing identity"))?;
+        let request = identity
+            .request_verification()
+            .await
+            .map_err(|e| failure(&e))?;
+        self.verifier.report(DeviceVerification::Requested);
+        tokio::spawn(self.verifier.clone().handle_request(request));
+        Ok(())
+    }
+
+    async fn confirm_verification(&mut self, confirmed: bool) -> Result<()> {
+        let confirmation = self.verifier.confirmation.lock().unwrap().take();
+        let Some(confirmation) = confirmation else {
+            return Err(Error::Failure(
+                "No verification waiting for confirmation".to_owned(),
+                String::new(),
+            ));
+        };
+        // the verification was cancelled if nothing is waiting
+        let _ = confirmation.send(confirmed);
+        Ok(())
+    }
+
+    async fn invites(&self) -> Result<Vec<Invite>> {
+        let mut invites = Vec::new();
+        for room in self.client.invited_rooms() {
+            invites.push(invite(&room).await);
+        }
+        Ok(invites)
+    }
+
+    async fn answer_invite(&mut self, contact_id: ContactId, accept: bool) -> Result<()> {
+        let room = self.room(&contact_id)?;
+        if accept {
+            room.join()
+                .await
+                .map_err(|e| Error::Failure("Failed to join room".to_owned(), e.to_string()))
+        } else {
+            room.leave()
+                .await
+                .map_err(|e| Error::Failure("Failed to decline invite".to_owned(), e.to_string()))
+        }
+    }
+
+    async fn set_group_title(&mut self, contact_id: ContactId, title: String) -> Result<()> {
+        let room = self.room(&contact_id)?;
+        room.set_name(title)
+            .await
+            .map_err(|e| state_error("Failed to set room name", "change the room name", e))?;
+        Ok(())
+    }
+
+    async fn set_group_description(
+        &mut self,
+        contact_id: ContactId,
+        description: String,
+    ) -> Result<()> {
+        let room = self.room(&contact_id)?;
+        room.set_room_topic(&description)
+            .await
+            .map_err(|e| state_error("Failed to set room topic", "change the room topic", e))?;
+        Ok(())
+    }
+
+    async fn request_contact_sync(&mut self) -> Result<()> {
+        // the rooms are kept up to date by the sync, loading them again is enough
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn conversation_summaries(&self) -> Result<Vec<Message>> {
+        let mut summaries = Vec::new();
+        for room in self.client.joined_rooms() {
+            let contact_id = contact_id(&room).await;
+            let events = recent_events(&room, SUMMARY_EVENTS).await?;
+            let mut state = self.state.lock().unwrap();
+            let latest = events
+                .iter()
+                .filter_map(|event| match state.timeline_event(&contact_id, event)? {
+                    FrontendMessage::NewMessage { message } => Some(message),
+                    _ => None,
+                })
+                .find(|message| matches!(message.content, MessageContent::Text { .. }));
+            summaries.extend(latest);
+        }
+        Ok(summaries)
+    }
+}
+
+/// A link to the room, to return a call in it from a client such as Element.
+fn room_link(contact_id: &ContactId) -> String {
+    let (ContactId::User(id) | ContactId::Group(id)) = contact_id;
+    format!("https://matrix.to/#/{}", String::from_utf8_lossy(id))
+}
+
+impl Matrix {
+    fn room(&self, contact_id: &ContactId) -> Result<Room> {
+        let contact_bytes = match contact_id {
+            ContactId::User(vec) => vec,
+            ContactId::Group(vec) => vec,
+        };
+        let contact_str = String::from_utf8(contact_bytes.clone()).unwrap();
+        let room_id = RoomId::parse(contact_str).unwrap();
+        self.client
+            .get_room(&room_id)
+            .ok_or_else(|| Error::Failure("Unknown room".to_owned(), room_id.to_string()))
+    }
+
+    /// The id of the message at a timestamp, from those seen or the recent history of the room.
+    async fn event_id(
+        &self,
+        room: &Room,
+        contact_id: &ContactId,
+        timestamp: u64,
+    ) -> Result<OwnedEventId> {
+        let seen = self.state.lock().unwrap().message_id(contact_id, timestamp);
+        if let Some(event_id) = seen {
+            return Ok(event_id);
+        }
+        let events = recent_event_timestamps(room).await?;
+        events
+            .into_iter()
+            .find(|(_, ts)| *ts == timestamp)
+            .map(|(event_id, _)| event_id)
+            .ok_or_else(|| {
+                Error::Failure(
+                    "Failed to find message".to_owned(),
+                    format!("no recent event with timestamp {timestamp}"),
+                )
+            })
+    }
+}
+
+impl State {
+    /// Turn a timeline event into a message for the ui, remembering it so that later reactions
+    /// and redactions can find it.
+    fn timeline_event(&mut self, contact_id: &ContactId, event: &Value) -> Option<FrontendMessage> {
+        let event_id = EventId::parse(event["event_id"].as_str()?).ok()?;
+        let sender = event["sender"].as_str()?.as_bytes().to_vec();
+        let timestamp = event["origin_server_ts"].as_u64()?;
+        let content = &event["content"];
+        let content = match event["type"].as_str()? {
+            "m.room.message" => {
+                // redacted messages have no body
+                let mut text = content["body"].as_str()?.to_owned();
+                let mut formatting = Vec::new();
+                if content["format"] == "org.matrix.custom.html" {
+                    if let Some(html) = content["formatted_body"].as_str() {
+                        (text, formatting) = chatters_lib::html::parse_html(html);
+                    }
+                }
+                self.events.insert(
+                    event_id,
+                    SeenEvent::Message {
+                        contact_id: contact_id.clone(),
+                        timestamp,
+                        sender: sender.clone(),
+                    },
+                );
+                let mentions = content["m.mentions"]["user_ids"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|user_id| user_id.as_str())
+                    .map(|user_id| mention_in(&text, user_id))
+                    .collect();
+                MessageContent::Text {
+                    text,
+                    attachments: Vec::new(),
+                    mentions,
+                    formatting,
+                }
+            }
+            "m.reaction" => {
+                let relates_to = &content["m.relates_to"];
+                let reacted_to = EventId::parse(relates_to["event_id"].as_str()?).ok()?;
+                let reaction = relates_to["key"].as_str()?.to_owned();
+                let Some(SeenEvent::Message {
+                    timestamp: target_timestamp,
+                    sender: message_author,
+                    ..
+                }) = self.events.get(&reacted_to).cloned()
+                else {
+                    debug!(reacted_to:%; "Ignoring reaction to unknown message");
+                    return None;
+                };
+                self.events.insert(
+                    event_id,
+                    SeenEvent::Reaction {
+                        contact_id: contact_id.clone(),
+                        sender: sender.clone(),
+                        message_author: message_author.clone(),
+                        timestamp: target_timestamp,
+                        reaction: reaction.clone(),
+                    },
+                );
+                MessageContent::Reaction {
+                    message_author,
+                    timestamp: target_timestamp,
+                    reaction,
+                    remove: false,
+                }
+            }
+            "m.room.redaction" => {
+                // newer room versions only have it in the content
+                let redacts = content["redacts"].as_str().or(event["redacts"].as_str())?;
+                match self.events.remove(&EventId::parse(redacts).ok()?)? {
+                    SeenEvent::Message {
+                        contact_id,
+                        timestamp,
+                        ..
+                    } => {
+                        return Some(FrontendMessage::RemovedMessage {
+                            contact_id,
+                            timestamp,
+                        })
+                    }
+                    SeenEvent::Reaction {
+                        contact_id,
+                        sender,
+                        message_author,
+                        timestamp: target_timestamp,
+                        reaction,
+                    } => {
+                        return Some(FrontendMessage::NewMessage {
+                            message: Message {
+                                timestamp,
+                                sender,
+                                contact_id,
+                                content: MessageContent::Reaction {
+                                    message_author,
+                                    timestamp: target_timestamp,
+                                    reaction,
+                                    remove: true,
+                                },
+                                quote: None,
+                            },
+                        })
+                    }
+                }
+            }
+            // including calls with Element Call
+            "m.call.invite"
+            | "org.matrix.msc4075.call.notify"
+            | "org.matrix.msc4075.rtc.notification" => MessageContent::Call {
+                missed: false,
+                link: Some(room_link(contact_id)),
+            },
+            // the caller gave up before anyone answered
+            "m.call.hangup" if content["reason"] == "invite_timeout" => MessageContent::Call {
+                missed: true,
+                link: Some(room_link(contact_id)),
+            },
+            "m.room.name" | "m.room.topic" | "m.room.avatar" => MessageContent::Unsupported {
+                description: "Room updated".to_owned(),
+            },
+            _ => return None,
+        };
+        Some(FrontendMessage::NewMessage {
+            message: Message {
+                timestamp,
+                sender,
+                contact_id: contact_id.clone(),
+                content,
+                quote: None,
+            },
+        })
+    }
+
+    /// Whether an event from syncing was sent from here, and so has already been seen.
+    fn echoed(&mut self, event: &Value) -> bool {
+        event["event_id"]
+            .as_str()
+            .and_then(|id| EventId::parse(id).ok())
+            .is_some_and(|id| self.sent.remove(&id))
+    }
+
+    fn message_id(&self, contact_id: &ContactId, timestamp: u64) -> Option<OwnedEventId> {
+        self.events
+            .iter()
+            .find_map(|(event_id, event)| match event {
+                SeenEvent::Message {
+                    contact_id: c,
+                    timestamp: t,
+                    ..
+                } if c == contact_id && *t == timestamp => Some(event_id.clone()),
+                _ => None,
+            })
+    }
+
+    /// The id of a reaction sent to a message, to redact it.
+    fn own_reaction(
+        &self,
+        contact_id: &ContactId,
+        self_id: &[u8],
+        timestamp: u64,
+        reaction: &str,
+    ) -> Option<OwnedEventId> {
+        self.events
+            .iter()
+            .find_map(|(event_id, event)| match event {
+                SeenEvent::Reaction {
+                    contact_id: c,
+                    sender,
+                    timestamp: t,
+                    reaction: r,
+                    ..
+                } if c == contact_id && sender == self_id && *t == timestamp && r == reaction => {
+                    Some(event_id.clone())
+                }
+                _ => None,
+            })
+    }
+}
+
+/// The conversation for a room, those with at most two members being direct ones.
+async fn contact_id(room: &Room) -> ContactId {
+    let room_id = room.room_id().as_bytes().to_vec();
+    let member_count = room
+        .members(RoomMemberships::JOIN)
+        .await
+        .map_or(0, |members| members.len());
+    if member_count > 2 {
+        ContactId::Group(room_id)
+    } else {
+        ContactId::User(room_id)
+    }
+}
+
+/// The invite to a room, with what can be seen of it before joining.
+async fn invite(room: &Room) -> Invite {
+    let room_id = room.room_id().as_bytes().to_vec();
+    let contact_id = if room.is_direct().await.unwrap_or(false) {
+        ContactId::User(room_id)
+    } else {
+        ContactId::Group(room_id)
+    };
+    let inviter = match room.invite_details().await {
+        Ok(details) => details
+            .inviter
+            .map(|member| member.name().to_owned())
+            .unwrap_or_default(),
+        Err(error) => {
+            warn!(error:%; "Failed to load invite details");
+            String::new()
+        }
+    };
+    Invite {
+        contact_id,
+        name: room
+            .compute_display_name()
+            .await
+            .map_or(room.room_id().to_string(), |n| n.to_string()),
+        inviter,
+    }
+}
+
+/// Whether the room is muted through the user's push rules.
+async fn muted_until(room: &Room) -> Option<u64> {
+    mute_from_mode(room.user_defined_notification_mode().await)
+}
+
+fn mute_from_mode(mode: Option<RoomNotificationMode>) -> Option<u64> {
+    match mode {
+        // push rules have no expiry
+        Some(RoomNotificationMode::Mute) => Some(u64::MAX),
+        // still notified of some messages, so not muted
+        Some(RoomNotificationMode::AllMessages | RoomNotificationMode::MentionsAndKeywordsOnly)
+        | None => None,
+    }
+}
+
+/// Whether the other member of a direct room is verified through cross-signing.
+async fn verification(client: &Client, room: &Room) -> Verification {
+    let Some(own_id) = client.user_id() else {
+        return Verification::Unverified;
+    };
+    let Ok(members) = room.members(RoomMemberships::JOIN).await else {
+        return Verification::Unverified;
+    };
+    let Some(other) = members.iter().find(|member| member.user_id() != own_id) else {
+        return Verification::Unverified;
+    };
+    if let Ok(Some(identity)) = client.encryption().get_user_identity(other.user_id()).await {
+        if identity.is_verified() {
+            return Verification::Verified;
+        }
+    }
+    // the first of the room's identity changes is how things stand now, given straight away
+    // unless every member is verified or pinned
+    let Ok(changes) = room.subscribe_to_identity_status_changes().await else {
+        return Verification::Unverified;
+    };
+    pin_mut!(changes);
+    let violated = changes
+        .next()
+        .now_or_never()
+        .flatten()
+        .is_some_and(|changes| {
+            changes.iter().any(|change| {
+                change.user_id == other.user_id()
+                    && change.changed_to == IdentityState::VerificationViolation
+            })
+        });
+    if violated {
+        Verification::Changed
+    } else {
+        Verification::Unverified
+    }
+}
+
+/// The error for a state event the server refused, telling the user they aren't allowed to
+/// change it when their power level is too low.
+fn state_error(what: &str, not_allowed: &str, error: matrix_sdk::Error) -> Error {
+    match error.client_api_error_kind() {
+        Some(ErrorKind::Forbidden { .. }) => Error::NotAllowed(not_allowed.to_owned()),
+        _ => Error::Failure(what.to_owned(), error.to_string()),
+    }
+}
+
+/// The recent events in a room as json, newest first.
+async fn recent_events(room: &Room, limit: u32) -> Result<Vec<Value>> {
+    let mut options = MessagesOptions::backward();
+    options.limit = UInt::from(limit);
+    let messages = room
+        .messages(options)
+        .await
+        .map_err(|e| Error::Failure("Failed to load room history".to_owned(), e.to_string()))?;
+    Ok(messages
+        .chunk
+        .iter()
+        .filter_map(|event| event.raw().deserialize_as::<Value>().ok())
+        .collect())
+}
+
+/// The ids and timestamps of the recent events in a room.
+async fn recent_event_timestamps(room: &Room) -> Result<Vec<(OwnedEventId, u64)>> {
+    Ok(recent_events(room, HISTORY_EVENTS)
+        .await?
+        .iter()
+        .filter_map(|event| {
+            let event_id = EventId::parse(event["event_id"].as_str()?).ok()?;
+            let timestamp = event["origin_server_ts"].as_u64()?;
+            Some((event_id, timestamp))
+        })
+        .collect())
+}
+
+/// Whether the homeserver offers single sign-on among its login flows.
+fn supports_sso(flows: &[LoginType]) -> bool {
+    flows.iter().any(|flow| matches!(flow, LoginType::Sso(_)))
+}
+
+/// Log in through the homeserver's single sign-on page, sending its URL to be opened in the
+/// browser, which redirects back to a local server once done.
+async fn login_sso(
+    matrix_auth: &MatrixAuth,
+    device_name: &str,
+    provisioning_link_tx: futures::channel::oneshot::Sender<String>,
+) -> Result<()> {
+    let response = matrix_auth
+        .login_sso(|sso_url| async move {
+            if provisioning_link_tx.send(sso_url).is_err() {
+                warn!("Failed to send single sign-on URL");
+            }
+            Ok(())
+        })
+        .initial_device_display_name(device_name)
+        .await
+        .map_err(|e| {
+            Error::Failure(
+                "Failed to log in with single sign-on".to_owned(),
+                e.to_string(),
+            )
+        })?;
+    debug!(user_id:% = response.user_id; "Logged in with single sign-on");
+    Ok(())
+}
+
+/// Log in with a username and password, for homeservers without single sign-on.
+async fn login_password(
+    matrix_auth: &MatrixAuth,
+    device_name: &str,
+    username: &str,
+    password: &str,
+) -> Result<()> {
+    matrix_auth
+        .login_username(username, password)
+        .initial_device_display_name(device_name)
+        .await
+        .map_err(|e| Error::Failure("Failed to log in".to_owned(), e.to_string()))?;
+    debug!(username; "Logged in with password");
+    Ok(())
+}
+
+/// Save the sync token in the session file, keeping the rest of the session.
+fn persist_sync_token(session_file: &Path, sync_token: &str) {
+    let result = std::fs::read_to_string(session_file)
+        .map_err(anyhow::Error::from)
+        .and_then(|serialized| Ok(serde_json::from_str::<FullSession>(&serialized)?))
+        .and_then(|mut session| {
+            session.sync_token = Some(sync_token.to_owned());
+            write_atomically(session_file, &serde_json::to_string(&session)?)?;
+            Ok(())
+        });
+    if let Err(error) = result {
+        warn!(error:%; "Failed to persist sync token");
+    }
+}
+
+/// Replace the contents of a file without leaving it half written if we stop part way, by
+/// writing a copy alongside it and renaming that over it.
+fn write_atomically(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    let mut file = std::fs::File::create(&temporary)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&temporary, path)
+}
+
+/// Save the session, with the database passphrase and access token in the system keyring.
+///
+/// They are only written to the file in plaintext if the keyring can't be used and the config
+/// allows it.
+async fn save_session(
+    session_file: &Path,
+    client_session: &ClientSession,
+    user_session: &MatrixSession,
+    sync_token: Option<String>,
+) -> Result<()> {
+    let secrets = serde_json::to_string(&SessionSecrets {
+        passphrase: client_session.passphrase.clone(),
+        user_session: user_session.clone(),
+    })
+    .unwrap();
+    let name = keyring_name(user_session);
+    let mut session = FullSession {
+        client_session: ClientSession {
+            homeserver: client_session.homeserver.clone(),
+            db_path: client_session.db_path.clone(),
+            passphrase: String::new(),
+        },
+        user_session: None,
+        sync_token,
+        keyring_name: Some(name.clone()),
+    };
+    match secrets::store(KEYRING_SERVICE, &name, secrets).await {
+        Ok(()) => {}
+        Err(error) if secrets::plaintext_fallback() => {
+            warn!(error:%; "Keeping the session secrets in plaintext");
+            session.client_session.passphrase = client_session.passphrase.clone();
+            session.user_session = Some(user_session.clone());
+            session.keyring_name = None;
+        }
+        Err(error) => {
+            return Err(Error::Failure(
+                "Failed to store the session secrets, see plaintext_secrets in the config"
+                    .to_owned(),
+                error.to_string(),
+            ))
+        }
+    }
+    write_atomically(session_file, &serde_json::to_string(&session).unwrap())
+        .map_err(|e| Error::Failure("Failed to save the session".to_owned(), e.to_string()))
+}
+
+/// The name to keep the session secrets under in the system keyring, which stays the same when
+/// the data directory moves.
+fn keyring_name(user_session: &MatrixSession) -> String {
+    format!(
+        "{}/{}",
+        user_session.meta.user_id, user_session.meta.device_id
+    )
+}
+
+/// Move secrets kept under the path of the session file to the name from the account.
+async fn migrate_keyring_name(
+    session_file: &Path,
+    client_session: &ClientSession,
+    user_session: &MatrixSession,
+    sync_token: Option<String>,
+) {
+    if let Err(error) = save_session(session_file, client_session, user_session, sync_token).await {
+        warn!(error:%; "Failed to move the session secrets to their new keyring name");
+        return;
+    }
+    let legacy_name = session_file.to_string_lossy();
+    if let Err(error) = secrets::delete(KEYRING_SERVICE, &legacy_name).await {
+        warn!(error:%; "Failed to remove the session secrets under their old keyring name");
+    }
+}
+
+/// Load the parts of the session kept in the system keyring under the name.
+async fn load_session_secrets(name: &str) -> Result<SessionSecrets> {
+    let secrets = secrets::load(KEYRING_SERVICE, name).await.map_err(|e| {
+        Error::Failure(
+            "Failed to load the session secrets".to_owned(),
+            e.to_string(),
+        )
+    })?;
+    let Some(secrets) = secrets else {
+        // nothing to log in with, such as after moving the data to another machine
+        warn!("Session secrets missing from the system keyring, linking again");
+        return Err(Error::Unlinked);
+    };
+    serde_json::from_str(&secrets).map_err(|e| {
+        Error::Failure(
+            "Failed to parse the session secrets".to_owned(),
+            e.to_string(),
+        )
+    })
 }
 
 fn get_session_file(path: &Path) -> PathBuf {
     path.join("session.json")
 }
 
-/// Build a new client.
-async fn build_client(data_dir: &Path) -> anyhow::Result<(Client, ClientSession)> {
+/// Build a new client for the homeserver.
+async fn build_client(
+    data_dir: &Path,
+    homeserver: &str,
+) -> anyhow::Result<(Client, ClientSession)> {
     let mut rng = rand::rng();
 
     let db_path = data_dir.join("db");
@@ -356,180 +1453,450 @@ async fn build_client(data_dir: &Path) -> anyhow::Result<(Client, ClientSession)
         .map(char::from)
         .collect();
 
-    // We create a loop here so the user can retry if an error happens.
-    loop {
-        let mut homeserver = String::new();
+    let client = Client::builder()
+        .homeserver_url(homeserver)
+        .sqlite_store(&db_path, Some(&passphrase))
+        .build()
+        .await?;
+    Ok((
+        client,
+        ClientSession {
+            homeserver: homeserver.to_owned(),
+            db_path: db_path.to_owned(),
+            passphrase,
+        },
+    ))
+}
 
-        print!("Homeserver URL: ");
-        io::stdout().flush().expect("Unable to write to stdout");
-        io::stdin()
-            .read_line(&mut homeserver)
-            .expect("Unable to read user input");
+async fn own_identity_verified(client: &Client) -> bool {
+    let Some(user_id) = client.user_id() else {
+        return false;
+    };
+    match client.encryption().get_user_identity(user_id).await {
+        Ok(Some(identity)) => {
+            debug!(identity:?, verified = identity.is_verified(); "Loaded own identity");
+            identity.is_verified()
+        }
+        Ok(None) => false,
+        Err(error) => {
+            warn!(error:%; "Failed to load own identity");
+            false
+        }
+    }
+}
 
-        println!("\nChecking homeserver…");
+impl Verifier {
+    fn report(&self, verification: DeviceVerification) {
+        if let Some(updates) = &*self.updates.lock().unwrap() {
+            updates
+                .unbounded_send(FrontendMessage::DeviceVerification { verification })
+                .unwrap();
+        }
+    }
 
-        match Client::builder()
-            .homeserver_url(&homeserver)
-            .sqlite_store(&db_path, Some(&passphrase))
-            .build()
-            .await
-        {
-            Ok(client) => {
-                return Ok((
-                    client,
-                    ClientSession {
-                        homeserver,
-                        db_path: db_path.to_owned(),
-                        passphrase,
-                    },
-                ))
+    fn cancelled(&self, reason: impl ToString) {
+        self.report(DeviceVerification::Cancelled {
+            reason: reason.to_string(),
+        });
+    }
+
+    async fn handle_request(self, request: VerificationRequest) {
+        debug!(
+            other_user_id:? = request.other_user_id();
+            "Accepting verification request",
+        );
+        if let Err(error) = request.accept().await {
+            self.cancelled(error);
+            return;
+        }
+        debug!("Accepted our side during verification");
+
+        let mut stream = request.changes();
+
+        while let Some(state) = stream.next().await {
+            debug!(state:?; "Got state during verification");
+            match state {
+                VerificationRequestState::Created { .. }
+                | VerificationRequestState::Requested { .. }
+                | VerificationRequestState::Ready { .. } => (),
+                VerificationRequestState::Transitioned { verification } => {
+                    // We only support SAS verification.
+                    if let matrix_sdk::encryption::verification::Verification::SasV1(s) =
+                        verification
+                    {
+                        self.handle_sas(s).await;
+                        break;
+                    }
+                }
+                VerificationRequestState::Done => break,
+                VerificationRequestState::Cancelled(cancel_info) => {
+                    self.cancelled(cancel_info.reason());
+                    break;
+                }
             }
-            Err(error) => match &error {
-                matrix_sdk::ClientBuildError::AutoDiscovery(_)
-                | matrix_sdk::ClientBuildError::Url(_)
-                | matrix_sdk::ClientBuildError::Http(_) => {
-                    println!("Error checking the homeserver: {error}");
-                    println!("Please try again\n");
+        }
+    }
+
+    async fn handle_sas(&self, sas: SasVerification) {
+        let other_device = format!(
+            "{} {}",
+            sas.other_device().user_id(),
+            sas.other_device().device_id()
+        );
+        debug!(other_device; "Starting verification");
+        if let Err(error) = sas.accept().await {
+            self.cancelled(error);
+            return;
+        }
+
+        let mut stream = sas.changes();
+
+        while let Some(state) = stream.next().await {
+            match state {
+                SasState::KeysExchanged {
+                    emojis,
+                    decimals: _,
+                } => {
+                    let Some(emojis) = emojis else {
+                        // We only support verifications using emojis
+                        let _ = sas.cancel().await;
+                        self.cancelled("the other device can't show emoji");
+                        break;
+                    };
+                    let (confirmation_tx, confirmation_rx) = oneshot::channel();
+                    *self.confirmation.lock().unwrap() = Some(confirmation_tx);
+                    self.report(DeviceVerification::Compare {
+                        other_device: other_device.clone(),
+                        emoji: emojis
+                            .emojis
+                            .iter()
+                            .map(|e| format!("{} {}", e.symbol, e.description))
+                            .collect(),
+                    });
+                    let result = if confirmation_rx.await.unwrap_or_default() {
+                        sas.confirm().await
+                    } else {
+                        sas.cancel().await
+                    };
+                    if let Err(error) = result {
+                        self.cancelled(error);
+                        break;
+                    }
                 }
-                _ => {
-                    // Forward other errors, it's unlikely we can retry with a different outcome.
-                    return Err(error.into());
+                SasState::Done { .. } => {
+                    let device = sas.other_device();
+                    debug!(
+                        user_id:% = device.user_id(),
+                        device_id:% = device.device_id(),
+                        trust:? = device.local_trust_state();
+                        "Successfully verified device"
+                    );
+                    self.report(DeviceVerification::Done { other_device });
+                    break;
                 }
-            },
+                SasState::Cancelled(cancel_info) => {
+                    self.cancelled(cancel_info.reason());
+                    break;
+                }
+                SasState::Created { .. }
+                | SasState::Started { .. }
+                | SasState::Accepted { .. }
+                | SasState::Confirmed => (),
+            }
         }
     }
 }
 
-async fn verify(client: &Client) {
-    println!("Verifying device, please accept the request on another of your device");
-    let this_user = client
-        .encryption()
-        .request_user_identity(client.user_id().unwrap())
-        .await
-        .unwrap()
-        .unwrap();
-    let verification_request = this_user.request_verification().await.unwrap();
+/// A user mentioned in a message, covering their id in the text if it is there. Clients put their
+/// display names in the text instead, so the mention is usually left without a place in it.
+fn mention_in(text: &str, user_id: &str) -> Mention {
+    let (start, length) = text.find(user_id).map_or((0, 0), |i| {
+        (text[..i].chars().count(), user_id.chars().count())
+    });
+    Mention {
+        start,
+        length,
+        id: user_id.as_bytes().to_vec(),
+    }
+}
 
-    // TODO: cancel this after verification
-    let c = client.clone();
-    let sync = c.sync(SyncSettings::new());
-    pin_mut!(sync);
+/// A text message, with its formatting and the mentioned users as pills linking to them in the
+/// formatted body.
+fn formatted_message(
+    text: &str,
+    mentions: &[Mention],
+    formatting: &[Formatting],
+) -> RoomMessageEventContent {
+    // where each pill ends and who it is for, by where it starts
+    let pills: BTreeMap<usize, (usize, OwnedUserId)> = mentions
+        .iter()
+        .filter_map(|mention| {
+            let user_id = std::str::from_utf8(&mention.id).ok()?;
+            let user_id = OwnedUserId::try_from(user_id).ok()?;
+            Some((mention.start, (mention.start + mention.length, user_id)))
+        })
+        .collect();
+    if pills.is_empty() && formatting.is_empty() {
+        return RoomMessageEventContent::text_plain(text);
+    }
+
+    let chars: Vec<char> = text.chars().collect();
+    let mut html = String::new();
+    let mut open: Vec<FormatKind> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let mut active: Vec<FormatKind> = formatting
+            .iter()
+            .filter(|f| f.start <= i && i < f.start + f.length)
+            .map(|f| f.kind)
+            .collect();
+        active.dedup();
+        // close what has ended, and anything opened within it, to keep the tags nested
+        let kept = open.iter().zip(&active).take_while(|(o, a)| o == a).count();
+        for kind in open.drain(kept..).rev() {
+            html.push_str(&format!("</{}>", tag(kind)));
+        }
+        for kind in &active[kept..] {
+            html.push_str(&open_tag(*kind));
+        }
+        open = active;
+
+        if let Some((end, user_id)) = pills.get(&i) {
+            let end = (*end).min(chars.len());
+            let name: String = chars[i..end].iter().collect();
+            html.push_str(&format!(
+                "<a href=\"{}\">{}</a>",
+                user_id.matrix_to_uri(),
+                escape_html(name.trim_start_matches('@'))
+            ));
+            i = end;
+            continue;
+        }
+        html.push_str(&escape_html(&chars[i].to_string()));
+        i += 1;
+    }
+    for kind in open.into_iter().rev() {
+        html.push_str(&format!("</{}>", tag(kind)));
+    }
 
-    let verify = request_verification_handler(verification_request);
-    pin_mut!(verify);
+    let content = RoomMessageEventContent::text_html(text, html);
+    if pills.is_empty() {
+        content
+    } else {
+        let user_ids = pills.into_values().map(|(_, user_id)| user_id);
+        content.add_mentions(Mentions::with_user_ids(user_ids))
+    }
+}
 
-    // cancels the other future on drop
-    select(sync, verify).await;
+fn tag(kind: FormatKind) -> &'static str {
+    match kind {
+        FormatKind::Bold => "strong",
+        FormatKind::Italic => "em",
+        FormatKind::Strikethrough => "del",
+        FormatKind::Monospace => "code",
+        FormatKind::Spoiler => "span",
+    }
 }
 
-async fn request_verification_handler(request: VerificationRequest) {
-    debug!(
-        other_user_id:? = request.other_user_id();
-        "Accepting verification request",
-    );
-    request
-        .accept()
-        .await
-        .expect("Can't accept verification request");
-    debug!("Accepted our side during verification");
-
-    let mut stream = request.changes();
-
-    while let Some(state) = stream.next().await {
-        debug!(state:?; "Got state during verification");
-        match state {
-            VerificationRequestState::Created { .. }
-            | VerificationRequestState::Requested { .. }
-            | VerificationRequestState::Ready { .. } => (),
-            VerificationRequestState::Transitioned { verification } => {
-                // We only support SAS verification.
-                if let Verification::SasV1(s) = verification {
-                    sas_verification_handler(s).await;
-                    break;
-                }
-            }
-            VerificationRequestState::Done | VerificationRequestState::Cancelled(_) => break,
-        }
+fn open_tag(kind: FormatKind) -> String {
+    match kind {
+        FormatKind::Spoiler => "<span data-mx-spoiler>".to_owned(),
+        _ => format!("<{}>", tag(kind)),
     }
 }
 
-async fn wait_for_confirmation(emoji: [Emoji; 7]) -> bool {
-    loop {
-        println!("\nDo the emojis match: \n{}", format_emojis(emoji.clone()));
-        print!("Confirm with `yes` or cancel with `no`: ");
-        std::io::stdout()
-            .flush()
-            .expect("We should be able to flush stdout");
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "<br>")
+}
 
-        let mut input = String::new();
-        std::io::stdin()
-            .read_line(&mut input)
-            .expect("error: unable to read user input");
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        match input.trim().to_lowercase().as_ref() {
-            "yes" | "true" | "ok" => return true,
-            "no" | "false" => return false,
-            _ => {}
-        }
-        println!(
-            "\nSorry, {} isn't one of the expected values, please answer yes or no.",
-            input.trim()
+    #[test]
+    fn test_mute_from_mode() {
+        assert_eq!(
+            mute_from_mode(Some(RoomNotificationMode::Mute)),
+            Some(u64::MAX)
+        );
+        assert_eq!(
+            mute_from_mode(Some(RoomNotificationMode::AllMessages)),
+            None
         );
+        assert_eq!(
+            mute_from_mode(Some(RoomNotificationMode::MentionsAndKeywordsOnly)),
+            None
+        );
+        assert_eq!(mute_from_mode(None), None);
     }
-}
 
-async fn sas_verification_handler(sas: SasVerification) {
-    println!(
-        "Starting verification with {} {}",
-        &sas.other_device().user_id(),
-        &sas.other_device().device_id()
-    );
-    sas.accept().await.unwrap();
+    #[test]
+    fn test_supports_sso() {
+        let flows =
+            |types: serde_json::Value| -> Vec<LoginType> { serde_json::from_value(types).unwrap() };
+        assert!(supports_sso(&flows(serde_json::json!([
+            {"type": "m.login.password"},
+            {"type": "m.login.sso"},
+        ]))));
+        assert!(!supports_sso(&flows(serde_json::json!([
+            {"type": "m.login.password"},
+        ]))));
+        assert!(!supports_sso(&[]));
+    }
 
-    let mut stream = sas.changes();
+    #[test]
+    fn test_write_atomically() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("session.json");
+        std::fs::write(&path, "old").unwrap();
+        write_atomically(&path, "new").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        let files: Vec<_> = std::fs::read_dir(tmp.path()).unwrap().collect();
+        assert_eq!(files.len(), 1);
+    }
 
-    while let Some(state) = stream.next().await {
-        match state {
-            SasState::KeysExchanged {
-                emojis,
-                decimals: _,
-            } => {
-                let success = wait_for_confirmation(
-                    emojis
-                        .expect("We only support verifications using emojis")
-                        .emojis,
-                )
-                .await;
-                if success {
-                    sas.confirm().await.unwrap();
-                } else {
-                    sas.cancel().await.unwrap();
+    #[test]
+    fn test_keyring_name_from_account() {
+        // saved before the keyring name was kept in the file
+        let session: FullSession = serde_json::from_str(
+            r#"{
+                "client_session": {"homeserver": "https://matrix.org", "db_path": "/data/db"},
+                "user_session": {
+                    "user_id": "@alice:matrix.org",
+                    "device_id": "ABCDEF",
+                    "access_token": "secret"
                 }
-            }
-            SasState::Done { .. } => {
-                let device = sas.other_device();
-
-                println!(
-                    "Successfully verified device {} {} {:?}",
-                    device.user_id(),
-                    device.device_id(),
-                    device.local_trust_state()
-                );
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(session.keyring_name, None);
+        assert_eq!(
+            keyring_name(&session.user_session.unwrap()),
+            "@alice:matrix.org/ABCDEF"
+        );
+    }
 
-                break;
+    #[test]
+    fn test_reactions_and_redactions() {
+        let room = ContactId::Group(b"!room:matrix.org".to_vec());
+        let mut state = State::default();
+        let event = |id: &str, ts: u64, kind: &str, content: Value| {
+            serde_json::json!({
+                "event_id": id,
+                "sender": "@bob:matrix.org",
+                "origin_server_ts": ts,
+                "type": kind,
+                "content": content,
+            })
+        };
+
+        let message = event(
+            "$message",
+            10,
+            "m.room.message",
+            serde_json::json!({"body": "hi"}),
+        );
+        assert!(matches!(
+            state.timeline_event(&room, &message),
+            Some(FrontendMessage::NewMessage {
+                message: Message { timestamp: 10, .. }
+            })
+        ));
+
+        let reaction = event(
+            "$reaction",
+            20,
+            "m.reaction",
+            serde_json::json!({"m.relates_to": {"event_id": "$message", "key": "👍"}}),
+        );
+        let Some(FrontendMessage::NewMessage { message }) = state.timeline_event(&room, &reaction)
+        else {
+            panic!("reaction wasn't turned into a message");
+        };
+        assert!(matches!(
+            message.content,
+            MessageContent::Reaction { timestamp: 10, ref reaction, remove: false, .. } if reaction == "👍"
+        ));
+
+        // reactions to messages not seen are dropped
+        let unknown = event(
+            "$unknown",
+            25,
+            "m.reaction",
+            serde_json::json!({"m.relates_to": {"event_id": "$missing", "key": "👍"}}),
+        );
+        assert!(state.timeline_event(&room, &unknown).is_none());
+
+        // redacting the reaction takes it off again
+        let redaction = event(
+            "$redact1",
+            30,
+            "m.room.redaction",
+            serde_json::json!({"redacts": "$reaction"}),
+        );
+        let Some(FrontendMessage::NewMessage { message }) = state.timeline_event(&room, &redaction)
+        else {
+            panic!("redaction wasn't turned into a message");
+        };
+        assert!(matches!(
+            message.content,
+            MessageContent::Reaction {
+                timestamp: 10,
+                remove: true,
+                ..
             }
-            SasState::Cancelled(cancel_info) => {
-                println!(
-                    "The verification has been cancelled, reason: {}",
-                    cancel_info.reason()
-                );
+        ));
+
+        // and redacting the message removes it, found from older room versions' top level field
+        let mut redaction = event("$redact2", 40, "m.room.redaction", serde_json::json!({}));
+        redaction["redacts"] = "$message".into();
+        assert!(matches!(
+            state.timeline_event(&room, &redaction),
+            Some(FrontendMessage::RemovedMessage { timestamp: 10, .. })
+        ));
+        assert!(state.events.is_empty());
+    }
 
-                break;
+    #[test]
+    fn test_unsupported_events() {
+        let room = ContactId::Group(b"!room:matrix.org".to_vec());
+        let mut state = State::default();
+        let event = |kind: &str, content: serde_json::Value| {
+            serde_json::json!({
+                "event_id": "$event",
+                "sender": "@bob:matrix.org",
+                "origin_server_ts": 10,
+                "type": kind,
+                "content": content,
+            })
+        };
+        let mut content = |kind, content: serde_json::Value| {
+            let event = event(kind, content);
+            match state.timeline_event(&room, &event) {
+                Some(FrontendMessage::NewMessage { message }) => Some(message.content),
+                _ => None,
             }
-            SasState::Created { .. }
-            | SasState::Started { .. }
-            | SasState::Accepted { .. }
-            | SasState::Confirmed => (),
-        }
+        };
+        let link = "https://matrix.to/#/!room:matrix.org";
+        assert!(matches!(
+            content("m.call.invite", serde_json::json!({})),
+            Some(MessageContent::Call { missed: false, link: Some(ref l) }) if l == link
+        ));
+        assert!(matches!(
+            content("m.call.hangup", serde_json::json!({"reason": "invite_timeout"})),
+            Some(MessageContent::Call { missed: true, link: Some(ref l) }) if l == link
+        ));
+        // hanging up an answered call isn't worth showing
+        assert!(content("m.call.hangup", serde_json::json!({})).is_none());
+        assert!(matches!(
+            content("m.room.topic", serde_json::json!({})),
+            Some(MessageContent::Unsupported { ref description }) if description == "Room updated"
+        ));
+        // nothing to show at all for the rest
+        assert!(content("m.room.power_levels", serde_json::json!({})).is_none());
     }
 }
@@ -13,6 +13,19 @@ pub struct Arguments {
 
     #[clap(long)]
     config_file: Option<PathBuf>,
+
+    /// Record messages between the frontend and backend, and the keys pressed, to this file for
+    /// debugging.
+    #[clap(long, alias = "journal")]
+    record: Option<PathBuf>,
+
+    /// Listen for control requests from external tools on this unix socket.
+    #[clap(long)]
+    ipc_socket: Option<PathBuf>,
+
+    #[cfg(feature = "tracing")]
+    #[clap(flatten)]
+    trace: chatters_lib::telemetry::TraceArgs,
 }
 
 #[tokio::main]
@@ -25,6 +38,9 @@ async fn main() -> anyhow::Result<()> {
 
     let args = Arguments::parse();
 
+    #[cfg(feature = "tracing")]
+    let _tracing_guard = chatters_lib::telemetry::init_tracing(&args.trace);
+
     let config_file = match args.config_file {
         Some(cf) => cf,
         None => project_dirs.config_local_dir().join("config.toml"),
@@ -35,6 +51,9 @@ async fn main() -> anyhow::Result<()> {
         data_local_dir: data_local_dir.to_owned(),
         config_file,
         app_name: "chatters-matrix".to_owned(),
+        journal_file: args.record,
+        replay_file: None,
+        ipc_socket: args.ipc_socket,
     };
 
     util::run::<Matrix>(options).await;
@@ -9,16 +9,27 @@ use log::warn;
 use mime_guess::mime::APPLICATION_OCTET_STREAM;
 use presage::libsignal_service::content::Content;
 use presage::libsignal_service::content::ContentBody;
+use presage::libsignal_service::groups_v2::PendingMember;
+use presage::libsignal_service::prelude::ProfileKey;
 use presage::libsignal_service::prelude::Uuid;
+use presage::libsignal_service::proto::access_control::AccessRequired;
+use presage::libsignal_service::proto::data_message::Delete;
 use presage::libsignal_service::proto::data_message::Reaction;
+use presage::libsignal_service::proto::member::Role;
 use presage::libsignal_service::proto::DataMessage;
 use presage::libsignal_service::protocol::ServiceId;
 use presage::libsignal_service::sender::AttachmentSpec;
 use presage::libsignal_service::zkgroup::GroupMasterKeyBytes;
+use presage::proto::body_range;
 use presage::proto::body_range::AssociatedValue;
+use presage::proto::receipt_message;
+use presage::proto::sync_message::call_event;
 use presage::proto::sync_message::Sent;
+use presage::proto::typing_message;
+use presage::proto::verified;
 use presage::proto::AttachmentPointer;
 use presage::proto::BodyRange;
+use presage::proto::CallMessage;
 use presage::proto::EditMessage;
 use presage::proto::SyncMessage;
 use presage::store::Thread;
@@ -27,35 +38,72 @@ use presage::{
     model::identity::OnNewIdentity, store::ContentsStore, Manager,
 };
 use presage_store_sled::{MigrationConflictStrategy, SledStore};
+use std::collections::BTreeMap;
 use std::fs::create_dir_all;
 use std::ops::Bound;
+use std::ops::Range;
 use std::path::Path;
 use std::path::PathBuf;
-use url::Url;
+use std::sync::Arc;
+use std::sync::Mutex;
 
+use chatters_lib::backends::sort_contacts;
 use chatters_lib::backends::timestamp;
 use chatters_lib::backends::Backend;
+use chatters_lib::backends::Connectivity;
 use chatters_lib::backends::Contact;
+use chatters_lib::backends::ContactDetails;
 use chatters_lib::backends::ContactId;
 use chatters_lib::backends::Error;
+use chatters_lib::backends::FormatKind;
+use chatters_lib::backends::Formatting;
+use chatters_lib::backends::Invite;
+use chatters_lib::backends::Limits;
+use chatters_lib::backends::LoadProgress;
+use chatters_lib::backends::LoadStatus;
+use chatters_lib::backends::Mention;
 use chatters_lib::backends::Message;
 use chatters_lib::backends::MessageAttachment;
 use chatters_lib::backends::MessageContent;
+use chatters_lib::backends::Progress;
 use chatters_lib::backends::Quote;
+use chatters_lib::backends::Receipt;
 use chatters_lib::backends::Result;
+use chatters_lib::backends::Setup;
+use chatters_lib::backends::SetupQuestion;
+use chatters_lib::backends::Verification;
+use chatters_lib::backoff::Backoff;
 use chatters_lib::message::FrontendMessage;
+use chatters_lib::pins::PinStore;
+use chatters_lib::receipts::{ReceiptKind, ReceiptStore};
+
+/// How many messages of the initial sync to take in before refreshing the contacts.
+const INITIAL_SYNC_BATCH: u64 = 200;
 
 #[derive(Debug, Clone)]
 pub struct Signal {
     manager: Manager<SledStore, Registered>,
     self_uuid: Uuid,
     self_name: String,
-    attachment_pointers: Vec<AttachmentPointer>,
+    /// Shared between clones, so that attachments seen by one can be downloaded by another.
+    attachment_pointers: Arc<Mutex<Vec<AttachmentPointer>>>,
     attachments_dir: PathBuf,
+    pins: PinStore,
+    receipts: ReceiptStore,
+    /// Users found by looking them up, listed along with the named contacts.
+    found: Arc<Mutex<Vec<Contact>>>,
+    /// Where the found users are kept between runs.
+    found_file: PathBuf,
+    /// The time of the latest message in each conversation, so that loading the contacts doesn't
+    /// go through the messages of every conversation each time.
+    latest: LatestMessages,
 }
 
 impl Backend for Signal {
-    async fn load(path: &Path) -> Result<Self> {
+    const NAME: &'static str = "Signal";
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn load(path: &Path, _progress: LoadProgress) -> Result<Self> {
         info!(path:? = path; "Loading signal backend");
         let db_path = path.join("db");
         let config_store = SledStore::open(
@@ -71,18 +119,9 @@ impl Backend for Signal {
             Err(_err) => return Err(Error::Unlinked),
         };
 
-        let messages = manager.receive_messages().await.unwrap();
-        pin_mut!(messages);
-        while let Some(message) = messages.next().await {
-            debug!(message:? = message; "Received message during load");
-            match message {
-                presage::model::messages::Received::QueueEmpty => break,
-                presage::model::messages::Received::Contacts => continue,
-                presage::model::messages::Received::Content(_) => {}
-            }
-        }
-
-        let self_uuid = manager.whoami().await.unwrap().aci;
+        // what was queued since the last run, which can take minutes on a busy account, is
+        // received in the background sync so that the stored conversations show straight away
+        let self_uuid = manager.registration_data().service_ids.aci;
         let self_name = self_name(&mut manager).await;
 
         let attachments_dir = path.join("attachments");
@@ -92,15 +131,24 @@ impl Backend for Signal {
             manager,
             self_uuid,
             self_name,
-            attachment_pointers: Vec::new(),
+            attachment_pointers: Arc::default(),
             attachments_dir,
+            pins: PinStore::load(&path.join("pins.json")),
+            receipts: ReceiptStore::load(&path.join("receipts.json")),
+            found: Arc::new(Mutex::new(load_found(&path.join("found.json")))),
+            found_file: path.join("found.json"),
+            latest: LatestMessages::default(),
         })
     }
 
+    fn setup_questions() -> Vec<SetupQuestion> {
+        Vec::new()
+    }
+
     async fn link(
         path: &Path,
-        device_name: &str,
-        provisioning_link_tx: oneshot::Sender<Url>,
+        setup: &Setup,
+        provisioning_link_tx: oneshot::Sender<String>,
     ) -> Result<Self> {
         let db_path = path.join("db");
         let config_store = SledStore::open(
@@ -110,14 +158,22 @@ impl Backend for Signal {
         )
         .await
         .unwrap();
-        let mut manager = Manager::link_secondary_device(
-            config_store,
-            SignalServers::Production,
-            device_name.to_owned(),
-            provisioning_link_tx,
+        let (url_tx, url_rx) = oneshot::channel();
+        let (manager, ()) = futures::future::join(
+            Manager::link_secondary_device(
+                config_store,
+                SignalServers::Production,
+                setup.device_name.clone(),
+                url_tx,
+            ),
+            async move {
+                if let Ok(url) = url_rx.await {
+                    let _ = provisioning_link_tx.send(url.to_string());
+                }
+            },
         )
-        .await
-        .unwrap();
+        .await;
+        let mut manager = manager.unwrap();
 
         let self_uuid = manager.whoami().await.unwrap().aci;
         let self_name = self_name(&mut manager).await;
@@ -129,8 +185,13 @@ impl Backend for Signal {
             manager,
             self_uuid,
             self_name,
-            attachment_pointers: Vec::new(),
+            attachment_pointers: Arc::default(),
             attachments_dir,
+            pins: PinStore::load(&path.join("pins.json")),
+            receipts: ReceiptStore::load(&path.join("receipts.json")),
+            found: Arc::new(Mutex::new(load_found(&path.join("found.json")))),
+            found_file: path.join("found.json"),
+            latest: LatestMessages::default(),
         })
     }
 
@@ -138,19 +199,100 @@ impl Backend for Signal {
         &mut self,
         ba_tx: mpsc::UnboundedSender<FrontendMessage>,
     ) -> Result<()> {
+        // the backlog from before starting is shown in batches rather than message by message
+        let mut initial_sync = true;
+        let progress = LoadProgress::new(ba_tx.clone());
+        let mut status = LoadStatus {
+            catching_up: true,
+            ..Default::default()
+        };
+        let mut backoff = Backoff::default();
+        let report = |connectivity| {
+            ba_tx
+                .unbounded_send(FrontendMessage::Connectivity { connectivity })
+                .unwrap();
+        };
         loop {
-            let messages = self.manager.receive_messages().await.unwrap();
+            let messages = match self.manager.receive_messages().await {
+                Ok(messages) => messages,
+                Err(error) => {
+                    warn!(error:%; "Failed to connect to Signal");
+                    report(Connectivity::Offline);
+                    backoff.wait().await;
+                    report(Connectivity::Reconnecting);
+                    continue;
+                }
+            };
             pin_mut!(messages);
             while let Some(message) = messages.next().await {
                 debug!(message:? = message; "Received message during background_sync");
                 match message {
-                    presage::model::messages::Received::QueueEmpty => {}
-                    presage::model::messages::Received::Contacts => {}
+                    presage::model::messages::Received::QueueEmpty => {
+                        if initial_sync {
+                            initial_sync = false;
+                            info!(synced = status.messages; "Finished initial sync");
+                            status.catching_up = false;
+                            progress.report(status);
+                            self.refresh_contacts(&ba_tx).await;
+                        }
+                        // anything queued while we were offline has now arrived
+                        backoff.reset();
+                        report(Connectivity::Online);
+                    }
+                    presage::model::messages::Received::Contacts => {
+                        // synced from the primary device, so the names can be shown
+                        if initial_sync {
+                            status.contacts_synced = true;
+                            progress.report(status);
+                        }
+                        self.refresh_contacts(&ba_tx).await;
+                    }
                     presage::model::messages::Received::Content(message) => {
-                        if let Some((msg, attachment_pointers)) =
-                            self.message_content_to_frontend_message(*message).await
+                        if let ContentBody::TypingMessage(typing) = &message.body {
+                            // group typing messages name the group by its id rather than the
+                            // master key it is known by here, so only show those in direct chats
+                            if typing.group_id.is_none() {
+                                let sender = message.metadata.sender.raw_uuid();
+                                let sender = sender.into_bytes().to_vec();
+                                let started = typing.action() == typing_message::Action::Started;
+                                ba_tx
+                                    .unbounded_send(FrontendMessage::Typing {
+                                        contact_id: ContactId::User(sender.clone()),
+                                        senders: if started { vec![sender] } else { Vec::new() },
+                                    })
+                                    .unwrap();
+                            }
+                            continue;
+                        }
+                        if let ContentBody::ReceiptMessage(receipt) = &message.body {
+                            let kind = match receipt.r#type() {
+                                receipt_message::Type::Delivery => ReceiptKind::Delivered,
+                                receipt_message::Type::Read | receipt_message::Type::Viewed => {
+                                    ReceiptKind::Read
+                                }
+                            };
+                            let recipient = message.metadata.sender.raw_uuid().into_bytes();
+                            if let Err(error) = self.receipts.record(
+                                &receipt.timestamp,
+                                &recipient,
+                                kind,
+                                message.metadata.timestamp,
+                            ) {
+                                warn!(error:%; "Failed to save message receipt");
+                            }
+                            continue;
+                        }
+                        if let Some(msg) = self.message_content_to_frontend_message(*message).await
                         {
-                            self.attachment_pointers.extend(attachment_pointers);
+                            self.latest.note_message(&msg);
+                            if initial_sync {
+                                status.messages += 1;
+                                progress.report(status);
+                                if status.messages % INITIAL_SYNC_BATCH == 0 {
+                                    self.refresh_contacts(&ba_tx).await;
+                                }
+                                continue;
+                            }
                             ba_tx
                                 .unbounded_send(FrontendMessage::NewMessage { message: msg })
                                 .unwrap();
@@ -158,9 +300,14 @@ impl Backend for Signal {
                     }
                 }
             }
+            // the websocket closed, receiving again will reconnect
+            report(Connectivity::Offline);
+            backoff.wait().await;
+            report(Connectivity::Reconnecting);
         }
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
     async fn users(&self) -> Result<Vec<Contact>> {
         let mut ret = Vec::new();
         let contacts = self.manager.store().contacts().await.unwrap();
@@ -178,25 +325,26 @@ impl Backend for Signal {
                 .last_message_timestamp(&Thread::Contact(contact.uuid))
                 .await;
             debug!(contact:? = contact; "Found contact");
-            ret.push(Contact {
-                id: ContactId::User(contact.uuid.into_bytes().to_vec()),
-                name,
-                address: contact
-                    .phone_number
-                    .map(|n| n.to_string())
-                    .unwrap_or_default(),
-                last_message_timestamp,
-                description: String::new(),
-            });
+            ret.push(to_contact(contact, name, last_message_timestamp));
+        }
+        for found in self.found.lock().unwrap().iter() {
+            if !ret.iter().any(|c| c.id == found.id) {
+                ret.push(found.clone());
+            }
         }
         Ok(ret)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
     async fn groups(&self) -> Result<Vec<Contact>> {
         let mut ret = Vec::new();
         let groups = self.manager.store().groups().await.unwrap();
         for group in groups {
             let (key, group) = group.unwrap();
+            if self.own_invite(&group.pending_members).is_some() {
+                // listed with the invites until joined
+                continue;
+            }
             let last_message_timestamp = self.last_message_timestamp(&Thread::Group(key)).await;
             debug!(group:? = group; "Found group");
             ret.push(Contact {
@@ -205,11 +353,17 @@ impl Backend for Signal {
                 address: String::new(),
                 last_message_timestamp,
                 description: group.description.unwrap_or_default(),
+                // see the note on contacts, local mutes are all that can be used for now
+                muted_until: None,
+                verification: Verification::Unverified,
+                members: Some(group.members.len()),
+                encrypted: true,
             });
         }
         Ok(ret)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
     async fn messages(
         &mut self,
         contact: ContactId,
@@ -230,10 +384,7 @@ impl Backend for Signal {
         for message in messages {
             match message {
                 Ok(message) => {
-                    if let Some((msg, attachment_pointers)) =
-                        self.message_content_to_frontend_message(message).await
-                    {
-                        self.attachment_pointers.extend(attachment_pointers);
+                    if let Some(msg) = self.message_content_to_frontend_message(message).await {
                         ret.push(msg)
                     }
                 }
@@ -245,6 +396,7 @@ impl Backend for Signal {
         Ok(ret)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, content, quoting)))]
     async fn send_message(
         &mut self,
         contact: ContactId,
@@ -254,28 +406,36 @@ impl Backend for Signal {
         let now = timestamp();
         let quote = quoting.map(|q| {
             let sender = Uuid::try_from(q.sender.clone()).unwrap();
+            let (text, body_ranges) = signal_body(&q.text, &q.mentions, &q.formatting);
             presage::proto::data_message::Quote {
                 id: Some(q.timestamp),
                 author_aci: Some(sender.to_string()),
-                text: Some(q.text.clone()),
+                text: Some(text),
                 attachments: Vec::new(),
-                body_ranges: Vec::new(),
+                body_ranges,
                 r#type: Some(presage::proto::data_message::quote::Type::Normal as i32),
             }
         });
         let content_body = match &content {
-            MessageContent::Text { text, attachments } => {
+            MessageContent::Text {
+                text,
+                attachments,
+                mentions,
+                formatting,
+            } => {
                 let attachments = if attachments.is_empty() {
                     Vec::new()
                 } else {
                     self.upload_attachments(attachments).await
                 };
+                let (body, body_ranges) = signal_body(text, mentions, formatting);
                 // TODO: copy attachments into local data dir if not already present
                 ContentBody::DataMessage(DataMessage {
-                    body: Some(text.clone()),
+                    body: Some(body),
                     timestamp: Some(now),
                     quote,
                     attachments,
+                    body_ranges,
                     ..Default::default()
                 })
             }
@@ -298,17 +458,22 @@ impl Backend for Signal {
                     ..Default::default()
                 })
             }
-            MessageContent::Edit {
-                timestamp: _,
-                text: _,
-            } => {
+            MessageContent::Edit { .. } => {
                 todo!()
             }
+            MessageContent::Unsupported { .. } | MessageContent::Call { .. } => {
+                return Err(Error::Failure(
+                    "Failed to send message".to_owned(),
+                    "calls and unsupported messages can't be sent".to_owned(),
+                ))
+            }
         };
         let quote = quoting.map(|quoted| Quote {
             timestamp: quoted.timestamp,
             sender: quoted.sender.clone(),
             text: quoted.text.clone(),
+            mentions: quoted.mentions.clone(),
+            formatting: quoted.formatting.clone(),
         });
         let ui_msg = Message {
             timestamp: now,
@@ -333,24 +498,42 @@ impl Backend for Signal {
                     .unwrap();
             }
         }
+        self.latest.note_message(&ui_msg);
         Ok(ui_msg)
     }
 
     async fn self_id(&self) -> Vec<u8> {
-        debug!("Getting self_uuid");
-        self.manager
-            .whoami()
-            .await
-            .unwrap()
-            .aci
-            .into_bytes()
-            .to_vec()
+        self.self_uuid.into_bytes().to_vec()
+    }
+
+    fn limits(&self) -> Limits {
+        Limits {
+            // longer bodies are sent as a text attachment by the official clients
+            max_body_length: Some(2_000),
+            max_attachment_size: Some(100_000_000),
+            max_attachment_count: Some(32),
+            any_reaction: false,
+        }
     }
 
-    async fn download_attachment(&self, attachment_index: usize) -> Result<PathBuf> {
-        let Some(attachment_pointer) = self.attachment_pointers.get(attachment_index) else {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn download_attachment(
+        &self,
+        attachment_index: usize,
+        progress: Progress,
+    ) -> Result<PathBuf> {
+        let attachment_pointer = self
+            .attachment_pointers
+            .lock()
+            .unwrap()
+            .get(attachment_index)
+            .cloned();
+        let Some(attachment_pointer) = &attachment_pointer else {
             return Err(Error::UnknownAttachment(attachment_index));
         };
+        // the attachment comes all at once
+        let size = attachment_pointer.size.map_or(0, u64::from);
+        progress.report(0, size);
         let attachment_data = match self.manager.get_attachment(attachment_pointer).await {
             Ok(ad) => ad,
             Err(error) => {
@@ -358,6 +541,7 @@ impl Backend for Signal {
                 return Err(Error::Failure("Failed to fetch attachment".to_owned(), error.to_string()));
             }
         };
+        progress.report(attachment_data.len() as u64, attachment_data.len() as u64);
 
         let file_name = self.attachment_name(attachment_pointer);
         let file_path = self.attachments_dir.join(&file_name);
@@ -375,10 +559,376 @@ impl Backend for Signal {
             }
         }
     }
+
+    async fn pin_message(
+        &mut self,
+        contact_id: ContactId,
+        timestamp: u64,
+        pinned: bool,
+    ) -> Result<()> {
+        // signal has no pinned messages so keep them locally
+        self.pins
+            .set_pinned(&contact_id, timestamp, pinned)
+            .map_err(|e| Error::Failure("Failed to save pinned messages".to_owned(), e.to_string()))
+    }
+
+    async fn delete_message(&mut self, contact_id: ContactId, target: u64) -> Result<()> {
+        let now = timestamp();
+        let content_body = ContentBody::DataMessage(DataMessage {
+            delete: Some(Delete {
+                target_sent_timestamp: Some(target),
+            }),
+            timestamp: Some(now),
+            ..Default::default()
+        });
+        let result = match contact_id {
+            ContactId::User(id) => {
+                let uuid = Uuid::try_from(id).unwrap();
+                self.manager
+                    .send_message(ServiceId::Aci(uuid.into()), content_body, now)
+                    .await
+            }
+            ContactId::Group(key) => {
+                self.manager
+                    .send_message_to_group(&key, content_body, now)
+                    .await
+            }
+        };
+        result.map_err(|e| Error::Failure("Failed to delete message".to_owned(), e.to_string()))
+    }
+
+    async fn pinned_messages(&self, contact_id: ContactId) -> Result<Vec<u64>> {
+        Ok(self.pins.pinned(&contact_id))
+    }
+
+    async fn find_user(&mut self, query: String) -> Result<Contact> {
+        let failure = |e: &dyn std::fmt::Display| {
+            Error::Failure("Failed to find user".to_owned(), e.to_string())
+        };
+        let query = query.trim();
+        let phone: String = query
+            .chars()
+            .filter(|c| c.is_ascii_digit() || *c == '+')
+            .collect();
+        let aci = Uuid::parse_str(query).ok();
+        // contacts without names aren't listed, but are known from messages and the last sync
+        let contacts = self
+            .manager
+            .store()
+            .contacts()
+            .await
+            .map_err(|e| failure(&e))?;
+        let known = contacts.filter_map(|c| c.ok()).find(|c| {
+            Some(c.uuid) == aci
+                || (phone.starts_with('+')
+                    && c.phone_number.as_ref().map(|n| n.to_string()) == Some(phone.clone()))
+        });
+        let contact = match (known, aci) {
+            (Some(contact), _) => {
+                let name = if contact.name.is_empty() {
+                    contact
+                        .phone_number
+                        .as_ref()
+                        .map_or_else(|| contact.uuid.to_string(), |n| n.to_string())
+                } else {
+                    contact.name.clone()
+                };
+                let last_message_timestamp = self
+                    .last_message_timestamp(&Thread::Contact(contact.uuid))
+                    .await;
+                to_contact(contact, name, last_message_timestamp)
+            }
+            // messages can be sent to anyone by their ACI
+            (None, Some(aci)) => Contact {
+                id: ContactId::User(aci.into_bytes().to_vec()),
+                name: aci.to_string(),
+                address: String::new(),
+                last_message_timestamp: None,
+                description: String::new(),
+                muted_until: None,
+                verification: Verification::Unverified,
+                members: None,
+                encrypted: true,
+            },
+            // presage has no client for the contact discovery service (CDSI) or username lookups
+            // yet, so only contacts already synced or given by ACI can be found
+            (None, None) => {
+                return Err(failure(&format!(
+                    "{query} isn't a known phone number or an ACI, and presage can't look up others"
+                )))
+            }
+        };
+        let mut found = self.found.lock().unwrap();
+        if !found.iter().any(|c| c.id == contact.id) {
+            found.push(contact.clone());
+            save_found(&self.found_file, &found);
+        }
+        Ok(contact)
+    }
+
+    async fn message_receipts(
+        &self,
+        _contact_id: ContactId,
+        timestamp: u64,
+    ) -> Result<Vec<Receipt>> {
+        Ok(self.receipts.receipts(timestamp))
+    }
+
+    async fn contact_details(&self, contact_id: ContactId) -> Result<ContactDetails> {
+        let failure = |e: &dyn std::fmt::Display| {
+            Error::Failure("Failed to load contact details".to_owned(), e.to_string())
+        };
+        let mut details = ContactDetails::default();
+        match contact_id {
+            ContactId::User(id) => {
+                let contacts = self
+                    .manager
+                    .store()
+                    .contacts()
+                    .await
+                    .map_err(|e| failure(&e))?;
+                let Some(contact) = contacts
+                    .filter_map(|c| c.ok())
+                    .find(|c| c.uuid.as_bytes().as_slice() == id)
+                else {
+                    return Ok(details);
+                };
+                details.disappearing_timer =
+                    (contact.expire_timer > 0).then_some(contact.expire_timer.into());
+                let Ok(key) = <[u8; 32]>::try_from(contact.profile_key.as_slice()) else {
+                    return Ok(details);
+                };
+                // only what has been fetched before, fetching needs the manager mutably
+                if let Ok(Some(profile)) = self
+                    .manager
+                    .store()
+                    .profile(contact.uuid, ProfileKey::create(key))
+                    .await
+                {
+                    let fields = [
+                        ("About", profile.about),
+                        ("About emoji", profile.about_emoji),
+                    ];
+                    details.profile.extend(
+                        fields
+                            .into_iter()
+                            .filter_map(|(name, value)| Some((name.to_owned(), value?))),
+                    );
+                }
+            }
+            ContactId::Group(key) => {
+                let groups = self
+                    .manager
+                    .store()
+                    .groups()
+                    .await
+                    .map_err(|e| failure(&e))?;
+                let Some((_, group)) = groups
+                    .filter_map(|g| g.ok())
+                    .find(|(k, _)| k.as_slice() == key)
+                else {
+                    return Ok(details);
+                };
+                details.disappearing_timer = group
+                    .disappearing_messages_timer
+                    .map(|t| t.duration.into())
+                    .filter(|d| *d > 0);
+                details.role = group
+                    .members
+                    .iter()
+                    .find(|m| m.uuid == self.self_uuid)
+                    .map(|m| match m.role {
+                        Role::Administrator => "admin".to_owned(),
+                        _ => "member".to_owned(),
+                    });
+            }
+        }
+        Ok(details)
+    }
+
+    async fn verify_device(&mut self) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support verifying devices", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn confirm_verification(&mut self, _confirmed: bool) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support verifying devices", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn invites(&self) -> Result<Vec<Invite>> {
+        let groups = self
+            .manager
+            .store()
+            .groups()
+            .await
+            .map_err(|e| Error::Failure("Failed to load invites".to_owned(), e.to_string()))?;
+        let mut invites = Vec::new();
+        for (key, group) in groups.filter_map(|g| g.ok()) {
+            let Some(pending) = self.own_invite(&group.pending_members) else {
+                continue;
+            };
+            let inviter = match self
+                .manager
+                .store()
+                .contact_by_id(&pending.added_by_uuid)
+                .await
+            {
+                Ok(Some(contact)) => contact.name,
+                _ => String::new(),
+            };
+            invites.push(Invite {
+                contact_id: ContactId::Group(key.to_vec()),
+                name: group.title,
+                inviter,
+            });
+        }
+        Ok(invites)
+    }
+
+    async fn answer_invite(&mut self, _contact_id: ContactId, _accept: bool) -> Result<()> {
+        // like other group changes, presage can't send these yet
+        Err(Error::Failure(
+            format!("{} doesn't support answering invites yet", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn set_group_title(&mut self, contact_id: ContactId, _title: String) -> Result<()> {
+        self.change_group_attributes(contact_id, "change the title of this group")
+            .await
+    }
+
+    async fn set_group_description(
+        &mut self,
+        contact_id: ContactId,
+        _description: String,
+    ) -> Result<()> {
+        self.change_group_attributes(contact_id, "change the description of this group")
+            .await
+    }
+
+    async fn request_contact_sync(&mut self) -> Result<()> {
+        // the primary device replies with them, which the background sync picks up
+        self.manager
+            .request_contacts()
+            .await
+            .map_err(|e| Error::Failure("Failed to request contacts".to_owned(), e.to_string()))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn conversation_summaries(&self) -> Result<Vec<Message>> {
+        let mut threads = Vec::new();
+        for contact in self.manager.store().contacts().await.unwrap() {
+            threads.push(Thread::Contact(contact.unwrap().uuid));
+        }
+        for group in self.manager.store().groups().await.unwrap() {
+            threads.push(Thread::Group(group.unwrap().0));
+        }
+        let mut summaries = Vec::new();
+        for thread in threads {
+            if let Some(message) = self.latest_text_message(&thread).await {
+                summaries.push(message);
+            }
+        }
+        Ok(summaries)
+    }
 }
 
 impl Signal {
+    /// Give the ui the contacts with their latest message times, most recent first as when
+    /// loading them, which also reloads the messages of the selected one.
+    async fn refresh_contacts(&self, ba_tx: &mpsc::UnboundedSender<FrontendMessage>) {
+        let mut contacts = self.users().await.unwrap();
+        contacts.append(&mut self.groups().await.unwrap());
+        sort_contacts(&mut contacts);
+        ba_tx
+            .unbounded_send(FrontendMessage::LoadedContacts { contacts })
+            .unwrap();
+    }
+
+    /// The user's invite to a group they aren't a member of yet.
+    fn own_invite<'a>(&self, pending_members: &'a [PendingMember]) -> Option<&'a PendingMember> {
+        pending_members
+            .iter()
+            .find(|m| m.address.raw_uuid() == self.self_uuid)
+    }
+
+    /// Check the user may change the title and description of the group, which its access control
+    /// can limit to admins.
+    async fn change_group_attributes(&self, contact_id: ContactId, what: &str) -> Result<()> {
+        let failure = |e: &dyn std::fmt::Display| {
+            Error::Failure("Failed to change group".to_owned(), e.to_string())
+        };
+        let ContactId::Group(key) = contact_id else {
+            return Err(failure(&"not a group"));
+        };
+        let groups = self
+            .manager
+            .store()
+            .groups()
+            .await
+            .map_err(|e| failure(&e))?;
+        let Some((_, group)) = groups
+            .filter_map(|g| g.ok())
+            .find(|(k, _)| k.as_slice() == key)
+        else {
+            return Err(failure(&"unknown group"));
+        };
+        let admins_only = group
+            .access_control
+            .is_some_and(|a| a.attributes == AccessRequired::Administrator);
+        let is_admin = group
+            .members
+            .iter()
+            .any(|m| m.uuid == self.self_uuid && m.role == Role::Administrator);
+        if admins_only && !is_admin {
+            return Err(Error::NotAllowed(what.to_owned()));
+        }
+        // presage can only read groups so far, sending a group change needs the encrypted
+        // actions to be built here first
+        Err(Error::Failure(
+            format!("{} doesn't support changing groups yet", Self::NAME),
+            String::new(),
+        ))
+    }
+
     async fn last_message_timestamp(&self, thread_id: &Thread) -> Option<u64> {
+        let contact_id = match thread_id {
+            Thread::Contact(uuid) => ContactId::User(uuid.into_bytes().to_vec()),
+            Thread::Group(key) => ContactId::Group(key.to_vec()),
+        };
+        if let Some(timestamp) = self.latest.get(&contact_id) {
+            return timestamp;
+        }
+
+        // only walked the first time, after which receiving and sending keep it up to date
+        let messages = self
+            .manager
+            .store()
+            .messages(thread_id, ..)
+            .await
+            .unwrap()
+            .rev()
+            .map(|m| m.unwrap());
+        let mut timestamp = None;
+        for msg in messages {
+            let msg = self.message_content_to_frontend_message(msg).await;
+            if let Some(msg) = msg.filter(moves_conversation) {
+                timestamp = Some(msg.timestamp);
+                break;
+            }
+        }
+        // messages received while walking are newer
+        self.latest.note(contact_id, timestamp)
+    }
+
+    /// The latest message with text or attachments, going back past reactions and edits.
+    async fn latest_text_message(&self, thread_id: &Thread) -> Option<Message> {
         let messages = self
             .manager
             .store()
@@ -388,20 +938,24 @@ impl Signal {
             .rev()
             .map(|m| m.unwrap());
         for msg in messages {
-            if let Some((msg, _)) = self.message_content_to_frontend_message(msg).await {
-                return Some(msg.timestamp);
+            let msg = self.message_content_to_frontend_message(msg).await;
+            if let Some(msg) = msg.filter(|m| matches!(m.content, MessageContent::Text { .. })) {
+                return Some(msg);
             }
         }
         None
     }
 
-    async fn message_content_to_frontend_message(
-        &self,
-        message: Content,
-    ) -> Option<(Message, Vec<AttachmentPointer>)> {
+    async fn message_content_to_frontend_message(&self, message: Content) -> Option<Message> {
         debug!(message:? = message; "Converting message to frontend message");
         let timestamp = message.metadata.timestamp;
-        let thread = Thread::try_from(&message).unwrap();
+        let thread = match Thread::try_from(&message) {
+            Ok(thread) => thread,
+            Err(error) => {
+                warn!(error:%, timestamp; "Failed to find the conversation of a message");
+                return None;
+            }
+        };
         let sender = message.metadata.sender.raw_uuid();
         if let Some(m) = self
             .signal_message_to_message(timestamp, sender, thread, &message)
@@ -419,13 +973,50 @@ impl Signal {
         sender: Uuid,
         thread: Thread,
         content: &Content,
-    ) -> Option<(Message, Vec<AttachmentPointer>)> {
+    ) -> Option<Message> {
         match &content.body {
             ContentBody::DataMessage(dm) => {
                 return self
                     .data_message_to_message(timestamp, sender, thread, dm)
                     .await
             }
+            ContentBody::CallMessage(CallMessage { offer: Some(_), .. }) => {
+                let msg = Message {
+                    timestamp,
+                    sender: sender.into_bytes().to_vec(),
+                    contact_id: match thread {
+                        Thread::Contact(uuid) => ContactId::User(uuid.into_bytes().to_vec()),
+                        Thread::Group(key) => ContactId::Group(key.to_vec()),
+                    },
+                    content: MessageContent::Call {
+                        missed: false,
+                        link: None,
+                    },
+                    quote: None,
+                };
+                Some(msg)
+            }
+            // the primary device telling us about a call from someone that wasn't answered
+            ContentBody::SynchronizeMessage(SyncMessage {
+                call_event: Some(event),
+                ..
+            }) if event.direction() == call_event::Direction::Incoming
+                && event.event() == call_event::Event::NotAccepted =>
+            {
+                // group calls are identified differently to the groups, so only 1:1 calls
+                let caller = Uuid::from_slice(event.conversation_id.as_deref()?).ok()?;
+                let msg = Message {
+                    timestamp: event.timestamp.unwrap_or(timestamp),
+                    sender: caller.into_bytes().to_vec(),
+                    contact_id: ContactId::User(caller.into_bytes().to_vec()),
+                    content: MessageContent::Call {
+                        missed: true,
+                        link: None,
+                    },
+                    quote: None,
+                };
+                Some(msg)
+            }
             ContentBody::SynchronizeMessage(SyncMessage {
                 sent: Some(Sent {
                     message: Some(dm), ..
@@ -443,14 +1034,17 @@ impl Signal {
                             Some(EditMessage {
                                 target_sent_timestamp: Some(target_sent_timestamp),
                                 data_message:
-                                    Some(DataMessage {
-                                        body: Some(text), ..
-                                    }),
+                                    Some(
+                                        dm @ DataMessage {
+                                            body: Some(text), ..
+                                        },
+                                    ),
                             }),
                         ..
                     }),
                 ..
             }) => {
+                let (formatting, mentions) = body_ranges(text, &dm.body_ranges);
                 let msg = Message {
                     timestamp: *target_sent_timestamp,
                     sender: sender.into_bytes().to_vec(),
@@ -461,10 +1055,12 @@ impl Signal {
                     content: MessageContent::Edit {
                         timestamp,
                         text: text.clone(),
+                        mentions,
+                        formatting,
                     },
                     quote: None,
                 };
-                Some((msg, Vec::new()))
+                Some(msg)
             }
             _ => None,
         }
@@ -476,7 +1072,7 @@ impl Signal {
         sender: Uuid,
         thread: Thread,
         dm: &DataMessage,
-    ) -> Option<(Message, Vec<AttachmentPointer>)> {
+    ) -> Option<Message> {
         let mut message = Message {
             timestamp,
             sender: sender.into_bytes().to_vec(),
@@ -487,23 +1083,30 @@ impl Signal {
             content: MessageContent::Text {
                 text: String::new(),
                 attachments: Vec::new(),
+                mentions: Vec::new(),
+                formatting: Vec::new(),
             },
             quote: None,
         };
 
         if dm.body.is_some() || !dm.attachments.is_empty() || dm.quote.is_some() {
-            assert!(dm.reaction.is_none());
-            let mut attachment_pointers = Vec::new();
+            if dm.reaction.is_some() {
+                warn!(timestamp; "Ignoring the reaction sent along with a message");
+            }
             let attachments =
                 dm.attachments
                     .iter()
                     .map(|attachment_pointer| {
-                        let attachment_index = self.attachment_pointers.len();
+                        let attachment_index = {
+                            let mut attachment_pointers = self.attachment_pointers.lock().unwrap();
+                            attachment_pointers.push(attachment_pointer.clone());
+                            attachment_pointers.len() - 1
+                        };
                         let filename = attachment_pointer.file_name.clone().unwrap_or_else(|| {
                             Local::now().format("%Y-%m-%d-%H-%M-%s").to_string()
                         });
-                        let size = attachment_pointer.size.unwrap() as u64;
-                        attachment_pointers.push(attachment_pointer.clone());
+                        // shown as empty until downloaded when the size is missing
+                        let size = attachment_pointer.size.map_or(0, u64::from);
                         let attachment_name = self.attachment_name(attachment_pointer);
                         let attachment_path = self.attachments_dir.join(&attachment_name);
                         let downloaded_path = if attachment_path.is_file() {
@@ -516,68 +1119,88 @@ impl Signal {
                             index: attachment_index,
                             size,
                             path: downloaded_path,
+                            content_type: attachment_pointer.content_type.clone(),
                         }
                     })
                     .collect();
 
-            let mut body = dm.body().to_owned();
-            self.add_body_ranges(&mut body, &dm.body_ranges).await;
+            let (formatting, mentions) = body_ranges(dm.body(), &dm.body_ranges);
 
             message.content = MessageContent::Text {
-                text: body,
+                text: dm.body().to_owned(),
                 attachments,
+                mentions,
+                formatting,
             };
             if let Some(quote) = &dm.quote {
-                let mut text = quote.text().to_owned();
-                self.add_body_ranges(&mut text, &quote.body_ranges).await;
-                let author_uuid: Uuid = quote.author_aci().parse().unwrap();
-                message.quote = Some(Quote {
-                    timestamp: quote.id(),
-                    sender: author_uuid.into_bytes().to_vec(),
-                    text,
-                });
-            }
-            return Some((message, attachment_pointers));
+                let (formatting, mentions) = body_ranges(quote.text(), &quote.body_ranges);
+                // replying to a quote without an author would fail, so it is left off
+                match quote.author_aci().parse::<Uuid>() {
+                    Ok(author_uuid) => {
+                        message.quote = Some(Quote {
+                            timestamp: quote.id(),
+                            sender: author_uuid.into_bytes().to_vec(),
+                            text: quote.text().to_owned(),
+                            mentions,
+                            formatting,
+                        })
+                    }
+                    Err(error) => {
+                        warn!(error:%, timestamp; "Dropping quote with an invalid author")
+                    }
+                }
+            }
+            // the story replied to isn't shown, so it is quoted to tell the reply apart
+            if let Some(story) = &dm.story_context {
+                if let Ok(author_uuid) = story.author_aci().parse::<Uuid>() {
+                    message.quote = Some(Quote {
+                        timestamp: story.sent_timestamp(),
+                        sender: author_uuid.into_bytes().to_vec(),
+                        text: "Story".to_owned(),
+                        mentions: Vec::new(),
+                        formatting: Vec::new(),
+                    });
+                }
+            }
+            return Some(message);
         } else if let Some(r) = &dm.reaction {
-            assert!(dm.body.is_none());
-            assert!(dm.attachments.is_empty());
             let emoji = r.emoji.clone()?;
-            let author_uuid: Uuid = r.target_author_aci.as_ref().unwrap().parse().unwrap();
+            if dm.story_context.is_some() {
+                // the story isn't among the messages to show the reaction on
+                return Some(unsupported_message(
+                    message,
+                    &format!("Reacted {emoji} to a story"),
+                ));
+            }
+            let author_uuid = r
+                .target_author_aci
+                .as_deref()
+                .and_then(|aci| aci.parse::<Uuid>().ok());
+            let (Some(author_uuid), Some(target_timestamp)) =
+                (author_uuid, r.target_sent_timestamp)
+            else {
+                warn!(timestamp; "Reaction without a valid target");
+                return Some(unsupported_message(message, "Unsupported message"));
+            };
             message.content = MessageContent::Reaction {
                 message_author: author_uuid.into_bytes().to_vec(),
-                timestamp: r.target_sent_timestamp.unwrap(),
+                timestamp: target_timestamp,
                 reaction: emoji,
                 remove: r.remove(),
             };
-            return Some((message, Vec::new()));
+            return Some(message);
+        } else if dm.payment.is_some() {
+            return Some(unsupported_message(message, "💸 Payment"));
+        } else if dm
+            .group_v2
+            .as_ref()
+            .is_some_and(|g| g.group_change.is_some())
+        {
+            return Some(unsupported_message(message, "Group updated"));
         }
         None
     }
 
-    async fn add_body_ranges(&self, body: &mut String, ranges: &[BodyRange]) {
-        for body_range in ranges {
-            if let Some(AssociatedValue::MentionAci(aci)) = &body_range.associated_value {
-                let user_uuid: Uuid = aci.parse().unwrap();
-                if let Some(user) = self
-                    .manager
-                    .store()
-                    .contact_by_id(&user_uuid)
-                    .await
-                    .unwrap()
-                {
-                    let username = format!("@{:?}", user.name);
-                    let start = body_range.start.unwrap() as usize;
-                    let end = start + body_range.length.unwrap() as usize;
-                    let char_indices = body.char_indices().collect::<Vec<_>>();
-                    let start_boundary = body.char_indices().nth(start).unwrap().0;
-                    let end_boundary = body.char_indices().nth(end).unwrap().0;
-                    debug!(body:?, start:?, end:?, username:?, char_indices:?, start_boundary:?, end_boundary:?; "Replacing body range");
-                    body.replace_range(start_boundary..end_boundary, &username);
-                }
-            }
-        }
-    }
-
     fn attachment_name(&self, attachment_pointer: &AttachmentPointer) -> String {
         let hash: String = hex::encode(attachment_pointer.digest())
             .chars()
@@ -652,6 +1275,54 @@ impl Signal {
     }
 }
 
+/// Load the users found by looking them up on earlier runs.
+fn load_found(path: &Path) -> Vec<Contact> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+            warn!(error:%, path:?; "Failed to parse found users, starting afresh");
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn save_found(path: &Path, found: &[Contact]) {
+    let result = serde_json::to_string(found)
+        .map_err(std::io::Error::from)
+        .and_then(|content| std::fs::write(path, content));
+    if let Err(error) = result {
+        warn!(error:%, path:?; "Failed to save found users");
+    }
+}
+
+fn to_contact(
+    contact: presage::model::contacts::Contact,
+    name: String,
+    last_message_timestamp: Option<u64>,
+) -> Contact {
+    Contact {
+        id: ContactId::User(contact.uuid.into_bytes().to_vec()),
+        name,
+        address: contact
+            .phone_number
+            .map(|n| n.to_string())
+            .unwrap_or_default(),
+        last_message_timestamp,
+        description: String::new(),
+        // mute settings live in the storage service, which presage doesn't sync, so mutes made on
+        // other devices aren't known here and chatters' own mutes are all that apply
+        muted_until: None,
+        verification: match contact.verified.state() {
+            verified::State::Verified => Verification::Verified,
+            // marked unverified when the safety number changes after verifying it
+            verified::State::Unverified => Verification::Changed,
+            verified::State::Default => Verification::Unverified,
+        },
+        members: None,
+        encrypted: true,
+    }
+}
+
 async fn self_name(manager: &mut Manager<SledStore, Registered>) -> String {
     manager
         .retrieve_profile()
@@ -667,3 +1338,388 @@ async fn self_name(manager: &mut Manager<SledStore, Registered>) -> String {
         })
         .unwrap_or("Self".to_owned())
 }
+
+/// Replace each mention in the text with the placeholder Signal expects, and give body ranges
+/// for who it is for and the formatting, with offsets in UTF-16 code units.
+fn signal_body(
+    text: &str,
+    mentions: &[Mention],
+    formatting: &[Formatting],
+) -> (String, Vec<BodyRange>) {
+    let mut body = String::new();
+    let mut body_ranges = Vec::new();
+    // where each char of the text ends up in the body, and the end of it
+    let mut offsets = Vec::new();
+    let mut chars = text.chars().enumerate().peekable();
+    for mention in mentions {
+        while let Some((_, c)) = chars.next_if(|(i, _)| *i < mention.start) {
+            offsets.push(body.encode_utf16().count());
+            body.push(c);
+        }
+        let Ok(aci) = Uuid::try_from(mention.id.clone()) else {
+            continue;
+        };
+        let start = body.encode_utf16().count();
+        // skip the `@name` the placeholder is shown as
+        while chars
+            .next_if(|(i, _)| *i < mention.start + mention.length)
+            .is_some()
+        {
+            offsets.push(start);
+        }
+        body_ranges.push(BodyRange {
+            start: Some(start as u32),
+            length: Some(1),
+            associated_value: Some(AssociatedValue::MentionAci(aci.to_string())),
+        });
+        body.push('\u{fffc}');
+    }
+    for (_, c) in chars {
+        offsets.push(body.encode_utf16().count());
+        body.push(c);
+    }
+    offsets.push(body.encode_utf16().count());
+
+    for f in formatting {
+        let (Some(start), Some(end)) = (offsets.get(f.start), offsets.get(f.start + f.length))
+        else {
+            continue;
+        };
+        let style = match f.kind {
+            FormatKind::Bold => body_range::Style::Bold,
+            FormatKind::Italic => body_range::Style::Italic,
+            FormatKind::Strikethrough => body_range::Style::Strikethrough,
+            FormatKind::Monospace => body_range::Style::Monospace,
+            FormatKind::Spoiler => body_range::Style::Spoiler,
+        };
+        body_ranges.push(BodyRange {
+            start: Some(*start as u32),
+            length: Some((end - start) as u32),
+            associated_value: Some(AssociatedValue::Style(style as i32)),
+        });
+    }
+    (body, body_ranges)
+}
+
+/// The formatting for a style body range, converting its offsets from UTF-16 code units to chars.
+/// Stand in for something that can't be shown as a message, so that it doesn't leave a gap in the
+/// conversation.
+fn unsupported_message(mut message: Message, description: &str) -> Message {
+    message.content = MessageContent::Unsupported {
+        description: description.to_owned(),
+    };
+    message
+}
+
+/// The styles and mentions of a body from its body ranges, converting their offsets from UTF-16
+/// code units to chars.
+///
+/// The body is kept as it is, with the placeholder Signal leaves where each mention goes for the
+/// ui to show the name of who is mentioned in its place.
+fn body_ranges(body: &str, ranges: &[BodyRange]) -> (Vec<Formatting>, Vec<Mention>) {
+    let formatting = ranges
+        .iter()
+        .filter_map(|body_range| style_formatting(body, body_range))
+        .collect();
+    let mut mentioned = Vec::new();
+    for body_range in ranges {
+        let Some(AssociatedValue::MentionAci(aci)) = &body_range.associated_value else {
+            continue;
+        };
+        let (Ok(user_uuid), Some(start), Some(length)) =
+            (aci.parse::<Uuid>(), body_range.start, body_range.length)
+        else {
+            warn!(body_range:?; "Ignoring invalid mention");
+            continue;
+        };
+        let start = start as usize;
+        let (Some(start), Some(end)) = (
+            utf16_to_byte(body, start),
+            utf16_to_byte(body, start + length as usize),
+        ) else {
+            warn!(body_range:?; "Ignoring mention outside of the body");
+            continue;
+        };
+        mentioned.push((start..end, user_uuid.into_bytes().to_vec()));
+    }
+    (formatting, mention_spans(body, mentioned))
+}
+
+/// The byte offset in the body of an offset from a body range, which counts utf-16 code units,
+/// `None` if it is past the end of the body or inside a char.
+fn utf16_to_byte(body: &str, utf16_offset: usize) -> Option<usize> {
+    let mut units = 0;
+    for (i, c) in body.char_indices() {
+        match units.cmp(&utf16_offset) {
+            std::cmp::Ordering::Less => units += c.len_utf16(),
+            std::cmp::Ordering::Equal => return Some(i),
+            std::cmp::Ordering::Greater => return None,
+        }
+    }
+    (units == utf16_offset).then_some(body.len())
+}
+
+/// The mentions of the body by chars from their byte ranges and who they are for, in order.
+///
+/// Mentions overlapping others are dropped.
+fn mention_spans(body: &str, mut mentioned: Vec<(Range<usize>, Vec<u8>)>) -> Vec<Mention> {
+    mentioned.sort_by_key(|(range, _)| range.start);
+    let mut mentions = Vec::new();
+    let mut mentioned_to = 0;
+    for (range, id) in mentioned {
+        if range.start < mentioned_to {
+            warn!(range:?; "Ignoring overlapping mention");
+            continue;
+        }
+        mentioned_to = range.end;
+        let start = body[..range.start].chars().count();
+        mentions.push(Mention {
+            start,
+            length: body[range].chars().count(),
+            id,
+        });
+    }
+    mentions
+}
+
+fn style_formatting(body: &str, body_range: &BodyRange) -> Option<Formatting> {
+    let Some(AssociatedValue::Style(style)) = body_range.associated_value else {
+        return None;
+    };
+    let kind = match body_range::Style::try_from(style).ok()? {
+        body_range::Style::None => return None,
+        body_range::Style::Bold => FormatKind::Bold,
+        body_range::Style::Italic => FormatKind::Italic,
+        body_range::Style::Spoiler => FormatKind::Spoiler,
+        body_range::Style::Strikethrough => FormatKind::Strikethrough,
+        body_range::Style::Monospace => FormatKind::Monospace,
+    };
+    let start = body_range.start? as usize;
+    let end = start + body_range.length? as usize;
+    let char_offset = |utf16_offset: usize| {
+        let mut units = 0;
+        body.chars()
+            .take_while(|c| {
+                units += c.len_utf16();
+                units <= utf16_offset
+            })
+            .count()
+    };
+    let start = char_offset(start);
+    Some(Formatting {
+        start,
+        length: char_offset(end) - start,
+        kind,
+    })
+}
+
+/// The time of the latest message in each conversation, none for those without.
+#[derive(Debug, Default, Clone)]
+struct LatestMessages(Arc<Mutex<BTreeMap<ContactId, Option<u64>>>>);
+
+impl LatestMessages {
+    /// The cached time for the conversation, if it has been found yet.
+    fn get(&self, contact_id: &ContactId) -> Option<Option<u64>> {
+        self.0.lock().unwrap().get(contact_id).copied()
+    }
+
+    /// Note the time of a message in the conversation, keeping the latest, and return it.
+    fn note(&self, contact_id: ContactId, timestamp: Option<u64>) -> Option<u64> {
+        let mut latest = self.0.lock().unwrap();
+        let latest = latest.entry(contact_id).or_default();
+        *latest = (*latest).max(timestamp);
+        *latest
+    }
+
+    /// Keep the time up to date with a message received or sent.
+    fn note_message(&self, message: &Message) {
+        if moves_conversation(message) {
+            self.note(message.contact_id.clone(), Some(message.timestamp));
+        }
+    }
+}
+
+/// Whether the message counts towards when the conversation was last active, which reactions and
+/// edits to earlier messages don't.
+fn moves_conversation(message: &Message) -> bool {
+    !matches!(
+        message.content,
+        MessageContent::Reaction { .. } | MessageContent::Edit { .. }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utf16_to_byte() {
+        let body = "a👍é";
+        assert_eq!(utf16_to_byte(body, 0), Some(0));
+        assert_eq!(utf16_to_byte(body, 1), Some(1));
+        // half of the surrogate pair
+        assert_eq!(utf16_to_byte(body, 2), None);
+        assert_eq!(utf16_to_byte(body, 3), Some(5));
+        assert_eq!(utf16_to_byte(body, 4), Some(body.len()));
+        assert_eq!(utf16_to_byte(body, 5), None);
+        assert_eq!(utf16_to_byte("", 0), Some(0));
+    }
+
+    fn mention(start: u32, length: u32, id: u128) -> BodyRange {
+        BodyRange {
+            start: Some(start),
+            length: Some(length),
+            associated_value: Some(AssociatedValue::MentionAci(Uuid::from_u128(id).to_string())),
+        }
+    }
+
+    fn mentioned(start: usize, id: u128) -> Mention {
+        Mention {
+            start,
+            length: 1,
+            id: Uuid::from_u128(id).into_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_body_ranges() {
+        // signal puts a placeholder char where each mention goes, which is kept for the ui
+        let body = "🎉🎉 hi \u{fffc} and \u{fffc}";
+        let bold = BodyRange {
+            start: Some(5),
+            length: Some(4),
+            associated_value: Some(AssociatedValue::Style(body_range::Style::Bold as i32)),
+        };
+        let (formatting, mentions) =
+            body_ranges(body, &[mention(14, 1, 2), bold, mention(8, 1, 1)]);
+        assert_eq!(mentions, vec![mentioned(6, 1), mentioned(12, 2)]);
+        let chars = body.chars().collect::<Vec<_>>();
+        let text =
+            |start: usize, length: usize| chars[start..start + length].iter().collect::<String>();
+        assert_eq!(text(mentions[0].start, mentions[0].length), "\u{fffc}");
+        assert_eq!(text(mentions[1].start, mentions[1].length), "\u{fffc}");
+        assert_eq!(
+            text(formatting[0].start, formatting[0].length),
+            "hi \u{fffc}"
+        );
+    }
+
+    #[test]
+    fn test_body_ranges_invalid_and_overlapping() {
+        let body = "👋 \u{fffc}";
+        let (_, mentions) = body_ranges(
+            body,
+            &[
+                mention(2, 2, 1),
+                mention(3, 1, 2),
+                // inside the surrogate pair of the emoji
+                mention(1, 1, 3),
+                mention(4, 1, 4),
+            ],
+        );
+        assert_eq!(
+            mentions,
+            vec![Mention {
+                start: 1,
+                length: 2,
+                id: Uuid::from_u128(1).into_bytes().to_vec(),
+            }]
+        );
+    }
+
+    fn message(contact: u8, timestamp: u64, content: MessageContent) -> Message {
+        Message {
+            timestamp,
+            sender: vec![contact],
+            contact_id: ContactId::User(vec![contact]),
+            content,
+            quote: None,
+        }
+    }
+
+    fn text(contact: u8, timestamp: u64) -> Message {
+        message(
+            contact,
+            timestamp,
+            MessageContent::Text {
+                text: "hi".to_owned(),
+                attachments: Vec::new(),
+                mentions: Vec::new(),
+                formatting: Vec::new(),
+            },
+        )
+    }
+
+    #[test]
+    fn test_latest_messages_only_move_forward() {
+        let latest = LatestMessages::default();
+        let alice = ContactId::User(vec![1]);
+        assert_eq!(latest.get(&alice), None);
+
+        latest.note_message(&text(1, 20));
+        assert_eq!(latest.get(&alice), Some(Some(20)));
+        // an older message, such as one synced late, keeps the newer time
+        latest.note_message(&text(1, 10));
+        assert_eq!(latest.get(&alice), Some(Some(20)));
+        // walking the store after a newer message came in keeps the newer one
+        assert_eq!(latest.note(alice.clone(), Some(15)), Some(20));
+        assert_eq!(latest.note(alice.clone(), None), Some(20));
+        latest.note_message(&text(1, 30));
+        assert_eq!(latest.get(&alice), Some(Some(30)));
+        // other conversations are kept apart
+        assert_eq!(latest.get(&ContactId::User(vec![2])), None);
+    }
+
+    #[test]
+    fn test_latest_messages_skip_reactions_and_edits() {
+        let latest = LatestMessages::default();
+        let alice = ContactId::User(vec![1]);
+        latest.note_message(&text(1, 10));
+        latest.note_message(&message(
+            1,
+            20,
+            MessageContent::Reaction {
+                message_author: vec![1],
+                timestamp: 10,
+                reaction: "👍".to_owned(),
+                remove: false,
+            },
+        ));
+        latest.note_message(&message(
+            1,
+            30,
+            MessageContent::Edit {
+                timestamp: 10,
+                text: "hello".to_owned(),
+                mentions: Vec::new(),
+                formatting: Vec::new(),
+            },
+        ));
+        assert_eq!(latest.get(&alice), Some(Some(10)));
+
+        // nor do they start the cache for a conversation that hasn't been walked yet
+        let bob = ContactId::User(vec![2]);
+        latest.note_message(&message(
+            2,
+            40,
+            MessageContent::Edit {
+                timestamp: 5,
+                text: "hello".to_owned(),
+                mentions: Vec::new(),
+                formatting: Vec::new(),
+            },
+        ));
+        assert_eq!(latest.get(&bob), None);
+    }
+
+    #[test]
+    fn test_latest_messages_without_messages() {
+        let latest = LatestMessages::default();
+        let alice = ContactId::User(vec![1]);
+        // walked and found nothing, which is cached so it isn't walked again
+        assert_eq!(latest.note(alice.clone(), None), None);
+        assert_eq!(latest.get(&alice), Some(None));
+        latest.note_message(&text(1, 10));
+        assert_eq!(latest.get(&alice), Some(Some(10)));
+    }
+}
@@ -0,0 +1,5 @@
+module github.com/jeffa5/chatters/crates/chatters-whatsapp/sidecar
+
+go 1.23
+
+// dependencies are resolved with `go mod tidy`
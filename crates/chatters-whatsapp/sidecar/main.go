@@ -0,0 +1,849 @@
+// Sidecar for chatters-whatsapp, speaking the WhatsApp multidevice protocol with whatsmeow and
+// talking line-delimited JSON-RPC 2.0 to chatters over stdin and stdout.
+//
+// Messages are kept in the same sqlite database as the whatsmeow store so that history is
+// available after the initial history sync.
+package main
+
+import (
+	"bufio"
+	"context"
+	"database/sql"
+	"encoding/base64"
+	"encoding/json"
+	"errors"
+	"flag"
+	"fmt"
+	"os"
+	"path/filepath"
+	"sync"
+	"time"
+
+	_ "github.com/mattn/go-sqlite3"
+	"go.mau.fi/whatsmeow"
+	"go.mau.fi/whatsmeow/proto/waCompanionReg"
+	"go.mau.fi/whatsmeow/proto/waE2E"
+	"go.mau.fi/whatsmeow/store"
+	"go.mau.fi/whatsmeow/store/sqlstore"
+	"go.mau.fi/whatsmeow/types"
+	"go.mau.fi/whatsmeow/types/events"
+	waLog "go.mau.fi/whatsmeow/util/log"
+	"google.golang.org/protobuf/proto"
+)
+
+type request struct {
+	ID     *uint64         `json:"id"`
+	Method string          `json:"method"`
+	Params json.RawMessage `json:"params"`
+}
+
+type contact struct {
+	JID                  string  `json:"jid"`
+	Name                 string  `json:"name"`
+	Description          string  `json:"description"`
+	LastMessageTimestamp *uint64 `json:"last_message_timestamp"`
+	MutedUntil           *uint64 `json:"muted_until"`
+}
+
+// details are more about a chat than is needed to list it.
+type details struct {
+	Role              string  `json:"role,omitempty"`
+	DisappearingTimer *uint64 `json:"disappearing_timer"`
+	Status            string  `json:"status,omitempty"`
+	BusinessName      string  `json:"business_name,omitempty"`
+}
+
+type attachment struct {
+	Name     string `json:"name"`
+	Size     uint64 `json:"size"`
+	Mimetype string `json:"mimetype,omitempty"`
+	Media    media  `json:"media"`
+}
+
+// media is enough to download an attachment again later.
+type media struct {
+	Kind  string `json:"kind"`
+	Proto string `json:"proto"`
+}
+
+type quote struct {
+	Sender    string `json:"sender"`
+	Timestamp uint64 `json:"timestamp"`
+	Text      string `json:"text"`
+}
+
+type message struct {
+	Chat            string       `json:"chat"`
+	Sender          string       `json:"sender"`
+	Timestamp       uint64       `json:"timestamp"`
+	Type            string       `json:"type"`
+	Text            string       `json:"text,omitempty"`
+	Attachments     []attachment `json:"attachments,omitempty"`
+	Quote           *quote       `json:"quote,omitempty"`
+	TargetSender    string       `json:"target_sender,omitempty"`
+	TargetTimestamp uint64       `json:"target_timestamp,omitempty"`
+	Reaction        string       `json:"reaction,omitempty"`
+	Remove          bool         `json:"remove,omitempty"`
+
+	id string
+}
+
+type sidecar struct {
+	client         *whatsmeow.Client
+	db             *sql.DB
+	attachmentsDir string
+
+	out      sync.Mutex
+	writer   *bufio.Writer
+	linkDone chan bool
+}
+
+func main() {
+	dbPath := flag.String("db", "whatsapp.db", "database to keep state in")
+	attachmentsDir := flag.String("attachments", "attachments", "directory to download attachments to")
+	flag.Parse()
+
+	ctx := context.Background()
+	// stdout is for talking to chatters
+	logger := waLog.Noop
+
+	dsn := "file:" + *dbPath + "?_foreign_keys=on"
+	container, err := sqlstore.New(ctx, "sqlite3", dsn, logger)
+	if err != nil {
+		fatal(err)
+	}
+	device, err := container.GetFirstDevice(ctx)
+	if err != nil {
+		fatal(err)
+	}
+	db, err := sql.Open("sqlite3", dsn)
+	if err != nil {
+		fatal(err)
+	}
+	_, err = db.Exec(`CREATE TABLE IF NOT EXISTS chatters_messages (
+		chat TEXT NOT NULL,
+		id TEXT NOT NULL,
+		sender TEXT NOT NULL,
+		timestamp INTEGER NOT NULL,
+		data TEXT NOT NULL,
+		PRIMARY KEY (chat, id)
+	)`)
+	if err != nil {
+		fatal(err)
+	}
+
+	s := &sidecar{
+		client:         whatsmeow.NewClient(device, logger),
+		db:             db,
+		attachmentsDir: *attachmentsDir,
+		writer:         bufio.NewWriter(os.Stdout),
+	}
+	s.client.AddEventHandler(s.handleEvent)
+
+	scanner := bufio.NewScanner(os.Stdin)
+	scanner.Buffer(make([]byte, 1024*1024), 16*1024*1024)
+	for scanner.Scan() {
+		var req request
+		if err := json.Unmarshal(scanner.Bytes(), &req); err != nil {
+			continue
+		}
+		go func() {
+			result, err := s.handle(ctx, req.Method, req.Params)
+			if req.ID == nil {
+				return
+			}
+			response := map[string]any{"jsonrpc": "2.0", "id": *req.ID}
+			if err != nil {
+				response["error"] = map[string]any{"code": -32000, "message": err.Error()}
+			} else {
+				response["result"] = result
+			}
+			s.write(response)
+		}()
+	}
+	s.client.Disconnect()
+}
+
+func fatal(err error) {
+	fmt.Fprintln(os.Stderr, err)
+	os.Exit(1)
+}
+
+func (s *sidecar) write(value any) {
+	line, _ := json.Marshal(value)
+	s.out.Lock()
+	defer s.out.Unlock()
+	s.writer.Write(line)
+	s.writer.WriteByte('\n')
+	s.writer.Flush()
+}
+
+func (s *sidecar) notify(method string, params any) {
+	s.write(map[string]any{"jsonrpc": "2.0", "method": method, "params": params})
+}
+
+func (s *sidecar) handle(ctx context.Context, method string, raw json.RawMessage) (any, error) {
+	switch method {
+	case "status":
+		return s.status(), nil
+	case "connect":
+		if s.client.IsConnected() {
+			return nil, nil
+		}
+		return nil, s.client.Connect()
+	case "link":
+		var params struct {
+			DeviceName string `json:"device_name"`
+		}
+		json.Unmarshal(raw, &params)
+		return s.link(ctx, params.DeviceName)
+	case "wait_for_link":
+		if s.linkDone == nil {
+			return nil, errors.New("not linking")
+		}
+		<-s.linkDone
+		return s.status(), nil
+	case "contacts":
+		return s.contacts(ctx)
+	case "groups":
+		return s.groups(ctx)
+	case "find_user":
+		var params struct {
+			Phone string `json:"phone"`
+		}
+		if err := json.Unmarshal(raw, &params); err != nil {
+			return nil, err
+		}
+		return s.findUser(ctx, params.Phone)
+	case "details":
+		var params struct {
+			Chat string `json:"chat"`
+		}
+		if err := json.Unmarshal(raw, &params); err != nil {
+			return nil, err
+		}
+		return s.details(ctx, params.Chat)
+	case "messages":
+		var params struct {
+			Chat  string  `json:"chat"`
+			Start *uint64 `json:"start"`
+			End   *uint64 `json:"end"`
+		}
+		if err := json.Unmarshal(raw, &params); err != nil {
+			return nil, err
+		}
+		return s.messages(params.Chat, params.Start, params.End)
+	case "summaries":
+		return s.summaries()
+	case "send_message":
+		var params struct {
+			Chat        string   `json:"chat"`
+			Text        string   `json:"text"`
+			Attachments []string `json:"attachments"`
+			Quote       *quote   `json:"quote"`
+		}
+		if err := json.Unmarshal(raw, &params); err != nil {
+			return nil, err
+		}
+		return s.sendMessage(ctx, params.Chat, params.Text, params.Attachments, params.Quote)
+	case "send_reaction":
+		var params struct {
+			Chat            string `json:"chat"`
+			TargetSender    string `json:"target_sender"`
+			TargetTimestamp uint64 `json:"target_timestamp"`
+			Reaction        string `json:"reaction"`
+			Remove          bool   `json:"remove"`
+		}
+		if err := json.Unmarshal(raw, &params); err != nil {
+			return nil, err
+		}
+		return s.sendReaction(ctx, params.Chat, params.TargetSender, params.TargetTimestamp, params.Reaction, params.Remove)
+	case "send_edit":
+		var params struct {
+			Chat            string `json:"chat"`
+			TargetTimestamp uint64 `json:"target_timestamp"`
+			Text            string `json:"text"`
+		}
+		if err := json.Unmarshal(raw, &params); err != nil {
+			return nil, err
+		}
+		return s.sendEdit(ctx, params.Chat, params.TargetTimestamp, params.Text)
+	case "revoke":
+		var params struct {
+			Chat            string `json:"chat"`
+			TargetTimestamp uint64 `json:"target_timestamp"`
+		}
+		if err := json.Unmarshal(raw, &params); err != nil {
+			return nil, err
+		}
+		return nil, s.revoke(ctx, params.Chat, params.TargetTimestamp)
+	case "download":
+		var params struct {
+			Media media `json:"media"`
+		}
+		if err := json.Unmarshal(raw, &params); err != nil {
+			return nil, err
+		}
+		path, err := s.download(ctx, params.Media)
+		if err != nil {
+			return nil, err
+		}
+		return map[string]string{"path": path}, nil
+	}
+	return nil, fmt.Errorf("unknown method %q", method)
+}
+
+func (s *sidecar) status() map[string]any {
+	if s.client.Store.ID == nil {
+		return map[string]any{"logged_in": false}
+	}
+	return map[string]any{"logged_in": true, "jid": s.client.Store.ID.ToNonAD().String()}
+}
+
+func (s *sidecar) link(ctx context.Context, deviceName string) (any, error) {
+	store.DeviceProps.Os = proto.String(deviceName)
+	store.DeviceProps.PlatformType = waCompanionReg.DeviceProps_DESKTOP.Enum()
+	qrChan, err := s.client.GetQRChannel(ctx)
+	if err != nil {
+		return nil, err
+	}
+	if err := s.client.Connect(); err != nil {
+		return nil, err
+	}
+	for evt := range qrChan {
+		if evt.Event != "code" {
+			return nil, fmt.Errorf("linking failed: %s", evt.Event)
+		}
+		s.linkDone = make(chan bool, 1)
+		go func() {
+			success := false
+			for evt := range qrChan {
+				if evt.Event == "success" {
+					success = true
+				}
+			}
+			s.linkDone <- success
+		}()
+		return map[string]string{"code": evt.Code}, nil
+	}
+	return nil, errors.New("no QR code received")
+}
+
+func (s *sidecar) contacts(ctx context.Context) ([]contact, error) {
+	all, err := s.client.Store.Contacts.GetAllContacts(ctx)
+	if err != nil {
+		return nil, err
+	}
+	contacts := []contact{}
+	for jid, info := range all {
+		name := info.FullName
+		if name == "" {
+			name = info.PushName
+		}
+		if name == "" {
+			name = info.BusinessName
+		}
+		if name == "" {
+			name = "+" + jid.User
+		}
+		contacts = append(contacts, s.contact(ctx, jid, name, ""))
+	}
+	return contacts, nil
+}
+
+func (s *sidecar) groups(ctx context.Context) ([]contact, error) {
+	groups, err := s.client.GetJoinedGroups(ctx)
+	if err != nil {
+		return nil, err
+	}
+	contacts := []contact{}
+	for _, group := range groups {
+		contacts = append(contacts, s.contact(ctx, group.JID, group.Name, group.Topic))
+	}
+	return contacts, nil
+}
+
+func (s *sidecar) details(ctx context.Context, chat string) (*details, error) {
+	jid, err := types.ParseJID(chat)
+	if err != nil {
+		return nil, err
+	}
+	d := &details{}
+	if jid.Server == types.GroupServer {
+		info, err := s.client.GetGroupInfo(ctx, jid)
+		if err != nil {
+			return nil, err
+		}
+		if info.IsEphemeral {
+			timer := uint64(info.DisappearingTimer)
+			d.DisappearingTimer = &timer
+		}
+		own := s.client.Store.ID.ToNonAD()
+		for _, participant := range info.Participants {
+			if participant.JID.ToNonAD() != own {
+				continue
+			}
+			switch {
+			case participant.IsSuperAdmin:
+				d.Role = "owner"
+			case participant.IsAdmin:
+				d.Role = "admin"
+			default:
+				d.Role = "member"
+			}
+		}
+		return d, nil
+	}
+	users, err := s.client.GetUserInfo(ctx, []types.JID{jid})
+	if err != nil {
+		return nil, err
+	}
+	if info, ok := users[jid]; ok {
+		d.Status = info.Status
+	}
+	if info, err := s.client.Store.Contacts.GetContact(ctx, jid); err == nil {
+		d.BusinessName = info.BusinessName
+	}
+	return d, nil
+}
+
+// findUser looks up whether a phone number is on WhatsApp, for chatting with people who aren't
+// contacts.
+func (s *sidecar) findUser(ctx context.Context, phone string) (*contact, error) {
+	responses, err := s.client.IsOnWhatsApp(ctx, []string{phone})
+	if err != nil {
+		return nil, err
+	}
+	if len(responses) == 0 || !responses[0].IsIn {
+		return nil, fmt.Errorf("%s isn't on WhatsApp", phone)
+	}
+	jid := responses[0].JID
+	name := "+" + jid.User
+	if info, err := s.client.Store.Contacts.GetContact(ctx, jid); err == nil && info.Found && info.PushName != "" {
+		name = info.PushName
+	}
+	c := s.contact(ctx, jid, name, "")
+	return &c, nil
+}
+
+func (s *sidecar) contact(ctx context.Context, jid types.JID, name, description string) contact {
+	c := contact{JID: jid.String(), Name: name, Description: description}
+	var last sql.NullInt64
+	s.db.QueryRow(`SELECT MAX(timestamp) FROM chatters_messages WHERE chat = ?`, c.JID).Scan(&last)
+	if last.Valid {
+		ts := uint64(last.Int64)
+		c.LastMessageTimestamp = &ts
+	}
+	if settings, err := s.client.Store.ChatSettings.GetChatSettings(ctx, jid); err == nil && settings.Found {
+		if settings.MutedUntil == store.MutedForever {
+			until := ^uint64(0)
+			c.MutedUntil = &until
+		} else if settings.MutedUntil.After(time.Now()) {
+			until := uint64(settings.MutedUntil.UnixMilli())
+			c.MutedUntil = &until
+		}
+	}
+	return c
+}
+
+func (s *sidecar) messages(chat string, start, end *uint64) ([]message, error) {
+	query := `SELECT data FROM chatters_messages WHERE chat = ?`
+	args := []any{chat}
+	if start != nil {
+		query += ` AND timestamp >= ?`
+		args = append(args, *start)
+	}
+	if end != nil {
+		query += ` AND timestamp <= ?`
+		args = append(args, *end)
+	}
+	rows, err := s.db.Query(query+` ORDER BY timestamp`, args...)
+	if err != nil {
+		return nil, err
+	}
+	defer rows.Close()
+	messages := []message{}
+	for rows.Next() {
+		var data string
+		var m message
+		if err := rows.Scan(&data); err == nil && json.Unmarshal([]byte(data), &m) == nil {
+			messages = append(messages, m)
+		}
+	}
+	return messages, rows.Err()
+}
+
+// summaries finds the latest text message of each chat, sqlite taking the data from the row
+// with the greatest timestamp.
+func (s *sidecar) summaries() ([]message, error) {
+	rows, err := s.db.Query(`SELECT data, MAX(timestamp) FROM chatters_messages
+		WHERE json_extract(data, '$.type') = 'text' GROUP BY chat`)
+	if err != nil {
+		return nil, err
+	}
+	defer rows.Close()
+	messages := []message{}
+	for rows.Next() {
+		var data string
+		var timestamp uint64
+		var m message
+		if err := rows.Scan(&data, &timestamp); err == nil && json.Unmarshal([]byte(data), &m) == nil {
+			messages = append(messages, m)
+		}
+	}
+	return messages, rows.Err()
+}
+
+func (s *sidecar) store(m *message) {
+	data, _ := json.Marshal(m)
+	s.db.Exec(
+		`INSERT OR REPLACE INTO chatters_messages (chat, id, sender, timestamp, data) VALUES (?, ?, ?, ?, ?)`,
+		m.Chat, m.id, m.Sender, m.Timestamp, string(data),
+	)
+}
+
+// lookup finds the id and sender of the message in the chat with the timestamp.
+func (s *sidecar) lookup(chat string, sender string, timestamp uint64) (string, string, error) {
+	query := `SELECT id, sender FROM chatters_messages WHERE chat = ? AND timestamp = ?`
+	args := []any{chat, timestamp}
+	if sender != "" {
+		query += ` AND sender = ?`
+		args = append(args, sender)
+	}
+	var id, foundSender string
+	err := s.db.QueryRow(query, args...).Scan(&id, &foundSender)
+	return id, foundSender, err
+}
+
+// lookupID finds the timestamp and sender of the message in the chat with the id.
+func (s *sidecar) lookupID(chat string, id string) (uint64, string) {
+	var timestamp uint64
+	var sender string
+	s.db.QueryRow(`SELECT timestamp, sender FROM chatters_messages WHERE chat = ? AND id = ?`, chat, id).Scan(&timestamp, &sender)
+	return timestamp, sender
+}
+
+func (s *sidecar) sendMessage(ctx context.Context, chat, text string, paths []string, q *quote) (*message, error) {
+	to, err := types.ParseJID(chat)
+	if err != nil {
+		return nil, err
+	}
+	var contextInfo *waE2E.ContextInfo
+	if q != nil {
+		id, sender, err := s.lookup(chat, q.Sender, q.Timestamp)
+		if err == nil {
+			contextInfo = &waE2E.ContextInfo{
+				StanzaID:      proto.String(id),
+				Participant:   proto.String(sender),
+				QuotedMessage: &waE2E.Message{Conversation: proto.String(q.Text)},
+			}
+		}
+	}
+
+	var sent *message
+	// each attachment goes as its own message, with the text on the last one
+	for i, path := range paths {
+		data, err := os.ReadFile(path)
+		if err != nil {
+			return nil, err
+		}
+		uploaded, err := s.client.Upload(ctx, data, whatsmeow.MediaDocument)
+		if err != nil {
+			return nil, err
+		}
+		document := &waE2E.DocumentMessage{
+			URL:           proto.String(uploaded.URL),
+			DirectPath:    proto.String(uploaded.DirectPath),
+			MediaKey:      uploaded.MediaKey,
+			FileEncSHA256: uploaded.FileEncSHA256,
+			FileSHA256:    uploaded.FileSHA256,
+			FileLength:    proto.Uint64(uploaded.FileLength),
+			FileName:      proto.String(filepath.Base(path)),
+			ContextInfo:   contextInfo,
+		}
+		if i == len(paths)-1 {
+			document.Caption = proto.String(text)
+		}
+		sent, err = s.send(ctx, to, &waE2E.Message{DocumentMessage: document})
+		if err != nil {
+			return nil, err
+		}
+	}
+	if len(paths) > 0 {
+		return sent, nil
+	}
+	msg := &waE2E.Message{Conversation: proto.String(text)}
+	if contextInfo != nil {
+		msg = &waE2E.Message{ExtendedTextMessage: &waE2E.ExtendedTextMessage{
+			Text:        proto.String(text),
+			ContextInfo: contextInfo,
+		}}
+	}
+	return s.send(ctx, to, msg)
+}
+
+func (s *sidecar) sendReaction(ctx context.Context, chat, targetSender string, targetTimestamp uint64, reaction string, remove bool) (*message, error) {
+	to, err := types.ParseJID(chat)
+	if err != nil {
+		return nil, err
+	}
+	id, sender, err := s.lookup(chat, targetSender, targetTimestamp)
+	if err != nil {
+		return nil, fmt.Errorf("unknown message to react to: %w", err)
+	}
+	senderJID, _ := types.ParseJID(sender)
+	if remove {
+		reaction = ""
+	}
+	return s.send(ctx, to, s.client.BuildReaction(to, senderJID, id, reaction))
+}
+
+func (s *sidecar) sendEdit(ctx context.Context, chat string, targetTimestamp uint64, text string) (*message, error) {
+	to, err := types.ParseJID(chat)
+	if err != nil {
+		return nil, err
+	}
+	id, _, err := s.lookup(chat, s.client.Store.ID.ToNonAD().String(), targetTimestamp)
+	if err != nil {
+		return nil, fmt.Errorf("unknown message to edit: %w", err)
+	}
+	return s.send(ctx, to, s.client.BuildEdit(to, id, &waE2E.Message{Conversation: proto.String(text)}))
+}
+
+// revoke deletes a message the user sent for everyone in the chat.
+func (s *sidecar) revoke(ctx context.Context, chat string, targetTimestamp uint64) error {
+	to, err := types.ParseJID(chat)
+	if err != nil {
+		return err
+	}
+	id, _, err := s.lookup(chat, s.client.Store.ID.ToNonAD().String(), targetTimestamp)
+	if err != nil {
+		return fmt.Errorf("unknown message to delete: %w", err)
+	}
+	_, err = s.client.SendMessage(ctx, to, s.client.BuildRevoke(to, types.EmptyJID, id))
+	return err
+}
+
+func (s *sidecar) send(ctx context.Context, to types.JID, msg *waE2E.Message) (*message, error) {
+	resp, err := s.client.SendMessage(ctx, to, msg)
+	if err != nil {
+		return nil, err
+	}
+	evt := &events.Message{
+		Info: types.MessageInfo{
+			MessageSource: types.MessageSource{
+				Chat:     to,
+				Sender:   *s.client.Store.ID,
+				IsFromMe: true,
+			},
+			ID:        resp.ID,
+			Timestamp: resp.Timestamp,
+		},
+		Message: msg,
+	}
+	m := s.convert(evt)
+	if m == nil {
+		return nil, errors.New("sent an unsupported message")
+	}
+	s.store(m)
+	return m, nil
+}
+
+func (s *sidecar) download(ctx context.Context, m media) (string, error) {
+	data, err := base64.StdEncoding.DecodeString(m.Proto)
+	if err != nil {
+		return "", err
+	}
+	var downloadable whatsmeow.DownloadableMessage
+	var name string
+	switch m.Kind {
+	case "image":
+		msg := &waE2E.ImageMessage{}
+		err = proto.Unmarshal(data, msg)
+		downloadable, name = msg, fmt.Sprintf("%x.jpg", msg.GetFileSHA256()[:8])
+	case "video":
+		msg := &waE2E.VideoMessage{}
+		err = proto.Unmarshal(data, msg)
+		downloadable, name = msg, fmt.Sprintf("%x.mp4", msg.GetFileSHA256()[:8])
+	case "audio":
+		msg := &waE2E.AudioMessage{}
+		err = proto.Unmarshal(data, msg)
+		downloadable, name = msg, fmt.Sprintf("%x.ogg", msg.GetFileSHA256()[:8])
+	case "document":
+		msg := &waE2E.DocumentMessage{}
+		err = proto.Unmarshal(data, msg)
+		downloadable, name = msg, filepath.Base(msg.GetFileName())
+	default:
+		return "", fmt.Errorf("unknown media kind %q", m.Kind)
+	}
+	if err != nil {
+		return "", err
+	}
+	path := filepath.Join(s.attachmentsDir, name)
+	if _, err := os.Stat(path); err == nil {
+		// already downloaded
+		return path, nil
+	}
+	content, err := s.client.Download(ctx, downloadable)
+	if err != nil {
+		return "", err
+	}
+	if err := os.MkdirAll(s.attachmentsDir, 0o755); err != nil {
+		return "", err
+	}
+	return path, os.WriteFile(path, content, 0o644)
+}
+
+func (s *sidecar) handleEvent(evt any) {
+	switch evt := evt.(type) {
+	case *events.Message:
+		if m := s.convert(evt); m != nil {
+			s.store(m)
+			s.notify("message", m)
+		}
+	case *events.HistorySync:
+		for _, conv := range evt.Data.GetConversations() {
+			chat, err := types.ParseJID(conv.GetID())
+			if err != nil {
+				continue
+			}
+			for _, hist := range conv.GetMessages() {
+				parsed, err := s.client.ParseWebMessage(chat, hist.GetMessage())
+				if err != nil {
+					continue
+				}
+				if m := s.convert(parsed); m != nil {
+					s.store(m)
+				}
+			}
+		}
+	case *events.Receipt:
+		s.receipt(evt)
+	case *events.Disconnected:
+		s.notify("connection", map[string]bool{"connected": false})
+	case *events.OfflineSyncCompleted:
+		// sent once the messages queued while offline have been delivered
+		s.notify("connection", map[string]bool{"connected": true})
+	}
+}
+
+// receipt tells chatters about the user's messages reaching someone.
+func (s *sidecar) receipt(evt *events.Receipt) {
+	// those from the user's other devices are for messages sent to them
+	if evt.IsFromMe || s.client.Store.ID == nil {
+		return
+	}
+	var kind string
+	switch evt.Type {
+	case types.ReceiptTypeDelivered:
+		kind = "delivered"
+	case types.ReceiptTypeRead, types.ReceiptTypePlayed:
+		kind = "read"
+	default:
+		return
+	}
+	own := s.client.Store.ID.ToNonAD().String()
+	timestamps := []uint64{}
+	for _, id := range evt.MessageIDs {
+		if timestamp, sender := s.lookupID(evt.Chat.String(), id); sender == own {
+			timestamps = append(timestamps, timestamp)
+		}
+	}
+	if len(timestamps) == 0 {
+		return
+	}
+	s.notify("receipt", map[string]any{
+		"recipient":  evt.Sender.ToNonAD().String(),
+		"kind":       kind,
+		"timestamps": timestamps,
+		"at":         uint64(evt.Timestamp.UnixMilli()),
+	})
+}
+
+// convert turns a whatsmeow message into the message sent to chatters, or nil if it isn't one
+// chatters shows.
+func (s *sidecar) convert(evt *events.Message) *message {
+	msg := evt.Message
+	if msg == nil {
+		return nil
+	}
+	chat := evt.Info.Chat.ToNonAD().String()
+	m := &message{
+		Chat:      chat,
+		Sender:    evt.Info.Sender.ToNonAD().String(),
+		Timestamp: uint64(evt.Info.Timestamp.UnixMilli()),
+		id:        evt.Info.ID,
+	}
+
+	if reaction := msg.GetReactionMessage(); reaction != nil {
+		m.Type = "reaction"
+		m.TargetTimestamp, m.TargetSender = s.lookupID(chat, reaction.GetKey().GetID())
+		m.Reaction = reaction.GetText()
+		m.Remove = reaction.GetText() == ""
+		return m
+	}
+	if protocol := msg.GetProtocolMessage(); protocol != nil {
+		if protocol.GetType() != waE2E.ProtocolMessage_MESSAGE_EDIT {
+			return nil
+		}
+		edited := protocol.GetEditedMessage()
+		m.Type = "edit"
+		m.TargetTimestamp, _ = s.lookupID(chat, protocol.GetKey().GetID())
+		m.Text = edited.GetConversation()
+		if m.Text == "" {
+			m.Text = edited.GetExtendedTextMessage().GetText()
+		}
+		return m
+	}
+
+	m.Type = "text"
+	var contextInfo *waE2E.ContextInfo
+	addMedia := func(kind string, media proto.Message, name string, mimetype string, size uint64, caption string, ci *waE2E.ContextInfo) {
+		data, _ := proto.Marshal(media)
+		m.Attachments = append(m.Attachments, attachment{
+			Name:     name,
+			Size:     size,
+			Mimetype: mimetype,
+			Media:    mediaFor(kind, data),
+		})
+		m.Text = caption
+		contextInfo = ci
+	}
+	switch {
+	case msg.GetConversation() != "":
+		m.Text = msg.GetConversation()
+	case msg.GetExtendedTextMessage() != nil:
+		m.Text = msg.GetExtendedTextMessage().GetText()
+		contextInfo = msg.GetExtendedTextMessage().GetContextInfo()
+	case msg.GetImageMessage() != nil:
+		image := msg.GetImageMessage()
+		addMedia("image", image, "image", image.GetMimetype(), image.GetFileLength(), image.GetCaption(), image.GetContextInfo())
+	case msg.GetVideoMessage() != nil:
+		video := msg.GetVideoMessage()
+		addMedia("video", video, "video", video.GetMimetype(), video.GetFileLength(), video.GetCaption(), video.GetContextInfo())
+	case msg.GetAudioMessage() != nil:
+		audio := msg.GetAudioMessage()
+		addMedia("audio", audio, "audio", audio.GetMimetype(), audio.GetFileLength(), "", audio.GetContextInfo())
+	case msg.GetDocumentMessage() != nil:
+		document := msg.GetDocumentMessage()
+		addMedia("document", document, document.GetFileName(), document.GetMimetype(), document.GetFileLength(), document.GetCaption(), document.GetContextInfo())
+	default:
+		return nil
+	}
+	if contextInfo.GetStanzaID() != "" {
+		timestamp, sender := s.lookupID(chat, contextInfo.GetStanzaID())
+		if sender == "" {
+			sender = contextInfo.GetParticipant()
+		}
+		text := contextInfo.GetQuotedMessage().GetConversation()
+		if text == "" {
+			text = contextInfo.GetQuotedMessage().GetExtendedTextMessage().GetText()
+		}
+		m.Quote = &quote{Sender: sender, Timestamp: timestamp, Text: text}
+	}
+	return m
+}
+
+func mediaFor(kind string, data []byte) media {
+	return media{Kind: kind, Proto: base64.StdEncoding.EncodeToString(data)}
+}
@@ -0,0 +1,665 @@
+use std::ops::Bound;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use chatters_lib::backends::Backend;
+use chatters_lib::backends::Connectivity;
+use chatters_lib::backends::Contact;
+use chatters_lib::backends::ContactDetails;
+use chatters_lib::backends::ContactId;
+use chatters_lib::backends::Error;
+use chatters_lib::backends::Invite;
+use chatters_lib::backends::Limits;
+use chatters_lib::backends::LoadProgress;
+use chatters_lib::backends::Message;
+use chatters_lib::backends::MessageAttachment;
+use chatters_lib::backends::MessageContent;
+use chatters_lib::backends::Progress;
+use chatters_lib::backends::Quote;
+use chatters_lib::backends::Receipt;
+use chatters_lib::backends::Result;
+use chatters_lib::backends::Setup;
+use chatters_lib::backends::SetupQuestion;
+use chatters_lib::backends::Verification;
+use chatters_lib::message::FrontendMessage;
+use chatters_lib::pins::PinStore;
+use chatters_lib::receipts::{ReceiptKind, ReceiptStore};
+use futures::channel::{mpsc, oneshot};
+use futures::StreamExt as _;
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+
+use crate::sidecar::Sidecar;
+
+mod sidecar;
+
+/// Suffix of the JIDs of groups, users have `@s.whatsapp.net`.
+const GROUP_SERVER: &str = "@g.us";
+
+#[derive(Debug, serde::Deserialize)]
+struct Status {
+    logged_in: bool,
+    #[serde(default)]
+    jid: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct LinkCode {
+    code: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct WaContact {
+    jid: String,
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    last_message_timestamp: Option<u64>,
+    #[serde(default)]
+    muted_until: Option<u64>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct WaDetails {
+    #[serde(default)]
+    role: Option<String>,
+    #[serde(default)]
+    disappearing_timer: Option<u64>,
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    business_name: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct WaMessage {
+    chat: String,
+    sender: String,
+    timestamp: u64,
+    #[serde(flatten)]
+    content: WaContent,
+    #[serde(default)]
+    quote: Option<WaQuote>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WaContent {
+    Text {
+        #[serde(default)]
+        text: String,
+        #[serde(default)]
+        attachments: Vec<WaAttachment>,
+    },
+    Reaction {
+        target_sender: String,
+        target_timestamp: u64,
+        #[serde(default)]
+        reaction: String,
+        #[serde(default)]
+        remove: bool,
+    },
+    Edit {
+        target_timestamp: u64,
+        #[serde(default)]
+        text: String,
+    },
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct WaAttachment {
+    name: String,
+    size: u64,
+    #[serde(default)]
+    mimetype: Option<String>,
+    /// Opaque handle for the sidecar to download the media with.
+    media: Value,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct WaQuote {
+    sender: String,
+    timestamp: u64,
+    text: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct WaReceipt {
+    recipient: String,
+    kind: String,
+    timestamps: Vec<u64>,
+    at: u64,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Connection {
+    connected: bool,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Downloaded {
+    path: PathBuf,
+}
+
+/// WhatsApp, through the whatsmeow based sidecar in `sidecar/`.
+///
+/// Build the sidecar with `go mod tidy && go build -o chatters-whatsapp-sidecar` and put it on the
+/// `PATH`, or point `CHATTERS_WHATSAPP_SIDECAR` at it. The flake's `chatters-whatsapp` package
+/// includes it.
+#[derive(Clone)]
+pub struct WhatsApp {
+    sidecar: Arc<Sidecar>,
+    self_jid: String,
+    /// Media handles of the attachments seen so far, indexed by attachment index.
+    media: Arc<Mutex<Vec<Value>>>,
+    pins: PinStore,
+    receipts: ReceiptStore,
+}
+
+impl Backend for WhatsApp {
+    const NAME: &'static str = "WhatsApp";
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn load(path: &Path, _progress: LoadProgress) -> Result<Self> {
+        let sidecar = Sidecar::spawn(path)?;
+        let status: Status = sidecar.call("status", json!({})).await?;
+        if !status.logged_in {
+            debug!("WhatsApp sidecar not logged in, getting to link");
+            return Err(Error::Unlinked);
+        }
+        sidecar.call::<Value>("connect", json!({})).await?;
+        Ok(Self::new(path, sidecar, status.jid))
+    }
+
+    fn setup_questions() -> Vec<SetupQuestion> {
+        Vec::new()
+    }
+
+    async fn link(
+        path: &Path,
+        setup: &Setup,
+        provisioning_link_tx: oneshot::Sender<String>,
+    ) -> Result<Self> {
+        let sidecar = Sidecar::spawn(path)?;
+        let link: LinkCode = sidecar
+            .call("link", json!({ "device_name": setup.device_name }))
+            .await?;
+        // the code stays valid for about a minute, after which linking needs to be restarted
+        let _ = provisioning_link_tx.send(link.code);
+        let status: Status = sidecar.call("wait_for_link", json!({})).await?;
+        if !status.logged_in {
+            return Err(Error::Failure(
+                "Failed to link WhatsApp".to_owned(),
+                "QR code was not scanned in time".to_owned(),
+            ));
+        }
+        sidecar.call::<Value>("connect", json!({})).await?;
+        Ok(Self::new(path, sidecar, status.jid))
+    }
+
+    async fn background_sync(
+        &mut self,
+        ba_tx: mpsc::UnboundedSender<FrontendMessage>,
+    ) -> Result<()> {
+        let mut notifications = match self.sidecar.take_notifications() {
+            Some(notifications) => notifications,
+            // taken by the last sync, which ended with the sidecar exiting
+            None => {
+                info!("Restarting WhatsApp sidecar");
+                self.sidecar.restart().await?;
+                self.sidecar.call::<Value>("connect", json!({})).await?;
+                self.sidecar.take_notifications().unwrap()
+            }
+        };
+        while let Some(notification) = notifications.next().await {
+            match notification.method.as_str() {
+                "message" => match serde_json::from_value::<WaMessage>(notification.params) {
+                    Ok(message) => {
+                        let message = self.to_message(message);
+                        ba_tx
+                            .unbounded_send(FrontendMessage::NewMessage { message })
+                            .unwrap();
+                    }
+                    Err(error) => warn!(error:%; "Invalid message from WhatsApp sidecar"),
+                },
+                "receipt" => match serde_json::from_value::<WaReceipt>(notification.params) {
+                    Ok(receipt) => {
+                        let kind = if receipt.kind == "read" {
+                            ReceiptKind::Read
+                        } else {
+                            ReceiptKind::Delivered
+                        };
+                        if let Err(error) = self.receipts.record(
+                            &receipt.timestamps,
+                            receipt.recipient.as_bytes(),
+                            kind,
+                            receipt.at,
+                        ) {
+                            warn!(error:%; "Failed to save message receipt");
+                        }
+                    }
+                    Err(error) => warn!(error:%; "Invalid receipt from WhatsApp sidecar"),
+                },
+                "connection" => match serde_json::from_value::<Connection>(notification.params) {
+                    Ok(Connection { connected }) => {
+                        // whatsmeow reconnects by itself
+                        let connectivity = if connected {
+                            Connectivity::Online
+                        } else {
+                            Connectivity::Reconnecting
+                        };
+                        ba_tx
+                            .unbounded_send(FrontendMessage::Connectivity { connectivity })
+                            .unwrap();
+                    }
+                    Err(error) => warn!(error:%; "Invalid connection from WhatsApp sidecar"),
+                },
+                method => debug!(method; "Ignoring notification from WhatsApp sidecar"),
+            }
+        }
+        Err(Error::Failure(
+            "Failed to sync WhatsApp".to_owned(),
+            "sidecar exited".to_owned(),
+        ))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn users(&self) -> Result<Vec<Contact>> {
+        let contacts: Vec<WaContact> = self.sidecar.call("contacts", json!({})).await?;
+        Ok(contacts.into_iter().map(to_contact).collect())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn groups(&self) -> Result<Vec<Contact>> {
+        let groups: Vec<WaContact> = self.sidecar.call("groups", json!({})).await?;
+        Ok(groups.into_iter().map(to_contact).collect())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn messages(
+        &mut self,
+        contact_id: ContactId,
+        start_ts: Bound<u64>,
+        end_ts: Bound<u64>,
+    ) -> Result<Vec<Message>> {
+        let start = match start_ts {
+            Bound::Included(ts) => Some(ts),
+            Bound::Excluded(ts) => Some(ts.saturating_add(1)),
+            Bound::Unbounded => None,
+        };
+        let end = match end_ts {
+            Bound::Included(ts) => Some(ts),
+            Bound::Excluded(ts) => Some(ts.saturating_sub(1)),
+            Bound::Unbounded => None,
+        };
+        let messages: Vec<WaMessage> = self
+            .sidecar
+            .call(
+                "messages",
+                json!({"chat": jid(&contact_id), "start": start, "end": end}),
+            )
+            .await?;
+        Ok(messages.into_iter().map(|m| self.to_message(m)).collect())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, content, quoting)))]
+    async fn send_message(
+        &mut self,
+        contact_id: ContactId,
+        content: MessageContent,
+        quoting: Option<&Quote>,
+    ) -> Result<Message> {
+        let chat = jid(&contact_id);
+        let quote = quoting.map(|q| {
+            json!({
+                "sender": String::from_utf8_lossy(&q.sender),
+                "timestamp": q.timestamp,
+            })
+        });
+        let sent: WaMessage = match &content {
+            MessageContent::Text {
+                text, attachments, ..
+            } => {
+                let paths = attachments
+                    .iter()
+                    .filter_map(|a| a.path.as_ref())
+                    .collect::<Vec<_>>();
+                self.sidecar
+                    .call(
+                        "send_message",
+                        json!({"chat": chat, "text": text, "attachments": paths, "quote": quote}),
+                    )
+                    .await?
+            }
+            MessageContent::Reaction {
+                message_author,
+                timestamp,
+                reaction,
+                remove,
+            } => {
+                self.sidecar
+                    .call(
+                        "send_reaction",
+                        json!({
+                            "chat": chat,
+                            "target_sender": String::from_utf8_lossy(message_author),
+                            "target_timestamp": timestamp,
+                            "reaction": reaction,
+                            "remove": remove,
+                        }),
+                    )
+                    .await?
+            }
+            MessageContent::Edit {
+                timestamp, text, ..
+            } => {
+                self.sidecar
+                    .call(
+                        "send_edit",
+                        json!({"chat": chat, "target_timestamp": timestamp, "text": text}),
+                    )
+                    .await?
+            }
+            MessageContent::Unsupported { .. } | MessageContent::Call { .. } => {
+                return Err(Error::Failure(
+                    "Failed to send message".to_owned(),
+                    "calls and unsupported messages can't be sent".to_owned(),
+                ))
+            }
+        };
+        Ok(self.to_message(sent))
+    }
+
+    async fn self_id(&self) -> Vec<u8> {
+        self.self_jid.as_bytes().to_vec()
+    }
+
+    fn limits(&self) -> Limits {
+        Limits {
+            max_body_length: Some(65_536),
+            max_attachment_size: Some(2_000_000_000),
+            max_attachment_count: None,
+            any_reaction: false,
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn download_attachment(
+        &self,
+        attachment_index: usize,
+        _progress: Progress,
+    ) -> Result<PathBuf> {
+        let Some(media) = self.media.lock().unwrap().get(attachment_index).cloned() else {
+            return Err(Error::UnknownAttachment(attachment_index));
+        };
+        let downloaded: Downloaded = self
+            .sidecar
+            .call("download", json!({ "media": media }))
+            .await?;
+        Ok(downloaded.path)
+    }
+
+    async fn pin_message(
+        &mut self,
+        contact_id: ContactId,
+        timestamp: u64,
+        pinned: bool,
+    ) -> Result<()> {
+        self.pins
+            .set_pinned(&contact_id, timestamp, pinned)
+            .map_err(|e| Error::Failure("Failed to save pinned messages".to_owned(), e.to_string()))
+    }
+
+    async fn delete_message(&mut self, contact_id: ContactId, timestamp: u64) -> Result<()> {
+        self.sidecar
+            .call::<Value>(
+                "revoke",
+                json!({"chat": jid(&contact_id), "target_timestamp": timestamp}),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn pinned_messages(&self, contact_id: ContactId) -> Result<Vec<u64>> {
+        Ok(self.pins.pinned(&contact_id))
+    }
+
+    async fn find_user(&mut self, query: String) -> Result<Contact> {
+        // WhatsApp finds people by their phone number alone
+        let phone: String = query
+            .chars()
+            .filter(|c| c.is_ascii_digit() || *c == '+')
+            .collect();
+        if !phone.starts_with('+') {
+            return Err(Error::Failure(
+                "Failed to find user".to_owned(),
+                "WhatsApp users are found by their phone number, starting with +".to_owned(),
+            ));
+        }
+        let contact: WaContact = self
+            .sidecar
+            .call("find_user", json!({ "phone": phone }))
+            .await?;
+        Ok(to_contact(contact))
+    }
+
+    async fn message_receipts(
+        &self,
+        _contact_id: ContactId,
+        timestamp: u64,
+    ) -> Result<Vec<Receipt>> {
+        Ok(self.receipts.receipts(timestamp))
+    }
+
+    async fn contact_details(&self, contact_id: ContactId) -> Result<ContactDetails> {
+        let details: WaDetails = self
+            .sidecar
+            .call("details", json!({ "chat": jid(&contact_id) }))
+            .await?;
+        let profile = [
+            ("Status", details.status),
+            ("Business", details.business_name),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_owned(), value?)))
+        .collect();
+        Ok(ContactDetails {
+            role: details.role,
+            disappearing_timer: details.disappearing_timer,
+            profile,
+        })
+    }
+
+    async fn verify_device(&mut self) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support verifying devices", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn confirm_verification(&mut self, _confirmed: bool) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support verifying devices", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn invites(&self) -> Result<Vec<Invite>> {
+        Ok(Vec::new())
+    }
+
+    async fn answer_invite(&mut self, _contact_id: ContactId, _accept: bool) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support invites", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn set_group_title(&mut self, _contact_id: ContactId, _title: String) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support changing groups", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn set_group_description(
+        &mut self,
+        _contact_id: ContactId,
+        _description: String,
+    ) -> Result<()> {
+        Err(Error::Failure(
+            format!("{} doesn't support changing groups", Self::NAME),
+            String::new(),
+        ))
+    }
+
+    async fn request_contact_sync(&mut self) -> Result<()> {
+        // the sidecar keeps the contacts up to date, loading them again is enough
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn conversation_summaries(&self) -> Result<Vec<Message>> {
+        let messages: Vec<WaMessage> = self.sidecar.call("summaries", json!({})).await?;
+        Ok(messages.into_iter().map(|m| self.to_message(m)).collect())
+    }
+}
+
+impl WhatsApp {
+    fn new(path: &Path, sidecar: Sidecar, self_jid: String) -> Self {
+        Self {
+            sidecar: Arc::new(sidecar),
+            self_jid,
+            media: Arc::default(),
+            pins: PinStore::load(&path.join("pins.json")),
+            receipts: ReceiptStore::load(&path.join("receipts.json")),
+        }
+    }
+
+    fn to_message(&self, message: WaMessage) -> Message {
+        let content = match message.content {
+            WaContent::Text { text, attachments } => {
+                let mut media = self.media.lock().unwrap();
+                let attachments = attachments
+                    .into_iter()
+                    .map(|a| {
+                        let index = media.len();
+                        media.push(a.media);
+                        MessageAttachment {
+                            name: a.name,
+                            size: a.size,
+                            index,
+                            path: None,
+                            content_type: a.mimetype,
+                        }
+                    })
+                    .collect();
+                MessageContent::Text {
+                    text,
+                    attachments,
+                    mentions: Vec::new(),
+                    formatting: Vec::new(),
+                }
+            }
+            WaContent::Reaction {
+                target_sender,
+                target_timestamp,
+                reaction,
+                remove,
+            } => MessageContent::Reaction {
+                message_author: target_sender.into_bytes(),
+                timestamp: target_timestamp,
+                reaction,
+                remove,
+            },
+            WaContent::Edit {
+                target_timestamp,
+                text,
+            } => MessageContent::Edit {
+                timestamp: target_timestamp,
+                text,
+                mentions: Vec::new(),
+                formatting: Vec::new(),
+            },
+        };
+        Message {
+            timestamp: message.timestamp,
+            sender: message.sender.into_bytes(),
+            contact_id: contact_id(message.chat),
+            content,
+            quote: message.quote.map(|q| Quote {
+                timestamp: q.timestamp,
+                sender: q.sender.into_bytes(),
+                text: q.text,
+                mentions: Vec::new(),
+                formatting: Vec::new(),
+            }),
+        }
+    }
+}
+
+fn contact_id(jid: String) -> ContactId {
+    if jid.ends_with(GROUP_SERVER) {
+        ContactId::Group(jid.into_bytes())
+    } else {
+        ContactId::User(jid.into_bytes())
+    }
+}
+
+fn jid(contact_id: &ContactId) -> String {
+    match contact_id {
+        ContactId::User(id) | ContactId::Group(id) => String::from_utf8_lossy(id).into_owned(),
+    }
+}
+
+fn to_contact(contact: WaContact) -> Contact {
+    let address = if contact.jid.ends_with(GROUP_SERVER) {
+        contact.jid.clone()
+    } else {
+        // user JIDs are their phone number
+        let number = contact.jid.split('@').next().unwrap_or_default();
+        format!("+{number}")
+    };
+    Contact {
+        id: contact_id(contact.jid),
+        name: contact.name,
+        address,
+        last_message_timestamp: contact.last_message_timestamp,
+        description: contact.description,
+        muted_until: contact.muted_until,
+        verification: Verification::Unverified,
+        members: None,
+        // whatsapp encrypts every conversation end to end
+        encrypted: true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_contact() {
+        let contact = |jid: &str| WaContact {
+            jid: jid.to_owned(),
+            name: "Alice".to_owned(),
+            description: String::new(),
+            last_message_timestamp: None,
+            muted_until: None,
+        };
+        let user = to_contact(contact("447700900123@s.whatsapp.net"));
+        assert_eq!(user.address, "+447700900123");
+        assert_eq!(
+            user.id,
+            ContactId::User(b"447700900123@s.whatsapp.net".to_vec())
+        );
+        let group = to_contact(contact("120363025246125486@g.us"));
+        assert_eq!(group.address, "120363025246125486@g.us");
+        assert!(matches!(group.id, ContactId::Group(_)));
+
+        assert_eq!(jid(&user.id), "447700900123@s.whatsapp.net");
+        assert_eq!(jid(&group.id), "120363025246125486@g.us");
+    }
+}
@@ -0,0 +1,273 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use chatters_lib::backends::{Error, Result};
+use futures::channel::{mpsc, oneshot};
+use log::{debug, info, warn};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+    process::{Child, ChildStdin, ChildStdout, Command},
+};
+
+/// Environment variable to override the sidecar program to run.
+const SIDECAR_ENV: &str = "CHATTERS_WHATSAPP_SIDECAR";
+/// The sidecar program to run when not overridden, looked up on the `PATH`.
+const DEFAULT_SIDECAR: &str = "chatters-whatsapp-sidecar";
+
+type Pending = Arc<Mutex<BTreeMap<u64, oneshot::Sender<std::result::Result<Value, String>>>>>;
+
+/// A notification sent by the sidecar without being asked.
+#[derive(Debug)]
+pub struct Notification {
+    pub method: String,
+    pub params: Value,
+}
+
+#[derive(serde::Deserialize)]
+struct Incoming {
+    id: Option<u64>,
+    method: Option<String>,
+    #[serde(default)]
+    params: Value,
+    result: Option<Value>,
+    error: Option<IncomingError>,
+}
+
+#[derive(serde::Deserialize)]
+struct IncomingError {
+    message: String,
+}
+
+/// The whatsmeow based sidecar process that speaks the WhatsApp multidevice protocol for us,
+/// talking line-delimited JSON-RPC 2.0 over its stdin and stdout.
+pub struct Sidecar {
+    path: PathBuf,
+    process: tokio::sync::Mutex<Process>,
+    next_id: AtomicU64,
+    pending: Pending,
+    notifications: Mutex<Option<mpsc::UnboundedReceiver<Notification>>>,
+}
+
+struct Process {
+    // kept so the process is killed when the sidecar is dropped
+    _child: Child,
+    stdin: ChildStdin,
+}
+
+impl Sidecar {
+    /// Start the sidecar, keeping its state in the directory at the path.
+    pub fn spawn(path: &Path) -> Result<Self> {
+        let pending = Pending::default();
+        let (process, notifications_rx) = start(path, &pending)?;
+        Ok(Self {
+            path: path.to_owned(),
+            process: tokio::sync::Mutex::new(process),
+            next_id: AtomicU64::new(0),
+            pending,
+            notifications: Mutex::new(Some(notifications_rx)),
+        })
+    }
+
+    /// Start the sidecar again after it exited, with a new stream of notifications to take.
+    pub async fn restart(&self) -> Result<()> {
+        let mut process = self.process.lock().await;
+        let (new_process, notifications_rx) = start(&self.path, &self.pending)?;
+        *process = new_process;
+        *self.notifications.lock().unwrap() = Some(notifications_rx);
+        Ok(())
+    }
+
+    /// Call a method on the sidecar, waiting for its result.
+    pub async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (result_tx, result_rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, result_tx);
+
+        let request = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+        let mut line = serde_json::to_vec(&request).unwrap();
+        line.push(b'\n');
+        let written = {
+            let mut process = self.process.lock().await;
+            match process.stdin.write_all(&line).await {
+                Ok(()) => process.stdin.flush().await,
+                Err(e) => Err(e),
+            }
+        };
+        if let Err(error) = written {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(Error::Failure(
+                "Failed to write to WhatsApp sidecar".to_owned(),
+                error.to_string(),
+            ));
+        }
+
+        let result = match result_rx.await {
+            Ok(Ok(result)) => result,
+            Ok(Err(error)) => {
+                return Err(Error::Failure(
+                    format!("WhatsApp sidecar failed to {method}"),
+                    error,
+                ))
+            }
+            Err(_) => {
+                return Err(Error::Failure(
+                    "WhatsApp sidecar exited".to_owned(),
+                    format!("no response to {method}"),
+                ))
+            }
+        };
+        serde_json::from_value(result).map_err(|e| {
+            Error::Failure(
+                format!("Invalid response from WhatsApp sidecar to {method}"),
+                e.to_string(),
+            )
+        })
+    }
+
+    /// Take the stream of notifications from the sidecar, which can only be done once each time it
+    /// is started.
+    pub fn take_notifications(&self) -> Option<mpsc::UnboundedReceiver<Notification>> {
+        self.notifications.lock().unwrap().take()
+    }
+}
+
+/// Run the sidecar program, handing its output to a task that answers the pending calls.
+fn start(
+    path: &Path,
+    pending: &Pending,
+) -> Result<(Process, mpsc::UnboundedReceiver<Notification>)> {
+    let program = std::env::var(SIDECAR_ENV).unwrap_or_else(|_| DEFAULT_SIDECAR.to_owned());
+    std::fs::create_dir_all(path).map_err(|e| {
+        Error::Failure(
+            "Failed to create backend directory".to_owned(),
+            e.to_string(),
+        )
+    })?;
+    let mut child = Command::new(&program)
+        .arg("--db")
+        .arg(path.join("whatsapp.db"))
+        .arg("--attachments")
+        .arg(path.join("attachments"))
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| {
+            Error::Failure(
+                format!("Failed to start WhatsApp sidecar {program:?}"),
+                e.to_string(),
+            )
+        })?;
+    info!(program:%; "Started WhatsApp sidecar");
+
+    let stdin = child.stdin.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let (notifications_tx, notifications_rx) = mpsc::unbounded();
+    tokio::spawn(read_output(stdout, Arc::clone(pending), notifications_tx));
+    let process = Process {
+        _child: child,
+        stdin,
+    };
+    Ok((process, notifications_rx))
+}
+
+async fn read_output(
+    stdout: ChildStdout,
+    pending: Pending,
+    notifications_tx: mpsc::UnboundedSender<Notification>,
+) {
+    let mut lines = BufReader::new(stdout).lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(error) => {
+                warn!(error:%; "Failed to read from WhatsApp sidecar");
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let incoming = match serde_json::from_str::<Incoming>(&line) {
+            Ok(incoming) => incoming,
+            Err(error) => {
+                warn!(error:%; "Invalid message from WhatsApp sidecar");
+                continue;
+            }
+        };
+        if let Some(method) = incoming.method {
+            debug!(method:%; "Notification from WhatsApp sidecar");
+            let notification = Notification {
+                method,
+                params: incoming.params,
+            };
+            // nobody listening for notifications yet is fine
+            let _ = notifications_tx.unbounded_send(notification);
+        } else if let Some(id) = incoming.id {
+            let Some(result_tx) = pending.lock().unwrap().remove(&id) else {
+                warn!(id; "Response from WhatsApp sidecar to an unknown request");
+                continue;
+            };
+            let result = match incoming.error {
+                Some(error) => Err(error.message),
+                None => Ok(incoming.result.unwrap_or_default()),
+            };
+            let _ = result_tx.send(result);
+        }
+    }
+    info!("WhatsApp sidecar closed its output");
+    // fail any waiting calls
+    pending.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    use futures::StreamExt as _;
+
+    use super::*;
+
+    /// Answers each request after sending a notification, failing `fail` requests.
+    const FAKE_SIDECAR: &str = r#"#!/bin/sh
+while read -r line; do
+  id=$(printf '%s' "$line" | sed 's/.*"id":\([0-9]*\).*/\1/')
+  echo '{"jsonrpc":"2.0","method":"typing","params":{"chat":"123@s.whatsapp.net"}}'
+  case "$line" in
+    *'"method":"fail"'*) echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"error\":{\"code\":1,\"message\":\"nope\"}}" ;;
+    *) echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"jid\":\"123@s.whatsapp.net\"}}" ;;
+  esac
+done
+"#;
+
+    #[tokio::test]
+    async fn test_call() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = dir.path().join("sidecar");
+        std::fs::write(&program, FAKE_SIDECAR).unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::env::set_var(SIDECAR_ENV, &program);
+
+        let sidecar = Sidecar::spawn(&dir.path().join("backend")).unwrap();
+        let mut notifications = sidecar.take_notifications().unwrap();
+        assert!(sidecar.take_notifications().is_none());
+
+        let status: Value = sidecar.call("status", json!({})).await.unwrap();
+        assert_eq!(status, json!({"jid": "123@s.whatsapp.net"}));
+        let notification = notifications.next().await.unwrap();
+        assert_eq!(notification.method, "typing");
+
+        let error = sidecar.call::<Value>("fail", json!({})).await.unwrap_err();
+        assert!(matches!(&error, Error::Failure(_, why) if why == "nope"), "{error:?}");
+    }
+}
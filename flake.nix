@@ -78,15 +78,47 @@
       pkg = cargoNix.workspaceMembers.chatters-matrix;
       name = "chatters-matrix";
     };
+    chatters-whatsapp-sidecar = pkgs.buildGoModule {
+      pname = "chatters-whatsapp-sidecar";
+      version = "0.1.0";
+      src = ./crates/chatters-whatsapp/sidecar;
+      # set once go.mod and go.sum have been resolved with `go mod tidy`
+      vendorHash = pkgs.lib.fakeHash;
+      postInstall = ''
+        mv $out/bin/sidecar $out/bin/chatters-whatsapp-sidecar
+      '';
+    };
+    # the backend looks for the sidecar on the PATH, so ship them together
+    chatters-whatsapp = pkgs.symlinkJoin {
+      name = "chatters-whatsapp";
+      paths = [
+        (wrap-chatters {
+          pkg = cargoNix.workspaceMembers.chatters-whatsapp;
+          name = "chatters-whatsapp";
+        })
+        chatters-whatsapp-sidecar
+      ];
+    };
+    chatters-email = wrap-chatters {
+      pkg = cargoNix.workspaceMembers.chatters-email;
+      name = "chatters-email";
+    };
+    chatters-discord = wrap-chatters {
+      pkg = cargoNix.workspaceMembers.chatters-discord;
+      name = "chatters-discord";
+    };
   in {
     packages.${system} = {
-      inherit chatters-local chatters-signal chatters-matrix;
+      inherit chatters-local chatters-signal chatters-matrix chatters-whatsapp chatters-whatsapp-sidecar chatters-email chatters-discord;
       chatters = pkgs.symlinkJoin {
         name = "chatters";
         paths = [
           chatters-local
           chatters-signal
           chatters-matrix
+          chatters-whatsapp
+          chatters-email
+          chatters-discord
         ];
       };
     };
@@ -101,6 +133,9 @@
 
         pkgs.crate2nix
 
+        # for the whatsapp sidecar
+        pkgs.go
+
         pkgs.openssl
         pkgs.pkg-config
         pkgs.sqlite